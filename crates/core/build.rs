@@ -13,4 +13,12 @@ fn main() {
     } else {
         let _ = Command::new("cargo").arg("fmt").status();
     }
+
+    // unlike the flatbuffers schemas the generated code is not checked in, so protoc is
+    // required to build with the gRPC API
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("../../schemas/proto/client_api.proto").expect(
+        "failed compiling the gRPC client API schema, refer to \
+         https://grpc.io/docs/protoc-installation to install the protoc compiler",
+    );
 }
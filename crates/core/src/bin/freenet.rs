@@ -17,7 +17,7 @@ async fn run(config: Config) -> anyhow::Result<()> {
 
 async fn run_local(config: Config) -> anyhow::Result<()> {
     tracing::info!("Starting freenet node in local mode");
-    let socket = config.ws_api;
+    let socket = config.ws_api.clone();
 
     let executor = Executor::from_config(Arc::new(config), None)
         .await
@@ -31,7 +31,7 @@ async fn run_local(config: Config) -> anyhow::Result<()> {
 async fn run_network(config: Config) -> anyhow::Result<()> {
     tracing::info!("Starting freenet node in network mode");
 
-    let clients = serve_gateway(config.ws_api).await;
+    let clients = serve_gateway(config.ws_api.clone()).await;
     tracing::info!("Initializing node configuration");
 
     let node_config = NodeConfig::new(config)
@@ -0,0 +1,170 @@
+//! Attestations of the delegate a webapp is talking to, signed by the node so the app can bind
+//! its secrets to a specific version of the code of the delegate.
+//!
+//! Attestations are requested with a message of the `FNAT` magic bytes followed by the key of
+//! the delegate (32 bytes), the hash of its code (32 bytes) and a nonce chosen by the client (at
+//! most 64 bytes). The node answers with the `FNAT` magic bytes, the size of the statement it
+//! signs (u32) and the statement, followed by the signature.
+//!
+//! The statement is made of the key and code hash of the delegate registered in the node, the
+//! contract the connection is attested for (a byte, 1 if followed by the contract instance id
+//! and 0 otherwise), the unix time in seconds it was issued at (u64), the nonce prefixed by its
+//! size (u8) and the public key of the node as DER prefixed by its size (u16), all integers big
+//! endian. The signature is a RSA PKCS#1 v1.5 signature of the blake3 digest of the statement,
+//! made with the transport key of the node.
+
+use std::io::{self, Cursor, Read};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use freenet_stdlib::prelude::{CodeHash, ContractInstanceId, DelegateKey};
+
+use crate::transport::TransportKeypair;
+
+/// Magic bytes prefixing attestation requests and responses.
+const ATTESTATION_MAGIC: [u8; 4] = *b"FNAT";
+
+/// Max size of the nonce of a request.
+pub(crate) const MAX_NONCE_SIZE: usize = 64;
+
+/// Request for the attestation of a delegate.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct AttestationRequest<'a> {
+    pub delegate: DelegateKey,
+    pub nonce: &'a [u8],
+}
+
+impl<'a> AttestationRequest<'a> {
+    pub fn is_request(msg: &[u8]) -> bool {
+        msg.starts_with(&ATTESTATION_MAGIC)
+    }
+
+    pub fn decode(msg: &'a [u8]) -> io::Result<Self> {
+        let Some(framed) = msg.strip_prefix(&ATTESTATION_MAGIC) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an attestation request",
+            ));
+        };
+        let mut reader = Cursor::new(framed);
+        let mut key = [0; 32];
+        let mut code_hash = [0; 32];
+        reader.read_exact(&mut key)?;
+        reader.read_exact(&mut code_hash)?;
+        let nonce = &framed[reader.position() as usize..];
+        if nonce.len() > MAX_NONCE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "nonce of {} bytes, at most {MAX_NONCE_SIZE} are allowed",
+                    nonce.len()
+                ),
+            ));
+        }
+        Ok(Self {
+            delegate: DelegateKey::new(key, CodeHash::new(code_hash)),
+            nonce,
+        })
+    }
+}
+
+/// Statement the node signs about a delegate.
+#[derive(Debug)]
+pub(crate) struct Attestation<'a> {
+    pub delegate: DelegateKey,
+    /// Contract the connection of the client is attested for.
+    pub contract: Option<ContractInstanceId>,
+    /// Unix timestamp, in seconds, the attestation was issued at.
+    pub issued_at: u64,
+    pub nonce: &'a [u8],
+}
+
+impl Attestation<'_> {
+    fn statement(&self, keypair: &TransportKeypair) -> io::Result<Vec<u8>> {
+        let node_key = keypair.public().to_der();
+        let node_key_size = u16::try_from(node_key.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "node key too large"))?;
+        let mut statement = Vec::with_capacity(32 * 3 + 12 + self.nonce.len() + node_key.len());
+        statement.extend_from_slice(&*self.delegate);
+        statement.extend_from_slice(&**self.delegate.code_hash());
+        match &self.contract {
+            Some(contract) => {
+                statement.write_u8(1)?;
+                statement.extend_from_slice(contract.as_bytes());
+            }
+            None => statement.write_u8(0)?,
+        }
+        statement.write_u64::<BigEndian>(self.issued_at)?;
+        statement.write_u8(self.nonce.len() as u8)?;
+        statement.extend_from_slice(self.nonce);
+        statement.write_u16::<BigEndian>(node_key_size)?;
+        statement.extend_from_slice(&node_key);
+        Ok(statement)
+    }
+
+    /// The message answering the request, with the attestation signed by `keypair`.
+    pub fn sign(&self, keypair: &TransportKeypair) -> io::Result<Vec<u8>> {
+        let statement = self.statement(keypair)?;
+        let signature = keypair.sign(&statement);
+        let mut msg =
+            Vec::with_capacity(ATTESTATION_MAGIC.len() + 4 + statement.len() + signature.len());
+        msg.extend_from_slice(&ATTESTATION_MAGIC);
+        msg.write_u32::<BigEndian>(statement.len() as u32)?;
+        msg.extend_from_slice(&statement);
+        msg.extend_from_slice(&signature);
+        Ok(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The statement and the signature of a signed attestation.
+    fn split(msg: &[u8]) -> io::Result<(&[u8], &[u8])> {
+        let framed = msg
+            .strip_prefix(&ATTESTATION_MAGIC)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))?;
+        let size = Cursor::new(framed).read_u32::<BigEndian>()? as usize;
+        let statement = framed
+            .get(4..4 + size)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        Ok((statement, &framed[4 + size..]))
+    }
+
+    #[test]
+    fn signed_attestation() -> Result<(), Box<dyn std::error::Error>> {
+        let delegate = DelegateKey::new([1; 32], CodeHash::new([2; 32]));
+        let encoded = [&ATTESTATION_MAGIC[..], &[1; 32], &[2; 32], b"nonce"].concat();
+        assert!(AttestationRequest::is_request(&encoded));
+        let request = AttestationRequest::decode(&encoded)?;
+        assert_eq!(request.delegate, delegate);
+        assert_eq!(request.nonce, b"nonce");
+        let too_long = [&ATTESTATION_MAGIC[..], &[1; 32], &[2; 32], &[0; 65]].concat();
+        assert!(AttestationRequest::decode(&too_long).is_err());
+        assert!(AttestationRequest::decode(&encoded[..40]).is_err());
+
+        let keypair = TransportKeypair::new();
+        let contract = ContractInstanceId::new([3; 32]);
+        let msg = Attestation {
+            delegate,
+            contract: Some(contract),
+            issued_at: 10,
+            nonce: request.nonce,
+        }
+        .sign(&keypair)?;
+        let (statement, signature) = split(&msg)?;
+        assert!(keypair.public().verify(statement, signature));
+        assert!(!TransportKeypair::new()
+            .public()
+            .verify(statement, signature));
+        assert_eq!(&statement[..32], &[1; 32]);
+        assert_eq!(&statement[32..64], &[2; 32]);
+        assert_eq!(statement[64], 1);
+        assert_eq!(&statement[65..97], &[3; 32]);
+        assert_eq!(&statement[97..105], &10u64.to_be_bytes());
+        assert_eq!(statement[105] as usize, b"nonce".len());
+        assert_eq!(&statement[106..111], b"nonce");
+        assert_eq!(&statement[113..], keypair.public().to_der());
+        Ok(())
+    }
+}
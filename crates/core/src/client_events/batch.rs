@@ -0,0 +1,248 @@
+//! Batches of client requests carried by a single websocket message, answered by a single
+//! message with the results of every request in the same order.
+//!
+//! Batches are framed as the `FNBT` magic bytes, an id chosen by the client (u64), the number
+//! of entries (u32) and every entry prefixed by its length (u32), all integers big endian.
+//! Requests and results are encoded with the protocol negotiated for the connection, as when
+//! sent on their own.
+//!
+//! Batches framed with the `FNBO` magic bytes instead carry options for their requests after
+//! the id: the time in milliseconds the node has to complete each request (u32, no deadline
+//! when 0) and how many times get, put and subscribe requests which fail or time out are
+//! retried (u8). Requests timing out are stopped and answered with an operation error.
+//!
+//! A batch still running can be cancelled with a message of the `FNCX` magic bytes followed by
+//! the id of the batch (u64). Its requests are dropped, and the node stops the operations
+//! started for them, the batch being answered with an error for every request instead.
+
+use std::{
+    io::{self, Cursor, Read},
+    time::Duration,
+};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+/// Magic bytes prefixing batch messages, no request encoding starts with them.
+const BATCH_MAGIC: [u8; 4] = *b"FNBT";
+
+/// Magic bytes prefixing batch messages with request options.
+const BATCH_OPTIONS_MAGIC: [u8; 4] = *b"FNBO";
+
+/// Magic bytes prefixing the cancellation of a batch.
+const CANCEL_MAGIC: [u8; 4] = *b"FNCX";
+
+/// Max number of requests in a single batch.
+pub(crate) const MAX_BATCH_SIZE: usize = 256;
+
+/// Max number of times a request is retried.
+pub(crate) const MAX_RETRIES: u8 = 5;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Batch<T> {
+    /// Id of the batch, echoed in the message carrying the results.
+    pub id: u64,
+    pub options: RequestOptions,
+    pub entries: Vec<T>,
+}
+
+/// Options applied to every request of a batch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct RequestOptions {
+    /// Time the node has to complete each request, requests wait indefinitely when `None`.
+    pub timeout: Option<Duration>,
+    /// Times get, put and subscribe requests which fail or time out are retried.
+    pub retries: u8,
+}
+
+impl Batch<()> {
+    pub fn is_batch(msg: &[u8]) -> bool {
+        msg.starts_with(&BATCH_MAGIC) || msg.starts_with(&BATCH_OPTIONS_MAGIC)
+    }
+}
+
+impl<'a> Batch<&'a [u8]> {
+    pub fn decode(msg: &'a [u8]) -> io::Result<Self> {
+        let (framed, with_options) = match msg.strip_prefix(&BATCH_MAGIC) {
+            Some(framed) => (framed, false),
+            None => match msg.strip_prefix(&BATCH_OPTIONS_MAGIC) {
+                Some(framed) => (framed, true),
+                None => return Err(io::Error::new(io::ErrorKind::InvalidData, "not a batch")),
+            },
+        };
+        let mut reader = Cursor::new(framed);
+        let id = reader.read_u64::<BigEndian>()?;
+        let mut options = RequestOptions::default();
+        if with_options {
+            let timeout = reader.read_u32::<BigEndian>()?;
+            options.timeout = (timeout > 0).then(|| Duration::from_millis(timeout.into()));
+            options.retries = reader.read_u8()?;
+            if options.retries > MAX_RETRIES {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{} retries, at most {MAX_RETRIES} are allowed",
+                        options.retries
+                    ),
+                ));
+            }
+        }
+        let len = reader.read_u32::<BigEndian>()? as usize;
+        if len > MAX_BATCH_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("batch of {len} requests, at most {MAX_BATCH_SIZE} are allowed"),
+            ));
+        }
+        let mut entries = Vec::with_capacity(len);
+        for _ in 0..len {
+            let size = reader.read_u32::<BigEndian>()? as usize;
+            let start = reader.position() as usize;
+            let entry = framed
+                .get(start..start + size)
+                .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+            reader.set_position((start + size) as u64);
+            entries.push(entry);
+        }
+        if reader.read(&mut [0])? != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "trailing bytes after the batch entries",
+            ));
+        }
+        Ok(Self {
+            id,
+            options,
+            entries,
+        })
+    }
+}
+
+impl<T: AsRef<[u8]>> Batch<T> {
+    pub fn encode(&self) -> io::Result<Vec<u8>> {
+        let size = self
+            .entries
+            .iter()
+            .map(|entry| entry.as_ref().len() + 4)
+            .sum::<usize>();
+        let mut msg = Vec::with_capacity(BATCH_MAGIC.len() + 17 + size);
+        if self.options == RequestOptions::default() {
+            msg.extend_from_slice(&BATCH_MAGIC);
+            msg.write_u64::<BigEndian>(self.id)?;
+        } else {
+            let timeout = self
+                .options
+                .timeout
+                .map_or(0, |timeout| timeout.as_millis());
+            msg.extend_from_slice(&BATCH_OPTIONS_MAGIC);
+            msg.write_u64::<BigEndian>(self.id)?;
+            msg.write_u32::<BigEndian>(u32::try_from(timeout).unwrap_or(u32::MAX))?;
+            msg.write_u8(self.options.retries)?;
+        }
+        msg.write_u32::<BigEndian>(self.entries.len() as u32)?;
+        for entry in &self.entries {
+            let entry = entry.as_ref();
+            msg.write_u32::<BigEndian>(entry.len() as u32)?;
+            msg.extend_from_slice(entry);
+        }
+        Ok(msg)
+    }
+}
+
+/// Cancellation of a running batch.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Cancel {
+    pub id: u64,
+}
+
+impl Cancel {
+    pub fn is_cancel(msg: &[u8]) -> bool {
+        msg.starts_with(&CANCEL_MAGIC)
+    }
+
+    pub fn decode(msg: &[u8]) -> io::Result<Self> {
+        let Some(framed) = msg.strip_prefix(&CANCEL_MAGIC) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a cancellation",
+            ));
+        };
+        let id = <[u8; 8]>::try_from(framed)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid batch id"))?;
+        Ok(Self {
+            id: u64::from_be_bytes(id),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch_framing() -> Result<(), Box<dyn std::error::Error>> {
+        let batch = Batch {
+            id: 7,
+            options: RequestOptions::default(),
+            entries: vec![b"first".to_vec(), vec![], b"third".to_vec()],
+        };
+        let encoded = batch.encode()?;
+        assert!(Batch::is_batch(&encoded));
+        let decoded = Batch::decode(&encoded)?;
+        assert_eq!(decoded.id, 7);
+        assert_eq!(decoded.entries, [&b"first"[..], &[], &b"third"[..]]);
+
+        assert!(Batch::decode(&encoded[..encoded.len() - 1]).is_err());
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert!(Batch::decode(&trailing).is_err());
+
+        let too_large = Batch {
+            id: 1,
+            options: RequestOptions::default(),
+            entries: vec![[0u8; 0]; MAX_BATCH_SIZE + 1],
+        };
+        assert!(Batch::decode(&too_large.encode()?).is_err());
+        assert!(!Batch::is_batch(&bincode::serialize(&0u32)?));
+        Ok(())
+    }
+
+    #[test]
+    fn batch_options_framing() -> Result<(), Box<dyn std::error::Error>> {
+        let options = RequestOptions {
+            timeout: Some(Duration::from_millis(1500)),
+            retries: 2,
+        };
+        let batch = Batch {
+            id: 7,
+            options,
+            entries: vec![b"first".to_vec()],
+        };
+        let encoded = batch.encode()?;
+        assert!(encoded.starts_with(&BATCH_OPTIONS_MAGIC));
+        assert!(Batch::is_batch(&encoded));
+        let decoded = Batch::decode(&encoded)?;
+        assert_eq!(decoded.options, options);
+        assert_eq!(decoded.entries, [&b"first"[..]]);
+
+        let too_many_retries = Batch {
+            id: 7,
+            options: RequestOptions {
+                timeout: None,
+                retries: MAX_RETRIES + 1,
+            },
+            entries: Vec::<Vec<u8>>::new(),
+        };
+        assert!(Batch::decode(&too_many_retries.encode()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn cancel_framing() -> Result<(), Box<dyn std::error::Error>> {
+        let cancel = [&CANCEL_MAGIC[..], &7u64.to_be_bytes()].concat();
+        assert!(Cancel::is_cancel(&cancel));
+        assert!(!Batch::is_batch(&cancel));
+        assert_eq!(Cancel::decode(&cancel)?, Cancel { id: 7 });
+        assert!(Cancel::decode(&cancel[..cancel.len() - 1]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+//! Messages to websocket clients larger than the configured chunk size, streamed as a
+//! sequence of smaller messages the client reassembles.
+//!
+//! Chunks are framed as the `FNCH` magic bytes, the id of the stream (u64), the index of the
+//! chunk (u32), the number of chunks in the stream (u32) and the total size of the message
+//! (u64), followed by the bytes of the chunk, all integers big endian. Chunks of a stream are
+//! sent in order and are not interleaved with other messages.
+
+use std::io;
+
+use byteorder::{BigEndian, WriteBytesExt};
+
+/// Magic bytes prefixing chunks, no response encoding starts with them.
+const CHUNK_MAGIC: [u8; 4] = *b"FNCH";
+
+const HEADER_SIZE: usize = CHUNK_MAGIC.len() + 8 + 4 + 4 + 8;
+
+#[derive(Debug, PartialEq, Eq)]
+struct Chunk<'a> {
+    stream: u64,
+    index: u32,
+    count: u32,
+    /// Size of the whole message.
+    total_size: u64,
+    data: &'a [u8],
+}
+
+impl Chunk<'_> {
+    fn encode(&self) -> io::Result<Vec<u8>> {
+        let mut msg = Vec::with_capacity(HEADER_SIZE + self.data.len());
+        msg.extend_from_slice(&CHUNK_MAGIC);
+        msg.write_u64::<BigEndian>(self.stream)?;
+        msg.write_u32::<BigEndian>(self.index)?;
+        msg.write_u32::<BigEndian>(self.count)?;
+        msg.write_u64::<BigEndian>(self.total_size)?;
+        msg.extend_from_slice(self.data);
+        Ok(msg)
+    }
+}
+
+/// Splits the messages sent to a client which are over the chunk size.
+#[derive(Debug)]
+pub(crate) struct Chunker {
+    /// Max size of the data in each chunk, messages are never split when `None`.
+    chunk_size: Option<usize>,
+    next_stream: u64,
+}
+
+impl Chunker {
+    pub fn new(chunk_size: Option<usize>) -> Self {
+        Self {
+            chunk_size: chunk_size.filter(|size| *size > 0),
+            next_stream: 0,
+        }
+    }
+
+    /// The messages to send in place of `msg`, which is returned as is if it fits.
+    pub fn split(&mut self, msg: Vec<u8>) -> io::Result<Vec<Vec<u8>>> {
+        let chunk_size = match self.chunk_size {
+            Some(chunk_size) if msg.len() > chunk_size => chunk_size,
+            _ => return Ok(vec![msg]),
+        };
+        let stream = self.next_stream;
+        self.next_stream += 1;
+        let count = u32::try_from(msg.len().div_ceil(chunk_size))
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "message too large"))?;
+        msg.chunks(chunk_size)
+            .enumerate()
+            .map(|(index, data)| {
+                Chunk {
+                    stream,
+                    index: index as u32,
+                    count,
+                    total_size: msg.len() as u64,
+                    data,
+                }
+                .encode()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use byteorder::ReadBytesExt;
+
+    use super::*;
+
+    fn decode(msg: &[u8]) -> io::Result<Chunk<'_>> {
+        let Some(framed) = msg.strip_prefix(&CHUNK_MAGIC) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a chunk"));
+        };
+        let mut reader = Cursor::new(framed);
+        let stream = reader.read_u64::<BigEndian>()?;
+        let index = reader.read_u32::<BigEndian>()?;
+        let count = reader.read_u32::<BigEndian>()?;
+        let total_size = reader.read_u64::<BigEndian>()?;
+        if index >= count {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("chunk {index} of a stream of {count} chunks"),
+            ));
+        }
+        let data = &framed[reader.position() as usize..];
+        Ok(Chunk {
+            stream,
+            index,
+            count,
+            total_size,
+            data,
+        })
+    }
+
+    /// Reassembles the message streamed in `chunks`, all of them of the same stream and in order.
+    fn reassemble(chunks: &[Vec<u8>]) -> io::Result<Vec<u8>> {
+        let mut msg = Vec::new();
+        let mut stream = None;
+        for (expected, chunk) in chunks.iter().enumerate() {
+            let chunk = decode(chunk)?;
+            if *stream.get_or_insert(chunk.stream) != chunk.stream
+                || chunk.index as usize != expected
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "chunk out of order",
+                ));
+            }
+            msg.extend_from_slice(chunk.data);
+        }
+        Ok(msg)
+    }
+
+    #[test]
+    fn split_messages() -> Result<(), Box<dyn std::error::Error>> {
+        let mut chunker = Chunker::new(Some(4));
+        assert_eq!(chunker.split(b"tiny".to_vec())?, [b"tiny".to_vec()]);
+
+        let msg = b"a state of 22 bytes...".to_vec();
+        let chunks = chunker.split(msg.clone())?;
+        assert_eq!(chunks.len(), 6);
+        let last = decode(&chunks[5])?;
+        assert_eq!((last.stream, last.index, last.count), (0, 5, 6));
+        assert_eq!(last.total_size, 22);
+        assert_eq!(last.data, b"..");
+        assert_eq!(reassemble(&chunks)?, msg);
+
+        // every split message is a new stream
+        let chunks = chunker.split(msg.clone())?;
+        assert_eq!(decode(&chunks[0])?.stream, 1);
+        assert!(reassemble(&[chunks[1].clone(), chunks[0].clone()]).is_err());
+
+        let mut disabled = Chunker::new(Some(0));
+        assert_eq!(disabled.split(msg.clone())?, [msg]);
+        Ok(())
+    }
+}
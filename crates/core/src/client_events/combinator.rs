@@ -13,11 +13,17 @@ type HostIncomingMsg = Result<OpenRequest<'static>, ClientError>;
 type ClientEventsFut =
     BoxFuture<'static, (usize, Receiver<HostIncomingMsg>, Option<HostIncomingMsg>)>;
 
+/// Response of the host to a client of one of the combined proxies.
+enum HostMsg {
+    Result(HostResult),
+    StateConflict(ClientError),
+}
+
 /// This type allows combining different sources of events into one and interoperation between them.
 pub struct ClientEventsCombinator<const N: usize> {
     pending_futs: FuturesUnordered<ClientEventsFut>,
     /// receiving end of the different client applications from the node
-    clients: [Sender<(ClientId, HostResult)>; N],
+    clients: [Sender<(ClientId, HostMsg)>; N],
     /// a map of the individual protocols, external, sending client events ids to an internal list of ids
     external_clients: [HashMap<ClientId, ClientId>; N],
     /// a map of the external id to which protocol it belongs (represented by the index in the array)
@@ -78,6 +84,7 @@ impl<const N: usize> super::ClientEventsProxy for ClientEventsCombinator<N> {
                             client_id: external,
                             request,
                             notification_channel,
+                            subscription_mode,
                             token,
                             attested_contract,
                         }) => {
@@ -94,6 +101,7 @@ impl<const N: usize> super::ClientEventsProxy for ClientEventsCombinator<N> {
                                 client_id: id,
                                 request,
                                 notification_channel,
+                                subscription_mode,
                                 token,
                                 attested_contract
                             })
@@ -121,31 +129,49 @@ impl<const N: usize> super::ClientEventsProxy for ClientEventsCombinator<N> {
         internal: ClientId,
         response: Result<HostResponse, ClientError>,
     ) -> BoxFuture<'_, Result<(), ClientError>> {
-        async move {
-            let (idx, external) = self
-                .internal_clients
-                .get(&internal)
-                .ok_or(ErrorKind::UnknownClient(internal.0))?;
-            self.clients[*idx]
-                .send((*external, response))
-                .await
-                .map_err(|_| ErrorKind::TransportProtocolDisconnect)?;
-            Ok(())
-        }
-        .boxed()
+        self.forward(internal, HostMsg::Result(response)).boxed()
+    }
+
+    fn send_state_conflict(
+        &mut self,
+        internal: ClientId,
+        error: ClientError,
+    ) -> BoxFuture<'_, Result<(), ClientError>> {
+        self.forward(internal, HostMsg::StateConflict(error))
+            .boxed()
+    }
+}
+
+impl<const N: usize> ClientEventsCombinator<N> {
+    async fn forward(&mut self, internal: ClientId, msg: HostMsg) -> Result<(), ClientError> {
+        let (idx, external) = self
+            .internal_clients
+            .get(&internal)
+            .ok_or(ErrorKind::UnknownClient(internal.0))?;
+        self.clients[*idx]
+            .send((*external, msg))
+            .await
+            .map_err(|_| ErrorKind::TransportProtocolDisconnect)?;
+        Ok(())
     }
 }
 
 async fn client_fn(
     mut client: BoxedClient,
-    mut rx: Receiver<(ClientId, HostResult)>,
+    mut rx: Receiver<(ClientId, HostMsg)>,
     tx_host: Sender<Result<OpenRequest<'static>, ClientError>>,
 ) {
     loop {
         tokio::select! {
             host_msg = rx.recv() => {
-                if let Some((client_id, response)) = host_msg {
-                    if client.send(client_id, response).await.is_err() {
+                if let Some((client_id, msg)) = host_msg {
+                    let sent = match msg {
+                        HostMsg::Result(response) => client.send(client_id, response).await,
+                        HostMsg::StateConflict(error) => {
+                            client.send_state_conflict(client_id, error).await
+                        }
+                    };
+                    if sent.is_err() {
                         break;
                     }
                 } else {
@@ -155,9 +181,9 @@ async fn client_fn(
             }
             client_msg = client.recv() => {
                 match client_msg {
-                    Ok(OpenRequest { client_id,  request, notification_channel, token, attested_contract }) => {
+                    Ok(OpenRequest { client_id,  request, notification_channel, subscription_mode, token, attested_contract }) => {
                         tracing::debug!("received msg @ combinator from external id {client_id}, msg: {request}");
-                        if tx_host.send(Ok(OpenRequest { client_id,  request, notification_channel, token, attested_contract })).await.is_err() {
+                        if tx_host.send(Ok(OpenRequest { client_id,  request, notification_channel, subscription_mode, token, attested_contract })).await.is_err() {
                             break;
                         }
                     }
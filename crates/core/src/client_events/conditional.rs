@@ -0,0 +1,41 @@
+//! Conditional updates, so applications can implement optimistic concurrency instead of racing
+//! blind when several clients update the same contract.
+//!
+//! Websocket clients make an update conditional by sending it framed as the `FNIF` magic bytes,
+//! the BLAKE3 hash of the state they expect the contract to have (32 bytes) and the request
+//! encoded as for other requests. The node rejects the update without merging it if the state it
+//! holds differs, with an error of the `state-conflict` code.
+
+/// Magic bytes prefixing conditional requests.
+const CONDITIONAL_MAGIC: [u8; 4] = *b"FNIF";
+
+const HASH_LEN: usize = blake3::OUT_LEN;
+
+/// Splits a conditional request into the hash of the state expected and the request, `None` if
+/// it isn't conditional.
+pub(crate) fn split(msg: &[u8]) -> Option<(blake3::Hash, &[u8])> {
+    let framed = msg.strip_prefix(&CONDITIONAL_MAGIC)?;
+    if framed.len() < HASH_LEN {
+        return None;
+    }
+    let (hash, request) = framed.split_at(HASH_LEN);
+    let hash: [u8; HASH_LEN] = hash.try_into().ok()?;
+    Some((blake3::Hash::from(hash), request))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_conditional_requests() {
+        let expected = blake3::hash(b"state");
+        let mut msg = b"FNIF".to_vec();
+        msg.extend_from_slice(expected.as_bytes());
+        msg.extend_from_slice(b"request");
+        assert_eq!(split(&msg), Some((expected, &b"request"[..])));
+
+        assert_eq!(split(b"request"), None);
+        assert_eq!(split(b"FNIF1234"), None);
+    }
+}
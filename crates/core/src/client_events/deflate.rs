@@ -0,0 +1,139 @@
+//! Compression of the messages sent to websocket clients, so large states and updates shrink on
+//! the wire.
+//!
+//! This is not the permessage-deflate extension (RFC 7692): the websocket implementation of the
+//! node doesn't support extensions, the frames it receives with the RSV1 bit set closing the
+//! connection, so `Sec-WebSocket-Extensions` offers are ignored and the compression is negotiated
+//! and framed by the API itself instead. Clients, browsers included, get uncompressed messages
+//! unless they opt in, and those opting in inflate the messages themselves, e.g. with a
+//! `DecompressionStream("deflate-raw")` in browsers.
+//!
+//! Clients ask for it by connecting with `compression=deflate` in the query of the URL, and the
+//! node answers the upgrade with the `compression: deflate` header when granted. Messages of at
+//! least [`MIN_SIZE`] bytes are then sent as the `FNDF` magic bytes followed by the message
+//! compressed with raw deflate. As with permessage-deflate, the compression context of the
+//! connection is kept across messages, so clients must inflate the messages in the order they are
+//! received with a single context, and each message ends with a sync flush so it is inflated
+//! whole on arrival. Messages streamed in chunks are compressed before being split.
+//!
+//! Each connection compresses its messages with a single context, of a few hundred kB of memory.
+//! The contexts are drawn from a pool shared by all the connections, sized by the
+//! `compression-contexts` setting, so connections granted none are sent their messages
+//! uncompressed.
+
+use std::{io, sync::Arc};
+
+use axum::http::HeaderName;
+use flate2::{Compress, Compression, FlushCompress};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Magic bytes prefixing compressed messages, no response encoding starts with them.
+const DEFLATE_MAGIC: [u8; 4] = *b"FNDF";
+
+/// Messages smaller than this are sent uncompressed, as compressing them saves little.
+pub(crate) const MIN_SIZE: usize = 1024;
+
+/// Header the node answers upgrades with when compression is granted.
+pub(crate) static COMPRESSION: HeaderName = HeaderName::from_static("compression");
+
+/// Compression of the messages sent to a client, as requested in the connection URL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum MessageCompression {
+    Deflate,
+}
+
+/// Compression contexts available to the connections of the websocket API.
+#[derive(Clone, Debug)]
+pub(crate) struct CompressionContexts(Arc<Semaphore>);
+
+impl CompressionContexts {
+    pub fn new(max: usize) -> Self {
+        Self(Arc::new(Semaphore::new(max)))
+    }
+
+    /// A compression context for a connection, `None` if all of them are in use.
+    pub fn acquire(&self) -> Option<Deflater> {
+        let context = self.0.clone().try_acquire_owned().ok()?;
+        Some(Deflater {
+            compress: Compress::new(Compression::default(), false),
+            _context: context,
+        })
+    }
+}
+
+/// Compression context of a connection, returned to the available ones when dropped.
+pub(crate) struct Deflater {
+    compress: Compress,
+    _context: OwnedSemaphorePermit,
+}
+
+impl std::fmt::Debug for Deflater {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Deflater").finish_non_exhaustive()
+    }
+}
+
+impl Deflater {
+    /// The message to send in place of `msg`, which is returned as is if it's too small.
+    pub fn deflate(&mut self, msg: Vec<u8>) -> io::Result<Vec<u8>> {
+        if msg.len() < MIN_SIZE {
+            return Ok(msg);
+        }
+        let mut compressed = Vec::with_capacity(DEFLATE_MAGIC.len() + msg.len() / 2 + 64);
+        compressed.extend_from_slice(&DEFLATE_MAGIC);
+        let mut input = msg.as_slice();
+        loop {
+            if compressed.capacity() - compressed.len() < 64 {
+                compressed.reserve(compressed.capacity());
+            }
+            let consumed = self.compress.total_in();
+            self.compress
+                .compress_vec(input, &mut compressed, FlushCompress::Sync)
+                .map_err(io::Error::other)?;
+            input = &input[(self.compress.total_in() - consumed) as usize..];
+            // the flush is complete once the output wasn't filled
+            if input.is_empty() && compressed.len() < compressed.capacity() {
+                return Ok(compressed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::{Decompress, FlushDecompress};
+
+    use super::*;
+
+    fn inflate(decompress: &mut Decompress, msg: &[u8]) -> Vec<u8> {
+        let compressed = msg.strip_prefix(&DEFLATE_MAGIC).unwrap();
+        let mut msg = Vec::with_capacity(compressed.len() * 20);
+        decompress
+            .decompress_vec(compressed, &mut msg, FlushDecompress::Sync)
+            .unwrap();
+        msg
+    }
+
+    #[test]
+    fn compresses_large_messages_keeping_the_context() {
+        let contexts = CompressionContexts::new(1);
+        let mut deflater = contexts.acquire().unwrap();
+        assert!(contexts.acquire().is_none());
+
+        assert_eq!(deflater.deflate(b"small".to_vec()).unwrap(), b"small");
+        let msg = b"contract state ".repeat(200);
+        let first = deflater.deflate(msg.clone()).unwrap();
+        assert!(first.len() < msg.len() / 4);
+        let second = deflater.deflate(msg.clone()).unwrap();
+        // the second message refers to the first one
+        assert!(second.len() < first.len());
+
+        let mut decompress = Decompress::new(false);
+        assert_eq!(inflate(&mut decompress, &first), msg);
+        assert_eq!(inflate(&mut decompress, &second), msg);
+
+        drop(deflater);
+        assert!(contexts.acquire().is_some());
+    }
+}
@@ -0,0 +1,84 @@
+//! Options of the requests of websocket clients, framed before the encoded request.
+//!
+//! Each option is framed as its magic bytes and value, and clients prefix the request with the
+//! options they need, in any order:
+//!
+//! - `FNPR`, the priority of the operation, see [`super::priority`].
+//! - `FNRT`, the retry policy of gets, puts and subscriptions, see [`super::retry`].
+//! - `FNQY` or `FNSM`, the query answered instead of the state by gets, see [`super::query`].
+//! - `FNIF`, the state expected by conditional updates, see [`super::conditional`].
+//!
+//! Requests giving the same option twice are rejected.
+
+use crate::{config::RetryPolicy, message::Priority, operations::get::GetQuery};
+
+use super::{conditional, priority, query, retry};
+
+/// Options framed before a request.
+#[derive(Debug, Default)]
+pub(crate) struct RequestEnvelope {
+    pub priority: Option<Priority>,
+    pub retry_policy: Option<RetryPolicy>,
+    pub query: Option<GetQuery>,
+    pub expected_state: Option<blake3::Hash>,
+}
+
+/// Splits a message into the options framed before the request and the request.
+pub(crate) fn split(mut msg: &[u8]) -> Result<(RequestEnvelope, &[u8]), &'static str> {
+    let mut envelope = RequestEnvelope::default();
+    loop {
+        if let Some((priority, request)) = priority::split(msg) {
+            set_once(&mut envelope.priority, priority)?;
+            msg = request;
+        } else if let Some((retry_policy, request)) = retry::split(msg) {
+            set_once(&mut envelope.retry_policy, retry_policy)?;
+            msg = request;
+        } else if let Some((query, request)) = query::split(msg) {
+            set_once(&mut envelope.query, query)?;
+            msg = request;
+        } else if let Some((expected_state, request)) = conditional::split(msg) {
+            set_once(&mut envelope.expected_state, expected_state)?;
+            msg = request;
+        } else {
+            return Ok((envelope, msg));
+        }
+    }
+}
+
+fn set_once<T>(option: &mut Option<T>, value: T) -> Result<(), &'static str> {
+    if option.replace(value).is_some() {
+        return Err("request option given twice");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_options_in_any_order() {
+        let expected = blake3::hash(b"state");
+        let mut conditional = b"FNIF".to_vec();
+        conditional.extend_from_slice(expected.as_bytes());
+        let priority = b"FNPR\x01";
+
+        for msg in [
+            [&conditional[..], priority, b"request"].concat(),
+            [&priority[..], &conditional, b"request"].concat(),
+        ] {
+            let (envelope, request) = split(&msg).unwrap();
+            assert_eq!(envelope.expected_state, Some(expected));
+            assert_eq!(envelope.priority, Some(Priority::Background));
+            assert!(envelope.retry_policy.is_none() && envelope.query.is_none());
+            assert_eq!(request, b"request");
+        }
+
+        let (envelope, request) = split(b"request").unwrap();
+        assert!(envelope.priority.is_none() && envelope.expected_state.is_none());
+        assert_eq!(request, b"request");
+
+        let twice = [&priority[..], priority, b"request"].concat();
+        assert!(split(&twice).is_err());
+    }
+}
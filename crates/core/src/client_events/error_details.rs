@@ -0,0 +1,310 @@
+//! Stable codes and structured details of the errors returned to clients, so SDKs can decide
+//! whether to retry a request or how to present its failure without parsing error messages,
+//! which are kept for humans.
+//!
+//! Details are serialized as JSON objects with the code of the error, the phase of the request
+//! it happened at and the contract it is about when known, whether retrying the request may
+//! succeed and the message of the error. Codes and phases are serialized in kebab case and are
+//! never renamed nor reused; new ones may be added, which clients should handle as
+//! [`ErrorCode::Internal`].
+//!
+//! Websocket clients speaking version 4 of the protocol or later are answered errors framed as
+//! the `FNER` magic bytes, the size of the encoded error (u32, big endian), the error encoded
+//! as for older clients and its details.
+
+use std::io;
+
+use byteorder::{BigEndian, WriteBytesExt};
+use freenet_stdlib::{
+    client_api::{ClientError, ContractError, DelegateError, ErrorKind, RequestError},
+    prelude::ContractInstanceId,
+};
+use serde::{Deserialize, Serialize};
+
+/// Magic bytes prefixing errors framed with their details.
+const ERROR_MAGIC: [u8; 4] = *b"FNER";
+
+/// Code of an error returned to a client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCode {
+    /// The connection to the node was lost before the request completed.
+    Disconnected,
+    /// The node can't handle requests at the moment.
+    NodeUnavailable,
+    /// The node is shutting down.
+    Shutdown,
+    /// The request didn't complete in time.
+    Timeout,
+    /// The request is malformed.
+    InvalidRequest,
+    /// The client isn't authorized to make the request.
+    Unauthorized,
+    /// The node refused to handle the request, e.g. because of the limits of the client.
+    Rejected,
+    /// The client isn't known to the node.
+    UnknownClient,
+    /// The contract isn't known to the node.
+    ContractNotFound,
+    /// A contract required by the contract of the request is missing.
+    MissingRelatedContract,
+    /// The contract failed handling the request, e.g. rejecting an invalid state.
+    ContractFailed,
+    /// The state of the contract differs from the one a conditional update expected.
+    StateConflict,
+    /// The delegate isn't registered in the node.
+    DelegateNotFound,
+    /// The delegate failed handling the request.
+    DelegateFailed,
+    /// The operation failed in the network, e.g. finding no peer holding the contract.
+    OperationFailed,
+    /// Any other failure of the node.
+    Internal,
+}
+
+impl ErrorCode {
+    /// Whether retrying a request failing with this error may succeed.
+    pub fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            Self::Disconnected | Self::NodeUnavailable | Self::Timeout | Self::OperationFailed
+        )
+    }
+}
+
+/// Phase of a request an error happened at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorPhase {
+    /// Reaching the node.
+    Connection,
+    /// Decoding and checking the request, before starting any operation for it.
+    Validation,
+    Put,
+    Get,
+    Update,
+    Subscribe,
+    Delegate,
+}
+
+/// Details of an error returned to a client.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorDetails {
+    pub code: ErrorCode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phase: Option<ErrorPhase>,
+    /// Instance id of the contract the error is about.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract: Option<String>,
+    pub retryable: bool,
+    pub message: String,
+}
+
+impl ErrorDetails {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            phase: None,
+            contract: None,
+            retryable: code.is_retryable(),
+            message: message.into(),
+        }
+    }
+
+    pub fn with_phase(mut self, phase: ErrorPhase) -> Self {
+        self.phase = Some(phase);
+        self
+    }
+
+    pub fn with_contract(mut self, contract: &ContractInstanceId) -> Self {
+        self.contract = Some(contract.to_string());
+        self
+    }
+
+    /// Details of the error rejecting a conditional update of a contract whose state differs
+    /// from the one expected, which can't be told apart from other update errors by itself.
+    pub fn state_conflict(error: &ClientError) -> Self {
+        Self {
+            code: ErrorCode::StateConflict,
+            retryable: ErrorCode::StateConflict.is_retryable(),
+            ..Self::from(error)
+        }
+    }
+
+    /// Details of an error of the node, described by `message`.
+    pub fn of_kind(kind: &ErrorKind, message: impl Into<String>) -> Self {
+        let (code, phase) = match kind {
+            ErrorKind::Disconnect
+            | ErrorKind::ChannelClosed
+            | ErrorKind::TransportProtocolDisconnect
+            | ErrorKind::RequestError(RequestError::Disconnect) => {
+                (ErrorCode::Disconnected, Some(ErrorPhase::Connection))
+            }
+            ErrorKind::NodeUnavailable => {
+                (ErrorCode::NodeUnavailable, Some(ErrorPhase::Connection))
+            }
+            ErrorKind::Shutdown => (ErrorCode::Shutdown, Some(ErrorPhase::Connection)),
+            ErrorKind::UnknownClient(_) => (ErrorCode::UnknownClient, Some(ErrorPhase::Connection)),
+            ErrorKind::DeserializationError { .. } => {
+                (ErrorCode::InvalidRequest, Some(ErrorPhase::Validation))
+            }
+            ErrorKind::Unhandled { .. } => (ErrorCode::Rejected, Some(ErrorPhase::Validation)),
+            ErrorKind::OperationError { .. } | ErrorKind::FailedOperation => {
+                (ErrorCode::OperationFailed, None)
+            }
+            ErrorKind::RequestError(RequestError::ContractError(error)) => {
+                return Self::of_contract_error(error, message)
+            }
+            ErrorKind::RequestError(RequestError::DelegateError(DelegateError::Missing(_))) => {
+                (ErrorCode::DelegateNotFound, Some(ErrorPhase::Delegate))
+            }
+            ErrorKind::RequestError(RequestError::DelegateError(_)) => {
+                (ErrorCode::DelegateFailed, Some(ErrorPhase::Delegate))
+            }
+            ErrorKind::RequestError(RequestError::Timeout) => (ErrorCode::Timeout, None),
+            _ => (ErrorCode::Internal, None),
+        };
+        Self {
+            phase,
+            ..Self::new(code, message)
+        }
+    }
+
+    fn of_contract_error(error: &ContractError, message: impl Into<String>) -> Self {
+        let (code, phase, contract) = match error {
+            ContractError::Put { key, .. } => {
+                (ErrorCode::ContractFailed, Some(ErrorPhase::Put), key.id())
+            }
+            ContractError::Get { key, .. } => {
+                (ErrorCode::ContractFailed, Some(ErrorPhase::Get), key.id())
+            }
+            ContractError::Update { key, .. } => (
+                ErrorCode::ContractFailed,
+                Some(ErrorPhase::Update),
+                key.id(),
+            ),
+            ContractError::Subscribe { key, .. } => (
+                ErrorCode::ContractFailed,
+                Some(ErrorPhase::Subscribe),
+                key.id(),
+            ),
+            ContractError::MissingContract { key } => (ErrorCode::ContractNotFound, None, key),
+            ContractError::MissingRelated { key } => (ErrorCode::MissingRelatedContract, None, key),
+            _ => return Self::new(ErrorCode::ContractFailed, message),
+        };
+        Self {
+            phase,
+            ..Self::new(code, message).with_contract(contract)
+        }
+    }
+}
+
+/// Frames an error encoded as a response to a websocket client with its details.
+pub(crate) fn frame(encoded_error: &[u8], details: &ErrorDetails) -> io::Result<Vec<u8>> {
+    let details = serde_json::to_vec(details)?;
+    let mut msg = Vec::with_capacity(ERROR_MAGIC.len() + 4 + encoded_error.len() + details.len());
+    msg.extend_from_slice(&ERROR_MAGIC);
+    msg.write_u32::<BigEndian>(encoded_error.len() as u32)?;
+    msg.extend_from_slice(encoded_error);
+    msg.extend_from_slice(&details);
+    Ok(msg)
+}
+
+impl From<&ClientError> for ErrorDetails {
+    fn from(error: &ClientError) -> Self {
+        Self::of_kind(error.kind(), error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use freenet_stdlib::prelude::ContractKey;
+
+    use super::*;
+
+    #[test]
+    fn error_codes() -> Result<(), Box<dyn std::error::Error>> {
+        let key = ContractKey::from(ContractInstanceId::new([1; 32]));
+        let error = ClientError::from(ErrorKind::RequestError(
+            ContractError::Update {
+                key,
+                cause: "invalid state".into(),
+            }
+            .into(),
+        ));
+        let details = ErrorDetails::from(&error);
+        assert_eq!(details.code, ErrorCode::ContractFailed);
+        assert_eq!(details.phase, Some(ErrorPhase::Update));
+        assert_eq!(details.contract, Some(key.id().to_string()));
+        assert!(!details.retryable);
+        assert_eq!(details.message, error.to_string());
+        assert_eq!(
+            serde_json::to_value(&details)?,
+            serde_json::json!({
+                "code": "contract-failed",
+                "phase": "update",
+                "contract": key.id().to_string(),
+                "retryable": false,
+                "message": error.to_string(),
+            })
+        );
+
+        let missing = ClientError::from(ErrorKind::RequestError(
+            ContractError::MissingContract { key: *key.id() }.into(),
+        ));
+        let details = ErrorDetails::from(&missing);
+        assert_eq!(details.code, ErrorCode::ContractNotFound);
+        assert_eq!(details.phase, None);
+
+        let details = ErrorDetails::from(&ClientError::from(ErrorKind::FailedOperation));
+        assert_eq!(details.code, ErrorCode::OperationFailed);
+        assert!(details.retryable);
+        assert_eq!(
+            serde_json::to_value(&details)?,
+            serde_json::json!({
+                "code": "operation-failed",
+                "retryable": true,
+                "message": details.message,
+            })
+        );
+        let details = ErrorDetails::from(&ClientError::from(ErrorKind::Unhandled {
+            cause: "Too many operations in flight".into(),
+        }));
+        assert_eq!(details.code, ErrorCode::Rejected);
+        assert_eq!(details.phase, Some(ErrorPhase::Validation));
+
+        let conflict = ClientError::from(ErrorKind::RequestError(
+            ContractError::Update {
+                key,
+                cause: "state conflict, the contract has no state".into(),
+            }
+            .into(),
+        ));
+        // only reported as such by the node
+        assert_eq!(
+            ErrorDetails::from(&conflict).code,
+            ErrorCode::ContractFailed
+        );
+        let details = ErrorDetails::state_conflict(&conflict);
+        assert_eq!(details.code, ErrorCode::StateConflict);
+        assert_eq!(details.contract, Some(key.id().to_string()));
+        assert_eq!(details.phase, Some(ErrorPhase::Update));
+        assert!(!details.retryable);
+        Ok(())
+    }
+
+    #[test]
+    fn error_framing() -> Result<(), Box<dyn std::error::Error>> {
+        let details = ErrorDetails::new(ErrorCode::Timeout, "timed out");
+        assert!(details.retryable);
+        let framed = frame(b"error", &details)?;
+        assert_eq!(&framed[..4], b"FNER");
+        assert_eq!(&framed[4..8], &5u32.to_be_bytes());
+        assert_eq!(&framed[8..13], b"error");
+        assert_eq!(
+            serde_json::from_slice::<ErrorDetails>(&framed[13..])?,
+            details
+        );
+        Ok(())
+    }
+}
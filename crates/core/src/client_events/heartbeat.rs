@@ -0,0 +1,194 @@
+//! Heartbeats reporting websocket clients the round-trip times measured by the node, so
+//! latency-sensitive apps can show users the quality of their connection.
+//!
+//! Clients ask for heartbeats with a message of the `FNHB` magic bytes followed by the interval
+//! between them in milliseconds (u32), heartbeats being stopped when it is 0. Every interval the
+//! node sends a heartbeat and pings the client, the time until the pong answering it being
+//! reported by the next heartbeat. Heartbeats are framed as the `FNHB` magic bytes, the
+//! round-trip time to the client, the number of peers of the node the round-trip time is known
+//! for (u16) and the round-trip time to each of them. Round-trip times are in milliseconds
+//! (u32, `u32::MAX` for the client until measured), all integers big endian.
+
+use std::{
+    io::{self, Cursor},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+/// Magic bytes prefixing heartbeat requests and heartbeats.
+const HEARTBEAT_MAGIC: [u8; 4] = *b"FNHB";
+
+/// Min interval between heartbeats.
+pub(crate) const MIN_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Round-trip time not measured yet.
+const UNKNOWN: u32 = u32::MAX;
+
+/// Request to start or stop sending heartbeats.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct HeartbeatRequest {
+    /// Interval between heartbeats, `None` to stop them.
+    pub interval: Option<Duration>,
+}
+
+impl HeartbeatRequest {
+    pub fn is_request(msg: &[u8]) -> bool {
+        msg.starts_with(&HEARTBEAT_MAGIC)
+    }
+
+    pub fn decode(msg: &[u8]) -> io::Result<Self> {
+        let Some(framed) = msg.strip_prefix(&HEARTBEAT_MAGIC) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a heartbeat request",
+            ));
+        };
+        if framed.len() != 4 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid heartbeat interval",
+            ));
+        }
+        let millis = Cursor::new(framed).read_u32::<BigEndian>()?;
+        if millis == 0 {
+            return Ok(Self { interval: None });
+        }
+        let interval = Duration::from_millis(millis.into());
+        if interval < MIN_INTERVAL {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "heartbeats every {millis} ms, at least {} ms apart are allowed",
+                    MIN_INTERVAL.as_millis()
+                ),
+            ));
+        }
+        Ok(Self {
+            interval: Some(interval),
+        })
+    }
+}
+
+/// Round-trip times reported to a client.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Heartbeat {
+    pub client: Option<Duration>,
+    pub peers: Vec<Duration>,
+}
+
+impl Heartbeat {
+    pub fn encode(&self) -> io::Result<Vec<u8>> {
+        let peers = &self.peers[..self.peers.len().min(u16::MAX as usize)];
+        let mut msg = Vec::with_capacity(HEARTBEAT_MAGIC.len() + 6 + peers.len() * 4);
+        msg.extend_from_slice(&HEARTBEAT_MAGIC);
+        msg.write_u32::<BigEndian>(self.client.map_or(UNKNOWN, millis))?;
+        msg.write_u16::<BigEndian>(peers.len() as u16)?;
+        for rtt in peers {
+            msg.write_u32::<BigEndian>(millis(*rtt))?;
+        }
+        Ok(msg)
+    }
+}
+
+fn millis(duration: Duration) -> u32 {
+    u32::try_from(duration.as_millis()).unwrap_or(UNKNOWN - 1)
+}
+
+/// Round-trip time of the pings sent to a client.
+#[derive(Clone, Default)]
+pub(crate) struct ClientRoundTrip(Arc<Mutex<PingState>>);
+
+#[derive(Default)]
+struct PingState {
+    next_id: u64,
+    /// Ping waiting for its pong, and when it was sent.
+    pending: Option<(u64, Instant)>,
+    last: Option<Duration>,
+}
+
+impl ClientRoundTrip {
+    /// Payload of a new ping to the client, pings still waiting for their pong are not timed.
+    pub fn ping(&self) -> Vec<u8> {
+        let mut state = self.0.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.pending = Some((id, Instant::now()));
+        id.to_be_bytes().to_vec()
+    }
+
+    /// Times the ping answered by a pong with `payload`.
+    pub fn pong(&self, payload: &[u8]) {
+        let Ok(id) = <[u8; 8]>::try_from(payload).map(u64::from_be_bytes) else {
+            return;
+        };
+        let mut state = self.0.lock().unwrap();
+        if let Some((pending, sent_at)) = state.pending {
+            if pending == id {
+                state.pending = None;
+                state.last = Some(sent_at.elapsed());
+            }
+        }
+    }
+
+    /// Round-trip time of the last ping answered.
+    pub fn get(&self) -> Option<Duration> {
+        self.0.lock().unwrap().last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heartbeat_framing() -> Result<(), Box<dyn std::error::Error>> {
+        let request = [&HEARTBEAT_MAGIC[..], &1000u32.to_be_bytes()].concat();
+        assert!(HeartbeatRequest::is_request(&request));
+        assert_eq!(
+            HeartbeatRequest::decode(&request)?.interval,
+            Some(Duration::from_secs(1))
+        );
+        let stop = [&HEARTBEAT_MAGIC[..], &[0; 4]].concat();
+        assert_eq!(HeartbeatRequest::decode(&stop)?.interval, None);
+        let too_often = [&HEARTBEAT_MAGIC[..], &10u32.to_be_bytes()].concat();
+        assert!(HeartbeatRequest::decode(&too_often).is_err());
+        assert!(HeartbeatRequest::decode(&request[..6]).is_err());
+
+        let heartbeat = Heartbeat {
+            client: Some(Duration::from_millis(25)),
+            peers: vec![Duration::from_millis(80), Duration::from_millis(120)],
+        };
+        assert_eq!(
+            heartbeat.encode()?,
+            [
+                &HEARTBEAT_MAGIC[..],
+                &25u32.to_be_bytes(),
+                &2u16.to_be_bytes(),
+                &80u32.to_be_bytes(),
+                &120u32.to_be_bytes()
+            ]
+            .concat()
+        );
+        assert_eq!(
+            Heartbeat::default().encode()?,
+            [&HEARTBEAT_MAGIC[..], &UNKNOWN.to_be_bytes(), &[0; 2]].concat()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn client_round_trip() {
+        let round_trip = ClientRoundTrip::default();
+        let first = round_trip.ping();
+        let second = round_trip.ping();
+        // only the last ping is timed
+        round_trip.pong(&first);
+        assert_eq!(round_trip.get(), None);
+        round_trip.pong(&second);
+        assert!(round_trip.get().is_some());
+        round_trip.pong(b"unknown");
+        assert!(round_trip.get().is_some());
+    }
+}
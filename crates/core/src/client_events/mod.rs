@@ -14,6 +14,7 @@ use std::collections::HashSet;
 use std::fmt::Display;
 use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
+use std::time::Duration;
 use std::{convert::Infallible, fmt::Debug};
 use tracing::Instrument;
 
@@ -21,13 +22,50 @@ use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::{self, UnboundedSender};
 
 use crate::contract::{ClientResponsesReceiver, ContractHandlerEvent};
-use crate::message::{NodeEvent, QueryResult};
+use crate::message::{NodeEvent, Priority, QueryResult};
 use crate::node::OpManager;
-use crate::operations::{get, put, update, OpError};
-use crate::{config::GlobalExecutor, contract::StoreResponse};
+use crate::operations::{
+    get::{self, GetQuery},
+    put, update, OpError,
+};
+use crate::{
+    config::{GlobalExecutor, RetryPolicy},
+    contract::StoreResponse,
+};
+
+use self::revalidate::Revalidations;
 
+#[cfg(feature = "websocket")]
+pub(crate) mod attestation;
+#[cfg(feature = "websocket")]
+pub(crate) mod batch;
+#[cfg(feature = "websocket")]
+pub(crate) mod chunks;
 pub(crate) mod combinator;
 #[cfg(feature = "websocket")]
+pub(crate) mod conditional;
+#[cfg(feature = "websocket")]
+pub(crate) mod deflate;
+#[cfg(feature = "websocket")]
+pub(crate) mod envelope;
+#[cfg(feature = "websocket")]
+pub(crate) mod error_details;
+#[cfg(feature = "websocket")]
+pub(crate) mod heartbeat;
+#[cfg(feature = "websocket")]
+pub(crate) mod outbound;
+#[cfg(feature = "websocket")]
+pub(crate) mod priority;
+#[cfg(feature = "websocket")]
+pub(crate) mod query;
+#[cfg(feature = "websocket")]
+pub(crate) mod retry;
+pub(crate) mod revalidate;
+#[cfg(feature = "websocket")]
+pub(crate) mod session;
+#[cfg(feature = "websocket")]
+pub(crate) mod status;
+#[cfg(feature = "websocket")]
 pub(crate) mod websocket;
 
 pub(crate) type BoxedClient = Box<dyn ClientEventsProxy + Send + 'static>;
@@ -103,11 +141,148 @@ impl From<String> for AuthToken {
     }
 }
 
+/// What the update notifications of a subscription carry.
+///
+/// Notifications carrying only the summary of the new state aren't offered, as they couldn't be
+/// told apart from those carrying the state until the client API has an update variant for them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SubscriptionMode {
+    /// The delta from the summary given when subscribing, or the whole state without one.
+    #[default]
+    Auto,
+    /// The whole state, even if a summary was given when subscribing.
+    FullState,
+    /// Only the delta from the last state notified, the first notification carries the whole
+    /// state if no summary was given when subscribing.
+    Delta,
+}
+
+/// When the puts and updates of a client are reported as completed, trading latency for
+/// durability.
+///
+/// Given as `local`, `target` or `peers:<n>`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum AckLevel {
+    /// Once applied by this node, the value keeps propagating to the network afterwards.
+    Local,
+    /// Once applied by the peer the request is routed to.
+    #[default]
+    Target,
+    /// Once the target confirms at least this many peers, itself included, hold the value,
+    /// counting those the target broadcasts it to.
+    Peers(usize),
+}
+
+impl AckLevel {
+    /// Peers the operations have to collect confirmations from, none unless [`AckLevel::Peers`].
+    pub(crate) fn confirmations(&self) -> usize {
+        match self {
+            Self::Peers(peers) => *peers,
+            Self::Local | Self::Target => 0,
+        }
+    }
+}
+
+impl std::str::FromStr for AckLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "local" => Ok(Self::Local),
+            "target" => Ok(Self::Target),
+            _ => match s.strip_prefix("peers:").map(str::parse) {
+                Some(Ok(peers)) if peers > 0 => Ok(Self::Peers(peers)),
+                _ => Err(format!(
+                    "invalid ack level `{s}`, expected `local`, `target` or `peers:<n>`"
+                )),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for AckLevel {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for AckLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Local => write!(f, "local"),
+            Self::Target => write!(f, "target"),
+            Self::Peers(peers) => write!(f, "peers:{peers}"),
+        }
+    }
+}
+
+/// Peers near a contract the values put by a client are replicated to, the peer the put is
+/// routed to included, so they survive the churn of any single one of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "usize")]
+pub struct Replicas(usize);
+
+impl Replicas {
+    /// Peers values can be replicated to at most.
+    pub const MAX: usize = 8;
+
+    pub fn get(self) -> usize {
+        self.0
+    }
+}
+
+impl Default for Replicas {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+impl TryFrom<usize> for Replicas {
+    type Error = String;
+
+    fn try_from(replicas: usize) -> Result<Self, Self::Error> {
+        match replicas {
+            1..=Self::MAX => Ok(Self(replicas)),
+            _ => Err(format!(
+                "invalid replicas `{replicas}`, expected 1 to {}",
+                Self::MAX
+            )),
+        }
+    }
+}
+
+/// How the gets of a client for contracts stored by this node are answered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GetMode {
+    /// With the state stored, the contract being got from the network only if it isn't.
+    #[default]
+    Stored,
+    /// With the state stored and its age right away, the contract being got from the network
+    /// in the background to send the state fetched too if it changed (see [`revalidate`]).
+    Revalidate,
+}
+
 #[non_exhaustive]
 pub struct OpenRequest<'a> {
     pub client_id: ClientId,
     pub request: Box<ClientRequest<'a>>,
     pub notification_channel: Option<UnboundedSender<HostResult>>,
+    pub subscription_mode: SubscriptionMode,
+    pub ack_level: AckLevel,
+    pub get_mode: GetMode,
+    pub replicas: Replicas,
+    /// Hash of the state the contract of an update is expected to have, for conditional updates.
+    pub expected_state: Option<blake3::Hash>,
+    /// Retries of the operation requested by the client, overriding those of the node.
+    pub retry_policy: Option<RetryPolicy>,
+    /// Query of a get, returning the answer computed by the contract instead of the state.
+    pub query: Option<GetQuery>,
+    pub priority: Priority,
     pub token: Option<AuthToken>,
     pub attested_contract: Option<ContractInstanceId>,
 }
@@ -135,6 +310,14 @@ impl<'a> OpenRequest<'a> {
             client_id: id,
             request,
             notification_channel: None,
+            subscription_mode: SubscriptionMode::default(),
+            ack_level: AckLevel::default(),
+            get_mode: GetMode::default(),
+            replicas: Replicas::default(),
+            expected_state: None,
+            retry_policy: None,
+            query: None,
+            priority: Priority::default(),
             token: None,
             attested_contract: None,
         }
@@ -145,6 +328,46 @@ impl<'a> OpenRequest<'a> {
         self
     }
 
+    pub fn with_subscription_mode(mut self, mode: SubscriptionMode) -> Self {
+        self.subscription_mode = mode;
+        self
+    }
+
+    pub fn with_ack_level(mut self, ack_level: AckLevel) -> Self {
+        self.ack_level = ack_level;
+        self
+    }
+
+    pub fn with_get_mode(mut self, get_mode: GetMode) -> Self {
+        self.get_mode = get_mode;
+        self
+    }
+
+    pub fn with_replicas(mut self, replicas: Replicas) -> Self {
+        self.replicas = replicas;
+        self
+    }
+
+    pub fn with_expected_state(mut self, expected_state: Option<blake3::Hash>) -> Self {
+        self.expected_state = expected_state;
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: Option<RetryPolicy>) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn with_query(mut self, query: Option<GetQuery>) -> Self {
+        self.query = query;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
     pub fn with_token(mut self, token: Option<AuthToken>) -> Self {
         self.token = token;
         self
@@ -167,6 +390,27 @@ pub trait ClientEventsProxy {
         id: ClientId,
         response: Result<HostResponse, ClientError>,
     ) -> BoxFuture<Result<(), ClientError>>;
+
+    /// Sends a response with a state stored by the node served while it's revalidated, `age`
+    /// being the time since it was last updated if known.
+    fn send_cached(
+        &mut self,
+        id: ClientId,
+        response: Result<HostResponse, ClientError>,
+        _age: Option<Duration>,
+    ) -> BoxFuture<Result<(), ClientError>> {
+        self.send(id, response)
+    }
+
+    /// Sends the error rejecting a conditional update of a contract whose state differs from the
+    /// one expected, so the client can tell it apart from other errors updating the contract.
+    fn send_state_conflict(
+        &mut self,
+        id: ClientId,
+        error: ClientError,
+    ) -> BoxFuture<Result<(), ClientError>> {
+        self.send(id, Err(error))
+    }
 }
 
 /// Process client events.
@@ -180,6 +424,7 @@ where
     ClientEv: ClientEventsProxy + Send + 'static,
 {
     let mut results = FuturesUnordered::new();
+    let revalidations = Revalidations::default();
     loop {
         tokio::select! {
             client_request = client_events.recv() => {
@@ -201,27 +446,38 @@ where
                     }
                 };
                 let cli_id = req.client_id;
-                let res = process_open_request(req, op_manager.clone()).await;
+                let res = process_open_request(req, op_manager.clone(), revalidations.clone()).await;
                 results.push(async move {
                     match res.await {
                         Ok(Some(Either::Left(res))) => (cli_id, Ok(Some(res))),
                         Ok(Some(Either::Right(mut cb))) => {
                             match cb.recv().await {
                                 Some(res) => (cli_id, Ok(Some(res))),
-                                None => (cli_id, Err(ClientError::from(ErrorKind::ChannelClosed))),
+                                None => (cli_id, Err(Failure::Error(ErrorKind::ChannelClosed.into()))),
                             }
                         }
                         Ok(None) => (cli_id, Ok(None)),
                         Err(Error::Disconnected) => {
                             tracing::debug!("client disconnected");
-                            (cli_id, Err(ClientError::from(ErrorKind::Disconnect)))
+                            (cli_id, Err(Failure::Error(ErrorKind::Disconnect.into())))
+                        }
+                        Err(Error::Executor(err)) if err.is_state_conflict() => {
+                            (cli_id, Err(Failure::StateConflict(ErrorKind::RequestError(err.unwrap_request()).into())))
                         }
-                        Err(err) => (cli_id, Err(ErrorKind::OperationError { cause: format!("{err}").into() }.into())),
+                        Err(err) => (cli_id, Err(Failure::Error(ErrorKind::OperationError { cause: format!("{err}").into() }.into()))),
                     }
                 });
             }
             res = client_responses.recv() => {
                 if let Some((cli_id, res)) = res {
+                    let (cli_id, res) = if revalidations.is_pending(&cli_id) {
+                        match revalidations.finish(&cli_id, res) {
+                            Some(fresh) => fresh,
+                            None => continue,
+                        }
+                    } else {
+                        (cli_id, res)
+                    };
                     if let Ok(result) = &res {
                         tracing::debug!(%result, "sending client response");
                     }
@@ -236,6 +492,17 @@ where
                     unreachable!();
                 };
                 match f_res {
+                    (cli_id, Ok(Some(QueryResult::CachedGetResult { key, state, contract, age }))) => {
+                        let res = Ok(HostResponse::ContractResponse(ContractResponse::GetResponse {
+                            key,
+                            state,
+                            contract,
+                        }));
+                        if let Err(err) = client_events.send_cached(cli_id, res, age).await {
+                            tracing::debug!("channel closed: {err}");
+                            anyhow::bail!(err);
+                        }
+                    }
                     (cli_id, Ok(Some(res))) => {
                         let res = match res {
                             QueryResult::Connections(conns) => {
@@ -253,6 +520,8 @@ where
                             QueryResult::DelegateResult { response, .. } => {
                                 response
                             }
+                            QueryResult::Accepted(response) => Ok(response),
+                            QueryResult::CachedGetResult { .. } => unreachable!("sent above"),
                         };
                         if let Ok(result) = &res {
                             tracing::debug!(%result, "sending client operation response");
@@ -265,13 +534,24 @@ where
                     (_, Ok(None)) => continue,
                     // TODO: we should change the API so client requests have a unique id so we can map specific responses
                     // to the specific client request
-                    (cli_id, Err(err)) => client_events.send(cli_id, Err(err)).await?,
+                    (cli_id, Err(Failure::Error(err))) => client_events.send(cli_id, Err(err)).await?,
+                    (cli_id, Err(Failure::StateConflict(err))) => {
+                        client_events.send_state_conflict(cli_id, err).await?
+                    }
                 }
             }
         }
     }
 }
 
+/// Failure answering a request of a client.
+enum Failure {
+    Error(ClientError),
+    /// A conditional update was rejected as the state of the contract differs from the one
+    /// expected.
+    StateConflict(ClientError),
+}
+
 #[derive(Debug, thiserror::Error)]
 enum Error {
     #[error("Node not connected to network")]
@@ -290,6 +570,7 @@ enum Error {
 async fn process_open_request(
     mut request: OpenRequest<'static>,
     op_manager: Arc<OpManager>,
+    revalidations: Revalidations,
 ) -> BoxFuture<'static, Result<Option<Either<QueryResult, mpsc::Receiver<QueryResult>>>, Error>> {
     let (callback_tx, callback_rx) = if matches!(
         &*request.request,
@@ -335,8 +616,28 @@ async fn process_open_request(
                             state,
                             op_manager.ring.max_hops_to_live,
                             subscribe,
-                        );
+                        )
+                        .with_confirmations(request.ack_level.confirmations())
+                        .with_replicas(request.replicas.get());
                         let op_id = op.id;
+                        op_manager.set_priority(op_id, request.priority);
+                        if let Some(policy) = request.retry_policy {
+                            op_manager.override_retry_policy(op_id, policy);
+                        }
+
+                        if request.ack_level == AckLevel::Local {
+                            let key = put::store_locally(&op_manager, &op)
+                                .await
+                                .inspect_err(|err| tracing::error!("Local put error: {}", err))?;
+                            if let Err(err) = put::request_put(&op_manager, op).await {
+                                tracing::error!("Put request error: {}", err);
+                            }
+                            return Ok(Some(Either::Left(QueryResult::Accepted(
+                                HostResponse::ContractResponse(ContractResponse::PutResponse {
+                                    key,
+                                }),
+                            ))));
+                        }
 
                         op_manager
                             .ch_outbound
@@ -374,12 +675,19 @@ async fn process_open_request(
                                 key,
                                 data,
                                 related_contracts: related_contracts.clone(),
+                                expected_state: request.expected_state,
                             })
                             .await
                         {
                             Ok(ContractHandlerEvent::UpdateResponse {
                                 new_value: Ok(new_val),
                             }) => Ok(new_val),
+                            Ok(ContractHandlerEvent::UpdateResponse {
+                                new_value: Err(err),
+                            }) if err.is_state_conflict() => {
+                                tracing::debug!(%key, "update expected another state");
+                                return Err(err.into());
+                            }
                             Ok(ContractHandlerEvent::UpdateResponse {
                                 new_value: Err(err),
                             }) => Err(OpError::from(err)),
@@ -397,7 +705,22 @@ async fn process_open_request(
                             ?new_state,
                             "Sending update op",
                         );
-                        let op = update::start_op(key, new_state, related_contracts);
+                        let summary = StateSummary::from(new_state.as_ref().to_vec());
+                        let op = update::start_op(key, new_state, related_contracts)
+                            .with_confirmations(request.ack_level.confirmations());
+                        op_manager.set_priority(op.id, request.priority);
+
+                        if request.ack_level == AckLevel::Local {
+                            if let Err(err) = update::request_update(&op_manager, op).await {
+                                tracing::error!("request update error {}", err)
+                            }
+                            return Ok(Some(Either::Left(QueryResult::Accepted(
+                                HostResponse::ContractResponse(ContractResponse::UpdateResponse {
+                                    key,
+                                    summary,
+                                }),
+                            ))));
+                        }
 
                         op_manager
                             .ch_outbound
@@ -422,7 +745,36 @@ async fn process_open_request(
                             return Err(Error::Disconnected);
                         };
 
-                        let (state, contract) = match op_manager
+                        if let Some(query) = &request.query {
+                            let response = op_manager
+                                .notify_contract_handler(query.contract_request(key))
+                                .await
+                                .map_err(|err| {
+                                    tracing::error!("state query failed: {}", err);
+                                    Error::Contract(err)
+                                })?;
+                            match GetQuery::answer(response) {
+                                Ok(Some(answer)) => {
+                                    tracing::debug!(
+                                        this_peer = %peer_id,
+                                        "Contract found, returning query result",
+                                    );
+                                    return Ok(Some(Either::Left(QueryResult::GetResult {
+                                        key,
+                                        state: answer,
+                                        contract: None,
+                                    })));
+                                }
+                                // not stored by this node, queried at the network
+                                Ok(None) => {}
+                                Err(err) => {
+                                    tracing::error!("state query failed: {}", err);
+                                    return Err(Error::Op(err));
+                                }
+                            }
+                        }
+
+                        let (state, contract, updated_at) = match op_manager
                             .notify_contract_handler(ContractHandlerEvent::GetQuery {
                                 key,
                                 return_contract_code,
@@ -431,8 +783,9 @@ async fn process_open_request(
                         {
                             Ok(ContractHandlerEvent::GetResponse {
                                 response: Ok(StoreResponse { state, contract }),
+                                updated_at,
                                 ..
-                            }) => (state, contract),
+                            }) => (state, contract, updated_at),
                             Ok(ContractHandlerEvent::GetResponse {
                                 response: Err(err), ..
                             }) => {
@@ -453,6 +806,30 @@ async fn process_open_request(
                             || (return_contract_code && state.is_some() && contract.is_some())
                         {
                             if let Some(state) = state {
+                                if request.get_mode == GetMode::Revalidate {
+                                    tracing::debug!(
+                                        this_peer = %peer_id,
+                                        "Contract found, returning get result and revalidating it",
+                                    );
+                                    if let Err(err) = revalidations
+                                        .refresh(
+                                            &op_manager,
+                                            client_id,
+                                            key,
+                                            return_contract_code,
+                                            &state,
+                                        )
+                                        .await
+                                    {
+                                        tracing::debug!(%key, "Failed revalidating contract: {err}");
+                                    }
+                                    return Ok(Some(Either::Left(QueryResult::CachedGetResult {
+                                        key,
+                                        state,
+                                        contract,
+                                        age: updated_at.and_then(|at| at.elapsed().ok()),
+                                    })));
+                                }
                                 tracing::debug!(
                                     this_peer = %peer_id,
                                     "Contract found, returning get result",
@@ -470,7 +847,12 @@ async fn process_open_request(
                                 "Contract not found, starting get op",
                             );
 
-                            let op = get::start_op(key, return_contract_code, subscribe);
+                            let op = get::start_op(key, return_contract_code, subscribe)
+                                .with_query(request.query.clone());
+                            op_manager.set_priority(op.id, request.priority);
+                            if let Some(policy) = request.retry_policy {
+                                op_manager.override_retry_policy(op.id, policy);
+                            }
 
                             op_manager
                                 .ch_outbound
@@ -491,15 +873,30 @@ async fn process_open_request(
                         }
                     }
                     ContractRequest::Subscribe { key, summary } => {
-                        let op_id =
-                            crate::node::subscribe(op_manager.clone(), key, Some(client_id))
-                                .await
-                                .inspect_err(|err| {
-                                    tracing::error!("Subscribe error: {}", err);
-                                })?;
+                        // clients subscribing to contracts this node is already subscribed to
+                        // are served by its subscription, instead of subscribing it again
+                        let op_id = if op_manager.ring.is_subscribed(&key) {
+                            tracing::debug!(%key, %client_id, "Already subscribed to contract");
+                            None
+                        } else {
+                            let op_id = crate::node::subscribe(
+                                op_manager.clone(),
+                                key,
+                                Some(client_id),
+                                request.priority,
+                            )
+                            .await
+                            .inspect_err(|err| {
+                                tracing::error!("Subscribe error: {}", err);
+                            })?;
+                            if let Some(policy) = request.retry_policy {
+                                op_manager.override_retry_policy(op_id, policy);
+                            }
+                            Some(op_id)
+                        };
 
                         let Some(subscriber_listener) = subscription_listener else {
-                            tracing::error!(%op_id, %client_id, "No subscriber listener");
+                            tracing::error!(?op_id, %client_id, "No subscriber listener");
                             return Ok(None);
                         };
 
@@ -509,32 +906,43 @@ async fn process_open_request(
                                     key,
                                     client_id,
                                     summary,
+                                    mode: request.subscription_mode,
                                     subscriber_listener,
                                 },
                             )
                             .await
                             .inspect_err(|err| {
                                 tracing::error!(
-                                    %op_id, %client_id,
+                                    ?op_id, %client_id,
                                     "Register subscriber listener error: {}", err
                                 );
                             });
                         match register_listener {
                             Ok(ContractHandlerEvent::RegisterSubscriberListenerResponse) => {
                                 tracing::debug!(
-                                    %op_id, %client_id,
+                                    ?op_id, %client_id,
                                     "Subscriber listener registered successfully"
                                 );
                             }
                             _ => {
                                 tracing::error!(
-                                    %op_id, %client_id,
+                                    ?op_id, %client_id,
                                     "Subscriber listener registration failed"
                                 );
                                 return Err(Error::Op(OpError::UnexpectedOpState));
                             }
                         }
 
+                        let Some(op_id) = op_id else {
+                            return Ok(Some(Either::Left(QueryResult::Accepted(
+                                HostResponse::ContractResponse(
+                                    ContractResponse::SubscribeResponse {
+                                        key,
+                                        subscribed: true,
+                                    },
+                                ),
+                            ))));
+                        };
                         op_manager
                             .ch_outbound
                             .waiting_for_transaction_result(op_id, client_id)
@@ -592,7 +1000,20 @@ async fn process_open_request(
                 })));
             }
             ClientRequest::Disconnect { .. } => {
-                unreachable!();
+                // operations the client was waiting on are no longer needed, nor are the
+                // updates of the contracts it subscribed to
+                op_manager
+                    .notify_node_event(NodeEvent::ClientDisconnected(client_id))
+                    .await?;
+                if let Err(err) = op_manager
+                    .notify_contract_handler(ContractHandlerEvent::UnregisterSubscriberListeners {
+                        client_id,
+                    })
+                    .await
+                {
+                    tracing::warn!(%client_id, "Unregister subscriber listeners error: {err}");
+                }
+                return Ok(None);
             }
             ClientRequest::NodeQueries(_) => {
                 tracing::debug!("Received node queries from user event");
@@ -768,6 +1189,7 @@ pub(crate) mod test {
                                     .ok_or_else(|| ClientError::from(ErrorKind::Disconnect))?
                                     .into(),
                                 notification_channel: None,
+                                subscription_mode: SubscriptionMode::default(),
                                 token: None,
                                 attested_contract: None,
                             };
@@ -780,6 +1202,7 @@ pub(crate) mod test {
                                     .expect("event not found")
                                     .into(),
                                 notification_channel: None,
+                                subscription_mode: SubscriptionMode::default(),
                                 token: None,
                                 attested_contract: None,
                             };
@@ -873,6 +1296,7 @@ pub(crate) mod test {
                                             })?
                                             .into(),
                                         notification_channel: None,
+                                        subscription_mode: SubscriptionMode::default(),
                                         token: None,
                                         attested_contract: None,
                                     };
@@ -0,0 +1,136 @@
+//! Notifications queued for a websocket client, bounded so a client which doesn't read them
+//! fast enough can't make the node buffer an unbounded amount of them.
+
+use std::collections::VecDeque;
+
+use freenet_stdlib::{
+    client_api::{ContractResponse, HostResponse},
+    prelude::{ContractKey, UpdateData},
+};
+
+use super::HostResult;
+use crate::config::{OutboundQueueConfig, SlowClientPolicy};
+
+/// The client is to be disconnected, its queue is full.
+#[derive(Debug)]
+pub(crate) struct QueueFull;
+
+impl std::fmt::Display for QueueFull {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "client not keeping up with notifications")
+    }
+}
+
+impl std::error::Error for QueueFull {}
+
+pub(crate) struct NotificationQueue {
+    config: OutboundQueueConfig,
+    queue: VecDeque<(ContractKey, HostResult)>,
+}
+
+impl NotificationQueue {
+    pub fn new(config: OutboundQueueConfig) -> Self {
+        Self {
+            config,
+            queue: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, key: ContractKey, notification: HostResult) -> Result<(), QueueFull> {
+        if self.queue.len() >= self.config.high_water_mark {
+            match self.config.policy {
+                SlowClientPolicy::Disconnect => return Err(QueueFull),
+                SlowClientPolicy::Coalesce if carries_state(&notification) => {
+                    self.queue.retain(|(queued, _)| *queued != key);
+                }
+                SlowClientPolicy::Coalesce | SlowClientPolicy::DropOldest => {}
+            }
+            while self.queue.len() >= self.config.high_water_mark.max(1) {
+                if let Some((key, _)) = self.queue.pop_front() {
+                    tracing::debug!(contract = %key, "Dropping notification for a slow client");
+                }
+            }
+        }
+        self.queue.push_back((key, notification));
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<HostResult> {
+        self.queue.pop_front().map(|(_, notification)| notification)
+    }
+}
+
+/// Notifications carrying the whole state of the contract, which supersede any previous one.
+fn carries_state(notification: &HostResult) -> bool {
+    matches!(
+        notification,
+        Ok(HostResponse::ContractResponse(
+            ContractResponse::UpdateNotification {
+                update: UpdateData::State(_),
+                ..
+            }
+        ))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use freenet_stdlib::prelude::{ContractInstanceId, State, StateDelta};
+
+    use super::*;
+
+    fn notification(key: ContractKey, update: UpdateData<'static>) -> HostResult {
+        Ok(ContractResponse::UpdateNotification { key, update }.into())
+    }
+
+    fn queue(policy: SlowClientPolicy) -> NotificationQueue {
+        NotificationQueue::new(OutboundQueueConfig {
+            high_water_mark: 2,
+            policy,
+        })
+    }
+
+    #[test]
+    fn slow_client_policies() {
+        let a = ContractKey::from(ContractInstanceId::new([1; 32]));
+        let b = ContractKey::from(ContractInstanceId::new([2; 32]));
+        let delta = || UpdateData::Delta(StateDelta::from(vec![1]));
+        let state = || UpdateData::State(State::from(vec![2]));
+
+        let mut disconnect = queue(SlowClientPolicy::Disconnect);
+        disconnect.push(a, notification(a, delta())).unwrap();
+        disconnect.push(a, notification(a, delta())).unwrap();
+        assert!(disconnect.push(a, notification(a, delta())).is_err());
+
+        let mut drop_oldest = queue(SlowClientPolicy::DropOldest);
+        drop_oldest.push(a, notification(a, delta())).unwrap();
+        drop_oldest.push(b, notification(b, delta())).unwrap();
+        drop_oldest.push(a, notification(a, state())).unwrap();
+        assert!(matches!(
+            drop_oldest.pop(),
+            Some(Ok(HostResponse::ContractResponse(ContractResponse::UpdateNotification { key, .. }))) if key == b
+        ));
+        assert!(drop_oldest.pop().is_some());
+        assert!(drop_oldest.pop().is_none());
+
+        let mut coalesce = queue(SlowClientPolicy::Coalesce);
+        coalesce.push(b, notification(b, delta())).unwrap();
+        coalesce.push(a, notification(a, delta())).unwrap();
+        // the state of `a` replaces its delta instead of dropping the notification of `b`
+        coalesce.push(a, notification(a, state())).unwrap();
+        assert!(matches!(
+            coalesce.pop(),
+            Some(Ok(HostResponse::ContractResponse(ContractResponse::UpdateNotification { key, .. }))) if key == b
+        ));
+        assert!(matches!(
+            coalesce.pop(),
+            Some(Ok(HostResponse::ContractResponse(
+                ContractResponse::UpdateNotification {
+                    update: UpdateData::State(_),
+                    ..
+                }
+            )))
+        ));
+        assert!(coalesce.pop().is_none());
+    }
+}
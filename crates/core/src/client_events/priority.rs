@@ -0,0 +1,44 @@
+//! Priority of the operations requested by clients, so bulk publishes don't hold up the
+//! operations users are waiting on.
+//!
+//! Websocket clients tag their operations as background ones by sending the request framed as
+//! the `FNPR` magic bytes, the priority (1 byte, 0 interactive and 1 background) and the request
+//! encoded as for other requests. Untagged operations are interactive. The node processes and
+//! sends interactive operations first under load. The priority is kept by the node and never
+//! sent to peers, so peers running older versions aren't affected by it.
+
+use crate::message::Priority;
+
+/// Magic bytes prefixing requests with a priority.
+const PRIORITY_MAGIC: [u8; 4] = *b"FNPR";
+
+/// Splits a request with a priority into the priority and the request, `None` if it hasn't one
+/// or the priority is unknown.
+pub(crate) fn split(msg: &[u8]) -> Option<(Priority, &[u8])> {
+    let framed = msg.strip_prefix(&PRIORITY_MAGIC)?;
+    let (priority, request) = framed.split_first()?;
+    let priority = match priority {
+        0 => Priority::Interactive,
+        1 => Priority::Background,
+        _ => return None,
+    };
+    Some((priority, request))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_requests_with_priorities() {
+        let mut msg = b"FNPR".to_vec();
+        msg.push(1);
+        msg.extend_from_slice(b"request");
+        assert_eq!(split(&msg), Some((Priority::Background, &b"request"[..])));
+
+        assert_eq!(split(b"request"), None);
+        msg[4] = 2;
+        assert_eq!(split(&msg), None);
+        assert_eq!(split(b"FNPR"), None);
+    }
+}
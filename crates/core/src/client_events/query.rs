@@ -0,0 +1,63 @@
+//! Partial gets, so clients needing a small part of a large state don't have to fetch all of it.
+//!
+//! Websocket clients query the state of a contract by sending a get framed as the `FNQY` magic
+//! bytes, the length of the query (4 bytes, big-endian), the query and the request encoded as for
+//! other requests. The node holding the state passes the query to the `query_state` entry point of
+//! the contract, and only the projection of the state answering the query returned by the
+//! contract travels back as the state of the get response. Only contracts exporting the entry
+//! point answer queries, gets querying others failing, so existing contracts never get queries
+//! they would take for summaries.
+//!
+//! Clients get only the summary of the state computed by the contract, e.g. to check whether it
+//! changed before fetching it, by sending the get framed as the `FNSM` magic bytes and the
+//! request instead, the summary travelling back as the state of the get response.
+
+use freenet_stdlib::prelude::StateSummary;
+
+use crate::operations::get::GetQuery;
+
+/// Magic bytes prefixing queries.
+const QUERY_MAGIC: [u8; 4] = *b"FNQY";
+
+/// Magic bytes prefixing gets of the summary of the state.
+const SUMMARY_MAGIC: [u8; 4] = *b"FNSM";
+
+/// Splits a query into the query passed to the contract and the get request, `None` if it isn't
+/// a query.
+pub(crate) fn split(msg: &[u8]) -> Option<(GetQuery, &[u8])> {
+    if let Some(request) = msg.strip_prefix(&SUMMARY_MAGIC) {
+        return Some((GetQuery::Summary, request));
+    }
+    let framed = msg.strip_prefix(&QUERY_MAGIC)?;
+    let len = u32::from_be_bytes(framed.get(..4)?.try_into().ok()?) as usize;
+    let query = framed.get(4..4 + len)?;
+    let query = GetQuery::Projection(StateSummary::from(query.to_vec()));
+    Some((query, &framed[4 + len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_queries() {
+        let mut msg = b"FNQY".to_vec();
+        msg.extend_from_slice(&5u32.to_be_bytes());
+        msg.extend_from_slice(b"query");
+        msg.extend_from_slice(b"request");
+        let (query, request) = split(&msg).unwrap();
+        assert!(matches!(query, GetQuery::Projection(query) if query.as_ref() == b"query"));
+        assert_eq!(request, b"request");
+
+        assert!(split(b"request").is_none());
+        msg.truncate(10);
+        assert!(split(&msg).is_none());
+    }
+
+    #[test]
+    fn splits_summary_gets() {
+        let (query, request) = split(b"FNSMrequest").unwrap();
+        assert!(matches!(query, GetQuery::Summary));
+        assert_eq!(request, b"request");
+    }
+}
@@ -0,0 +1,75 @@
+//! Retry policies requested by clients for their own get, put and subscribe operations,
+//! overriding those configured for the node.
+//!
+//! Websocket clients override the policy by sending a request framed as the `FNRT` magic bytes,
+//! the most retries (1 byte), whether to retry through alternate routes (1 byte, 0 or 1), the
+//! backoff curve (1 byte, 0 constant, 1 linear and 2 exponential), the base and longest delays in
+//! milliseconds (4 bytes each, big-endian) and the request encoded as for other requests.
+
+use crate::config::{BackoffCurve, RetryPolicy};
+
+/// Magic bytes prefixing requests with a retry policy.
+const RETRY_MAGIC: [u8; 4] = *b"FNRT";
+
+const POLICY_LEN: usize = 11;
+
+/// Splits a request with a retry policy into the policy and the request, `None` if it hasn't
+/// one or the policy is malformed.
+pub(crate) fn split(msg: &[u8]) -> Option<(RetryPolicy, &[u8])> {
+    let framed = msg.strip_prefix(&RETRY_MAGIC)?;
+    if framed.len() < POLICY_LEN {
+        return None;
+    }
+    let (policy, request) = framed.split_at(POLICY_LEN);
+    let alternate_routes = match policy[1] {
+        0 => false,
+        1 => true,
+        _ => return None,
+    };
+    let backoff = match policy[2] {
+        0 => BackoffCurve::Constant,
+        1 => BackoffCurve::Linear,
+        2 => BackoffCurve::Exponential,
+        _ => return None,
+    };
+    let backoff_base = u32::from_be_bytes(policy[3..7].try_into().ok()?);
+    let backoff_max = u32::from_be_bytes(policy[7..11].try_into().ok()?);
+    let policy = RetryPolicy {
+        max_retries: policy[0] as usize,
+        alternate_routes,
+        backoff,
+        backoff_base: backoff_base as u64,
+        backoff_max: backoff_max as u64,
+    };
+    Some((policy, request))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_requests_with_retry_policies() {
+        let mut msg = b"FNRT".to_vec();
+        msg.extend_from_slice(&[3, 0, 1]);
+        msg.extend_from_slice(&100u32.to_be_bytes());
+        msg.extend_from_slice(&1_000u32.to_be_bytes());
+        msg.extend_from_slice(b"request");
+        let (policy, request) = split(&msg).unwrap();
+        assert_eq!(
+            policy,
+            RetryPolicy {
+                max_retries: 3,
+                alternate_routes: false,
+                backoff: BackoffCurve::Linear,
+                backoff_base: 100,
+                backoff_max: 1_000,
+            }
+        );
+        assert_eq!(request, b"request");
+
+        assert_eq!(split(b"request"), None);
+        msg[6] = 3;
+        assert_eq!(split(&msg), None);
+    }
+}
@@ -0,0 +1,191 @@
+//! Stale-while-revalidate gets, so clients are answered right away with the state stored by the
+//! node instead of waiting for the network, and are still told about the latest state.
+//!
+//! Clients connecting with [`GetMode::Revalidate`](super::GetMode::Revalidate) are answered the gets of contracts stored by
+//! the node with the stored state and its age, and the node gets the contract from the network
+//! in the background. The state fetched is sent to the client as the answer to another get if it
+//! differs from the one answered, nothing being sent otherwise or if the get fails.
+//!
+//! Websocket clients are answered states served this way framed as the `FNCA` magic bytes, the
+//! milliseconds since the state was last updated by the node (u64, big endian, `u64::MAX` if it
+//! wasn't since the node started) and the response encoded as for other requests.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use freenet_stdlib::{
+    client_api::{ContractResponse, HostResponse},
+    prelude::{ContractKey, WrappedState},
+};
+use parking_lot::Mutex;
+
+use crate::{
+    node::OpManager,
+    operations::{get, OpError},
+};
+
+use super::{ClientId, HostResult};
+
+#[cfg(feature = "websocket")]
+use byteorder::{BigEndian, WriteBytesExt};
+#[cfg(feature = "websocket")]
+use std::{io, time::Duration};
+
+/// Magic bytes prefixing states served before being revalidated.
+#[cfg(feature = "websocket")]
+const CACHED_MAGIC: [u8; 4] = *b"FNCA";
+
+struct Revalidation {
+    client: ClientId,
+    served: blake3::Hash,
+}
+
+/// Gets refreshing the states served to clients, waited for under ids of their own so their
+/// results are told apart from those of the requests of the clients.
+#[derive(Clone, Default)]
+pub(crate) struct Revalidations(Arc<Mutex<HashMap<ClientId, Revalidation>>>);
+
+impl Revalidations {
+    /// Gets the contract from the network to send the client the state fetched if it differs
+    /// from the one `served` to it.
+    pub async fn refresh(
+        &self,
+        op_manager: &OpManager,
+        client: ClientId,
+        key: ContractKey,
+        return_contract_code: bool,
+        served: &WrappedState,
+    ) -> Result<(), OpError> {
+        let id = self.start(client, served);
+        let op = get::start_op(key, return_contract_code, false);
+        let result = async {
+            op_manager
+                .ch_outbound
+                .waiting_for_transaction_result(op.id, id)
+                .await?;
+            get::request_get(op_manager, op, HashSet::new()).await
+        }
+        .await;
+        if result.is_err() {
+            self.0.lock().remove(&id);
+        }
+        result
+    }
+
+    /// Records the refresh of the state served to a client, returning the id to wait for the
+    /// result of the get with.
+    fn start(&self, client: ClientId, served: &WrappedState) -> ClientId {
+        let id = ClientId::next();
+        self.0.lock().insert(
+            id,
+            Revalidation {
+                client,
+                served: blake3::hash(served.as_ref()),
+            },
+        );
+        id
+    }
+
+    /// Whether `id` is waiting for the result of a refresh.
+    pub fn is_pending(&self, id: &ClientId) -> bool {
+        self.0.lock().contains_key(id)
+    }
+
+    /// The client to send the result of the refresh waited for with `id` to, with the fresh
+    /// state, `None` if it didn't change or couldn't be fetched.
+    pub fn finish(&self, id: &ClientId, result: HostResult) -> Option<(ClientId, HostResult)> {
+        let revalidation = self.0.lock().remove(id)?;
+        match &result {
+            Ok(HostResponse::ContractResponse(ContractResponse::GetResponse { state, .. }))
+                if blake3::hash(state.as_ref()) != revalidation.served =>
+            {
+                Some((revalidation.client, result))
+            }
+            Ok(_) => None,
+            Err(error) => {
+                tracing::debug!(client = %revalidation.client, %error, "Failed revalidating state");
+                None
+            }
+        }
+    }
+}
+
+/// Frames the response with a state served before being revalidated, `age` being the time since
+/// it was last updated.
+#[cfg(feature = "websocket")]
+pub(crate) fn frame(encoded: &[u8], age: Option<Duration>) -> io::Result<Vec<u8>> {
+    let age = age.map_or(u64::MAX, |age| {
+        u64::try_from(age.as_millis()).unwrap_or(u64::MAX)
+    });
+    let mut msg = Vec::with_capacity(CACHED_MAGIC.len() + 8 + encoded.len());
+    msg.extend_from_slice(&CACHED_MAGIC);
+    msg.write_u64::<BigEndian>(age)?;
+    msg.extend_from_slice(encoded);
+    Ok(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use freenet_stdlib::{
+        client_api::{ClientError, ErrorKind},
+        prelude::{ContractInstanceId, ContractKey},
+    };
+
+    use super::*;
+
+    fn get_response(state: &[u8]) -> HostResult {
+        Ok(HostResponse::ContractResponse(
+            ContractResponse::GetResponse {
+                key: ContractKey::from(ContractInstanceId::new([1; 32])),
+                contract: None,
+                state: WrappedState::new(state.to_vec()),
+            },
+        ))
+    }
+
+    #[test]
+    fn pushes_changed_states() {
+        let revalidations = Revalidations::default();
+        let client = ClientId::next();
+        let served = WrappedState::new(vec![1, 2, 3]);
+
+        let unchanged = revalidations.start(client, &served);
+        assert!(revalidations.is_pending(&unchanged));
+        assert!(revalidations
+            .finish(&unchanged, get_response(&[1, 2, 3]))
+            .is_none());
+        assert!(!revalidations.is_pending(&unchanged));
+
+        let changed = revalidations.start(client, &served);
+        let (to, fresh) = revalidations
+            .finish(&changed, get_response(&[4]))
+            .expect("fresh state");
+        assert_eq!(to, client);
+        assert!(matches!(
+            fresh,
+            Ok(HostResponse::ContractResponse(ContractResponse::GetResponse { state, .. }))
+                if state.as_ref() == [4]
+        ));
+        // answered once
+        assert!(revalidations.finish(&changed, get_response(&[5])).is_none());
+
+        let failed = revalidations.start(client, &served);
+        let error = Err(ClientError::from(ErrorKind::Disconnect));
+        assert!(revalidations.finish(&failed, error).is_none());
+    }
+
+    #[cfg(feature = "websocket")]
+    #[test]
+    fn cached_framing() -> Result<(), Box<dyn std::error::Error>> {
+        let framed = frame(b"response", Some(Duration::from_secs(2)))?;
+        assert_eq!(&framed[..4], b"FNCA");
+        assert_eq!(&framed[4..12], &2000u64.to_be_bytes());
+        assert_eq!(&framed[12..], b"response");
+
+        let unknown = frame(b"response", None)?;
+        assert_eq!(&unknown[4..12], &u64::MAX.to_be_bytes());
+        Ok(())
+    }
+}
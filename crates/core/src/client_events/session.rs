@@ -0,0 +1,420 @@
+//! Sessions of websocket clients, so a client which lost its connection can resume its
+//! subscriptions after reconnecting instead of subscribing and fetching every contract again.
+//!
+//! Every connection is given a session token. When the connection is lost its subscriptions
+//! are parked for a while, buffering the notifications the client misses, and a client
+//! reconnecting with the token gets them back together with the missed notifications.
+//!
+//! When a sessions file is configured the contracts every session is subscribed to are saved to
+//! it periodically. After the node restarts and connects to the network it subscribes to them
+//! again on behalf of the sessions saved, parking the subscriptions so clients reconnecting with
+//! their token resume them as if they had only lost their connection.
+
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use freenet_stdlib::prelude::ContractKey;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, Mutex};
+
+use super::{AuthToken, HostResult};
+use crate::config::SessionConfig;
+
+/// How often parked sessions are drained into their replay buffer.
+const DRAIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Interval the subscriptions of the sessions are saved at.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+pub(crate) type Subscription = (ContractKey, mpsc::UnboundedReceiver<HostResult>);
+
+pub(crate) type SubscriptionListeners = Arc<Mutex<VecDeque<Subscription>>>;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String")]
+pub(crate) struct SessionToken(Arc<str>);
+
+impl SessionToken {
+    fn generate() -> Self {
+        use rand::Rng;
+        let mut token = [0u8; 32];
+        rand::thread_rng().fill(&mut token);
+        Self(bs58::encode(token).into_string().into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SessionToken {
+    fn from(value: String) -> Self {
+        Self(value.into())
+    }
+}
+
+/// Session of a connected client.
+pub(crate) struct Session {
+    pub token: SessionToken,
+    /// Whether the session was resumed from a previous connection.
+    pub resumed: bool,
+    pub subscriptions: Vec<Subscription>,
+    /// Notifications the client missed while disconnected, oldest first.
+    pub missed: VecDeque<HostResult>,
+}
+
+/// Session of a connected client, tracked to save its subscriptions.
+struct LiveSession {
+    auth_token: Option<AuthToken>,
+    subscriptions: SubscriptionListeners,
+}
+
+/// Contracts a session is subscribed to, as saved to the sessions file.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SavedSession {
+    pub token: SessionToken,
+    pub auth_token: Option<AuthToken>,
+    pub contracts: Vec<ContractKey>,
+}
+
+struct ParkedSession {
+    /// Token the client was authenticated with, the session is only resumed with the same.
+    auth_token: Option<AuthToken>,
+    subscriptions: Vec<Subscription>,
+    missed: VecDeque<HostResult>,
+    /// More notifications than fit in the replay buffer were missed.
+    overflowed: bool,
+    expires: Instant,
+}
+
+impl ParkedSession {
+    fn drain(&mut self, replay_buffer: usize) {
+        for (_, updates) in &mut self.subscriptions {
+            while let Ok(update) = updates.try_recv() {
+                if self.missed.len() >= replay_buffer {
+                    self.overflowed = true;
+                    self.missed.pop_front();
+                }
+                self.missed.push_back(update);
+            }
+        }
+    }
+}
+
+/// Sessions of the clients of the websocket API, disabled unless configured.
+#[derive(Clone, Default)]
+pub(crate) struct Sessions {
+    config: Option<SessionConfig>,
+    /// File the subscriptions of the sessions are saved to.
+    file: Option<PathBuf>,
+    live: Arc<DashMap<SessionToken, LiveSession>>,
+    parked: Arc<DashMap<SessionToken, ParkedSession>>,
+}
+
+impl Sessions {
+    pub fn new(config: Option<SessionConfig>, file: Option<PathBuf>) -> Self {
+        Self {
+            config,
+            file: config.and(file),
+            live: Arc::default(),
+            parked: Arc::default(),
+        }
+    }
+
+    /// Opens the session of a new connection, resuming the session of `token` if it was
+    /// parked for the same client and is complete. `None` if sessions are disabled.
+    pub fn open(
+        &self,
+        token: Option<&SessionToken>,
+        auth_token: Option<&AuthToken>,
+    ) -> Option<Session> {
+        let config = self.config?;
+        let parked = token.and_then(|token| {
+            self.parked
+                .remove_if(token, |_, parked| parked.auth_token.as_ref() == auth_token)
+        });
+        match parked {
+            Some((token, mut parked)) => {
+                parked.drain(config.replay_buffer);
+                if parked.overflowed {
+                    tracing::debug!(
+                        session = token.as_str(),
+                        "Session missed too many notifications to be resumed"
+                    );
+                } else if parked.expires > Instant::now() {
+                    return Some(Session {
+                        token,
+                        resumed: true,
+                        subscriptions: parked.subscriptions,
+                        missed: parked.missed,
+                    });
+                }
+            }
+            None if token.is_some() => {
+                tracing::debug!("Unknown session, opening a new one");
+            }
+            None => {}
+        }
+        Some(Session {
+            token: SessionToken::generate(),
+            resumed: false,
+            subscriptions: Vec::new(),
+            missed: VecDeque::new(),
+        })
+    }
+
+    /// Tracks the subscriptions of the session of a connected client until it is parked.
+    pub fn attach(
+        &self,
+        token: SessionToken,
+        auth_token: Option<AuthToken>,
+        subscriptions: SubscriptionListeners,
+    ) {
+        if self.file.is_some() {
+            self.live.insert(
+                token,
+                LiveSession {
+                    auth_token,
+                    subscriptions,
+                },
+            );
+        }
+    }
+
+    /// Keeps the subscriptions of a disconnected client until it resumes the session or it
+    /// expires.
+    pub fn park(
+        &self,
+        token: SessionToken,
+        auth_token: Option<AuthToken>,
+        subscriptions: Vec<Subscription>,
+    ) {
+        let Some(config) = self.config else {
+            return;
+        };
+        self.live.remove(&token);
+        if subscriptions.is_empty() {
+            return;
+        }
+        self.parked.insert(
+            token.clone(),
+            ParkedSession {
+                auth_token,
+                subscriptions,
+                missed: VecDeque::new(),
+                overflowed: false,
+                expires: Instant::now() + Duration::from_secs(config.ttl),
+            },
+        );
+        let parked = self.parked.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(DRAIN_INTERVAL).await;
+                let Some(mut session) = parked.get_mut(&token) else {
+                    // resumed
+                    break;
+                };
+                if session.expires <= Instant::now() {
+                    drop(session);
+                    parked.remove(&token);
+                    tracing::debug!(session = token.as_str(), "Session expired");
+                    break;
+                }
+                session.drain(config.replay_buffer);
+            }
+        });
+    }
+
+    /// Takes the sessions saved before the node restarted, none if sessions aren't saved.
+    pub fn take_saved(&self) -> Vec<SavedSession> {
+        let Some(file) = &self.file else {
+            return vec![];
+        };
+        match read_sessions(file) {
+            Ok(saved) => saved,
+            Err(error) => {
+                if file.exists() {
+                    tracing::warn!(file = %file.display(), %error, "Failed reading saved sessions");
+                }
+                vec![]
+            }
+        }
+    }
+
+    /// Saves the subscriptions of the sessions periodically, if a sessions file is configured.
+    pub fn save_periodically(&self) {
+        let Some(file) = self.file.clone() else {
+            return;
+        };
+        let sessions = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SAVE_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let saved = sessions.to_save().await;
+                if let Err(error) = write_sessions(&file, &saved) {
+                    tracing::warn!(file = %file.display(), %error, "Failed saving sessions");
+                }
+            }
+        });
+    }
+
+    /// The contracts the sessions of connected and disconnected clients are subscribed to.
+    async fn to_save(&self) -> Vec<SavedSession> {
+        let live: Vec<_> = self
+            .live
+            .iter()
+            .map(|session| {
+                (
+                    session.key().clone(),
+                    session.auth_token.clone(),
+                    session.subscriptions.clone(),
+                )
+            })
+            .collect();
+        let mut saved = Vec::with_capacity(live.len() + self.parked.len());
+        for (token, auth_token, subscriptions) in live {
+            let contracts = subscriptions
+                .lock()
+                .await
+                .iter()
+                .map(|(key, _)| *key)
+                .collect();
+            saved.push(SavedSession {
+                token,
+                auth_token,
+                contracts,
+            });
+        }
+        saved.extend(self.parked.iter().map(|session| SavedSession {
+            token: session.key().clone(),
+            auth_token: session.auth_token.clone(),
+            contracts: session.subscriptions.iter().map(|(key, _)| *key).collect(),
+        }));
+        saved.retain(|session| !session.contracts.is_empty());
+        saved
+    }
+}
+
+fn read_sessions(path: &Path) -> anyhow::Result<Vec<SavedSession>> {
+    let bytes = std::fs::read(path)?;
+    Ok(bincode::deserialize(&bytes)?)
+}
+
+fn write_sessions(path: &Path, sessions: &[SavedSession]) -> anyhow::Result<()> {
+    // written aside first so a crash while saving doesn't lose the previous sessions
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, bincode::serialize(sessions)?)?;
+    std::fs::rename(tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use freenet_stdlib::{client_api::HostResponse, prelude::ContractInstanceId};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn resume_session() {
+        let sessions = Sessions::new(
+            Some(SessionConfig {
+                ttl: 60,
+                replay_buffer: 2,
+            }),
+            None,
+        );
+        assert!(Sessions::default().open(None, None).is_none());
+
+        let auth_token = Some(AuthToken::from("token".to_owned()));
+        let session = sessions.open(None, auth_token.as_ref()).unwrap();
+        assert!(!session.resumed);
+        let key = ContractKey::from(ContractInstanceId::new([1; 32]));
+        let (updates, rx) = mpsc::unbounded_channel();
+        sessions.park(session.token.clone(), auth_token.clone(), vec![(key, rx)]);
+        updates.send(Ok(HostResponse::Ok)).unwrap();
+
+        // other clients can not take over the session
+        let other = sessions.open(Some(&session.token), None).unwrap();
+        assert!(!other.resumed);
+
+        let resumed = sessions
+            .open(Some(&session.token), auth_token.as_ref())
+            .unwrap();
+        assert!(resumed.resumed);
+        assert_eq!(resumed.token, session.token);
+        assert_eq!(resumed.subscriptions.len(), 1);
+        assert_eq!(resumed.missed.len(), 1);
+
+        // sessions missing more notifications than buffered are not resumed
+        sessions.park(
+            resumed.token.clone(),
+            auth_token.clone(),
+            resumed.subscriptions,
+        );
+        for _ in 0..3 {
+            updates.send(Ok(HostResponse::Ok)).unwrap();
+        }
+        let session = sessions
+            .open(Some(&resumed.token), auth_token.as_ref())
+            .unwrap();
+        assert!(!session.resumed);
+        assert_ne!(session.token, resumed.token);
+    }
+
+    #[tokio::test]
+    async fn saves_and_restores_sessions() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("sessions.bin");
+        let sessions = Sessions::new(Some(SessionConfig::default()), Some(file.clone()));
+        let auth_token = Some(AuthToken::from("token".to_owned()));
+        let (a, b) = (
+            ContractKey::from(ContractInstanceId::new([1; 32])),
+            ContractKey::from(ContractInstanceId::new([2; 32])),
+        );
+
+        let connected = sessions.open(None, auth_token.as_ref()).unwrap();
+        let (_updates, rx) = mpsc::unbounded_channel();
+        let listeners = Arc::new(Mutex::new(VecDeque::from([(a, rx)])));
+        sessions.attach(connected.token.clone(), auth_token.clone(), listeners);
+        let disconnected = sessions.open(None, None).unwrap();
+        let (_updates, rx) = mpsc::unbounded_channel();
+        sessions.park(disconnected.token.clone(), None, vec![(b, rx)]);
+        // sessions without subscriptions aren't saved
+        let idle = sessions.open(None, None).unwrap();
+        sessions.attach(idle.token, None, Arc::default());
+
+        let mut saved = sessions.to_save().await;
+        saved.sort_by_key(|session| session.contracts[0] == b);
+        write_sessions(&file, &saved)?;
+        let restarted = Sessions::new(Some(SessionConfig::default()), Some(file));
+        let mut restored = restarted.take_saved();
+        restored.sort_by_key(|session| session.contracts[0] == b);
+        assert_eq!(
+            restored,
+            vec![
+                SavedSession {
+                    token: connected.token,
+                    auth_token,
+                    contracts: vec![a],
+                },
+                SavedSession {
+                    token: disconnected.token,
+                    auth_token: None,
+                    contracts: vec![b],
+                },
+            ]
+        );
+
+        assert!(Sessions::new(None, Some(dir.path().join("sessions.bin")))
+            .take_saved()
+            .is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+//! Status of the connection of the node to the network pushed to websocket clients, so apps
+//! can tell whether they are online instead of inferring it from failing operations.
+//!
+//! Clients subscribe with a message of the `FNST` magic bytes. They are sent the current status
+//! and then a new one whenever the node connects to or disconnects from the network, its peers
+//! cross the number required for the node to report itself as ready, or it reaches or loses
+//! its gateways. Status messages are framed as the `FNST` magic bytes, a byte of flags (1 when
+//! connected to some peer, 2 when connected to the required number of peers and 4 when
+//! connected to a gateway or being one) and the number of connected peers (u32, big endian).
+
+use std::{io, sync::OnceLock, time::Duration};
+
+use byteorder::{BigEndian, WriteBytesExt};
+use tokio::sync::watch;
+
+use crate::node::admin::{NetworkStatus, NodeHandle};
+
+/// Magic bytes prefixing status subscriptions and messages.
+const STATUS_MAGIC: [u8; 4] = *b"FNST";
+
+/// How often the status of the node is checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+const CONNECTED: u8 = 1;
+const MIN_PEERS_REACHED: u8 = 2;
+const GATEWAY_REACHABLE: u8 = 4;
+
+static STATUS: OnceLock<watch::Sender<NetworkStatus>> = OnceLock::new();
+
+pub(crate) fn is_subscription(msg: &[u8]) -> bool {
+    msg == STATUS_MAGIC
+}
+
+/// Changes of the status of the node, checked as long as the process runs once first
+/// subscribed to.
+pub(crate) fn subscribe() -> watch::Receiver<NetworkStatus> {
+    STATUS
+        .get_or_init(|| {
+            let (sender, _) = watch::channel(current());
+            let checked = sender.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(CHECK_INTERVAL);
+                interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                loop {
+                    interval.tick().await;
+                    let status = current();
+                    checked.send_if_modified(|previous| {
+                        let changed = flags(previous) != flags(&status);
+                        *previous = status;
+                        changed
+                    });
+                }
+            });
+            sender
+        })
+        .subscribe()
+}
+
+fn current() -> NetworkStatus {
+    NodeHandle::running()
+        .map(|node| node.network_status())
+        .unwrap_or_default()
+}
+
+fn flags(status: &NetworkStatus) -> u8 {
+    let mut flags = 0;
+    if status.peers > 0 {
+        flags |= CONNECTED;
+    }
+    if status.min_peers_reached {
+        flags |= MIN_PEERS_REACHED;
+    }
+    if status.gateway_reachable {
+        flags |= GATEWAY_REACHABLE;
+    }
+    flags
+}
+
+pub(crate) fn encode(status: &NetworkStatus) -> io::Result<Vec<u8>> {
+    let mut msg = Vec::with_capacity(STATUS_MAGIC.len() + 5);
+    msg.extend_from_slice(&STATUS_MAGIC);
+    msg.write_u8(flags(status))?;
+    msg.write_u32::<BigEndian>(u32::try_from(status.peers).unwrap_or(u32::MAX))?;
+    Ok(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_framing() -> Result<(), Box<dyn std::error::Error>> {
+        assert!(is_subscription(b"FNST"));
+        assert!(!is_subscription(b"FNSTX"));
+
+        let online = NetworkStatus {
+            peers: 3,
+            min_peers_reached: true,
+            gateway_reachable: false,
+        };
+        assert_eq!(
+            encode(&online)?,
+            [&b"FNST"[..], &[3], &3u32.to_be_bytes()].concat()
+        );
+        let offline = NetworkStatus::default();
+        assert_eq!(encode(&offline)?, [&b"FNST"[..], &[0], &[0; 4]].concat());
+        // more peers are not a change of status
+        assert_eq!(flags(&online), flags(&NetworkStatus { peers: 4, ..online }));
+        Ok(())
+    }
+}
@@ -1,35 +1,69 @@
 use std::{
     collections::{HashMap, VecDeque},
-    sync::{Arc, OnceLock, RwLock},
-    time::Duration,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, OnceLock, RwLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use axum::{
     extract::{
-        ws::{Message, WebSocket},
-        Query, WebSocketUpgrade,
+        ws::{close_code, CloseFrame, Message, WebSocket},
+        ConnectInfo, Query, State, WebSocketUpgrade,
     },
-    http::StatusCode,
+    http::{HeaderName, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     routing::get,
     Extension, Router,
 };
+use dashmap::{mapref::entry::Entry, DashMap};
 use freenet_stdlib::{
-    client_api::{ClientRequest, ContractRequest, ContractResponse, ErrorKind, HostResponse},
+    client_api::{
+        ClientRequest, ContractRequest, ContractResponse, DelegateError, ErrorKind, HostResponse,
+    },
     prelude::*,
 };
-use futures::{future::BoxFuture, stream::SplitSink, FutureExt, SinkExt, StreamExt};
-use headers::Header;
+use futures::{
+    future::{AbortHandle, Abortable, BoxFuture},
+    stream::SplitSink,
+    FutureExt, SinkExt, StreamExt,
+};
+use headers::{Header, HeaderMapExt};
 use serde::Deserialize;
-use tokio::sync::{mpsc, Mutex};
+use tokio::{
+    sync::{mpsc, Mutex, Notify},
+    task::JoinHandle,
+};
 
 use crate::{
     client_events::AuthToken,
-    server::{ClientConnection, HostCallbackResult},
+    config::{ClientLimitsConfig, ListenerAuth, OutboundQueueConfig, WebsocketApiConfig},
+    message::Priority,
+    node::admin::NodeHandle,
+    server::{
+        access_log::{AccessLog, ClientAccessLog},
+        admin::OpenClients,
+        ApiTokens, ClientConnection, HostCallbackResult, TokenGrant,
+    },
     util::EncodingProtocol,
 };
 
-use super::{ClientError, ClientEventsProxy, ClientId, HostResult, OpenRequest};
+use super::{
+    attestation::{Attestation, AttestationRequest},
+    batch::{Batch, Cancel, RequestOptions},
+    chunks::Chunker,
+    deflate::{self, CompressionContexts, Deflater, MessageCompression},
+    envelope::{self, RequestEnvelope},
+    error_details::{self, ErrorDetails},
+    heartbeat::{ClientRoundTrip, Heartbeat, HeartbeatRequest},
+    outbound::{NotificationQueue, QueueFull},
+    revalidate,
+    session::{SavedSession, SessionToken, Sessions, Subscription, SubscriptionListeners},
+    status, AckLevel, ClientError, ClientEventsProxy, ClientId, GetMode, HostResult, OpenRequest,
+    Replicas, SubscriptionMode,
+};
 use crate::server::http_gateway::AttestedContractMap;
 
 #[derive(Clone)]
@@ -46,10 +80,159 @@ impl std::ops::Deref for WebSocketRequest {
 pub(crate) struct WebSocketProxy {
     proxy_server_request: mpsc::Receiver<ClientConnection>,
     response_channels: HashMap<ClientId, mpsc::UnboundedSender<HostCallbackResult>>,
+    subscription_modes: HashMap<ClientId, SubscriptionMode>,
+    ack_levels: HashMap<ClientId, AckLevel>,
+    get_modes: HashMap<ClientId, GetMode>,
+    replicas: HashMap<ClientId, Replicas>,
 }
 
 const PARALLELISM: usize = 10; // TODO: get this from config, or whatever optimal way
 
+/// How long the messages left for a closed connection are given to be written to it.
+const WRITER_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug)]
+pub(crate) struct OutboundSettings {
+    /// Max size of the messages sent to clients speaking [`ProtocolVersion::V3`] or later.
+    stream_chunk_size: usize,
+    queue: OutboundQueueConfig,
+    compression_contexts: CompressionContexts,
+}
+
+impl From<&WebsocketApiConfig> for OutboundSettings {
+    fn from(config: &WebsocketApiConfig) -> Self {
+        Self {
+            stream_chunk_size: config.stream_chunk_size as usize,
+            queue: config.outbound_queue,
+            compression_contexts: CompressionContexts::new(config.compression_contexts),
+        }
+    }
+}
+
+/// Resources each websocket client can hold, unlimited unless configured.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ClientLimits {
+    config: Option<ClientLimitsConfig>,
+    /// Open connections per client IP.
+    connections: Arc<DashMap<IpAddr, usize>>,
+}
+
+/// Counts as an open connection of the client until dropped.
+struct ConnectionSlot {
+    connections: Arc<DashMap<IpAddr, usize>>,
+    addr: Option<IpAddr>,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let Some(addr) = self.addr else {
+            return;
+        };
+        if let Entry::Occupied(mut entry) = self.connections.entry(addr) {
+            *entry.get_mut() -= 1;
+            if *entry.get() == 0 {
+                entry.remove();
+            }
+        }
+    }
+}
+
+impl ClientLimits {
+    pub fn new(config: Option<ClientLimitsConfig>) -> Self {
+        Self {
+            config,
+            connections: Arc::default(),
+        }
+    }
+
+    /// Reserves a connection for the client, `None` if it already has as many open as allowed.
+    fn connect(&self, addr: Option<IpAddr>) -> Option<ConnectionSlot> {
+        let addr = match (&self.config, addr) {
+            (Some(config), Some(addr)) => {
+                let mut open = self.connections.entry(addr).or_insert(0);
+                if *open >= config.max_connections_per_ip as usize {
+                    return None;
+                }
+                *open += 1;
+                Some(addr)
+            }
+            _ => None,
+        };
+        Some(ConnectionSlot {
+            connections: self.connections.clone(),
+            addr,
+        })
+    }
+
+    /// Checks whether a connection holding `subscriptions` subscriptions and awaiting the result
+    /// of `in_flight_ops` operations can issue the request.
+    fn check_request(
+        &self,
+        req: &ClientRequest,
+        subscriptions: usize,
+        in_flight_ops: usize,
+    ) -> Result<(), ClientError> {
+        let Some(config) = &self.config else {
+            return Ok(());
+        };
+        if subscribes(req) && subscriptions >= config.max_subscriptions_per_connection as usize {
+            return Err(ErrorKind::Unhandled {
+                cause: format!(
+                    "Too many subscriptions, at most {} allowed per connection",
+                    config.max_subscriptions_per_connection
+                )
+                .into(),
+            }
+            .into());
+        }
+        if is_operation(req) && in_flight_ops >= config.max_in_flight_ops_per_connection as usize {
+            return Err(ErrorKind::Unhandled {
+                cause: format!(
+                    "Too many operations in flight, at most {} allowed per connection",
+                    config.max_in_flight_ops_per_connection
+                )
+                .into(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Requests subscribing the client to a contract.
+fn subscribes(req: &ClientRequest) -> bool {
+    matches!(
+        req,
+        ClientRequest::ContractOp(
+            ContractRequest::Subscribe { .. }
+                | ContractRequest::Get {
+                    subscribe: true,
+                    ..
+                }
+        )
+    )
+}
+
+/// Requests answered with the result of an operation on a contract or delegate.
+/// Requests which can be retried, as running them again has no other effect.
+fn is_retriable(req: &ClientRequest) -> bool {
+    matches!(
+        req,
+        ClientRequest::ContractOp(
+            ContractRequest::Get { .. }
+                | ContractRequest::Put { .. }
+                | ContractRequest::Subscribe { .. }
+        )
+    )
+}
+
+fn is_operation(req: &ClientRequest) -> bool {
+    matches!(
+        req,
+        ClientRequest::ContractOp(_) | ClientRequest::DelegateOp(_)
+    )
+}
+
 impl WebSocketProxy {
     pub fn create_router(server_routing: Router) -> (Self, Router) {
         // Create a default empty attested contracts map
@@ -57,19 +240,42 @@ impl WebSocketProxy {
             AuthToken,
             (ContractInstanceId, ClientId),
         >::new()));
-        Self::create_router_with_attested_contracts(server_routing, attested_contracts)
+        Self::create_router_with_attested_contracts(
+            server_routing,
+            attested_contracts,
+            ApiTokens::default(),
+            ClientLimits::default(),
+            Sessions::default(),
+            OutboundSettings::from(&WebsocketApiConfig::default()),
+        )
     }
 
     pub fn create_router_with_attested_contracts(
         server_routing: Router,
         attested_contracts: AttestedContractMap,
+        api_tokens: ApiTokens,
+        client_limits: ClientLimits,
+        sessions: Sessions,
+        outbound: OutboundSettings,
     ) -> (Self, Router) {
         let (proxy_request_sender, proxy_server_request) = mpsc::channel(PARALLELISM);
+        restore_sessions(
+            sessions.clone(),
+            WebSocketRequest(proxy_request_sender.clone()),
+        );
 
         // Using Extension instead of with_state to avoid changing the Router's type parameter
         let router = server_routing
             .route("/v1/contract/command", get(websocket_commands))
             .layer(Extension(attested_contracts))
+            .layer(axum::middleware::from_fn_with_state(
+                api_tokens,
+                listener_api_tokens,
+            ))
+            .layer(Extension(OpenClients::default()))
+            .layer(Extension(client_limits))
+            .layer(Extension(sessions))
+            .layer(Extension(outbound))
             .layer(Extension(WebSocketRequest(proxy_request_sender)))
             .layer(axum::middleware::from_fn(connection_info));
 
@@ -77,6 +283,10 @@ impl WebSocketProxy {
             WebSocketProxy {
                 proxy_server_request,
                 response_channels: HashMap::new(),
+                subscription_modes: HashMap::new(),
+                ack_levels: HashMap::new(),
+                get_modes: HashMap::new(),
+                replicas: HashMap::new(),
             },
             router,
         )
@@ -87,13 +297,24 @@ impl WebSocketProxy {
         msg: ClientConnection,
     ) -> Result<Option<OpenRequest>, ClientError> {
         match msg {
-            ClientConnection::NewConnection { callbacks, .. } => {
+            ClientConnection::NewConnection {
+                callbacks,
+                subscription_mode,
+                ack_level,
+                get_mode,
+                replicas,
+                ..
+            } => {
                 // is a new client, assign an id and open a channel to communicate responses from the node
                 let cli_id = ClientId::next();
                 callbacks
                     .send(HostCallbackResult::NewId { id: cli_id })
                     .map_err(|_e| ErrorKind::NodeUnavailable)?;
                 self.response_channels.insert(cli_id, callbacks);
+                self.subscription_modes.insert(cli_id, subscription_mode);
+                self.ack_levels.insert(cli_id, ack_level);
+                self.get_modes.insert(cli_id, get_mode);
+                self.replicas.insert(cli_id, replicas);
                 Ok(None)
             }
             ClientConnection::Request {
@@ -101,6 +322,10 @@ impl WebSocketProxy {
                 req,
                 auth_token,
                 attested_contract,
+                expected_state,
+                retry_policy,
+                query,
+                priority,
             } => {
                 let open_req = match &*req {
                     ClientRequest::ContractOp(ContractRequest::Subscribe { key, .. }) => {
@@ -114,8 +339,15 @@ impl WebSocketProxy {
                                 callback: rx,
                             })
                             .map_err(|_| ErrorKind::ChannelClosed)?;
+                            let mode = self
+                                .subscription_modes
+                                .get(&client_id)
+                                .copied()
+                                .unwrap_or_default();
                             OpenRequest::new(client_id, req)
                                 .with_notification(tx)
+                                .with_subscription_mode(mode)
+                                .with_priority(priority)
                                 .with_token(auth_token)
                                 .with_attested_contract(attested_contract)
                         } else {
@@ -125,7 +357,18 @@ impl WebSocketProxy {
                     }
                     _ => {
                         // just forward the request to the node
+                        let ack_level =
+                            self.ack_levels.get(&client_id).copied().unwrap_or_default();
+                        let get_mode = self.get_modes.get(&client_id).copied().unwrap_or_default();
+                        let replicas = self.replicas.get(&client_id).copied().unwrap_or_default();
                         OpenRequest::new(client_id, req)
+                            .with_ack_level(ack_level)
+                            .with_get_mode(get_mode)
+                            .with_replicas(replicas)
+                            .with_expected_state(expected_state)
+                            .with_retry_policy(retry_policy)
+                            .with_query(query)
+                            .with_priority(priority)
                             .with_token(auth_token)
                             .with_attested_contract(attested_contract)
                     }
@@ -134,6 +377,24 @@ impl WebSocketProxy {
             }
         }
     }
+
+    /// Sends a response to the client, dropping it if it disconnected or `should_rm`.
+    fn respond(&mut self, id: ClientId, response: HostCallbackResult, should_rm: bool) {
+        if let Some(ch) = self.response_channels.remove(&id) {
+            if ch.send(response).is_ok() && !should_rm {
+                // still alive connection, keep it
+                self.response_channels.insert(id, ch);
+            } else {
+                self.subscription_modes.remove(&id);
+                self.ack_levels.remove(&id);
+                self.get_modes.remove(&id);
+                self.replicas.remove(&id);
+                tracing::info!("dropped connection to client #{id}");
+            }
+        } else {
+            tracing::warn!("client: {id} not found");
+        }
+    }
 }
 
 struct EncodingProtocolExt(EncodingProtocol);
@@ -154,6 +415,9 @@ impl headers::Header for EncodingProtocolExt {
             .and_then(|val| match val.to_str().ok()? {
                 "native" => Some(EncodingProtocolExt(EncodingProtocol::Native)),
                 "flatbuffers" => Some(EncodingProtocolExt(EncodingProtocol::Flatbuffers)),
+                "messagepack" | "msgpack" => {
+                    Some(EncodingProtocolExt(EncodingProtocol::MessagePack))
+                }
                 _ => None,
             })
             .ok_or_else(headers::Error::invalid)
@@ -163,30 +427,129 @@ impl headers::Header for EncodingProtocolExt {
         let header = match self.0 {
             EncodingProtocol::Native => axum::http::HeaderValue::from_static("native"),
             EncodingProtocol::Flatbuffers => axum::http::HeaderValue::from_static("flatbuffers"),
+            EncodingProtocol::MessagePack => axum::http::HeaderValue::from_static("messagepack"),
         };
         values.extend([header]);
     }
 }
 
+/// Versions of the websocket protocol spoken by the server.
+///
+/// Clients ask for the highest version they speak when connecting, through the
+/// `protocol-version` header or query parameter, and the server answers with the version
+/// used for the connection. Clients which don't ask for any predate versioning and are
+/// served [`ProtocolVersion::V1`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ProtocolVersion {
+    /// Protocol spoken before versions were negotiated.
+    V1 = 1,
+    /// The connection is closed with a close frame stating the reason when the node shuts
+    /// down, instead of a serialized error.
+    V2 = 2,
+    /// Responses and notifications larger than the stream chunk size are streamed in chunks
+    /// (see [`super::chunks`]).
+    V3 = 3,
+    /// Errors responded to requests are framed with their code and details (see
+    /// [`super::error_details`]).
+    V4 = 4,
+}
+
+impl ProtocolVersion {
+    const LATEST: Self = Self::V4;
+
+    /// Version to use with a client speaking up to `requested`, `None` if not supported.
+    fn negotiate(requested: u16) -> Option<Self> {
+        match requested {
+            0 => None,
+            1 => Some(Self::V1),
+            2 => Some(Self::V2),
+            3 => Some(Self::V3),
+            _ => Some(Self::LATEST),
+        }
+    }
+}
+
+struct ProtocolVersionExt(u16);
+
+impl headers::Header for ProtocolVersionExt {
+    fn name() -> &'static axum::http::HeaderName {
+        static HEADER: OnceLock<axum::http::HeaderName> = OnceLock::new();
+        HEADER.get_or_init(|| axum::http::HeaderName::from_static("protocol-version"))
+    }
+
+    fn decode<'i, I>(values: &mut I) -> Result<Self, headers::Error>
+    where
+        Self: Sized,
+        I: Iterator<Item = &'i axum::http::HeaderValue>,
+    {
+        values
+            .next()
+            .and_then(|val| val.to_str().ok()?.trim().parse().ok())
+            .map(ProtocolVersionExt)
+            .ok_or_else(headers::Error::invalid)
+    }
+
+    fn encode<E: Extend<axum::http::HeaderValue>>(&self, values: &mut E) {
+        values.extend([axum::http::HeaderValue::from(self.0)]);
+    }
+}
+
+/// Token of the session of the client, to resume it when reconnecting.
+static SESSION_TOKEN: HeaderName = HeaderName::from_static("session-token");
+/// Whether the session of a previous connection was resumed.
+static SESSION_RESUMED: HeaderName = HeaderName::from_static("session-resumed");
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct ConnectionInfo {
     auth_token: Option<AuthToken>,
     encoding_protocol: Option<EncodingProtocol>,
+    protocol_version: Option<u16>,
+    /// What the update notifications of the subscriptions of the client carry.
+    subscription_mode: Option<SubscriptionMode>,
+    /// When the puts and updates of the client are reported as completed.
+    ack_level: Option<AckLevel>,
+    /// How the gets of the client for contracts stored by the node are answered.
+    get_mode: Option<GetMode>,
+    /// Peers near the contracts the values put by the client are replicated to.
+    replicas: Option<Replicas>,
+    /// Session of a previous connection to resume.
+    session_token: Option<SessionToken>,
+    /// Compression of the messages sent to the client, see [`super::deflate`].
+    compression: Option<MessageCompression>,
+}
+
+/// Provides the API tokens to the handlers, none for listeners open to any client so the
+/// API is not restricted on them.
+async fn listener_api_tokens(
+    State(api_tokens): State<ApiTokens>,
+    mut req: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let api_tokens = match req.extensions().get::<ListenerAuth>() {
+        Some(ListenerAuth::Open) => ApiTokens::default(),
+        Some(ListenerAuth::Tokens) | None => api_tokens,
+    };
+    req.extensions_mut().insert(api_tokens);
+    next.run(req).await
 }
 
 async fn connection_info(
     Query(ConnectionInfo {
         auth_token: auth_token_q,
         encoding_protocol,
+        protocol_version,
+        subscription_mode,
+        ack_level,
+        get_mode,
+        replicas,
+        session_token,
+        compression,
     }): Query<ConnectionInfo>,
     mut req: axum::extract::Request,
     next: axum::middleware::Next,
 ) -> Response {
-    use headers::{
-        authorization::{Authorization, Bearer},
-        HeaderMapExt,
-    };
+    use headers::authorization::{Authorization, Bearer};
     // tracing::info!(
     //     "headers: {:?}",
     //     req.headers()
@@ -225,11 +588,57 @@ async fn connection_info(
         }
     };
 
+    let requested_version = match req.headers().typed_try_get::<ProtocolVersionExt>() {
+        Ok(Some(version)) => Some(version.0),
+        Ok(None) => protocol_version,
+        Err(_error) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Incorrect `{header}` header specification",
+                    header = ProtocolVersionExt::name()
+                ),
+            )
+                .into_response()
+        }
+    };
+    let protocol_version = match requested_version {
+        Some(requested) => match ProtocolVersion::negotiate(requested) {
+            Some(version) => version,
+            None => {
+                return (
+                    StatusCode::UPGRADE_REQUIRED,
+                    format!(
+                        "Unsupported protocol version {requested}, supported versions are {} to {}",
+                        ProtocolVersion::V1 as u16,
+                        ProtocolVersion::LATEST as u16
+                    ),
+                )
+                    .into_response()
+            }
+        },
+        None => ProtocolVersion::V1,
+    };
+
     tracing::debug!(
-        ?auth_token_q, ?auth_token, request_uri = ?req.uri(), "connection_info middleware extracting auth token and encoding protocol",
+        ?auth_token_q, ?auth_token, ?protocol_version, request_uri = ?req.uri(), "connection_info middleware extracting auth token and encoding protocol",
     );
     req.extensions_mut().insert(encoding_protoc);
+    req.extensions_mut().insert(protocol_version);
     req.extensions_mut().insert(auth_token);
+    req.extensions_mut()
+        .insert(subscription_mode.unwrap_or_default());
+    req.extensions_mut().insert(ack_level.unwrap_or_default());
+    req.extensions_mut().insert(get_mode.unwrap_or_default());
+    req.extensions_mut().insert(replicas.unwrap_or_default());
+    req.extensions_mut().insert(compression);
+    let session_token = req
+        .headers()
+        .get(&SESSION_TOKEN)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| SessionToken::from(v.to_owned()))
+        .or(session_token);
+    req.extensions_mut().insert(session_token);
 
     next.run(req).await
 }
@@ -238,10 +647,73 @@ async fn websocket_commands(
     ws: WebSocketUpgrade,
     Extension(auth_token): Extension<Option<AuthToken>>,
     Extension(encoding_protoc): Extension<EncodingProtocol>,
+    Extension(protocol_version): Extension<ProtocolVersion>,
+    Extension(subscription_mode): Extension<SubscriptionMode>,
+    Extension(ack_level): Extension<AckLevel>,
+    Extension(get_mode): Extension<GetMode>,
+    Extension(replicas): Extension<Replicas>,
+    Extension(compression): Extension<Option<MessageCompression>>,
     Extension(rs): Extension<WebSocketRequest>,
     Extension(attested_contracts): Extension<AttestedContractMap>,
+    Extension(api_tokens): Extension<ApiTokens>,
+    Extension(open_clients): Extension<OpenClients>,
+    Extension(client_limits): Extension<ClientLimits>,
+    Extension(sessions): Extension<Sessions>,
+    Extension(session_token): Extension<Option<SessionToken>>,
+    Extension(outbound): Extension<OutboundSettings>,
+    access_log: Option<Extension<AccessLog>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
 ) -> Response {
+    // tokens assigned to webapps served by the gateway are restricted to their contract
+    let grant = if api_tokens.is_enabled() {
+        let attested = auth_token.as_ref().and_then(|token| {
+            let attested_contracts = attested_contracts.read().unwrap();
+            attested_contracts.get(token).map(|(contract, _)| *contract)
+        });
+        match auth_token
+            .as_ref()
+            .and_then(|token| api_tokens.grant(token))
+        {
+            Some(grant) => Some(grant),
+            None if attested.is_some() => attested.map(TokenGrant::attested),
+            None => {
+                tracing::debug!("Rejecting websocket connection without a valid API token");
+                return (StatusCode::UNAUTHORIZED, "Missing or invalid API token").into_response();
+            }
+        }
+    } else {
+        None
+    };
+
+    let Some(connection_slot) =
+        client_limits.connect(connect_info.map(|ConnectInfo(addr)| addr.ip()))
+    else {
+        tracing::debug!(
+            client = ?connect_info.map(|ConnectInfo(addr)| addr),
+            "Rejecting websocket connection over the per client limit"
+        );
+        return (StatusCode::TOO_MANY_REQUESTS, "Too many open connections").into_response();
+    };
+
+    let session = sessions.open(session_token.as_ref(), auth_token.as_ref());
+    let session_headers = session.as_ref().and_then(|session| {
+        Some((
+            HeaderValue::from_str(session.token.as_str()).ok()?,
+            session.resumed,
+        ))
+    });
+    let (session_token, subscriptions, missed) = match session {
+        Some(session) => (Some(session.token), session.subscriptions, session.missed),
+        None => (None, Vec::new(), VecDeque::new()),
+    };
+    let deflater = match compression {
+        Some(MessageCompression::Deflate) => outbound.compression_contexts.acquire(),
+        None => None,
+    };
+    let compressed = deflater.is_some();
+
     let on_upgrade = move |ws: WebSocket| async move {
+        let _connection_slot = connection_slot;
         // Get the data we need and immediately drop the lock
         let auth_and_instance = if let Some(token) = auth_token.as_ref() {
             let attested_contracts_read = attested_contracts.read().unwrap();
@@ -270,27 +742,118 @@ async fn websocket_commands(
         } else {
             tracing::trace!(protoc = ?ws.protocol(), "websocket connection established");
         }
-        if let Err(error) =
-            websocket_interface(rs.clone(), auth_and_instance, encoding_protoc, ws).await
+        let chunker = Chunker::new(
+            (protocol_version >= ProtocolVersion::V3).then_some(outbound.stream_chunk_size),
+        );
+        let client = (
+            open_clients,
+            connect_info.map(|ConnectInfo(addr)| addr),
+            access_log.map(|Extension(log)| log),
+        );
+        let contract_updates = Arc::new(Mutex::new(VecDeque::from(subscriptions)));
+        if let Some(session_token) = &session_token {
+            sessions.attach(
+                session_token.clone(),
+                auth_token.clone(),
+                contract_updates.clone(),
+            );
+        }
+        if let Err(error) = websocket_interface(
+            rs.clone(),
+            (contract_updates.clone(), missed),
+            auth_and_instance,
+            grant,
+            (encoding_protoc, protocol_version),
+            (chunker, deflater, outbound.queue),
+            (subscription_mode, ack_level, get_mode, replicas),
+            client,
+            client_limits,
+            ws,
+        )
+        .await
         {
             tracing::error!("{error}");
         }
+        if let Some(session_token) = session_token {
+            let subscriptions = contract_updates.lock().await.drain(..).collect();
+            sessions.park(session_token, auth_token, subscriptions);
+        }
     };
 
-    ws.on_upgrade(on_upgrade)
+    let mut response = ws.on_upgrade(on_upgrade);
+    response
+        .headers_mut()
+        .typed_insert(ProtocolVersionExt(protocol_version as u16));
+    if compressed {
+        response
+            .headers_mut()
+            .insert(&deflate::COMPRESSION, HeaderValue::from_static("deflate"));
+    }
+    if let Some((session_token, resumed)) = session_headers {
+        let headers = response.headers_mut();
+        headers.insert(&SESSION_TOKEN, session_token);
+        headers.insert(
+            &SESSION_RESUMED,
+            HeaderValue::from_static(if resumed { "true" } else { "false" }),
+        );
+    }
+    response
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn websocket_interface(
     request_sender: WebSocketRequest,
+    (contract_updates, missed): (SubscriptionListeners, VecDeque<HostResult>),
     mut auth_token: Option<(AuthToken, ContractInstanceId)>,
-    encoding_protoc: EncodingProtocol,
+    grant: Option<TokenGrant>,
+    (encoding_protoc, protocol_version): (EncodingProtocol, ProtocolVersion),
+    (chunker, deflater, queue): (Chunker, Option<Deflater>, OutboundQueueConfig),
+    (subscription_mode, ack_level, get_mode, replicas): (
+        SubscriptionMode,
+        AckLevel,
+        GetMode,
+        Replicas,
+    ),
+    (open_clients, remote_addr, access_log): (OpenClients, Option<SocketAddr>, Option<AccessLog>),
+    client_limits: ClientLimits,
     ws: WebSocket,
 ) -> anyhow::Result<()> {
-    let (mut response_rx, client_id) =
-        new_client_connection(&request_sender, auth_token.clone()).await?;
-    let (mut server_sink, mut client_stream) = ws.split();
-    let contract_updates: Arc<Mutex<VecDeque<(_, mpsc::UnboundedReceiver<HostResult>)>>> =
-        Arc::new(Mutex::new(VecDeque::new()));
+    let (mut response_rx, client_id) = new_client_connection(
+        &request_sender,
+        auth_token.clone(),
+        subscription_mode,
+        ack_level,
+        get_mode,
+        replicas,
+    )
+    .await?;
+    let _registration = open_clients.register(client_id, remote_addr, grant.clone());
+    let (server_sink, mut client_stream) = ws.split();
+    let (outbound, _writer) =
+        Outbound::spawn(server_sink, (chunker, deflater), encoding_protoc, queue);
+    for notification in missed {
+        outbound.send(Message::Binary(encode_result(
+            notification,
+            encoding_protoc,
+        )?))?;
+    }
+    let in_flight_ops = Arc::new(AtomicUsize::new(0));
+    let access_log = access_log.map(|log| log.for_client(remote_addr));
+    let (batch_outcomes, mut batch_outcomes_rx) = mpsc::unbounded_channel();
+    let batches = BatchRunner {
+        request_sender: request_sender.clone(),
+        encoding_protoc,
+        protocol_version,
+        subscription_mode,
+        ack_level,
+        replicas,
+        in_flight_ops: in_flight_ops.clone(),
+        outcomes: batch_outcomes,
+        running: Arc::default(),
+    };
+    let status_updates = std::sync::Mutex::new(None);
+    let heartbeats = std::sync::Mutex::new(None);
+    let client_round_trip = ClientRoundTrip::default();
     loop {
         let contract_updates_cp = contract_updates.clone();
         let listeners_task = async move {
@@ -302,7 +865,7 @@ async fn websocket_interface(
                         match listener.try_recv() {
                             Ok(r) => {
                                 active_listeners.push_back((key, listener));
-                                return Ok(r);
+                                return Ok((key, r));
                             }
                             Err(mpsc::error::TryRecvError::Empty) => {
                                 active_listeners.push_back((key, listener));
@@ -331,37 +894,105 @@ async fn websocket_interface(
                 }
                 Ok(v) => v,
             };
+            let subscriptions = contract_updates.lock().await.len();
+            if let Ok(Message::Pong(payload)) = &next_msg {
+                client_round_trip.pong(payload);
+                return Ok(None);
+            }
+            if let Ok(Message::Binary(data)) = &next_msg {
+                if status::is_subscription(data) {
+                    status_updates
+                        .lock()
+                        .unwrap()
+                        .get_or_insert_with(|| outbound.forward_status());
+                    return Ok(None);
+                }
+                if HeartbeatRequest::is_request(data) {
+                    let interval = match HeartbeatRequest::decode(data) {
+                        Ok(HeartbeatRequest { interval }) => interval,
+                        Err(err) => {
+                            let error = ErrorKind::DeserializationError {
+                                cause: format!("invalid heartbeat request: {err}").into(),
+                            };
+                            return error_message(
+                                error.into(),
+                                (encoding_protoc, protocol_version),
+                            )
+                            .map(Some)
+                            .map_err(Some);
+                        }
+                    };
+                    *heartbeats.lock().unwrap() = interval.map(|interval| {
+                        outbound.forward_heartbeats(interval, client_round_trip.clone())
+                    });
+                    return Ok(None);
+                }
+                if AttestationRequest::is_request(data) {
+                    let contract = auth_token.as_ref().map(|(_, contract)| *contract);
+                    return attest_delegate(data, contract, (encoding_protoc, protocol_version))
+                        .await
+                        .map(Some)
+                        .map_err(Some);
+                }
+                if Cancel::is_cancel(data) {
+                    return cancel_batch(data, &batches).map_err(Some);
+                }
+                if Batch::is_batch(data) {
+                    let usage = (&client_limits, subscriptions, &*in_flight_ops);
+                    return start_batch(
+                        data,
+                        &batches,
+                        auth_token.clone(),
+                        grant.as_ref(),
+                        usage,
+                        access_log.as_ref(),
+                    )
+                    .map_err(Some);
+                }
+            }
             process_client_request(
                 client_id,
                 next_msg,
                 &request_sender,
                 &mut auth_token.as_mut().map(|t| t.0.clone()),
                 auth_token.as_mut().map(|t| t.1),
-                encoding_protoc,
+                grant.as_ref(),
+                (&client_limits, subscriptions, &*in_flight_ops),
+                access_log.as_ref(),
+                (encoding_protoc, protocol_version),
             )
             .await
         };
 
         tokio::select! { biased;
-            msg = async { process_host_response(response_rx.recv().await, client_id, encoding_protoc, &mut server_sink).await } => {
+            msg = response_rx.recv() => {
                 let active_listeners = contract_updates.clone();
-                if let Some(NewSubscription { key, callback }) = msg? {
+                if let Some(NewSubscription { key, callback }) = process_host_response(msg, client_id, encoding_protoc, protocol_version, &outbound, &in_flight_ops)? {
                     tracing::debug!(cli_id = %client_id, contract = %key, "added new notification listener");
                     let active_listeners = &mut *active_listeners.lock().await;
                     active_listeners.push_back((key, callback));
                 }
             }
+            Some(outcome) = batch_outcomes_rx.recv() => {
+                match outcome {
+                    BatchOutcome::Subscription(NewSubscription { key, callback }) => {
+                        tracing::debug!(cli_id = %client_id, contract = %key, "added new notification listener from batch");
+                        contract_updates.lock().await.push_back((key, callback));
+                    }
+                    BatchOutcome::Results(msg) => {
+                        outbound.send(Message::Binary(msg))?;
+                    }
+                }
+            }
             process_client_request = client_req_task => {
                 match process_client_request {
                     Ok(Some(error)) => {
-                        server_sink.send(error).await.inspect_err(|err| {
-                            tracing::debug!(err = %err, "error sending message to client");
-                        })?;
+                        outbound.send(error)?;
                     }
                     Ok(None) => continue,
                     Err(None) => {
                         tracing::debug!("client channel closed on request");
-                        let _ = server_sink.send(Message::Close(None)).await;
+                        let _ = outbound.send(Message::Close(None));
                         return Ok(())
                     },
                     Err(Some(err)) => {
@@ -371,29 +1002,114 @@ async fn websocket_interface(
                 }
             }
             response = listeners_task => {
-                let response = response?;
+                let (key, response) = response?;
                 match &response {
-                    Ok(res) => tracing::debug!(response = %res, cli_id = %client_id, "sending notification"),
-                    Err(err) => tracing::debug!(response = %err, cli_id = %client_id, "sending notification error"),
+                    Ok(res) => tracing::debug!(response = %res, cli_id = %client_id, "queueing notification"),
+                    Err(err) => tracing::debug!(response = %err, cli_id = %client_id, "queueing notification error"),
+                }
+                if let Err(err) = outbound.notify(key, response) {
+                    tracing::debug!(cli_id = %client_id, "{err}, disconnecting");
+                    let close = (protocol_version >= ProtocolVersion::V2).then(|| CloseFrame {
+                        code: close_code::AGAIN,
+                        reason: err.to_string().into(),
+                    });
+                    let _ = outbound.send(Message::Close(close));
+                    return Err(err.into());
                 }
-                let serialized_res = match encoding_protoc {
-                    EncodingProtocol::Flatbuffers => match response {
-                        Ok(res) => res.into_fbs_bytes()?,
-                        Err(err) => err.into_fbs_bytes()?,
-                    },
-                    EncodingProtocol::Native => bincode::serialize(&response)?,
-                };
-                server_sink.send(Message::Binary(serialized_res)).await.inspect_err(|err| {
-                    tracing::debug!(err = %err, "error sending message to client");
-                })?;
             }
         }
     }
 }
 
+/// Subscribes again on behalf of the sessions saved before the node restarted once it connects
+/// to the network, parking their subscriptions until the clients reconnect, and then saves the
+/// sessions periodically.
+fn restore_sessions(sessions: Sessions, request_sender: WebSocketRequest) {
+    let saved = sessions.take_saved();
+    if saved.is_empty() {
+        sessions.save_periodically();
+        return;
+    }
+    tokio::spawn(async move {
+        let mut status = status::subscribe();
+        if status.wait_for(|status| status.peers > 0).await.is_ok() {
+            for session in saved {
+                let token = session.token.clone();
+                if let Err(error) = restore_session(&sessions, &request_sender, session).await {
+                    tracing::warn!(session = token.as_str(), %error, "Failed restoring session");
+                }
+            }
+        }
+        sessions.save_periodically();
+    });
+}
+
+async fn restore_session(
+    sessions: &Sessions,
+    request_sender: &WebSocketRequest,
+    session: SavedSession,
+) -> Result<(), ClientError> {
+    let (mut callbacks, client_id) = new_client_connection(
+        request_sender,
+        None,
+        SubscriptionMode::default(),
+        AckLevel::default(),
+        GetMode::default(),
+        Replicas::default(),
+    )
+    .await?;
+    let mut subscriptions = Vec::with_capacity(session.contracts.len());
+    for key in session.contracts {
+        request_sender
+            .send(ClientConnection::Request {
+                client_id,
+                req: Box::new(ContractRequest::Subscribe { key, summary: None }.into()),
+                auth_token: session.auth_token.clone(),
+                attested_contract: None,
+                expected_state: None,
+                retry_policy: None,
+                query: None,
+                priority: Priority::default(),
+            })
+            .await
+            .map_err(|_| ErrorKind::NodeUnavailable)?;
+        let mut listener = None;
+        loop {
+            match callbacks.recv().await {
+                Some(HostCallbackResult::SubscriptionChannel { callback, .. }) => {
+                    listener = Some(callback);
+                }
+                Some(HostCallbackResult::Result { result: Ok(_), .. }) => {
+                    subscriptions.extend(listener.map(|listener| (key, listener)));
+                    break;
+                }
+                Some(HostCallbackResult::Result {
+                    result: Err(error), ..
+                }) => {
+                    tracing::debug!(%key, %error, "Failed restoring subscription");
+                    break;
+                }
+                Some(_) => {}
+                None => return Err(ErrorKind::NodeUnavailable.into()),
+            }
+        }
+    }
+    tracing::debug!(
+        session = session.token.as_str(),
+        subscriptions = subscriptions.len(),
+        "Restored session"
+    );
+    sessions.park(session.token, session.auth_token, subscriptions);
+    Ok(())
+}
+
 async fn new_client_connection(
     request_sender: &WebSocketRequest,
     assigned_token: Option<(AuthToken, ContractInstanceId)>,
+    subscription_mode: SubscriptionMode,
+    ack_level: AckLevel,
+    get_mode: GetMode,
+    replicas: Replicas,
 ) -> Result<(mpsc::UnboundedReceiver<HostCallbackResult>, ClientId), ClientError> {
     let (response_sender, mut response_recv) = mpsc::unbounded_channel();
     tracing::debug!(?assigned_token, "sending new client connection request");
@@ -401,6 +1117,10 @@ async fn new_client_connection(
         .send(ClientConnection::NewConnection {
             callbacks: response_sender,
             assigned_token,
+            subscription_mode,
+            ack_level,
+            get_mode,
+            replicas,
         })
         .await
         .map_err(|_| ErrorKind::NodeUnavailable)?;
@@ -416,13 +1136,17 @@ struct NewSubscription {
     callback: mpsc::UnboundedReceiver<HostResult>,
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn process_client_request(
     client_id: ClientId,
     msg: Result<Message, axum::Error>,
     request_sender: &mpsc::Sender<ClientConnection>,
     auth_token: &mut Option<AuthToken>,
     attested_contract: Option<ContractInstanceId>,
-    encoding_protoc: EncodingProtocol,
+    grant: Option<&TokenGrant>,
+    (client_limits, subscriptions, in_flight_ops): (&ClientLimits, usize, &AtomicUsize),
+    access_log: Option<&ClientAccessLog>,
+    (encoding_protoc, protocol_version): (EncodingProtocol, ProtocolVersion),
 ) -> Result<Option<Message>, Option<anyhow::Error>> {
     let msg = match msg {
         Ok(Message::Binary(data)) => data,
@@ -436,29 +1160,101 @@ async fn process_client_request(
         Err(err) => return Err(Some(err.into())),
     };
 
-    // Try to deserialize the ClientRequest message
-    let req = {
-        match encoding_protoc {
-            EncodingProtocol::Flatbuffers => match ClientRequest::try_decode_fbs(&msg) {
-                Ok(decoded) => decoded.into_owned(),
-                Err(err) => return Ok(Some(Message::Binary(err.into_fbs_bytes()))),
-            },
-            EncodingProtocol::Native => match bincode::deserialize::<ClientRequest>(&msg) {
-                Ok(decoded) => decoded.into_owned(),
-                Err(err) => {
-                    let result_error = bincode::serialize(&Err::<HostResponse, ClientError>(
-                        ErrorKind::DeserializationError {
-                            cause: format!("{err}").into(),
-                        }
-                        .into(),
-                    ))
-                    .map_err(|err| Some(err.into()))?;
-                    return Ok(Some(Message::Binary(result_error)));
-                }
-            },
+    let (
+        RequestEnvelope {
+            priority,
+            retry_policy,
+            query,
+            expected_state,
+        },
+        encoded,
+    ) = match envelope::split(&msg) {
+        Ok(split) => split,
+        Err(cause) => {
+            let error = ErrorKind::DeserializationError {
+                cause: cause.into(),
+            };
+            return error_message(error.into(), (encoding_protoc, protocol_version))
+                .map(Some)
+                .map_err(Some);
         }
     };
 
+    // Try to deserialize the ClientRequest message
+    let req = match decode_request(encoded, (encoding_protoc, protocol_version)).map_err(Some)? {
+        Ok(req) => req,
+        Err(error) => return Ok(Some(Message::Binary(error))),
+    };
+
+    if expected_state.is_some()
+        && !matches!(
+            req,
+            ClientRequest::ContractOp(ContractRequest::Update { .. })
+        )
+    {
+        let error = ErrorKind::DeserializationError {
+            cause: "only updates can be conditional".into(),
+        };
+        return error_message(error.into(), (encoding_protoc, protocol_version))
+            .map(Some)
+            .map_err(Some);
+    }
+
+    if retry_policy.is_some()
+        && !matches!(
+            req,
+            ClientRequest::ContractOp(
+                ContractRequest::Get { .. }
+                    | ContractRequest::Put { .. }
+                    | ContractRequest::Subscribe { .. }
+            )
+        )
+    {
+        let error = ErrorKind::DeserializationError {
+            cause: "only gets, puts and subscriptions take retry policies".into(),
+        };
+        return error_message(error.into(), (encoding_protoc, protocol_version))
+            .map(Some)
+            .map_err(Some);
+    }
+
+    if query.is_some()
+        && !matches!(
+            req,
+            ClientRequest::ContractOp(ContractRequest::Get {
+                return_contract_code: false,
+                subscribe: false,
+                ..
+            })
+        )
+    {
+        let error = ErrorKind::DeserializationError {
+            cause: "only gets of the state without subscribing can be queries".into(),
+        };
+        return error_message(error.into(), (encoding_protoc, protocol_version))
+            .map(Some)
+            .map_err(Some);
+    }
+
+    if priority.is_some()
+        && !matches!(
+            req,
+            ClientRequest::ContractOp(
+                ContractRequest::Get { .. }
+                    | ContractRequest::Put { .. }
+                    | ContractRequest::Update { .. }
+                    | ContractRequest::Subscribe { .. }
+            )
+        )
+    {
+        let error = ErrorKind::DeserializationError {
+            cause: "only contract operations take priorities".into(),
+        };
+        return error_message(error.into(), (encoding_protoc, protocol_version))
+            .map(Some)
+            .map_err(Some);
+    }
+
     // Intercept explicit disconnect requests sent by the client as data messages
     if matches!(req, ClientRequest::Disconnect { .. }) {
         // Treat this like a WebSocket close message
@@ -466,32 +1262,738 @@ async fn process_client_request(
         return Err(None); // Signal graceful closure to websocket_interface
     }
 
+    if let Err(error) = check_request(
+        &req,
+        grant,
+        client_limits,
+        subscriptions,
+        in_flight_ops.load(Ordering::Acquire),
+    ) {
+        return error_message(error, (encoding_protoc, protocol_version))
+            .map(Some)
+            .map_err(Some);
+    }
+
     if let ClientRequest::Authenticate { token } = &req {
         *auth_token = Some(AuthToken::from(token.clone()));
     }
 
     tracing::debug!(req = %req, "received client request");
+    if let Some(access_log) = access_log {
+        access_log.operation(&req, msg.len());
+    }
+    if is_operation(&req) {
+        in_flight_ops.fetch_add(1, Ordering::AcqRel);
+    }
     request_sender
         .send(ClientConnection::Request {
             client_id,
             req: Box::new(req),
             auth_token: auth_token.clone(),
             attested_contract,
+            expected_state,
+            retry_policy,
+            query,
+            priority: priority.unwrap_or_default(),
         })
         .await
         .map_err(|err| Some(err.into()))?;
     Ok(None)
 }
 
-async fn process_host_response(
+/// Decodes a request, or encodes the error responded to the client if it is malformed.
+fn decode_request(
+    msg: &[u8],
+    (encoding_protoc, protocol_version): (EncodingProtocol, ProtocolVersion),
+) -> anyhow::Result<Result<ClientRequest<'static>, Vec<u8>>> {
+    let error = |cause: String| {
+        encode_response(
+            Err(ErrorKind::DeserializationError {
+                cause: cause.into(),
+            }
+            .into()),
+            (encoding_protoc, protocol_version),
+        )
+    };
+    let req = match encoding_protoc {
+        EncodingProtocol::Flatbuffers => match ClientRequest::try_decode_fbs(msg) {
+            Ok(decoded) => decoded.into_owned(),
+            Err(err) => return Ok(Err(err.into_fbs_bytes())),
+        },
+        EncodingProtocol::Native => match bincode::deserialize::<ClientRequest>(msg) {
+            Ok(decoded) => decoded.into_owned(),
+            Err(err) => return Ok(Err(error(format!("{err}"))?)),
+        },
+        EncodingProtocol::MessagePack => match rmp_serde::from_slice::<ClientRequest>(msg) {
+            Ok(decoded) => decoded.into_owned(),
+            Err(err) => return Ok(Err(error(format!("{err}"))?)),
+        },
+    };
+    Ok(Ok(req))
+}
+
+/// Checks the request is granted by the API token of the client and within its limits.
+fn check_request(
+    req: &ClientRequest,
+    grant: Option<&TokenGrant>,
+    client_limits: &ClientLimits,
+    subscriptions: usize,
+    in_flight_ops: usize,
+) -> Result<(), ClientError> {
+    if let Some(Err(cause)) = grant.map(|grant| grant.check(req)) {
+        tracing::debug!(req = %req, %cause, "API token does not grant the requested operation");
+        return Err(ErrorKind::Unhandled {
+            cause: cause.into(),
+        }
+        .into());
+    }
+    client_limits
+        .check_request(req, subscriptions, in_flight_ops)
+        .inspect_err(|error| {
+            tracing::debug!(req = %req, %error, "Rejecting request over the client limits");
+        })
+}
+
+/// Decodes and checks the requests of a batch, and starts running them.
+fn start_batch(
+    msg: &[u8],
+    batches: &BatchRunner,
+    assigned_token: Option<(AuthToken, ContractInstanceId)>,
+    grant: Option<&TokenGrant>,
+    (client_limits, mut subscriptions, in_flight_ops): (&ClientLimits, usize, &AtomicUsize),
+    access_log: Option<&ClientAccessLog>,
+) -> anyhow::Result<Option<Message>> {
+    let batch = match Batch::decode(msg) {
+        Ok(batch) => batch,
+        Err(err) => {
+            let error = ErrorKind::DeserializationError {
+                cause: format!("invalid batch: {err}").into(),
+            };
+            return error_message(error.into(), batches.protocols()).map(Some);
+        }
+    };
+    if batches.running.contains_key(&batch.id) {
+        let error = ErrorKind::Unhandled {
+            cause: format!("Batch {} already running", batch.id).into(),
+        };
+        return error_message(error.into(), batches.protocols()).map(Some);
+    }
+    let mut in_flight_ops = in_flight_ops.load(Ordering::Acquire);
+    let mut requests = Vec::with_capacity(batch.entries.len());
+    for entry in batch.entries {
+        let req = match decode_request(entry, batches.protocols())? {
+            Ok(req) => req,
+            Err(error) => {
+                requests.push(Err(error));
+                continue;
+            }
+        };
+        // requests rejected by the node on their own are rejected within batches too
+        if matches!(
+            req,
+            ClientRequest::Disconnect { .. } | ClientRequest::Authenticate { .. }
+        ) {
+            let error = ErrorKind::Unhandled {
+                cause: "Request not allowed in batches".into(),
+            };
+            requests.push(Err(encode_response(
+                Err(error.into()),
+                batches.protocols(),
+            )?));
+            continue;
+        }
+        if let Err(error) = check_request(&req, grant, client_limits, subscriptions, in_flight_ops)
+        {
+            requests.push(Err(encode_response(Err(error), batches.protocols())?));
+            continue;
+        }
+        subscriptions += subscribes(&req) as usize;
+        in_flight_ops += is_operation(&req) as usize;
+        if let Some(access_log) = access_log {
+            access_log.operation(&req, entry.len());
+        }
+        requests.push(Ok(req));
+    }
+    tracing::debug!(
+        batch = batch.id,
+        requests = requests.len(),
+        "received batch request"
+    );
+    batches.run(batch.id, batch.options, requests, assigned_token);
+    Ok(None)
+}
+
+/// Cancels a running batch, answering every request of it with a cancellation error.
+fn cancel_batch(msg: &[u8], batches: &BatchRunner) -> anyhow::Result<Option<Message>> {
+    let id = match Cancel::decode(msg) {
+        Ok(Cancel { id }) => id,
+        Err(err) => {
+            let error = ErrorKind::DeserializationError {
+                cause: format!("invalid batch cancellation: {err}").into(),
+            };
+            return error_message(error.into(), batches.protocols()).map(Some);
+        }
+    };
+    let Some(requests) = batches.cancel(id) else {
+        tracing::debug!(batch = id, "batch to cancel already finished");
+        return Ok(None);
+    };
+    tracing::debug!(batch = id, requests, "cancelled batch");
+    let cancelled = ErrorKind::OperationError {
+        cause: "Operation cancelled".into(),
+    };
+    let cancelled = encode_response(Err(cancelled.into()), batches.protocols())?;
+    let results = Batch {
+        id,
+        options: RequestOptions::default(),
+        entries: vec![cancelled; requests],
+    };
+    Ok(Some(Message::Binary(results.encode()?)))
+}
+
+/// Answers the request for the attestation of a delegate with the attestation signed by the node.
+async fn attest_delegate(
+    msg: &[u8],
+    contract: Option<ContractInstanceId>,
+    protocols: (EncodingProtocol, ProtocolVersion),
+) -> anyhow::Result<Message> {
+    let request = match AttestationRequest::decode(msg) {
+        Ok(request) => request,
+        Err(err) => {
+            let error = ErrorKind::DeserializationError {
+                cause: format!("invalid attestation request: {err}").into(),
+            };
+            return error_message(error.into(), protocols);
+        }
+    };
+    let Some(node) = NodeHandle::running() else {
+        let error = ErrorKind::OperationError {
+            cause: "delegate attestations are only issued by network nodes".into(),
+        };
+        return error_message(error.into(), protocols);
+    };
+    match node.delegate_code_hash(request.delegate.clone()).await {
+        Ok(Some(code_hash)) if code_hash == *request.delegate.code_hash() => {}
+        Ok(_) => {
+            let error = ErrorKind::RequestError(DelegateError::Missing(request.delegate).into());
+            return error_message(error.into(), protocols);
+        }
+        Err(err) => {
+            let error = ErrorKind::OperationError {
+                cause: err.to_string().into(),
+            };
+            return error_message(error.into(), protocols);
+        }
+    }
+    tracing::debug!(delegate = %request.delegate, ?contract, "attesting delegate");
+    let attestation = Attestation {
+        delegate: request.delegate,
+        contract,
+        issued_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        nonce: request.nonce,
+    };
+    Ok(Message::Binary(attestation.sign(node.keypair())?))
+}
+
+enum BatchOutcome {
+    Subscription(NewSubscription),
+    /// Message with the results of a batch.
+    Results(Vec<u8>),
+}
+
+/// Runs the requests of batches concurrently, each as a separate client of the node so the
+/// results can be told apart.
+#[derive(Clone)]
+struct BatchRunner {
+    request_sender: WebSocketRequest,
+    encoding_protoc: EncodingProtocol,
+    protocol_version: ProtocolVersion,
+    subscription_mode: SubscriptionMode,
+    ack_level: AckLevel,
+    replicas: Replicas,
+    in_flight_ops: Arc<AtomicUsize>,
+    outcomes: mpsc::UnboundedSender<BatchOutcome>,
+    /// Batches running, with the number of requests in them.
+    running: Arc<DashMap<u64, (AbortHandle, usize)>>,
+}
+
+impl BatchRunner {
+    fn protocols(&self) -> (EncodingProtocol, ProtocolVersion) {
+        (self.encoding_protoc, self.protocol_version)
+    }
+
+    /// Runs the batch, requests which failed to decode or were rejected are replaced by the
+    /// encoded error responded for them.
+    fn run(
+        &self,
+        id: u64,
+        options: RequestOptions,
+        requests: Vec<Result<ClientRequest<'static>, Vec<u8>>>,
+        assigned_token: Option<(AuthToken, ContractInstanceId)>,
+    ) {
+        let (abort, registration) = AbortHandle::new_pair();
+        self.running.insert(id, (abort, requests.len()));
+        let runner = self.clone();
+        let batch = async move {
+            let ops = requests
+                .iter()
+                .filter(|req| req.as_ref().is_ok_and(is_operation))
+                .count();
+            let in_flight_ops = InFlightOps::new(runner.in_flight_ops.clone(), ops);
+            let results = futures::future::join_all(requests.into_iter().map(|req| async {
+                match req {
+                    Ok(req) => encode_response(
+                        runner.execute_with(req, &assigned_token, options).await,
+                        runner.protocols(),
+                    ),
+                    Err(error) => Ok(error),
+                }
+            }))
+            .await;
+            drop(in_flight_ops);
+            if runner.running.remove(&id).is_none() {
+                // cancelled while finishing
+                return;
+            }
+
+            let msg = results
+                .into_iter()
+                .collect::<anyhow::Result<Vec<_>>>()
+                .and_then(|entries| {
+                    let results = Batch {
+                        id,
+                        options: RequestOptions::default(),
+                        entries,
+                    };
+                    Ok(results.encode()?)
+                });
+            match msg {
+                Ok(msg) => {
+                    let _ = runner.outcomes.send(BatchOutcome::Results(msg));
+                }
+                Err(err) => tracing::error!(batch = id, "Failed encoding batch results: {err}"),
+            }
+        };
+        tokio::spawn(Abortable::new(batch, registration));
+    }
+
+    /// Aborts a running batch, returns the number of requests in it.
+    fn cancel(&self, id: u64) -> Option<usize> {
+        let (_, (abort, requests)) = self.running.remove(&id)?;
+        abort.abort();
+        Some(requests)
+    }
+
+    /// Executes a request within the deadline of the options, retrying get, put and subscribe
+    /// requests which fail or time out as many times as allowed.
+    async fn execute_with(
+        &self,
+        req: ClientRequest<'static>,
+        assigned_token: &Option<(AuthToken, ContractInstanceId)>,
+        options: RequestOptions,
+    ) -> HostResult {
+        let retries = if is_retriable(&req) {
+            options.retries
+        } else {
+            0
+        };
+        let mut attempt = 0;
+        loop {
+            let execution = self.execute(req.clone(), assigned_token);
+            let result = match options.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, execution)
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(ErrorKind::OperationError {
+                            cause: format!("Operation timed out after {} ms", timeout.as_millis())
+                                .into(),
+                        }
+                        .into())
+                    }),
+                None => execution.await,
+            };
+            match result {
+                Err(err)
+                    if attempt < retries
+                        && matches!(
+                            err.kind(),
+                            ErrorKind::OperationError { .. } | ErrorKind::FailedOperation
+                        ) =>
+                {
+                    attempt += 1;
+                    tracing::debug!(%err, attempt, "retrying request");
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn execute(
+        &self,
+        req: ClientRequest<'static>,
+        assigned_token: &Option<(AuthToken, ContractInstanceId)>,
+    ) -> HostResult {
+        let keep_client = subscribes(&req);
+        let (mut responses, client_id) = new_client_connection(
+            &self.request_sender,
+            assigned_token.clone(),
+            self.subscription_mode,
+            self.ack_level,
+            // batches are answered once per request
+            GetMode::Stored,
+            self.replicas,
+        )
+        .await?;
+        let client = BatchClient {
+            request_sender: Some(self.request_sender.clone()),
+            client_id,
+        };
+        self.request_sender
+            .send(ClientConnection::Request {
+                client_id,
+                req: Box::new(req),
+                auth_token: assigned_token.as_ref().map(|(token, _)| token.clone()),
+                attested_contract: assigned_token.as_ref().map(|(_, contract)| *contract),
+                expected_state: None,
+                retry_policy: None,
+                query: None,
+                priority: Priority::default(),
+            })
+            .await
+            .map_err(|_| ErrorKind::NodeUnavailable)?;
+        loop {
+            match responses.recv().await {
+                Some(
+                    HostCallbackResult::Result { result, .. }
+                    | HostCallbackResult::CachedResult { result, .. },
+                ) => {
+                    // subscriptions last as long as the client they were requested by
+                    if keep_client && result.is_ok() {
+                        client.keep();
+                    }
+                    return result;
+                }
+                Some(HostCallbackResult::StateConflict { error, .. }) => return Err(error),
+                Some(HostCallbackResult::SubscriptionChannel { key, callback, .. }) => {
+                    let subscription = NewSubscription { key, callback };
+                    let _ = self.outcomes.send(BatchOutcome::Subscription(subscription));
+                }
+                Some(HostCallbackResult::NewId { .. }) => {}
+                None => return Err(ErrorKind::NodeUnavailable.into()),
+            }
+        }
+    }
+}
+
+/// Operations of a batch, counted as in flight until dropped.
+struct InFlightOps {
+    counter: Arc<AtomicUsize>,
+    ops: usize,
+}
+
+impl InFlightOps {
+    fn new(counter: Arc<AtomicUsize>, ops: usize) -> Self {
+        counter.fetch_add(ops, Ordering::AcqRel);
+        Self { counter, ops }
+    }
+}
+
+impl Drop for InFlightOps {
+    fn drop(&mut self) {
+        self.counter.fetch_sub(self.ops, Ordering::AcqRel);
+    }
+}
+
+/// Client of the node running a request of a batch, disconnected once dropped so the node
+/// stops any operation left for it when the batch is cancelled or the request times out.
+struct BatchClient {
+    request_sender: Option<WebSocketRequest>,
+    client_id: ClientId,
+}
+
+impl BatchClient {
+    /// Keeps the client connected, for the subscription it holds.
+    fn keep(mut self) {
+        self.request_sender = None;
+    }
+}
+
+impl Drop for BatchClient {
+    fn drop(&mut self) {
+        let Some(request_sender) = self.request_sender.take() else {
+            return;
+        };
+        let client_id = self.client_id;
+        tokio::spawn(async move {
+            let _ = request_sender
+                .send(ClientConnection::Request {
+                    client_id,
+                    req: Box::new(ClientRequest::Disconnect { cause: None }),
+                    auth_token: None,
+                    attested_contract: None,
+                    expected_state: None,
+                    retry_policy: None,
+                    query: None,
+                    priority: Priority::default(),
+                })
+                .await;
+        });
+    }
+}
+
+/// Serializes an error as a response to the client.
+fn error_message(
+    error: ClientError,
+    protocols: (EncodingProtocol, ProtocolVersion),
+) -> anyhow::Result<Message> {
+    Ok(Message::Binary(encode_response(Err(error), protocols)?))
+}
+
+/// Serializes the result of a request, errors being framed with their details for clients
+/// speaking [`ProtocolVersion::V4`] or later.
+fn encode_response(
+    result: HostResult,
+    (encoding_protoc, protocol_version): (EncodingProtocol, ProtocolVersion),
+) -> anyhow::Result<Vec<u8>> {
+    match result {
+        Err(error) => {
+            let details = ErrorDetails::from(&error);
+            encode_error(error, details, (encoding_protoc, protocol_version))
+        }
+        result => encode_result(result, encoding_protoc),
+    }
+}
+
+/// Serializes an error, framed with `details` for clients speaking [`ProtocolVersion::V4`] or
+/// later.
+fn encode_error(
+    error: ClientError,
+    details: ErrorDetails,
+    (encoding_protoc, protocol_version): (EncodingProtocol, ProtocolVersion),
+) -> anyhow::Result<Vec<u8>> {
+    let encoded = encode_result(Err(error), encoding_protoc)?;
+    if protocol_version < ProtocolVersion::V4 {
+        return Ok(encoded);
+    }
+    Ok(error_details::frame(&encoded, &details)?)
+}
+
+fn encode_result(result: HostResult, encoding_protoc: EncodingProtocol) -> anyhow::Result<Vec<u8>> {
+    let serialized = match encoding_protoc {
+        EncodingProtocol::Flatbuffers => match result {
+            Ok(res) => res.into_fbs_bytes()?,
+            Err(err) => err.into_fbs_bytes()?,
+        },
+        EncodingProtocol::Native => bincode::serialize(&result)?,
+        EncodingProtocol::MessagePack => rmp_serde::to_vec_named(&result)?,
+    };
+    Ok(serialized)
+}
+
+/// Sends a message to the client, compressed if it negotiated compression and in chunks if it's
+/// over the chunk size.
+async fn send_binary(
+    tx: &mut SplitSink<WebSocket, Message>,
+    (chunker, deflater): (&mut Chunker, &mut Option<Deflater>),
+    msg: Vec<u8>,
+) -> anyhow::Result<()> {
+    let msg = match deflater {
+        Some(deflater) => deflater.deflate(msg)?,
+        None => msg,
+    };
+    for msg in chunker.split(msg)? {
+        tx.send(Message::Binary(msg)).await?;
+    }
+    Ok(())
+}
+
+/// Messages to send to a client, written to its websocket by a separate task so a client slow
+/// reading them doesn't hold up the handling of its requests and notifications.
+struct Outbound {
+    messages: mpsc::UnboundedSender<Message>,
+    notifications: Arc<std::sync::Mutex<NotificationQueue>>,
+    queued: Arc<Notify>,
+}
+
+impl Outbound {
+    /// Spawns the task writing to `sink` the messages sent through the returned handle.
+    fn spawn(
+        sink: SplitSink<WebSocket, Message>,
+        (chunker, deflater): (Chunker, Option<Deflater>),
+        encoding_protoc: EncodingProtocol,
+        queue: OutboundQueueConfig,
+    ) -> (Self, OutboundWriter) {
+        let (messages, messages_rx) = mpsc::unbounded_channel();
+        let notifications = Arc::new(std::sync::Mutex::new(NotificationQueue::new(queue)));
+        let queued = Arc::new(Notify::new());
+        let outbound = Self {
+            messages,
+            notifications: notifications.clone(),
+            queued: queued.clone(),
+        };
+        let writer = tokio::spawn(async move {
+            let written = write_messages(
+                (sink, chunker, deflater),
+                messages_rx,
+                (&notifications, &queued),
+                encoding_protoc,
+            )
+            .await;
+            if let Err(err) = written {
+                tracing::debug!(err = %err, "error sending message to client");
+            }
+        });
+        (outbound, OutboundWriter(writer))
+    }
+
+    fn send(&self, msg: Message) -> anyhow::Result<()> {
+        self.messages
+            .send(msg)
+            .map_err(|_| anyhow::anyhow!("connection to the client closed"))
+    }
+
+    /// Queues a notification, the client is to be disconnected if it's not keeping up with
+    /// them as configured.
+    fn notify(&self, key: ContractKey, notification: HostResult) -> Result<(), QueueFull> {
+        self.notifications.lock().unwrap().push(key, notification)?;
+        self.queued.notify_one();
+        Ok(())
+    }
+
+    /// Sends the status of the node to the client, and again every time it changes.
+    fn forward_status(&self) -> StatusUpdates {
+        let mut updates = status::subscribe();
+        let messages = self.messages.clone();
+        StatusUpdates(tokio::spawn(async move {
+            loop {
+                let msg = match status::encode(&updates.borrow_and_update()) {
+                    Ok(msg) => msg,
+                    Err(err) => {
+                        tracing::error!(err = %err, "failed encoding the network status");
+                        break;
+                    }
+                };
+                if messages.send(Message::Binary(msg)).is_err() || updates.changed().await.is_err()
+                {
+                    break;
+                }
+            }
+        }))
+    }
+
+    /// Pings the client and sends it the round-trip times measured by the node every `interval`.
+    fn forward_heartbeats(&self, interval: Duration, round_trip: ClientRoundTrip) -> Heartbeats {
+        let messages = self.messages.clone();
+        Heartbeats(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let heartbeat = Heartbeat {
+                    client: round_trip.get(),
+                    peers: NodeHandle::running()
+                        .map(|node| node.round_trip_times())
+                        .unwrap_or_default(),
+                };
+                let msg = match heartbeat.encode() {
+                    Ok(msg) => msg,
+                    Err(err) => {
+                        tracing::error!(err = %err, "failed encoding a heartbeat");
+                        break;
+                    }
+                };
+                if messages.send(Message::Binary(msg)).is_err()
+                    || messages.send(Message::Ping(round_trip.ping())).is_err()
+                {
+                    break;
+                }
+            }
+        }))
+    }
+}
+
+/// Task sending a client the changes of the status of the node, stopped when dropped.
+struct StatusUpdates(JoinHandle<()>);
+
+impl Drop for StatusUpdates {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Task pinging a client and sending it heartbeats, stopped when dropped.
+struct Heartbeats(JoinHandle<()>);
+
+impl Drop for Heartbeats {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Task writing the messages of a client, given some time to write those left when the
+/// connection closes before being aborted.
+struct OutboundWriter(JoinHandle<()>);
+
+impl Drop for OutboundWriter {
+    fn drop(&mut self) {
+        let writer = self.0.abort_handle();
+        tokio::spawn(async move {
+            tokio::time::sleep(WRITER_SHUTDOWN_TIMEOUT).await;
+            writer.abort();
+        });
+    }
+}
+
+/// Writes the messages and queued notifications of a client until its [`Outbound`] is dropped,
+/// messages first.
+async fn write_messages(
+    (mut sink, mut chunker, mut deflater): (
+        SplitSink<WebSocket, Message>,
+        Chunker,
+        Option<Deflater>,
+    ),
+    mut messages: mpsc::UnboundedReceiver<Message>,
+    (notifications, queued): (&std::sync::Mutex<NotificationQueue>, &Notify),
+    encoding_protoc: EncodingProtocol,
+) -> anyhow::Result<()> {
+    loop {
+        tokio::select! { biased;
+            msg = messages.recv() => match msg {
+                Some(Message::Binary(msg)) => {
+                    send_binary(&mut sink, (&mut chunker, &mut deflater), msg).await?
+                }
+                Some(msg) => sink.send(msg).await?,
+                None => return Ok(()),
+            },
+            _ = queued.notified() => loop {
+                let next = notifications.lock().unwrap().pop();
+                let Some(notification) = next else {
+                    break;
+                };
+                let msg = encode_result(notification, encoding_protoc)?;
+                send_binary(&mut sink, (&mut chunker, &mut deflater), msg).await?;
+            },
+        }
+    }
+}
+
+fn process_host_response(
     msg: Option<HostCallbackResult>,
     client_id: ClientId,
     encoding_protoc: EncodingProtocol,
-    tx: &mut SplitSink<WebSocket, Message>,
+    protocol_version: ProtocolVersion,
+    outbound: &Outbound,
+    in_flight_ops: &AtomicUsize,
 ) -> anyhow::Result<Option<NewSubscription>> {
     match msg {
         Some(HostCallbackResult::Result { id, result }) => {
             debug_assert_eq!(id, client_id);
+            let _ = in_flight_ops.fetch_update(Ordering::AcqRel, Ordering::Acquire, |ops| {
+                ops.checked_sub(1)
+            });
             let result = match result {
                 Ok(res) => {
                     let response_type = match res {
@@ -521,14 +2023,29 @@ async fn process_host_response(
                     Err(err)
                 }
             };
-            let serialized_res = match encoding_protoc {
-                EncodingProtocol::Flatbuffers => match result {
-                    Ok(res) => res.into_fbs_bytes()?,
-                    Err(err) => err.into_fbs_bytes()?,
-                },
-                EncodingProtocol::Native => bincode::serialize(&result)?,
-            };
-            tx.send(Message::Binary(serialized_res)).await?;
+            let serialized_res = encode_response(result, (encoding_protoc, protocol_version))?;
+            outbound.send(Message::Binary(serialized_res))?;
+            Ok(None)
+        }
+        Some(HostCallbackResult::CachedResult { id, result, age }) => {
+            debug_assert_eq!(id, client_id);
+            let _ = in_flight_ops.fetch_update(Ordering::AcqRel, Ordering::Acquire, |ops| {
+                ops.checked_sub(1)
+            });
+            tracing::debug!(cli_id = %id, ?age, "sending response served while revalidated");
+            let serialized_res = encode_response(result, (encoding_protoc, protocol_version))?;
+            outbound.send(Message::Binary(revalidate::frame(&serialized_res, age)?))?;
+            Ok(None)
+        }
+        Some(HostCallbackResult::StateConflict { id, error }) => {
+            debug_assert_eq!(id, client_id);
+            let _ = in_flight_ops.fetch_update(Ordering::AcqRel, Ordering::Acquire, |ops| {
+                ops.checked_sub(1)
+            });
+            tracing::debug!(response = %error, cli_id = %id, "sending state conflict");
+            let details = ErrorDetails::state_conflict(&error);
+            let serialized_res = encode_error(error, details, (encoding_protoc, protocol_version))?;
+            outbound.send(Message::Binary(serialized_res))?;
             Ok(None)
         }
         Some(HostCallbackResult::SubscriptionChannel { key, id, callback }) => {
@@ -539,12 +2056,22 @@ async fn process_host_response(
             tracing::debug!(%cli_id, "new client registered");
             Ok(None)
         }
+        None if protocol_version >= ProtocolVersion::V2 => {
+            outbound.send(Message::Close(Some(CloseFrame {
+                code: close_code::AWAY,
+                reason: "node shut down".into(),
+            })))?;
+            tracing::warn!("node shut down while handling responses for {client_id}");
+            Err(anyhow::anyhow!(
+                "node shut down while handling responses for {client_id}"
+            ))
+        }
         None => {
             let result_error = bincode::serialize(&Err::<HostResponse, ClientError>(
                 ErrorKind::NodeUnavailable.into(),
             ))?;
-            tx.send(Message::Binary(result_error)).await?;
-            tx.send(Message::Close(None)).await?;
+            outbound.send(Message::Binary(result_error))?;
+            outbound.send(Message::Close(None))?;
             tracing::warn!("node shut down while handling responses for {client_id}");
             Err(anyhow::anyhow!(
                 "node shut down while handling responses for {client_id}"
@@ -576,23 +2103,368 @@ impl ClientEventsProxy for WebSocketProxy {
         result: Result<HostResponse, ClientError>,
     ) -> BoxFuture<Result<(), ClientError>> {
         async move {
-            if let Some(ch) = self.response_channels.remove(&id) {
-                let should_rm = result
-                    .as_ref()
-                    .map_err(|err| matches!(err.kind(), ErrorKind::Disconnect))
-                    .err()
-                    .unwrap_or(false);
-                if ch.send(HostCallbackResult::Result { id, result }).is_ok() && !should_rm {
-                    // still alive connection, keep it
-                    self.response_channels.insert(id, ch);
-                } else {
-                    tracing::info!("dropped connection to client #{id}");
-                }
-            } else {
-                tracing::warn!("client: {id} not found");
-            }
+            let should_rm = result
+                .as_ref()
+                .map_err(|err| matches!(err.kind(), ErrorKind::Disconnect))
+                .err()
+                .unwrap_or(false);
+            self.respond(id, HostCallbackResult::Result { id, result }, should_rm);
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn send_cached(
+        &mut self,
+        id: ClientId,
+        result: Result<HostResponse, ClientError>,
+        age: Option<Duration>,
+    ) -> BoxFuture<Result<(), ClientError>> {
+        async move {
+            self.respond(
+                id,
+                HostCallbackResult::CachedResult { id, result, age },
+                false,
+            );
             Ok(())
         }
         .boxed()
     }
+
+    fn send_state_conflict(
+        &mut self,
+        id: ClientId,
+        error: ClientError,
+    ) -> BoxFuture<Result<(), ClientError>> {
+        async move {
+            self.respond(id, HostCallbackResult::StateConflict { id, error }, false);
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol_version_negotiation() {
+        assert_eq!(ProtocolVersion::negotiate(0), None);
+        assert_eq!(ProtocolVersion::negotiate(1), Some(ProtocolVersion::V1));
+        assert_eq!(ProtocolVersion::negotiate(2), Some(ProtocolVersion::V2));
+        assert_eq!(ProtocolVersion::negotiate(3), Some(ProtocolVersion::V3));
+        assert_eq!(ProtocolVersion::negotiate(4), Some(ProtocolVersion::V4));
+        // clients newer than the server speak the latest version it knows
+        assert_eq!(ProtocolVersion::negotiate(9), Some(ProtocolVersion::LATEST));
+    }
+
+    #[test]
+    fn subscription_mode_query() -> Result<(), Box<dyn std::error::Error>> {
+        let uri = "/v1/contract/command?encodingProtocol=native&subscriptionMode=full-state";
+        let Query(info) = Query::<ConnectionInfo>::try_from_uri(&uri.parse()?)?;
+        assert_eq!(info.subscription_mode, Some(SubscriptionMode::FullState));
+        let Query(info) = Query::<ConnectionInfo>::try_from_uri(&"/v1/contract/command".parse()?)?;
+        assert_eq!(info.subscription_mode, None);
+        assert!(Query::<ConnectionInfo>::try_from_uri(
+            &"/v1/contract/command?subscriptionMode=everything".parse()?
+        )
+        .is_err());
+        // summaries would be notified as states
+        assert!(Query::<ConnectionInfo>::try_from_uri(
+            &"/v1/contract/command?subscriptionMode=summary".parse()?
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn ack_level_query() -> Result<(), Box<dyn std::error::Error>> {
+        let ack_level = |query: &str| -> Result<Option<AckLevel>, Box<dyn std::error::Error>> {
+            let uri = format!("/v1/contract/command?{query}").parse()?;
+            let Query(info) = Query::<ConnectionInfo>::try_from_uri(&uri)?;
+            Ok(info.ack_level)
+        };
+        assert_eq!(ack_level("ackLevel=local")?, Some(AckLevel::Local));
+        assert_eq!(ack_level("ackLevel=target")?, Some(AckLevel::Target));
+        assert_eq!(ack_level("ackLevel=peers:3")?, Some(AckLevel::Peers(3)));
+        assert_eq!(ack_level("encodingProtocol=native")?, None);
+        assert!(ack_level("ackLevel=peers:0").is_err());
+        assert!(ack_level("ackLevel=everyone").is_err());
+        assert_eq!(AckLevel::Peers(3).to_string(), "peers:3");
+        assert_eq!(AckLevel::Peers(3).confirmations(), 3);
+        assert_eq!(AckLevel::Local.confirmations(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn get_mode_query() -> Result<(), Box<dyn std::error::Error>> {
+        let get_mode = |query: &str| -> Result<Option<GetMode>, Box<dyn std::error::Error>> {
+            let uri = format!("/v1/contract/command?{query}").parse()?;
+            let Query(info) = Query::<ConnectionInfo>::try_from_uri(&uri)?;
+            Ok(info.get_mode)
+        };
+        assert_eq!(get_mode("getMode=stored")?, Some(GetMode::Stored));
+        assert_eq!(get_mode("getMode=revalidate")?, Some(GetMode::Revalidate));
+        assert_eq!(get_mode("ackLevel=local")?, None);
+        assert!(get_mode("getMode=fresh").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn replicas_query() -> Result<(), Box<dyn std::error::Error>> {
+        let replicas = |query: &str| -> Result<Option<Replicas>, Box<dyn std::error::Error>> {
+            let uri = format!("/v1/contract/command?{query}").parse()?;
+            let Query(info) = Query::<ConnectionInfo>::try_from_uri(&uri)?;
+            Ok(info.replicas)
+        };
+        assert_eq!(replicas("replicas=3")?.map(Replicas::get), Some(3));
+        assert_eq!(replicas("ackLevel=local")?, None);
+        assert_eq!(Replicas::default().get(), 1);
+        assert!(replicas("replicas=0").is_err());
+        assert!(replicas(&format!("replicas={}", Replicas::MAX + 1)).is_err());
+        assert!(replicas("replicas=all").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn messagepack_encoding() -> Result<(), Box<dyn std::error::Error>> {
+        let key = ContractKey::from(ContractInstanceId::new([1; 32]));
+        let req = ClientRequest::ContractOp(ContractRequest::Subscribe { key, summary: None });
+        let encoded = rmp_serde::to_vec_named(&req)?;
+        let decoded = rmp_serde::from_slice::<ClientRequest>(&encoded)?;
+        assert!(matches!(
+            decoded,
+            ClientRequest::ContractOp(ContractRequest::Subscribe { key: decoded, .. }) if decoded == key
+        ));
+
+        let encoded = encode_result(Ok(HostResponse::Ok), EncodingProtocol::MessagePack)?;
+        let decoded = rmp_serde::from_slice::<HostResult>(&encoded)?;
+        assert!(matches!(decoded, Ok(HostResponse::Ok)));
+        Ok(())
+    }
+
+    #[test]
+    fn error_responses() -> Result<(), Box<dyn std::error::Error>> {
+        let error = || Err(ErrorKind::FailedOperation.into());
+        let legacy = encode_response(error(), (EncodingProtocol::Native, ProtocolVersion::V3))?;
+        assert!(bincode::deserialize::<HostResult>(&legacy)?.is_err());
+
+        let framed = encode_response(error(), (EncodingProtocol::Native, ProtocolVersion::V4))?;
+        assert_eq!(&framed[..4], b"FNER");
+        let size = u32::from_be_bytes(framed[4..8].try_into()?) as usize;
+        assert_eq!(&framed[8..8 + size], legacy);
+        let details = serde_json::from_slice::<ErrorDetails>(&framed[8 + size..])?;
+        assert_eq!(details.code, error_details::ErrorCode::OperationFailed);
+
+        // successful responses are left as they are
+        let ok = encode_response(
+            Ok(HostResponse::Ok),
+            (EncodingProtocol::Native, ProtocolVersion::V4),
+        )?;
+        assert!(bincode::deserialize::<HostResult>(&ok)?.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batch_request_checks() -> Result<(), Box<dyn std::error::Error>> {
+        let (request_sender, _requests) = mpsc::channel(1);
+        let (outcomes, mut outcomes_rx) = mpsc::unbounded_channel();
+        let batches = BatchRunner {
+            request_sender: WebSocketRequest(request_sender),
+            encoding_protoc: EncodingProtocol::Native,
+            protocol_version: ProtocolVersion::V3,
+            subscription_mode: SubscriptionMode::default(),
+            ack_level: AckLevel::default(),
+            replicas: Replicas::default(),
+            in_flight_ops: Arc::default(),
+            outcomes,
+            running: Arc::default(),
+        };
+        let limits = ClientLimits::new(Some(ClientLimitsConfig {
+            max_subscriptions_per_connection: 1,
+            ..Default::default()
+        }));
+        let key = ContractKey::from(ContractInstanceId::new([1; 32]));
+        let subscribe =
+            bincode::serialize(&ClientRequest::ContractOp(ContractRequest::Subscribe {
+                key,
+                summary: None,
+            }))?;
+        let batch = Batch {
+            id: 7,
+            options: RequestOptions::default(),
+            entries: vec![subscribe, b"garbage".to_vec()],
+        };
+        // the client is already at its subscription limit, so no request reaches the node
+        let response = start_batch(
+            &batch.encode()?,
+            &batches,
+            None,
+            None,
+            (&limits, 1, &AtomicUsize::new(0)),
+            None,
+        )?;
+        assert!(response.is_none());
+        let Some(BatchOutcome::Results(results)) = outcomes_rx.recv().await else {
+            panic!("expected the batch results");
+        };
+        let results = Batch::decode(&results)?;
+        assert_eq!(results.id, 7);
+        assert_eq!(results.entries.len(), 2);
+        for result in results.entries {
+            assert!(bincode::deserialize::<HostResult>(result)?.is_err());
+        }
+
+        let Some(Message::Binary(invalid)) = start_batch(
+            b"FNBT\x00",
+            &batches,
+            None,
+            None,
+            (&limits, 0, &AtomicUsize::new(0)),
+            None,
+        )?
+        else {
+            panic!("expected an error response");
+        };
+        assert!(bincode::deserialize::<HostResult>(&invalid)?.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cancel_running_batch() -> Result<(), Box<dyn std::error::Error>> {
+        // the node never answers, so the batch runs until cancelled
+        let (request_sender, _requests) = mpsc::channel(1);
+        let (outcomes, mut outcomes_rx) = mpsc::unbounded_channel();
+        let batches = BatchRunner {
+            request_sender: WebSocketRequest(request_sender),
+            encoding_protoc: EncodingProtocol::Native,
+            protocol_version: ProtocolVersion::V3,
+            subscription_mode: SubscriptionMode::default(),
+            ack_level: AckLevel::default(),
+            replicas: Replicas::default(),
+            in_flight_ops: Arc::default(),
+            outcomes,
+            running: Arc::default(),
+        };
+        let get = bincode::serialize(&ClientRequest::ContractOp(ContractRequest::Get {
+            key: ContractKey::from(ContractInstanceId::new([1; 32])),
+            return_contract_code: false,
+            subscribe: false,
+        }))?;
+        let batch = Batch {
+            id: 3,
+            options: RequestOptions::default(),
+            entries: vec![get.clone(), get],
+        };
+        let limits = ClientLimits::default();
+        let usage = (&limits, 0, &AtomicUsize::new(0));
+        assert!(start_batch(&batch.encode()?, &batches, None, None, usage, None)?.is_none());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(batches.in_flight_ops.load(Ordering::Acquire), 2);
+        let duplicate = start_batch(&batch.encode()?, &batches, None, None, usage, None)?;
+        assert!(duplicate.is_some());
+
+        let cancel = [&b"FNCX"[..], &3u64.to_be_bytes()].concat();
+        let Some(Message::Binary(results)) = cancel_batch(&cancel, &batches)? else {
+            panic!("expected the cancelled batch results");
+        };
+        let results = Batch::decode(&results)?;
+        assert_eq!(results.id, 3);
+        assert_eq!(results.entries.len(), 2);
+        for result in results.entries {
+            assert!(bincode::deserialize::<HostResult>(result)?.is_err());
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(batches.in_flight_ops.load(Ordering::Acquire), 0);
+        assert!(outcomes_rx.try_recv().is_err());
+        // already cancelled
+        assert!(cancel_batch(&cancel, &batches)?.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn request_timeout_and_retries() -> Result<(), Box<dyn std::error::Error>> {
+        // the node never answers, so every attempt times out
+        let (request_sender, mut requests) = mpsc::channel(10);
+        let (outcomes, _outcomes_rx) = mpsc::unbounded_channel();
+        let batches = BatchRunner {
+            request_sender: WebSocketRequest(request_sender),
+            encoding_protoc: EncodingProtocol::Native,
+            protocol_version: ProtocolVersion::V3,
+            subscription_mode: SubscriptionMode::default(),
+            ack_level: AckLevel::default(),
+            replicas: Replicas::default(),
+            in_flight_ops: Arc::default(),
+            outcomes,
+            running: Arc::default(),
+        };
+        let get = ClientRequest::ContractOp(ContractRequest::Get {
+            key: ContractKey::from(ContractInstanceId::new([1; 32])),
+            return_contract_code: false,
+            subscribe: false,
+        });
+        let options = RequestOptions {
+            timeout: Some(Duration::from_millis(10)),
+            retries: 2,
+        };
+        let result = batches.execute_with(get, &None, options).await;
+        assert!(
+            matches!(result, Err(err) if matches!(err.kind(), ErrorKind::OperationError { .. }))
+        );
+        let mut attempts = 0;
+        while let Ok(ClientConnection::NewConnection { .. }) = requests.try_recv() {
+            attempts += 1;
+        }
+        assert_eq!(attempts, 3);
+
+        // other requests are not retried
+        let disconnect = ClientRequest::Disconnect { cause: None };
+        assert!(batches
+            .execute_with(disconnect, &None, options)
+            .await
+            .is_err());
+        assert!(matches!(
+            requests.try_recv(),
+            Ok(ClientConnection::NewConnection { .. })
+        ));
+        assert!(requests.try_recv().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn client_limits() {
+        let limits = ClientLimits::new(Some(ClientLimitsConfig {
+            max_connections_per_ip: 2,
+            max_subscriptions_per_connection: 1,
+            max_in_flight_ops_per_connection: 1,
+        }));
+        let client = Some(IpAddr::from([203, 0, 113, 7]));
+        let first = limits.connect(client);
+        let second = limits.connect(client);
+        assert!(first.is_some() && second.is_some());
+        assert!(limits.connect(client).is_none());
+        assert!(limits
+            .connect(Some(IpAddr::from([203, 0, 113, 8])))
+            .is_some());
+        drop(first);
+        assert!(limits.connect(client).is_some());
+        drop(second);
+        assert!(limits.connections.is_empty());
+
+        let key = ContractKey::from(ContractInstanceId::new([1; 32]));
+        let subscribe =
+            ClientRequest::ContractOp(ContractRequest::Subscribe { key, summary: None });
+        assert!(limits.check_request(&subscribe, 0, 0).is_ok());
+        assert!(limits.check_request(&subscribe, 1, 0).is_err());
+        assert!(limits.check_request(&subscribe, 0, 1).is_err());
+        let authenticate = ClientRequest::Authenticate {
+            token: "token".into(),
+        };
+        assert!(limits.check_request(&authenticate, 1, 1).is_ok());
+
+        let unlimited = ClientLimits::default();
+        assert!(unlimited.connect(client).is_some());
+        assert!(unlimited.check_request(&subscribe, 1000, 1000).is_ok());
+    }
 }
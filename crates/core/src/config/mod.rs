@@ -1,9 +1,9 @@
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     fs::{self, File},
     future::Future,
     io::{Read, Write},
-    net::{IpAddr, Ipv4Addr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     path::{Path, PathBuf},
     sync::{atomic::AtomicBool, Arc},
     time::Duration,
@@ -12,13 +12,33 @@ use std::{
 use anyhow::Context;
 use directories::ProjectDirs;
 use either::Either;
+use freenet_stdlib::prelude::{ContractInstanceId, ContractKey};
 use itertools::Itertools;
 use once_cell::sync::Lazy;
 use pkcs8::DecodePublicKey;
 use serde::{Deserialize, Serialize};
 use tokio::runtime::Runtime;
 
-use crate::{dev_tool::PeerId, local_node::OperationMode, transport::TransportKeypair};
+use crate::{
+    client_events::AuthToken,
+    dev_tool::PeerId,
+    local_node::OperationMode,
+    node::{
+        neighborhood::BootstrapLimits,
+        peer_policy::{PeerLists, PeerPolicy},
+        InboundLimits, OpLimits,
+    },
+    ring::CacheLimits,
+    server::{
+        app_packaging::{
+            WebAppLimits, DEFAULT_MAX_ASSEMBLED_SIZE, DEFAULT_MAX_METADATA_SIZE,
+            DEFAULT_MAX_WEB_SIZE,
+        },
+        path_handlers::DEFAULT_COMPRESSION_MIN_SIZE,
+        ApiScope, ApiTokens, TokenGrant,
+    },
+    transport::{BandwidthLimits, RekeyLimits, RelayLimits, ReplayLimits, TransportKeypair},
+};
 
 mod secret;
 pub use secret::*;
@@ -52,6 +72,9 @@ const APPLICATION: &str = "Freenet";
 
 const FREENET_GATEWAYS_INDEX: &str = "https://freenet.org/keys/gateways.toml";
 
+/// File in the data directory the subscriptions of websocket sessions are saved to.
+const SESSIONS_FILE: &str = "sessions.bin";
+
 #[derive(clap::Parser, Debug)]
 pub struct ConfigArgs {
     /// Node operation mode. Default is network mode.
@@ -87,7 +110,7 @@ impl Default for ConfigArgs {
         Self {
             mode: Some(OperationMode::Network),
             network_api: NetworkArgs {
-                address: Some(default_listening_address()),
+                address: Some(default_network_address()),
                 network_port: Some(default_network_api_port()),
                 public_address: None,
                 public_port: None,
@@ -97,11 +120,45 @@ impl Default for ConfigArgs {
                 gateways: None,
                 location: None,
                 bandwidth_limit: None,
+                download_limit: None,
+                peer_upload_limit: None,
+                peer_download_limit: None,
+                min_connections: None,
+                max_connections: None,
+                rekey_interval: None,
+                rekey_bytes: None,
+                replay_window: None,
+                relay: false,
+                relay_rate_limit: None,
+                location_strategy: None,
+                peer_message_rate_limit: None,
+                peer_op_rate_limit: None,
+                max_concurrent_gets: None,
+                max_concurrent_puts: None,
+                max_concurrent_subscribes: None,
+                max_queued_ops: None,
+                opportunistic_caching: false,
+                cache_budget: None,
+                storage_less: false,
+                bootstrap_budget: None,
                 blocked_addresses: None,
+                quic_port: None,
+                port_mapping: false,
+                dns_seed: None,
+                seed_list: None,
+                seed_list_keys: None,
+                mdns: false,
+                allowed_peers: None,
+                denied_peers: None,
             },
             ws_api: WebsocketApiArgs {
                 address: Some(default_listening_address()),
                 ws_api_port: Some(default_http_gateway_port()),
+                webapp_max_metadata_size: None,
+                webapp_max_web_size: None,
+                webapp_max_assembled_size: None,
+                ready_min_peers: None,
+                compression_min_size: None,
             },
             secrets: Default::default(),
             log_level: Some(tracing::log::LevelFilter::Info),
@@ -225,12 +282,152 @@ impl ConfigArgs {
 
         let should_persist = cfg.is_none();
 
+        // settings which can only be provided through the configuration file
+        let mut webapp_publisher_keys = HashMap::new();
+        let mut webapp_cache_dir = None;
+        let mut stream_chunk_size = default_stream_chunk_size();
+        let mut compression_contexts = default_compression_contexts();
+        let mut tls = None;
+        let mut cors = None;
+        let mut subdomains = None;
+        let mut trusted_proxies = Vec::new();
+        let mut rate_limit = None;
+        let mut client_limits = None;
+        let mut access_log = None;
+        let mut sessions = None;
+        let mut sessions_file = None;
+        let mut retries = RetryPolicies::default();
+        let mut get_fanout = default_get_fanout();
+        let mut outbound_queue = OutboundQueueConfig::default();
+        let mut listeners = Vec::new();
+        let mut api_tokens = HashMap::new();
+
         // merge the configuration from the file with the command line arguments
         if let Some(cfg) = cfg {
             self.secrets.merge(cfg.secrets);
             self.mode.get_or_insert(cfg.mode);
             self.ws_api.address.get_or_insert(cfg.ws_api.address);
             self.ws_api.ws_api_port.get_or_insert(cfg.ws_api.port);
+            self.ws_api
+                .webapp_max_metadata_size
+                .get_or_insert(cfg.ws_api.webapp_max_metadata_size);
+            self.ws_api
+                .webapp_max_web_size
+                .get_or_insert(cfg.ws_api.webapp_max_web_size);
+            self.ws_api
+                .webapp_max_assembled_size
+                .get_or_insert(cfg.ws_api.webapp_max_assembled_size);
+            self.ws_api
+                .ready_min_peers
+                .get_or_insert(cfg.ws_api.ready_min_peers);
+            self.ws_api
+                .compression_min_size
+                .get_or_insert(cfg.ws_api.compression_min_size);
+            webapp_publisher_keys = cfg.ws_api.webapp_publisher_keys;
+            webapp_cache_dir = cfg.ws_api.webapp_cache_dir;
+            stream_chunk_size = cfg.ws_api.stream_chunk_size;
+            compression_contexts = cfg.ws_api.compression_contexts;
+            tls = cfg.ws_api.tls;
+            cors = cfg.ws_api.cors;
+            subdomains = cfg.ws_api.subdomains;
+            trusted_proxies = cfg.ws_api.trusted_proxies;
+            rate_limit = cfg.ws_api.rate_limit;
+            client_limits = cfg.ws_api.client_limits;
+            access_log = cfg.ws_api.access_log.clone();
+            sessions = cfg.ws_api.sessions;
+            sessions_file = cfg.ws_api.sessions_file;
+            retries = cfg.network_api.retries;
+            get_fanout = cfg.network_api.get_fanout;
+            outbound_queue = cfg.ws_api.outbound_queue;
+            listeners = cfg.ws_api.listeners.clone();
+            api_tokens = cfg.ws_api.api_tokens;
+            self.network_api.port_mapping |= cfg.network_api.port_mapping;
+            self.network_api.dns_seed = self.network_api.dns_seed.or(cfg.network_api.dns_seed);
+            self.network_api.seed_list = self.network_api.seed_list.or(cfg.network_api.seed_list);
+            self.network_api
+                .seed_list_keys
+                .get_or_insert(cfg.network_api.seed_list_keys);
+            self.network_api.mdns |= cfg.network_api.mdns;
+            self.network_api.download_limit = self
+                .network_api
+                .download_limit
+                .or(cfg.network_api.download_limit);
+            self.network_api.peer_upload_limit = self
+                .network_api
+                .peer_upload_limit
+                .or(cfg.network_api.peer_upload_limit);
+            self.network_api.peer_download_limit = self
+                .network_api
+                .peer_download_limit
+                .or(cfg.network_api.peer_download_limit);
+            self.network_api.min_connections = self
+                .network_api
+                .min_connections
+                .or(cfg.network_api.min_connections);
+            self.network_api.max_connections = self
+                .network_api
+                .max_connections
+                .or(cfg.network_api.max_connections);
+            self.network_api.rekey_interval = self
+                .network_api
+                .rekey_interval
+                .or(cfg.network_api.rekey_interval);
+            self.network_api.rekey_bytes =
+                self.network_api.rekey_bytes.or(cfg.network_api.rekey_bytes);
+            self.network_api.replay_window = self
+                .network_api
+                .replay_window
+                .or(cfg.network_api.replay_window);
+            self.network_api.relay |= cfg.network_api.relay;
+            self.network_api.relay_rate_limit = self
+                .network_api
+                .relay_rate_limit
+                .or(cfg.network_api.relay_rate_limit);
+            self.network_api.location_strategy = self
+                .network_api
+                .location_strategy
+                .or(cfg.network_api.location_strategy);
+            self.network_api.peer_message_rate_limit = self
+                .network_api
+                .peer_message_rate_limit
+                .or(cfg.network_api.peer_message_rate_limit);
+            self.network_api.peer_op_rate_limit = self
+                .network_api
+                .peer_op_rate_limit
+                .or(cfg.network_api.peer_op_rate_limit);
+            self.network_api.max_concurrent_gets = self
+                .network_api
+                .max_concurrent_gets
+                .or(cfg.network_api.max_concurrent_gets);
+            self.network_api.max_concurrent_puts = self
+                .network_api
+                .max_concurrent_puts
+                .or(cfg.network_api.max_concurrent_puts);
+            self.network_api.max_concurrent_subscribes = self
+                .network_api
+                .max_concurrent_subscribes
+                .or(cfg.network_api.max_concurrent_subscribes);
+            self.network_api.max_queued_ops = self
+                .network_api
+                .max_queued_ops
+                .or(cfg.network_api.max_queued_ops);
+            self.network_api.opportunistic_caching |= cfg.network_api.opportunistic_caching;
+            self.network_api.storage_less |= cfg.network_api.storage_less;
+            self.network_api.cache_budget = self
+                .network_api
+                .cache_budget
+                .or(cfg.network_api.cache_budget);
+            self.network_api.bootstrap_budget = self
+                .network_api
+                .bootstrap_budget
+                .or(cfg.network_api.bootstrap_budget);
+            self.network_api.quic_port = self.network_api.quic_port.or(cfg.network_api.quic_port);
+            self.network_api
+                .allowed_peers
+                .get_or_insert(cfg.network_api.allowed_peers);
+            self.network_api
+                .denied_peers
+                .get_or_insert(cfg.network_api.denied_peers);
             self.log_level.get_or_insert(cfg.log_level);
             self.config_paths.merge(cfg.config_paths.as_ref().clone());
         }
@@ -289,6 +486,9 @@ impl ConfigArgs {
                 if peer_id.is_none()
                     && mode == OperationMode::Network
                     && remotely_loaded_gateways.gateways.is_empty()
+                    && self.network_api.dns_seed.is_none()
+                    && self.network_api.seed_list.is_none()
+                    && !self.network_api.mdns
                 {
                     tracing::error!(file = ?gateways_file, "Failed to read gateways file: {err}");
 
@@ -311,7 +511,7 @@ impl ConfigArgs {
             network_api: NetworkApiConfig {
                 address: self.network_api.address.unwrap_or_else(|| match mode {
                     OperationMode::Local => default_local_address(),
-                    OperationMode::Network => default_listening_address(),
+                    OperationMode::Network => default_network_address(),
                 }),
                 port: self
                     .network_api
@@ -321,10 +521,41 @@ impl ConfigArgs {
                 public_port: self.network_api.public_port,
                 ignore_protocol_version: self.network_api.ignore_protocol_checking,
                 bandwidth_limit: self.network_api.bandwidth_limit,
+                download_limit: self.network_api.download_limit,
+                peer_upload_limit: self.network_api.peer_upload_limit,
+                peer_download_limit: self.network_api.peer_download_limit,
+                min_connections: self.network_api.min_connections,
+                max_connections: self.network_api.max_connections,
+                rekey_interval: self.network_api.rekey_interval,
+                rekey_bytes: self.network_api.rekey_bytes,
+                replay_window: self.network_api.replay_window,
+                relay: self.network_api.relay,
+                relay_rate_limit: self.network_api.relay_rate_limit,
+                location_strategy: self.network_api.location_strategy,
+                peer_message_rate_limit: self.network_api.peer_message_rate_limit,
+                peer_op_rate_limit: self.network_api.peer_op_rate_limit,
+                max_concurrent_gets: self.network_api.max_concurrent_gets,
+                max_concurrent_puts: self.network_api.max_concurrent_puts,
+                max_concurrent_subscribes: self.network_api.max_concurrent_subscribes,
+                max_queued_ops: self.network_api.max_queued_ops,
+                opportunistic_caching: self.network_api.opportunistic_caching,
+                cache_budget: self.network_api.cache_budget,
+                storage_less: self.network_api.storage_less,
+                bootstrap_budget: self.network_api.bootstrap_budget,
                 blocked_addresses: self
                     .network_api
                     .blocked_addresses
                     .map(|addrs| addrs.into_iter().collect()),
+                quic_port: self.network_api.quic_port,
+                port_mapping: self.network_api.port_mapping,
+                dns_seed: self.network_api.dns_seed,
+                seed_list: self.network_api.seed_list,
+                seed_list_keys: self.network_api.seed_list_keys.unwrap_or_default(),
+                mdns: self.network_api.mdns,
+                retries,
+                get_fanout,
+                allowed_peers: self.network_api.allowed_peers.unwrap_or_default(),
+                denied_peers: self.network_api.denied_peers.unwrap_or_default(),
             },
             ws_api: WebsocketApiConfig {
                 // the websocket API is always local
@@ -333,6 +564,44 @@ impl ConfigArgs {
                     .ws_api
                     .ws_api_port
                     .unwrap_or(default_http_gateway_port()),
+                webapp_publisher_keys,
+                webapp_max_metadata_size: self
+                    .ws_api
+                    .webapp_max_metadata_size
+                    .unwrap_or(DEFAULT_MAX_METADATA_SIZE),
+                webapp_max_web_size: self
+                    .ws_api
+                    .webapp_max_web_size
+                    .unwrap_or(DEFAULT_MAX_WEB_SIZE),
+                webapp_max_assembled_size: self
+                    .ws_api
+                    .webapp_max_assembled_size
+                    .unwrap_or(DEFAULT_MAX_ASSEMBLED_SIZE),
+                ready_min_peers: self
+                    .ws_api
+                    .ready_min_peers
+                    .unwrap_or(default_ready_min_peers()),
+                compression_min_size: self
+                    .ws_api
+                    .compression_min_size
+                    .unwrap_or(default_compression_min_size()),
+                webapp_cache_dir,
+                stream_chunk_size,
+                compression_contexts,
+                tls,
+                cors,
+                subdomains,
+                trusted_proxies,
+                rate_limit,
+                client_limits,
+                access_log,
+                sessions,
+                sessions_file: Some(
+                    sessions_file.unwrap_or_else(|| config_paths.db_dir(mode).join(SESSIONS_FILE)),
+                ),
+                outbound_queue,
+                listeners,
+                api_tokens,
             },
             secrets,
             log_level: self.log_level.unwrap_or(tracing::log::LevelFilter::Info),
@@ -342,6 +611,67 @@ impl ConfigArgs {
             location: self.network_api.location,
         };
 
+        this.ws_api.publisher_keys()?;
+        if let Some(tls) = &this.ws_api.tls {
+            tls.validate()?;
+        }
+        if let Some(cors) = &this.ws_api.cors {
+            cors.validate()?;
+        }
+        if let Some(subdomains) = &this.ws_api.subdomains {
+            subdomains.validate()?;
+        }
+        this.ws_api.trusted_proxy_networks()?;
+        PeerPolicy::new(this.network_api.peer_lists())?;
+        if let (Some(min), Some(max)) = (
+            this.network_api.min_connections,
+            this.network_api.max_connections,
+        ) {
+            if min > max {
+                anyhow::bail!("min connections ({min}) can not exceed max connections ({max})");
+            }
+        }
+        if this.network_api.rekey_interval == Some(0) || this.network_api.rekey_bytes == Some(0) {
+            anyhow::bail!("session keys can not be rotated every 0 seconds or bytes");
+        }
+        if this.network_api.replay_window == Some(0) {
+            anyhow::bail!("the replay window must span at least one packet");
+        }
+        if this.network_api.location_strategy == Some(LocationStrategy::Pinned)
+            && this.location.is_none()
+        {
+            anyhow::bail!("a pinned location strategy requires the location of the node to be set");
+        }
+        if this.network_api.relay && this.network_api.relay_rate_limit == Some(0) {
+            anyhow::bail!("relaying is enabled with a rate limit of 0 bytes per second");
+        }
+        if this.network_api.peer_message_rate_limit == Some(0)
+            || this.network_api.peer_op_rate_limit == Some(0)
+        {
+            anyhow::bail!("peers can not be limited to 0 messages or operations per second");
+        }
+        if [
+            this.network_api.max_concurrent_gets,
+            this.network_api.max_concurrent_puts,
+            this.network_api.max_concurrent_subscribes,
+        ]
+        .contains(&Some(0))
+        {
+            anyhow::bail!("operations can not be limited to 0 processed concurrently");
+        }
+        if this.network_api.seed_list.is_some() {
+            if this.network_api.seed_list_keys.is_empty() {
+                anyhow::bail!("a seed list requires the keys trusted to sign it");
+            }
+            this.network_api.seed_list_keys()?;
+        }
+        if this.network_api.opportunistic_caching && this.network_api.cache_budget == Some(0) {
+            anyhow::bail!("opportunistic caching is enabled with a cache budget of 0 bytes");
+        }
+        for listener in &this.ws_api.listeners {
+            listener.listen_address()?;
+        }
+
         fs::create_dir_all(this.config_dir())?;
         gateways.save_to_file(&gateways_file)?;
 
@@ -437,7 +767,8 @@ impl Config {
 
 #[derive(clap::Parser, Debug, Default, Clone, Serialize, Deserialize)]
 pub struct NetworkArgs {
-    /// Address to bind to for the network event listener, default is 0.0.0.0
+    /// Address to bind to for the network event listener, default is `::`, accepting IPv4 traffic
+    /// too or falling back to 0.0.0.0 on hosts without IPv6
     #[arg(
         name = "network_address",
         long = "network-address",
@@ -492,9 +823,186 @@ pub struct NetworkArgs {
     #[arg(long)]
     pub bandwidth_limit: Option<usize>,
 
+    /// Limit of the downstream traffic of the node, in bytes per second.
+    #[arg(long, env = "DOWNLOAD_LIMIT")]
+    #[serde(rename = "download-limit", skip_serializing_if = "Option::is_none")]
+    pub download_limit: Option<usize>,
+
+    /// Limit of the upstream traffic to each peer, in bytes per second.
+    #[arg(long, env = "PEER_UPLOAD_LIMIT")]
+    #[serde(rename = "peer-upload-limit", skip_serializing_if = "Option::is_none")]
+    pub peer_upload_limit: Option<usize>,
+
+    /// Limit of the downstream traffic from each peer, in bytes per second.
+    #[arg(long, env = "PEER_DOWNLOAD_LIMIT")]
+    #[serde(
+        rename = "peer-download-limit",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub peer_download_limit: Option<usize>,
+
+    /// Number of connections the node keeps acquiring peers until it reaches.
+    #[arg(long, env = "MIN_CONNECTIONS")]
+    #[serde(rename = "min-connections", skip_serializing_if = "Option::is_none")]
+    pub min_connections: Option<usize>,
+
+    /// Number of connections above which the node stops accepting peers and evicts the
+    /// connections least worth keeping.
+    #[arg(long, env = "MAX_CONNECTIONS")]
+    #[serde(rename = "max-connections", skip_serializing_if = "Option::is_none")]
+    pub max_connections: Option<usize>,
+
+    /// Seconds after which the keys of connections to peers are rotated, an hour by default.
+    #[arg(long, env = "REKEY_INTERVAL")]
+    #[serde(rename = "rekey-interval", skip_serializing_if = "Option::is_none")]
+    pub rekey_interval: Option<u64>,
+
+    /// Bytes received from a peer after which the key of the connection is rotated, 1 GiB by
+    /// default.
+    #[arg(long, env = "REKEY_BYTES")]
+    #[serde(rename = "rekey-bytes", skip_serializing_if = "Option::is_none")]
+    pub rekey_bytes: Option<u64>,
+
+    /// Packets of a peer accepted out of order behind its latest one, older ones being rejected
+    /// as replayed, 8192 by default.
+    #[arg(long, env = "REPLAY_WINDOW")]
+    #[serde(rename = "replay-window", skip_serializing_if = "Option::is_none")]
+    pub replay_window: Option<u32>,
+
+    /// Relays the packets of connected peers which can't reach each other directly because of
+    /// their NATs.
+    #[arg(long, env = "RELAY")]
+    #[serde(default)]
+    pub relay: bool,
+
+    /// Limit of the traffic relayed for other peers, in bytes per second, 1 MiB by default.
+    #[arg(long, env = "RELAY_RATE_LIMIT")]
+    #[serde(rename = "relay-rate-limit", skip_serializing_if = "Option::is_none")]
+    pub relay_rate_limit: Option<usize>,
+
+    /// How the node chooses its location in the ring, derived from its public address by
+    /// default.
+    #[arg(long, value_enum, env = "LOCATION_STRATEGY")]
+    #[serde(rename = "location-strategy", skip_serializing_if = "Option::is_none")]
+    pub location_strategy: Option<LocationStrategy>,
+
+    /// Messages accepted from each peer per second, 200 by default.
+    #[arg(long, env = "PEER_MESSAGE_RATE_LIMIT")]
+    #[serde(
+        rename = "peer-message-rate-limit",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub peer_message_rate_limit: Option<u32>,
+
+    /// Operations each peer can start on this node per second, 20 by default.
+    #[arg(long, env = "PEER_OP_RATE_LIMIT")]
+    #[serde(rename = "peer-op-rate-limit", skip_serializing_if = "Option::is_none")]
+    pub peer_op_rate_limit: Option<u32>,
+
+    /// GET operations processed concurrently, 64 by default.
+    #[arg(long, env = "MAX_CONCURRENT_GETS")]
+    #[serde(
+        rename = "max-concurrent-gets",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_concurrent_gets: Option<usize>,
+
+    /// PUT operations processed concurrently, 32 by default.
+    #[arg(long, env = "MAX_CONCURRENT_PUTS")]
+    #[serde(
+        rename = "max-concurrent-puts",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_concurrent_puts: Option<usize>,
+
+    /// SUBSCRIBE operations processed concurrently, 32 by default.
+    #[arg(long, env = "MAX_CONCURRENT_SUBSCRIBES")]
+    #[serde(
+        rename = "max-concurrent-subscribes",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_concurrent_subscribes: Option<usize>,
+
+    /// Messages of each type of operation waiting to be processed past which new operations
+    /// requested by other peers are dropped, 256 by default.
+    #[arg(long, env = "MAX_QUEUED_OPS")]
+    #[serde(rename = "max-queued-ops", skip_serializing_if = "Option::is_none")]
+    pub max_queued_ops: Option<usize>,
+
+    /// Caches the states of the contracts routed through the node, serving them to later
+    /// requests.
+    #[arg(long, env = "OPPORTUNISTIC_CACHING")]
+    #[serde(default, rename = "opportunistic-caching")]
+    pub opportunistic_caching: bool,
+
+    /// Size of the states of the contracts cached opportunistically, in bytes, 64 MiB by
+    /// default. Also bounds the contracts kept in memory by storage-less nodes.
+    #[arg(long, env = "CACHE_BUDGET")]
+    #[serde(rename = "cache-budget", skip_serializing_if = "Option::is_none")]
+    pub cache_budget: Option<u64>,
+
+    /// Routes requests and serves clients without ever writing contracts, delegates or states to
+    /// disk, keeping those of the operations of clients in memory, up to the cache budget, and
+    /// not seeding contracts for the network.
+    #[arg(long, env = "STORAGE_LESS")]
+    #[serde(default, rename = "storage-less")]
+    pub storage_less: bool,
+
+    /// Size of the states of the contracts near the location of the node fetched from its first
+    /// neighbors when joining the network, in bytes, 16 MiB by default, 0 disabling it.
+    #[arg(long, env = "BOOTSTRAP_BUDGET")]
+    #[serde(rename = "bootstrap-budget", skip_serializing_if = "Option::is_none")]
+    pub bootstrap_budget: Option<u64>,
+
     /// List of IP:port addresses to refuse connections to/from.
     #[arg(long, num_args = 0..)]
     pub blocked_addresses: Option<Vec<SocketAddr>>,
+
+    /// Port to listen on for QUIC links with peers supporting them, QUIC is not used if unset.
+    #[arg(long, env = "QUIC_PORT")]
+    #[serde(rename = "quic-port", skip_serializing_if = "Option::is_none")]
+    pub quic_port: Option<u16>,
+
+    /// Asks the router of the local network to forward the network port with NAT-PMP or UPnP,
+    /// so the node is directly reachable by other peers.
+    #[arg(long, env = "PORT_MAPPING")]
+    #[serde(default, rename = "port-mapping")]
+    pub port_mapping: bool,
+
+    /// Domain name whose DNS records list the gateways of the network, resolved periodically to
+    /// find them in addition to the configured ones.
+    #[arg(long, env = "DNS_SEED")]
+    #[serde(rename = "dns-seed", skip_serializing_if = "Option::is_none")]
+    pub dns_seed: Option<String>,
+
+    /// URL of a signed list of the gateways of the network, fetched periodically to find them in
+    /// addition to the configured ones. Its signature is fetched from the same URL with a `.sig`
+    /// suffix.
+    #[arg(long, env = "SEED_LIST")]
+    #[serde(rename = "seed-list", skip_serializing_if = "Option::is_none")]
+    pub seed_list: Option<String>,
+
+    /// Base58 encoded ed25519 keys trusted to sign the seed list.
+    #[arg(long, num_args = 0..)]
+    #[serde(rename = "seed-list-keys", skip_serializing_if = "Option::is_none")]
+    pub seed_list_keys: Option<Vec<String>>,
+
+    /// Announces the node to the local network with mDNS and connects with the peers found in
+    /// it, so nodes of a LAN find each other without gateways.
+    #[arg(long, env = "MDNS")]
+    #[serde(default)]
+    pub mdns: bool,
+
+    /// Peers to only connect with, by key fingerprint, IP address or network (CIDR). Every peer
+    /// is allowed if empty.
+    #[arg(long, num_args = 0.., value_delimiter = ',', env = "ALLOWED_PEERS")]
+    #[serde(rename = "allowed-peers", skip_serializing_if = "Option::is_none")]
+    pub allowed_peers: Option<Vec<String>>,
+
+    /// Peers to refuse connections with, by key fingerprint, IP address or network (CIDR).
+    #[arg(long, num_args = 0.., value_delimiter = ',', env = "DENIED_PEERS")]
+    #[serde(rename = "denied-peers", skip_serializing_if = "Option::is_none")]
+    pub denied_peers: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -530,7 +1038,7 @@ impl NetworkArgs {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkApiConfig {
     /// Address to listen to locally
-    #[serde(default = "default_listening_address", rename = "network-address")]
+    #[serde(default = "default_network_address", rename = "network-address")]
     pub address: IpAddr,
 
     /// Port to expose api on
@@ -555,9 +1063,390 @@ pub struct NetworkApiConfig {
     /// Hard limit the bandwidth usage for upstream traffic.
     pub bandwidth_limit: Option<usize>,
 
+    /// Limit of the downstream traffic of the node, in bytes per second.
+    #[serde(rename = "download-limit", skip_serializing_if = "Option::is_none")]
+    pub download_limit: Option<usize>,
+
+    /// Limit of the upstream traffic to each peer, in bytes per second.
+    #[serde(rename = "peer-upload-limit", skip_serializing_if = "Option::is_none")]
+    pub peer_upload_limit: Option<usize>,
+
+    /// Limit of the downstream traffic from each peer, in bytes per second.
+    #[serde(
+        rename = "peer-download-limit",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub peer_download_limit: Option<usize>,
+
+    /// Number of connections the node keeps acquiring peers until it reaches.
+    #[serde(rename = "min-connections", skip_serializing_if = "Option::is_none")]
+    pub min_connections: Option<usize>,
+
+    /// Number of connections above which connections are evicted.
+    #[serde(rename = "max-connections", skip_serializing_if = "Option::is_none")]
+    pub max_connections: Option<usize>,
+
+    /// Seconds after which the keys of connections to peers are rotated.
+    #[serde(rename = "rekey-interval", skip_serializing_if = "Option::is_none")]
+    pub rekey_interval: Option<u64>,
+
+    /// Bytes received from a peer after which the key of the connection is rotated.
+    #[serde(rename = "rekey-bytes", skip_serializing_if = "Option::is_none")]
+    pub rekey_bytes: Option<u64>,
+
+    /// Packets of a peer accepted out of order behind its latest one.
+    #[serde(rename = "replay-window", skip_serializing_if = "Option::is_none")]
+    pub replay_window: Option<u32>,
+
+    /// Whether to relay the packets of connected peers which can't reach each other directly.
+    #[serde(default)]
+    pub relay: bool,
+
+    /// Limit of the traffic relayed for other peers, in bytes per second.
+    #[serde(rename = "relay-rate-limit", skip_serializing_if = "Option::is_none")]
+    pub relay_rate_limit: Option<usize>,
+
+    /// How the node chooses its location in the ring.
+    #[serde(rename = "location-strategy", skip_serializing_if = "Option::is_none")]
+    pub location_strategy: Option<LocationStrategy>,
+
+    /// Messages accepted from each peer per second.
+    #[serde(
+        rename = "peer-message-rate-limit",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub peer_message_rate_limit: Option<u32>,
+
+    /// Operations each peer can start on this node per second.
+    #[serde(rename = "peer-op-rate-limit", skip_serializing_if = "Option::is_none")]
+    pub peer_op_rate_limit: Option<u32>,
+
+    /// GET operations processed concurrently.
+    #[serde(
+        rename = "max-concurrent-gets",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_concurrent_gets: Option<usize>,
+
+    /// PUT operations processed concurrently.
+    #[serde(
+        rename = "max-concurrent-puts",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_concurrent_puts: Option<usize>,
+
+    /// SUBSCRIBE operations processed concurrently.
+    #[serde(
+        rename = "max-concurrent-subscribes",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_concurrent_subscribes: Option<usize>,
+
+    /// Messages of each type of operation waiting to be processed past which new operations are
+    /// shed.
+    #[serde(rename = "max-queued-ops", skip_serializing_if = "Option::is_none")]
+    pub max_queued_ops: Option<usize>,
+
+    /// Whether to cache the states of the contracts routed through the node.
+    #[serde(default, rename = "opportunistic-caching")]
+    pub opportunistic_caching: bool,
+
+    /// Size of the states of the contracts cached opportunistically, in bytes, also bounding
+    /// the contracts kept in memory by storage-less nodes.
+    #[serde(rename = "cache-budget", skip_serializing_if = "Option::is_none")]
+    pub cache_budget: Option<u64>,
+
+    /// Whether to never write contracts, delegates or states to disk nor seed contracts for the
+    /// network.
+    #[serde(default, rename = "storage-less")]
+    pub storage_less: bool,
+
+    /// Size of the states of the contracts near the location of the node fetched from its
+    /// neighbors when joining the network, in bytes.
+    #[serde(rename = "bootstrap-budget", skip_serializing_if = "Option::is_none")]
+    pub bootstrap_budget: Option<u64>,
+
     /// List of IP:port addresses to refuse connections to/from.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub blocked_addresses: Option<HashSet<SocketAddr>>,
+
+    /// Port to listen on for QUIC links with peers supporting them.
+    #[serde(rename = "quic-port", skip_serializing_if = "Option::is_none")]
+    pub quic_port: Option<u16>,
+
+    /// Whether to ask the router of the local network to forward the network port.
+    #[serde(default, rename = "port-mapping")]
+    pub port_mapping: bool,
+
+    /// Domain name whose DNS records list the gateways of the network.
+    #[serde(rename = "dns-seed", skip_serializing_if = "Option::is_none")]
+    pub dns_seed: Option<String>,
+
+    /// URL of a signed list of the gateways of the network.
+    #[serde(rename = "seed-list", skip_serializing_if = "Option::is_none")]
+    pub seed_list: Option<String>,
+
+    /// Base58 encoded ed25519 keys trusted to sign the seed list.
+    #[serde(
+        default,
+        rename = "seed-list-keys",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub seed_list_keys: Vec<String>,
+
+    /// Whether to discover the peers of the local network with mDNS.
+    #[serde(default)]
+    pub mdns: bool,
+
+    /// Peers to only connect with, every peer is allowed if empty.
+    #[serde(
+        default,
+        rename = "allowed-peers",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub allowed_peers: Vec<String>,
+
+    /// Peers to refuse connections with.
+    #[serde(
+        default,
+        rename = "denied-peers",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub denied_peers: Vec<String>,
+
+    /// Retries of the operations failing to reach a peer able to handle them, per type of
+    /// operation.
+    #[serde(default)]
+    pub retries: RetryPolicies,
+
+    /// Peers a get is sent to at once when several of them may hold the contract, the first
+    /// valid response answering it and the others being aborted. Each extra peer adds to the
+    /// traffic of every get, so it defaults to 1, only sending it to the peer routed to.
+    #[serde(default = "default_get_fanout", rename = "get-fanout")]
+    pub get_fanout: usize,
+}
+
+impl NetworkApiConfig {
+    pub(crate) fn peer_lists(&self) -> PeerLists {
+        PeerLists {
+            allowed: self.allowed_peers.clone(),
+            denied: self.denied_peers.clone(),
+        }
+    }
+
+    pub(crate) fn bandwidth_limits(&self) -> BandwidthLimits {
+        BandwidthLimits {
+            upload: self.bandwidth_limit,
+            download: self.download_limit,
+            peer_upload: self.peer_upload_limit,
+            peer_download: self.peer_download_limit,
+        }
+    }
+
+    pub(crate) fn rekey_limits(&self) -> RekeyLimits {
+        let default = RekeyLimits::default();
+        RekeyLimits {
+            interval: self
+                .rekey_interval
+                .map(Duration::from_secs)
+                .unwrap_or(default.interval),
+            bytes: self.rekey_bytes.unwrap_or(default.bytes),
+        }
+    }
+
+    pub(crate) fn replay_limits(&self) -> ReplayLimits {
+        ReplayLimits {
+            window: self.replay_window.unwrap_or(ReplayLimits::default().window),
+        }
+    }
+
+    pub(crate) fn relay_limits(&self) -> RelayLimits {
+        RelayLimits {
+            enabled: self.relay,
+            bytes_per_second: self
+                .relay_rate_limit
+                .unwrap_or(RelayLimits::default().bytes_per_second),
+        }
+    }
+
+    pub(crate) fn inbound_limits(&self) -> InboundLimits {
+        let default = InboundLimits::default();
+        InboundLimits {
+            messages_per_second: self
+                .peer_message_rate_limit
+                .unwrap_or(default.messages_per_second),
+            ops_per_second: self.peer_op_rate_limit.unwrap_or(default.ops_per_second),
+        }
+    }
+
+    pub(crate) fn op_limits(&self) -> OpLimits {
+        let default = OpLimits::default();
+        OpLimits {
+            gets: self.max_concurrent_gets.unwrap_or(default.gets),
+            puts: self.max_concurrent_puts.unwrap_or(default.puts),
+            subscribes: self.max_concurrent_subscribes.unwrap_or(default.subscribes),
+            max_queued: self.max_queued_ops.unwrap_or(default.max_queued),
+        }
+    }
+
+    /// Parses the keys trusted to sign the seed list.
+    pub(crate) fn seed_list_keys(&self) -> anyhow::Result<Vec<ed25519_dalek::VerifyingKey>> {
+        self.seed_list_keys
+            .iter()
+            .map(|key| {
+                let key_bytes: [u8; ed25519_dalek::PUBLIC_KEY_LENGTH] = bs58::decode(key)
+                    .into_vec()
+                    .with_context(|| format!("invalid seed list key {key}"))?
+                    .try_into()
+                    .map_err(|_| anyhow::anyhow!("invalid seed list key length of {key}"))?;
+                ed25519_dalek::VerifyingKey::from_bytes(&key_bytes)
+                    .with_context(|| format!("invalid seed list key {key}"))
+            })
+            .collect()
+    }
+
+    pub(crate) fn cache_limits(&self) -> CacheLimits {
+        CacheLimits {
+            enabled: self.opportunistic_caching,
+            budget: self.cache_budget.unwrap_or(CacheLimits::default().budget),
+        }
+    }
+
+    pub(crate) fn bootstrap_limits(&self) -> BootstrapLimits {
+        // storage-less nodes don't seed contracts for the network
+        let budget = if self.storage_less {
+            0
+        } else {
+            self.bootstrap_budget
+                .unwrap_or(BootstrapLimits::default().budget)
+        };
+        BootstrapLimits { budget }
+    }
+}
+
+/// Retries of the get, put and subscribe operations, which clients may override per request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RetryPolicies {
+    #[serde(default = "RetryPolicy::get")]
+    pub get: RetryPolicy,
+    #[serde(default = "RetryPolicy::put")]
+    pub put: RetryPolicy,
+    #[serde(default = "RetryPolicy::subscribe")]
+    pub subscribe: RetryPolicy,
+}
+
+impl Default for RetryPolicies {
+    fn default() -> Self {
+        Self {
+            get: RetryPolicy::get(),
+            put: RetryPolicy::put(),
+            subscribe: RetryPolicy::subscribe(),
+        }
+    }
+}
+
+/// Retries of a type of operation.
+///
+/// Gets and subscriptions are retried when a peer doesn't hold the contract, puts when they
+/// can't be forwarded to the next peer. Retries wait for a delay growing with the number of
+/// attempts along the `backoff` curve, from `backoff-base` up to `backoff-max` milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RetryPolicy {
+    /// Times an operation is retried at most.
+    pub max_retries: usize,
+    /// Whether retries go through other peers than those failing the operation, retrying the
+    /// same peer otherwise.
+    #[serde(default = "default_alternate_routes")]
+    pub alternate_routes: bool,
+    #[serde(default)]
+    pub backoff: BackoffCurve,
+    /// Delay before the first retry in milliseconds, 0 retrying right away.
+    #[serde(default)]
+    pub backoff_base: u64,
+    /// Longest delay before a retry in milliseconds.
+    #[serde(default = "default_backoff_max")]
+    pub backoff_max: u64,
+}
+
+impl RetryPolicy {
+    fn new(max_retries: usize) -> Self {
+        Self {
+            max_retries,
+            alternate_routes: default_alternate_routes(),
+            backoff: BackoffCurve::default(),
+            backoff_base: 0,
+            backoff_max: default_backoff_max(),
+        }
+    }
+
+    fn get() -> Self {
+        Self::new(10)
+    }
+
+    fn put() -> Self {
+        Self::new(0)
+    }
+
+    fn subscribe() -> Self {
+        Self::new(10)
+    }
+
+    /// Delay before retrying an operation already retried `retries` times.
+    pub fn delay(&self, retries: usize) -> Duration {
+        let base = self.backoff_base;
+        let delay = match self.backoff {
+            BackoffCurve::Constant => base,
+            BackoffCurve::Linear => base.saturating_mul(retries as u64 + 1),
+            BackoffCurve::Exponential => {
+                base.saturating_mul(1u64.checked_shl(retries as u32).unwrap_or(u64::MAX))
+            }
+        };
+        Duration::from_millis(delay.min(self.backoff_max))
+    }
+}
+
+/// How the delay before retrying an operation grows with the number of attempts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackoffCurve {
+    Constant,
+    Linear,
+    #[default]
+    Exponential,
+}
+
+#[inline]
+const fn default_alternate_routes() -> bool {
+    true
+}
+
+#[inline]
+const fn default_backoff_max() -> u64 {
+    10_000
+}
+
+#[inline]
+const fn default_get_fanout() -> usize {
+    1
+}
+
+/// How the node chooses its location in the ring.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LocationStrategy {
+    /// Derived from the public address of the node, as assigned by the gateways, so peers can't
+    /// choose where they join the ring.
+    #[default]
+    Address,
+    /// Chosen at random, for private networks whose gateways trust the locations of peers.
+    Random,
+    /// Moved over time to the widest gap between the neighbors of the node, spreading the
+    /// keyspace evenly across the nodes of private networks.
+    KeyspaceBalancing,
+    /// Set by the operator with the location option.
+    Pinned,
 }
 
 mod port_allocation;
@@ -582,9 +1471,47 @@ pub struct WebsocketApiArgs {
     #[arg(long, env = "WS_API_PORT")]
     #[serde(rename = "ws-api-port", skip_serializing_if = "Option::is_none")]
     pub ws_api_port: Option<u16>,
+
+    /// Max size in bytes of the metadata section of packed webapps, default is 1kB
+    #[arg(long, env = "WEBAPP_MAX_METADATA_SIZE")]
+    #[serde(
+        rename = "webapp-max-metadata-size",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub webapp_max_metadata_size: Option<u64>,
+
+    /// Max size in bytes of the compressed web archive of packed webapps, default is 100MB
+    #[arg(long, env = "WEBAPP_MAX_WEB_SIZE")]
+    #[serde(
+        rename = "webapp-max-web-size",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub webapp_max_web_size: Option<u64>,
+
+    /// Max size in bytes of the web archive of webapps split across contracts once
+    /// reassembled, default is 1GB
+    #[arg(long, env = "WEBAPP_MAX_ASSEMBLED_SIZE")]
+    #[serde(
+        rename = "webapp-max-assembled-size",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub webapp_max_assembled_size: Option<u64>,
+
+    /// Peers the node must be connected to before reporting itself as ready, default is 1
+    #[arg(long, env = "READY_MIN_PEERS")]
+    #[serde(rename = "ready-min-peers", skip_serializing_if = "Option::is_none")]
+    pub ready_min_peers: Option<usize>,
+
+    /// Min size in bytes of the webapp files compressed by the HTTP gateway, default is 1kB
+    #[arg(long, env = "COMPRESSION_MIN_SIZE")]
+    #[serde(
+        rename = "compression-min-size",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub compression_min_size: Option<u64>,
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WebsocketApiConfig {
     /// Address to bind to
     #[serde(default = "default_listening_address", rename = "ws-api-address")]
@@ -593,6 +1520,597 @@ pub struct WebsocketApiConfig {
     /// Port to expose api on
     #[serde(default = "default_http_gateway_port", rename = "ws-api-port")]
     pub port: u16,
+
+    /// Trusted publisher keys (base58 encoded ed25519 public keys) indexed by webapp contract id.
+    ///
+    /// The HTTP gateway refuses to serve the webapp of any of those contracts unless
+    /// the state was signed by the matching publisher.
+    #[serde(
+        default,
+        rename = "webapp-publisher-keys",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub webapp_publisher_keys: HashMap<String, String>,
+
+    /// Max size in bytes of the metadata section of packed webapps.
+    #[serde(
+        default = "default_webapp_max_metadata_size",
+        rename = "webapp-max-metadata-size"
+    )]
+    pub webapp_max_metadata_size: u64,
+
+    /// Max size in bytes of the compressed web archive of packed webapps.
+    #[serde(
+        default = "default_webapp_max_web_size",
+        rename = "webapp-max-web-size"
+    )]
+    pub webapp_max_web_size: u64,
+
+    /// Max size in bytes of the web archive of webapps split across contracts once
+    /// reassembled, each chunk being bounded by the max size of the web archive.
+    #[serde(
+        default = "default_webapp_max_assembled_size",
+        rename = "webapp-max-assembled-size"
+    )]
+    pub webapp_max_assembled_size: u64,
+
+    /// Peers the node must be connected to before `/readyz` reports it as ready.
+    #[serde(default = "default_ready_min_peers", rename = "ready-min-peers")]
+    pub ready_min_peers: usize,
+
+    /// Min size in bytes of the webapp files the HTTP gateway compresses when clients
+    /// accept gzip or brotli encoded responses.
+    #[serde(
+        default = "default_compression_min_size",
+        rename = "compression-min-size"
+    )]
+    pub compression_min_size: u64,
+
+    /// Directory where the HTTP gateway extracts webapps, keyed by contract and state hash.
+    ///
+    /// Point it to a persistent location so webapps extracted before a restart are served
+    /// without decompressing them again, defaults to a temporary directory.
+    #[serde(
+        default,
+        rename = "webapp-cache-dir",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub webapp_cache_dir: Option<PathBuf>,
+
+    /// Max size in bytes of the messages sent to websocket clients, larger responses and
+    /// notifications are streamed in chunks to clients able to reassemble them.
+    ///
+    /// Set to 0 to always send them whole.
+    #[serde(default = "default_stream_chunk_size", rename = "stream-chunk-size")]
+    pub stream_chunk_size: u64,
+
+    /// Max number of websocket connections whose messages are compressed at once, each holding
+    /// a single compression context of a few hundred kB. Clients connecting past it are sent
+    /// their messages uncompressed.
+    ///
+    /// Compression is requested by clients connecting with `compression=deflate` in the query of
+    /// the URL, the permessage-deflate extension not being supported.
+    ///
+    /// Set to 0 to never compress them.
+    #[serde(
+        default = "default_compression_contexts",
+        rename = "compression-contexts"
+    )]
+    pub compression_contexts: usize,
+
+    /// Serve the HTTP gateway and websocket API over TLS.
+    #[serde(default, rename = "tls", skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+
+    /// Cross origin access policy of the HTTP gateway and websocket API.
+    ///
+    /// When set, requests from origins other than the gateway itself are rejected unless allowed.
+    #[serde(default, rename = "cors", skip_serializing_if = "Option::is_none")]
+    pub cors: Option<CorsConfig>,
+
+    /// Serve webapps from subdomains of the gateway, so each of them gets its own origin.
+    #[serde(
+        default,
+        rename = "subdomains",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub subdomains: Option<SubdomainConfig>,
+
+    /// Addresses (e.g. `127.0.0.1`) or networks (e.g. `10.0.0.0/8`) of the reverse proxies in
+    /// front of the gateway.
+    ///
+    /// Requests from those are attributed to the client in their `X-Forwarded-For` header,
+    /// and served for the scheme and host in `X-Forwarded-Proto` and `X-Forwarded-Host`.
+    #[serde(
+        default,
+        rename = "trusted-proxies",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub trusted_proxies: Vec<String>,
+
+    /// Request rate limits of the HTTP gateway and websocket API, unlimited when not set.
+    #[serde(
+        default,
+        rename = "rate-limit",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub rate_limit: Option<RateLimitConfig>,
+
+    /// Resources each client can hold in the websocket API, unlimited when not set.
+    #[serde(
+        default,
+        rename = "client-limits",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub client_limits: Option<ClientLimitsConfig>,
+
+    /// Access log of the requests served by the gateway and the websocket API.
+    #[serde(
+        default,
+        rename = "access-log",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub access_log: Option<AccessLogConfig>,
+
+    /// Let websocket clients resume their subscriptions after reconnecting, disabled when
+    /// not set.
+    #[serde(default, rename = "sessions", skip_serializing_if = "Option::is_none")]
+    pub sessions: Option<SessionConfig>,
+
+    /// File the subscriptions of the sessions are saved to, so clients resume them after the
+    /// node restarts. Defaults to a file in the data directory when building the configuration.
+    #[serde(
+        default,
+        rename = "sessions-file",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub sessions_file: Option<PathBuf>,
+
+    /// Notifications queued for websocket clients which don't keep up with them.
+    #[serde(default, rename = "outbound-queue")]
+    pub outbound_queue: OutboundQueueConfig,
+
+    /// Additional addresses the gateway and websocket API are served on.
+    #[serde(default, rename = "listeners", skip_serializing_if = "Vec::is_empty")]
+    pub listeners: Vec<ListenerConfig>,
+
+    /// Bearer tokens accepted by the websocket API, and the operations each of them grants.
+    ///
+    /// When any is set, clients must present one of them (or a token assigned to a webapp
+    /// served through the gateway) when connecting. The admin API is only available to
+    /// tokens granted the `admin` scope.
+    #[serde(
+        default,
+        rename = "api-tokens",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub api_tokens: HashMap<String, HashSet<ApiScope>>,
+}
+
+/// Resources each client can hold in the websocket API, so a misbehaving application can
+/// not exhaust the node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ClientLimitsConfig {
+    /// Concurrent websocket connections from the same IP.
+    #[serde(default = "default_max_connections_per_ip")]
+    pub max_connections_per_ip: u32,
+    /// Contracts a single connection can be subscribed to.
+    #[serde(default = "default_max_subscriptions_per_connection")]
+    pub max_subscriptions_per_connection: u32,
+    /// Requests a single connection can be awaiting the result of.
+    #[serde(default = "default_max_in_flight_ops_per_connection")]
+    pub max_in_flight_ops_per_connection: u32,
+}
+
+impl Default for ClientLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_connections_per_ip: default_max_connections_per_ip(),
+            max_subscriptions_per_connection: default_max_subscriptions_per_connection(),
+            max_in_flight_ops_per_connection: default_max_in_flight_ops_per_connection(),
+        }
+    }
+}
+
+#[inline]
+const fn default_max_connections_per_ip() -> u32 {
+    64
+}
+
+#[inline]
+const fn default_max_subscriptions_per_connection() -> u32 {
+    256
+}
+
+#[inline]
+const fn default_max_in_flight_ops_per_connection() -> u32 {
+    64
+}
+
+/// Address the gateway and websocket API are served on besides `ws-api-address`, e.g. a Unix
+/// socket for local apps or a LAN address for trusted clients.
+///
+/// Additional listeners serve plain HTTP, TLS only applies to the main address.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ListenerConfig {
+    /// `<ip>:<port>` socket address, or `unix:<path>` for a Unix domain socket.
+    pub address: String,
+    #[serde(default)]
+    pub auth: ListenerAuth,
+}
+
+/// How clients of a listener are authorized.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ListenerAuth {
+    /// Clients present one of the configured API tokens, as on the main address.
+    #[default]
+    Tokens,
+    /// Any client reaching the listener can use the websocket API, the admin API is not
+    /// available through it.
+    Open,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddress {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl ListenerConfig {
+    pub fn listen_address(&self) -> anyhow::Result<ListenAddress> {
+        if let Some(path) = self.address.strip_prefix("unix:") {
+            if !cfg!(unix) {
+                anyhow::bail!("Unix sockets are not supported on this platform: {path}");
+            }
+            if path.is_empty() {
+                anyhow::bail!("missing path of the Unix socket listener");
+            }
+            return Ok(ListenAddress::Unix(PathBuf::from(path)));
+        }
+        let addr = self
+            .address
+            .parse()
+            .with_context(|| format!("invalid listener address: {}", self.address))?;
+        Ok(ListenAddress::Tcp(addr))
+    }
+}
+
+/// Access log written as JSON lines, one entry per request, for traffic analysis.
+///
+/// The file is rotated once it grows past `max-size`, keeping `max-files` rotated files
+/// suffixed with `.1` (the most recent) to `.<max-files>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AccessLogConfig {
+    pub path: PathBuf,
+    /// Size in bytes after which the log file is rotated.
+    #[serde(default = "default_access_log_max_size")]
+    pub max_size: u64,
+    /// Rotated log files kept besides the current one.
+    #[serde(default = "default_access_log_max_files")]
+    pub max_files: u32,
+}
+
+#[inline]
+const fn default_access_log_max_size() -> u64 {
+    64 * 1024 * 1024
+}
+
+#[inline]
+const fn default_access_log_max_files() -> u32 {
+    5
+}
+
+/// Resumption of the sessions of websocket clients which lost their connection.
+///
+/// Subscriptions of disconnected clients are kept for `ttl` seconds, buffering up to
+/// `replay-buffer` notifications which are sent to the client once it reconnects. Sessions
+/// which missed more notifications than that can not be resumed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SessionConfig {
+    /// Seconds the subscriptions of a disconnected client are kept.
+    #[serde(default = "default_session_ttl")]
+    pub ttl: u64,
+    /// Notifications buffered for a disconnected client.
+    #[serde(default = "default_session_replay_buffer")]
+    pub replay_buffer: usize,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            ttl: default_session_ttl(),
+            replay_buffer: default_session_replay_buffer(),
+        }
+    }
+}
+
+#[inline]
+const fn default_session_ttl() -> u64 {
+    60
+}
+
+#[inline]
+const fn default_session_replay_buffer() -> usize {
+    256
+}
+
+/// Notifications queued for a websocket client while it is not reading them fast enough.
+///
+/// Once `high-water-mark` notifications are queued for a client, `policy` decides what
+/// happens to further ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct OutboundQueueConfig {
+    #[serde(default = "default_outbound_high_water_mark")]
+    pub high_water_mark: usize,
+    #[serde(default)]
+    pub policy: SlowClientPolicy,
+}
+
+impl Default for OutboundQueueConfig {
+    fn default() -> Self {
+        Self {
+            high_water_mark: default_outbound_high_water_mark(),
+            policy: SlowClientPolicy::default(),
+        }
+    }
+}
+
+#[inline]
+const fn default_outbound_high_water_mark() -> usize {
+    1024
+}
+
+/// What happens to notifications for a client with a full outbound queue.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SlowClientPolicy {
+    /// The oldest queued notification is dropped.
+    DropOldest,
+    /// Notifications carrying the whole state of a contract replace the ones queued for it,
+    /// otherwise the oldest queued notification is dropped.
+    Coalesce,
+    /// The client is disconnected, so it can reconnect and catch up.
+    #[default]
+    Disconnect,
+}
+
+/// Token bucket rate limits, as sustained requests per second and max burst of requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RateLimitConfig {
+    /// Requests per second allowed for each client IP.
+    #[serde(default = "default_ip_requests_per_second")]
+    pub ip_requests_per_second: u32,
+    /// Requests a client IP can make in a burst.
+    #[serde(default = "default_ip_burst")]
+    pub ip_burst: u32,
+    /// Requests per second allowed for each contract.
+    #[serde(default = "default_contract_requests_per_second")]
+    pub contract_requests_per_second: u32,
+    /// Requests a contract can receive in a burst.
+    #[serde(default = "default_contract_burst")]
+    pub contract_burst: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            ip_requests_per_second: default_ip_requests_per_second(),
+            ip_burst: default_ip_burst(),
+            contract_requests_per_second: default_contract_requests_per_second(),
+            contract_burst: default_contract_burst(),
+        }
+    }
+}
+
+#[inline]
+const fn default_ip_requests_per_second() -> u32 {
+    20
+}
+
+#[inline]
+const fn default_ip_burst() -> u32 {
+    100
+}
+
+#[inline]
+const fn default_contract_requests_per_second() -> u32 {
+    100
+}
+
+#[inline]
+const fn default_contract_burst() -> u32 {
+    500
+}
+
+/// CORS settings of the HTTP gateway and websocket API.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CorsConfig {
+    /// Origins (e.g. `https://app.example.com`) allowed to access the API, `*` allows any origin.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Allow requests carrying credentials (cookies or authorization headers).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_credentials: bool,
+}
+
+impl CorsConfig {
+    /// Whether any origin is allowed.
+    pub fn allows_any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|origin| origin == "*")
+    }
+
+    pub fn is_allowed(&self, origin: &str) -> bool {
+        self.allows_any_origin()
+            || self
+                .allowed_origins
+                .iter()
+                .any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(origin))
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.allow_credentials && self.allows_any_origin() {
+            anyhow::bail!("CORS credentials can not be allowed for any origin");
+        }
+        for origin in self.allowed_origins.iter().filter(|o| *o != "*") {
+            let uri: axum::http::Uri = origin
+                .parse()
+                .with_context(|| format!("invalid CORS origin: {origin}"))?;
+            if uri.scheme().is_none() || uri.host().is_none() {
+                anyhow::bail!("CORS origin must include scheme and host: {origin}");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Routing of `<contract>.<domain>` hostnames to the webapp of the contract.
+///
+/// Contracts are addressed either by one of the aliases or by their id encoded in lowercase
+/// base32, as hostnames are case insensitive.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SubdomainConfig {
+    /// Domain the webapp subdomains belong to, e.g. `gateway.example` or `localhost`.
+    pub domain: String,
+    /// Subdomain labels (e.g. `chat`) mapped to the id of the webapp contract they serve.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub aliases: HashMap<String, String>,
+}
+
+impl SubdomainConfig {
+    /// Parses the configured aliases, keyed by lowercase label.
+    pub fn contract_aliases(&self) -> anyhow::Result<HashMap<String, ContractInstanceId>> {
+        self.aliases
+            .iter()
+            .map(|(alias, contract)| {
+                let valid_label = !alias.is_empty()
+                    && alias.len() <= 63
+                    && !alias.starts_with('-')
+                    && !alias.ends_with('-')
+                    && alias.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+                if !valid_label {
+                    anyhow::bail!("invalid subdomain alias: {alias}");
+                }
+                let contract_key = ContractKey::from_id(contract.clone()).with_context(|| {
+                    format!("invalid contract id for alias {alias}: {contract}")
+                })?;
+                Ok((alias.to_ascii_lowercase(), *contract_key.id()))
+            })
+            .collect()
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let domain = self.domain.trim_matches('.');
+        if domain.is_empty() || domain.contains(['/', ':']) {
+            anyhow::bail!("invalid subdomain routing domain: {}", self.domain);
+        }
+        self.contract_aliases()?;
+        Ok(())
+    }
+}
+
+/// TLS settings of the HTTP gateway and websocket API.
+///
+/// Either a certificate and key are provided, or certificates for the given domains
+/// are provisioned automatically through ACME (Let's Encrypt).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TlsConfig {
+    /// Path to the PEM encoded certificate chain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert: Option<PathBuf>,
+    /// Path to the PEM encoded private key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<PathBuf>,
+    /// Domains to provision certificates for through ACME, using the TLS-ALPN-01 challenge.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub acme_domains: Vec<String>,
+    /// Contact email registered with the ACME account.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acme_contact: Option<String>,
+    /// Directory where the ACME account and certificates are persisted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acme_cache_dir: Option<PathBuf>,
+    /// Use the staging ACME directory, for testing.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub acme_staging: bool,
+}
+
+impl TlsConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match (&self.cert, &self.key, self.acme_domains.is_empty()) {
+            (Some(_), Some(_), true) | (None, None, false) => Ok(()),
+            (Some(_), Some(_), false) => {
+                anyhow::bail!("TLS certificate and ACME domains are mutually exclusive")
+            }
+            (None, None, true) => {
+                anyhow::bail!("TLS requires either a certificate and key or ACME domains")
+            }
+            _ => anyhow::bail!("TLS certificate and key must be provided together"),
+        }
+    }
+}
+
+impl WebsocketApiConfig {
+    /// Size limits enforced on packed webapp states served by the gateway.
+    pub fn webapp_limits(&self) -> WebAppLimits {
+        WebAppLimits {
+            max_metadata_size: self.webapp_max_metadata_size,
+            max_web_size: self.webapp_max_web_size,
+            max_assembled_size: self.webapp_max_assembled_size,
+        }
+    }
+
+    /// Parses the configured trusted proxy addresses and networks.
+    pub fn trusted_proxy_networks(&self) -> anyhow::Result<Vec<ipnet::IpNet>> {
+        self.trusted_proxies
+            .iter()
+            .map(|proxy| {
+                proxy
+                    .parse::<ipnet::IpNet>()
+                    .or_else(|_| proxy.parse::<IpAddr>().map(ipnet::IpNet::from))
+                    .with_context(|| format!("invalid trusted proxy address: {proxy}"))
+            })
+            .collect()
+    }
+
+    pub(crate) fn api_tokens(&self) -> ApiTokens {
+        ApiTokens::new(self.api_tokens.iter().map(|(token, scopes)| {
+            (
+                AuthToken::from(token.clone()),
+                TokenGrant::new(scopes.iter().copied()),
+            )
+        }))
+    }
+
+    /// Parses the configured webapp publisher keys.
+    pub(crate) fn publisher_keys(
+        &self,
+    ) -> anyhow::Result<HashMap<ContractInstanceId, ed25519_dalek::VerifyingKey>> {
+        self.webapp_publisher_keys
+            .iter()
+            .map(|(contract, key)| {
+                let contract_key = ContractKey::from_id(contract.clone())
+                    .with_context(|| format!("invalid webapp contract id: {contract}"))?;
+                let key_bytes: [u8; ed25519_dalek::PUBLIC_KEY_LENGTH] = bs58::decode(key)
+                    .into_vec()
+                    .with_context(|| format!("invalid publisher key for {contract}"))?
+                    .try_into()
+                    .map_err(|_| anyhow::anyhow!("invalid publisher key length for {contract}"))?;
+                let pub_key = ed25519_dalek::VerifyingKey::from_bytes(&key_bytes)
+                    .with_context(|| format!("invalid publisher key for {contract}"))?;
+                Ok((*contract_key.id(), pub_key))
+            })
+            .collect()
+    }
 }
 
 impl From<SocketAddr> for WebsocketApiConfig {
@@ -600,6 +2118,27 @@ impl From<SocketAddr> for WebsocketApiConfig {
         Self {
             address: addr.ip(),
             port: addr.port(),
+            webapp_publisher_keys: HashMap::new(),
+            webapp_max_metadata_size: default_webapp_max_metadata_size(),
+            webapp_max_web_size: default_webapp_max_web_size(),
+            webapp_max_assembled_size: default_webapp_max_assembled_size(),
+            ready_min_peers: default_ready_min_peers(),
+            compression_min_size: default_compression_min_size(),
+            webapp_cache_dir: None,
+            stream_chunk_size: default_stream_chunk_size(),
+            compression_contexts: default_compression_contexts(),
+            tls: None,
+            cors: None,
+            subdomains: None,
+            trusted_proxies: Vec::new(),
+            rate_limit: None,
+            client_limits: None,
+            access_log: None,
+            sessions: None,
+            sessions_file: None,
+            outbound_queue: OutboundQueueConfig::default(),
+            listeners: Vec::new(),
+            api_tokens: HashMap::new(),
         }
     }
 }
@@ -610,6 +2149,27 @@ impl Default for WebsocketApiConfig {
         Self {
             address: default_listening_address(),
             port: default_http_gateway_port(),
+            webapp_publisher_keys: HashMap::new(),
+            webapp_max_metadata_size: default_webapp_max_metadata_size(),
+            webapp_max_web_size: default_webapp_max_web_size(),
+            webapp_max_assembled_size: default_webapp_max_assembled_size(),
+            ready_min_peers: default_ready_min_peers(),
+            compression_min_size: default_compression_min_size(),
+            webapp_cache_dir: None,
+            stream_chunk_size: default_stream_chunk_size(),
+            compression_contexts: default_compression_contexts(),
+            tls: None,
+            cors: None,
+            subdomains: None,
+            trusted_proxies: Vec::new(),
+            rate_limit: None,
+            client_limits: None,
+            access_log: None,
+            sessions: None,
+            sessions_file: None,
+            outbound_queue: OutboundQueueConfig::default(),
+            listeners: Vec::new(),
+            api_tokens: HashMap::new(),
         }
     }
 }
@@ -619,6 +2179,12 @@ const fn default_listening_address() -> IpAddr {
     IpAddr::V4(Ipv4Addr::UNSPECIFIED)
 }
 
+/// Unspecified IPv6 address, which the transport binds to dual-stack sockets.
+#[inline]
+const fn default_network_address() -> IpAddr {
+    IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+}
+
 #[inline]
 const fn default_local_address() -> IpAddr {
     IpAddr::V4(Ipv4Addr::LOCALHOST)
@@ -629,6 +2195,41 @@ const fn default_http_gateway_port() -> u16 {
     50509
 }
 
+#[inline]
+const fn default_webapp_max_metadata_size() -> u64 {
+    DEFAULT_MAX_METADATA_SIZE
+}
+
+#[inline]
+const fn default_webapp_max_web_size() -> u64 {
+    DEFAULT_MAX_WEB_SIZE
+}
+
+#[inline]
+const fn default_webapp_max_assembled_size() -> u64 {
+    DEFAULT_MAX_ASSEMBLED_SIZE
+}
+
+#[inline]
+const fn default_ready_min_peers() -> usize {
+    1
+}
+
+#[inline]
+const fn default_compression_min_size() -> u64 {
+    DEFAULT_COMPRESSION_MIN_SIZE
+}
+
+#[inline]
+const fn default_stream_chunk_size() -> u64 {
+    1024 * 1024
+}
+
+#[inline]
+const fn default_compression_contexts() -> usize {
+    256
+}
+
 #[derive(clap::Parser, Default, Debug, Clone, Serialize, Deserialize)]
 pub struct ConfigPathsArgs {
     /// The configuration directory.
@@ -1059,6 +2660,35 @@ mod tests {
         let _: Config = toml::from_str(&serialized).unwrap();
     }
 
+    #[tokio::test]
+    async fn test_merge_persisted_config() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let args = || ConfigArgs {
+            mode: Some(OperationMode::Local),
+            config_paths: ConfigPathsArgs {
+                config_dir: Some(dir.path().to_path_buf()),
+                data_dir: Some(dir.path().to_path_buf()),
+            },
+            ..Default::default()
+        };
+        let persisted = ConfigArgs {
+            network_api: NetworkArgs {
+                quic_port: Some(50600),
+                ..Default::default()
+            },
+            ..args()
+        }
+        .build()
+        .await?;
+        assert!(dir.path().join("config.toml").is_file());
+        assert_eq!(persisted.network_api.quic_port, Some(50600));
+
+        // restarted from the persisted configuration
+        let restarted = args().build().await?;
+        assert_eq!(restarted.network_api.quic_port, Some(50600));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_load_gateways_from_index() {
         let server = Server::run();
@@ -1144,4 +2774,262 @@ mod tests {
             assert!(socket.port() > 1024); // Ensure we're using unprivileged ports
         }
     }
+
+    #[test]
+    fn test_tls_config() {
+        let ws_api: WebsocketApiConfig = toml::from_str(
+            r#"
+            [tls]
+            acme-domains = ["gateway.example.com"]
+            acme-contact = "admin@example.com"
+            "#,
+        )
+        .unwrap();
+        let tls = ws_api.tls.unwrap();
+        assert_eq!(tls.acme_domains, ["gateway.example.com"]);
+        tls.validate().unwrap();
+
+        let cert_and_acme = TlsConfig {
+            cert: Some("cert.pem".into()),
+            key: Some("key.pem".into()),
+            ..tls
+        };
+        assert!(cert_and_acme.validate().is_err());
+        assert!(TlsConfig::default().validate().is_err());
+        assert!(TlsConfig {
+            cert: Some("cert.pem".into()),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_trusted_proxies() {
+        let ws_api = WebsocketApiConfig {
+            trusted_proxies: vec!["127.0.0.1".into(), "10.0.0.0/8".into(), "::1".into()],
+            ..Default::default()
+        };
+        let networks = ws_api.trusted_proxy_networks().unwrap();
+        assert!(networks[0].contains(&IpAddr::from([127, 0, 0, 1])));
+        assert!(networks[1].contains(&IpAddr::from([10, 1, 2, 3])));
+        assert!(!networks[1].contains(&IpAddr::from([11, 1, 2, 3])));
+        assert!(networks[2].contains(&IpAddr::V6(std::net::Ipv6Addr::LOCALHOST)));
+
+        let invalid = WebsocketApiConfig {
+            trusted_proxies: vec!["proxy.example".into()],
+            ..Default::default()
+        };
+        assert!(invalid.trusted_proxy_networks().is_err());
+    }
+
+    #[test]
+    fn test_subdomain_config() {
+        let contract = ContractInstanceId::new([1; 32]);
+        let ws_api: WebsocketApiConfig = toml::from_str(&format!(
+            r#"
+            [subdomains]
+            domain = "gateway.example"
+            aliases = {{ Chat = "{contract}" }}
+            "#
+        ))
+        .unwrap();
+        let subdomains = ws_api.subdomains.unwrap();
+        subdomains.validate().unwrap();
+        assert_eq!(
+            subdomains.contract_aliases().unwrap().get("chat"),
+            Some(&contract)
+        );
+
+        let invalid_alias = SubdomainConfig {
+            aliases: HashMap::from([("a.b".to_owned(), contract.to_string())]),
+            ..subdomains.clone()
+        };
+        assert!(invalid_alias.validate().is_err());
+        let invalid_domain = SubdomainConfig {
+            domain: "https://gateway.example".into(),
+            ..subdomains
+        };
+        assert!(invalid_domain.validate().is_err());
+    }
+
+    #[test]
+    fn test_client_limits_config() {
+        let ws_api: WebsocketApiConfig = toml::from_str(
+            r#"
+            [client-limits]
+            max-connections-per-ip = 8
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            ws_api.client_limits,
+            Some(ClientLimitsConfig {
+                max_connections_per_ip: 8,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_stream_chunk_size_config() {
+        let ws_api: WebsocketApiConfig = toml::from_str("").unwrap();
+        assert_eq!(ws_api.stream_chunk_size, 1024 * 1024);
+        let ws_api: WebsocketApiConfig = toml::from_str("stream-chunk-size = 0").unwrap();
+        assert_eq!(ws_api.stream_chunk_size, 0);
+    }
+
+    #[test]
+    fn test_compression_contexts_config() {
+        let ws_api: WebsocketApiConfig = toml::from_str("").unwrap();
+        assert_eq!(ws_api.compression_contexts, 256);
+        let ws_api: WebsocketApiConfig = toml::from_str("compression-contexts = 0").unwrap();
+        assert_eq!(ws_api.compression_contexts, 0);
+    }
+
+    #[test]
+    fn test_sessions_config() {
+        let ws_api: WebsocketApiConfig = toml::from_str(
+            r#"
+            [sessions]
+            ttl = 300
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            ws_api.sessions,
+            Some(SessionConfig {
+                ttl: 300,
+                replay_buffer: 256,
+            })
+        );
+    }
+
+    #[test]
+    fn test_retries_config() {
+        let retries: RetryPolicies = toml::from_str(
+            r#"
+            [get]
+            max-retries = 3
+            alternate-routes = false
+            backoff = "linear"
+            backoff-base = 100
+            backoff-max = 250
+            "#,
+        )
+        .unwrap();
+        assert_eq!(retries.put, RetryPolicy::put());
+        assert_eq!(retries.subscribe, RetryPolicy::subscribe());
+        let get = retries.get;
+        assert_eq!(get.max_retries, 3);
+        assert!(!get.alternate_routes);
+        assert_eq!(get.delay(0), Duration::from_millis(100));
+        assert_eq!(get.delay(1), Duration::from_millis(200));
+        assert_eq!(get.delay(2), Duration::from_millis(250));
+
+        let exponential = RetryPolicy {
+            backoff: BackoffCurve::Exponential,
+            ..get
+        };
+        assert_eq!(exponential.delay(1), Duration::from_millis(200));
+        assert_eq!(exponential.delay(100), Duration::from_millis(250));
+        assert_eq!(RetryPolicy::get().delay(5), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_outbound_queue_config() {
+        let ws_api: WebsocketApiConfig = toml::from_str(
+            r#"
+            [outbound-queue]
+            policy = "coalesce"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            ws_api.outbound_queue,
+            OutboundQueueConfig {
+                high_water_mark: 1024,
+                policy: SlowClientPolicy::Coalesce,
+            }
+        );
+    }
+
+    #[test]
+    fn test_access_log_config() {
+        let ws_api: WebsocketApiConfig = toml::from_str(
+            r#"
+            [access-log]
+            path = "/var/log/freenet/access.log"
+            max-files = 2
+            "#,
+        )
+        .unwrap();
+        let access_log = ws_api.access_log.unwrap();
+        assert_eq!(
+            access_log.path,
+            PathBuf::from("/var/log/freenet/access.log")
+        );
+        assert_eq!(access_log.max_size, default_access_log_max_size());
+        assert_eq!(access_log.max_files, 2);
+    }
+
+    #[test]
+    fn test_listeners_config() {
+        let ws_api: WebsocketApiConfig = toml::from_str(
+            r#"
+            [[listeners]]
+            address = "unix:/run/freenet/api.sock"
+            auth = "open"
+
+            [[listeners]]
+            address = "192.168.1.10:7509"
+            "#,
+        )
+        .unwrap();
+        let [unix, lan] = ws_api.listeners.as_slice() else {
+            panic!("expected two listeners");
+        };
+        assert_eq!(unix.auth, ListenerAuth::Open);
+        if cfg!(unix) {
+            assert_eq!(
+                unix.listen_address().unwrap(),
+                ListenAddress::Unix("/run/freenet/api.sock".into())
+            );
+        }
+        assert_eq!(lan.auth, ListenerAuth::Tokens);
+        assert_eq!(
+            lan.listen_address().unwrap(),
+            ListenAddress::Tcp(([192, 168, 1, 10], 7509).into())
+        );
+
+        let invalid = ListenerConfig {
+            address: "localhost".into(),
+            auth: ListenerAuth::Tokens,
+        };
+        assert!(invalid.listen_address().is_err());
+    }
+
+    #[test]
+    fn test_cors_config() {
+        let cors = CorsConfig {
+            allowed_origins: vec!["https://app.example.com/".into()],
+            allow_credentials: true,
+        };
+        cors.validate().unwrap();
+        assert!(cors.is_allowed("https://app.example.com"));
+        assert!(!cors.is_allowed("https://evil.example.com"));
+
+        let any = CorsConfig {
+            allowed_origins: vec!["*".into()],
+            ..cors.clone()
+        };
+        assert!(any.is_allowed("https://evil.example.com"));
+        assert!(any.validate().is_err());
+
+        let no_scheme = CorsConfig {
+            allowed_origins: vec!["app.example.com".into()],
+            ..cors
+        };
+        assert!(no_scheme.validate().is_err());
+    }
 }
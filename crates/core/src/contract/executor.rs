@@ -7,7 +7,7 @@ use std::fmt::Display;
 use std::future::Future;
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
 use blake3::traits::digest::generic_array::GenericArray;
 use either::Either;
@@ -29,10 +29,10 @@ use crate::operations::get::GetResult;
 use crate::operations::{OpEnum, OpError};
 use crate::wasm_runtime::{
     ContractExecError, ContractRuntimeInterface, ContractStore, DelegateRuntimeInterface,
-    DelegateStore, Runtime, SecretsStore, StateStore, StateStoreError,
+    DelegateStore, Runtime, SecretsStore, StateStore, StateStoreError, StoredState,
 };
 use crate::{
-    client_events::{ClientId, HostResult},
+    client_events::{ClientId, HostResult, SubscriptionMode},
     operations::{self, Operation},
 };
 
@@ -43,11 +43,15 @@ pub(super) mod runtime;
 pub struct ExecutorError {
     inner: Either<Box<RequestError>, anyhow::Error>,
     fatal: bool,
+    /// Whether it rejects a conditional update of a contract whose state differs from the one
+    /// expected.
+    state_conflict: bool,
 }
 
 enum InnerOpError {
     Upsert(ContractKey),
     Delegate(DelegateKey),
+    Query(ContractKey),
 }
 
 impl std::error::Error for ExecutorError {}
@@ -57,6 +61,7 @@ impl ExecutorError {
         Self {
             inner: Either::Right(error.into()),
             fatal: false,
+            state_conflict: false,
         }
     }
 
@@ -65,6 +70,7 @@ impl ExecutorError {
         Self {
             inner: Either::Right(anyhow::anyhow!("internal error")),
             fatal: false,
+            state_conflict: false,
         }
     }
 
@@ -72,6 +78,7 @@ impl ExecutorError {
         Self {
             inner: Either::Left(Box::new(error.into())),
             fatal: false,
+            state_conflict: false,
         }
     }
 
@@ -90,6 +97,15 @@ impl ExecutorError {
             if let Some(InnerOpError::Upsert(key)) = &op {
                 return ExecutorError::request(StdContractError::update_exec_error(*key, e));
             }
+            if let Some(InnerOpError::Query(key)) = &op {
+                return ExecutorError {
+                    fatal,
+                    ..ExecutorError::request(StdContractError::Get {
+                        key: *key,
+                        cause: format!("{e}").into(),
+                    })
+                };
+            }
         }
 
         if let RuntimeInnerError::DelegateNotFound(key) = error {
@@ -140,10 +156,30 @@ impl ExecutorError {
         err
     }
 
+    /// The state of the contract differs from the one a conditional update expected, the hash of
+    /// its state being `current`.
+    fn state_conflict(key: ContractKey, current: Option<blake3::Hash>) -> Self {
+        let cause = match current {
+            Some(current) => format!("state conflict, the current state hash is {current}"),
+            None => "state conflict, the contract has no state".to_owned(),
+        };
+        Self {
+            state_conflict: true,
+            ..ExecutorError::request(StdContractError::Update {
+                key,
+                cause: cause.into(),
+            })
+        }
+    }
+
     pub fn is_request(&self) -> bool {
         matches!(self.inner, Either::Left(_))
     }
 
+    pub fn is_state_conflict(&self) -> bool {
+        self.state_conflict
+    }
+
     pub fn is_fatal(&self) -> bool {
         self.fatal
     }
@@ -161,6 +197,7 @@ impl From<RequestError> for ExecutorError {
         Self {
             inner: Either::Left(Box::new(value)),
             fatal: false,
+            state_conflict: false,
         }
     }
 }
@@ -179,10 +216,26 @@ impl From<Box<RequestError>> for ExecutorError {
         Self {
             inner: Either::Left(value),
             fatal: false,
+            state_conflict: false,
         }
     }
 }
 
+/// Checks the hash of the state of the contract is the one a conditional update expects.
+pub(crate) async fn expect_state(
+    executor: &mut impl ContractExecutor,
+    key: ContractKey,
+    expected: &blake3::Hash,
+) -> Result<(), ExecutorError> {
+    let (state, _) = executor.fetch_contract(key, false).await?;
+    let current = state.map(|state| blake3::hash(state.as_ref()));
+    if current.as_ref() == Some(expected) {
+        Ok(())
+    } else {
+        Err(ExecutorError::state_conflict(key, current))
+    }
+}
+
 type Response = Result<HostResponse, ExecutorError>;
 
 #[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -472,13 +525,68 @@ pub(crate) trait ContractExecutor: Send + 'static {
         cli_id: ClientId,
         notification_ch: tokio::sync::mpsc::UnboundedSender<HostResult>,
         summary: Option<StateSummary<'_>>,
+        mode: SubscriptionMode,
     ) -> Result<(), Box<RequestError>>;
 
+    /// Stops notifying the client of the updates of the contracts it subscribed to.
+    fn unregister_contract_notifiers(&mut self, cli_id: ClientId);
+
     fn execute_delegate_request(
         &mut self,
         req: DelegateRequest<'_>,
         attested_contract: Option<&ContractInstanceId>,
     ) -> Response;
+
+    fn stored_contracts(
+        &mut self,
+    ) -> impl Future<Output = Result<Vec<StoredContract>, ExecutorError>> + Send;
+
+    /// Projection of the state of the contract computed by the `query_state` entry point of the
+    /// contract from the query of a client, failing for contracts without one. `None` if the
+    /// state isn't stored by this node.
+    fn query_state(
+        &mut self,
+        key: ContractKey,
+        query: StateSummary<'static>,
+    ) -> impl Future<Output = Result<Option<StateDelta<'static>>, ExecutorError>> + Send;
+
+    /// Summary of the state of the contract computed by the contract, `None` if the state isn't
+    /// stored by this node.
+    fn state_summary(
+        &mut self,
+        key: ContractKey,
+    ) -> impl Future<Output = Result<Option<StateSummary<'static>>, ExecutorError>> + Send;
+
+    /// Removes the state of a contract none of the clients of this node are subscribed to,
+    /// returning whether it was removed.
+    fn evict_contract(
+        &mut self,
+        key: &ContractKey,
+    ) -> impl Future<Output = Result<bool, ExecutorError>> + Send;
+
+    /// Hash of the code of the delegate, if registered in this node.
+    fn delegate_code_hash(&self, key: &DelegateKey) -> Option<CodeHash>;
+
+    /// Last time the state of the contract was updated, `None` if it wasn't since the node
+    /// started.
+    fn state_updated_at(&self, key: &ContractKey) -> Option<SystemTime>;
+}
+
+/// A contract with its state stored by this node.
+#[derive(Debug)]
+pub(crate) struct StoredContract {
+    pub id: ContractInstanceId,
+    pub state_size: usize,
+    /// Last time the state was updated, `None` if it wasn't since the node started.
+    pub updated_at: Option<SystemTime>,
+    /// Clients of this node subscribed to updates of the contract.
+    pub subscribers: usize,
+}
+
+/// State of a client subscribed to a contract, which the notifications sent to it are based on.
+struct SubscriberSummary {
+    mode: SubscriptionMode,
+    summary: Option<StateSummary<'static>>,
 }
 
 /// A WASM executor which will run any contracts, delegates, etc. registered.
@@ -493,9 +601,12 @@ pub struct Executor<R = Runtime> {
     /// Notification channels for any clients subscribed to updates for a given contract.
     update_notifications: HashMap<ContractKey, Vec<(ClientId, mpsc::UnboundedSender<HostResult>)>>,
     /// Summaries of the state of all clients subscribed to a given contract.
-    subscriber_summaries: HashMap<ContractKey, HashMap<ClientId, Option<StateSummary<'static>>>>,
+    subscriber_summaries: HashMap<ContractKey, HashMap<ClientId, SubscriberSummary>>,
     /// Attested contract instances for a given delegate.
     delegate_attested_ids: HashMap<DelegateKey, Vec<ContractInstanceId>>,
+    /// Bytes of the states and code of the contracts kept at most, for storage-less nodes
+    /// keeping them in memory.
+    memory_budget: Option<u64>,
 
     event_loop_channel: Option<ExecutorToEventLoopChannel<ExecutorHalve>>,
 }
@@ -517,10 +628,87 @@ impl<R> Executor<R> {
             update_notifications: HashMap::default(),
             subscriber_summaries: HashMap::default(),
             delegate_attested_ids: HashMap::default(),
+            memory_budget: None,
             event_loop_channel,
         })
     }
 
+    async fn list_stored_contracts(&self) -> Result<Vec<StoredContract>, ExecutorError> {
+        let mut subscribers = HashMap::<ContractInstanceId, usize>::new();
+        for (key, channels) in &self.update_notifications {
+            *subscribers.entry(*key.id()).or_default() += channels.len();
+        }
+        let states = self
+            .state_store
+            .stored()
+            .await
+            .map_err(ExecutorError::other)?;
+        Ok(states
+            .into_iter()
+            .map(|state| StoredContract {
+                id: state.id,
+                state_size: state.size,
+                updated_at: state.updated_at,
+                subscribers: subscribers.get(&state.id).copied().unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    fn remove_contract_notifiers(&mut self, cli_id: ClientId) {
+        for channels in self.update_notifications.values_mut() {
+            channels.retain(|(id, _)| *id != cli_id);
+        }
+        self.update_notifications.retain(|_, channels| !channels.is_empty());
+        for summaries in self.subscriber_summaries.values_mut() {
+            summaries.remove(&cli_id);
+        }
+        self.subscriber_summaries.retain(|_, summaries| !summaries.is_empty());
+    }
+
+    /// Contracts to evict, least recently updated first, for those kept in memory to fit in the
+    /// memory budget. Neither `keep` nor contracts clients are subscribed to are evicted.
+    async fn over_memory_budget(
+        &self,
+        keep: &ContractKey,
+        code_size: impl Fn(&ContractInstanceId) -> usize,
+    ) -> Result<Vec<ContractKey>, ExecutorError> {
+        let Some(budget) = self.memory_budget else {
+            return Ok(vec![]);
+        };
+        let mut stored = self
+            .state_store
+            .stored()
+            .await
+            .map_err(ExecutorError::other)?;
+        let size = |state: &StoredState| (state.size + code_size(&state.id)) as u64;
+        let mut used: u64 = stored.iter().map(size).sum();
+        stored.sort_by_key(|state| state.updated_at);
+        let mut evicted = vec![];
+        for state in stored {
+            if used <= budget {
+                break;
+            }
+            let key = ContractKey::from(state.id);
+            if key == *keep || self.update_notifications.contains_key(&key) {
+                continue;
+            }
+            used -= size(&state);
+            evicted.push(key);
+        }
+        Ok(evicted)
+    }
+
+    async fn evict_stored_contract(&mut self, key: &ContractKey) -> Result<bool, ExecutorError> {
+        if self.update_notifications.contains_key(key) {
+            return Ok(false);
+        }
+        self.state_store
+            .remove(key)
+            .await
+            .map_err(ExecutorError::other)?;
+        Ok(true)
+    }
+
     pub fn test_data_dir(identifier: &str) -> PathBuf {
         std::env::temp_dir().join(format!("freenet-executor-{identifier}"))
     }
@@ -539,6 +727,17 @@ impl<R> Executor<R> {
         const MAX_SIZE: i64 = 10 * 1024 * 1024;
         const MAX_MEM_CACHE: u32 = 10_000_000;
 
+        if config.network_api.storage_less {
+            tracing::info!("Storage-less mode, keeping contracts, delegates and states in memory");
+            let state_store = StateStore::new(Storage::in_memory().await?, MAX_MEM_CACHE).unwrap();
+            return Ok((
+                ContractStore::in_memory(MAX_SIZE),
+                DelegateStore::in_memory(MAX_SIZE),
+                SecretsStore::in_memory(config.secrets.clone()),
+                state_store,
+            ));
+        }
+
         let state_store =
             StateStore::new(Storage::new(&config.db_dir()).await?, MAX_MEM_CACHE).unwrap();
         let contract_store = ContractStore::new(config.contracts_dir(), MAX_SIZE)?;
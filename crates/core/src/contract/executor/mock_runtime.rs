@@ -115,6 +115,7 @@ impl ContractExecutor for Executor<MockRuntime> {
         _cli_id: ClientId,
         _notification_ch: UnboundedSender<HostResult>,
         _summary: Option<StateSummary<'_>>,
+        _mode: SubscriptionMode,
     ) -> Result<(), Box<RequestError>> {
         Ok(())
     }
@@ -128,6 +129,45 @@ impl ContractExecutor for Executor<MockRuntime> {
             "not supported in mock runtime"
         )))
     }
+
+    async fn stored_contracts(&mut self) -> Result<Vec<StoredContract>, ExecutorError> {
+        self.list_stored_contracts().await
+    }
+
+    async fn query_state(
+        &mut self,
+        _key: ContractKey,
+        _query: StateSummary<'static>,
+    ) -> Result<Option<StateDelta<'static>>, ExecutorError> {
+        Err(ExecutorError::other(anyhow::anyhow!(
+            "not supported in mock runtime"
+        )))
+    }
+
+    async fn state_summary(
+        &mut self,
+        _key: ContractKey,
+    ) -> Result<Option<StateSummary<'static>>, ExecutorError> {
+        Err(ExecutorError::other(anyhow::anyhow!(
+            "not supported in mock runtime"
+        )))
+    }
+
+    fn unregister_contract_notifiers(&mut self, cli_id: ClientId) {
+        self.remove_contract_notifiers(cli_id);
+    }
+
+    async fn evict_contract(&mut self, key: &ContractKey) -> Result<bool, ExecutorError> {
+        self.evict_stored_contract(key).await
+    }
+
+    fn delegate_code_hash(&self, _key: &DelegateKey) -> Option<CodeHash> {
+        None
+    }
+
+    fn state_updated_at(&self, key: &ContractKey) -> Option<SystemTime> {
+        self.state_store.updated_at(key)
+    }
 }
 
 #[cfg(test)]
@@ -161,4 +201,109 @@ mod test {
         assert_eq!(counter, 1);
         Ok(())
     }
+
+    /// Files under `dir` with their sizes.
+    fn files(dir: &std::path::Path) -> std::io::Result<Vec<(PathBuf, u64)>> {
+        let mut files = vec![];
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                files.extend(files(&entry.path())?);
+            } else {
+                files.push((entry.path(), metadata.len()));
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn storage_less_stores_write_nothing() -> Result<(), Box<dyn std::error::Error>> {
+        let data_dir = tempfile::tempdir()?;
+        let config_dir = tempfile::tempdir()?;
+        let mut args = crate::config::ConfigArgs {
+            mode: Some(OperationMode::Local),
+            ..Default::default()
+        };
+        args.network_api.storage_less = true;
+        args.config_paths.data_dir = Some(data_dir.path().into());
+        args.config_paths.config_dir = Some(config_dir.path().into());
+        let config = args.build().await?;
+        let written = files(data_dir.path())?;
+
+        let (mut contract_store, mut delegate_store, mut secret_store, mut state_store) =
+            Executor::<MockRuntime>::get_stores(&config).await?;
+        let contract = WrappedContract::new(
+            Arc::new(ContractCode::from(vec![0, 1, 2])),
+            Parameters::from(vec![]),
+        );
+        let key = *contract.key();
+        contract_store.store_contract(ContractContainer::Wasm(ContractWasmAPIVersion::V1(
+            contract,
+        )))?;
+        state_store
+            .store(key, WrappedState::new(vec![1]), Parameters::from(vec![]))
+            .await?;
+        let delegate = Delegate::from((&vec![0, 1, 2].into(), &vec![].into()));
+        delegate_store.store_delegate(DelegateContainer::Wasm(DelegateWasmAPIVersion::V1(
+            delegate.clone(),
+        )))?;
+        secret_store.store_secret(delegate.key(), &SecretsId::new(vec![0]), vec![1])?;
+
+        assert_eq!(state_store.get(&key).await?.as_ref(), &[1]);
+        assert!(contract_store
+            .fetch_contract(&key, &Parameters::from(vec![]))
+            .is_some());
+        assert_eq!(files(data_dir.path())?, written);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn evicts_past_memory_budget() -> Result<(), Box<dyn std::error::Error>> {
+        let state_store = StateStore::new(Storage::in_memory().await?, u16::MAX as u32).unwrap();
+        let mut executor = Executor::new(
+            state_store,
+            || Ok(()),
+            OperationMode::Local,
+            MockRuntime {
+                contract_store: ContractStore::in_memory(u16::MAX as i64),
+            },
+            None,
+        )
+        .await?;
+        let keys: Vec<_> = (0..3)
+            .map(|i| ContractKey::from(ContractInstanceId::new([i; 32])))
+            .collect();
+        for key in &keys {
+            executor
+                .state_store
+                .store(
+                    *key,
+                    WrappedState::new(vec![0; 4]),
+                    Parameters::from(vec![]),
+                )
+                .await?;
+        }
+        assert!(executor
+            .over_memory_budget(&keys[2], |_| 0)
+            .await?
+            .is_empty());
+
+        executor.memory_budget = Some(10);
+        let (tx, _rx) = mpsc::unbounded_channel();
+        executor
+            .update_notifications
+            .insert(keys[0], vec![(ClientId::FIRST, tx)]);
+        // neither the contract stored nor those clients are subscribed to are evicted
+        assert_eq!(
+            executor.over_memory_budget(&keys[2], |_| 0).await?,
+            vec![keys[1]]
+        );
+        assert_eq!(
+            executor.over_memory_budget(&keys[2], |_| 4).await?,
+            vec![keys[1]]
+        );
+        Ok(())
+    }
 }
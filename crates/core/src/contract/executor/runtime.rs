@@ -3,6 +3,7 @@ use super::{
     ContractExecutor, ContractRequest, ContractResponse, ExecutorError, ExecutorHalve,
     ExecutorToEventLoopChannel, RequestError, Response, StateStoreError,
 };
+use crate::server::{app_packaging, WebApp, WebAppLimits};
 
 impl ContractExecutor for Executor<Runtime> {
     async fn fetch_contract(
@@ -106,6 +107,7 @@ impl ContractExecutor for Executor<Runtime> {
                                 .store(key, state_to_store, params.clone())
                                 .await
                                 .map_err(ExecutorError::other)?;
+                            self.enforce_memory_budget(&key).await?;
 
                             return Ok(UpsertResult::Updated(incoming_state));
                         }
@@ -197,6 +199,7 @@ impl ContractExecutor for Executor<Runtime> {
         cli_id: ClientId,
         notification_ch: tokio::sync::mpsc::UnboundedSender<HostResult>,
         summary: Option<StateSummary<'_>>,
+        mode: SubscriptionMode,
     ) -> Result<(), Box<RequestError>> {
         let channels = self.update_notifications.entry(key).or_default();
         if let Ok(i) = channels.binary_search_by_key(&&cli_id, |(p, _)| p) {
@@ -216,7 +219,13 @@ impl ContractExecutor for Executor<Runtime> {
             .subscriber_summaries
             .entry(key)
             .or_default()
-            .insert(cli_id, summary.map(StateSummary::into_owned))
+            .insert(
+                cli_id,
+                SubscriberSummary {
+                    mode,
+                    summary: summary.map(StateSummary::into_owned),
+                },
+            )
             .is_some()
         {
             tracing::warn!(
@@ -331,12 +340,134 @@ impl ContractExecutor for Executor<Runtime> {
             _ => Err(ExecutorError::other(anyhow::anyhow!("not supported"))),
         }
     }
+
+    async fn stored_contracts(&mut self) -> Result<Vec<StoredContract>, ExecutorError> {
+        self.list_stored_contracts().await
+    }
+
+    async fn query_state(
+        &mut self,
+        key: ContractKey,
+        query: StateSummary<'static>,
+    ) -> Result<Option<StateDelta<'static>>, ExecutorError> {
+        let state = match self.state_store.get(&key).await {
+            Ok(state) => state,
+            Err(StateStoreError::MissingContract(_)) => return Ok(None),
+            Err(err) => return Err(ExecutorError::other(err)),
+        };
+        let params = self
+            .state_store
+            .get_params(&key)
+            .await
+            .map_err(ExecutorError::other)?
+            .ok_or_else(|| {
+                ExecutorError::request(StdContractError::Get {
+                    key,
+                    cause: "missing contract parameters".into(),
+                })
+            })?;
+        let projection = self
+            .runtime
+            .query_state(&key, &params, &state, &query)
+            .map_err(|err| ExecutorError::execution(err, Some(InnerOpError::Query(key))))?;
+        tracing::debug!(
+            contract = %key,
+            state_size = state.size(),
+            projection_size = projection.size(),
+            "queried contract state"
+        );
+        Ok(Some(projection))
+    }
+
+    async fn state_summary(
+        &mut self,
+        key: ContractKey,
+    ) -> Result<Option<StateSummary<'static>>, ExecutorError> {
+        let state = match self.state_store.get(&key).await {
+            Ok(state) => state,
+            Err(StateStoreError::MissingContract(_)) => return Ok(None),
+            Err(err) => return Err(ExecutorError::other(err)),
+        };
+        let params = self
+            .state_store
+            .get_params(&key)
+            .await
+            .map_err(ExecutorError::other)?
+            .ok_or_else(|| {
+                ExecutorError::request(StdContractError::Get {
+                    key,
+                    cause: "missing contract parameters".into(),
+                })
+            })?;
+        let summary = self
+            .runtime
+            .summarize_state(&key, &params, &state)
+            .map_err(|err| ExecutorError::execution(err, None))?;
+        Ok(Some(summary))
+    }
+
+    fn unregister_contract_notifiers(&mut self, cli_id: ClientId) {
+        self.remove_contract_notifiers(cli_id);
+    }
+
+    async fn evict_contract(&mut self, key: &ContractKey) -> Result<bool, ExecutorError> {
+        self.evict_stored_contract(key).await
+    }
+
+    fn delegate_code_hash(&self, key: &DelegateKey) -> Option<CodeHash> {
+        self.runtime.delegate_code_hash(key)
+    }
+
+    fn state_updated_at(&self, key: &ContractKey) -> Option<SystemTime> {
+        self.state_store.updated_at(key)
+    }
 }
 
 impl Executor<Runtime> {
     // Private implementation methods
 }
 
+fn is_webapp_delta(update: &UpdateData<'_>) -> bool {
+    matches!(update, UpdateData::Delta(delta) if WebApp::is_delta(delta.as_ref()))
+}
+
+/// Packed webapp deltas are applied by the node itself, each against the state left by the
+/// previous updates, so the contract receives the full new states regardless of how they were
+/// published. Only done for webapps which opted in through their manifest, deltas of other
+/// contracts are handed to them untouched.
+///
+/// The new states are bounded by the default webapp limits rather than the ones configured for
+/// the gateway, so every node agrees on which deltas apply.
+fn resolve_webapp_deltas<'a>(
+    key: &ContractKey,
+    current_state: &WrappedState,
+    updates: &[UpdateData<'a>],
+) -> Result<Vec<UpdateData<'a>>, ExecutorError> {
+    let limits = WebAppLimits::default();
+    let mut state = current_state.clone();
+    updates
+        .iter()
+        .map(|update| match update {
+            UpdateData::Delta(delta) if WebApp::is_delta(delta.as_ref()) => {
+                let new_state = WebApp::apply_delta(state.as_ref(), delta.as_ref(), &limits)
+                    .map_err(|err| {
+                        ExecutorError::request(StdContractError::Update {
+                            key: *key,
+                            cause: format!("failed applying webapp delta: {err}").into(),
+                        })
+                    })?;
+                state = WrappedState::new(new_state);
+                Ok(UpdateData::State(state.clone().into()))
+            }
+            UpdateData::State(new_state) => {
+                state = WrappedState::new(new_state.as_ref().to_vec());
+                Ok(update.clone())
+            }
+            other => Ok(other.clone()),
+        })
+        .collect()
+}
+
 impl Executor<Runtime> {
     pub async fn from_config(
         config: Arc<Config>,
@@ -345,7 +476,11 @@ impl Executor<Runtime> {
         let (contract_store, delegate_store, secret_store, state_store) =
             Self::get_stores(&config).await?;
         let rt = Runtime::build(contract_store, delegate_store, secret_store, false).unwrap();
-        Executor::new(
+        let memory_budget = config
+            .network_api
+            .storage_less
+            .then(|| config.network_api.cache_limits().budget);
+        let mut executor = Executor::new(
             state_store,
             move || {
                 let _ =
@@ -358,7 +493,27 @@ impl Executor<Runtime> {
             rt,
             event_loop_channel,
         )
-        .await
+        .await?;
+        executor.memory_budget = memory_budget;
+        crate::metrics::set_executor_ready();
+        Ok(executor)
+    }
+
+    /// Evicts the contracts kept in memory past the memory budget, other than `keep`.
+    async fn enforce_memory_budget(&mut self, keep: &ContractKey) -> Result<(), ExecutorError> {
+        let contract_store = &self.runtime.contract_store;
+        let evicted = self
+            .over_memory_budget(keep, |id| contract_store.memory_size(id))
+            .await?;
+        for key in evicted {
+            tracing::debug!(contract = %key, "Evicting contract past the memory budget");
+            self.evict_stored_contract(&key).await?;
+            self.runtime
+                .contract_store
+                .remove_contract(&key)
+                .map_err(ExecutorError::other)?;
+        }
+        Ok(())
     }
 
     pub async fn preload(
@@ -378,6 +533,7 @@ impl Executor<Runtime> {
                 },
                 cli_id,
                 None,
+                SubscriptionMode::default(),
             )
             .await
         {
@@ -395,7 +551,10 @@ impl Executor<Runtime> {
         updates: Option<mpsc::UnboundedSender<Result<HostResponse, WsClientError>>>,
     ) -> Response {
         match req {
-            ClientRequest::ContractOp(op) => self.contract_requests(op, id, updates).await,
+            ClientRequest::ContractOp(op) => {
+                self.contract_requests(op, id, updates, SubscriptionMode::default())
+                    .await
+            }
             ClientRequest::DelegateOp(op) => self.delegate_request(op, None),
             ClientRequest::Disconnect { cause } => {
                 if let Some(cause) = cause {
@@ -420,6 +579,7 @@ impl Executor<Runtime> {
         req: ContractRequest<'_>,
         cli_id: ClientId,
         updates: Option<mpsc::UnboundedSender<Result<HostResponse, WsClientError>>>,
+        subscription_mode: SubscriptionMode,
     ) -> Response {
         tracing::debug!(
             client = %cli_id,
@@ -476,7 +636,7 @@ impl Executor<Runtime> {
                 let updates = updates.ok_or_else(|| {
                     ExecutorError::other(anyhow::anyhow!("missing update channel"))
                 })?;
-                self.register_contract_notifier(key, cli_id, updates, summary)?;
+                self.register_contract_notifier(key, cli_id, updates, summary, subscription_mode)?;
 
                 // by default a subscribe op has an implicit get
                 let _res = self.perform_contract_get(false, key).await?;
@@ -701,6 +861,15 @@ impl Executor<Runtime> {
         key: &ContractKey,
         updates: &[UpdateData<'_>],
     ) -> Result<Either<WrappedState, Vec<RelatedContract>>, ExecutorError> {
+        let resolved_updates;
+        let updates = if updates.iter().any(is_webapp_delta)
+            && WebApp::accepts_deltas(current_state.as_ref())
+        {
+            resolved_updates = resolve_webapp_deltas(key, current_state, updates)?;
+            resolved_updates.as_slice()
+        } else {
+            updates
+        };
         let update_modification =
             match self
                 .runtime
@@ -1022,6 +1191,7 @@ impl Executor<Runtime> {
                     );
                     ExecutorError::other(e)
                 })?;
+            self.enforce_memory_budget(&trying_key).await?;
             if trying_key != original_key {
                 trying_key = original_key;
                 trying_params = original_params.clone();
@@ -1045,25 +1215,44 @@ impl Executor<Runtime> {
         new_state: &WrappedState,
     ) -> Result<(), ExecutorError> {
         tracing::debug!(contract = %key, "notify of contract update");
+        app_packaging::state_changed(key.id());
         let key = *key;
         if let Some(notifiers) = self.update_notifications.get_mut(&key) {
             let summaries = self.subscriber_summaries.get_mut(&key).unwrap();
             // in general there should be less than 32 failures
             let mut failures = Vec::with_capacity(32);
+            // summarized at most once, for the subscribers which need it
+            let mut new_summary = None;
             for (peer_key, notifier) in notifiers.iter() {
-                let peer_summary = summaries.get_mut(peer_key).unwrap();
-                let update = match peer_summary {
-                    Some(summary) => self
+                let subscriber = summaries.get_mut(peer_key).unwrap();
+                if subscriber.mode == SubscriptionMode::Delta && new_summary.is_none() {
+                    let summary = self
+                        .runtime
+                        .summarize_state(&key, params, new_state)
+                        .map_err(|err| {
+                            ExecutorError::execution(err, Some(InnerOpError::Upsert(key)))
+                        })?;
+                    new_summary = Some(summary);
+                }
+                let update = match (subscriber.mode, &subscriber.summary) {
+                    (SubscriptionMode::Auto | SubscriptionMode::Delta, Some(summary)) => self
                         .runtime
-                        .get_state_delta(&key, params, new_state, &*summary)
+                        .get_state_delta(&key, params, new_state, summary)
                         .map_err(|err| {
                             tracing::error!("{err}");
                             ExecutorError::execution(err, Some(InnerOpError::Upsert(key)))
                         })?
                         .to_owned()
                         .into(),
-                    None => UpdateData::State(State::from(new_state.as_ref()).into_owned()),
+                    (SubscriptionMode::FullState, _)
+                    | (SubscriptionMode::Auto | SubscriptionMode::Delta, None) => {
+                        UpdateData::State(State::from(new_state.as_ref()).into_owned())
+                    }
                 };
+                // next deltas are from the state just notified
+                if subscriber.mode == SubscriptionMode::Delta {
+                    subscriber.summary.clone_from(&new_summary);
+                }
                 if let Err(err) =
                     notifier.send(Ok(
                         ContractResponse::UpdateNotification { key, update }.into()
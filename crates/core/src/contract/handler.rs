@@ -11,20 +11,20 @@ use std::future::Future;
 use std::hash::Hash;
 use std::sync::atomic::{AtomicU64, Ordering::SeqCst};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use freenet_stdlib::client_api::DelegateRequest;
 use freenet_stdlib::prelude::*;
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 
-use super::executor::{ExecutorHalve, ExecutorToEventLoopChannel};
+use super::executor::{ExecutorHalve, ExecutorToEventLoopChannel, StoredContract};
 use super::ExecutorError;
 use super::{
     executor::{ContractExecutor, Executor},
     ContractError,
 };
-use crate::client_events::HostResult;
+use crate::client_events::{HostResult, SubscriptionMode};
 use crate::config::Config;
 use crate::message::Transaction;
 use crate::{client_events::ClientId, wasm_runtime::Runtime};
@@ -252,6 +252,7 @@ impl ContractHandlerChannel<SenderHalve> {
             .event_sender
             .send(InternalCHEvent { ev, id, result })
             .map_err(|err| ContractError::ChannelDropped(Box::new(err.0.ev)))?;
+        crate::metrics::executor_event_queued();
         match tokio::time::timeout(Self::CH_EV_RESPONSE_TIME_OUT, result_receiver).await {
             Ok(Ok((_, res))) => Ok(res),
             Ok(Err(_)) | Err(_) => Err(ContractError::NoEvHandlerResponse),
@@ -290,6 +291,7 @@ impl ContractHandlerChannel<ContractHandlerHalve> {
         &mut self,
     ) -> Result<(EventId, ContractHandlerEvent), ContractError> {
         if let Some(InternalCHEvent { ev, id, result }) = self.end.event_receiver.recv().await {
+            crate::metrics::executor_event_dequeued();
             self.end.waiting_response.insert(id, result);
             return Ok((EventId { id }, ev));
         }
@@ -337,12 +339,17 @@ pub(crate) enum ContractHandlerEvent {
     GetResponse {
         key: ContractKey,
         response: Result<StoreResponse, ExecutorError>,
+        /// Last time the state was updated by this node, if known.
+        updated_at: Option<SystemTime>,
     },
     /// Updates a supposedly existing contract in this node
     UpdateQuery {
         key: ContractKey,
         data: UpdateData<'static>,
         related_contracts: RelatedContracts<'static>,
+        /// Hash of the state the contract is expected to have, the update being rejected
+        /// otherwise.
+        expected_state: Option<blake3::Hash>,
     },
     /// The response to an update query
     UpdateResponse {
@@ -356,9 +363,56 @@ pub(crate) enum ContractHandlerEvent {
         key: ContractKey,
         client_id: ClientId,
         summary: Option<StateSummary<'static>>,
+        mode: SubscriptionMode,
         subscriber_listener: UnboundedSender<HostResult>,
     },
     RegisterSubscriberListenerResponse,
+    /// Remove the subscriber listeners of a client which disconnected
+    UnregisterSubscriberListeners {
+        client_id: ClientId,
+    },
+    UnregisterSubscriberListenersResponse,
+    /// List the contracts with a state stored in this node
+    ListContractsQuery,
+    /// The response to a list contracts query
+    ListContractsResponse {
+        contracts: Result<Vec<StoredContract>, ExecutorError>,
+    },
+    /// Hash of the code of a delegate registered in this node
+    DelegateCodeQuery {
+        key: DelegateKey,
+    },
+    /// The response to a delegate code query, `None` if the delegate is not registered
+    DelegateCodeResponse {
+        code_hash: Option<CodeHash>,
+    },
+    /// Compute a projection of the state of a contract from the query of a client
+    StateQuery {
+        key: ContractKey,
+        query: StateSummary<'static>,
+    },
+    /// The response to a state query, `None` if the state is not stored by this node
+    StateQueryResponse {
+        key: ContractKey,
+        projection: Result<Option<StateDelta<'static>>, ExecutorError>,
+    },
+    /// Summary of the state of a contract computed by the contract
+    SummaryQuery {
+        key: ContractKey,
+    },
+    /// The response to a summary query, `None` if the state is not stored by this node
+    SummaryResponse {
+        key: ContractKey,
+        summary: Result<Option<StateSummary<'static>>, ExecutorError>,
+    },
+    /// Remove the state of a contract cached by this node
+    EvictQuery {
+        key: ContractKey,
+    },
+    /// The response to an evict query, whether the state was removed
+    EvictResponse {
+        evicted: Result<bool, ExecutorError>,
+    },
 }
 
 impl std::fmt::Display for ContractHandlerEvent {
@@ -410,7 +464,7 @@ impl std::fmt::Display for ContractHandlerEvent {
                     "get query {{ {key}, return contract code: {return_contract_code} }}",
                 )
             }
-            ContractHandlerEvent::GetResponse { key, response } => match response {
+            ContractHandlerEvent::GetResponse { key, response, .. } => match response {
                 Ok(_) => {
                     write!(f, "get query response {{ {key} }}",)
                 }
@@ -441,6 +495,58 @@ impl std::fmt::Display for ContractHandlerEvent {
             ContractHandlerEvent::RegisterSubscriberListenerResponse => {
                 write!(f, "register subscriber listener response")
             }
+            ContractHandlerEvent::UnregisterSubscriberListeners { client_id } => {
+                write!(
+                    f,
+                    "unregister subscriber listeners {{ client_id: {client_id} }}",
+                )
+            }
+            ContractHandlerEvent::UnregisterSubscriberListenersResponse => {
+                write!(f, "unregister subscriber listeners response")
+            }
+            ContractHandlerEvent::ListContractsQuery => {
+                write!(f, "list contracts query")
+            }
+            ContractHandlerEvent::ListContractsResponse { contracts } => match contracts {
+                Ok(contracts) => {
+                    write!(
+                        f,
+                        "list contracts response {{ {} contracts }}",
+                        contracts.len()
+                    )
+                }
+                Err(e) => {
+                    write!(f, "list contracts failed {{ {e} }}")
+                }
+            },
+            ContractHandlerEvent::DelegateCodeQuery { key } => {
+                write!(f, "delegate code query {{ {key} }}")
+            }
+            ContractHandlerEvent::DelegateCodeResponse { code_hash } => match code_hash {
+                Some(code_hash) => write!(f, "delegate code response {{ {code_hash} }}"),
+                None => write!(f, "delegate code response {{ not registered }}"),
+            },
+            ContractHandlerEvent::StateQuery { key, .. } => {
+                write!(f, "state query {{ {key} }}")
+            }
+            ContractHandlerEvent::StateQueryResponse { key, projection } => match projection {
+                Ok(_) => write!(f, "state query response {{ {key} }}"),
+                Err(e) => write!(f, "state query failed {{ {key}, {e} }}"),
+            },
+            ContractHandlerEvent::SummaryQuery { key } => {
+                write!(f, "summary query {{ {key} }}")
+            }
+            ContractHandlerEvent::SummaryResponse { key, summary } => match summary {
+                Ok(_) => write!(f, "summary query response {{ {key} }}"),
+                Err(e) => write!(f, "summary query failed {{ {key}, {e} }}"),
+            },
+            ContractHandlerEvent::EvictQuery { key } => {
+                write!(f, "evict query {{ {key} }}")
+            }
+            ContractHandlerEvent::EvictResponse { evicted } => match evicted {
+                Ok(evicted) => write!(f, "evict response {{ evicted: {evicted} }}"),
+                Err(e) => write!(f, "evict failed {{ {e} }}"),
+            },
         }
     }
 }
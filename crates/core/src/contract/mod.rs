@@ -11,7 +11,7 @@ pub mod storages;
 
 pub(crate) use executor::{
     executor_channel, mock_runtime::MockRuntime, Callback, ExecutorToEventLoopChannel,
-    NetworkEventListenerHalve, UpsertResult,
+    NetworkEventListenerHalve, StoredContract, UpsertResult,
 };
 pub(crate) use handler::{
     client_responses_channel, contract_handler_channel, in_memory::MemoryContractHandler,
@@ -45,6 +45,7 @@ where
                 {
                     Ok((state, contract)) => {
                         tracing::debug!(with_contract_code = %return_contract_code, has_contract = %contract.is_some(), "Fetched contract {key}");
+                        let updated_at = contract_handler.executor().state_updated_at(&key);
                         contract_handler
                             .channel()
                             .send_to_sender(
@@ -52,6 +53,7 @@ where
                                 ContractHandlerEvent::GetResponse {
                                     key,
                                     response: Ok(StoreResponse { state, contract }),
+                                    updated_at,
                                 },
                             )
                             .await
@@ -72,6 +74,7 @@ where
                                 ContractHandlerEvent::GetResponse {
                                     key,
                                     response: Err(err),
+                                    updated_at: None,
                                 },
                             )
                             .await
@@ -129,6 +132,7 @@ where
                 key,
                 data,
                 related_contracts,
+                expected_state,
             } => {
                 let update_value: Either<WrappedState, StateDelta<'static>> = match data {
                     freenet_stdlib::prelude::UpdateData::State(state) => {
@@ -137,11 +141,17 @@ where
                     freenet_stdlib::prelude::UpdateData::Delta(delta) => Either::Right(delta),
                     _ => unreachable!(),
                 };
-                let update_result = contract_handler
-                    .executor()
-                    .upsert_contract_state(key, update_value, related_contracts, None)
-                    .instrument(tracing::info_span!("upsert_contract_state", %key))
-                    .await;
+                let update_result = async {
+                    if let Some(expected) = &expected_state {
+                        executor::expect_state(contract_handler.executor(), key, expected).await?;
+                    }
+                    contract_handler
+                        .executor()
+                        .upsert_contract_state(key, update_value, related_contracts, None)
+                        .await
+                }
+                .instrument(tracing::info_span!("upsert_contract_state", %key))
+                .await;
 
                 let event_result = match update_result {
                     Ok(UpsertResult::NoChange) => ContractHandlerEvent::UpdateNoChange { key },
@@ -210,11 +220,12 @@ where
                 key,
                 client_id,
                 summary,
+                mode,
                 subscriber_listener,
             } => {
                 let _ = contract_handler
                     .executor()
-                    .register_contract_notifier(key, client_id, subscriber_listener, summary)
+                    .register_contract_notifier(key, client_id, subscriber_listener, summary, mode)
                     .inspect_err(|err| {
                         tracing::warn!("Error while registering subscriber listener: {err}");
                     });
@@ -228,6 +239,103 @@ where
                         tracing::debug!(%error, "shutting down contract handler");
                     })?;
             }
+            ContractHandlerEvent::UnregisterSubscriberListeners { client_id } => {
+                contract_handler
+                    .executor()
+                    .unregister_contract_notifiers(client_id);
+                contract_handler
+                    .channel()
+                    .send_to_sender(
+                        id,
+                        ContractHandlerEvent::UnregisterSubscriberListenersResponse,
+                    )
+                    .await
+                    .inspect_err(|error| {
+                        tracing::debug!(%error, "shutting down contract handler");
+                    })?;
+            }
+            ContractHandlerEvent::ListContractsQuery => {
+                let contracts = contract_handler
+                    .executor()
+                    .stored_contracts()
+                    .await
+                    .inspect_err(|err| {
+                        tracing::warn!("Error while listing stored contracts: {err}");
+                    });
+                contract_handler
+                    .channel()
+                    .send_to_sender(
+                        id,
+                        ContractHandlerEvent::ListContractsResponse { contracts },
+                    )
+                    .await
+                    .inspect_err(|error| {
+                        tracing::debug!(%error, "shutting down contract handler");
+                    })?;
+            }
+            ContractHandlerEvent::StateQuery { key, query } => {
+                let projection = contract_handler
+                    .executor()
+                    .query_state(key, query)
+                    .instrument(tracing::info_span!("query_state", %key))
+                    .await
+                    .inspect_err(|err| {
+                        tracing::warn!(%key, "Error while querying contract state: {err}");
+                    });
+                contract_handler
+                    .channel()
+                    .send_to_sender(
+                        id,
+                        ContractHandlerEvent::StateQueryResponse { key, projection },
+                    )
+                    .await
+                    .inspect_err(|error| {
+                        tracing::debug!(%error, "shutting down contract handler");
+                    })?;
+            }
+            ContractHandlerEvent::SummaryQuery { key } => {
+                let summary = contract_handler
+                    .executor()
+                    .state_summary(key)
+                    .instrument(tracing::info_span!("state_summary", %key))
+                    .await
+                    .inspect_err(|err| {
+                        tracing::warn!(%key, "Error while summarizing contract state: {err}");
+                    });
+                contract_handler
+                    .channel()
+                    .send_to_sender(id, ContractHandlerEvent::SummaryResponse { key, summary })
+                    .await
+                    .inspect_err(|error| {
+                        tracing::debug!(%error, "shutting down contract handler");
+                    })?;
+            }
+            ContractHandlerEvent::EvictQuery { key } => {
+                let evicted = contract_handler
+                    .executor()
+                    .evict_contract(&key)
+                    .await
+                    .inspect_err(|err| {
+                        tracing::warn!(%key, "Error while evicting contract: {err}");
+                    });
+                contract_handler
+                    .channel()
+                    .send_to_sender(id, ContractHandlerEvent::EvictResponse { evicted })
+                    .await
+                    .inspect_err(|error| {
+                        tracing::debug!(%error, "shutting down contract handler");
+                    })?;
+            }
+            ContractHandlerEvent::DelegateCodeQuery { key } => {
+                let code_hash = contract_handler.executor().delegate_code_hash(&key);
+                contract_handler
+                    .channel()
+                    .send_to_sender(id, ContractHandlerEvent::DelegateCodeResponse { code_hash })
+                    .await
+                    .inspect_err(|error| {
+                        tracing::debug!(%error, "shutting down contract handler");
+                    })?;
+            }
             _ => unreachable!(),
         }
     }
@@ -1,7 +1,7 @@
 use std::path::Path;
 
 use freenet_stdlib::prelude::*;
-use redb::{Database, TableDefinition};
+use redb::{backends::InMemoryBackend, Database, TableDefinition};
 
 use crate::wasm_runtime::StateStorage;
 
@@ -41,6 +41,20 @@ impl ReDb {
     }
 }
 
+impl ReDb {
+    /// A store kept in memory, never written to disk.
+    pub async fn in_memory() -> Result<Self, redb::Error> {
+        let db = Database::builder().create_with_backend(InMemoryBackend::new())?;
+        let txn = db.begin_write()?;
+        {
+            txn.open_table(STATE_TABLE)?;
+            txn.open_table(CONTRACT_PARAMS_TABLE)?;
+        }
+        txn.commit()?;
+        Ok(Self(db))
+    }
+}
+
 impl StateStorage for ReDb {
     type Error = redb::Error;
 
@@ -68,6 +82,29 @@ impl StateStorage for ReDb {
         }
     }
 
+    async fn list(&self) -> Result<Vec<(ContractInstanceId, usize)>, Self::Error> {
+        let txn = self.0.begin_read()?;
+        let tbl = txn.open_table(STATE_TABLE)?;
+        let mut states = Vec::new();
+        for entry in tbl.iter()? {
+            let (key, state) = entry?;
+            if let Ok(id) = <[u8; 32]>::try_from(key.value()) {
+                states.push((ContractInstanceId::new(id), state.value().len()));
+            }
+        }
+        Ok(states)
+    }
+
+    async fn remove(&mut self, key: &ContractKey) -> Result<(), Self::Error> {
+        let txn = self.0.begin_write()?;
+        {
+            txn.open_table(STATE_TABLE)?.remove(key.as_bytes())?;
+            txn.open_table(CONTRACT_PARAMS_TABLE)?
+                .remove(key.as_bytes())?;
+        }
+        txn.commit().map_err(Into::into)
+    }
+
     async fn store_params(
         &mut self,
         key: ContractKey,
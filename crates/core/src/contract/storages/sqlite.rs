@@ -45,6 +45,11 @@ impl Pool {
         create_contracts_table(&pool).await?;
         Ok(Self(pool.clone()))
     }
+
+    /// A store kept in memory, never written to disk.
+    pub async fn in_memory() -> Result<Self, SqlDbError> {
+        Self::new(None).await
+    }
 }
 
 impl StateStorage for Pool {
@@ -77,6 +82,32 @@ impl StateStorage for Pool {
         }
     }
 
+    async fn list(&self) -> Result<Vec<(ContractInstanceId, usize)>, Self::Error> {
+        let rows = sqlx::query(
+            "SELECT contract, length(state) AS size FROM states WHERE state IS NOT NULL",
+        )
+        .fetch_all(&self.0)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                let id = <[u8; 32]>::try_from(row.get::<Vec<u8>, _>("contract")).ok()?;
+                Some((
+                    ContractInstanceId::new(id),
+                    row.get::<i64, _>("size") as usize,
+                ))
+            })
+            .collect())
+    }
+
+    async fn remove(&mut self, key: &ContractKey) -> Result<(), Self::Error> {
+        sqlx::query("DELETE FROM states WHERE contract = ?")
+            .bind(key.as_bytes())
+            .execute(&self.0)
+            .await?;
+        Ok(())
+    }
+
     async fn store_params(
         &mut self,
         key: ContractKey,
@@ -17,6 +17,9 @@ mod message;
 mod node;
 pub use node::{run_local_node, run_network_node};
 
+/// Node metrics exposed in the Prometheus format.
+mod metrics;
+
 /// Network operation/transaction state machines.
 mod operations;
 
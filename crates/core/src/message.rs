@@ -9,8 +9,8 @@ use std::{
 };
 
 use crate::{
-    client_events::HostResult,
-    node::PeerId,
+    client_events::{ClientId, HostResult},
+    node::{neighborhood::NeighborhoodMsg, PeerId},
     operations::{
         connect::ConnectMsg, get::GetMsg, put::PutMsg, subscribe::SubscribeMsg, update::UpdateMsg,
     },
@@ -33,10 +33,29 @@ use ulid::Ulid;
 ///
 /// A transaction may span different messages sent across the network.
 #[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(try_from = "EncodedTransaction")]
 pub struct Transaction {
     id: Ulid,
 }
 
+/// A transaction as received from peers, before checking its type.
+#[derive(Deserialize)]
+struct EncodedTransaction {
+    id: Ulid,
+}
+
+impl TryFrom<EncodedTransaction> for Transaction {
+    type Error = String;
+
+    fn try_from(tx: EncodedTransaction) -> Result<Self, Self::Error> {
+        let tx = Transaction { id: tx.id };
+        match tx.type_byte() {
+            0..=5 => Ok(tx),
+            other => Err(format!("unknown transaction type {other} in {tx}")),
+        }
+    }
+}
+
 impl Transaction {
     pub const NULL: &'static Transaction = &Transaction { id: Ulid(0) };
 
@@ -48,17 +67,21 @@ impl Transaction {
     }
 
     pub(crate) fn transaction_type(&self) -> TransactionType {
-        let id_byte = (self.id.0 & 0xFFu128) as u8;
-        match id_byte {
+        match self.type_byte() {
             0 => TransactionType::Connect,
             1 => TransactionType::Put,
             2 => TransactionType::Get,
             3 => TransactionType::Subscribe,
             4 => TransactionType::Update,
-            _ => unsafe { std::hint::unreachable_unchecked() },
+            5 => TransactionType::Neighborhood,
+            other => unreachable!("transaction type {other} rejected when decoded"),
         }
     }
 
+    fn type_byte(&self) -> u8 {
+        (self.id.0 & 0xFFu128) as u8
+    }
+
     pub fn timed_out(&self) -> bool {
         self.elapsed() >= crate::config::OPERATION_TTL
     }
@@ -73,7 +96,7 @@ impl Transaction {
         self.id.0.to_le_bytes()
     }
 
-    fn elapsed(&self) -> Duration {
+    pub(crate) fn elapsed(&self) -> Duration {
         let current_unix_epoch_ts = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .expect("now should be always be later than unix epoch")
@@ -148,6 +171,15 @@ impl Ord for Transaction {
     }
 }
 
+/// Whether an operation is waited on by a user or runs in the background, such as bulk
+/// publishes, so the node handles interactive operations first under load.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    #[default]
+    Interactive,
+    Background,
+}
+
 /// Get the transaction type associated to a given message type.
 pub trait TxType: sealed_msg_type::SealedTxType {
     fn tx_type_id() -> TransactionTypeId;
@@ -182,6 +214,7 @@ mod sealed_msg_type {
         Get = 2,
         Subscribe = 3,
         Update = 4,
+        Neighborhood = 5,
     }
 
     impl TransactionType {
@@ -192,6 +225,7 @@ mod sealed_msg_type {
                 TransactionType::Get => "get",
                 TransactionType::Subscribe => "subscribe",
                 TransactionType::Update => "update",
+                TransactionType::Neighborhood => "neighborhood",
             }
         }
     }
@@ -225,7 +259,8 @@ mod sealed_msg_type {
         Put -> PutMsg,
         Get -> GetMsg,
         Subscribe -> SubscribeMsg,
-        Update -> UpdateMsg
+        Update -> UpdateMsg,
+        Neighborhood -> NeighborhoodMsg
     });
 }
 
@@ -255,6 +290,7 @@ pub(crate) enum NetMessageV1 {
     },
     Update(UpdateMsg),
     Aborted(Transaction),
+    Neighborhood(NeighborhoodMsg),
 }
 
 trait Versioned {
@@ -277,6 +313,7 @@ impl Versioned for NetMessageV1 {
             NetMessageV1::Get(_) => semver::Version::new(1, 0, 0),
             NetMessageV1::Subscribe(_) => semver::Version::new(1, 0, 0),
             NetMessageV1::Unsubscribed { .. } => semver::Version::new(1, 0, 0),
+            NetMessageV1::Neighborhood(_) => semver::Version::new(1, 0, 0),
             NetMessageV1::Update(_) => semver::Version::new(1, 0, 0),
             NetMessageV1::Aborted(_) => semver::Version::new(1, 0, 0),
         }
@@ -319,6 +356,8 @@ pub(crate) enum NodeEvent {
         callback: tokio::sync::mpsc::Sender<QueryResult>,
     },
     TransactionTimedOut(Transaction),
+    /// A client disconnected, the operations it was waiting on are no longer needed.
+    ClientDisconnected(ClientId),
 }
 
 pub(crate) enum QueryResult {
@@ -328,11 +367,21 @@ pub(crate) enum QueryResult {
         state: WrappedState,
         contract: Option<ContractContainer>,
     },
+    /// State stored by this node served while it's revalidated, with the time since it was last
+    /// updated if known.
+    CachedGetResult {
+        key: ContractKey,
+        state: WrappedState,
+        contract: Option<ContractContainer>,
+        age: Option<Duration>,
+    },
     DelegateResult {
         #[allow(dead_code)]
         key: DelegateKey,
         response: HostResult,
     },
+    /// Operation reported as completed once accepted by this node.
+    Accepted(HostResponse),
 }
 
 impl Display for NodeEvent {
@@ -356,6 +405,9 @@ impl Display for NodeEvent {
             NodeEvent::TransactionTimedOut(transaction) => {
                 write!(f, "Transaction timed out ({})", transaction)
             }
+            NodeEvent::ClientDisconnected(client_id) => {
+                write!(f, "Client disconnected ({client_id})")
+            }
         }
     }
 }
@@ -390,6 +442,7 @@ impl MessageStats for NetMessageV1 {
             NetMessageV1::Update(op) => op.id(),
             NetMessageV1::Aborted(tx) => tx,
             NetMessageV1::Unsubscribed { transaction, .. } => transaction,
+            NetMessageV1::Neighborhood(msg) => msg.id(),
         }
     }
 
@@ -402,6 +455,7 @@ impl MessageStats for NetMessageV1 {
             NetMessageV1::Update(op) => op.target().as_ref().map(|b| b.borrow().clone()),
             NetMessageV1::Aborted(_) => None,
             NetMessageV1::Unsubscribed { .. } => None,
+            NetMessageV1::Neighborhood(msg) => Some(msg.target().clone()),
         }
     }
 
@@ -414,6 +468,7 @@ impl MessageStats for NetMessageV1 {
             NetMessageV1::Update(op) => op.requested_location(),
             NetMessageV1::Aborted(_) => None,
             NetMessageV1::Unsubscribed { .. } => None,
+            NetMessageV1::Neighborhood(msg) => msg.requested_location(),
         }
     }
 }
@@ -430,6 +485,7 @@ impl Display for NetMessage {
                 Subscribe(msg) => msg.fmt(f)?,
                 Update(msg) => msg.fmt(f)?,
                 Aborted(msg) => msg.fmt(f)?,
+                Neighborhood(msg) => msg.fmt(f)?,
                 Unsubscribed { key, from, .. } => {
                     write!(f, "Unsubscribed {{  key: {}, from: {} }}", key, from)?;
                 }
@@ -458,6 +514,8 @@ mod tests {
         std::thread::sleep(Duration::from_millis(1));
         let tx = Transaction::update(TransactionType::Connect, Ulid::new());
         assert_eq!(tx.transaction_type(), TransactionType::Connect);
+        let tx = Transaction::new::<NeighborhoodMsg>();
+        assert_eq!(tx.transaction_type(), TransactionType::Neighborhood);
         let tx = Transaction::update(TransactionType::Subscribe, Ulid::new());
         assert_eq!(tx.transaction_type(), TransactionType::Subscribe);
         std::thread::sleep(Duration::from_millis(1));
@@ -492,4 +550,17 @@ mod tests {
                 < crate::config::OPERATION_TTL.as_millis() as u64 + 5
         );
     }
+
+    #[test]
+    fn reject_unknown_transaction_types() {
+        let tx = Transaction::new::<crate::operations::get::GetMsg>();
+        let encoded = bincode::serialize(&tx).unwrap();
+        assert_eq!(bincode::deserialize::<Transaction>(&encoded).unwrap(), tx);
+
+        let unknown = Transaction {
+            id: Ulid(tx.id.0 | 0x80),
+        };
+        let encoded = bincode::serialize(&unknown).unwrap();
+        assert!(bincode::deserialize::<Transaction>(&encoded).is_err());
+    }
 }
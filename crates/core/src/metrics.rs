@@ -0,0 +1,332 @@
+//! Node metrics, exposed to operators in the Prometheus text format.
+//!
+//! Metrics are process wide: nodes sharing a process (e.g. in network simulations)
+//! report aggregated values.
+
+use std::sync::atomic::AtomicU64;
+
+use once_cell::sync::Lazy;
+use prometheus_client::{
+    encoding::{text::encode, EncodeLabelSet},
+    metrics::{
+        counter::Counter,
+        family::Family,
+        gauge::Gauge,
+        histogram::{exponential_buckets, Histogram},
+    },
+    registry::Registry,
+};
+
+use crate::{
+    message::Transaction,
+    transport::{Replay, TransportStats},
+};
+
+/// Content type of the encoded metrics.
+#[cfg_attr(not(feature = "websocket"), allow(dead_code))]
+pub(crate) const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct OpLabels {
+    op: String,
+}
+
+impl From<&Transaction> for OpLabels {
+    fn from(tx: &Transaction) -> Self {
+        Self {
+            op: tx.transaction_type().description().to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ReplayLabels {
+    kind: String,
+}
+
+impl From<Replay> for ReplayLabels {
+    fn from(replay: Replay) -> Self {
+        let kind = match replay {
+            Replay::Duplicate => "duplicate",
+            Replay::Stale => "stale",
+        };
+        Self {
+            kind: kind.to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct PeerLabels {
+    peer: String,
+}
+
+type PeerGauge = Family<PeerLabels, Gauge<f64, AtomicU64>>;
+
+struct Metrics {
+    registry: Registry,
+    op_duration: Family<OpLabels, Histogram, fn() -> Histogram>,
+    op_failures: Family<OpLabels, Counter>,
+    op_shed: Family<OpLabels, Counter>,
+    connected_peers: Gauge,
+    bytes_sent: Counter,
+    bytes_received: Counter,
+    replayed_packets: Family<ReplayLabels, Counter>,
+    executor_queue_depth: Gauge,
+    executor_ready: Gauge,
+    peer_round_trip: PeerGauge,
+    peer_packet_loss: PeerGauge,
+    peer_retransmissions: Family<PeerLabels, Gauge>,
+    peer_sent_throughput: PeerGauge,
+    peer_received_throughput: PeerGauge,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        fn op_duration_histogram() -> Histogram {
+            // 5ms up to ~40s
+            Histogram::new(exponential_buckets(0.005, 2.0, 14))
+        }
+
+        let op_duration: Family<OpLabels, Histogram, fn() -> Histogram> =
+            Family::new_with_constructor(op_duration_histogram);
+        let op_failures = Family::<OpLabels, Counter>::default();
+        let op_shed = Family::<OpLabels, Counter>::default();
+        let connected_peers = Gauge::default();
+        let bytes_sent = Counter::default();
+        let bytes_received = Counter::default();
+        let replayed_packets = Family::<ReplayLabels, Counter>::default();
+        let executor_queue_depth = Gauge::default();
+        let executor_ready = Gauge::default();
+        let peer_round_trip = PeerGauge::default();
+        let peer_packet_loss = PeerGauge::default();
+        let peer_retransmissions = Family::<PeerLabels, Gauge>::default();
+        let peer_sent_throughput = PeerGauge::default();
+        let peer_received_throughput = PeerGauge::default();
+
+        let mut registry = Registry::with_prefix("freenet");
+        registry.register(
+            "op_duration_seconds",
+            "Time taken to complete network operations",
+            op_duration.clone(),
+        );
+        registry.register(
+            "op_failures",
+            "Network operations which finished with an error",
+            op_failures.clone(),
+        );
+        registry.register(
+            "op_shed",
+            "Network operations started by other peers dropped for the backlog being full",
+            op_shed.clone(),
+        );
+        registry.register(
+            "connected_peers",
+            "Open connections to other peers",
+            connected_peers.clone(),
+        );
+        registry.register(
+            "transport_sent_bytes",
+            "Bytes sent to other peers",
+            bytes_sent.clone(),
+        );
+        registry.register(
+            "transport_received_bytes",
+            "Bytes received from other peers",
+            bytes_received.clone(),
+        );
+        registry.register(
+            "transport_replayed_packets",
+            "Packets from other peers rejected as replayed, either duplicate or stale",
+            replayed_packets.clone(),
+        );
+        registry.register(
+            "executor_queue_depth",
+            "Events waiting to be processed by the contract executor",
+            executor_queue_depth.clone(),
+        );
+        registry.register(
+            "executor_ready",
+            "Whether the contract executor finished initializing",
+            executor_ready.clone(),
+        );
+        registry.register(
+            "peer_round_trip_seconds",
+            "Round-trip time of the connections to peers",
+            peer_round_trip.clone(),
+        );
+        registry.register(
+            "peer_packet_loss",
+            "Proportion of the packets sent to peers which were lost",
+            peer_packet_loss.clone(),
+        );
+        registry.register(
+            "peer_retransmissions",
+            "Packets resent to peers since their connections were opened",
+            peer_retransmissions.clone(),
+        );
+        registry.register(
+            "peer_sent_bytes_per_second",
+            "Average bytes per second sent to peers since their connections were opened",
+            peer_sent_throughput.clone(),
+        );
+        registry.register(
+            "peer_received_bytes_per_second",
+            "Average bytes per second received from peers since their connections were opened",
+            peer_received_throughput.clone(),
+        );
+        Self {
+            registry,
+            op_duration,
+            op_failures,
+            op_shed,
+            connected_peers,
+            bytes_sent,
+            bytes_received,
+            replayed_packets,
+            executor_queue_depth,
+            executor_ready,
+            peer_round_trip,
+            peer_packet_loss,
+            peer_retransmissions,
+            peer_sent_throughput,
+            peer_received_throughput,
+        }
+    }
+}
+
+pub(crate) fn op_completed(tx: &Transaction) {
+    METRICS
+        .op_duration
+        .get_or_create(&tx.into())
+        .observe(tx.elapsed().as_secs_f64());
+}
+
+pub(crate) fn op_failed(tx: &Transaction) {
+    METRICS.op_failures.get_or_create(&tx.into()).inc();
+}
+
+pub(crate) fn op_shed(tx: &Transaction) {
+    METRICS.op_shed.get_or_create(&tx.into()).inc();
+}
+
+pub(crate) fn set_connected_peers(peers: usize) {
+    METRICS.connected_peers.set(peers as i64);
+}
+
+pub(crate) fn bytes_sent(bytes: usize) {
+    METRICS.bytes_sent.inc_by(bytes as u64);
+}
+
+pub(crate) fn bytes_received(bytes: usize) {
+    METRICS.bytes_received.inc_by(bytes as u64);
+}
+
+pub(crate) fn packet_replayed(replay: Replay) {
+    METRICS.replayed_packets.get_or_create(&replay.into()).inc();
+}
+
+pub(crate) fn executor_event_queued() {
+    METRICS.executor_queue_depth.inc();
+}
+
+pub(crate) fn executor_event_dequeued() {
+    METRICS.executor_queue_depth.dec();
+}
+
+pub(crate) fn set_executor_ready() {
+    METRICS.executor_ready.set(1);
+}
+
+#[cfg_attr(not(feature = "websocket"), allow(dead_code))]
+pub(crate) fn executor_ready() -> bool {
+    METRICS.executor_ready.get() > 0
+}
+
+#[cfg_attr(not(feature = "websocket"), allow(dead_code))]
+pub(crate) fn connected_peers() -> usize {
+    METRICS.connected_peers.get().max(0) as usize
+}
+
+/// Replaces the statistics of the connections to peers with those of the peers connected,
+/// labeled by the fingerprint of their key.
+#[cfg_attr(not(feature = "websocket"), allow(dead_code))]
+pub(crate) fn set_peer_stats(peers: &[(String, TransportStats)]) {
+    METRICS.peer_round_trip.clear();
+    METRICS.peer_packet_loss.clear();
+    METRICS.peer_retransmissions.clear();
+    METRICS.peer_sent_throughput.clear();
+    METRICS.peer_received_throughput.clear();
+    for (fingerprint, stats) in peers {
+        let labels = PeerLabels {
+            peer: fingerprint.clone(),
+        };
+        if let Some(rtt) = stats.round_trip_ms {
+            METRICS
+                .peer_round_trip
+                .get_or_create(&labels)
+                .set(rtt as f64 / 1000.0);
+        }
+        METRICS
+            .peer_packet_loss
+            .get_or_create(&labels)
+            .set(stats.packet_loss);
+        METRICS
+            .peer_retransmissions
+            .get_or_create(&labels)
+            .set(stats.retransmissions as i64);
+        METRICS
+            .peer_sent_throughput
+            .get_or_create(&labels)
+            .set(stats.sent_bytes_per_second);
+        METRICS
+            .peer_received_throughput
+            .get_or_create(&labels)
+            .set(stats.received_bytes_per_second);
+    }
+}
+
+/// Encodes the current value of all metrics.
+#[cfg_attr(not(feature = "websocket"), allow(dead_code))]
+pub(crate) fn encode_metrics() -> String {
+    let mut buf = String::new();
+    encode(&mut buf, &METRICS.registry).expect("infallible encoding");
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::operations::get::GetMsg;
+
+    use super::*;
+
+    #[test]
+    fn encode_registered_metrics() {
+        let tx = Transaction::new::<GetMsg>();
+        op_completed(&tx);
+        op_failed(&tx);
+        op_shed(&tx);
+        bytes_sent(1024);
+        packet_replayed(Replay::Stale);
+        set_peer_stats(&[(
+            "fingerprint".to_owned(),
+            TransportStats {
+                round_trip_ms: Some(20),
+                retransmissions: 3,
+                ..Default::default()
+            },
+        )]);
+
+        let encoded = encode_metrics();
+        assert!(encoded.contains(r#"freenet_op_duration_seconds_count{op="get"}"#));
+        assert!(encoded.contains(r#"freenet_op_failures_total{op="get"}"#));
+        assert!(encoded.contains(r#"freenet_op_shed_total{op="get"}"#));
+        assert!(encoded.contains("freenet_transport_sent_bytes_total"));
+        assert!(encoded.contains(r#"freenet_transport_replayed_packets_total{kind="stale"}"#));
+        assert!(encoded.contains("freenet_executor_queue_depth"));
+        assert!(encoded.contains(r#"freenet_peer_retransmissions{peer="fingerprint"} 3"#));
+        assert!(encoded.ends_with("# EOF\n"));
+    }
+}
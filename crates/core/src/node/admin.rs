@@ -0,0 +1,466 @@
+//! Runtime management of the running node, backing the admin API of the HTTP gateway.
+
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use freenet_stdlib::prelude::{CodeHash, ContractKey, DelegateKey};
+use serde::Serialize;
+
+use crate::{
+    config::{Config, GlobalExecutor},
+    contract::{ContractError, ContractHandlerEvent, StoredContract},
+    message::NodeEvent,
+    node::gateway_health::GatewayStatus,
+    operations::OpError,
+    ring::PartitionStatus,
+    router::PeerStats,
+    transport::{PortMappingStatus, TransportKeypair, TransportStats},
+};
+
+use super::{bans::Ban, peer_policy::PeerLists, pins, OpManager};
+
+/// The network node running in this process, if any.
+static RUNNING_NODE: RwLock<Option<NodeHandle>> = RwLock::new(None);
+
+/// Handle to a running network node.
+#[derive(Clone)]
+pub(crate) struct NodeHandle {
+    op_manager: Arc<OpManager>,
+    config: Arc<Config>,
+    /// Addresses of the gateways the node joins the network through.
+    gateways: Arc<[SocketAddr]>,
+}
+
+/// A peer the node is connected to.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct PeerInfo {
+    pub pub_key: String,
+    /// Fingerprint of the key, which peers are listed by in the peer policy.
+    pub fingerprint: String,
+    pub addr: SocketAddr,
+    pub location: f64,
+    /// Round-trip time, packet loss, retransmissions and throughput of the connection.
+    #[serde(flatten)]
+    pub stats: TransportStats,
+}
+
+/// Reputation of a peer the node interacted with, connected or not.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct PeerReputation {
+    pub pub_key: String,
+    pub fingerprint: String,
+    pub addr: SocketAddr,
+    /// Reliability of the peer from 0 to 1, biasing routing and the connections kept.
+    pub score: f64,
+    #[serde(flatten)]
+    pub stats: PeerStats,
+}
+
+/// A gateway of the node, configured or listed by its DNS seed.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct GatewayInfo {
+    pub addr: SocketAddr,
+    pub connected: bool,
+    /// Whether the gateway failed its last health checks.
+    pub down: bool,
+    #[serde(flatten)]
+    pub status: GatewayStatus,
+}
+
+/// A network operation in flight.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct OpInfo {
+    pub transaction: String,
+    pub op: &'static str,
+    pub elapsed_ms: u128,
+}
+
+/// A contract the node stores or is subscribed to.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct ContractInfo {
+    pub id: String,
+    /// Size in bytes of the state, `None` if the node doesn't store it.
+    pub state_size: Option<usize>,
+    /// Unix timestamp, in seconds, of the last update of the state since the node started.
+    pub last_update: Option<u64>,
+    /// Whether the node is subscribed to updates of the contract in the network.
+    pub subscribed: bool,
+    /// Clients of this node subscribed to updates of the contract.
+    pub local_subscribers: usize,
+}
+
+/// A contract pinned to the node.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct PinInfo {
+    pub id: String,
+    /// Whether the node is seeding the contract already, subscribed to its updates.
+    pub seeding: bool,
+}
+
+/// Status of the connection of the node to the network.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct NetworkStatus {
+    /// Peers the node is connected to.
+    pub peers: usize,
+    /// Whether the node is connected to as many peers as required to report itself as ready.
+    pub min_peers_reached: bool,
+    /// Whether the node is connected to any of its gateways, always the case for gateways.
+    pub gateway_reachable: bool,
+}
+
+impl NodeHandle {
+    /// Registers the node built in this process, replacing any previously registered.
+    pub(super) fn register(
+        op_manager: Arc<OpManager>,
+        config: Arc<Config>,
+        gateways: impl IntoIterator<Item = SocketAddr>,
+    ) {
+        *RUNNING_NODE.write().unwrap() = Some(Self {
+            op_manager,
+            config,
+            gateways: gateways.into_iter().collect(),
+        });
+    }
+
+    /// The network node running in this process, `None` in local mode.
+    pub fn running() -> Option<Self> {
+        RUNNING_NODE.read().unwrap().clone()
+    }
+
+    pub fn peers(&self) -> Vec<PeerInfo> {
+        let connection_manager = &self.op_manager.ring.connection_manager;
+        let mut stats = connection_manager.transport_stats();
+        connection_manager
+            .peer_locations()
+            .into_iter()
+            .map(|(peer, location)| PeerInfo {
+                pub_key: peer.pub_key.to_string(),
+                fingerprint: peer.pub_key.fingerprint(),
+                addr: peer.addr,
+                location: location.as_f64(),
+                stats: stats.remove(&peer.addr).unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// Statistics of the connections to peers, by the fingerprint of their key so their
+    /// addresses aren't disclosed.
+    pub fn transport_stats(&self) -> Vec<(String, TransportStats)> {
+        let connection_manager = &self.op_manager.ring.connection_manager;
+        let mut stats = connection_manager.transport_stats();
+        connection_manager
+            .peer_locations()
+            .into_iter()
+            .filter_map(|(peer, _)| Some((peer.pub_key.fingerprint(), stats.remove(&peer.addr)?)))
+            .collect()
+    }
+
+    /// Round-trip times to the connected peers they have been measured for.
+    pub fn round_trip_times(&self) -> Vec<Duration> {
+        let connection_manager = &self.op_manager.ring.connection_manager;
+        connection_manager
+            .peer_locations()
+            .into_iter()
+            .filter_map(|(peer, _)| connection_manager.round_trip_time(&peer.addr))
+            .collect()
+    }
+
+    pub fn reputation(&self) -> Vec<PeerReputation> {
+        self.op_manager
+            .ring
+            .connection_manager
+            .reputation()
+            .stats()
+            .into_iter()
+            .map(|(peer, stats)| PeerReputation {
+                pub_key: peer.pub_key.to_string(),
+                fingerprint: peer.pub_key.fingerprint(),
+                addr: peer.addr,
+                score: stats.score(),
+                stats,
+            })
+            .collect()
+    }
+
+    /// Health of the gateways, the fastest first.
+    pub fn gateways(&self) -> Vec<GatewayInfo> {
+        let connection_manager = &self.op_manager.ring.connection_manager;
+        let mut addrs = self.gateways.to_vec();
+        for seeded in connection_manager.seeded_gateways() {
+            if !addrs.contains(&seeded.peer.addr) {
+                addrs.push(seeded.peer.addr);
+            }
+        }
+        let peers = connection_manager.peer_locations();
+        let health = connection_manager.gateway_health();
+        let mut gateways: Vec<_> = addrs
+            .into_iter()
+            .map(|addr| {
+                let status = health.status(&addr);
+                GatewayInfo {
+                    addr,
+                    connected: peers.iter().any(|(peer, _)| peer.addr == addr),
+                    down: status.is_down(),
+                    status,
+                }
+            })
+            .collect();
+        gateways.sort_by(|a, b| {
+            let rtt = |gw: &GatewayInfo| gw.status.rtt_ms.unwrap_or(f64::INFINITY);
+            a.down.cmp(&b.down).then(rtt(a).total_cmp(&rtt(b)))
+        });
+        gateways
+    }
+
+    /// State of the forwarding of the network port by the router of the node.
+    pub fn port_mapping(&self) -> PortMappingStatus {
+        self.op_manager.ring.connection_manager.port_mapping()
+    }
+
+    pub fn network_status(&self) -> NetworkStatus {
+        let peers = self.op_manager.ring.connection_manager.peer_locations();
+        let gateway_reachable = self.config.is_gateway
+            || peers
+                .iter()
+                .any(|(peer, _)| self.gateways.contains(&peer.addr));
+        NetworkStatus {
+            peers: peers.len(),
+            min_peers_reached: peers.len() >= self.config.ws_api.ready_min_peers,
+            gateway_reachable,
+        }
+    }
+
+    /// Whether the neighborhood of the node is partitioned from the rest of the network, with the
+    /// partitions detected and healed lately.
+    pub fn partition(&self) -> PartitionStatus {
+        self.op_manager.ring.connection_manager.partition().status()
+    }
+
+    /// Drops the connection to the peer at `addr`, returns whether the node was connected to it.
+    pub async fn drop_peer(&self, addr: SocketAddr) -> Result<bool, OpError> {
+        let peer = self
+            .op_manager
+            .ring
+            .connection_manager
+            .peer_locations()
+            .into_iter()
+            .find_map(|(peer, _)| (peer.addr == addr).then_some(peer));
+        let Some(peer) = peer else {
+            return Ok(false);
+        };
+        tracing::info!(%peer, "Dropping connection on operator request");
+        self.op_manager
+            .notify_node_event(NodeEvent::DropConnection(peer))
+            .await?;
+        Ok(true)
+    }
+
+    /// Peers banned for violating the protocol.
+    pub fn bans(&self) -> Vec<Ban> {
+        self.op_manager
+            .ring
+            .connection_manager
+            .bans()
+            .bans(SystemTime::now())
+    }
+
+    /// Lifts the bans of the peer with the key fingerprint or IP address given, returns whether
+    /// it was banned.
+    pub fn lift_ban(&self, peer: &str) -> bool {
+        tracing::info!(%peer, "Lifting ban on operator request");
+        self.op_manager
+            .ring
+            .connection_manager
+            .bans()
+            .lift(peer, SystemTime::now())
+    }
+
+    pub fn peer_lists(&self) -> PeerLists {
+        self.op_manager
+            .ring
+            .connection_manager
+            .peer_policy()
+            .lists()
+    }
+
+    /// Replaces the peer policy lists, dropping the connections to the peers no longer allowed.
+    pub async fn set_peer_lists(&self, lists: PeerLists) -> anyhow::Result<()> {
+        let connection_manager = &self.op_manager.ring.connection_manager;
+        let policy = connection_manager.peer_policy();
+        policy.set(lists)?;
+        for (peer, _) in connection_manager.peer_locations() {
+            if !policy.is_allowed(&peer) {
+                tracing::info!(%peer, "Dropping connection to peer denied by the peer policy");
+                self.op_manager
+                    .notify_node_event(NodeEvent::DropConnection(peer))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn in_flight_ops(&self) -> Vec<OpInfo> {
+        self.op_manager
+            .in_flight()
+            .into_iter()
+            .map(|tx| OpInfo {
+                transaction: tx.to_string(),
+                op: tx.transaction_type().description(),
+                elapsed_ms: tx.elapsed().as_millis(),
+            })
+            .collect()
+    }
+
+    /// Contracts with a state stored by the node or which it's subscribed to.
+    pub async fn contracts(&self) -> Result<Vec<ContractInfo>, OpError> {
+        let stored = match self
+            .op_manager
+            .notify_contract_handler(ContractHandlerEvent::ListContractsQuery)
+            .await?
+        {
+            ContractHandlerEvent::ListContractsResponse { contracts } => contracts?,
+            _ => return Err(ContractError::NoEvHandlerResponse.into()),
+        };
+        Ok(contract_infos(
+            stored,
+            self.op_manager.ring.seeding_contracts(),
+        ))
+    }
+
+    pub fn pins(&self) -> Vec<PinInfo> {
+        let ring = &self.op_manager.ring;
+        ring.pins()
+            .list()
+            .into_iter()
+            .map(|key| PinInfo {
+                id: key.id().to_string(),
+                seeding: ring.is_seeding_contract(&key),
+            })
+            .collect()
+    }
+
+    /// Pins the contract, seeding it right away, returns whether it wasn't pinned already.
+    pub fn pin(&self, key: ContractKey) -> bool {
+        tracing::info!(%key, "Pinning contract on operator request");
+        let pinned = self.op_manager.ring.pins().pin(key);
+        GlobalExecutor::spawn(pins::seed_pinned(self.op_manager.clone(), key));
+        pinned
+    }
+
+    /// Unpins the contract, which the node may stop seeding from then on, returns whether it was
+    /// pinned.
+    pub fn unpin(&self, key: &ContractKey) -> bool {
+        tracing::info!(%key, "Unpinning contract on operator request");
+        self.op_manager.ring.pins().unpin(key.id())
+    }
+
+    /// Hash of the code of the delegate, `None` if it's not registered in the node.
+    pub async fn delegate_code_hash(&self, key: DelegateKey) -> Result<Option<CodeHash>, OpError> {
+        match self
+            .op_manager
+            .notify_contract_handler(ContractHandlerEvent::DelegateCodeQuery { key })
+            .await?
+        {
+            ContractHandlerEvent::DelegateCodeResponse { code_hash } => Ok(code_hash),
+            _ => Err(ContractError::NoEvHandlerResponse.into()),
+        }
+    }
+
+    /// Keypair identifying the node in the network, which attestations are signed with.
+    pub fn keypair(&self) -> &TransportKeypair {
+        self.config.transport_keypair()
+    }
+
+    /// Compacts the contract, delegate and secret stores, dropping the records of removed entries.
+    pub fn compact_storage(&self) -> std::io::Result<()> {
+        crate::wasm_runtime::compact_stores(
+            &self.config.contracts_dir(),
+            &self.config.delegates_dir(),
+            &self.config.secrets_dir(),
+        )
+    }
+}
+
+fn contract_infos(stored: Vec<StoredContract>, seeding: Vec<ContractKey>) -> Vec<ContractInfo> {
+    let mut contracts = BTreeMap::new();
+    for contract in stored {
+        let id = contract.id.to_string();
+        let last_update = contract
+            .updated_at
+            .map(|at| at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs());
+        contracts.insert(
+            id.clone(),
+            ContractInfo {
+                id,
+                state_size: Some(contract.state_size),
+                last_update,
+                subscribed: false,
+                local_subscribers: contract.subscribers,
+            },
+        );
+    }
+    for key in seeding {
+        let id = key.id().to_string();
+        contracts
+            .entry(id.clone())
+            .or_insert_with(|| ContractInfo {
+                id,
+                state_size: None,
+                last_update: None,
+                subscribed: false,
+                local_subscribers: 0,
+            })
+            .subscribed = true;
+    }
+    contracts.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use freenet_stdlib::prelude::ContractInstanceId;
+
+    use super::*;
+
+    #[test]
+    fn merge_stored_and_subscribed_contracts() {
+        let stored = ContractInstanceId::new([1; 32]);
+        let seeded = ContractInstanceId::new([2; 32]);
+        let contracts = contract_infos(
+            vec![StoredContract {
+                id: stored,
+                state_size: 42,
+                updated_at: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(10)),
+                subscribers: 2,
+            }],
+            vec![ContractKey::from(stored), ContractKey::from(seeded)],
+        );
+        assert_eq!(contracts.len(), 2);
+        let info = |id: ContractInstanceId| {
+            contracts
+                .iter()
+                .find(|info| info.id == id.to_string())
+                .unwrap()
+        };
+        let stored = info(stored);
+        assert_eq!(stored.state_size, Some(42));
+        assert_eq!(stored.last_update, Some(10));
+        assert!(stored.subscribed);
+        assert_eq!(stored.local_subscribers, 2);
+        let seeded = info(seeded);
+        assert_eq!(seeded.state_size, None);
+        assert!(seeded.subscribed);
+    }
+}
@@ -0,0 +1,306 @@
+//! Detection of peers violating the protocol and the escalating penalties applied to them, up to
+//! temporary bans.
+//!
+//! Violations are weighed by how unlikely they are from honest peers: malformed messages may come
+//! from peers running a different version of the protocol, while packets failing authentication
+//! or messages claiming locations out of the ring can't. The weight of the violations of a peer
+//! decays over time; past [`DISCONNECT_WEIGHT`] the peer is disconnected and past [`BAN_WEIGHT`]
+//! it is banned, for [`FIRST_BAN`] the first time and twice as long every time after, up to
+//! [`MAX_BAN`]. Bans cover both the key of the peer and its IP address, are saved to the data
+//! directory so they survive restarts, and are listed and lifted through the admin API.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    message::{MessageStats, NetMessage},
+    ring::Location,
+};
+
+use super::PeerId;
+
+const BANS_FILE: &str = "bans.json";
+
+/// Time for the weight of the violations of a peer to halve.
+const HALF_LIFE: Duration = Duration::from_secs(30 * 60);
+
+/// Weight of the violations of a peer past which it is disconnected.
+const DISCONNECT_WEIGHT: f64 = 4.0;
+
+/// Weight of the violations of a peer past which it is banned.
+const BAN_WEIGHT: f64 = 8.0;
+
+const FIRST_BAN: Duration = Duration::from_secs(10 * 60);
+
+const MAX_BAN: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Time after a ban expires its peer is forgiven, the next ban starting over from [`FIRST_BAN`].
+const FORGIVE_AFTER: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// A breach of the protocol by a peer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Violation {
+    /// A message from the peer couldn't be decoded.
+    MalformedMessage,
+    /// Packets from the peer kept failing authentication.
+    InvalidSignature,
+    /// A message from the peer claimed a route through locations out of the ring.
+    ImpossibleRoute,
+}
+
+impl Violation {
+    /// The violation committed by sending `msg`, if any.
+    pub fn of_message(msg: &NetMessage) -> Option<Self> {
+        let on_ring = |location: Location| (0.0..=1.0).contains(&location.as_f64());
+        let target = msg.target().and_then(|target| target.location);
+        if target
+            .into_iter()
+            .chain(msg.requested_location())
+            .all(on_ring)
+        {
+            None
+        } else {
+            Some(Self::ImpossibleRoute)
+        }
+    }
+
+    fn weight(&self) -> f64 {
+        match self {
+            Self::MalformedMessage => 1.0,
+            Self::ImpossibleRoute => 2.0,
+            Self::InvalidSignature => 3.0,
+        }
+    }
+}
+
+/// What to do with a peer after a violation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Penalty {
+    /// Lower its reputation only.
+    Warn,
+    Disconnect,
+    /// Disconnect it and refuse its connections for the time given.
+    Ban(Duration),
+}
+
+/// A peer banned, or banned before and not forgiven yet.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct Ban {
+    /// Fingerprint of the key of the peer.
+    pub fingerprint: String,
+    pub ip: IpAddr,
+    /// Unix timestamp, in seconds, the ban is lifted at.
+    pub until: u64,
+    /// Times the peer was banned, doubling the duration of its bans.
+    pub count: u32,
+    pub violation: Violation,
+}
+
+#[derive(Default)]
+struct State {
+    /// Where the bans are saved to, kept in memory only without.
+    path: Option<PathBuf>,
+    /// Weight of the violations of each peer and when it was last updated.
+    weights: HashMap<PeerId, (f64, SystemTime)>,
+    bans: Vec<Ban>,
+}
+
+impl State {
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let saved = serde_json::to_vec_pretty(&self.bans)
+            .map_err(anyhow::Error::from)
+            .and_then(|bans| Ok(std::fs::write(path, bans)?));
+        if let Err(error) = saved {
+            tracing::warn!(%error, "Failed saving peer bans");
+        }
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Violations of the peers and their bans, shared by the components detecting and enforcing
+/// them.
+#[derive(Clone, Default)]
+pub(crate) struct PeerBans(Arc<RwLock<State>>);
+
+impl PeerBans {
+    /// Loads the bans saved in the data directory of the node.
+    pub fn load(config: &Config) -> Self {
+        let path = config.db_dir().join(BANS_FILE);
+        let bans = match std::fs::read(&path) {
+            Ok(bans) => serde_json::from_slice(&bans).unwrap_or_else(|error| {
+                tracing::warn!(%error, "Failed loading peer bans");
+                vec![]
+            }),
+            Err(_) => vec![],
+        };
+        Self(Arc::new(RwLock::new(State {
+            path: Some(path),
+            weights: HashMap::new(),
+            bans,
+        })))
+    }
+
+    /// Records a violation of the peer, returning the penalty for it.
+    pub fn record(&self, peer: &PeerId, violation: Violation, now: SystemTime) -> Penalty {
+        let mut state = self.0.write();
+        let (weight, updated) = state.weights.entry(peer.clone()).or_insert((0.0, now));
+        let elapsed = now.duration_since(*updated).unwrap_or_default();
+        *weight = *weight * 0.5f64.powf(elapsed.as_secs_f64() / HALF_LIFE.as_secs_f64())
+            + violation.weight();
+        *updated = now;
+        let weight = *weight;
+        if weight < DISCONNECT_WEIGHT {
+            return Penalty::Warn;
+        }
+        if weight < BAN_WEIGHT {
+            return Penalty::Disconnect;
+        }
+        state.weights.remove(peer);
+
+        let fingerprint = peer.pub_key.fingerprint();
+        let now_secs = unix_secs(now);
+        state
+            .bans
+            .retain(|ban| ban.until + FORGIVE_AFTER.as_secs() > now_secs);
+        let count = state
+            .bans
+            .iter()
+            .filter(|ban| ban.fingerprint == fingerprint || ban.ip == peer.addr.ip())
+            .map(|ban| ban.count)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        let duration = FIRST_BAN
+            .saturating_mul(1 << (count - 1).min(16))
+            .min(MAX_BAN);
+        state
+            .bans
+            .retain(|ban| ban.fingerprint != fingerprint && ban.ip != peer.addr.ip());
+        state.bans.push(Ban {
+            fingerprint,
+            ip: peer.addr.ip(),
+            until: now_secs + duration.as_secs(),
+            count,
+            violation,
+        });
+        state.save();
+        Penalty::Ban(duration)
+    }
+
+    pub fn is_banned(&self, peer: &PeerId, now: SystemTime) -> bool {
+        let state = self.0.read();
+        if state.bans.is_empty() {
+            return false;
+        }
+        let now = unix_secs(now);
+        let fingerprint = peer.pub_key.fingerprint();
+        state.bans.iter().any(|ban| {
+            ban.until > now && (ban.fingerprint == fingerprint || ban.ip == peer.addr.ip())
+        })
+    }
+
+    /// The bans in force.
+    pub fn bans(&self, now: SystemTime) -> Vec<Ban> {
+        let now = unix_secs(now);
+        self.0
+            .read()
+            .bans
+            .iter()
+            .filter(|ban| ban.until > now)
+            .cloned()
+            .collect()
+    }
+
+    /// Lifts the bans of the peer with the key fingerprint or IP address given, returning whether
+    /// any was in force. The peer is forgiven, its next ban starting over.
+    pub fn lift(&self, peer: &str, now: SystemTime) -> bool {
+        let now = unix_secs(now);
+        let mut state = self.0.write();
+        let before = state.bans.len();
+        let mut lifted = false;
+        state.bans.retain(|ban| {
+            let matches = ban.fingerprint == peer || ban.ip.to_string() == peer;
+            lifted |= matches && ban.until > now;
+            !matches
+        });
+        if state.bans.len() != before {
+            state.save();
+        }
+        lifted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ring::PeerKeyLocation;
+
+    use super::*;
+
+    #[test]
+    fn escalates_penalties() {
+        let bans = PeerBans::default();
+        let hostile = PeerKeyLocation::random().peer;
+        let other = PeerKeyLocation::random().peer;
+        let start = UNIX_EPOCH + Duration::from_secs(1_900_000_000);
+
+        // violations long apart are forgiven
+        for hour in 0..10 {
+            let at = start + Duration::from_secs(hour * 60 * 60);
+            assert_eq!(
+                bans.record(&hostile, Violation::MalformedMessage, at),
+                Penalty::Warn
+            );
+        }
+        let now = start + Duration::from_secs(10 * 60 * 60);
+        assert_eq!(
+            bans.record(&hostile, Violation::InvalidSignature, now),
+            Penalty::Disconnect
+        );
+        assert_eq!(
+            bans.record(&hostile, Violation::ImpossibleRoute, now),
+            Penalty::Disconnect
+        );
+        assert_eq!(
+            bans.record(&hostile, Violation::InvalidSignature, now),
+            Penalty::Ban(FIRST_BAN)
+        );
+        assert!(bans.is_banned(&hostile, now));
+        assert!(!bans.is_banned(&other, now));
+        assert!(!bans.is_banned(&hostile, now + FIRST_BAN));
+
+        // banned for longer the next time
+        let later = now + FIRST_BAN;
+        for _ in 0..2 {
+            bans.record(&hostile, Violation::InvalidSignature, later);
+        }
+        assert_eq!(
+            bans.record(&hostile, Violation::InvalidSignature, later),
+            Penalty::Ban(FIRST_BAN * 2)
+        );
+        assert_eq!(bans.bans(later).len(), 1);
+        assert_eq!(bans.bans(later)[0].count, 2);
+
+        assert!(bans.lift(&hostile.pub_key.fingerprint(), later));
+        assert!(!bans.is_banned(&hostile, later));
+        assert!(!bans.lift(&hostile.addr.ip().to_string(), later));
+    }
+}
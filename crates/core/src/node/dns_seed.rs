@@ -0,0 +1,192 @@
+//! Discovery of gateways from the DNS records of a seed name, so nodes find the current gateways
+//! of the network even when those they were configured with are gone.
+//!
+//! Gateways are listed either by TXT records at the seed name of the form
+//! `v=fngw1 addr=<host:port> key=<public key>`, or by SRV records at `_freenet._udp.<seed>` whose
+//! targets have a TXT record `v=fngw1 key=<public key>`. Keys are the base64 encoding of their
+//! DER SubjectPublicKeyInfo, the body of their PEM file. Records split in several strings are
+//! joined back.
+
+use std::{collections::HashSet, time::Duration};
+
+use base64::Engine;
+use hickory_resolver::TokioAsyncResolver;
+use rsa::pkcs8::DecodePublicKey;
+
+use crate::{
+    config::Address,
+    ring::{ConnectionManager, Location, PeerKeyLocation},
+    transport::TransportPublicKey,
+};
+
+use super::{NodeConfig, PeerId};
+
+const RECORD_VERSION: &str = "v=fngw1";
+
+/// Interval the seed is resolved again at.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// A gateway listed by a TXT record, its address being the target of a SRV record if missing.
+#[derive(Debug, PartialEq)]
+pub(super) struct GatewayRecord {
+    pub addr: Option<String>,
+    pub key: TransportPublicKey,
+}
+
+/// The TXT record listing the gateway at `addr` with the given key.
+pub(super) fn format_record(addr: Option<&str>, key: &TransportPublicKey) -> String {
+    let key = base64::engine::general_purpose::STANDARD.encode(key.to_der());
+    match addr {
+        Some(addr) => format!("{RECORD_VERSION} addr={addr} key={key}"),
+        None => format!("{RECORD_VERSION} key={key}"),
+    }
+}
+
+/// Parses a TXT record, returning `None` for records not listing gateways.
+pub(super) fn parse_record(record: &str) -> Option<anyhow::Result<GatewayRecord>> {
+    let mut fields = record.split_whitespace();
+    if fields.next() != Some(RECORD_VERSION) {
+        return None;
+    }
+    let mut addr = None;
+    let mut key = None;
+    for field in fields {
+        match field.split_once('=') {
+            Some(("addr", value)) => addr = Some(value.to_owned()),
+            Some(("key", value)) => key = Some(value),
+            _ => {}
+        }
+    }
+    Some(
+        key.ok_or_else(|| anyhow::anyhow!("missing key"))
+            .and_then(decode_key)
+            .map(|key| GatewayRecord { addr, key }),
+    )
+}
+
+/// Decodes the key of a gateway, the base64 encoding of its DER SubjectPublicKeyInfo.
+pub(super) fn decode_key(key: &str) -> anyhow::Result<TransportPublicKey> {
+    let der = base64::engine::general_purpose::STANDARD.decode(key)?;
+    Ok(rsa::RsaPublicKey::from_public_key_der(&der)?.into())
+}
+
+async fn txt_records(resolver: &TokioAsyncResolver, name: &str) -> Vec<GatewayRecord> {
+    let records = match resolver.txt_lookup(name).await {
+        Ok(records) => records,
+        Err(error) => {
+            tracing::debug!(%name, %error, "No TXT records");
+            return vec![];
+        }
+    };
+    records
+        .iter()
+        .filter_map(|txt| {
+            let record: String = txt
+                .txt_data()
+                .iter()
+                .map(|data| String::from_utf8_lossy(data))
+                .collect();
+            match parse_record(&record)? {
+                Ok(record) => Some(record),
+                Err(error) => {
+                    tracing::warn!(%name, %record, %error, "Invalid gateway record");
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Resolves the gateways listed at the `seed` name.
+pub(crate) async fn resolve(seed: &str) -> anyhow::Result<Vec<PeerKeyLocation>> {
+    let resolver = super::system_resolver()?;
+    // only issue one query with .
+    let seed = seed.trim_end_matches('.');
+    let mut records: Vec<_> = txt_records(&resolver, &format!("{seed}."))
+        .await
+        .into_iter()
+        .filter(|record| record.addr.is_some())
+        .collect();
+    if let Ok(services) = resolver.srv_lookup(format!("_freenet._udp.{seed}.")).await {
+        for srv in services.iter() {
+            let target = srv.target().to_utf8();
+            let host = target.trim_end_matches('.');
+            records.extend(
+                txt_records(&resolver, &target)
+                    .await
+                    .into_iter()
+                    .map(|record| GatewayRecord {
+                        addr: Some(format!("{host}:{}", srv.port())),
+                        ..record
+                    }),
+            );
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut gateways = vec![];
+    for GatewayRecord { addr, key } in records {
+        let Some(addr) = addr else { continue };
+        let addr = match NodeConfig::parse_socket_addr(&Address::Hostname(addr.clone())).await {
+            Ok(addr) => addr,
+            Err(error) => {
+                tracing::warn!(%addr, %error, "Failed resolving seeded gateway");
+                continue;
+            }
+        };
+        if seen.insert(addr) {
+            gateways.push(PeerKeyLocation {
+                peer: PeerId::new(addr, key),
+                location: Some(Location::from_address(&addr)),
+            });
+        }
+    }
+    if gateways.is_empty() {
+        anyhow::bail!("no gateway listed at {seed}");
+    }
+    Ok(gateways)
+}
+
+/// Resolves the seed periodically, making the gateways listed available to join through.
+pub(crate) async fn refresh_gateways(seed: String, connection_manager: ConnectionManager) {
+    let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+    loop {
+        interval.tick().await;
+        match resolve(&seed).await {
+            Ok(gateways) => {
+                tracing::debug!(%seed, count = gateways.len(), "Refreshed seeded gateways");
+                connection_manager.set_seeded_gateways(&seed, gateways);
+            }
+            Err(error) => tracing::warn!(%seed, %error, "Failed resolving DNS seed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::transport::TransportKeypair;
+
+    use super::*;
+
+    #[test]
+    fn gateway_records() -> anyhow::Result<()> {
+        let key = TransportKeypair::new().public().clone();
+        let encoded = base64::engine::general_purpose::STANDARD.encode(key.to_der());
+
+        let record =
+            parse_record(&format!("v=fngw1 addr=gw.example.org:31337 key={encoded}")).unwrap()?;
+        assert_eq!(record.addr.as_deref(), Some("gw.example.org:31337"));
+        assert_eq!(record.key, key);
+
+        let record = parse_record(&format!("v=fngw1 key={encoded}")).unwrap()?;
+        assert_eq!(record.addr, None);
+        assert_eq!(format_record(None, &key), format!("v=fngw1 key={encoded}"));
+
+        assert!(parse_record("v=spf1 -all").is_none());
+        assert!(parse_record("v=fngw1 addr=gw.example.org:31337")
+            .unwrap()
+            .is_err());
+        assert!(parse_record("v=fngw1 key=bm90IGEga2V5").unwrap().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+//! Health of the gateways of the node, checked periodically, so joins go through the fastest
+//! gateways alive and the node fails over to another gateway when those it is connected through
+//! stop answering.
+//!
+//! Gateways are checked with the binding requests of the NAT discovery, timing their answers.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use parking_lot::RwLock;
+use serde::Serialize;
+
+use crate::{
+    ring::{ConnectionManager, PeerKeyLocation},
+    transport::OutboundConnectionHandler,
+};
+
+/// Interval the gateways are checked at.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Consecutive failed checks after which a gateway is considered down.
+const DOWN_AFTER_FAILURES: u32 = 3;
+
+/// Weight of the last check in the smoothed round-trip time.
+const RTT_WEIGHT: f64 = 0.25;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct GatewayStatus {
+    /// Smoothed round-trip time of the checks in milliseconds, `None` until one succeeds.
+    pub rtt_ms: Option<f64>,
+    pub consecutive_failures: u32,
+}
+
+impl GatewayStatus {
+    pub fn is_down(&self) -> bool {
+        self.consecutive_failures >= DOWN_AFTER_FAILURES
+    }
+}
+
+/// Status of the gateways checked, by address.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct GatewayHealth(Arc<RwLock<HashMap<SocketAddr, GatewayStatus>>>);
+
+impl GatewayHealth {
+    pub fn record_success(&self, gateway: SocketAddr, rtt: Duration) {
+        let mut gateways = self.0.write();
+        let status = gateways.entry(gateway).or_default();
+        let rtt = rtt.as_secs_f64() * 1000.0;
+        status.rtt_ms = Some(match status.rtt_ms {
+            Some(smoothed) => smoothed + RTT_WEIGHT * (rtt - smoothed),
+            None => rtt,
+        });
+        status.consecutive_failures = 0;
+    }
+
+    pub fn record_failure(&self, gateway: SocketAddr) {
+        self.0
+            .write()
+            .entry(gateway)
+            .or_default()
+            .consecutive_failures += 1;
+    }
+
+    pub fn is_down(&self, gateway: &SocketAddr) -> bool {
+        self.0
+            .read()
+            .get(gateway)
+            .is_some_and(GatewayStatus::is_down)
+    }
+
+    pub fn status(&self, gateway: &SocketAddr) -> GatewayStatus {
+        self.0.read().get(gateway).copied().unwrap_or_default()
+    }
+
+    /// Sorts the gateways from the most to the least preferable: those up by increasing
+    /// round-trip time, those not checked yet and those down, keeping the order of the rest.
+    pub fn rank(&self, gateways: &mut [&PeerKeyLocation]) {
+        let statuses = self.0.read();
+        let key = |gateway: &PeerKeyLocation| {
+            let status = statuses
+                .get(&gateway.peer.addr)
+                .copied()
+                .unwrap_or_default();
+            match status.rtt_ms {
+                _ if status.is_down() => (2, 0.0),
+                Some(rtt) => (0, rtt),
+                None => (1, 0.0),
+            }
+        };
+        gateways.sort_by(|a, b| {
+            let (a, b) = (key(a), key(b));
+            a.0.cmp(&b.0).then(a.1.total_cmp(&b.1))
+        });
+    }
+}
+
+/// Checks the `gateways` configured and those seeded periodically.
+pub(crate) async fn check_gateways(
+    outbound_conn_handler: OutboundConnectionHandler,
+    gateways: Vec<PeerKeyLocation>,
+    connection_manager: ConnectionManager,
+) {
+    let health = connection_manager.gateway_health().clone();
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let mut addrs: Vec<_> = gateways.iter().map(|gw| gw.peer.addr).collect();
+        for seeded in connection_manager.seeded_gateways() {
+            if !addrs.contains(&seeded.peer.addr) {
+                addrs.push(seeded.peer.addr);
+            }
+        }
+        let checks = addrs.into_iter().map(|gateway| {
+            let outbound_conn_handler = &outbound_conn_handler;
+            async move {
+                let start = Instant::now();
+                let result = outbound_conn_handler.reflexive_address(gateway).await;
+                (gateway, result.map(|_| start.elapsed()))
+            }
+        });
+        for (gateway, result) in futures::future::join_all(checks).await {
+            match result {
+                Ok(rtt) => health.record_success(gateway, rtt),
+                Err(error) => {
+                    tracing::debug!(%gateway, %error, "Gateway health check failed");
+                    health.record_failure(gateway);
+                    if health.is_down(&gateway) {
+                        tracing::warn!(%gateway, "Gateway is down");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rank_gateways() {
+        let fast = PeerKeyLocation::random();
+        let slow = PeerKeyLocation::random();
+        let unchecked = PeerKeyLocation::random();
+        let down = PeerKeyLocation::random();
+
+        let health = GatewayHealth::default();
+        health.record_success(fast.peer.addr, Duration::from_millis(20));
+        health.record_success(slow.peer.addr, Duration::from_millis(10));
+        health.record_success(slow.peer.addr, Duration::from_millis(200));
+        health.record_success(down.peer.addr, Duration::from_millis(1));
+        for _ in 0..DOWN_AFTER_FAILURES {
+            assert!(!health.is_down(&down.peer.addr));
+            health.record_failure(down.peer.addr);
+        }
+        assert!(health.is_down(&down.peer.addr));
+        assert_eq!(health.status(&slow.peer.addr).rtt_ms, Some(57.5));
+
+        let mut gateways = vec![&down, &unchecked, &slow, &fast];
+        health.rank(&mut gateways);
+        assert_eq!(gateways, vec![&fast, &slow, &unchecked, &down]);
+
+        health.record_success(down.peer.addr, Duration::from_millis(1));
+        assert!(!health.is_down(&down.peer.addr));
+    }
+}
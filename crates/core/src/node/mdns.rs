@@ -0,0 +1,227 @@
+//! Discovery of the peers of the local network with multicast DNS, so nodes on the same LAN
+//! connect with each other directly, even without access to the internet.
+//!
+//! Nodes answer the queries for the `_freenet._udp.local` service with the records of their
+//! instance, named after the fingerprint of their key: a PTR record from the service to the
+//! instance, a SRV record with their network port and a TXT record with their key, as the
+//! records of [DNS seeds](super::dns_seed). They announce themselves when starting and query the
+//! service periodically, joining the network through the peers answering, at the address the
+//! answers are from.
+
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use hickory_resolver::proto::{
+    op::{Message, MessageType, Query},
+    rr::{
+        rdata::{PTR, SRV, TXT},
+        Name, RData, Record, RecordType,
+    },
+    serialize::binary::BinEncodable,
+};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+
+use crate::{
+    operations::{connect, OpError},
+    ring::{Location, PeerKeyLocation},
+    transport::TransportPublicKey,
+};
+
+use super::{dns_seed, ConnectionError, OpManager, PeerId};
+
+const MDNS_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+const SERVICE: &str = "_freenet._udp.local.";
+
+/// Seconds the records announced are valid for.
+const TTL: u32 = 120;
+
+/// Interval the service is queried at.
+const QUERY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Maximum length of each of the strings of a TXT record.
+const TXT_STRING_LEN: usize = 255;
+
+fn service_name() -> Name {
+    Name::from_ascii(SERVICE).expect("valid service name")
+}
+
+fn bind() -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    // shared with the other mDNS responders of the host, e.g. other nodes
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, MDNS_PORT)).into())?;
+    socket.join_multicast_v4(&MDNS_ADDR, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_loop_v4(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+fn query() -> Message {
+    let mut message = Message::new();
+    message
+        .set_message_type(MessageType::Query)
+        .add_query(Query::query(service_name(), RecordType::PTR));
+    message
+}
+
+/// Response announcing the node listening at `port` with the given key.
+fn announcement(port: u16, key: &TransportPublicKey) -> anyhow::Result<Message> {
+    let service = service_name();
+    let fingerprint = key.fingerprint();
+    let instance = Name::from_ascii(format!("{fingerprint}.{SERVICE}"))?;
+    let target = Name::from_ascii(format!("{fingerprint}.local."))?;
+    let txt = dns_seed::format_record(None, key)
+        .as_bytes()
+        .chunks(TXT_STRING_LEN)
+        .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+        .collect();
+
+    let mut message = Message::new();
+    message
+        .set_message_type(MessageType::Response)
+        .set_authoritative(true)
+        .add_answer(Record::from_rdata(
+            service,
+            TTL,
+            RData::PTR(PTR(instance.clone())),
+        ))
+        .add_answer(Record::from_rdata(
+            instance.clone(),
+            TTL,
+            RData::SRV(SRV::new(0, 0, port, target)),
+        ))
+        .add_answer(Record::from_rdata(instance, TTL, RData::TXT(TXT::new(txt))));
+    Ok(message)
+}
+
+fn is_service_query(message: &Message) -> bool {
+    let service = service_name();
+    message.message_type() == MessageType::Query
+        && message.queries().iter().any(|query| {
+            matches!(query.query_type(), RecordType::PTR | RecordType::ANY)
+                && *query.name() == service
+        })
+}
+
+/// The port and key of the node announced by the message, if any.
+fn parse_announcement(message: &Message) -> Option<(u16, TransportPublicKey)> {
+    if message.message_type() != MessageType::Response {
+        return None;
+    }
+    let service = service_name();
+    let records = || {
+        message
+            .answers()
+            .iter()
+            .chain(message.additionals())
+            .filter(|record| service.zone_of(record.name()))
+    };
+    let port = records().find_map(|record| match record.data()? {
+        RData::SRV(srv) => Some(srv.port()),
+        _ => None,
+    })?;
+    let key = records().find_map(|record| match record.data()? {
+        RData::TXT(txt) => {
+            let record: String = txt
+                .txt_data()
+                .iter()
+                .map(|data| String::from_utf8_lossy(data))
+                .collect();
+            dns_seed::parse_record(&record)?.ok()
+        }
+        _ => None,
+    })?;
+    Some((port, key.key))
+}
+
+/// Announces the node listening at `port` to the local network and joins the network through
+/// the peers discovered in it.
+pub(crate) async fn discover_peers(port: u16, op_manager: Arc<OpManager>) {
+    if let Err(error) = run(port, op_manager).await {
+        tracing::warn!(%error, "Stopped discovering LAN peers with mDNS");
+    }
+}
+
+async fn run(port: u16, op_manager: Arc<OpManager>) -> anyhow::Result<()> {
+    let socket = bind()?;
+    let group = SocketAddr::from((MDNS_ADDR, MDNS_PORT));
+    let own_key = op_manager.ring.connection_manager.pub_key.as_ref().clone();
+    let announcement = announcement(port, &own_key)?.to_bytes()?;
+    let query = query().to_bytes()?;
+    socket.send_to(&announcement, group).await?;
+
+    let mut interval = tokio::time::interval(QUERY_INTERVAL);
+    let mut buf = [0; 9000];
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                socket.send_to(&query, group).await?;
+            }
+            received = socket.recv_from(&mut buf) => {
+                let (size, from) = received?;
+                let Ok(message) = Message::from_vec(&buf[..size]) else {
+                    continue;
+                };
+                if is_service_query(&message) {
+                    socket.send_to(&announcement, group).await?;
+                    continue;
+                }
+                let Some((port, key)) = parse_announcement(&message) else {
+                    continue;
+                };
+                if key == own_key {
+                    continue;
+                }
+                let addr = SocketAddr::new(from.ip(), port);
+                let peer = PeerKeyLocation {
+                    peer: PeerId::new(addr, key),
+                    location: Some(Location::from_address(&addr)),
+                };
+                if op_manager.ring.is_not_connected(std::iter::once(&peer)).next().is_none() {
+                    continue;
+                }
+                tracing::debug!(%peer, "Discovered LAN peer");
+                let op_manager = op_manager.clone();
+                tokio::spawn(async move {
+                    if let Err(error) = connect::join_ring_request(None, &peer, &op_manager).await {
+                        if !matches!(
+                            error,
+                            OpError::ConnError(ConnectionError::UnwantedConnection)
+                        ) {
+                            tracing::debug!(%peer, %error, "Failed connecting to LAN peer");
+                        }
+                    }
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::transport::TransportKeypair;
+
+    use super::*;
+
+    #[test]
+    fn announcements() -> anyhow::Result<()> {
+        let key = TransportKeypair::new().public().clone();
+        let bytes = announcement(31337, &key)?.to_bytes()?;
+        let message = Message::from_vec(&bytes)?;
+        assert!(!is_service_query(&message));
+        assert_eq!(parse_announcement(&message), Some((31337, key)));
+
+        let message = Message::from_vec(&query().to_bytes()?)?;
+        assert!(is_service_query(&message));
+        assert_eq!(parse_announcement(&message), None);
+        Ok(())
+    }
+}
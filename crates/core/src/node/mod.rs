@@ -33,7 +33,7 @@ use std::{
 };
 use std::{collections::HashSet, convert::Infallible};
 
-use self::p2p_impl::NodeP2P;
+use self::{bans::PeerBans, p2p_impl::NodeP2P, peer_policy::PeerPolicy, pins::PinnedContracts};
 use crate::{
     client_events::{BoxedClient, ClientEventsProxy, ClientId, OpenRequest},
     config::{Address, GatewayConfig, WebsocketApiConfig},
@@ -42,7 +42,7 @@ use crate::{
         NetworkContractHandler, WaitingTransaction,
     },
     local_node::Executor,
-    message::{InnerMessage, NetMessage, Transaction, TransactionType},
+    message::{InnerMessage, NetMessage, Priority, Transaction, TransactionType},
     operations::{
         connect::{self, ConnectOp},
         get, put, subscribe, update, OpEnum, OpError, OpOutcome,
@@ -61,15 +61,28 @@ use serde::{Deserialize, Serialize};
 use tracing::Instrument;
 
 use crate::operations::handle_op_request;
-pub(crate) use network_bridge::{ConnectionError, EventLoopNotificationsSender, NetworkBridge};
+pub(crate) use network_bridge::{
+    ConnectionError, EventLoopNotificationsSender, InboundLimits, NetworkBridge, OpLimits,
+};
 
 use crate::topology::rate::Rate;
 use crate::transport::{TransportKeypair, TransportPublicKey};
 pub(crate) use op_state_manager::{OpManager, OpNotAvailable};
 
+#[cfg_attr(not(feature = "websocket"), allow(dead_code))]
+pub(crate) mod admin;
+pub(crate) mod bans;
+mod dns_seed;
+pub(crate) mod gateway_health;
+mod mdns;
+pub(crate) mod neighborhood;
 mod network_bridge;
 mod op_state_manager;
 mod p2p_impl;
+mod peer_cache;
+pub(crate) mod peer_policy;
+pub(crate) mod pins;
+mod seed_list;
 pub(crate) mod testing_impl;
 
 pub struct Node(NodeP2P);
@@ -126,6 +139,10 @@ pub struct NodeConfig {
     pub(crate) max_upstream_bandwidth: Option<Rate>,
     pub(crate) max_downstream_bandwidth: Option<Rate>,
     pub(crate) blocked_addresses: Option<HashSet<SocketAddr>>,
+    pub(crate) peer_policy: PeerPolicy,
+    pub(crate) bans: PeerBans,
+    /// Contracts pinned by the operator, always seeded by the node.
+    pub(crate) pins: PinnedContracts,
 }
 
 impl NodeConfig {
@@ -154,6 +171,40 @@ impl NodeConfig {
                 .unwrap_or_else(|| Location::from_address(&address));
             gateways.push(InitPeerNode::new(peer_id, location));
         }
+        if let Some(seed) = &config.network_api.dns_seed {
+            match dns_seed::resolve(seed).await {
+                Ok(seeded) => {
+                    tracing::info!(%seed, count = seeded.len(), "Resolved gateways from DNS seed");
+                    for gw in seeded {
+                        if gateways
+                            .iter()
+                            .all(|known| known.peer_id.addr != gw.peer.addr)
+                        {
+                            let location = Location::from_address(&gw.peer.addr);
+                            gateways.push(InitPeerNode::new(gw.peer, location));
+                        }
+                    }
+                }
+                Err(error) => tracing::warn!(%seed, %error, "Failed resolving DNS seed"),
+            }
+        }
+        if let Some(mut seed_list) = seed_list::SeedList::from_config(&config)? {
+            match seed_list.load().await {
+                Ok(listed) => {
+                    tracing::info!(count = listed.len(), "Loaded gateways from seed list");
+                    for gw in listed {
+                        if gateways
+                            .iter()
+                            .all(|known| known.peer_id.addr != gw.peer.addr)
+                        {
+                            let location = Location::from_address(&gw.peer.addr);
+                            gateways.push(InitPeerNode::new(gw.peer, location));
+                        }
+                    }
+                }
+                Err(error) => tracing::warn!(%error, "Failed loading seed list"),
+            }
+        }
         tracing::info!(
             "Node will be listening at {}:{} internal address",
             config.network_api.address,
@@ -174,25 +225,36 @@ impl NodeConfig {
             config: Arc::new(config.clone()),
             max_hops_to_live: None,
             rnd_if_htl_above: None,
-            max_number_conn: None,
-            min_number_conn: None,
+            max_number_conn: config.network_api.max_connections,
+            min_number_conn: config.network_api.min_connections,
             max_upstream_bandwidth: None,
             max_downstream_bandwidth: None,
             blocked_addresses: config.network_api.blocked_addresses.clone(),
+            peer_policy: PeerPolicy::new(config.network_api.peer_lists())?,
+            bans: PeerBans::load(&config),
+            pins: PinnedContracts::load(&config),
         })
     }
 
     pub(crate) async fn parse_socket_addr(address: &Address) -> anyhow::Result<SocketAddr> {
         let (hostname, port) = match address {
             crate::config::Address::Hostname(hostname) => {
+                // IPv6 addresses without port, which would be mistaken for one
+                let literal = hostname.trim_start_matches('[').trim_end_matches(']');
+                if let Ok(ip) = literal.parse::<IpAddr>() {
+                    return Ok(SocketAddr::new(
+                        ip,
+                        crate::config::default_network_api_port(),
+                    ));
+                }
                 match hostname.rsplit_once(':') {
                     None => {
                         // no port found, use default
                         let hostname_with_port =
                             format!("{}:{}", hostname, crate::config::default_network_api_port());
 
-                        if let Ok(mut addrs) = hostname_with_port.to_socket_addrs() {
-                            if let Some(addr) = addrs.next() {
+                        if let Ok(addrs) = hostname_with_port.to_socket_addrs() {
+                            if let Some(addr) = select_address(addrs) {
                                 return Ok(addr);
                             }
                         }
@@ -201,8 +263,8 @@ impl NodeConfig {
                     }
                     Some((host, port)) => match port.parse::<u16>() {
                         Ok(port) => {
-                            if let Ok(mut addrs) = hostname.to_socket_addrs() {
-                                if let Some(addr) = addrs.next() {
+                            if let Ok(addrs) = hostname.to_socket_addrs() {
+                                if let Some(addr) = select_address(addrs) {
                                     return Ok(addr);
                                 }
                             }
@@ -216,14 +278,7 @@ impl NodeConfig {
             Address::HostAddress(addr) => return Ok(*addr),
         };
 
-        let (conf, opts) = hickory_resolver::system_conf::read_system_conf()?;
-        let resolver = hickory_resolver::TokioAsyncResolver::new(
-            conf,
-            opts,
-            hickory_resolver::name_server::GenericConnector::new(
-                hickory_resolver::name_server::TokioRuntimeProvider::new(),
-            ),
-        );
+        let resolver = system_resolver()?;
 
         // only issue one query with .
         let hostname = if hostname.ends_with('.') {
@@ -233,13 +288,9 @@ impl NodeConfig {
         };
 
         let ips = resolver.lookup_ip(hostname.as_ref()).await?;
-        match ips.into_iter().next() {
-            Some(ip) => Ok(SocketAddr::new(
-                ip,
-                port.unwrap_or_else(crate::config::default_network_api_port),
-            )),
-            None => Err(anyhow::anyhow!("Fail to resolve IP address of {hostname}")),
-        }
+        let port = port.unwrap_or_else(crate::config::default_network_api_port);
+        select_address(ips.into_iter().map(|ip| SocketAddr::new(ip, port)))
+            .ok_or_else(|| anyhow::anyhow!("Fail to resolve IP address of {hostname}"))
     }
 
     pub fn config(&self) -> &Config {
@@ -342,7 +393,8 @@ impl NodeConfig {
             })
             .collect();
 
-        if !self.is_gateway && gateways.is_empty() {
+        // peers of the local network are discovered later on
+        if !self.is_gateway && gateways.is_empty() && !self.config.network_api.mdns {
             anyhow::bail!(
             "At least one remote gateway is required to join an existing network for non-gateway nodes."
         )
@@ -352,6 +404,28 @@ impl NodeConfig {
     }
 }
 
+fn system_resolver() -> anyhow::Result<hickory_resolver::TokioAsyncResolver> {
+    let (conf, opts) = hickory_resolver::system_conf::read_system_conf()?;
+    Ok(hickory_resolver::TokioAsyncResolver::new(
+        conf,
+        opts,
+        hickory_resolver::name_server::GenericConnector::new(
+            hickory_resolver::name_server::TokioRuntimeProvider::new(),
+        ),
+    ))
+}
+
+/// The first of the addresses resolved for a host the node has a route to, so hosts connected
+/// to the internet with a single IP family pick an address of it, or the first address if none.
+fn select_address(addrs: impl IntoIterator<Item = SocketAddr>) -> Option<SocketAddr> {
+    let addrs: Vec<_> = addrs.into_iter().collect();
+    addrs
+        .iter()
+        .find(|addr| crate::transport::has_route(addr))
+        .or(addrs.first())
+        .copied()
+}
+
 /// Gateway node to use for joining the network.
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct InitPeerNode {
@@ -375,6 +449,9 @@ async fn report_result(
 ) {
     match op_result {
         Ok(Some(op_res)) => {
+            if op_res.finalized() {
+                crate::metrics::op_completed(op_res.id());
+            }
             if let Some((client_ids, cb)) = client_req_handler_callback {
                 for client_id in client_ids {
                     tracing::debug!(?tx, %client_id,  "Sending response to client");
@@ -433,6 +510,7 @@ async fn report_result(
         Err(err) => {
             // just mark the operation as completed so no redundant messages are processed for this transaction anymore
             if let Some(tx) = tx {
+                crate::metrics::op_failed(&tx);
                 op_manager.completed(tx);
             }
             #[cfg(any(debug_assertions, test))]
@@ -676,6 +754,14 @@ async fn process_message_v1<CB>(
                 }
                 break;
             }
+            NetMessageV1::Neighborhood(ref msg) => {
+                if let Err(error) =
+                    neighborhood::handle_neighborhood_msg(&op_manager, &conn_manager, msg).await
+                {
+                    tracing::debug!(tx = %msg.id(), %error, "Failed handling neighborhood message");
+                }
+                break;
+            }
             _ => break, // Exit the loop if no applicable message type is found
         }
     }
@@ -686,10 +772,12 @@ pub async fn subscribe(
     op_manager: Arc<OpManager>,
     key: ContractKey,
     client_id: Option<ClientId>,
+    priority: Priority,
 ) -> Result<Transaction, OpError> {
     const TIMEOUT: Duration = Duration::from_secs(30);
     let op = subscribe::start_op(key);
     let id = op.id;
+    op_manager.set_priority(id, priority);
     if let Some(client_id) = client_id {
         let _ = op_manager
             .ch_outbound
@@ -706,6 +794,7 @@ pub async fn subscribe(
         Err(OpError::ContractError(ContractError::ContractNotFound(key))) => {
             tracing::info!(%key, "Trying to subscribe to a contract not present, requesting it first");
             let get_op = get::start_op(key, true, false);
+            op_manager.set_priority(get_op.id, priority);
             if let Err(error) = get::request_get(&op_manager, get_op, HashSet::new()).await {
                 tracing::error!(%key, %error, "Failed getting the contract while previously trying to subscribe; bailing");
                 return Err(error);
@@ -724,6 +813,7 @@ pub async fn subscribe(
         loop {
             // just start a new op to check if contract is present
             let op = subscribe::start_op(key);
+            op_manager.set_priority(op.id, priority);
             match subscribe::request_subscribe(&op_manager, op).await {
                 Err(OpError::ContractError(ContractError::ContractNotFound(_))) => {
                     tracing::warn!("Still waiting for {key} contract");
@@ -759,12 +849,19 @@ pub async fn subscribe(
     }
 }
 
-async fn handle_aborted_op(
+async fn handle_aborted_op<NB: NetworkBridge>(
     tx: Transaction,
     op_manager: &OpManager,
     gateways: &[PeerKeyLocation],
+    bridge: &NB,
 ) -> Result<(), OpError> {
     use crate::util::IterExt;
+    if let TransactionType::Get = tx.transaction_type() {
+        if let Ok(Some(OpEnum::Get(op))) = op_manager.pop(&tx) {
+            get::abort(op, op_manager, bridge).await;
+        }
+        return Ok(());
+    }
     if let TransactionType::Connect = tx.transaction_type() {
         // attempt to establish a connection failed, this could be a fatal error since the node
         // is useless without connecting to the network, we will retry with exponential backoff
@@ -952,6 +1049,7 @@ pub async fn run_local_node(
             client_id: id,
             request,
             notification_channel,
+            subscription_mode,
             token,
             ..
         } = req;
@@ -960,7 +1058,7 @@ pub async fn run_local_node(
         let res = match *request {
             ClientRequest::ContractOp(op) => {
                 executor
-                    .contract_requests(op, id, notification_channel)
+                    .contract_requests(op, id, notification_channel, subscription_mode)
                     .await
             }
             ClientRequest::DelegateOp(op) => {
@@ -1132,5 +1230,21 @@ mod tests {
         let addr = Address::Hostname("google.com:8080".to_string());
         let socket_addr = NodeConfig::parse_socket_addr(&addr).await.unwrap();
         assert_eq!(socket_addr.port(), 8080);
+
+        let addr = Address::Hostname("2001:db8::1".to_string());
+        let socket_addr = NodeConfig::parse_socket_addr(&addr).await.unwrap();
+        assert_eq!(socket_addr.ip(), "2001:db8::1".parse::<IpAddr>().unwrap());
+
+        let addr = Address::Hostname("[2001:db8::1]:8080".to_string());
+        let socket_addr = NodeConfig::parse_socket_addr(&addr).await.unwrap();
+        assert_eq!(socket_addr, "[2001:db8::1]:8080".parse().unwrap());
+    }
+
+    #[test]
+    fn select_routable_address() {
+        let v4 = SocketAddr::from((Ipv4Addr::LOCALHOST, 31337));
+        let v6 = SocketAddr::from((Ipv6Addr::LOCALHOST, 31337));
+        assert_eq!(select_address([v6, v4]), Some(v6));
+        assert_eq!(select_address([]), None);
     }
 }
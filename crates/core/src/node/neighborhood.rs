@@ -0,0 +1,500 @@
+//! Bootstrap of the contracts near the location of a node joining the network, so it takes its
+//! share of the storage of the ring right away instead of slowly as requests route through it.
+//!
+//! The first [`NEIGHBORS_ASKED`] neighbors of a joining node are asked for the contracts they
+//! store near its location, each offering those closest to it within a share of
+//! [`BootstrapLimits::budget`]. The node then gets the contracts offered one at a time, up to
+//! [`MAX_CONTRACTS`] across all neighbors and until the states fetched fill the budget, seeding
+//! them like any contract close to it fetched from the network. The sizes claimed by neighbors
+//! only rule out the contracts too large for what is left of the budget, the size of the states
+//! actually fetched being charged against it.
+//!
+//! Listing the contracts stored is costly, so the requests of neighbors are answered at most
+//! [`MAX_ANSWERS`] times per [`ANSWER_INTERVAL`].
+
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use freenet_stdlib::prelude::ContractKey;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{oneshot, Notify};
+
+use crate::{
+    config::OPERATION_TTL,
+    contract::{ContractError, ContractHandlerEvent},
+    message::Transaction,
+    operations::{get, OpError},
+    ring::{Location, PeerKeyLocation},
+};
+
+use super::{NetworkBridge, OpManager};
+
+/// Neighbors asked for the contracts near the location of the node when it joins.
+const NEIGHBORS_ASKED: usize = 3;
+
+/// Contracts fetched at most from the neighbors of the node when it joins.
+const MAX_CONTRACTS: usize = 256;
+
+/// Requests of neighbors answered at most per [`ANSWER_INTERVAL`].
+const MAX_ANSWERS: usize = 12;
+
+const ANSWER_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Contracts fetched from the neighbors of the node when it joins the network.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct BootstrapLimits {
+    /// Bytes of the states fetched at most, 0 disabling the bootstrap.
+    pub budget: u64,
+}
+
+impl Default for BootstrapLimits {
+    fn default() -> Self {
+        Self { budget: 16 << 20 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) enum NeighborhoodMsg {
+    /// Asks a neighbor for the contracts it stores near the location of the requester.
+    Request {
+        id: Transaction,
+        target: PeerKeyLocation,
+        requester: PeerKeyLocation,
+        location: Location,
+        /// Bytes of the states offered at most.
+        budget: u64,
+    },
+    /// The contracts stored near the location requested with the size of their states, closest
+    /// first.
+    Response {
+        id: Transaction,
+        target: PeerKeyLocation,
+        contracts: Vec<(ContractKey, u64)>,
+    },
+}
+
+impl NeighborhoodMsg {
+    pub fn id(&self) -> &Transaction {
+        match self {
+            Self::Request { id, .. } | Self::Response { id, .. } => id,
+        }
+    }
+
+    pub fn target(&self) -> &PeerKeyLocation {
+        match self {
+            Self::Request { target, .. } | Self::Response { target, .. } => target,
+        }
+    }
+
+    pub fn requested_location(&self) -> Option<Location> {
+        match self {
+            Self::Request { location, .. } => Some(*location),
+            Self::Response { .. } => None,
+        }
+    }
+}
+
+impl std::fmt::Display for NeighborhoodMsg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Request {
+                id,
+                location,
+                budget,
+                ..
+            } => write!(
+                f,
+                "NeighborhoodRequest(id: {id}, location: {location}, budget: {budget})"
+            ),
+            Self::Response { id, contracts, .. } => {
+                write!(
+                    f,
+                    "NeighborhoodResponse(id: {id}, contracts: {})",
+                    contracts.len()
+                )
+            }
+        }
+    }
+}
+
+struct State {
+    /// Requests sent to neighbors and not answered yet.
+    pending: HashSet<Transaction>,
+    asked: usize,
+    /// Bytes of the budget not spent yet.
+    remaining: u64,
+    requested: HashSet<ContractKey>,
+    /// Contracts offered and not fetched yet.
+    queued: VecDeque<ContractKey>,
+    /// Get of the contract being fetched, with the channel notified once it's over.
+    fetching: Option<(Transaction, oneshot::Sender<()>)>,
+}
+
+/// Progress of the bootstrap of the contracts near the location of the node.
+pub(crate) struct NeighborhoodBootstrap {
+    limits: BootstrapLimits,
+    state: Mutex<State>,
+    /// Notified when contracts are queued.
+    queued: Notify,
+    /// Times the requests of neighbors were answered over the last [`ANSWER_INTERVAL`].
+    answers: Mutex<VecDeque<Instant>>,
+}
+
+impl NeighborhoodBootstrap {
+    pub fn new(limits: BootstrapLimits) -> Self {
+        Self {
+            limits,
+            state: Mutex::new(State {
+                pending: HashSet::new(),
+                asked: 0,
+                remaining: limits.budget,
+                requested: HashSet::new(),
+                queued: VecDeque::new(),
+                fetching: None,
+            }),
+            queued: Notify::new(),
+            answers: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Records a request to a new neighbor, returning the share of the budget offered by it,
+    /// `None` if enough neighbors were asked already.
+    fn ask(&self, id: Transaction) -> Option<u64> {
+        let mut state = self.state.lock();
+        if self.limits.budget == 0 || state.asked >= NEIGHBORS_ASKED {
+            return None;
+        }
+        state.asked += 1;
+        state.pending.insert(id);
+        Some(self.limits.budget / NEIGHBORS_ASKED as u64)
+    }
+
+    /// Records the answer to a request, queuing the contracts offered that fit in the remaining
+    /// budget and weren't requested from other neighbors already, up to [`MAX_CONTRACTS`].
+    fn accept(&self, id: &Transaction, contracts: Vec<(ContractKey, u64)>) -> Vec<ContractKey> {
+        let mut state = self.state.lock();
+        if !state.pending.remove(id) {
+            return vec![];
+        }
+        let mut accepted = vec![];
+        for (key, size) in contracts {
+            if state.requested.len() >= MAX_CONTRACTS {
+                break;
+            }
+            if size > state.remaining || state.requested.contains(&key) {
+                continue;
+            }
+            state.requested.insert(key);
+            state.queued.push_back(key);
+            accepted.push(key);
+        }
+        if !accepted.is_empty() {
+            self.queued.notify_one();
+        }
+        accepted
+    }
+
+    /// The get of the next contract queued, with the channel notified once it's over, `None`
+    /// if none is queued or the budget is spent.
+    fn next(&self) -> Option<(ContractKey, get::GetOp, oneshot::Receiver<()>)> {
+        let mut state = self.state.lock();
+        if state.remaining == 0 {
+            state.queued.clear();
+            return None;
+        }
+        let key = state.queued.pop_front()?;
+        let op = get::start_op(key, true, false);
+        let (done, finished) = oneshot::channel();
+        state.fetching = Some((op.id, done));
+        Some((key, op, finished))
+    }
+
+    /// Charges the state fetched by the get `id` against the budget if it's the one of the
+    /// contract being fetched.
+    pub fn charge(&self, id: &Transaction, size: usize) {
+        let mut state = self.state.lock();
+        if matches!(&state.fetching, Some((fetching, _)) if fetching == id) {
+            state.remaining = state.remaining.saturating_sub(size as u64);
+        }
+    }
+
+    /// Records the end of the get `id`, successful or not, moving on to the next contract if
+    /// it's the one of the contract being fetched.
+    pub fn finished(&self, id: &Transaction) {
+        let mut state = self.state.lock();
+        if matches!(&state.fetching, Some((fetching, _)) if fetching == id) {
+            if let Some((_, done)) = state.fetching.take() {
+                let _ = done.send(());
+            }
+        }
+    }
+
+    /// Whether to answer a request of a neighbor, within [`MAX_ANSWERS`] per
+    /// [`ANSWER_INTERVAL`].
+    fn should_answer(&self, now: Instant) -> bool {
+        let mut answers = self.answers.lock();
+        while answers
+            .front()
+            .is_some_and(|at| now.saturating_duration_since(*at) >= ANSWER_INTERVAL)
+        {
+            answers.pop_front();
+        }
+        if answers.len() >= MAX_ANSWERS {
+            return false;
+        }
+        answers.push_back(now);
+        true
+    }
+}
+
+/// The contracts closest to `location`, with the size of their states, within `budget`.
+fn nearby(
+    mut contracts: Vec<(ContractKey, u64)>,
+    location: Location,
+    mut budget: u64,
+) -> Vec<(ContractKey, u64)> {
+    contracts.sort_by_key(|(key, _)| location.distance(Location::from(key)));
+    contracts.retain(|(_, size)| {
+        if *size > budget {
+            return false;
+        }
+        budget -= size;
+        true
+    });
+    contracts.truncate(MAX_CONTRACTS);
+    contracts
+}
+
+/// Asks a new neighbor for the contracts near the location of the node, as long as it is still
+/// bootstrapping them.
+pub(crate) async fn request_neighborhood(op_manager: &OpManager, neighbor: PeerKeyLocation) {
+    let requester = op_manager.ring.connection_manager.own_location();
+    let Some(location) = requester.location else {
+        return;
+    };
+    let id = Transaction::new::<NeighborhoodMsg>();
+    let Some(budget) = op_manager.neighborhood.ask(id) else {
+        return;
+    };
+    tracing::debug!(tx = %id, %neighbor, %location, "Requesting contracts near this node");
+    let msg = NeighborhoodMsg::Request {
+        id,
+        target: neighbor,
+        requester,
+        location,
+        budget,
+    };
+    if let Err(error) = op_manager.notify_message(msg.into()).await {
+        tracing::debug!(tx = %id, %error, "Failed requesting contracts near this node");
+    }
+}
+
+/// Handles the requests of the neighbors for the contracts near them, and their answers.
+pub(crate) async fn handle_neighborhood_msg<NB: NetworkBridge>(
+    op_manager: &OpManager,
+    conn_manager: &NB,
+    msg: &NeighborhoodMsg,
+) -> Result<(), OpError> {
+    let this_peer = op_manager.ring.connection_manager.get_peer_key();
+    match msg {
+        // requested by this node
+        NeighborhoodMsg::Request {
+            target, requester, ..
+        } if this_peer.as_ref() == Some(&requester.peer) => {
+            conn_manager.send(&target.peer, msg.clone().into()).await?;
+        }
+        NeighborhoodMsg::Request {
+            id,
+            requester,
+            location,
+            budget,
+            ..
+        } => {
+            if !op_manager.neighborhood.should_answer(Instant::now()) {
+                tracing::debug!(tx = %id, %requester, "Too many requests for contracts, ignoring");
+                return Ok(());
+            }
+            let stored = match op_manager
+                .notify_contract_handler(ContractHandlerEvent::ListContractsQuery)
+                .await?
+            {
+                ContractHandlerEvent::ListContractsResponse { contracts } => contracts?,
+                _ => return Err(ContractError::NoEvHandlerResponse.into()),
+            };
+            let seeding = op_manager.ring.seeding_contracts();
+            let stored = stored
+                .into_iter()
+                .map(|contract| {
+                    let key = seeding
+                        .iter()
+                        .find(|key| *key.id() == contract.id)
+                        .copied()
+                        .unwrap_or_else(|| ContractKey::from(contract.id));
+                    (key, contract.state_size as u64)
+                })
+                .collect();
+            let contracts = nearby(stored, *location, *budget);
+            tracing::debug!(
+                tx = %id,
+                %requester,
+                contracts = contracts.len(),
+                "Offering contracts near new neighbor"
+            );
+            let response = NeighborhoodMsg::Response {
+                id: *id,
+                target: requester.clone(),
+                contracts,
+            };
+            conn_manager.send(&requester.peer, response.into()).await?;
+        }
+        NeighborhoodMsg::Response { id, contracts, .. } => {
+            let accepted = op_manager.neighborhood.accept(id, contracts.clone());
+            tracing::debug!(
+                tx = %id,
+                contracts = accepted.len(),
+                "Queued contracts near this node"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Gets the contracts offered by the neighbors of the node one at a time, so the size of each
+/// state fetched is charged against the budget before getting the next one.
+pub(crate) async fn fetch_neighborhood(op_manager: Arc<OpManager>) {
+    let bootstrap = &op_manager.neighborhood;
+    loop {
+        let Some((key, op, finished)) = bootstrap.next() else {
+            bootstrap.queued.notified().await;
+            continue;
+        };
+        let id = op.id;
+        if op_manager.ring.is_seeding_contract(&key) {
+            bootstrap.finished(&id);
+            continue;
+        }
+        tracing::debug!(tx = %id, %key, "Getting contract near this node");
+        if let Err(error) = get::request_get(&op_manager, op, HashSet::new()).await {
+            tracing::debug!(%key, %error, "Failed getting contract near this node");
+        } else if tokio::time::timeout(OPERATION_TTL, finished).await.is_err() {
+            tracing::debug!(tx = %id, %key, "Timed out getting contract near this node");
+        }
+        bootstrap.finished(&id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use freenet_stdlib::prelude::ContractInstanceId;
+
+    use super::*;
+
+    fn contract(location: f64) -> ContractKey {
+        // find a key close enough to the location
+        loop {
+            let key = ContractKey::from(ContractInstanceId::new(rand::random()));
+            if Location::from(&key)
+                .distance(Location::new(location))
+                .as_f64()
+                < 0.05
+            {
+                return key;
+            }
+        }
+    }
+
+    #[test]
+    fn offers_nearby_contracts_within_budget() {
+        let near = contract(0.5);
+        let close = contract(0.7);
+        let far = contract(0.1);
+        let big = contract(0.5);
+        let offered = nearby(
+            vec![(far, 10), (big, 1000), (close, 10), (near, 10)],
+            Location::new(0.5),
+            25,
+        );
+        assert_eq!(offered, vec![(near, 10), (close, 10)]);
+    }
+
+    #[test]
+    fn bounds_bootstrap() {
+        let bootstrap = NeighborhoodBootstrap::new(BootstrapLimits { budget: 30 });
+        let ids: Vec<_> = (0..NEIGHBORS_ASKED)
+            .map(|_| Transaction::new::<NeighborhoodMsg>())
+            .collect();
+        for id in &ids {
+            assert_eq!(bootstrap.ask(*id), Some(10));
+        }
+        assert_eq!(bootstrap.ask(Transaction::new::<NeighborhoodMsg>()), None);
+
+        let (a, b, c, d) = (contract(0.1), contract(0.2), contract(0.3), contract(0.4));
+        assert_eq!(
+            bootstrap.accept(&ids[0], vec![(a, 10), (b, 10)]),
+            vec![a, b]
+        );
+        // answered once
+        assert!(bootstrap.accept(&ids[0], vec![(c, 5)]).is_empty());
+
+        // the size of the state fetched is charged, not the one claimed
+        let (key, op, mut finished) = bootstrap.next().unwrap();
+        assert_eq!(key, a);
+        bootstrap.charge(&op.id, 25);
+        bootstrap.finished(&Transaction::new::<get::GetMsg>());
+        assert!(finished.try_recv().is_err());
+        bootstrap.finished(&op.id);
+        assert!(finished.try_recv().is_ok());
+
+        // only within the budget left and without contracts requested already
+        assert_eq!(
+            bootstrap.accept(&ids[1], vec![(a, 1), (c, 10), (d, 5)]),
+            vec![d]
+        );
+        let (key, op, _) = bootstrap.next().unwrap();
+        assert_eq!(key, b);
+        bootstrap.charge(&op.id, 5);
+        bootstrap.finished(&op.id);
+        // the budget is spent
+        assert!(bootstrap.next().is_none());
+        assert!(bootstrap
+            .accept(&ids[2], vec![(contract(0.5), 1)])
+            .is_empty());
+        assert!(bootstrap
+            .accept(&Transaction::new::<NeighborhoodMsg>(), vec![])
+            .is_empty());
+
+        let disabled = NeighborhoodBootstrap::new(BootstrapLimits { budget: 0 });
+        assert_eq!(disabled.ask(ids[0]), None);
+    }
+
+    #[test]
+    fn caps_contracts_fetched() {
+        let bootstrap = NeighborhoodBootstrap::new(BootstrapLimits { budget: u64::MAX });
+        let id = Transaction::new::<NeighborhoodMsg>();
+        bootstrap.ask(id);
+        let offered = (0..=MAX_CONTRACTS)
+            .map(|_| {
+                (
+                    ContractKey::from(ContractInstanceId::new(rand::random())),
+                    0,
+                )
+            })
+            .collect();
+        assert_eq!(bootstrap.accept(&id, offered).len(), MAX_CONTRACTS);
+    }
+
+    #[test]
+    fn rate_limits_answers() {
+        let bootstrap = NeighborhoodBootstrap::new(BootstrapLimits::default());
+        let start = Instant::now();
+        for _ in 0..MAX_ANSWERS {
+            assert!(bootstrap.should_answer(start));
+        }
+        assert!(!bootstrap.should_answer(start + Duration::from_secs(1)));
+        assert!(bootstrap.should_answer(start + ANSWER_INTERVAL));
+    }
+}
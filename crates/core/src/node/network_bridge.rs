@@ -18,8 +18,13 @@ use crate::message::{NetMessage, NodeEvent};
 
 mod handshake;
 pub(crate) mod in_memory;
+mod inbound_budget;
+mod op_slots;
 pub(crate) mod p2p_protoc;
 
+pub(crate) use inbound_budget::InboundLimits;
+pub(crate) use op_slots::OpLimits;
+
 pub(crate) type ConnResult<T> = std::result::Result<T, ConnectionError>;
 
 /// Allows handling of connections to the network as well as sending messages
@@ -22,7 +22,8 @@ use crate::{
     ring::{ConnectionManager, PeerKeyLocation, Ring},
     router::Router,
     transport::{
-        InboundConnectionHandler, OutboundConnectionHandler, PeerConnection, TransportError,
+        InboundConnectionHandler, NatMapping, OutboundConnectionHandler, PeerConnection,
+        TransportError,
     },
 };
 
@@ -278,7 +279,7 @@ impl HandshakeHandler {
                                 self.connection_manager.try_set_peer_key(addr);
                                 if self.this_location.is_none() {
                                     // in the case trust locations is set to true, this peer already had its location set
-                                    self.connection_manager.update_location(Some(Location::from_address(&addr)));
+                                    self.connection_manager.update_location(Some(self.connection_manager.initial_location(&addr)));
                                 }
                             }
                             tracing::debug!(at=?connection.my_address(), from=%connection.remote_addr(), "Outbound connection to gw successful");
@@ -352,7 +353,8 @@ impl HandshakeHandler {
                     let (event, outbound_sender) = res?;
                     match event {
                         InternalEvent::InboundGwJoinRequest(mut req) => {
-                            let location = if let Some((_, other)) = self.this_location.zip(req.location) {
+                            let trusts_locations = self.this_location.is_some() || !self.connection_manager.accepts_assigned_location();
+                            let location = if let Some(other) = req.location.filter(|_| trusts_locations) {
                                 other
                             } else {
                                 Location::from_address(&req.conn.remote_addr())
@@ -389,6 +391,7 @@ impl HandshakeHandler {
                                     skip_connections,
                                     skip_forwards,
                                     joiner,
+                                    joiner_nat,
                                     ..
                                 } = req;
 
@@ -418,6 +421,7 @@ impl HandshakeHandler {
                                         skip_forwards,
                                         req_peer: my_peer_id.clone(),
                                         joiner: joiner_pk_loc.clone(),
+                                        joiner_nat,
                                     };
 
                                     let f = forward_conn(
@@ -467,6 +471,7 @@ impl HandshakeHandler {
                                     skip_connections,
                                     skip_forwards,
                                     joiner,
+                                    joiner_nat,
                                     ..
                                 } = req;
                                 let remote = conn.remote_addr();
@@ -474,6 +479,7 @@ impl HandshakeHandler {
                                 let mut tx = TransientConnection {
                                     tx: id,
                                     joiner: joiner.clone(),
+                                    joiner_nat,
                                     max_hops_to_live,
                                     hops_to_live,
                                     skip_connections,
@@ -585,6 +591,7 @@ impl HandshakeHandler {
             skip_forwards: transaction.skip_forwards.clone(),
             req_peer: my_peer_id.clone(),
             joiner: joiner_pk_loc.clone(),
+            joiner_nat: transaction.joiner_nat,
         };
 
         match forward_conn(
@@ -713,10 +720,17 @@ impl HandshakeHandler {
         }
         self.connecting.insert(remote.addr, transaction);
         tracing::debug!("Starting outbound connection to {addr}", addr = remote.addr);
-        let f = self
-            .outbound_conn_handler
-            .connect(remote.pub_key.clone(), remote.addr)
-            .await
+        let connection = if is_gw {
+            self.outbound_conn_handler
+                .connect_gateway(remote.pub_key.clone(), remote.addr)
+                .await
+        } else {
+            let relays = self.connection_manager.relay_candidates(&remote);
+            self.outbound_conn_handler
+                .connect_relayed(remote.pub_key.clone(), remote.addr, relays)
+                .await
+        };
+        let f = connection
             .map(move |c| match c {
                 Ok(conn) if is_gw => {
                     tracing::debug!(%remote, "established outbound gw connection");
@@ -750,7 +764,11 @@ impl HandshakeHandler {
         tracing::debug!(at=?conn.my_address(), %this_peer.addr, from=%conn.remote_addr(), remote_addr = %gw_peer_id, "Waiting for confirmation from gw");
         self.ongoing_outbound_connections.push(
             wait_for_gw_confirmation(
-                (this_peer, self.this_location),
+                (
+                    this_peer,
+                    self.this_location,
+                    self.connection_manager.nat_mapping(),
+                ),
                 AcceptedTracker {
                     gw_peer: gw_peer_id.into(),
                     gw_conn: conn,
@@ -804,6 +822,7 @@ struct InboundGwJoinRequest {
     pub id: Transaction,
     pub joiner: PeerId,
     pub location: Option<Location>,
+    pub joiner_nat: NatMapping,
     pub hops_to_live: usize,
     pub max_hops_to_live: usize,
     pub skip_connections: HashSet<PeerId>,
@@ -846,7 +865,7 @@ struct AcceptedTracker {
 
 /// Waits for confirmation from a gateway after initiating a connection.
 async fn wait_for_gw_confirmation(
-    (this_peer, this_location): (PeerId, Option<Location>),
+    (this_peer, this_location, nat_mapping): (PeerId, Option<Location>, NatMapping),
     mut tracker: AcceptedTracker,
 ) -> OutboundConnResult {
     let gw_peer_id = tracker.gw_peer.peer.clone();
@@ -857,6 +876,7 @@ async fn wait_for_gw_confirmation(
             joiner: Some(this_peer.clone()),
             joiner_key: this_peer.pub_key.clone(),
             joiner_location: this_location,
+            joiner_nat: nat_mapping,
             hops_to_live: tracker.total_checks,
             max_hops_to_live: tracker.total_checks,
             skip_connections: HashSet::from([this_peer.clone()]),
@@ -1011,7 +1031,8 @@ async fn gw_peer_connection_listener(
                             max_hops_to_live,
                             skip_connections,
                             skip_forwards,
-                            joiner_location
+                            joiner_location,
+                            joiner_nat,
                         },
                         ..
                     })) => {
@@ -1025,6 +1046,7 @@ async fn gw_peer_connection_listener(
                                 id,
                                 joiner,
                                 location: joiner_location,
+                                joiner_nat,
                                 hops_to_live,
                                 max_hops_to_live,
                                 skip_connections,
@@ -1139,6 +1161,7 @@ async fn gw_transient_peer_conn(
 struct TransientConnection {
     tx: Transaction,
     joiner: PeerId,
+    joiner_nat: NatMapping,
     max_hops_to_live: usize,
     hops_to_live: usize,
     skip_connections: HashSet<PeerId>,
@@ -1262,6 +1285,7 @@ mod tests {
                     joiner: None,
                     joiner_key: pub_key,
                     joiner_location: None,
+                    joiner_nat: NatMapping::Unknown,
                     hops_to_live,
                     max_hops_to_live: hops_to_live,
                     skip_connections: HashSet::new(),
@@ -1440,7 +1464,10 @@ mod tests {
             .recv()
             .await
             .ok_or_else(|| anyhow!("failed to get conn start req"))
-            .unwrap();
+            .unwrap()
+        else {
+            panic!("expected a connection start");
+        };
         assert_eq!(trying_addr, addr);
         assert_eq!(remote_public_key, pub_key);
         tracing::debug!("Received connection event");
@@ -1845,7 +1872,10 @@ mod tests {
                         .ok_or( anyhow!("Failed to receive event"))?;
                         let ConnectionEvent::ConnectionStart {
                             open_connection, ..
-                        } = ev;
+                        } = ev
+                        else {
+                            panic!("expected a connection start");
+                        };
                         let out_symm_key = Aes128Gcm::new_from_slice(&[0; 16]).unwrap();
                         let in_symm_key = Aes128Gcm::new_from_slice(&[1; 16]).unwrap();
                         let (conn, out, inb) = PeerConnection::new_remote_test(
@@ -0,0 +1,161 @@
+//! Budgets of the messages each peer can send to the node, so a single misbehaving peer can't
+//! flood the event loop and starve the rest of the network.
+//!
+//! Every connection gets a budget of messages and of operations initiated per second, refilled
+//! continuously and allowing bursts of a few seconds worth of them. Messages over budget are
+//! dropped; peers which keep exceeding it lose reputation, so they are routed through less and
+//! their connections are the first ones dropped, and are disconnected when they flood the node
+//! for long.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use crate::util::token_bucket::TokenBucket;
+
+/// Seconds worth of the rate allowed in a burst.
+const BURST_SECONDS: f64 = 4.0;
+
+/// Messages dropped between reputation penalties of a peer.
+const PENALTY_DROPS: u32 = 64;
+
+/// Window the messages dropped are counted over to disconnect a peer.
+const FLOOD_WINDOW: Duration = Duration::from_secs(60);
+
+/// Messages dropped within the flood window after which the peer is disconnected.
+const DISCONNECT_DROPS: u32 = 1024;
+
+/// Inbound messages accepted from each peer.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct InboundLimits {
+    pub messages_per_second: u32,
+    /// Operations started by messages of transactions unknown to the node, per second.
+    pub ops_per_second: u32,
+}
+
+impl Default for InboundLimits {
+    fn default() -> Self {
+        Self {
+            messages_per_second: 200,
+            ops_per_second: 20,
+        }
+    }
+}
+
+/// What to do with a message received from a peer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Admission {
+    Accept,
+    Drop,
+    /// Drop the message and lower the reputation of the peer.
+    Penalize,
+    /// Drop the message and the connection.
+    Disconnect,
+}
+
+fn bucket(rate: u32, now: Instant) -> TokenBucket {
+    TokenBucket::new(rate as f64, rate as f64 * BURST_SECONDS, now)
+}
+
+struct PeerBudget {
+    messages: TokenBucket,
+    ops: TokenBucket,
+    dropped: u32,
+    window_start: Instant,
+}
+
+pub(super) struct InboundBudgets {
+    limits: InboundLimits,
+    peers: HashMap<SocketAddr, PeerBudget>,
+}
+
+impl InboundBudgets {
+    pub fn new(limits: InboundLimits) -> Self {
+        Self {
+            limits,
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Charges a message from `peer` to its budget, `initiates_op` if it starts an operation.
+    pub fn admit(&mut self, peer: SocketAddr, initiates_op: bool, now: Instant) -> Admission {
+        let limits = self.limits;
+        let budget = self.peers.entry(peer).or_insert_with(|| PeerBudget {
+            messages: bucket(limits.messages_per_second, now),
+            ops: bucket(limits.ops_per_second, now),
+            dropped: 0,
+            window_start: now,
+        });
+        let within = budget.messages.has(1, now) && (!initiates_op || budget.ops.has(1, now));
+        if within {
+            budget.messages.take(1, now);
+            if initiates_op {
+                budget.ops.take(1, now);
+            }
+            return Admission::Accept;
+        }
+
+        if now.saturating_duration_since(budget.window_start) > FLOOD_WINDOW {
+            budget.dropped = 0;
+            budget.window_start = now;
+        }
+        budget.dropped += 1;
+        if budget.dropped >= DISCONNECT_DROPS {
+            Admission::Disconnect
+        } else if budget.dropped % PENALTY_DROPS == 0 {
+            Admission::Penalize
+        } else {
+            Admission::Drop
+        }
+    }
+
+    pub fn forget(&mut self, peer: &SocketAddr) {
+        self.peers.remove(peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_flooding_peers() {
+        let limits = InboundLimits {
+            messages_per_second: 100,
+            ops_per_second: 10,
+        };
+        let mut budgets = InboundBudgets::new(limits);
+        let flooder: SocketAddr = "10.0.0.1:1000".parse().unwrap();
+        let other: SocketAddr = "10.0.0.2:1000".parse().unwrap();
+        let now = Instant::now();
+
+        // a burst of operations is accepted, then they are dropped while other messages are not
+        let burst = (limits.ops_per_second as f64 * BURST_SECONDS) as usize;
+        for _ in 0..burst {
+            assert_eq!(budgets.admit(flooder, true, now), Admission::Accept);
+        }
+        assert_eq!(budgets.admit(flooder, true, now), Admission::Drop);
+        assert_eq!(budgets.admit(flooder, false, now), Admission::Accept);
+        // and refilled over time
+        let later = now + Duration::from_secs(1);
+        assert_eq!(budgets.admit(flooder, true, later), Admission::Accept);
+
+        // the budget of every peer is independent
+        assert_eq!(budgets.admit(other, true, later), Admission::Accept);
+
+        let mut admissions = vec![];
+        while admissions.last() != Some(&Admission::Disconnect) {
+            admissions.push(budgets.admit(flooder, false, later));
+        }
+        let penalties = admissions
+            .iter()
+            .filter(|admission| **admission == Admission::Penalize)
+            .count();
+        assert_eq!(penalties, (DISCONNECT_DROPS / PENALTY_DROPS) as usize - 1);
+
+        budgets.forget(&flooder);
+        assert_eq!(budgets.admit(flooder, true, later), Admission::Accept);
+    }
+}
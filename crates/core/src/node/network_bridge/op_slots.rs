@@ -0,0 +1,246 @@
+//! Bounds on the operations the node processes concurrently, per type, so small devices aren't
+//! overwhelmed by the network and servers can put their resources to use.
+//!
+//! Messages of GET, PUT and SUBSCRIBE operations are processed by as many tasks at once at most
+//! as allowed for their type, the rest waiting in a backlog. Once the backlog of a type is full,
+//! messages starting new operations of that type are shed, the peers requesting them timing out
+//! and retrying elsewhere, while messages of operations in flight are still queued so the work
+//! already done for them isn't wasted.
+//!
+//! Background operations only take up to three quarters of the slots of their type, leaving the
+//! rest to interactive ones, and new background operations are shed once the backlog is half
+//! full, so interactive operations keep being served when the node is loaded.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::message::{Priority, TransactionType};
+
+/// Operations processed concurrently by the node.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct OpLimits {
+    pub gets: usize,
+    pub puts: usize,
+    pub subscribes: usize,
+    /// Messages of each type waiting to be processed past which new operations are shed.
+    pub max_queued: usize,
+}
+
+impl Default for OpLimits {
+    fn default() -> Self {
+        Self {
+            gets: 64,
+            puts: 32,
+            subscribes: 32,
+            max_queued: 256,
+        }
+    }
+}
+
+struct Slots {
+    permits: Arc<Semaphore>,
+    /// Share of the permits background operations can take.
+    background: Arc<Semaphore>,
+    queued: AtomicUsize,
+    max_queued: usize,
+}
+
+impl Slots {
+    fn new(concurrency: usize, max_queued: usize) -> Arc<Self> {
+        Arc::new(Self {
+            permits: Arc::new(Semaphore::new(concurrency)),
+            background: Arc::new(Semaphore::new(concurrency - concurrency / 4)),
+            queued: AtomicUsize::new(0),
+            max_queued,
+        })
+    }
+
+    fn try_acquire(&self, priority: Priority) -> Option<Permit> {
+        let background = match priority {
+            Priority::Interactive => None,
+            Priority::Background => Some(self.background.clone().try_acquire_owned().ok()?),
+        };
+        let permit = self.permits.clone().try_acquire_owned().ok()?;
+        Some(Permit {
+            _permit: permit,
+            _background: background,
+        })
+    }
+
+    async fn acquire(&self, priority: Priority) -> Option<Permit> {
+        let background = match priority {
+            Priority::Interactive => None,
+            Priority::Background => Some(self.background.clone().acquire_owned().await.ok()?),
+        };
+        let permit = self.permits.clone().acquire_owned().await.ok()?;
+        Some(Permit {
+            _permit: permit,
+            _background: background,
+        })
+    }
+}
+
+/// Permission to process a message, held while processing it.
+pub(super) struct Permit {
+    _permit: OwnedSemaphorePermit,
+    /// The share of background operations taken, if it is one.
+    _background: Option<OwnedSemaphorePermit>,
+}
+
+/// The slots of each type of operation bounded.
+#[derive(Clone)]
+pub(super) struct OpSlots {
+    get: Arc<Slots>,
+    put: Arc<Slots>,
+    subscribe: Arc<Slots>,
+}
+
+/// The turn of a message to be processed.
+pub(super) enum Slot {
+    /// Operations of its type aren't bounded.
+    Unbounded,
+    Acquired(Permit),
+    Queued(Queued),
+}
+
+/// A message in the backlog of its type, leaving it when dropped.
+pub(super) struct Queued(Arc<Slots>, Priority);
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        self.0.queued.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl OpSlots {
+    pub fn new(limits: OpLimits) -> Self {
+        Self {
+            get: Slots::new(limits.gets, limits.max_queued),
+            put: Slots::new(limits.puts, limits.max_queued),
+            subscribe: Slots::new(limits.subscribes, limits.max_queued),
+        }
+    }
+
+    /// Takes the turn of a message of an operation of the type, `None` if it starts the
+    /// operation and the backlog of its type is full, or half full for background operations.
+    pub fn try_enter(
+        &self,
+        tx_type: TransactionType,
+        priority: Priority,
+        starts_op: bool,
+    ) -> Option<Slot> {
+        let slots = match tx_type {
+            TransactionType::Get => &self.get,
+            TransactionType::Put => &self.put,
+            TransactionType::Subscribe => &self.subscribe,
+            _ => return Some(Slot::Unbounded),
+        };
+        if let Some(permit) = slots.try_acquire(priority) {
+            return Some(Slot::Acquired(permit));
+        }
+        let queued = slots.queued.fetch_add(1, Ordering::SeqCst);
+        let slot = Queued(slots.clone(), priority);
+        let max_queued = match priority {
+            Priority::Interactive => slots.max_queued,
+            Priority::Background => slots.max_queued / 2,
+        };
+        if starts_op && queued >= max_queued {
+            return None;
+        }
+        Some(Slot::Queued(slot))
+    }
+}
+
+impl Slot {
+    /// Waits for the turn of the message, the permit returned being held while processing it.
+    pub async fn acquire(self) -> Option<Permit> {
+        match self {
+            Slot::Unbounded => None,
+            Slot::Acquired(permit) => Some(permit),
+            Slot::Queued(queued) => queued.0.acquire(queued.1).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn sheds_new_operations_past_backlog() {
+        let slots = OpSlots::new(OpLimits {
+            gets: 1,
+            max_queued: 1,
+            ..Default::default()
+        });
+
+        let processing = slots
+            .try_enter(TransactionType::Get, Priority::Interactive, true)
+            .unwrap();
+        assert!(matches!(processing, Slot::Acquired(_)));
+        let waiting = slots
+            .try_enter(TransactionType::Get, Priority::Interactive, true)
+            .unwrap();
+        assert!(matches!(waiting, Slot::Queued(_)));
+        assert!(slots
+            .try_enter(TransactionType::Get, Priority::Interactive, true)
+            .is_none());
+        // operations in flight are never shed, nor other types of operations
+        let in_flight = slots
+            .try_enter(TransactionType::Get, Priority::Interactive, false)
+            .unwrap();
+        assert!(matches!(in_flight, Slot::Queued(_)));
+        assert!(matches!(
+            slots.try_enter(TransactionType::Put, Priority::Interactive, true),
+            Some(Slot::Acquired(_))
+        ));
+        assert!(matches!(
+            slots.try_enter(TransactionType::Connect, Priority::Interactive, true),
+            Some(Slot::Unbounded)
+        ));
+
+        // the backlog drains as messages are processed
+        drop(processing.acquire().await);
+        drop(waiting.acquire().await);
+        drop(in_flight.acquire().await);
+        assert_eq!(slots.get.queued.load(Ordering::SeqCst), 0);
+        assert!(matches!(
+            slots.try_enter(TransactionType::Get, Priority::Interactive, true),
+            Some(Slot::Acquired(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn reserves_slots_for_interactive_operations() {
+        let slots = OpSlots::new(OpLimits {
+            gets: 4,
+            max_queued: 2,
+            ..Default::default()
+        });
+        let enter = |priority| slots.try_enter(TransactionType::Get, priority, true);
+
+        let background: Vec<_> = (0..3).map(|_| enter(Priority::Background)).collect();
+        assert!(background
+            .iter()
+            .all(|slot| matches!(slot, Some(Slot::Acquired(_)))));
+        // the last slot is left to interactive operations
+        let waiting = enter(Priority::Background).unwrap();
+        assert!(matches!(waiting, Slot::Queued(_)));
+        assert!(enter(Priority::Background).is_none());
+        let interactive = enter(Priority::Interactive).unwrap();
+        assert!(matches!(interactive, Slot::Acquired(_)));
+        // interactive operations are queued up to the full backlog
+        assert!(matches!(
+            enter(Priority::Interactive),
+            Some(Slot::Queued(_))
+        ));
+
+        // background operations get their turn once others are done
+        drop(background);
+        assert!(waiting.acquire().await.is_some());
+    }
+}
@@ -1,6 +1,8 @@
+use super::inbound_budget::{Admission, InboundBudgets};
+use super::op_slots::OpSlots;
 use super::{ConnectionError, EventLoopNotificationsReceiver, NetworkBridge};
 use crate::contract::WaitingTransaction;
-use crate::message::{NetMessageV1, QueryResult};
+use crate::message::{NetMessageV1, Priority, QueryResult};
 use crate::node::subscribe::SubscribeMsg;
 use crate::ring::Location;
 use dashmap::DashSet;
@@ -13,25 +15,27 @@ use std::convert::Infallible;
 use std::future::Future;
 use std::net::{IpAddr, SocketAddr};
 use std::pin::Pin;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 use std::{
     collections::{HashMap, HashSet},
     sync::Arc,
 };
-use tokio::net::UdpSocket;
 use tokio::select;
 use tokio::sync::mpsc::{self, Receiver, Sender};
 use tokio::sync::oneshot::{self};
 use tokio::time::timeout;
 use tracing::Instrument;
 
+use crate::node::bans::{Penalty, Violation};
 use crate::node::network_bridge::handshake::{
     Event as HandshakeEvent, ForwardInfo, HandshakeError, HandshakeHandler, HanshakeHandlerMsg,
     OutboundMessage,
 };
 use crate::node::PeerId;
 use crate::transport::{
-    create_connection_handler, PeerConnection, TransportError, TransportKeypair,
+    create_connection_handler, maintain_port_mapping, BandwidthLimits, NatMapping,
+    OutboundConnectionHandler, PeerConnection, RekeyLimits, RelayLimits, ReplayLimits,
+    TransportError, TransportKeypair, TunneledSocket,
 };
 use crate::{
     client_events::ClientId,
@@ -40,9 +44,17 @@ use crate::{
         ClientResponsesSender, ContractHandlerChannel, ExecutorToEventLoopChannel,
         NetworkEventListenerHalve, WaitingResolution,
     },
-    message::{MessageStats, NetMessage, NodeEvent, Transaction},
-    node::{handle_aborted_op, process_message, NetEventRegister, NodeConfig, OpManager},
-    ring::PeerKeyLocation,
+    message::{MessageStats, NetMessage, NodeEvent, Transaction, TransactionType},
+    node::{
+        dns_seed, gateway_health, handle_aborted_op, mdns, neighborhood,
+        peer_cache::{self, PeerCache},
+        pins, process_message,
+        seed_list::{self, SeedList},
+        NetEventRegister, NodeConfig, OpManager,
+    },
+    operations::{get, update},
+    ring::{ConnectionManager, PeerKeyLocation},
+    router::PeerEvent,
     tracing::NetEventLog,
 };
 
@@ -103,8 +115,8 @@ impl NetworkBridge for P2pBridge {
     }
 }
 
-type PeerConnChannelSender = Sender<Either<NetMessage, ConnEvent>>;
-type PeerConnChannelRecv = Receiver<Either<NetMessage, ConnEvent>>;
+type PeerConnChannelSender = Sender<Either<(NetMessage, Priority), ConnEvent>>;
+type PeerConnChannelRecv = Receiver<Either<(NetMessage, Priority), ConnEvent>>;
 
 pub(in crate::node) struct P2pConnManager {
     pub(in crate::node) gateways: Vec<PeerKeyLocation>,
@@ -124,7 +136,18 @@ pub(in crate::node) struct P2pConnManager {
     /// and locations should be derived from IP addresses.
     this_location: Option<Location>,
     check_version: bool,
-    bandwidth_limit: Option<usize>,
+    bandwidth_limits: BandwidthLimits,
+    rekey_limits: RekeyLimits,
+    replay_limits: ReplayLimits,
+    relay_limits: RelayLimits,
+    inbound_budgets: InboundBudgets,
+    op_slots: OpSlots,
+    quic_port: Option<u16>,
+    port_mapping: bool,
+    dns_seed: Option<String>,
+    seed_list: Option<SeedList>,
+    peer_cache: Option<PeerCache>,
+    mdns: bool,
     blocked_addresses: Option<HashSet<SocketAddr>>,
 }
 
@@ -154,7 +177,18 @@ impl P2pConnManager {
             is_gateway: config.is_gateway,
             this_location: config.location,
             check_version: !config.config.network_api.ignore_protocol_version,
-            bandwidth_limit: config.config.network_api.bandwidth_limit,
+            bandwidth_limits: config.config.network_api.bandwidth_limits(),
+            rekey_limits: config.config.network_api.rekey_limits(),
+            replay_limits: config.config.network_api.replay_limits(),
+            relay_limits: config.config.network_api.relay_limits(),
+            inbound_budgets: InboundBudgets::new(config.config.network_api.inbound_limits()),
+            op_slots: OpSlots::new(config.config.network_api.op_limits()),
+            quic_port: config.config.network_api.quic_port,
+            port_mapping: config.config.network_api.port_mapping,
+            dns_seed: config.config.network_api.dns_seed.clone(),
+            seed_list: SeedList::from_config(&config.config)?,
+            peer_cache: Some(PeerCache::from_config(&config.config)),
+            mdns: config.config.network_api.mdns,
             blocked_addresses: config.blocked_addresses.clone(),
         })
     }
@@ -174,14 +208,67 @@ impl P2pConnManager {
 
         let mut state = EventListenerState::new();
 
-        let (outbound_conn_handler, inbound_conn_handler) = create_connection_handler::<UdpSocket>(
-            self.key_pair.clone(),
-            self.listening_ip,
-            self.listening_port,
-            self.is_gateway,
-            self.bandwidth_limit,
-        )
-        .await?;
+        let (outbound_conn_handler, inbound_conn_handler) =
+            create_connection_handler::<TunneledSocket>(
+                self.key_pair.clone(),
+                self.listening_ip,
+                self.listening_port,
+                self.is_gateway,
+                self.bandwidth_limits,
+                self.rekey_limits,
+                self.replay_limits,
+                self.relay_limits,
+                self.quic_port,
+            )
+            .await?;
+
+        let connection_manager = &self.bridge.op_manager.ring.connection_manager;
+        if self.is_gateway {
+            // gateways are reachable by every peer at their public address
+            connection_manager.set_nat_mapping(NatMapping::EndpointIndependent);
+        } else {
+            GlobalExecutor::spawn(discover_nat_mapping(
+                outbound_conn_handler.clone(),
+                self.gateways.iter().map(|gw| gw.peer.addr).collect(),
+                connection_manager.clone(),
+            ));
+            if self.port_mapping {
+                let connection_manager = connection_manager.clone();
+                GlobalExecutor::spawn(maintain_port_mapping(self.listening_port, move |status| {
+                    connection_manager.set_port_mapping(status)
+                }));
+            }
+        }
+        if !self.gateways.is_empty() || self.dns_seed.is_some() || self.seed_list.is_some() {
+            GlobalExecutor::spawn(gateway_health::check_gateways(
+                outbound_conn_handler.clone(),
+                self.gateways.clone(),
+                connection_manager.clone(),
+            ));
+        }
+        if let Some(seed) = self.dns_seed.clone() {
+            GlobalExecutor::spawn(dns_seed::refresh_gateways(seed, connection_manager.clone()));
+        }
+        if let Some(seed_list) = self.seed_list.take() {
+            GlobalExecutor::spawn(seed_list::refresh_gateways(
+                seed_list,
+                connection_manager.clone(),
+            ));
+        }
+        if self.mdns {
+            GlobalExecutor::spawn(mdns::discover_peers(
+                self.listening_port,
+                op_manager.clone(),
+            ));
+        }
+        if let Some(peer_cache) = self.peer_cache.take() {
+            GlobalExecutor::spawn(peer_cache::rejoin(peer_cache, op_manager.clone()));
+        }
+        GlobalExecutor::spawn(update::broadcast_coalesced_updates(op_manager.clone()));
+        GlobalExecutor::spawn(update::synchronize_replicas(op_manager.clone()));
+        GlobalExecutor::spawn(pins::keep_pinned(op_manager.clone()));
+        GlobalExecutor::spawn(get::refresh_cached_contracts(op_manager.clone()));
+        GlobalExecutor::spawn(neighborhood::fetch_neighborhood(op_manager.clone()));
 
         let (mut handshake_handler, handshake_handler_msg, outbound_message) =
             HandshakeHandler::new(
@@ -220,6 +307,19 @@ impl P2pConnManager {
                             )
                             .await?;
                         }
+                        ConnEvent::OutboundAborted { target, tx } => {
+                            let Some(peer_connection) = self.connections.get(&target) else {
+                                tracing::debug!(%tx, %target, "No connection to abort the transaction at");
+                                continue;
+                            };
+                            let msg = NetMessage::V1(NetMessageV1::Aborted(tx));
+                            let priority = op_manager.priority(&tx);
+                            if let Err(e) =
+                                peer_connection.send(Left((msg, priority))).await
+                            {
+                                tracing::error!("Failed to send message to peer: {}", e);
+                            }
+                        }
                         ConnEvent::OutboundMessage(NetMessage::V1(NetMessageV1::Aborted(tx))) => {
                             // TODO: handle aborted transaction as internal message
                             tracing::error!(%tx, "Aborted transaction");
@@ -234,7 +334,10 @@ impl P2pConnManager {
                             tracing::debug!(%target_peer, %msg, "Sending message to peer");
                             match self.connections.get(&target_peer.peer) {
                                 Some(peer_connection) => {
-                                    if let Err(e) = peer_connection.send(Left(msg)).await {
+                                    let priority = op_manager.priority(msg.id());
+                                    if let Err(e) =
+                                        peer_connection.send(Left((msg, priority))).await
+                                    {
                                         tracing::error!("Failed to send message to peer: {}", e);
                                     }
                                 }
@@ -310,6 +413,19 @@ impl P2pConnManager {
                                 cli_response_sender
                                     .send((client, Err(ErrorKind::FailedOperation.into())))?;
                             }
+                            NodeEvent::ClientDisconnected(client_id) => {
+                                state.tx_to_client.retain(|tx, client| {
+                                    if *client != client_id {
+                                        return true;
+                                    }
+                                    tracing::debug!(%tx, %client_id, "Cancelling operation of disconnected client");
+                                    self.op_manager.cancel(*tx);
+                                    false
+                                });
+                                for (_, clients) in &mut state.client_waiting_transaction {
+                                    clients.remove(&client_id);
+                                }
+                            }
                             NodeEvent::Disconnect { cause } => {
                                 tracing::info!(
                                     "Disconnecting from network{}",
@@ -387,7 +503,7 @@ impl P2pConnManager {
     ) -> anyhow::Result<()> {
         match msg {
             NetMessage::V1(NetMessageV1::Aborted(tx)) => {
-                handle_aborted_op(tx, op_manager, &self.gateways).await?;
+                handle_aborted_op(tx, op_manager, &self.gateways, &self.bridge).await?;
             }
             msg => {
                 if let Some(addr) = state.transient_conn.get(msg.id()) {
@@ -416,6 +532,16 @@ impl P2pConnManager {
         cli_response_sender: &ClientResponsesSender,
         state: &mut EventListenerState,
     ) {
+        let starts_op = !op_manager.is_known(msg.id());
+        let Some(slot) = self.op_slots.try_enter(
+            msg.id().transaction_type(),
+            op_manager.priority(msg.id()),
+            starts_op,
+        ) else {
+            tracing::debug!(tx = %msg.id(), "Backlog of operations full, shedding new operation");
+            crate::metrics::op_shed(msg.id());
+            return;
+        };
         let executor_callback = state
             .pending_from_executor
             .remove(msg.id())
@@ -450,21 +576,36 @@ impl P2pConnManager {
 
         let pending_op_result = state.pending_op_results.get(msg.id()).cloned();
 
+        let processing = process_message(
+            msg,
+            op_manager.clone(),
+            self.bridge.clone(),
+            self.event_listener.trait_clone(),
+            executor_callback,
+            client_req_handler_callback,
+            pending_client_req,
+            pending_op_result,
+        );
         GlobalExecutor::spawn(
-            process_message(
-                msg,
-                op_manager.clone(),
-                self.bridge.clone(),
-                self.event_listener.trait_clone(),
-                executor_callback,
-                client_req_handler_callback,
-                pending_client_req,
-                pending_op_result,
-            )
+            async move {
+                let _permit = slot.acquire().await;
+                processing.await
+            }
             .instrument(span),
         );
     }
 
+    /// Whether connections with the peer are refused by the blocked addresses, peer policy or
+    /// bans.
+    fn is_blocked(&self, peer: &PeerId) -> bool {
+        let connection_manager = &self.bridge.op_manager.ring.connection_manager;
+        self.blocked_addresses
+            .as_ref()
+            .is_some_and(|addrs| addrs.contains(&peer.addr))
+            || !connection_manager.peer_policy().is_allowed(peer)
+            || connection_manager.bans().is_banned(peer, SystemTime::now())
+    }
+
     async fn handle_connect_peer(
         &mut self,
         peer: PeerId,
@@ -475,17 +616,15 @@ impl P2pConnManager {
         is_gw: bool,
     ) -> anyhow::Result<()> {
         tracing::info!(tx = %tx, remote = %peer, "Connecting to peer");
-        if let Some(blocked_addrs) = &self.blocked_addresses {
-            if blocked_addrs.contains(&peer.addr) {
-                tracing::info!(tx = %tx, remote = %peer.addr, "Outgoing connection to peer blocked by local policy");
-                // Ensure ConnectionError is correctly namespaced if HandshakeError::ConnectionError expects it directly
-                callback
-                    .send_result(Err(HandshakeError::ConnectionError(
-                        crate::node::network_bridge::ConnectionError::AddressBlocked(peer.addr),
-                    )))
-                    .await?;
-                return Ok(());
-            }
+        if self.is_blocked(&peer) {
+            tracing::info!(tx = %tx, remote = %peer.addr, "Outgoing connection to peer blocked by local policy");
+            // Ensure ConnectionError is correctly namespaced if HandshakeError::ConnectionError expects it directly
+            callback
+                .send_result(Err(HandshakeError::ConnectionError(
+                    crate::node::network_bridge::ConnectionError::AddressBlocked(peer.addr),
+                )))
+                .await?;
+            return Ok(());
         }
         state.awaiting_connection.insert(peer.addr, callback);
         let res = timeout(
@@ -523,18 +662,26 @@ impl P2pConnManager {
                 op,
                 forward_info,
             } => {
-                if let Some(blocked_addrs) = &self.blocked_addresses {
-                    if blocked_addrs.contains(&joiner.addr) {
-                        tracing::info!(%id, remote = %joiner.addr, "Inbound connection from peer blocked by local policy");
-                        // Not proceeding with adding connection or processing the operation.
-                        handshake_handler_msg
-                            .drop_connection_by_addr(joiner.addr)
-                            .await?;
-                        return Ok(());
-                    }
+                if self.is_blocked(&joiner) {
+                    tracing::info!(%id, remote = %joiner.addr, "Inbound connection from peer blocked by local policy");
+                    // Not proceeding with adding connection or processing the operation.
+                    handshake_handler_msg
+                        .drop_connection_by_addr(joiner.addr)
+                        .await?;
+                    return Ok(());
                 }
                 let (tx, rx) = mpsc::channel(1);
                 self.connections.insert(joiner.clone(), tx);
+                self.bridge
+                    .op_manager
+                    .ring
+                    .connection_manager
+                    .track_round_trip_time(joiner.addr, conn.round_trip_time());
+                self.bridge
+                    .op_manager
+                    .ring
+                    .connection_manager
+                    .track_traffic(joiner.addr, conn.traffic());
                 let was_reserved = {
                     // this is an unexpected inbound request at a gateway so it didn't have a reserved spot
                     false
@@ -643,8 +790,16 @@ impl P2pConnManager {
     async fn try_to_forward(&mut self, forward_to: &PeerId, msg: NetMessage) -> anyhow::Result<()> {
         if let Some(peer) = self.connections.get(forward_to) {
             tracing::debug!(%forward_to, %msg, "Forwarding message to peer");
+            let request_timeout = self
+                .bridge
+                .op_manager
+                .ring
+                .connection_manager
+                .connection_timeouts(&forward_to.addr)
+                .request;
             // TODO: review: this could potentially leave garbage tasks in the background with peer listener
-            timeout(Duration::from_secs(1), peer.send(Left(msg)))
+            let priority = self.bridge.op_manager.priority(msg.id());
+            timeout(request_timeout, peer.send(Left((msg, priority))))
                 .await
                 .inspect_err(|error| {
                     tracing::error!("Failed to forward message to peer: {:?}", error);
@@ -691,6 +846,16 @@ impl P2pConnManager {
         }
         let (tx, rx) = mpsc::channel(10);
         self.connections.insert(peer_id.clone(), tx);
+        self.bridge
+            .op_manager
+            .ring
+            .connection_manager
+            .track_round_trip_time(peer_id.addr, connection.round_trip_time());
+        self.bridge
+            .op_manager
+            .ring
+            .connection_manager
+            .track_traffic(peer_id.addr, connection.traffic());
         let task = peer_connection_listener(rx, connection).boxed();
         state.peer_connections.push(task);
         Ok(())
@@ -704,18 +869,45 @@ impl P2pConnManager {
     ) -> anyhow::Result<EventResult> {
         match msg {
             Some(Ok(peer_conn)) => {
+                let remote_addr = peer_conn.conn.remote_addr();
                 let task = peer_connection_listener(peer_conn.rx, peer_conn.conn).boxed();
                 state.peer_connections.push(task);
-                Ok(EventResult::Event(ConnEvent::InboundMessage(peer_conn.msg)))
+                match peer_conn.msg {
+                    Ok(msg) => match Violation::of_message(&msg) {
+                        Some(violation) => {
+                            tracing::warn!(
+                                %remote_addr,
+                                tx = %msg.id(),
+                                "Received message with impossible route from peer"
+                            );
+                            Ok(self.report_violation(remote_addr, violation))
+                        }
+                        None => Ok(self.admit_inbound(remote_addr, msg)),
+                    },
+                    Err(error) => {
+                        tracing::warn!(%remote_addr, %error, "Received invalid message from peer");
+                        Ok(self.report_violation(remote_addr, Violation::MalformedMessage))
+                    }
+                }
             }
             Some(Err(err)) => {
-                if let TransportError::ConnectionClosed(socket_addr) = err {
+                if let TransportError::Unauthenticated(socket_addr) = err {
+                    tracing::warn!(
+                        remote_addr = %socket_addr,
+                        "Packets from peer kept failing authentication"
+                    );
+                    self.report_violation(socket_addr, Violation::InvalidSignature);
+                }
+                if let TransportError::ConnectionClosed(socket_addr)
+                | TransportError::Unauthenticated(socket_addr) = err
+                {
                     if let Some(peer) = self
                         .connections
                         .keys()
                         .find_map(|k| (k.addr == socket_addr).then(|| k.clone()))
                     {
                         tracing::debug!(%peer, "Dropping connection");
+                        self.inbound_budgets.forget(&socket_addr);
                         self.bridge
                             .op_manager
                             .ring
@@ -734,6 +926,82 @@ impl P2pConnManager {
         }
     }
 
+    /// Records a violation of the protocol by a peer, lowering its reputation and disconnecting
+    /// it when the violations add up.
+    fn report_violation(&self, remote_addr: SocketAddr, violation: Violation) -> EventResult {
+        let Some(peer) = self.connections.keys().find(|k| k.addr == remote_addr) else {
+            return EventResult::Continue;
+        };
+        let connection_manager = &self.bridge.op_manager.ring.connection_manager;
+        connection_manager
+            .reputation()
+            .record(peer, PeerEvent::InvalidMessage);
+        match connection_manager
+            .bans()
+            .record(peer, violation, SystemTime::now())
+        {
+            Penalty::Warn => EventResult::Continue,
+            penalty => {
+                if let Penalty::Ban(duration) = penalty {
+                    tracing::warn!(
+                        %peer,
+                        ?violation,
+                        ?duration,
+                        "Banning peer violating the protocol"
+                    );
+                } else {
+                    tracing::warn!(%peer, ?violation, "Disconnecting peer violating the protocol");
+                }
+                EventResult::Event(ConnEvent::NodeAction(NodeEvent::DropConnection(
+                    peer.clone(),
+                )))
+            }
+        }
+    }
+
+    /// Charges a message from a peer to its inbound budget, dropping it when over budget.
+    fn admit_inbound(&mut self, remote_addr: SocketAddr, msg: NetMessage) -> EventResult {
+        let initiates_op = !self.bridge.op_manager.is_known(msg.id());
+        let admission = self
+            .inbound_budgets
+            .admit(remote_addr, initiates_op, Instant::now());
+        let peer = match admission {
+            Admission::Accept => return EventResult::Event(ConnEvent::InboundMessage(msg)),
+            Admission::Drop => {
+                tracing::debug!(
+                    %remote_addr,
+                    tx = %msg.id(),
+                    "Peer over its inbound budget, dropping message"
+                );
+                return EventResult::Continue;
+            }
+            Admission::Penalize | Admission::Disconnect => {
+                self.connections.keys().find(|k| k.addr == remote_addr)
+            }
+        };
+        let Some(peer) = peer else {
+            return EventResult::Continue;
+        };
+        match admission {
+            Admission::Penalize => {
+                tracing::warn!(%peer, "Peer flooding the node with messages");
+                self.bridge
+                    .op_manager
+                    .ring
+                    .connection_manager
+                    .reputation()
+                    .record(peer, PeerEvent::Flooding);
+                EventResult::Continue
+            }
+            _ => {
+                tracing::warn!(%peer, "Peer kept flooding the node, dropping connection");
+                EventResult::Event(ConnEvent::NodeAction(NodeEvent::DropConnection(
+                    peer.clone(),
+                )))
+            }
+        }
+    }
+
     fn handle_notification_msg(&self, msg: Option<Either<NetMessage, NodeEvent>>) -> EventResult {
         match msg {
             Some(Left(msg)) => EventResult::Event(ConnEvent::InboundMessage(msg)),
@@ -758,7 +1026,16 @@ impl P2pConnManager {
 
     fn handle_bridge_msg(&self, msg: Option<P2pBridgeEvent>) -> EventResult {
         match msg {
-            Some(Left((_, msg))) => EventResult::Event(ConnEvent::OutboundMessage(*msg)),
+            // aborts don't carry their target, those of gets are sent to the peer given so the
+            // paths of gets raced to several peers are aborted
+            Some(Left((target, msg))) => match *msg {
+                NetMessage::V1(NetMessageV1::Aborted(tx))
+                    if tx.transaction_type() == TransactionType::Get =>
+                {
+                    EventResult::Event(ConnEvent::OutboundAborted { target, tx })
+                }
+                msg => EventResult::Event(ConnEvent::OutboundMessage(msg)),
+            },
             Some(Right(action)) => EventResult::Event(ConnEvent::NodeAction(action)),
             None => EventResult::Event(ConnEvent::ClosedChannel),
         }
@@ -900,6 +1177,11 @@ enum EventResult {
 enum ConnEvent {
     InboundMessage(NetMessage),
     OutboundMessage(NetMessage),
+    /// Abort of a transaction to send to the peer.
+    OutboundAborted {
+        target: PeerId,
+        tx: Transaction,
+    },
     NodeAction(NodeEvent),
     ClosedChannel,
 }
@@ -916,7 +1198,7 @@ struct PeerConnectionInbound {
     conn: PeerConnection,
     /// Receiver for inbound messages for the peer connection
     rx: Receiver<Either<NetMessage, ConnEvent>>,
-    msg: NetMessage,
+    msg: Result<NetMessage, ConnectionError>,
 }
 
 async fn peer_connection_listener(
@@ -928,11 +1210,13 @@ async fn peer_connection_listener(
             msg = rx.recv() => {
                 let Some(msg) = msg else { break Err(TransportError::ConnectionClosed(conn.remote_addr())); };
                 match msg {
-                    Left(msg) => {
+                    Left((msg, priority)) => {
                         tracing::debug!(to=%conn.remote_addr() ,"Sending message to peer. Msg: {msg}");
-                        conn
-                            .send(msg)
-                            .await?;
+                        if priority == Priority::Background {
+                            conn.send_background(msg).await?;
+                        } else {
+                            conn.send(msg).await?;
+                        }
                     }
                     Right(action) => {
                         tracing::debug!(to=%conn.remote_addr(), "Received action from channel");
@@ -948,21 +1232,52 @@ async fn peer_connection_listener(
                 }
             }
             msg = conn.recv() => {
-                let Ok(msg) = msg
-                    .inspect_err(|error| {
+                let msg = match msg {
+                    Ok(msg) => msg,
+                    Err(error @ TransportError::Unauthenticated(_)) => break Err(error),
+                    Err(error) => {
                         tracing::error!(from=%conn.remote_addr(), "Error while receiving message: {error}");
-                    })
-                else {
-                    break Err(TransportError::ConnectionClosed(conn.remote_addr()));
+                        break Err(TransportError::ConnectionClosed(conn.remote_addr()));
+                    }
                 };
-                let net_message = decode_msg(&msg).unwrap();
-                tracing::debug!(from=%conn.remote_addr() ,"Received message from peer. Msg: {net_message}");
+                let net_message = decode_msg(&msg);
+                if let Ok(net_message) = &net_message {
+                    tracing::debug!(from=%conn.remote_addr() ,"Received message from peer. Msg: {net_message}");
+                }
                 break Ok(PeerConnectionInbound { conn, rx, msg: net_message });
             }
         }
     }
 }
 
+/// Learns how the NAT of this peer maps its socket from the reflexive addresses reported by
+/// the gateways, periodically since mappings may change.
+async fn discover_nat_mapping(
+    outbound_conn_handler: OutboundConnectionHandler,
+    gateways: Vec<SocketAddr>,
+    connection_manager: ConnectionManager,
+) {
+    const DISCOVERY_INTERVAL: Duration = Duration::from_secs(10 * 60);
+    let mut interval = tokio::time::interval(DISCOVERY_INTERVAL);
+    loop {
+        interval.tick().await;
+        let reflexive: Vec<_> = futures::future::join_all(
+            gateways
+                .iter()
+                .map(|gw| outbound_conn_handler.reflexive_address(*gw)),
+        )
+        .await
+        .into_iter()
+        .filter_map(Result::ok)
+        .collect();
+        let mapping = NatMapping::from_reflexive(&reflexive);
+        tracing::debug!(?reflexive, ?mapping, "Discovered NAT mapping");
+        if mapping != NatMapping::Unknown {
+            connection_manager.set_nat_mapping(mapping);
+        }
+    }
+}
+
 #[inline(always)]
 fn decode_msg(data: &[u8]) -> Result<NetMessage, ConnectionError> {
     bincode::deserialize(data).map_err(|err| ConnectionError::Serialization(Some(err)))
@@ -13,17 +13,25 @@ use either::Either;
 use tracing::Instrument;
 
 use crate::{
-    config::GlobalExecutor,
+    config::{GlobalExecutor, RetryPolicies, RetryPolicy},
     contract::{ContractError, ContractHandlerChannel, ContractHandlerEvent, SenderHalve},
-    message::{MessageStats, NetMessage, NodeEvent, Transaction, TransactionType},
+    message::{MessageStats, NetMessage, NodeEvent, Priority, Transaction, TransactionType},
     operations::{
-        connect::ConnectOp, get::GetOp, put::PutOp, subscribe::SubscribeOp, update::UpdateOp,
+        connect::ConnectOp,
+        get::GetOp,
+        put::PutOp,
+        subscribe::SubscribeOp,
+        update::{UpdateCoalescer, UpdateOp},
         OpEnum, OpError,
     },
     ring::{ConnectionManager, LiveTransactionTracker, Ring},
+    router::{PeerEvent, Reputation},
 };
 
-use super::{network_bridge::EventLoopNotificationsSender, NetEventRegister, NodeConfig, PeerId};
+use super::{
+    neighborhood::NeighborhoodBootstrap, network_bridge::EventLoopNotificationsSender,
+    NetEventRegister, NodeConfig, PeerId,
+};
 
 #[cfg(debug_assertions)]
 macro_rules! check_id_op {
@@ -61,6 +69,18 @@ pub(crate) struct OpManager {
     to_event_listener: EventLoopNotificationsSender,
     pub ch_outbound: ContractHandlerChannel<SenderHalve>,
     new_transactions: tokio::sync::mpsc::Sender<Transaction>,
+    /// Contracts near the location of this node fetched from its neighbors when joining.
+    pub neighborhood: NeighborhoodBootstrap,
+    /// Updates of contracts updated in quick succession waiting to be broadcast.
+    pub update_coalescer: UpdateCoalescer,
+    retry_policies: RetryPolicies,
+    /// Peers the gets started by this node are sent to at once.
+    pub get_fanout: usize,
+    /// Retry policies requested by clients for their own operations.
+    retry_overrides: DashMap<Transaction, RetryPolicy>,
+    /// Operations started by this node running in the background. Their priority is only known
+    /// to this node, peers handling them treat them as any other operation.
+    background: DashSet<Transaction>,
 }
 
 impl OpManager {
@@ -92,6 +112,7 @@ impl OpManager {
                 rx,
                 ops.clone(),
                 ring.live_tx_tracker.clone(),
+                ring.connection_manager.reputation().clone(),
                 notification_channel.clone(),
                 event_register,
             )
@@ -104,6 +125,12 @@ impl OpManager {
             to_event_listener: notification_channel,
             ch_outbound,
             new_transactions,
+            neighborhood: NeighborhoodBootstrap::new(config.config.network_api.bootstrap_limits()),
+            update_coalescer: UpdateCoalescer::default(),
+            retry_policies: config.config.network_api.retries,
+            get_fanout: config.config.network_api.get_fanout.max(1),
+            retry_overrides: DashMap::new(),
+            background: DashSet::new(),
         })
     }
 
@@ -122,6 +149,16 @@ impl OpManager {
             .map_err(Into::into)
     }
 
+    /// Processes a message originating in this node which isn't part of any operation, as if
+    /// received from the network.
+    pub async fn notify_message(&self, msg: NetMessage) -> Result<(), OpError> {
+        self.to_event_listener
+            .notifications_sender
+            .send(Either::Left(msg))
+            .await
+            .map_err(Into::into)
+    }
+
     // An early, fast path, return for communicating events in the node to the main message handler,
     // without any transmission in the network whatsoever and avoiding any state transition.
     //
@@ -231,16 +268,99 @@ impl OpManager {
                 .remove(id)
                 .map(|(_k, v)| v)
                 .map(OpEnum::Update),
+            TransactionType::Neighborhood => None,
         };
         self.ops.under_progress.insert(*id);
         Ok(op)
     }
 
+    /// Transactions of the operations currently in flight.
+    pub fn in_flight(&self) -> Vec<Transaction> {
+        let ops = &self.ops;
+        ops.connect
+            .iter()
+            .map(|op| *op.key())
+            .chain(ops.put.iter().map(|op| *op.key()))
+            .chain(ops.get.iter().map(|op| *op.key()))
+            .chain(ops.subscribe.iter().map(|op| *op.key()))
+            .chain(ops.update.iter().map(|op| *op.key()))
+            .chain(ops.under_progress.iter().map(|tx| *tx))
+            .filter(|tx| !ops.completed.contains(tx))
+            .collect()
+    }
+
+    /// Whether the transaction belongs to an operation this node is or was part of.
+    pub fn is_known(&self, id: &Transaction) -> bool {
+        let ops = &self.ops;
+        if ops.completed.contains(id) || ops.under_progress.contains(id) {
+            return true;
+        }
+        match id.transaction_type() {
+            TransactionType::Connect => ops.connect.contains_key(id),
+            TransactionType::Put => ops.put.contains_key(id),
+            TransactionType::Get => ops.get.contains_key(id),
+            TransactionType::Subscribe => ops.subscribe.contains_key(id),
+            TransactionType::Update => ops.update.contains_key(id),
+            TransactionType::Neighborhood => false,
+        }
+    }
+
     pub fn completed(&self, id: Transaction) {
         self.ring.live_tx_tracker.remove_finished_transaction(id);
+        self.neighborhood.finished(&id);
+        self.retry_overrides.remove(&id);
+        self.background.remove(&id);
         self.ops.completed.insert(id);
     }
 
+    /// Drops an operation nobody waits on anymore, messages for it are ignored from now on.
+    pub fn cancel(&self, id: Transaction) {
+        let _ = self.pop(&id);
+        self.ops.under_progress.remove(&id);
+        self.completed(id);
+    }
+
+    /// Retry policy that applies to the given operation.
+    pub fn retry_policy(&self, id: &Transaction) -> RetryPolicy {
+        if let Some(policy) = self.retry_overrides.get(id) {
+            return *policy;
+        }
+        match id.transaction_type() {
+            TransactionType::Put => self.retry_policies.put,
+            TransactionType::Subscribe => self.retry_policies.subscribe,
+            _ => self.retry_policies.get,
+        }
+    }
+
+    /// Applies a retry policy requested by a client to one of its operations.
+    pub fn override_retry_policy(&self, id: Transaction, policy: RetryPolicy) {
+        self.retry_overrides.retain(|id, _| !id.timed_out());
+        self.retry_overrides.insert(id, policy);
+    }
+
+    /// Priority of the given operation, interactive unless started by this node as a background
+    /// one.
+    pub fn priority(&self, id: &Transaction) -> Priority {
+        if self.background.contains(id) {
+            Priority::Background
+        } else {
+            Priority::Interactive
+        }
+    }
+
+    /// Sets the priority of an operation started by this node.
+    pub fn set_priority(&self, id: Transaction, priority: Priority) {
+        match priority {
+            Priority::Interactive => {
+                self.background.remove(&id);
+            }
+            Priority::Background => {
+                self.background.retain(|id| !id.timed_out());
+                self.background.insert(id);
+            }
+        }
+    }
+
     /// Notify the operation manager that a transaction is being transacted over the network.
     pub fn sending_transaction(&self, peer: &PeerId, msg: &NetMessage) {
         let transaction = msg.id();
@@ -258,6 +378,7 @@ async fn garbage_cleanup_task<ER: NetEventRegister>(
     mut new_transactions: tokio::sync::mpsc::Receiver<Transaction>,
     ops: Arc<Ops>,
     live_tx_tracker: LiveTransactionTracker,
+    reputation: Reputation,
     event_loop_notifier: EventLoopNotificationsSender,
     mut event_register: ER,
 ) {
@@ -292,6 +413,7 @@ async fn garbage_cleanup_task<ER: NetEventRegister>(
                         TransactionType::Get => ops.get.remove(&tx).is_none(),
                         TransactionType::Subscribe => ops.subscribe.remove(&tx).is_none(),
                         TransactionType::Update => ops.update.remove(&tx).is_none(),
+                        TransactionType::Neighborhood => false,
                     };
                     if still_waiting  {
                         delayed.push(tx);
@@ -299,6 +421,9 @@ async fn garbage_cleanup_task<ER: NetEventRegister>(
                         ops.under_progress.remove(&tx);
                         ops.completed.remove(&tx);
                         tracing::debug!("Transaction timed out: {tx}");
+                        for peer in live_tx_tracker.peers_of(&tx) {
+                            reputation.record(&peer, PeerEvent::Timeout);
+                        }
                         event_loop_notifier.notifications_sender.send(Either::Right(NodeEvent::TransactionTimedOut(tx))).await.unwrap();
                         live_tx_tracker.remove_finished_transaction(tx);
                     }
@@ -325,9 +450,13 @@ async fn garbage_cleanup_task<ER: NetEventRegister>(
                         TransactionType::Get => ops.get.remove(&tx).is_some(),
                         TransactionType::Subscribe => ops.subscribe.remove(&tx).is_some(),
                         TransactionType::Update => ops.update.remove(&tx).is_some(),
+                        TransactionType::Neighborhood => false,
                     };
                     if removed {
                         tracing::debug!("Transaction timed out: {tx}");
+                        for peer in live_tx_tracker.peers_of(&tx) {
+                            reputation.record(&peer, PeerEvent::Timeout);
+                        }
                         event_loop_notifier.notifications_sender.send(Either::Right(NodeEvent::TransactionTimedOut(tx))).await.unwrap();
                         live_tx_tracker.remove_finished_transaction(tx);
                     }
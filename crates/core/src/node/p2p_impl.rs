@@ -96,6 +96,11 @@ impl NodeP2P {
             event_register.clone(),
             connection_manager,
         )?);
+        super::admin::NodeHandle::register(
+            op_manager.clone(),
+            config.config.clone(),
+            config.gateways.iter().map(|gw| gw.peer_id.addr),
+        );
         let (executor_listener, executor_sender) = contract::executor_channel(op_manager.clone());
         let contract_handler = CH::build(ch_inbound, executor_sender, ch_builder)
             .await
@@ -0,0 +1,194 @@
+//! Persistence of the peers the node is connected to and of their reputation, so a restarted
+//! node rejoins the network through them in seconds instead of bootstrapping again entirely
+//! through its gateways.
+//!
+//! The connected peers, most reliable first, and the reputation of every peer tracked are saved
+//! to the data directory periodically. At startup the reputation is restored and the node
+//! connects straight to the peers saved, as with [LAN peers](super::mdns), while still joining
+//! through its gateways in case none of them is reachable anymore.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    operations::{connect, OpError},
+    ring::{ConnectionManager, PeerKeyLocation},
+    router::{PeerStats, Reputation},
+};
+
+use super::{ConnectionError, OpManager, PeerId};
+
+const CACHE_FILE: &str = "peers.bin";
+
+/// Interval the peers are saved at.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Peers saved at most, the least reliable ones being left out.
+const MAX_PEERS: usize = 64;
+
+/// Age after which the peers saved are likely gone and not connected to anymore.
+const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedPeers {
+    /// Unix timestamp, in seconds, of when the peers were saved.
+    saved_at: u64,
+    /// Peers connected to, most reliable first.
+    peers: Vec<PeerKeyLocation>,
+    reputation: Vec<(PeerId, PeerStats)>,
+}
+
+impl SavedPeers {
+    fn new(mut peers: Vec<PeerKeyLocation>, reputation: &Reputation, now: SystemTime) -> Self {
+        peers.sort_by(|a, b| {
+            reputation
+                .score(&b.peer)
+                .total_cmp(&reputation.score(&a.peer))
+        });
+        peers.truncate(MAX_PEERS);
+        Self {
+            saved_at: now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+            peers,
+            reputation: reputation.stats(),
+        }
+    }
+
+    fn read(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        Ok(bincode::deserialize(&bytes)?)
+    }
+
+    fn write(&self, path: &Path) -> anyhow::Result<()> {
+        // written aside first so a crash while saving doesn't lose the previous peers
+        let tmp = path.with_extension("bin.tmp");
+        std::fs::write(&tmp, bincode::serialize(self)?)?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// Peers worth connecting to at `now`, none if saved too long ago.
+    fn rejoin_peers(&self, now: SystemTime) -> &[PeerKeyLocation] {
+        let saved_at = UNIX_EPOCH + Duration::from_secs(self.saved_at);
+        match now.duration_since(saved_at) {
+            Ok(age) if age > MAX_AGE => &[],
+            _ => &self.peers,
+        }
+    }
+}
+
+/// The file the peers of the node are saved to.
+pub(crate) struct PeerCache {
+    path: PathBuf,
+}
+
+impl PeerCache {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            path: config.db_dir().join(CACHE_FILE),
+        }
+    }
+
+    fn save(&self, connection_manager: &ConnectionManager) {
+        let peers = connection_manager
+            .peer_locations()
+            .into_iter()
+            .map(|(peer, location)| PeerKeyLocation {
+                peer,
+                location: Some(location),
+            })
+            .collect();
+        let saved = SavedPeers::new(peers, connection_manager.reputation(), SystemTime::now());
+        match saved.write(&self.path) {
+            Ok(()) => tracing::debug!(count = saved.peers.len(), "Saved connected peers"),
+            Err(error) => tracing::warn!(%error, "Failed saving connected peers"),
+        }
+    }
+}
+
+/// Restores the reputation of the peers saved and connects to them, saving the peers connected
+/// to periodically afterwards.
+pub(crate) async fn rejoin(cache: PeerCache, op_manager: Arc<OpManager>) {
+    let connection_manager = &op_manager.ring.connection_manager;
+    match SavedPeers::read(&cache.path) {
+        Ok(saved) => {
+            connection_manager
+                .reputation()
+                .restore(saved.reputation.iter().cloned());
+            let peers = saved.rejoin_peers(SystemTime::now());
+            tracing::info!(count = peers.len(), "Rejoining through saved peers");
+            for peer in peers {
+                if op_manager
+                    .ring
+                    .is_not_connected(std::iter::once(peer))
+                    .next()
+                    .is_none()
+                {
+                    continue;
+                }
+                if let Err(error) = connect::join_ring_request(None, peer, &op_manager).await {
+                    if !matches!(
+                        error,
+                        OpError::ConnError(ConnectionError::UnwantedConnection)
+                    ) {
+                        tracing::debug!(%peer, %error, "Failed connecting to saved peer");
+                    }
+                }
+            }
+        }
+        Err(error) => tracing::debug!(%error, "No saved peers to rejoin through"),
+    }
+
+    let mut interval = tokio::time::interval(SAVE_INTERVAL);
+    // the first tick completes immediately, before any connection is established
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        cache.save(connection_manager);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::router::PeerEvent;
+
+    use super::*;
+
+    #[test]
+    fn saves_and_restores_peers() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(CACHE_FILE);
+        let reputation = Reputation::default();
+        let reliable = PeerKeyLocation::random();
+        let flaky = PeerKeyLocation::random();
+        for _ in 0..10 {
+            reputation.record(&reliable.peer, PeerEvent::Success);
+        }
+        reputation.record(&flaky.peer, PeerEvent::Timeout);
+        let now = UNIX_EPOCH + Duration::from_secs(1_900_000_000);
+
+        SavedPeers::new(vec![flaky.clone(), reliable.clone()], &reputation, now).write(&path)?;
+        let saved = SavedPeers::read(&path)?;
+        assert_eq!(saved.rejoin_peers(now), [reliable.clone(), flaky.clone()]);
+
+        // the statistics of the peers are restored, without replacing those tracked since
+        let restored = Reputation::default();
+        restored.record(&flaky.peer, PeerEvent::Success);
+        restored.restore(saved.reputation);
+        assert_eq!(
+            restored.score(&reliable.peer),
+            reputation.score(&reliable.peer)
+        );
+        assert!(restored.score(&flaky.peer) > reputation.score(&flaky.peer));
+
+        // peers saved long ago are not connected to
+        let saved = SavedPeers::read(&path)?;
+        assert!(saved.rejoin_peers(now + MAX_AGE * 2).is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+//! Lists of the peers the node is allowed or denied to connect with, enforced when connections
+//! are established.
+//!
+//! Peers are listed by the fingerprint of their public key (see
+//! [`TransportPublicKey::fingerprint`]) or by the IP address or network (CIDR) they connect
+//! from. Denied peers are always refused, and when any peer is allowed only the allowed ones
+//! are accepted, e.g. to build private networks, which must then allow their gateways too.
+
+use std::{net::IpAddr, str::FromStr, sync::Arc};
+
+use ipnet::IpNet;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use super::PeerId;
+
+/// A peer or set of peers listed in a [`PeerPolicy`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum PeerRule {
+    Key(String),
+    Network(IpNet),
+}
+
+impl FromStr for PeerRule {
+    type Err = anyhow::Error;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        if let Ok(network) = rule.parse::<IpNet>() {
+            return Ok(Self::Network(network));
+        }
+        if let Ok(ip) = rule.parse::<IpAddr>() {
+            return Ok(Self::Network(ip.into()));
+        }
+        match bs58::decode(rule).into_vec() {
+            Ok(hash) if hash.len() == blake3::OUT_LEN => Ok(Self::Key(rule.to_owned())),
+            _ => Err(anyhow::anyhow!(
+                "invalid peer `{rule}`, expecting a key fingerprint, an IP address or a network"
+            )),
+        }
+    }
+}
+
+impl PeerRule {
+    fn matches(&self, peer: &PeerId, fingerprint: &str) -> bool {
+        match self {
+            Self::Key(key) => key == fingerprint,
+            Self::Network(network) => network.contains(&peer.addr.ip()),
+        }
+    }
+}
+
+/// Peers listed as allowed or denied, as configured.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct PeerLists {
+    #[serde(default)]
+    pub allowed: Vec<String>,
+    #[serde(default)]
+    pub denied: Vec<String>,
+}
+
+#[derive(Default)]
+struct Rules {
+    lists: PeerLists,
+    allowed: Vec<PeerRule>,
+    denied: Vec<PeerRule>,
+}
+
+/// Peers allowed to connect with the node, adjustable at runtime.
+#[derive(Clone, Default)]
+pub(crate) struct PeerPolicy(Arc<RwLock<Rules>>);
+
+impl PeerPolicy {
+    pub fn new(lists: PeerLists) -> anyhow::Result<Self> {
+        let policy = Self::default();
+        policy.set(lists)?;
+        Ok(policy)
+    }
+
+    /// Replaces the lists, leaving them unchanged if any entry is invalid.
+    pub fn set(&self, lists: PeerLists) -> anyhow::Result<()> {
+        let parse = |entries: &[String]| {
+            entries
+                .iter()
+                .map(|entry| entry.parse())
+                .collect::<anyhow::Result<Vec<PeerRule>>>()
+        };
+        let allowed = parse(&lists.allowed)?;
+        let denied = parse(&lists.denied)?;
+        *self.0.write() = Rules {
+            lists,
+            allowed,
+            denied,
+        };
+        Ok(())
+    }
+
+    pub fn lists(&self) -> PeerLists {
+        self.0.read().lists.clone()
+    }
+
+    pub fn is_allowed(&self, peer: &PeerId) -> bool {
+        let rules = self.0.read();
+        if rules.allowed.is_empty() && rules.denied.is_empty() {
+            return true;
+        }
+        let fingerprint = peer.pub_key.fingerprint();
+        if rules
+            .denied
+            .iter()
+            .any(|rule| rule.matches(peer, &fingerprint))
+        {
+            return false;
+        }
+        rules.allowed.is_empty()
+            || rules
+                .allowed
+                .iter()
+                .any(|rule| rule.matches(peer, &fingerprint))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use crate::transport::TransportKeypair;
+
+    use super::*;
+
+    fn peer(addr: &str) -> PeerId {
+        let addr: SocketAddr = addr.parse().unwrap();
+        PeerId::new(addr, TransportKeypair::new().public().clone())
+    }
+
+    #[test]
+    fn allowed_and_denied_peers() -> anyhow::Result<()> {
+        let trusted = peer("203.0.113.5:31337");
+        let abusive = peer("198.51.100.7:31337");
+        let policy = PeerPolicy::new(PeerLists {
+            allowed: vec![],
+            denied: vec!["198.51.100.0/24".into()],
+        })?;
+        assert!(policy.is_allowed(&trusted));
+        assert!(!policy.is_allowed(&abusive));
+
+        policy.set(PeerLists {
+            allowed: vec![trusted.pub_key.fingerprint(), "2001:db8::/32".into()],
+            denied: vec![],
+        })?;
+        assert!(policy.is_allowed(&trusted));
+        assert!(!policy.is_allowed(&abusive));
+        assert!(policy.is_allowed(&peer("[2001:db8::1]:31337")));
+
+        let lists = policy.lists();
+        assert!(policy
+            .set(PeerLists {
+                allowed: vec!["not a peer".into()],
+                denied: vec![],
+            })
+            .is_err());
+        assert_eq!(policy.lists(), lists);
+        Ok(())
+    }
+}
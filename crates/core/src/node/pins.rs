@@ -0,0 +1,144 @@
+//! Contracts pinned by the operator, so they stay available from the node regardless of the
+//! pressure on its caches.
+//!
+//! The node seeds the contracts pinned: it always stores their state, never drops them for
+//! contracts closer to its location and keeps subscribed to their updates, subscribing again
+//! every [`REFRESH_INTERVAL`] if it isn't anymore, e.g. after restarting. Pins are saved to the
+//! data directory so they survive restarts, and are listed, added and removed through the admin
+//! API.
+
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+
+use freenet_stdlib::prelude::{ContractInstanceId, ContractKey};
+use parking_lot::RwLock;
+
+use crate::{
+    config::{Config, GlobalExecutor},
+    message::Priority,
+};
+
+use super::OpManager;
+
+const PINS_FILE: &str = "pins.json";
+
+/// Interval the node checks it still seeds the contracts pinned at.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Default)]
+struct State {
+    /// Where the pins are saved to, kept in memory only without.
+    path: Option<PathBuf>,
+    pins: HashMap<ContractInstanceId, ContractKey>,
+}
+
+impl State {
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let pins: Vec<_> = self.pins.values().collect();
+        let saved = serde_json::to_vec_pretty(&pins)
+            .map_err(anyhow::Error::from)
+            .and_then(|pins| Ok(std::fs::write(path, pins)?));
+        if let Err(error) = saved {
+            tracing::warn!(%error, "Failed saving pinned contracts");
+        }
+    }
+}
+
+/// Contracts pinned to the node, shared by the components seeding and caching contracts.
+#[derive(Clone, Default)]
+pub(crate) struct PinnedContracts(Arc<RwLock<State>>);
+
+impl PinnedContracts {
+    /// Loads the pins saved in the data directory of the node.
+    pub fn load(config: &Config) -> Self {
+        let path = config.db_dir().join(PINS_FILE);
+        let pins: Vec<ContractKey> = match std::fs::read(&path) {
+            Ok(pins) => serde_json::from_slice(&pins).unwrap_or_else(|error| {
+                tracing::warn!(%error, "Failed loading pinned contracts");
+                vec![]
+            }),
+            Err(_) => vec![],
+        };
+        Self(Arc::new(RwLock::new(State {
+            path: Some(path),
+            pins: pins.into_iter().map(|key| (*key.id(), key)).collect(),
+        })))
+    }
+
+    /// Pins the contract, returning whether it wasn't already.
+    pub fn pin(&self, key: ContractKey) -> bool {
+        let mut state = self.0.write();
+        let pinned = state.pins.insert(*key.id(), key).is_none();
+        if pinned {
+            state.save();
+        }
+        pinned
+    }
+
+    /// Unpins the contract, returning whether it was pinned.
+    pub fn unpin(&self, id: &ContractInstanceId) -> bool {
+        let mut state = self.0.write();
+        let unpinned = state.pins.remove(id).is_some();
+        if unpinned {
+            state.save();
+        }
+        unpinned
+    }
+
+    pub fn is_pinned(&self, key: &ContractKey) -> bool {
+        self.0.read().pins.contains_key(key.id())
+    }
+
+    /// The contracts pinned.
+    pub fn list(&self) -> Vec<ContractKey> {
+        self.0.read().pins.values().copied().collect()
+    }
+}
+
+/// Seeds the contract pinned and subscribes to its updates, fetching it first if the node doesn't
+/// store it yet.
+pub(crate) async fn seed_pinned(op_manager: Arc<OpManager>, key: ContractKey) {
+    let own_location = op_manager.ring.connection_manager.own_location();
+    if own_location.location.is_none() || op_manager.ring.is_seeding_contract(&key) {
+        return;
+    }
+    tracing::debug!(%key, "Seeding pinned contract");
+    op_manager.ring.seed_contract(key);
+    if let Err(error) = super::subscribe(op_manager, key, None, Priority::Background).await {
+        tracing::warn!(%key, %error, "Failed subscribing to pinned contract");
+    }
+}
+
+/// Keeps seeding the contracts pinned, subscribing again to those the node isn't seeding, e.g.
+/// after restarting.
+pub(crate) async fn keep_pinned(op_manager: Arc<OpManager>) {
+    let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+    loop {
+        interval.tick().await;
+        for key in op_manager.ring.pins().list() {
+            GlobalExecutor::spawn(seed_pinned(op_manager.clone(), key));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pins_contracts() {
+        let pins = PinnedContracts::default();
+        let key = ContractKey::from(ContractInstanceId::new([1; 32]));
+        let other = ContractKey::from(ContractInstanceId::new([2; 32]));
+        assert!(pins.pin(key));
+        assert!(!pins.pin(key));
+        assert!(pins.is_pinned(&key));
+        assert!(!pins.is_pinned(&other));
+        assert_eq!(pins.list(), vec![key]);
+        assert!(pins.unpin(key.id()));
+        assert!(!pins.unpin(key.id()));
+        assert!(pins.list().is_empty());
+    }
+}
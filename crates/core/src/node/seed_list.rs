@@ -0,0 +1,254 @@
+//! Signed lists of gateways fetched from a URL, so the gateways nodes bootstrap from can rotate
+//! without shipping a new binary.
+//!
+//! A list is a TOML document with an increasing `version`, an optional `expires` Unix timestamp
+//! and the `gateways` with their `address` and `key`, encoded as in the DNS seed records. It is
+//! signed with an ed25519 key, the base58 signature being served at the URL of the list with a
+//! `.sig` suffix. Lists not signed by one of the pinned keys, expired, or older than the last one
+//! accepted are rejected. The last list accepted is kept in the config directory, so the node
+//! still finds gateways when the URL is unreachable.
+
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::Deserialize;
+
+use crate::{
+    config::{Address, Config},
+    ring::{ConnectionManager, Location, PeerKeyLocation},
+};
+
+use super::{dns_seed, NodeConfig, PeerId};
+
+/// Interval the list is fetched again at.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+const CACHE_FILE: &str = "seed-list.toml";
+
+#[derive(Debug, Deserialize)]
+struct ListedGateway {
+    address: String,
+    key: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GatewayList {
+    version: u64,
+    expires: Option<u64>,
+    gateways: Vec<ListedGateway>,
+}
+
+/// Verifies the signature of the list by one of the keys, returning the list if valid and not
+/// expired at `now`.
+fn verify(
+    list: &[u8],
+    signature: &str,
+    keys: &[VerifyingKey],
+    now: SystemTime,
+) -> anyhow::Result<GatewayList> {
+    let signature: [u8; ed25519_dalek::SIGNATURE_LENGTH] = bs58::decode(signature.trim())
+        .into_vec()
+        .context("invalid signature encoding")?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("invalid signature length"))?;
+    let signature = Signature::from_bytes(&signature);
+    if !keys.iter().any(|key| key.verify(list, &signature).is_ok()) {
+        anyhow::bail!("list not signed by any of the pinned keys");
+    }
+    let list: GatewayList = toml::from_str(std::str::from_utf8(list)?)?;
+    let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    if list.expires.is_some_and(|expires| expires <= now) {
+        anyhow::bail!("list version {} expired", list.version);
+    }
+    Ok(list)
+}
+
+/// A signed list of gateways and the version of the last one accepted.
+pub(crate) struct SeedList {
+    url: String,
+    keys: Vec<VerifyingKey>,
+    cache: PathBuf,
+    version: Option<u64>,
+}
+
+impl SeedList {
+    /// The list configured for the node, if any.
+    pub fn from_config(config: &Config) -> anyhow::Result<Option<Self>> {
+        let Some(url) = &config.network_api.seed_list else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            url: url.clone(),
+            keys: config.network_api.seed_list_keys()?,
+            cache: config.config_dir().join(CACHE_FILE),
+            version: None,
+        }))
+    }
+
+    /// Fetches the list, falling back to the last one accepted if it can't be, and resolves the
+    /// gateways listed.
+    pub async fn load(&mut self) -> anyhow::Result<Vec<PeerKeyLocation>> {
+        let list = match self.fetch().await {
+            Ok(list) => list,
+            Err(error) if self.version.is_none() => {
+                tracing::warn!(
+                    url = %self.url,
+                    %error,
+                    "Failed fetching seed list, using the cached one"
+                );
+                self.load_cached()?
+            }
+            Err(error) => return Err(error),
+        };
+        resolve(list).await
+    }
+
+    async fn fetch(&mut self) -> anyhow::Result<GatewayList> {
+        let list = reqwest::get(&self.url)
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let signature = reqwest::get(format!("{}.sig", self.url))
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let verified = verify(&list, &signature, &self.keys, SystemTime::now())?;
+        let known = self.version.or_else(|| self.cached_version());
+        if known.is_some_and(|known| verified.version < known) {
+            anyhow::bail!(
+                "list version {} older than the one accepted",
+                verified.version
+            );
+        }
+        if known != Some(verified.version) {
+            tracing::info!(url = %self.url, version = verified.version, "Accepted new seed list");
+            if let Err(error) = std::fs::write(&self.cache, &list)
+                .and_then(|_| std::fs::write(self.signature_path(), &signature))
+            {
+                tracing::warn!(%error, "Failed caching seed list");
+            }
+        }
+        self.version = Some(verified.version);
+        Ok(verified)
+    }
+
+    fn load_cached(&mut self) -> anyhow::Result<GatewayList> {
+        let list = std::fs::read(&self.cache).context("no cached seed list")?;
+        let signature = std::fs::read_to_string(self.signature_path())?;
+        let verified = verify(&list, &signature, &self.keys, SystemTime::now())?;
+        self.version = Some(verified.version);
+        Ok(verified)
+    }
+
+    fn cached_version(&self) -> Option<u64> {
+        let list = std::fs::read(&self.cache).ok()?;
+        let signature = std::fs::read_to_string(self.signature_path()).ok()?;
+        let verified = verify(&list, &signature, &self.keys, UNIX_EPOCH).ok()?;
+        Some(verified.version)
+    }
+
+    fn signature_path(&self) -> PathBuf {
+        self.cache.with_extension("toml.sig")
+    }
+}
+
+async fn resolve(list: GatewayList) -> anyhow::Result<Vec<PeerKeyLocation>> {
+    let mut seen = HashSet::new();
+    let mut gateways = vec![];
+    for ListedGateway { address, key } in list.gateways {
+        let key = match dns_seed::decode_key(&key) {
+            Ok(key) => key,
+            Err(error) => {
+                tracing::warn!(%address, %error, "Invalid key of listed gateway");
+                continue;
+            }
+        };
+        let addr = match NodeConfig::parse_socket_addr(&Address::Hostname(address.clone())).await {
+            Ok(addr) => addr,
+            Err(error) => {
+                tracing::warn!(%address, %error, "Failed resolving listed gateway");
+                continue;
+            }
+        };
+        if seen.insert(addr) {
+            gateways.push(PeerKeyLocation {
+                peer: PeerId::new(addr, key),
+                location: Some(Location::from_address(&addr)),
+            });
+        }
+    }
+    if gateways.is_empty() {
+        anyhow::bail!("no gateway listed in version {}", list.version);
+    }
+    Ok(gateways)
+}
+
+/// Fetches the list periodically, making the gateways listed available to join through.
+pub(crate) async fn refresh_gateways(
+    mut seed_list: SeedList,
+    connection_manager: ConnectionManager,
+) {
+    let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+    loop {
+        interval.tick().await;
+        match seed_list.load().await {
+            Ok(gateways) => {
+                tracing::debug!(
+                    url = %seed_list.url,
+                    count = gateways.len(),
+                    "Refreshed listed gateways"
+                );
+                connection_manager.set_seeded_gateways(&seed_list.url, gateways);
+            }
+            Err(error) => {
+                tracing::warn!(url = %seed_list.url, %error, "Failed loading seed list")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Signer, SigningKey};
+
+    use super::*;
+
+    #[test]
+    fn verifies_signed_lists() -> anyhow::Result<()> {
+        let signing_key = SigningKey::generate(&mut rand::rngs::OsRng);
+        let other_key = SigningKey::generate(&mut rand::rngs::OsRng);
+        let keys = [other_key.verifying_key(), signing_key.verifying_key()];
+        let list = br#"
+            version = 3
+            expires = 2000000000
+
+            [[gateways]]
+            address = "gw.example.com:31337"
+            key = "MIIBIjAN"
+        "#;
+        let signature = bs58::encode(signing_key.sign(list).to_bytes()).into_string();
+        let now = UNIX_EPOCH + Duration::from_secs(1_900_000_000);
+
+        let verified = verify(list, &signature, &keys, now)?;
+        assert_eq!(verified.version, 3);
+        assert_eq!(verified.gateways[0].address, "gw.example.com:31337");
+
+        // signed by a key not pinned
+        assert!(verify(list, &signature, &keys[..1], now).is_err());
+        // tampered with
+        let mut tampered = list.to_vec();
+        tampered[23] = b'4';
+        assert!(verify(&tampered, &signature, &keys, now).is_err());
+        // expired
+        let later = UNIX_EPOCH + Duration::from_secs(2_000_000_000);
+        assert!(verify(list, &signature, &keys, later).is_err());
+        Ok(())
+    }
+}
@@ -877,7 +877,7 @@ where
         };
 
         if let Ok(Either::Left(NetMessage::V1(NetMessageV1::Aborted(tx)))) = msg {
-            super::handle_aborted_op(tx, &op_manager, &gateways).await?;
+            super::handle_aborted_op(tx, &op_manager, &gateways, &conn_manager).await?;
         }
 
         let msg = match msg {
@@ -912,6 +912,9 @@ where
                 NodeEvent::TransactionTimedOut(_) => {
                     unimplemented!()
                 }
+                NodeEvent::ClientDisconnected(_) => {
+                    continue;
+                }
             },
             Err(err) => {
                 super::report_result(
@@ -16,10 +16,10 @@ use crate::message::{NetMessageV1, NodeEvent};
 use crate::node::IsOperationCompleted;
 use crate::ring::ConnectionManager;
 use crate::router::Router;
-use crate::transport::TransportPublicKey;
+use crate::transport::{has_route, NatMapping, TransportPublicKey};
 use crate::{
     message::{InnerMessage, NetMessage, Transaction},
-    node::{NetworkBridge, OpManager, PeerId},
+    node::{neighborhood, NetworkBridge, OpManager, PeerId},
     operations::OpEnum,
     ring::PeerKeyLocation,
     util::Backoff,
@@ -163,6 +163,7 @@ impl Operation for ConnectOp {
                             query_target,
                             ideal_location,
                             joiner,
+                            joiner_nat,
                             max_hops_to_live,
                             skip_connections,
                             skip_forwards,
@@ -207,6 +208,7 @@ impl Operation for ConnectOp {
                                 *id,
                                 &own_loc,
                                 joiner,
+                                *joiner_nat,
                                 &desirable_peer,
                                 *max_hops_to_live,
                                 *max_hops_to_live,
@@ -245,6 +247,7 @@ impl Operation for ConnectOp {
                                 query_target: query_target.clone(),
                                 ideal_location: *ideal_location,
                                 joiner: joiner.clone(),
+                                joiner_nat: *joiner_nat,
                                 max_hops_to_live: *max_hops_to_live,
                                 skip_connections,
                                 skip_forwards,
@@ -260,11 +263,11 @@ impl Operation for ConnectOp {
                         ConnectRequest::CheckConnectivity {
                             sender,
                             joiner,
+                            joiner_nat,
                             hops_to_live,
                             max_hops_to_live,
                             skip_connections,
                             skip_forwards,
-                            ..
                         },
                     ..
                 } => {
@@ -291,10 +294,35 @@ impl Operation for ConnectOp {
                         "Checking connectivity request received"
                     );
 
-                    let should_accept = if op_manager
+                    let can_traverse = op_manager
                         .ring
                         .connection_manager
-                        .should_accept(joiner_loc, &joiner.peer)
+                        .nat_mapping()
+                        .can_traverse(*joiner_nat);
+                    if !can_traverse {
+                        tracing::debug!(
+                            tx = %id,
+                            at = %this_peer.peer,
+                            from = %joiner,
+                            "Both peers are behind address dependent NATs, hole punching can't succeed"
+                        );
+                    }
+                    // single stack peers can't connect to joiners of the other IP family
+                    let has_route = has_route(&joiner.peer.addr);
+                    if !has_route {
+                        tracing::debug!(
+                            tx = %id,
+                            at = %this_peer.peer,
+                            from = %joiner,
+                            "No route to the address of the joiner"
+                        );
+                    }
+                    let should_accept = if can_traverse
+                        && has_route
+                        && op_manager
+                            .ring
+                            .connection_manager
+                            .should_accept(joiner_loc, &joiner.peer)
                     {
                         tracing::debug!(tx = %id, %joiner, "Accepting connection from");
                         let (callback, mut result) = tokio::sync::mpsc::channel(1);
@@ -352,6 +380,7 @@ impl Operation for ConnectOp {
                                 skip_forwards: skip_forwards.clone(),
                                 req_peer: sender.clone(),
                                 joiner: joiner.clone(),
+                                joiner_nat: *joiner_nat,
                             },
                         )
                         .await?
@@ -433,16 +462,27 @@ impl Operation for ConnectOp {
 
                             let your_location: Location =
                                 target.location.expect("location not found");
-                            tracing::debug!(
-                                tx = %id,
-                                at = %this_peer_id,
-                                location = %your_location,
-                                "Updating assigned location"
-                            );
-                            op_manager
+                            if op_manager
                                 .ring
                                 .connection_manager
-                                .update_location(target.location);
+                                .accepts_assigned_location()
+                            {
+                                tracing::debug!(
+                                    tx = %id,
+                                    at = %this_peer_id,
+                                    location = %your_location,
+                                    "Updating assigned location"
+                                );
+                                op_manager
+                                    .ring
+                                    .connection_manager
+                                    .update_location(target.location);
+                            }
+                            if *accepted {
+                                // the location of this node is known by now
+                                neighborhood::request_neighborhood(op_manager, acceptor.clone())
+                                    .await;
+                            }
 
                             if remaining_connetions == 0 {
                                 tracing::debug!(
@@ -673,6 +713,10 @@ impl ConnectState {
 /// - gateways: Inmutable list of known gateways. Passed when starting up the node.
 ///   After the initial connections through the gateways are established all other connections
 ///   (to gateways or regular peers) will be treated as regular connections.
+///   The gateways last listed by the DNS seed of the node are tried too, the fastest first, and
+///   the peer joins through other gateways when those it is connected through are down.
+///   While the neighborhood of the peer is partitioned from the rest of the network, it joins
+///   again through the gateways it is not connected to.
 ///
 /// - is_gateway: Whether this peer is a gateway or not.
 pub(crate) async fn initial_join_procedure(
@@ -694,17 +738,41 @@ pub(crate) async fn initial_join_procedure(
             return;
         }
         loop {
-            if op_manager.ring.open_connections() == 0 {
+            let health = op_manager.ring.connection_manager.gateway_health();
+            let mut candidates = gateways.clone();
+            for seeded in op_manager.ring.connection_manager.seeded_gateways() {
+                if candidates.iter().all(|gw| gw.peer.addr != seeded.peer.addr) {
+                    candidates.push(seeded);
+                }
+            }
+            let down: Vec<_> = candidates
+                .iter()
+                .filter(|gw| health.is_down(&gw.peer.addr))
+                .collect();
+            let connected_down =
+                down.len() - op_manager.ring.is_not_connected(down.into_iter()).count();
+            let partitioned = op_manager
+                .ring
+                .connection_manager
+                .partition()
+                .is_partitioned();
+            if op_manager.ring.open_connections() <= connected_down || partitioned {
+                if partitioned {
+                    tracing::warn!("Neighborhood partitioned, joining again through gateways");
+                } else if connected_down > 0 {
+                    tracing::warn!("Gateways connected through are down, failing over");
+                }
                 tracing::info!(
                     "Attempting to connect to {} gateways in parallel",
                     number_of_parallel_connections
                 );
-                for gateway in op_manager
+                let mut ranked: Vec<_> = op_manager
                     .ring
-                    .is_not_connected(gateways.iter())
+                    .is_not_connected(candidates.iter())
                     .shuffle()
-                    .take(number_of_parallel_connections)
-                {
+                    .collect();
+                health.rank(&mut ranked);
+                for gateway in ranked.into_iter().take(number_of_parallel_connections) {
                     tracing::info!(%gateway, "Attempting connection to gateway");
                     if let Err(error) = join_ring_request(None, gateway, &op_manager).await {
                         if !matches!(
@@ -880,6 +948,8 @@ pub(crate) struct ForwardParams {
     pub skip_forwards: HashSet<PeerId>,
     pub req_peer: PeerKeyLocation,
     pub joiner: PeerKeyLocation,
+    /// How the NAT of the joiner maps its socket.
+    pub joiner_nat: NatMapping,
 }
 
 pub(crate) async fn forward_conn<NB>(
@@ -900,6 +970,7 @@ where
         mut skip_forwards,
         req_peer,
         joiner,
+        joiner_nat,
     } = params;
     if left_htl == 0 {
         tracing::debug!(
@@ -939,6 +1010,7 @@ where
                 id,
                 &req_peer,
                 &joiner,
+                joiner_nat,
                 &target_peer,
                 left_htl,
                 max_htl,
@@ -991,6 +1063,7 @@ fn create_forward_message(
     id: Transaction,
     request_peer: &PeerKeyLocation,
     joiner: &PeerKeyLocation,
+    joiner_nat: NatMapping,
     target: &PeerKeyLocation,
     hops_to_live: usize,
     max_hops_to_live: usize,
@@ -1003,6 +1076,7 @@ fn create_forward_message(
         msg: ConnectRequest::CheckConnectivity {
             sender: request_peer.clone(),
             joiner: joiner.clone(),
+            joiner_nat,
             hops_to_live: hops_to_live.saturating_sub(1), // decrement the hops to live for the next hop
             max_hops_to_live,
             skip_connections,
@@ -1144,6 +1218,8 @@ mod messages {
             /// Used for deterministic testing purposes. In production, this should be none and will be ignored
             /// by the gateway.
             joiner_location: Option<Location>,
+            /// How the NAT of the joiner maps its socket, as discovered from gateways.
+            joiner_nat: NatMapping,
             hops_to_live: usize,
             max_hops_to_live: usize,
             // Peers we don't want to connect to directly
@@ -1158,6 +1234,7 @@ mod messages {
             /// The ideal location of the peer to which you would connect.
             ideal_location: Location,
             joiner: PeerKeyLocation,
+            joiner_nat: NatMapping,
             max_hops_to_live: usize,
             skip_connections: HashSet<PeerId>,
             skip_forwards: HashSet<PeerId>,
@@ -1165,6 +1242,7 @@ mod messages {
         CheckConnectivity {
             sender: PeerKeyLocation,
             joiner: PeerKeyLocation,
+            joiner_nat: NatMapping,
             hops_to_live: usize,
             max_hops_to_live: usize,
             skip_connections: HashSet<PeerId>,
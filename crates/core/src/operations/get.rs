@@ -1,15 +1,18 @@
 use freenet_stdlib::client_api::{ErrorKind, HostResponse};
 use freenet_stdlib::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::fmt::Display;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
 use std::{future::Future, time::Instant};
 
 use crate::client_events::HostResult;
 use crate::node::IsOperationCompleted;
 use crate::{
     contract::{ContractHandlerEvent, StoreResponse},
-    message::{InnerMessage, NetMessage, Transaction},
+    message::{InnerMessage, NetMessage, NetMessageV1, Transaction},
     node::{NetworkBridge, OpManager, PeerId},
     operations::{OpInitialization, Operation},
     ring::{Location, PeerKeyLocation, RingError},
@@ -19,8 +22,48 @@ use super::{OpEnum, OpError, OpOutcome, OperationResult};
 
 pub(crate) use self::messages::GetMsg;
 
-/// Maximum number of retries to get values.
-const MAX_RETRIES: usize = 10;
+/// Interval the states cached near this node are checked for being due to a refresh at.
+const CACHE_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What a get is answered with instead of the state, computed by the contract at the peer
+/// holding the state so only the answer travels back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GetQuery {
+    /// The projection of the state answering the query, computed by the `query_state` entry
+    /// point of the contract.
+    Projection(
+        #[serde(deserialize_with = "StateSummary::deser_state_summary")] StateSummary<'static>,
+    ),
+    /// The summary of the state, so clients can check whether it changed before fetching it.
+    Summary,
+}
+
+impl GetQuery {
+    /// Request to the contract handler answering the query from the state stored by this node.
+    pub(crate) fn contract_request(&self, key: ContractKey) -> ContractHandlerEvent {
+        match self {
+            GetQuery::Projection(query) => ContractHandlerEvent::StateQuery {
+                key,
+                query: query.clone(),
+            },
+            GetQuery::Summary => ContractHandlerEvent::SummaryQuery { key },
+        }
+    }
+
+    /// The answer to the query from the response of the contract handler to
+    /// [`Self::contract_request`], `None` if the state isn't stored by this node.
+    pub(crate) fn answer(response: ContractHandlerEvent) -> Result<Option<WrappedState>, OpError> {
+        match response {
+            ContractHandlerEvent::StateQueryResponse { projection, .. } => {
+                Ok(projection?.map(|projection| WrappedState::new(projection.into_bytes())))
+            }
+            ContractHandlerEvent::SummaryResponse { summary, .. } => {
+                Ok(summary?.map(|summary| WrappedState::new(summary.as_ref().to_vec())))
+            }
+            _ => Err(OpError::UnexpectedOpState),
+        }
+    }
+}
 
 pub(crate) fn start_op(key: ContractKey, fetch_contract: bool, subscribe: bool) -> GetOp {
     let contract_location = Location::from(&key);
@@ -31,6 +74,7 @@ pub(crate) fn start_op(key: ContractKey, fetch_contract: bool, subscribe: bool)
         id,
         fetch_contract,
         subscribe,
+        query: None,
     });
     GetOp {
         id,
@@ -79,6 +123,7 @@ pub(crate) async fn request_get(
             key,
             id,
             subscribe,
+            query,
         }) => {
             let new_state = Some(GetState::AwaitingResponse {
                 retries: 0,
@@ -86,6 +131,8 @@ pub(crate) async fn request_get(
                 requester: None,
                 current_hop: op_manager.ring.max_hops_to_live,
                 subscribe,
+                query: query.clone(),
+                paths: Paths::to(target.clone()),
             });
 
             let msg = GetMsg::RequestGet {
@@ -94,6 +141,7 @@ pub(crate) async fn request_get(
                 target: target.clone(),
                 fetch_contract,
                 skip_list,
+                query,
             };
 
             let op = GetOp {
@@ -115,6 +163,24 @@ pub(crate) async fn request_get(
     Ok(())
 }
 
+/// Refreshes the states cached near this node before they become stale, getting a few of them
+/// from the network every [`CACHE_REFRESH_INTERVAL`] so refreshes don't compete with the
+/// requests of clients.
+pub(crate) async fn refresh_cached_contracts(op_manager: Arc<OpManager>) {
+    let mut interval = tokio::time::interval(CACHE_REFRESH_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        for key in op_manager.ring.cached_to_refresh() {
+            tracing::debug!(%key, "Refreshing cached contract");
+            let op = start_op(key, true, false);
+            if let Err(error) = request_get(&op_manager, op, HashSet::new()).await {
+                tracing::debug!(%key, %error, "Failed refreshing cached contract");
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 enum GetState {
     /// A new petition for a get op.
@@ -125,6 +191,7 @@ enum GetState {
         id: Transaction,
         fetch_contract: bool,
         subscribe: bool,
+        query: Option<GetQuery>,
     },
     /// Awaiting response from petition.
     AwaitingResponse {
@@ -134,11 +201,100 @@ enum GetState {
         retries: usize,
         current_hop: usize,
         subscribe: bool,
+        /// Query of the client, answered by the contract instead of returning the state.
+        query: Option<GetQuery>,
+        /// Peers the response is awaited from.
+        paths: Paths,
     },
     /// Operation completed successfully
     Finished { key: ContractKey },
 }
 
+/// Peers a get is awaited from: the one it was routed to, retried on failure, and the other
+/// peers a get started by this node was raced to, see [`OpManager::get_fanout`].
+#[derive(Debug, Default)]
+struct Paths {
+    next: Option<PeerKeyLocation>,
+    racers: Vec<PeerKeyLocation>,
+}
+
+impl Paths {
+    fn to(next: PeerKeyLocation) -> Self {
+        Self {
+            next: Some(next),
+            racers: vec![],
+        }
+    }
+
+    /// Drops the racer `peer` on failure, returning whether it was one. The get is only
+    /// retried on failures of the peer it was routed to, so a dead racer never holds it up.
+    fn racer_failed(&mut self, peer: &PeerId) -> bool {
+        let racers = self.racers.len();
+        self.racers.retain(|racer| &racer.peer != peer);
+        self.racers.len() < racers
+    }
+
+    /// The paths still running besides the one `peer` answered through, to be aborted.
+    fn others(&self, peer: &PeerId) -> Vec<PeerKeyLocation> {
+        self.next
+            .iter()
+            .chain(&self.racers)
+            .filter(|other| &other.peer != peer)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Aborts a get forwarded by this node on behalf of another peer, along the path it was
+/// forwarded to, once the peer which started it got its answer through another path.
+pub(crate) async fn abort<NB: NetworkBridge>(op: GetOp, op_manager: &OpManager, bridge: &NB) {
+    let id = op.id;
+    match op.state {
+        Some(GetState::AwaitingResponse {
+            requester: Some(_),
+            paths: Paths {
+                next: Some(next), ..
+            },
+            ..
+        }) => {
+            tracing::debug!(tx = %id, next = %next.peer, "Aborting get");
+            op_manager.completed(id);
+            if let Err(error) = bridge
+                .send(&next.peer, NetMessage::V1(NetMessageV1::Aborted(id)))
+                .await
+            {
+                tracing::debug!(tx = %id, %error, "Failed aborting get");
+            }
+        }
+        // gets started by this node are only aborted by their own outcome
+        _ => {
+            let _ = op_manager.push(id, OpEnum::Get(op)).await;
+        }
+    }
+}
+
+/// Aborts the paths a get started by this node is still awaited from besides the one `peer`
+/// answered it through.
+async fn abort_others<NB: NetworkBridge>(
+    id: Transaction,
+    state: Option<&GetState>,
+    peer: &PeerId,
+    conn_manager: &NB,
+) {
+    let Some(GetState::AwaitingResponse { paths, .. }) = state else {
+        return;
+    };
+    for other in paths.others(peer) {
+        tracing::debug!(tx = %id, peer = %other.peer, "Aborting get raced to peer");
+        if let Err(error) = conn_manager
+            .send(&other.peer, NetMessage::V1(NetMessageV1::Aborted(id)))
+            .await
+        {
+            tracing::debug!(tx = %id, peer = %other.peer, %error, "Failed aborting get");
+        }
+    }
+}
+
 impl Display for GetState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -148,6 +304,7 @@ impl Display for GetState {
                 id,
                 fetch_contract,
                 subscribe,
+                ..
             } => {
                 write!(
                     f,
@@ -161,6 +318,7 @@ impl Display for GetState {
                 retries,
                 current_hop,
                 subscribe,
+                ..
             } => {
                 write!(f, "AwaitingResponse(requester: {:?}, fetch_contract: {}, retries: {}, current_hop: {}, subscribe: {})", requester, fetch_contract, retries, current_hop, subscribe)
             }
@@ -205,6 +363,14 @@ pub(crate) struct GetOp {
 }
 
 impl GetOp {
+    /// Queries the state, getting the answer of the contract instead of it.
+    pub fn with_query(mut self, query: Option<GetQuery>) -> Self {
+        if let Some(GetState::PrepareRequest { query: q, .. }) = &mut self.state {
+            *q = query;
+        }
+        self
+    }
+
     pub(super) fn outcome(&self) -> OpOutcome {
         if let Some((
             GetResult {
@@ -304,8 +470,8 @@ impl Operation for GetOp {
     }
 
     fn process_message<'a, NB: NetworkBridge>(
-        self,
-        _conn_manager: &'a mut NB,
+        mut self,
+        conn_manager: &'a mut NB,
         op_manager: &'a OpManager,
         input: &'a Self::Message,
     ) -> Pin<Box<dyn Future<Output = Result<OperationResult, OpError>> + Send + 'a>> {
@@ -322,6 +488,7 @@ impl Operation for GetOp {
                     target,
                     fetch_contract,
                     skip_list,
+                    query,
                 } => {
                     // fast tracked from the request_get func
                     debug_assert!(matches!(
@@ -338,24 +505,56 @@ impl Operation for GetOp {
                         first_response_time: None,
                     }));
 
-                    // Keep current state
-                    new_state = self.state;
-
                     // Prepare skip list with own peer ID
                     let own_loc = op_manager.ring.connection_manager.own_location();
                     let mut new_skip_list = skip_list.clone();
                     new_skip_list.insert(own_loc.peer.clone());
-
-                    // Create seek node message
-                    return_msg = Some(GetMsg::SeekNode {
+                    new_skip_list.insert(target.peer.clone());
+
+                    // Race the request to other peers which may hold the contract, so a slow or
+                    // dead peer doesn't hold it up, the first valid response answering it
+                    let mut racers = vec![];
+                    while racers.len() + 1 < op_manager.get_fanout {
+                        let Some(racer) = op_manager
+                            .ring
+                            .closest_potentially_caching(key, &new_skip_list)
+                        else {
+                            break;
+                        };
+                        new_skip_list.insert(racer.peer.clone());
+                        racers.push(racer);
+                    }
+                    let seek = |target: &PeerKeyLocation| GetMsg::SeekNode {
                         key: *key,
                         id: *id,
                         target: target.clone(),
                         sender: own_loc.clone(),
                         fetch_contract: *fetch_contract,
                         htl: op_manager.ring.max_hops_to_live,
-                        skip_list: new_skip_list,
-                    });
+                        skip_list: new_skip_list.clone(),
+                        query: query.clone(),
+                    };
+                    let mut raced = vec![];
+                    for racer in racers {
+                        tracing::debug!(tx = %id, %key, racer = %racer.peer, "Racing get request");
+                        match conn_manager.send(&racer.peer, seek(&racer).into()).await {
+                            Ok(()) => raced.push(racer),
+                            Err(error) => {
+                                tracing::debug!(tx = %id, racer = %racer.peer, %error, "Failed racing get request");
+                            }
+                        }
+                    }
+
+                    if let Some(GetState::AwaitingResponse { paths, .. }) = self.state.as_mut() {
+                        *paths = Paths {
+                            next: Some(target.clone()),
+                            racers: raced,
+                        };
+                    }
+                    new_state = self.state;
+
+                    // Create seek node message
+                    return_msg = Some(seek(target));
                 }
                 GetMsg::SeekNode {
                     key,
@@ -365,6 +564,7 @@ impl Operation for GetOp {
                     target,
                     htl,
                     skip_list,
+                    query,
                 } => {
                     let htl = *htl;
                     let id = *id;
@@ -372,6 +572,33 @@ impl Operation for GetOp {
                     let fetch_contract = *fetch_contract;
                     let this_peer = target.clone();
 
+                    // Paths of a get raced to several peers may converge on this peer, the get
+                    // is only handled through the first one, retries of its requester aside
+                    if matches!(
+                        &self.state,
+                        Some(GetState::AwaitingResponse { requester, .. })
+                            if requester.as_ref().map(|r| &r.peer) != Some(&sender.peer)
+                    ) {
+                        tracing::debug!(tx = %id, %key, from = %sender.peer, "Get already handled through another path");
+                        return build_op_result(
+                            id,
+                            self.state,
+                            Some(GetMsg::ReturnGet {
+                                id,
+                                key,
+                                value: StoreResponse {
+                                    state: None,
+                                    contract: None,
+                                },
+                                sender: this_peer,
+                                target: sender.clone(),
+                                skip_list: skip_list.clone(),
+                            }),
+                            None,
+                            stats,
+                        );
+                    }
+
                     // Update stats with next peer
                     if let Some(s) = stats.as_mut() {
                         s.next_peer = Some(this_peer.clone());
@@ -381,24 +608,80 @@ impl Operation for GetOp {
                     let mut new_skip_list = skip_list.clone();
                     new_skip_list.insert(this_peer.clone().peer);
 
-                    // Try to get contract from local storage
-                    let get_result = op_manager
-                        .notify_contract_handler(ContractHandlerEvent::GetQuery {
-                            key,
-                            return_contract_code: fetch_contract,
-                        })
-                        .await;
+                    // Queries are answered by the contract from the state
+                    if let Some(query) = query {
+                        let projection = if op_manager.ring.is_cached_stale(&key) {
+                            None
+                        } else {
+                            op_manager
+                                .notify_contract_handler(query.contract_request(key))
+                                .await
+                                .map_err(OpError::from)
+                                .and_then(GetQuery::answer)
+                                .unwrap_or_default()
+                        };
+                        let Some(projection) = projection else {
+                            return try_forward_or_return(
+                                id,
+                                key,
+                                (htl, fetch_contract, Some(query.clone())),
+                                (this_peer, sender.clone()),
+                                new_skip_list,
+                                op_manager,
+                                stats,
+                            )
+                            .await;
+                        };
+                        let requester = match self.state {
+                            Some(GetState::AwaitingResponse {
+                                requester: Some(requester),
+                                ..
+                            }) => requester,
+                            Some(GetState::ReceivedRequest) => sender.clone(),
+                            _ => return Err(OpError::invalid_transition(self.id)),
+                        };
+                        tracing::debug!(tx = %id, "Returning query result for contract {} to {}", key, requester.peer);
+                        return build_op_result(
+                            id,
+                            None,
+                            Some(GetMsg::ReturnQuery {
+                                id,
+                                key,
+                                projection,
+                                sender: target.clone(),
+                                target: requester,
+                            }),
+                            None,
+                            stats,
+                        );
+                    }
+
+                    // Try to get contract from local storage, unless only cached and stale
+                    let get_result = if op_manager.ring.is_cached_stale(&key) {
+                        tracing::debug!(tx = %id, %key, "Cached contract stale, fetching it again");
+                        None
+                    } else {
+                        Some(
+                            op_manager
+                                .notify_contract_handler(ContractHandlerEvent::GetQuery {
+                                    key,
+                                    return_contract_code: fetch_contract,
+                                })
+                                .await,
+                        )
+                    };
 
                     // Process get result
                     match get_result {
-                        Ok(ContractHandlerEvent::GetResponse {
+                        Some(Ok(ContractHandlerEvent::GetResponse {
                             key,
                             response:
                                 Ok(StoreResponse {
                                     state: Some(state),
                                     contract,
                                 }),
-                        }) => {
+                            ..
+                        })) => {
                             tracing::debug!(tx = %id, "Contract {key} found @ peer {}", target.peer);
 
                             match self.state {
@@ -459,7 +742,7 @@ impl Operation for GetOp {
                             return try_forward_or_return(
                                 id,
                                 key,
-                                (htl, fetch_contract),
+                                (htl, fetch_contract, None),
                                 (this_peer, sender.clone()),
                                 new_skip_list,
                                 op_manager,
@@ -488,6 +771,15 @@ impl Operation for GetOp {
                         sender.peer
                     );
 
+                    // failures of the other peers the get was raced to aren't retried, the peer
+                    // it was routed to still answering it
+                    if let Some(GetState::AwaitingResponse { paths, .. }) = self.state.as_mut() {
+                        if paths.racer_failed(&sender.peer) {
+                            tracing::debug!(tx = %id, %key, racer = %sender.peer, "Racer failed getting the contract");
+                            return build_op_result(*id, self.state, None, None, stats);
+                        }
+                    }
+
                     match self.state {
                         Some(GetState::AwaitingResponse {
                             fetch_contract,
@@ -495,21 +787,35 @@ impl Operation for GetOp {
                             requester,
                             current_hop,
                             subscribe,
+                            query,
+                            paths,
                         }) => {
                             // todo: register in the stats for the outcome of the op that failed to get a response from this peer
-                            if retries < MAX_RETRIES {
+                            let policy = op_manager.retry_policy(id);
+                            if retries < policy.max_retries {
                                 // no response received from this peer, so skip it in the next iteration
                                 // Update skip list with current peer
                                 let mut new_skip_list = skip_list.clone();
                                 new_skip_list.insert(target.peer.clone());
-
-                                // Try to find another peer to query
-                                if let Some(target) = op_manager
-                                    .ring
-                                    .closest_potentially_caching(key, &new_skip_list)
-                                    .into_iter()
-                                    .next()
-                                {
+                                new_skip_list.extend(paths.racers.iter().map(|r| r.peer.clone()));
+                                let mut paths = Paths {
+                                    next: None,
+                                    racers: paths.racers,
+                                };
+
+                                // Try to find another peer to query, or the same one again
+                                let retry_target = if policy.alternate_routes {
+                                    op_manager
+                                        .ring
+                                        .closest_potentially_caching(key, &new_skip_list)
+                                        .into_iter()
+                                        .next()
+                                } else {
+                                    Some(sender.clone())
+                                };
+                                if let Some(target) = retry_target {
+                                    tokio::time::sleep(policy.delay(retries)).await;
+                                    paths.next = Some(target.clone());
                                     // Try with another peer
                                     return_msg = Some(GetMsg::SeekNode {
                                         id: *id,
@@ -519,6 +825,7 @@ impl Operation for GetOp {
                                         fetch_contract,
                                         htl: current_hop,
                                         skip_list: new_skip_list.clone(),
+                                        query: query.clone(),
                                     });
                                 } else if let Some(requester_peer) = requester.clone() {
                                     // No more peers to try, return failure to requester
@@ -562,6 +869,8 @@ impl Operation for GetOp {
                                     requester,
                                     current_hop,
                                     subscribe,
+                                    query,
+                                    paths,
                                 });
                             } else {
                                 // Max retries reached
@@ -606,6 +915,11 @@ impl Operation for GetOp {
                                         requester,
                                         current_hop,
                                         subscribe,
+                                        query,
+                                        paths: Paths {
+                                            next: None,
+                                            racers: paths.racers,
+                                        },
                                     });
                                     result = Some(GetResult {
                                         key: *key,
@@ -669,6 +983,22 @@ impl Operation for GetOp {
                         return Err(OpError::UnexpectedOpState);
                     };
 
+                    // Racers answering without the contract required are dropped, as on failure
+                    if let Some(GetState::AwaitingResponse {
+                        requester: None,
+                        paths,
+                        ..
+                    }) = self.state.as_mut()
+                    {
+                        if require_contract
+                            && contract.is_none()
+                            && paths.racer_failed(&sender.peer)
+                        {
+                            tracing::debug!(tx = %id, %key, racer = %sender.peer, "Contract not received from racer while required");
+                            return build_op_result(id, self.state, None, None, stats);
+                        }
+                    }
+
                     // Handle case where contract is required but not provided
                     if require_contract && contract.is_none() && requester.is_some() {
                         // no contract, consider this like an error ignoring the incoming update value
@@ -822,6 +1152,20 @@ impl Operation for GetOp {
                             }
                             _ => unreachable!(),
                         }
+                    } else if (!is_original_requester || op_manager.ring.is_cached(&key))
+                        && op_manager.ring.should_cache(&key)
+                    {
+                        // gets of the contracts cached started by this node refresh them
+                        if let Some(contract) = contract {
+                            super::cache_contract(
+                                op_manager,
+                                key,
+                                value.clone(),
+                                contract.clone(),
+                                is_original_requester,
+                            )
+                            .await;
+                        }
                     }
 
                     // Process based on current state
@@ -831,6 +1175,9 @@ impl Operation for GetOp {
                         }) => {
                             // Original requester, operation completed successfully
                             tracing::info!(tx = %id, %key, "Get response received for contract at original requester");
+                            abort_others(id, self.state.as_ref(), &sender.peer, conn_manager).await;
+                            // the contracts fetched when joining are bounded by the size of their states
+                            op_manager.neighborhood.charge(&id, value.size());
                             new_state = Some(GetState::Finished { key });
                             return_msg = None;
                             result = Some(GetResult {
@@ -889,6 +1236,42 @@ impl Operation for GetOp {
                         None => return Err(OpError::invalid_transition(self.id)),
                     };
                 }
+                GetMsg::ReturnQuery {
+                    id,
+                    key,
+                    projection,
+                    sender,
+                    target,
+                } => match self.state {
+                    Some(GetState::AwaitingResponse {
+                        requester: None, ..
+                    }) => {
+                        tracing::info!(tx = %id, %key, "Query response received for contract at original requester");
+                        abort_others(*id, self.state.as_ref(), &sender.peer, conn_manager).await;
+                        new_state = Some(GetState::Finished { key: *key });
+                        return_msg = None;
+                        result = Some(GetResult {
+                            key: *key,
+                            state: projection.clone(),
+                            contract: None,
+                        });
+                    }
+                    Some(GetState::AwaitingResponse {
+                        requester: Some(requester),
+                        ..
+                    }) => {
+                        tracing::info!(tx = %id, %key, "Query response received for contract at hop peer");
+                        new_state = None;
+                        return_msg = Some(GetMsg::ReturnQuery {
+                            id: *id,
+                            key: *key,
+                            projection: projection.clone(),
+                            sender: target.clone(),
+                            target: requester,
+                        });
+                    }
+                    _ => return Err(OpError::invalid_transition(self.id)),
+                },
             }
 
             build_op_result(self.id, new_state, return_msg, result, stats)
@@ -918,7 +1301,7 @@ fn build_op_result(
 async fn try_forward_or_return(
     id: Transaction,
     key: ContractKey,
-    (htl, fetch_contract): (usize, bool),
+    (htl, fetch_contract, query): (usize, bool, Option<GetQuery>),
     (this_peer, sender): (PeerKeyLocation, PeerKeyLocation),
     skip_list: HashSet<PeerId>,
     op_manager: &OpManager,
@@ -975,6 +1358,8 @@ async fn try_forward_or_return(
                 fetch_contract,
                 current_hop: new_htl,
                 subscribe: false,
+                query: query.clone(),
+                paths: Paths::to(target.clone()),
             }),
             Some(GetMsg::SeekNode {
                 id,
@@ -984,6 +1369,7 @@ async fn try_forward_or_return(
                 target,
                 htl: new_htl,
                 skip_list: new_skip_list,
+                query,
             }),
             None,
             stats,
@@ -1036,6 +1422,7 @@ mod messages {
             key: ContractKey,
             fetch_contract: bool,
             skip_list: HashSet<PeerId>,
+            query: Option<GetQuery>,
         },
         SeekNode {
             id: Transaction,
@@ -1045,6 +1432,8 @@ mod messages {
             sender: PeerKeyLocation,
             htl: usize,
             skip_list: HashSet<PeerId>,
+            /// Query of the client, answered with a projection of the state instead of it.
+            query: Option<GetQuery>,
         },
         ReturnGet {
             id: Transaction,
@@ -1054,6 +1443,14 @@ mod messages {
             target: PeerKeyLocation,
             skip_list: HashSet<PeerId>,
         },
+        /// Answer to a query computed by the contract, such as a projection of the state.
+        ReturnQuery {
+            id: Transaction,
+            key: ContractKey,
+            projection: WrappedState,
+            sender: PeerKeyLocation,
+            target: PeerKeyLocation,
+        },
     }
 
     impl InnerMessage for GetMsg {
@@ -1062,6 +1459,7 @@ mod messages {
                 Self::RequestGet { id, .. } => id,
                 Self::SeekNode { id, .. } => id,
                 Self::ReturnGet { id, .. } => id,
+                Self::ReturnQuery { id, .. } => id,
             }
         }
 
@@ -1070,6 +1468,7 @@ mod messages {
                 Self::SeekNode { target, .. } => Some(target),
                 Self::RequestGet { target, .. } => Some(target),
                 Self::ReturnGet { target, .. } => Some(target),
+                Self::ReturnQuery { target, .. } => Some(target),
             }
         }
 
@@ -1078,6 +1477,7 @@ mod messages {
                 GetMsg::RequestGet { key, .. } => Some(Location::from(key.id())),
                 GetMsg::SeekNode { key, .. } => Some(Location::from(key.id())),
                 GetMsg::ReturnGet { key, .. } => Some(Location::from(key.id())),
+                GetMsg::ReturnQuery { key, .. } => Some(Location::from(key.id())),
             }
         }
     }
@@ -1098,7 +1498,43 @@ mod messages {
                 Self::RequestGet { .. } => write!(f, "RequestGet(id: {id})"),
                 Self::SeekNode { .. } => write!(f, "SeekNode(id: {id})"),
                 Self::ReturnGet { .. } => write!(f, "ReturnGet(id: {id})"),
+                Self::ReturnQuery { .. } => write!(f, "ReturnQuery(id: {id})"),
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dead_racer_doesnt_hold_up_gets() {
+        let (primary, racer, retried) = (
+            PeerKeyLocation::random(),
+            PeerKeyLocation::random(),
+            PeerKeyLocation::random(),
+        );
+        let mut paths = Paths {
+            next: Some(primary.clone()),
+            racers: vec![racer.clone()],
+        };
+        // the racer never answers, the failure of the primary is retried right away
+        assert!(!paths.racer_failed(&primary.peer));
+        paths.next = Some(retried.clone());
+        // the racer is aborted once the get is answered
+        assert_eq!(
+            paths
+                .others(&retried.peer)
+                .into_iter()
+                .map(|other| other.peer)
+                .collect::<Vec<_>>(),
+            vec![racer.peer.clone()]
+        );
+
+        // failures of racers are dropped without retrying
+        assert!(paths.racer_failed(&racer.peer));
+        assert!(paths.racers.is_empty());
+        assert!(paths.others(&retried.peer).is_empty());
+    }
+}
@@ -2,13 +2,13 @@
 use std::backtrace::Backtrace as StdTrace;
 use std::{collections::HashSet, pin::Pin, time::Duration};
 
-use freenet_stdlib::prelude::ContractKey;
+use freenet_stdlib::prelude::{ContractContainer, ContractKey, RelatedContracts, WrappedState};
 use futures::Future;
 use tokio::sync::mpsc::error::SendError;
 
 use crate::{
     client_events::HostResult,
-    contract::{ContractError, ExecutorError},
+    contract::{ContractError, ContractHandlerEvent, ExecutorError},
     message::{InnerMessage, MessageStats, NetMessage, NetMessageV1, Transaction, TransactionType},
     node::{ConnectionError, NetworkBridge, OpManager, OpNotAvailable, PeerId},
     ring::{Location, PeerKeyLocation, RingError},
@@ -52,6 +52,39 @@ pub(crate) struct OperationResult {
     pub state: Option<OpEnum>,
 }
 
+/// Peers confirming they hold the value of a put or update, collected by the target of the
+/// operation from the peers it broadcasts the value to when the requester waits on them.
+#[derive(Debug)]
+pub(crate) struct Confirmations {
+    required: usize,
+    confirmed: HashSet<PeerId>,
+    pending: HashSet<PeerId>,
+}
+
+impl Confirmations {
+    pub fn new(required: usize, pending: HashSet<PeerId>) -> Self {
+        Self {
+            required,
+            confirmed: HashSet::new(),
+            pending,
+        }
+    }
+
+    pub fn confirm(&mut self, peer: PeerId) {
+        self.pending.remove(&peer);
+        self.confirmed.insert(peer);
+    }
+
+    /// Whether enough peers confirmed, or no other peer is left to confirm.
+    pub fn is_complete(&self) -> bool {
+        self.confirmed.len() >= self.required || self.pending.is_empty()
+    }
+
+    pub fn count(&self) -> usize {
+        self.confirmed.len()
+    }
+}
+
 pub(crate) struct OpInitialization<Op> {
     sender: Option<PeerId>,
     op: Op,
@@ -313,6 +346,10 @@ async fn start_subscription_request(
     try_get: bool,
     skip_list: HashSet<PeerId>,
 ) {
+    if op_manager.ring.is_subscribed(&key) {
+        tracing::debug!(%key, "Already subscribed to contract");
+        return;
+    }
     let sub_op = subscribe::start_op(key);
     if let Err(error) = subscribe::request_subscribe(op_manager, sub_op).await {
         if !try_get {
@@ -331,6 +368,72 @@ async fn start_subscription_request(
     }
 }
 
+/// Caches the state of a contract routed through this node, evicting the least recently used
+/// ones over the cache budget. `refreshed` states were fetched by this node to refresh those it
+/// cached, so they aren't recorded as used.
+async fn cache_contract(
+    op_manager: &OpManager,
+    key: ContractKey,
+    state: WrappedState,
+    contract: ContractContainer,
+    refreshed: bool,
+) {
+    let cached = if refreshed {
+        op_manager.ring.refresh_cached_contract(key, state.size())
+    } else {
+        op_manager.ring.cache_contract(key, state.size())
+    };
+    let Some(evicted) = cached else {
+        return;
+    };
+    match op_manager
+        .notify_contract_handler(ContractHandlerEvent::PutQuery {
+            key,
+            state,
+            related_contracts: RelatedContracts::default(),
+            contract: Some(contract),
+        })
+        .await
+    {
+        Ok(ContractHandlerEvent::PutResponse { new_value: Ok(_) }) => {
+            tracing::debug!(%key, "Cached contract routed through this node");
+        }
+        Ok(ContractHandlerEvent::PutResponse {
+            new_value: Err(error),
+        }) => {
+            tracing::debug!(%key, %error, "Failed caching contract");
+            op_manager.ring.uncache_contract(&key);
+        }
+        Ok(_) => unreachable!(),
+        Err(error) => {
+            tracing::warn!(%key, %error, "Failed caching contract");
+            op_manager.ring.uncache_contract(&key);
+        }
+    }
+    for key in evicted {
+        match op_manager
+            .notify_contract_handler(ContractHandlerEvent::EvictQuery { key })
+            .await
+        {
+            Ok(ContractHandlerEvent::EvictResponse { evicted: Ok(true) }) => {
+                tracing::debug!(%key, "Evicted cached contract");
+            }
+            Ok(ContractHandlerEvent::EvictResponse { evicted: Ok(false) }) => {
+                tracing::debug!(%key, "Cached contract subscribed to by clients, not evicted");
+            }
+            Ok(ContractHandlerEvent::EvictResponse {
+                evicted: Err(error),
+            }) => {
+                tracing::warn!(%key, %error, "Failed evicting cached contract");
+            }
+            Ok(_) => unreachable!(),
+            Err(error) => {
+                tracing::warn!(%key, %error, "Failed evicting cached contract");
+            }
+        }
+    }
+}
+
 async fn has_contract(op_manager: &OpManager, key: ContractKey) -> Result<bool, OpError> {
     match op_manager
         .notify_contract_handler(crate::contract::ContractHandlerEvent::GetQuery {
@@ -12,7 +12,9 @@ use freenet_stdlib::{
     prelude::*,
 };
 
-use super::{put, OpEnum, OpError, OpInitialization, OpOutcome, Operation, OperationResult};
+use super::{
+    put, Confirmations, OpEnum, OpError, OpInitialization, OpOutcome, Operation, OperationResult,
+};
 use crate::node::IsOperationCompleted;
 use crate::{
     client_events::HostResult,
@@ -59,21 +61,52 @@ impl PutOp {
     }
 
     pub(super) fn finalized(&self) -> bool {
-        self.state.is_none() || matches!(self.state, Some(PutState::Finished { .. }))
+        matches!(
+            self.state,
+            None | Some(PutState::Finished { .. } | PutState::Unconfirmed { .. })
+        )
     }
 
     pub(super) fn to_host_result(&self) -> HostResult {
-        if let Some(PutState::Finished { key }) = &self.state {
-            Ok(HostResponse::ContractResponse(
+        match &self.state {
+            Some(PutState::Finished { key }) => Ok(HostResponse::ContractResponse(
                 freenet_stdlib::client_api::ContractResponse::PutResponse { key: *key },
-            ))
-        } else {
-            Err(ErrorKind::OperationError {
+            )),
+            Some(PutState::Unconfirmed {
+                key,
+                stored_by,
+                required,
+            }) => Err(ErrorKind::OperationError {
+                cause: format!(
+                    "put of contract {key} confirmed by {stored_by} of the {required} peers required"
+                )
+                .into(),
+            }
+            .into()),
+            _ => Err(ErrorKind::OperationError {
                 cause: "put didn't finish successfully".into(),
             }
-            .into())
+            .into()),
         }
     }
+
+    /// Waits for the target to confirm the value is held by at least `peers` peers before
+    /// completing the put.
+    pub(crate) fn with_confirmations(mut self, peers: usize) -> Self {
+        if let Some(PutState::PrepareRequest { confirmations, .. }) = &mut self.state {
+            *confirmations = peers;
+        }
+        self
+    }
+
+    /// Replicates the value to peers near the contract until `peers` hold it, the target
+    /// included, collecting their confirmations.
+    pub(crate) fn with_replicas(mut self, peers: usize) -> Self {
+        if let Some(PutState::PrepareRequest { replicas, .. }) = &mut self.state {
+            *replicas = peers;
+        }
+        self
+    }
 }
 
 struct PutStats {
@@ -149,6 +182,8 @@ impl Operation for PutOp {
                     value,
                     htl,
                     target,
+                    confirmations,
+                    replicas,
                 } => {
                     // Get the contract key and own location
                     let key = contract.key();
@@ -171,6 +206,8 @@ impl Operation for PutOp {
                         contract: contract.clone(),
                         related_contracts: related_contracts.clone(),
                         htl: *htl,
+                        confirmations: *confirmations,
+                        replicas: *replicas,
                     });
 
                     // No changes to state yet, still in AwaitResponse state
@@ -184,6 +221,8 @@ impl Operation for PutOp {
                     htl,
                     target,
                     sender,
+                    confirmations,
+                    replicas,
                 } => {
                     // Get the contract key and check if we should handle it
                     let key = contract.key();
@@ -218,7 +257,7 @@ impl Operation for PutOp {
                     };
 
                     // Handle local storage and subscription if needed
-                    if should_handle_locally {
+                    let stored_here = if should_handle_locally {
                         // Store contract locally
                         tracing::debug!(
                             tx = %id,
@@ -260,9 +299,42 @@ impl Operation for PutOp {
                     } else {
                         false
                     };
+                    if !stored_here && op_manager.ring.should_cache(&key) {
+                        super::cache_contract(
+                            op_manager,
+                            key,
+                            value.clone(),
+                            contract.clone(),
+                            false,
+                        )
+                        .await;
+                    }
 
-                    // Broadcast changes to subscribers
-                    let broadcast_to = op_manager.get_broadcast_targets(&key, &sender.peer);
+                    // Broadcast changes to subscribers, and to the peers closest to the contract
+                    // until enough of them hold the value
+                    let mut broadcast_to = op_manager.get_broadcast_targets(&key, &sender.peer);
+                    let missing =
+                        replicas.saturating_sub(usize::from(stored_here) + broadcast_to.len());
+                    if missing > 0 {
+                        let replicate_to = op_manager.get_replica_targets(
+                            &key,
+                            missing,
+                            &sender.peer,
+                            &broadcast_to,
+                        );
+                        tracing::debug!(
+                            tx = %id,
+                            %key,
+                            replicas = replicate_to.len(),
+                            "Replicating contract to peers near it"
+                        );
+                        broadcast_to.extend(replicate_to);
+                    }
+                    let confirmations = if *replicas > 1 {
+                        (*confirmations).max(*replicas)
+                    } else {
+                        *confirmations
+                    };
                     match try_to_broadcast(
                         *id,
                         last_hop,
@@ -271,6 +343,7 @@ impl Operation for PutOp {
                         (broadcast_to, sender.clone()),
                         key,
                         (contract.clone(), value.clone()),
+                        (confirmations, stored_here),
                     )
                     .await
                     {
@@ -287,6 +360,7 @@ impl Operation for PutOp {
                     new_value,
                     contract,
                     sender,
+                    ack,
                     ..
                 } => {
                     // Get own location
@@ -304,6 +378,33 @@ impl Operation for PutOp {
                     .await?;
                     tracing::debug!(tx = %id, %key, "Contract successfully updated");
 
+                    // Keep the value when replicating it, subscribers seed the contract already
+                    if *ack && !op_manager.ring.is_seeding_contract(key) {
+                        tracing::debug!(tx = %id, %key, "Seeding contract replicated here");
+                        super::start_subscription_request(
+                            op_manager,
+                            *key,
+                            false,
+                            HashSet::from([sender.peer.clone()]),
+                        )
+                        .await;
+                        op_manager.ring.seed_contract(*key);
+                    }
+
+                    // Confirm to the broadcasting peer the value is stored here
+                    if *ack {
+                        let confirmation = PutMsg::Stored {
+                            id: *id,
+                            target: sender.clone(),
+                            key: *key,
+                            sender: target.clone(),
+                        };
+                        if let Err(err) = conn_manager.send(&sender.peer, confirmation.into()).await
+                        {
+                            tracing::warn!(tx = %id, %key, "Failed confirming put to {}: {err}", sender.peer);
+                        }
+                    }
+
                     // Broadcast changes to subscribers
                     let broadcast_to = op_manager.get_broadcast_targets(key, &sender.peer);
                     tracing::debug!(
@@ -322,6 +423,7 @@ impl Operation for PutOp {
                         (broadcast_to, sender.clone()),
                         *key,
                         (contract.clone(), updated_value),
+                        (0, true),
                     )
                     .await
                     {
@@ -340,6 +442,8 @@ impl Operation for PutOp {
                     new_value,
                     contract,
                     upstream,
+                    confirmations,
+                    stored_here,
                     ..
                 } => {
                     // Get own location and initialize counter
@@ -356,6 +460,7 @@ impl Operation for PutOp {
                             sender: sender.clone(),
                             contract: contract.clone(),
                             target: peer.clone(),
+                            ack: *confirmations > 0,
                         };
                         let f = conn_manager.send(&peer.peer, msg.into());
                         broadcasting.push(f);
@@ -376,6 +481,10 @@ impl Operation for PutOp {
 
                     // Handle failed broadcasts
                     let mut incorrect_results = 0;
+                    let mut delivered = broadcast_to
+                        .iter()
+                        .map(|peer| peer.peer.clone())
+                        .collect::<HashSet<_>>();
                     for (peer_num, err) in error_futures {
                         // Remove the failed peers in reverse order
                         let peer = broadcast_to.get(peer_num).unwrap();
@@ -386,6 +495,7 @@ impl Operation for PutOp {
                         );
                         // todo: review this, maybe we should just dropping this subscription
                         conn_manager.drop_connection(&peer.peer).await?;
+                        delivered.remove(&peer.peer);
                         incorrect_results += 1;
                     }
 
@@ -395,16 +505,74 @@ impl Operation for PutOp {
                         "Successfully broadcasted put into contract {key} to {broadcasted_to} peers"
                     );
 
-                    // Subscriber nodes have been notified of the change, the operation is completed
-                    return_msg = Some(PutMsg::SuccessfulPut {
-                        id: *id,
-                        target: upstream.clone(),
-                        key: *key,
-                        sender,
-                    });
-                    new_state = None;
+                    let mut confirmations = Confirmations::new(*confirmations, delivered);
+                    if *stored_here {
+                        confirmations.confirm(sender.peer.clone());
+                    }
+                    if confirmations.is_complete() {
+                        // Subscriber nodes have been notified of the change, the operation is completed
+                        return_msg = Some(PutMsg::SuccessfulPut {
+                            id: *id,
+                            target: upstream.clone(),
+                            key: *key,
+                            sender,
+                            stored_by: confirmations.count(),
+                        });
+                        new_state = None;
+                    } else {
+                        // The requester waits for the peers broadcasted to confirm they hold the value
+                        return_msg = None;
+                        new_state = Some(PutState::AwaitingConfirmations {
+                            key: *key,
+                            upstream: upstream.clone(),
+                            confirmations,
+                        });
+                    }
                 }
-                PutMsg::SuccessfulPut { id, .. } => {
+                PutMsg::Stored {
+                    id, key, sender, ..
+                } => match self.state {
+                    Some(PutState::AwaitingConfirmations {
+                        key,
+                        upstream,
+                        mut confirmations,
+                    }) => {
+                        confirmations.confirm(sender.peer.clone());
+                        if confirmations.is_complete() {
+                            tracing::debug!(
+                                tx = %id,
+                                %key,
+                                "Put confirmed by {} peers",
+                                confirmations.count()
+                            );
+                            return_msg = Some(PutMsg::SuccessfulPut {
+                                id: *id,
+                                target: upstream,
+                                key,
+                                sender: op_manager.ring.connection_manager.own_location(),
+                                stored_by: confirmations.count(),
+                            });
+                            new_state = None;
+                        } else {
+                            return_msg = None;
+                            new_state = Some(PutState::AwaitingConfirmations {
+                                key,
+                                upstream,
+                                confirmations,
+                            });
+                        }
+                    }
+                    Some(PutState::ReceivedRequest) | None => {
+                        tracing::debug!(tx = %id, %key, from = %sender.peer, "Put confirmed after completing");
+                        return_msg = None;
+                        new_state = None;
+                    }
+                    state => {
+                        return_msg = None;
+                        new_state = state;
+                    }
+                },
+                PutMsg::SuccessfulPut { id, stored_by, .. } => {
                     match self.state {
                         Some(PutState::AwaitingResponse {
                             key,
@@ -412,6 +580,7 @@ impl Operation for PutOp {
                             contract,
                             state,
                             subscribe,
+                            confirmations,
                         }) => {
                             // Check if already subscribed before any operations
                             let is_subscribed_contract = op_manager.ring.is_seeding_contract(&key);
@@ -473,7 +642,20 @@ impl Operation for PutOp {
                             );
 
                             // Mark operation as finished
-                            new_state = Some(PutState::Finished { key });
+                            new_state = if *stored_by < confirmations {
+                                tracing::warn!(
+                                    tx = %id,
+                                    %key,
+                                    "Put confirmed by {stored_by} of the {confirmations} peers required"
+                                );
+                                Some(PutState::Unconfirmed {
+                                    key,
+                                    stored_by: *stored_by,
+                                    required: confirmations,
+                                })
+                            } else {
+                                Some(PutState::Finished { key })
+                            };
 
                             // Forward success message upstream if needed
                             if let Some(upstream) = upstream {
@@ -482,11 +664,17 @@ impl Operation for PutOp {
                                     target: upstream,
                                     key,
                                     sender: op_manager.ring.connection_manager.own_location(),
+                                    stored_by: *stored_by,
                                 });
                             } else {
                                 return_msg = None;
                             }
                         }
+                        // Relayed by the peers broadcasted to while awaiting their confirmations
+                        state @ Some(PutState::AwaitingConfirmations { .. }) => {
+                            new_state = state;
+                            return_msg = None;
+                        }
                         _ => return Err(OpError::invalid_transition(self.id)),
                     };
                 }
@@ -620,6 +808,7 @@ impl Operation for PutOp {
                         (broadcast_to, sender.clone()),
                         key,
                         (contract.clone(), new_value.clone()),
+                        (0, already_put || last_hop),
                     )
                     .await
                     {
@@ -653,6 +842,30 @@ impl OpManager {
             .unwrap_or_default();
         subscribers
     }
+
+    /// Up to `replicas` peers closest to the contract to replicate its value to, other than
+    /// the sender and the peers broadcasted to.
+    fn get_replica_targets(
+        &self,
+        key: &ContractKey,
+        replicas: usize,
+        sender: &PeerId,
+        broadcast_to: &[PeerKeyLocation],
+    ) -> Vec<PeerKeyLocation> {
+        let mut skip_list: HashSet<_> = broadcast_to.iter().map(|pk| pk.peer.clone()).collect();
+        skip_list.insert(sender.clone());
+        skip_list.extend(self.ring.connection_manager.get_peer_key());
+        let location = Location::from(key);
+        let mut targets = Vec::with_capacity(replicas);
+        while targets.len() < replicas {
+            let Some(peer) = self.ring.closest_to_location(location, skip_list.clone()) else {
+                break;
+            };
+            skip_list.insert(peer.peer.clone());
+            targets.push(peer);
+        }
+        targets
+    }
 }
 
 fn build_op_result(
@@ -672,6 +885,10 @@ fn build_op_result(
     })
 }
 
+/// Broadcasts the value to `broadcast_to` or reports the put upstream, collecting confirmations
+/// from the peers broadcasted to when `confirmations` are required. `stored_here` is whether
+/// this peer holds the value.
+#[allow(clippy::too_many_arguments)]
 async fn try_to_broadcast(
     id: Transaction,
     last_hop: bool,
@@ -680,6 +897,7 @@ async fn try_to_broadcast(
     (broadcast_to, upstream): (Vec<PeerKeyLocation>, PeerKeyLocation),
     key: ContractKey,
     (contract, new_value): (ContractContainer, WrappedState),
+    (confirmations, stored_here): (usize, bool),
 ) -> Result<(Option<PutState>, Option<PutMsg>), OpError> {
     let new_state;
     let return_msg;
@@ -699,6 +917,7 @@ async fn try_to_broadcast(
                     contract: contract.clone(), // No longer optional
                     state: new_value.clone(),
                     subscribe: false,
+                    confirmations: 0,
                 });
                 return_msg = None;
             } else if !broadcast_to.is_empty() {
@@ -713,6 +932,8 @@ async fn try_to_broadcast(
                     contract,
                     upstream,
                     sender: op_manager.ring.connection_manager.own_location(),
+                    confirmations,
+                    stored_here,
                 });
 
                 let op = PutOp {
@@ -731,6 +952,7 @@ async fn try_to_broadcast(
                     target: upstream,
                     key,
                     sender: op_manager.ring.connection_manager.own_location(),
+                    stored_by: usize::from(stored_here),
                 });
             }
         }
@@ -759,6 +981,8 @@ pub(crate) fn start_op(
         value,
         htl,
         subscribe,
+        confirmations: 0,
+        replicas: 1,
     });
 
     PutOp {
@@ -777,6 +1001,10 @@ pub enum PutState {
         value: WrappedState,
         htl: usize,
         subscribe: bool,
+        /// Peers required to hold the value for the put to complete.
+        confirmations: usize,
+        /// Peers near the contract the value is replicated to, the target included.
+        replicas: usize,
     },
     /// Awaiting response from petition.
     AwaitingResponse {
@@ -785,13 +1013,53 @@ pub enum PutState {
         contract: ContractContainer,
         state: WrappedState,
         subscribe: bool,
+        confirmations: usize,
     },
     /// Broadcasting changes to subscribers.
     BroadcastOngoing,
+    /// Awaiting the peers broadcasted to to confirm they hold the value.
+    AwaitingConfirmations {
+        key: ContractKey,
+        upstream: PeerKeyLocation,
+        confirmations: Confirmations,
+    },
     /// Operation completed.
     Finished {
         key: ContractKey,
     },
+    /// Operation completed without the value being held by as many peers as required.
+    Unconfirmed {
+        key: ContractKey,
+        stored_by: usize,
+        required: usize,
+    },
+}
+
+/// Stores the value of a put about to be requested in this node, for it to be reported as
+/// completed before reaching the network.
+pub(crate) async fn store_locally(
+    op_manager: &OpManager,
+    put_op: &PutOp,
+) -> Result<ContractKey, OpError> {
+    let Some(PutState::PrepareRequest {
+        contract,
+        related_contracts,
+        value,
+        ..
+    }) = &put_op.state
+    else {
+        return Err(OpError::UnexpectedOpState);
+    };
+    let key = contract.key();
+    put_contract(
+        op_manager,
+        key,
+        value.clone(),
+        related_contracts.clone(),
+        contract,
+    )
+    .await?;
+    Ok(key)
 }
 
 /// Request to insert/update a value into a contract.
@@ -826,6 +1094,8 @@ pub(crate) async fn request_put(op_manager: &OpManager, mut put_op: PutOp) -> Re
             value,
             htl,
             subscribe,
+            confirmations,
+            replicas,
         }) => {
             let new_state = Some(PutState::AwaitingResponse {
                 key,
@@ -833,6 +1103,7 @@ pub(crate) async fn request_put(op_manager: &OpManager, mut put_op: PutOp) -> Re
                 contract: contract.clone(),
                 state: value.clone(),
                 subscribe,
+                confirmations,
             });
             let msg = PutMsg::RequestPut {
                 id,
@@ -841,6 +1112,8 @@ pub(crate) async fn request_put(op_manager: &OpManager, mut put_op: PutOp) -> Re
                 value,
                 htl,
                 target: target.clone(),
+                confirmations,
+                replicas,
             };
 
             let op = PutOp {
@@ -903,42 +1176,58 @@ async fn forward_put<CB>(
     new_value: WrappedState,
     id: Transaction,
     htl: usize,
-    skip_list: HashSet<PeerId>,
+    mut skip_list: HashSet<PeerId>,
 ) -> bool
 where
     CB: NetworkBridge,
 {
     let key = contract.key();
     let contract_loc = Location::from(&key);
-    let forward_to = op_manager
-        .ring
-        .closest_potentially_caching(&key, &skip_list);
     let own_pkloc = op_manager.ring.connection_manager.own_location();
     let own_loc = own_pkloc.location.expect("infallible");
-    if let Some(peer) = forward_to {
+    let policy = op_manager.retry_policy(&id);
+    let mut forward_to = op_manager
+        .ring
+        .closest_potentially_caching(&key, &skip_list);
+    let mut retries = 0;
+    while let Some(peer) = forward_to {
         let other_loc = peer.location.as_ref().expect("infallible");
         let other_distance = contract_loc.distance(other_loc);
         let self_distance = contract_loc.distance(own_loc);
-        if other_distance < self_distance {
-            // forward the contract towards this node since it is indeed closer to the contract location
-            // and forget about it, no need to keep track of this op or wait for response
-            let _ = conn_manager
-                .send(
-                    &peer.peer,
-                    (PutMsg::PutForward {
-                        id,
-                        sender: own_pkloc,
-                        target: peer.clone(),
-                        contract: contract.clone(),
-                        new_value: new_value.clone(),
-                        htl,
-                        skip_list,
-                    })
-                    .into(),
-                )
-                .await;
+        if other_distance >= self_distance {
+            break;
+        }
+        // forward the contract towards this node since it is indeed closer to the contract location
+        // and forget about it, no need to keep track of this op or wait for response
+        let sent = conn_manager
+            .send(
+                &peer.peer,
+                (PutMsg::PutForward {
+                    id,
+                    sender: own_pkloc.clone(),
+                    target: peer.clone(),
+                    contract: contract.clone(),
+                    new_value: new_value.clone(),
+                    htl,
+                    skip_list: skip_list.clone(),
+                })
+                .into(),
+            )
+            .await;
+        if sent.is_ok() || retries >= policy.max_retries {
             return false;
         }
+        tracing::debug!(tx = %id, target = %peer.peer, "Failed forwarding put, retrying");
+        tokio::time::sleep(policy.delay(retries)).await;
+        retries += 1;
+        forward_to = if policy.alternate_routes {
+            skip_list.insert(peer.peer.clone());
+            op_manager
+                .ring
+                .closest_potentially_caching(&key, &skip_list)
+        } else {
+            Some(peer)
+        };
     }
     true
 }
@@ -962,6 +1251,10 @@ mod messages {
             /// max hops to live
             htl: usize,
             target: PeerKeyLocation,
+            /// Peers required to hold the value, none when not waiting on them.
+            confirmations: usize,
+            /// Peers near the contract the value is replicated to, the target included.
+            replicas: usize,
         },
         /// Internal node instruction to await the result of a put.
         AwaitPut { id: Transaction },
@@ -982,6 +1275,8 @@ mod messages {
             target: PeerKeyLocation,
             key: ContractKey,
             sender: PeerKeyLocation,
+            /// Peers which confirmed holding the value.
+            stored_by: usize,
         },
         /// Target the node which is closest to the key
         SeekNode {
@@ -994,6 +1289,8 @@ mod messages {
             related_contracts: RelatedContracts<'static>,
             /// max hops to live
             htl: usize,
+            confirmations: usize,
+            replicas: usize,
         },
         /// Internal node instruction that  a change (either a first time insert or an update).
         Broadcasting {
@@ -1005,6 +1302,9 @@ mod messages {
             contract: ContractContainer,
             upstream: PeerKeyLocation,
             sender: PeerKeyLocation,
+            confirmations: usize,
+            /// Whether the broadcasting peer holds the value.
+            stored_here: bool,
         },
         /// Broadcasting a change to a peer, which then will relay the changes to other peers.
         BroadcastTo {
@@ -1014,6 +1314,16 @@ mod messages {
             new_value: WrappedState,
             contract: ContractContainer,
             target: PeerKeyLocation,
+            /// Whether the peer has to confirm it stored the value, seeding the contract to
+            /// hold it as a replica.
+            ack: bool,
+        },
+        /// A peer broadcasted to confirms it stored the value.
+        Stored {
+            id: Transaction,
+            target: PeerKeyLocation,
+            key: ContractKey,
+            sender: PeerKeyLocation,
         },
     }
 
@@ -1027,6 +1337,7 @@ mod messages {
                 Self::PutForward { id, .. } => id,
                 Self::AwaitPut { id } => id,
                 Self::BroadcastTo { id, .. } => id,
+                Self::Stored { id, .. } => id,
             }
         }
 
@@ -1037,6 +1348,7 @@ mod messages {
                 Self::SuccessfulPut { target, .. } => Some(target),
                 Self::PutForward { target, .. } => Some(target),
                 Self::BroadcastTo { target, .. } => Some(target),
+                Self::Stored { target, .. } => Some(target),
                 _ => None,
             }
         }
@@ -1058,6 +1370,7 @@ mod messages {
             match self {
                 Self::SeekNode { sender, .. } => Some(sender),
                 Self::BroadcastTo { sender, .. } => Some(sender),
+                Self::Stored { sender, .. } => Some(sender),
                 _ => None,
             }
         }
@@ -1074,6 +1387,7 @@ mod messages {
                 Self::PutForward { .. } => write!(f, "PutForward(id: {id})"),
                 Self::AwaitPut { .. } => write!(f, "AwaitPut(id: {id})"),
                 Self::BroadcastTo { .. } => write!(f, "BroadcastTo(id: {id})"),
+                Self::Stored { .. } => write!(f, "Stored(id: {id})"),
             }
         }
     }
@@ -18,8 +18,6 @@ use freenet_stdlib::{
 };
 use serde::{Deserialize, Serialize};
 
-const MAX_RETRIES: usize = 10;
-
 #[derive(Debug)]
 enum SubscribeState {
     /// Prepare the request to subscribe.
@@ -237,7 +235,11 @@ impl Operation for SubscribeOp {
                         }
                     };
 
-                    if !super::has_contract(op_manager, *key).await? {
+                    // subscriptions to contracts this node is subscribed to already are answered
+                    // here, aggregated into its own
+                    if !op_manager.ring.is_subscribed(key)
+                        && !super::has_contract(op_manager, *key).await?
+                    {
                         tracing::debug!(tx = %id, %key, "Contract not found, trying other peer");
 
                         let Some(new_target) =
@@ -330,14 +332,20 @@ impl Operation for SubscribeOp {
                             upstream_subscriber,
                             current_hop,
                         }) => {
-                            if retries < MAX_RETRIES {
-                                skip_list.insert(sender.peer.clone());
-                                if let Some(target) = op_manager
-                                    .ring
-                                    .closest_potentially_caching(key, &skip_list)
-                                    .into_iter()
-                                    .next()
-                                {
+                            let policy = op_manager.retry_policy(id);
+                            if retries < policy.max_retries {
+                                let retry_target = if policy.alternate_routes {
+                                    skip_list.insert(sender.peer.clone());
+                                    op_manager
+                                        .ring
+                                        .closest_potentially_caching(key, &skip_list)
+                                        .into_iter()
+                                        .next()
+                                } else {
+                                    Some(sender.clone())
+                                };
+                                if let Some(target) = retry_target {
+                                    tokio::time::sleep(policy.delay(retries)).await;
                                     let subscriber =
                                         op_manager.ring.connection_manager.own_location();
                                     return_msg = Some(SubscribeMsg::SeekNode {
@@ -396,6 +404,7 @@ impl Operation for SubscribeOp {
                             );
                             return Err(OpError::UnexpectedOpState);
                         }
+                        op_manager.ring.set_upstream(key, sender.clone());
 
                         new_state = Some(SubscribeState::Completed { key: *key });
                         if let Some(upstream_subscriber) = upstream_subscriber {
@@ -1,11 +1,18 @@
 // TODO: complete update logic in the network
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
 use freenet_stdlib::client_api::{ErrorKind, HostResponse};
 use freenet_stdlib::prelude::*;
+use parking_lot::Mutex;
 
 pub(crate) use self::messages::UpdateMsg;
-use super::{OpEnum, OpError, OpInitialization, OpOutcome, Operation, OperationResult};
+use super::{
+    Confirmations, OpEnum, OpError, OpInitialization, OpOutcome, Operation, OperationResult,
+};
 use crate::contract::ContractHandlerEvent;
-use crate::message::{InnerMessage, NetMessage, Transaction};
+use crate::message::{InnerMessage, NetMessage, Priority, Transaction};
 use crate::node::IsOperationCompleted;
 use crate::ring::{Location, PeerKeyLocation, RingError};
 use crate::{
@@ -25,24 +32,46 @@ impl UpdateOp {
     }
 
     pub fn finalized(&self) -> bool {
-        matches!(self.state, None | Some(UpdateState::Finished { .. }))
+        matches!(
+            self.state,
+            None | Some(UpdateState::Finished { .. } | UpdateState::Unconfirmed { .. })
+        )
     }
 
     pub(super) fn to_host_result(&self) -> HostResult {
-        if let Some(UpdateState::Finished { key, summary }) = &self.state {
-            Ok(HostResponse::ContractResponse(
+        match &self.state {
+            Some(UpdateState::Finished { key, summary }) => Ok(HostResponse::ContractResponse(
                 freenet_stdlib::client_api::ContractResponse::UpdateResponse {
                     key: *key,
                     summary: summary.clone(),
                 },
-            ))
-        } else {
-            Err(ErrorKind::OperationError {
+            )),
+            Some(UpdateState::Unconfirmed {
+                key,
+                stored_by,
+                required,
+            }) => Err(ErrorKind::OperationError {
+                cause: format!(
+                    "update of contract {key} confirmed by {stored_by} of the {required} peers required"
+                )
+                .into(),
+            }
+            .into()),
+            _ => Err(ErrorKind::OperationError {
                 cause: "update didn't finish successfully".into(),
             }
-            .into())
+            .into()),
         }
     }
+
+    /// Waits for the target to confirm the value is held by at least `peers` peers before
+    /// completing the update.
+    pub(crate) fn with_confirmations(mut self, peers: usize) -> Self {
+        if let Some(UpdateState::PrepareRequest { confirmations, .. }) = &mut self.state {
+            *confirmations = peers;
+        }
+        self
+    }
 }
 
 struct UpdateStats {
@@ -129,6 +158,7 @@ impl Operation for UpdateOp {
                     target,
                     related_contracts,
                     value,
+                    confirmations,
                 } => {
                     let sender = op_manager.ring.connection_manager.own_location();
 
@@ -146,6 +176,7 @@ impl Operation for UpdateOp {
                         value: value.clone(),
                         key: *key,
                         related_contracts: related_contracts.clone(),
+                        confirmations: *confirmations,
                     });
 
                     // no changes to state yet, still in AwaitResponse state
@@ -158,6 +189,7 @@ impl Operation for UpdateOp {
                     related_contracts,
                     target,
                     sender,
+                    confirmations,
                 } => {
                     let is_subscribed_contract = op_manager.ring.is_seeding_contract(key);
 
@@ -169,18 +201,34 @@ impl Operation for UpdateOp {
                         "Updating contract at target peer",
                     );
 
-                    let broadcast_to = op_manager.get_broadcast_targets_update(key, &sender.peer);
+                    let mut broadcast_to =
+                        op_manager.get_broadcast_targets_update(key, &sender.peer);
 
                     if is_subscribed_contract {
                         tracing::debug!("Peer is subscribed to contract. About to update it");
-                        update_contract(op_manager, *key, value.clone(), related_contracts.clone())
-                            .await?;
+                        let new_value = update_contract(
+                            op_manager,
+                            *key,
+                            value.clone(),
+                            related_contracts.clone(),
+                        )
+                        .await?;
                         tracing::debug!(
                             tx = %id,
                             "Successfully updated a value for contract {} @ {:?} - update",
                             key,
                             target.location
                         );
+                        // updates waiting on confirmations are broadcast right away for the
+                        // subscribers to confirm them
+                        if *confirmations == 0
+                            && !op_manager
+                                .update_coalescer
+                                .coalesce(*key, &new_value, &sender.peer)
+                        {
+                            tracing::debug!(tx = %id, %key, "Coalescing update of contract");
+                            broadcast_to.clear();
+                        }
                     } else {
                         tracing::debug!("contract not found in this peer. Should throw an error");
                         return Err(OpError::RingError(RingError::NoCachingPeers(*key)));
@@ -195,6 +243,7 @@ impl Operation for UpdateOp {
                         *key,
                         value.clone(),
                         false,
+                        *confirmations,
                     )
                     .await
                     {
@@ -211,6 +260,7 @@ impl Operation for UpdateOp {
                     new_value,
                     sender,
                     target,
+                    ack,
                 } => {
                     if let Some(UpdateState::AwaitingResponse { .. }) = self.state {
                         tracing::debug!("Trying to broadcast to a peer that was the initiator of the op because it received the client request, or is in the middle of a seek node process");
@@ -227,7 +277,28 @@ impl Operation for UpdateOp {
                     .await?;
                     tracing::debug!("Contract successfully updated - BroadcastTo - update");
 
-                    let broadcast_to = op_manager.get_broadcast_targets_update(key, &sender.peer);
+                    if *ack {
+                        let confirmation = UpdateMsg::Stored {
+                            id: *id,
+                            target: sender.clone(),
+                            sender: target.clone(),
+                        };
+                        if let Err(err) = conn_manager.send(&sender.peer, confirmation.into()).await
+                        {
+                            tracing::warn!(tx = %id, %key, "Failed confirming update to {}: {err}", sender.peer);
+                        }
+                    }
+
+                    let broadcast_to =
+                        if op_manager
+                            .update_coalescer
+                            .coalesce(*key, &new_value, &sender.peer)
+                        {
+                            op_manager.get_broadcast_targets_update(key, &sender.peer)
+                        } else {
+                            tracing::debug!(tx = %id, %key, "Coalescing update of contract");
+                            vec![]
+                        };
 
                     tracing::debug!(
                         "Successfully updated a value for contract {} @ {:?} - BroadcastTo - update",
@@ -244,6 +315,7 @@ impl Operation for UpdateOp {
                         *key,
                         new_value,
                         true,
+                        0,
                     )
                     .await
                     {
@@ -261,6 +333,7 @@ impl Operation for UpdateOp {
                     key,
                     new_value,
                     upstream,
+                    confirmations,
                 } => {
                     let sender = op_manager.ring.connection_manager.own_location();
                     let mut broadcasted_to = *broadcasted_to;
@@ -274,6 +347,7 @@ impl Operation for UpdateOp {
                             new_value: new_value.clone(),
                             sender: sender.clone(),
                             target: peer.clone(),
+                            ack: *confirmations > 0,
                         };
                         let f = conn_manager.send(&peer.peer, msg.into());
                         broadcasting.push(f);
@@ -291,6 +365,10 @@ impl Operation for UpdateOp {
                         });
 
                     let mut incorrect_results = 0;
+                    let mut delivered = broadcast_to
+                        .iter()
+                        .map(|peer| peer.peer.clone())
+                        .collect::<HashSet<_>>();
                     for (peer_num, err) in error_futures {
                         // remove the failed peers in reverse order
                         let peer = broadcast_to.get(peer_num).unwrap();
@@ -301,6 +379,7 @@ impl Operation for UpdateOp {
                         );
                         // TODO: review this, maybe we should just dropping this subscription
                         conn_manager.drop_connection(&peer.peer).await?;
+                        delivered.remove(&peer.peer);
                         incorrect_results += 1;
                     }
 
@@ -313,18 +392,82 @@ impl Operation for UpdateOp {
 
                     let summary = StateSummary::from(raw_state.into_bytes());
 
-                    // Subscriber nodes have been notified of the change, the operation is complete
-                    return_msg = Some(UpdateMsg::SuccessfulUpdate {
-                        id: *id,
-                        target: upstream.clone(),
-                        summary,
-                    });
-
-                    new_state = None;
+                    // the broadcasting peer always holds the value it broadcasts
+                    let mut confirmations = Confirmations::new(*confirmations, delivered);
+                    confirmations.confirm(sender.peer.clone());
+                    if confirmations.is_complete() && upstream.peer == sender.peer {
+                        // coalesced updates broadcast by this node, there is no one to report to
+                        return_msg = None;
+                        new_state = None;
+                    } else if confirmations.is_complete() {
+                        // Subscriber nodes have been notified of the change, the operation is complete
+                        return_msg = Some(UpdateMsg::SuccessfulUpdate {
+                            id: *id,
+                            target: upstream.clone(),
+                            summary,
+                            stored_by: confirmations.count(),
+                        });
+                        new_state = None;
+                    } else {
+                        return_msg = None;
+                        new_state = Some(UpdateState::AwaitingConfirmations {
+                            upstream: upstream.clone(),
+                            summary,
+                            confirmations,
+                        });
+                    }
                 }
-                UpdateMsg::SuccessfulUpdate { id, summary, .. } => {
+                UpdateMsg::Stored { id, sender, .. } => match self.state {
+                    Some(UpdateState::AwaitingConfirmations {
+                        upstream,
+                        summary,
+                        mut confirmations,
+                    }) => {
+                        confirmations.confirm(sender.peer.clone());
+                        if confirmations.is_complete() {
+                            tracing::debug!(
+                                tx = %id,
+                                "Update confirmed by {} peers",
+                                confirmations.count()
+                            );
+                            return_msg = Some(UpdateMsg::SuccessfulUpdate {
+                                id: *id,
+                                target: upstream,
+                                summary,
+                                stored_by: confirmations.count(),
+                            });
+                            new_state = None;
+                        } else {
+                            return_msg = None;
+                            new_state = Some(UpdateState::AwaitingConfirmations {
+                                upstream,
+                                summary,
+                                confirmations,
+                            });
+                        }
+                    }
+                    Some(UpdateState::ReceivedRequest) | None => {
+                        tracing::debug!(tx = %id, from = %sender.peer, "Update confirmed after completing");
+                        return_msg = None;
+                        new_state = None;
+                    }
+                    state => {
+                        return_msg = None;
+                        new_state = state;
+                    }
+                },
+                UpdateMsg::SuccessfulUpdate {
+                    id,
+                    summary,
+                    stored_by,
+                    ..
+                } => {
                     match self.state {
-                        Some(UpdateState::AwaitingResponse { key, upstream }) => {
+                        Some(UpdateState::AwaitingResponse {
+                            key,
+                            upstream,
+                            confirmations,
+                        }) => {
                             tracing::debug!(
                                 tx = %id,
                                 %key,
@@ -332,21 +475,40 @@ impl Operation for UpdateOp {
                                 "Peer completed contract value update - SuccessfulUpdate",
                             );
 
-                            new_state = Some(UpdateState::Finished {
-                                key,
-                                summary: summary.clone(),
-                            });
+                            new_state = if *stored_by < confirmations {
+                                tracing::warn!(
+                                    tx = %id,
+                                    %key,
+                                    "Update confirmed by {stored_by} of the {confirmations} peers required"
+                                );
+                                Some(UpdateState::Unconfirmed {
+                                    key,
+                                    stored_by: *stored_by,
+                                    required: confirmations,
+                                })
+                            } else {
+                                Some(UpdateState::Finished {
+                                    key,
+                                    summary: summary.clone(),
+                                })
+                            };
                             if let Some(upstream) = upstream {
                                 return_msg = Some(UpdateMsg::SuccessfulUpdate {
                                     id: *id,
                                     target: upstream,
                                     summary: summary.clone(),
+                                    stored_by: *stored_by,
                                 });
                             } else {
                                 // this means op finalized
                                 return_msg = None;
                             }
                         }
+                        // Relayed by the peers broadcasted to while awaiting their confirmations
+                        state @ Some(UpdateState::AwaitingConfirmations { .. }) => {
+                            new_state = state;
+                            return_msg = None;
+                        }
                         _ => {
                             tracing::error!(
                                 state = ?self.state,
@@ -357,6 +519,82 @@ impl Operation for UpdateOp {
                         }
                     };
                 }
+                UpdateMsg::RequestSync {
+                    id,
+                    key,
+                    target,
+                    summary,
+                } => {
+                    let sender = op_manager.ring.connection_manager.own_location();
+                    tracing::debug!(tx = %id, %key, target = %target.peer, "Synchronizing contract with replica");
+                    return_msg = Some(UpdateMsg::SyncSummary {
+                        id: *id,
+                        sender,
+                        target: target.clone(),
+                        key: *key,
+                        summary: summary.clone(),
+                    });
+                    new_state = self.state;
+                }
+                UpdateMsg::SyncSummary {
+                    id,
+                    sender,
+                    target,
+                    key,
+                    summary,
+                } => {
+                    let own_summary = state_summary(op_manager, *key).await?;
+                    let in_sync = own_summary
+                        .as_ref()
+                        .is_some_and(|own| own.as_ref() == summary.as_ref());
+                    let delta = if in_sync {
+                        None
+                    } else {
+                        missing_delta(op_manager, *key, summary.clone()).await?
+                    };
+                    tracing::debug!(tx = %id, %key, in_sync, missing = delta.is_some(), "Replica of contract synchronizing");
+                    // the replica responds with what this node is missing unless in sync
+                    let summary = own_summary.filter(|_| !in_sync);
+                    new_state = summary
+                        .is_some()
+                        .then_some(UpdateState::Synchronizing { key: *key });
+                    return_msg = Some(UpdateMsg::SyncDelta {
+                        id: *id,
+                        sender: target.clone(),
+                        target: sender.clone(),
+                        key: *key,
+                        delta,
+                        summary,
+                    });
+                }
+                UpdateMsg::SyncDelta {
+                    id,
+                    sender,
+                    target,
+                    key,
+                    delta,
+                    summary,
+                } => {
+                    if !matches!(self.state, Some(UpdateState::Synchronizing { .. })) {
+                        return Err(OpError::invalid_transition(self.id));
+                    }
+                    if let Some(delta) = delta {
+                        tracing::debug!(tx = %id, %key, from = %sender.peer, "Applying delta missed of contract");
+                        apply_delta(op_manager, *key, delta.clone()).await?;
+                    }
+                    return_msg = match summary {
+                        Some(summary) => Some(UpdateMsg::SyncDelta {
+                            id: *id,
+                            sender: target.clone(),
+                            target: sender.clone(),
+                            key: *key,
+                            delta: missing_delta(op_manager, *key, summary.clone()).await?,
+                            summary: None,
+                        }),
+                        None => None,
+                    };
+                    new_state = None;
+                }
                 _ => return Err(OpError::UnexpectedOpState),
             }
 
@@ -375,6 +613,7 @@ async fn try_to_broadcast(
     key: ContractKey,
     new_value: WrappedState,
     is_from_a_broadcasted_to_peer: bool,
+    confirmations: usize,
 ) -> Result<(Option<UpdateState>, Option<UpdateMsg>), OpError> {
     let new_state;
     let return_msg;
@@ -399,6 +638,7 @@ async fn try_to_broadcast(
                 new_state = Some(UpdateState::AwaitingResponse {
                     key,
                     upstream: Some(upstream),
+                    confirmations: 0,
                 });
             } else if !broadcast_to.is_empty() {
                 tracing::debug!(
@@ -414,6 +654,7 @@ async fn try_to_broadcast(
                     broadcast_to,
                     key,
                     upstream,
+                    confirmations,
                 });
 
                 let op = UpdateOp {
@@ -435,6 +676,7 @@ async fn try_to_broadcast(
                     id,
                     target: upstream,
                     summary,
+                    stored_by: 1,
                 });
             }
         }
@@ -466,6 +708,260 @@ impl OpManager {
     }
 }
 
+/// Window over which the updates of a contract are coalesced.
+const COALESCE_WINDOW: Duration = Duration::from_millis(200);
+
+struct CoalescedUpdate {
+    /// Value of the contract after merging the updates coalesced, not broadcast yet.
+    pending: Option<WrappedState>,
+    /// Peers the updates coalesced came from.
+    senders: HashSet<PeerId>,
+}
+
+/// Coalescing of the updates of contracts updated in quick succession, so chatty contracts are
+/// relayed to their subscribers at most about once per [`COALESCE_WINDOW`] instead of once per
+/// update.
+///
+/// The first update of a contract is broadcast right away. The updates received during the
+/// window after it are merged into the contract as usual but not broadcast, the value resulting
+/// from merging them all being broadcast once the window elapses. Subscribers merge it as any
+/// other update, so coalescing relies on the merge semantics of the contract.
+#[derive(Default)]
+pub(crate) struct UpdateCoalescer {
+    updates: Mutex<HashMap<ContractKey, CoalescedUpdate>>,
+}
+
+impl UpdateCoalescer {
+    /// Records an update of a contract from `sender`, `value` being the value of the contract
+    /// after merging it. Returns whether to broadcast the update right away, its value being
+    /// broadcast once the window elapses otherwise.
+    pub fn coalesce(&self, key: ContractKey, value: &WrappedState, sender: &PeerId) -> bool {
+        match self.updates.lock().entry(key) {
+            Entry::Occupied(mut entry) => {
+                let update = entry.get_mut();
+                update.pending = Some(value.clone());
+                update.senders.insert(sender.clone());
+                false
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(CoalescedUpdate {
+                    pending: None,
+                    senders: HashSet::new(),
+                });
+                true
+            }
+        }
+    }
+
+    /// Takes the values of the contracts pending broadcast with the peer all their updates came
+    /// from, if a single one. Contracts without any are forgotten, so their next update is
+    /// broadcast right away.
+    fn flush(&self) -> Vec<(ContractKey, WrappedState, Option<PeerId>)> {
+        let mut flushed = vec![];
+        self.updates.lock().retain(|key, update| {
+            let Some(value) = update.pending.take() else {
+                return false;
+            };
+            let senders = std::mem::take(&mut update.senders);
+            let sender = (senders.len() == 1)
+                .then(|| senders.into_iter().next())
+                .flatten();
+            flushed.push((*key, value, sender));
+            true
+        });
+        flushed
+    }
+}
+
+/// Broadcasts the values of the contracts whose updates were coalesced to their subscribers,
+/// once per [`COALESCE_WINDOW`].
+pub(crate) async fn broadcast_coalesced_updates(op_manager: Arc<OpManager>) {
+    let mut interval = tokio::time::interval(COALESCE_WINDOW);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        for (key, new_value, sender) in op_manager.update_coalescer.flush() {
+            let own_location = op_manager.ring.connection_manager.own_location();
+            // the sender of all the updates coalesced already holds their value
+            let sender = sender.unwrap_or_else(|| own_location.peer.clone());
+            let broadcast_to = op_manager.get_broadcast_targets_update(&key, &sender);
+            if broadcast_to.is_empty() {
+                continue;
+            }
+            let id = Transaction::new::<UpdateMsg>();
+            tracing::debug!(tx = %id, %key, "Broadcasting coalesced updates of contract");
+            let msg = UpdateMsg::Broadcasting {
+                id,
+                broadcast_to,
+                broadcasted_to: 0,
+                key,
+                new_value,
+                upstream: own_location,
+                confirmations: 0,
+            };
+            let op = UpdateOp {
+                id,
+                state: Some(UpdateState::BroadcastOngoing),
+                stats: None,
+            };
+            if let Err(error) = op_manager
+                .notify_op_change(NetMessage::from(msg), OpEnum::Update(op))
+                .await
+            {
+                tracing::debug!(tx = %id, %key, %error, "Failed broadcasting coalesced updates");
+            }
+        }
+    }
+}
+
+/// Interval the replicas of the contracts seeded by the node are synchronized at.
+const SYNC_INTERVAL: Duration = Duration::from_secs(2 * 60);
+
+/// Contracts synchronized per interval at most.
+const MAX_SYNCS: usize = 16;
+
+/// Anti-entropy between the replicas of the contracts seeded by the node, so replicas which
+/// missed updates, e.g. dropped while churning, converge instead of diverging until fetched
+/// again.
+///
+/// Every [`SYNC_INTERVAL`] the node sends the summary of its state of some of the contracts it
+/// seeds to a random peer holding a replica, a subscriber or the peer it is subscribed through.
+/// The replica responds with the delta of its state the node is missing and, unless both are
+/// in sync, the summary of its own state, which the node responds to with the delta the replica
+/// is missing in turn. Replicas further away along the subscription tree catch up as they
+/// synchronize with the peers they are connected with.
+pub(crate) async fn synchronize_replicas(op_manager: Arc<OpManager>) {
+    use rand::seq::{IteratorRandom, SliceRandom};
+
+    let mut interval = tokio::time::interval(SYNC_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let own_location = op_manager.ring.connection_manager.own_location();
+        let mut contracts = op_manager.ring.seeding_contracts();
+        contracts.shuffle(&mut rand::thread_rng());
+        for key in contracts.into_iter().take(MAX_SYNCS) {
+            let Some(replica) = op_manager
+                .ring
+                .replicas_of(&key)
+                .into_iter()
+                .filter(|replica| replica.peer != own_location.peer)
+                .choose(&mut rand::thread_rng())
+            else {
+                continue;
+            };
+            if let Err(error) = request_sync(&op_manager, key, replica).await {
+                tracing::debug!(%key, %error, "Failed synchronizing contract");
+            }
+        }
+    }
+}
+
+/// Sends the summary of the state of the contract to a replica to synchronize with it.
+async fn request_sync(
+    op_manager: &OpManager,
+    key: ContractKey,
+    target: PeerKeyLocation,
+) -> Result<(), OpError> {
+    let Some(summary) = state_summary(op_manager, key).await? else {
+        return Ok(());
+    };
+    let id = Transaction::new::<UpdateMsg>();
+    op_manager.set_priority(id, Priority::Background);
+    let msg = UpdateMsg::RequestSync {
+        id,
+        key,
+        target,
+        summary,
+    };
+    let op = UpdateOp {
+        id,
+        state: Some(UpdateState::Synchronizing { key }),
+        stats: None,
+    };
+    op_manager
+        .notify_op_change(NetMessage::from(msg), OpEnum::Update(op))
+        .await
+}
+
+/// Summary of the state of the contract, `None` if this node doesn't hold it.
+async fn state_summary(
+    op_manager: &OpManager,
+    key: ContractKey,
+) -> Result<Option<StateSummary<'static>>, OpError> {
+    match op_manager
+        .notify_contract_handler(ContractHandlerEvent::SummaryQuery { key })
+        .await?
+    {
+        ContractHandlerEvent::SummaryResponse {
+            summary: Ok(summary),
+            ..
+        } => Ok(summary),
+        ContractHandlerEvent::SummaryResponse {
+            summary: Err(_), ..
+        } => {
+            tracing::debug!(%key, "Failed summarizing contract state");
+            Ok(None)
+        }
+        _ => Err(OpError::UnexpectedOpState),
+    }
+}
+
+/// Delta of the state of the contract held by this node missing from the state summarized, `None`
+/// if nothing is or this node doesn't hold it.
+async fn missing_delta(
+    op_manager: &OpManager,
+    key: ContractKey,
+    summary: StateSummary<'static>,
+) -> Result<Option<WrappedState>, OpError> {
+    match op_manager
+        .notify_contract_handler(ContractHandlerEvent::StateQuery {
+            key,
+            query: summary,
+        })
+        .await?
+    {
+        ContractHandlerEvent::StateQueryResponse {
+            projection: Ok(delta),
+            ..
+        } => Ok(delta
+            .filter(|delta| delta.size() > 0)
+            .map(|delta| WrappedState::new(delta.into_bytes()))),
+        ContractHandlerEvent::StateQueryResponse {
+            projection: Err(_), ..
+        } => {
+            tracing::debug!(%key, "Failed computing delta of contract state");
+            Ok(None)
+        }
+        _ => Err(OpError::UnexpectedOpState),
+    }
+}
+
+async fn apply_delta(
+    op_manager: &OpManager,
+    key: ContractKey,
+    delta: WrappedState,
+) -> Result<(), OpError> {
+    match op_manager
+        .notify_contract_handler(ContractHandlerEvent::UpdateQuery {
+            key,
+            data: UpdateData::Delta(StateDelta::from(delta.as_ref().to_vec())),
+            related_contracts: RelatedContracts::default(),
+            expected_state: None,
+        })
+        .await?
+    {
+        ContractHandlerEvent::UpdateResponse { new_value: Ok(_) }
+        | ContractHandlerEvent::UpdateNoChange { .. } => Ok(()),
+        ContractHandlerEvent::UpdateResponse { new_value: Err(_) } => {
+            tracing::error!(%key, "Failed applying delta of contract state");
+            Err(OpError::UnexpectedOpState)
+        }
+        _ => Err(OpError::UnexpectedOpState),
+    }
+}
+
 fn build_op_result(
     id: Transaction,
     state: Option<UpdateState>,
@@ -496,6 +992,7 @@ async fn update_contract(
             key,
             data: update_data,
             related_contracts,
+            expected_state: None,
         })
         .await
     {
@@ -529,6 +1026,7 @@ pub(crate) fn start_op(
         key,
         related_contracts,
         value: new_state,
+        confirmations: 0,
     });
 
     UpdateOp {
@@ -585,10 +1083,12 @@ pub(crate) async fn request_update(
             key,
             value,
             related_contracts,
+            confirmations,
         }) => {
             let new_state = Some(UpdateState::AwaitingResponse {
                 key,
                 upstream: None,
+                confirmations,
             });
             let msg = UpdateMsg::RequestUpdate {
                 id,
@@ -596,6 +1096,7 @@ pub(crate) async fn request_update(
                 related_contracts,
                 target,
                 value,
+                confirmations,
             };
 
             let op = UpdateOp {
@@ -640,6 +1141,8 @@ mod messages {
             #[serde(deserialize_with = "RelatedContracts::deser_related_contracts")]
             related_contracts: RelatedContracts<'static>,
             value: WrappedState,
+            /// Peers required to hold the value, none when not waiting on them.
+            confirmations: usize,
         },
         /// Value successfully inserted/updated.
         SuccessfulUpdate {
@@ -647,6 +1150,8 @@ mod messages {
             target: PeerKeyLocation,
             #[serde(deserialize_with = "StateSummary::deser_state_summary")]
             summary: StateSummary<'static>,
+            /// Peers which confirmed holding the value.
+            stored_by: usize,
         },
         AwaitUpdate {
             id: Transaction,
@@ -659,6 +1164,7 @@ mod messages {
             key: ContractKey,
             #[serde(deserialize_with = "RelatedContracts::deser_related_contracts")]
             related_contracts: RelatedContracts<'static>,
+            confirmations: usize,
         },
         /// Internal node instruction that  a change (either a first time insert or an update).
         Broadcasting {
@@ -669,6 +1175,7 @@ mod messages {
             new_value: WrappedState,
             //contract: ContractContainer,
             upstream: PeerKeyLocation,
+            confirmations: usize,
         },
         /// Broadcasting a change to a peer, which then will relay the changes to other peers.
         BroadcastTo {
@@ -677,9 +1184,55 @@ mod messages {
             key: ContractKey,
             new_value: WrappedState,
             target: PeerKeyLocation,
+            /// Whether the peer has to confirm it applied the update.
+            ack: bool,
+        },
+        /// A peer broadcasted to confirms it applied the update.
+        Stored {
+            id: Transaction,
+            target: PeerKeyLocation,
+            sender: PeerKeyLocation,
+        },
+        /// Internal node instruction to synchronize the state of a contract with a replica.
+        RequestSync {
+            id: Transaction,
+            key: ContractKey,
+            target: PeerKeyLocation,
+            #[serde(deserialize_with = "StateSummary::deser_state_summary")]
+            summary: StateSummary<'static>,
+        },
+        /// Summary of the state of a contract sent to a peer holding a replica, which responds
+        /// with the delta the sender is missing.
+        SyncSummary {
+            id: Transaction,
+            sender: PeerKeyLocation,
+            target: PeerKeyLocation,
+            key: ContractKey,
+            #[serde(deserialize_with = "StateSummary::deser_state_summary")]
+            summary: StateSummary<'static>,
+        },
+        /// Delta of the state of a contract the target is missing, if any, and the summary of
+        /// the state of the sender unless already in sync, for the target to respond with the
+        /// delta the sender is missing in turn.
+        SyncDelta {
+            id: Transaction,
+            sender: PeerKeyLocation,
+            target: PeerKeyLocation,
+            key: ContractKey,
+            delta: Option<WrappedState>,
+            #[serde(deserialize_with = "deser_summary")]
+            summary: Option<StateSummary<'static>>,
         },
     }
 
+    fn deser_summary<'de, D>(deser: D) -> Result<Option<StateSummary<'static>>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let summary: Option<StateSummary<'de>> = Deserialize::deserialize(deser)?;
+        Ok(summary.map(StateSummary::into_owned))
+    }
+
     impl InnerMessage for UpdateMsg {
         fn id(&self) -> &Transaction {
             match self {
@@ -689,6 +1242,10 @@ mod messages {
                 UpdateMsg::SeekNode { id, .. } => id,
                 UpdateMsg::Broadcasting { id, .. } => id,
                 UpdateMsg::BroadcastTo { id, .. } => id,
+                UpdateMsg::Stored { id, .. } => id,
+                UpdateMsg::RequestSync { id, .. } => id,
+                UpdateMsg::SyncSummary { id, .. } => id,
+                UpdateMsg::SyncDelta { id, .. } => id,
             }
         }
 
@@ -698,6 +1255,10 @@ mod messages {
                 UpdateMsg::SuccessfulUpdate { target, .. } => Some(target),
                 UpdateMsg::SeekNode { target, .. } => Some(target),
                 UpdateMsg::BroadcastTo { target, .. } => Some(target),
+                UpdateMsg::Stored { target, .. } => Some(target),
+                UpdateMsg::RequestSync { target, .. } => Some(target),
+                UpdateMsg::SyncSummary { target, .. } => Some(target),
+                UpdateMsg::SyncDelta { target, .. } => Some(target),
                 _ => None,
             }
         }
@@ -708,6 +1269,9 @@ mod messages {
                 UpdateMsg::SeekNode { key, .. } => Some(Location::from(key.id())),
                 UpdateMsg::Broadcasting { key, .. } => Some(Location::from(key.id())),
                 UpdateMsg::BroadcastTo { key, .. } => Some(Location::from(key.id())),
+                UpdateMsg::RequestSync { key, .. } => Some(Location::from(key.id())),
+                UpdateMsg::SyncSummary { key, .. } => Some(Location::from(key.id())),
+                UpdateMsg::SyncDelta { key, .. } => Some(Location::from(key.id())),
                 _ => None,
             }
         }
@@ -718,6 +1282,9 @@ mod messages {
             match self {
                 Self::SeekNode { sender, .. } => Some(sender),
                 Self::BroadcastTo { sender, .. } => Some(sender),
+                Self::Stored { sender, .. } => Some(sender),
+                Self::SyncSummary { sender, .. } => Some(sender),
+                Self::SyncDelta { sender, .. } => Some(sender),
                 _ => None,
             }
         }
@@ -732,6 +1299,10 @@ mod messages {
                 UpdateMsg::SeekNode { id, .. } => write!(f, "SeekNode(id: {id})"),
                 UpdateMsg::Broadcasting { id, .. } => write!(f, "Broadcasting(id: {id})"),
                 UpdateMsg::BroadcastTo { id, .. } => write!(f, "BroadcastTo(id: {id})"),
+                UpdateMsg::Stored { id, .. } => write!(f, "Stored(id: {id})"),
+                UpdateMsg::RequestSync { id, .. } => write!(f, "RequestSync(id: {id})"),
+                UpdateMsg::SyncSummary { id, .. } => write!(f, "SyncSummary(id: {id})"),
+                UpdateMsg::SyncDelta { id, .. } => write!(f, "SyncDelta(id: {id})"),
             }
         }
     }
@@ -743,15 +1314,69 @@ pub enum UpdateState {
     AwaitingResponse {
         key: ContractKey,
         upstream: Option<PeerKeyLocation>,
+        confirmations: usize,
     },
     Finished {
         key: ContractKey,
         summary: StateSummary<'static>,
     },
+    /// Completed without the value being held by as many peers as required.
+    Unconfirmed {
+        key: ContractKey,
+        stored_by: usize,
+        required: usize,
+    },
     PrepareRequest {
         key: ContractKey,
         related_contracts: RelatedContracts<'static>,
         value: WrappedState,
+        /// Peers required to hold the value for the update to complete.
+        confirmations: usize,
     },
     BroadcastOngoing,
+    /// Awaiting the peers broadcasted to to confirm they applied the update.
+    AwaitingConfirmations {
+        upstream: PeerKeyLocation,
+        summary: StateSummary<'static>,
+        confirmations: Confirmations,
+    },
+    /// Synchronizing the state of the contract with a replica, awaiting its delta.
+    Synchronizing {
+        key: ContractKey,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coalesces_updates() {
+        let coalescer = UpdateCoalescer::default();
+        let key = ContractKey::from(ContractInstanceId::new([1; 32]));
+        let (a, b) = (PeerId::random(), PeerId::random());
+
+        // first update broadcast right away, the following ones once flushed
+        assert!(coalescer.coalesce(key, &WrappedState::new(vec![1]), &a));
+        assert!(!coalescer.coalesce(key, &WrappedState::new(vec![1, 2]), &a));
+        assert!(!coalescer.coalesce(key, &WrappedState::new(vec![1, 2, 3]), &a));
+        let flushed = coalescer.flush();
+        assert_eq!(flushed.len(), 1);
+        let (flushed_key, value, sender) = &flushed[0];
+        assert_eq!(flushed_key, &key);
+        assert_eq!(value.as_ref(), [1, 2, 3]);
+        assert_eq!(sender.as_ref(), Some(&a));
+
+        // updates from different peers are broadcast to both
+        assert!(!coalescer.coalesce(key, &WrappedState::new(vec![4]), &a));
+        assert!(!coalescer.coalesce(key, &WrappedState::new(vec![5]), &b));
+        let (_, value, sender) = coalescer.flush().remove(0);
+        assert_eq!(value.as_ref(), [5]);
+        assert_eq!(sender, None);
+
+        // quiet contracts are forgotten
+        assert!(coalescer.flush().is_empty());
+        assert!(coalescer.flush().is_empty());
+        assert!(coalescer.coalesce(key, &WrappedState::new(vec![6]), &b));
+    }
 }
@@ -0,0 +1,233 @@
+//! Opportunistic caching of the states of contracts routed through the node, so popular contracts
+//! are served by the peers on the way to them instead of only by those seeding them.
+//!
+//! States are cached without subscribing to their updates, so they are served for [`MAX_AGE`]
+//! at most, after which requests for them are routed to the network again and the state returned
+//! refreshes the cache. The size of the states cached is bounded by a budget, the least recently
+//! used ones being evicted to make room for new ones.
+//!
+//! Contracts near the location of the node are requested from it more often than others, so the
+//! states cached of those are refreshed in the background once older than [`REFRESH_AGE`],
+//! before they become stale. Refreshing them doesn't count as using them, so those seldom used
+//! are still the first ones evicted.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use freenet_stdlib::prelude::ContractKey;
+
+use super::{Distance, Location};
+
+/// Time the state of a contract cached is served before it is fetched from the network again.
+const MAX_AGE: Duration = Duration::from_secs(5 * 60);
+
+/// Age at which the states cached near the node are refreshed in the background.
+const REFRESH_AGE: Duration = Duration::from_secs(4 * 60);
+
+/// Distance to the node of the contracts whose states cached are refreshed in the background.
+const REFRESH_DISTANCE: f64 = 0.05;
+
+/// States refreshed in the background at most each time, so refreshes don't compete with the
+/// requests of clients.
+const MAX_REFRESHES: usize = 8;
+
+/// Caching done by the node of the contracts routed through it.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct CacheLimits {
+    pub enabled: bool,
+    /// Bytes of the states cached at most.
+    pub budget: u64,
+}
+
+impl Default for CacheLimits {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            budget: 64 << 20,
+        }
+    }
+}
+
+struct Entry {
+    size: u64,
+    cached_at: Instant,
+    last_used: Instant,
+}
+
+pub(super) struct ContractCache {
+    budget: u64,
+    used: u64,
+    entries: HashMap<ContractKey, Entry>,
+}
+
+impl ContractCache {
+    pub fn new(budget: u64) -> Self {
+        Self {
+            budget,
+            used: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Records the state of the contract cached, returning the contracts to evict to fit it in
+    /// the budget, `None` if it doesn't fit in it at all.
+    pub fn insert(
+        &mut self,
+        key: ContractKey,
+        size: u64,
+        now: Instant,
+    ) -> Option<Vec<ContractKey>> {
+        if size > self.budget {
+            return None;
+        }
+        self.remove(&key);
+        let mut evicted = vec![];
+        while self.used + size > self.budget {
+            let Some(lru) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| *key)
+            else {
+                break;
+            };
+            self.remove(&lru);
+            evicted.push(lru);
+        }
+        self.used += size;
+        self.entries.insert(
+            key,
+            Entry {
+                size,
+                cached_at: now,
+                last_used: now,
+            },
+        );
+        Some(evicted)
+    }
+
+    /// Records the state of the contract cached refreshed in the background, like
+    /// [`Self::insert`] but keeping when it was last used.
+    pub fn refresh(
+        &mut self,
+        key: ContractKey,
+        size: u64,
+        now: Instant,
+    ) -> Option<Vec<ContractKey>> {
+        let last_used = self.entries.get(&key).map(|entry| entry.last_used);
+        let evicted = self.insert(key, size, now)?;
+        if let (Some(last_used), Some(entry)) = (last_used, self.entries.get_mut(&key)) {
+            entry.last_used = last_used;
+        }
+        Some(evicted)
+    }
+
+    pub fn contains(&self, key: &ContractKey) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// The contracts cached near `location` due to be refreshed, the oldest ones first.
+    pub fn to_refresh(&self, location: Location, now: Instant) -> Vec<ContractKey> {
+        let mut due: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(key, entry)| {
+                now.saturating_duration_since(entry.cached_at) > REFRESH_AGE
+                    && location.distance(Location::from(*key)) <= Distance::new(REFRESH_DISTANCE)
+            })
+            .map(|(key, entry)| (entry.cached_at, *key))
+            .collect();
+        due.sort_unstable_by_key(|(cached_at, _)| *cached_at);
+        due.into_iter()
+            .take(MAX_REFRESHES)
+            .map(|(_, key)| key)
+            .collect()
+    }
+
+    /// Whether the state cached of the contract is too old to be served, marking it as used
+    /// otherwise. Contracts not cached are never stale.
+    pub fn is_stale(&mut self, key: &ContractKey, now: Instant) -> bool {
+        let Some(entry) = self.entries.get_mut(key) else {
+            return false;
+        };
+        if now.saturating_duration_since(entry.cached_at) > MAX_AGE {
+            return true;
+        }
+        entry.last_used = now;
+        false
+    }
+
+    pub fn remove(&mut self, key: &ContractKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.used -= entry.size;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use freenet_stdlib::prelude::ContractInstanceId;
+
+    use super::*;
+
+    fn key(byte: u8) -> ContractKey {
+        ContractKey::from(ContractInstanceId::new([byte; 32]))
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = ContractCache::new(100);
+        let now = Instant::now();
+        assert_eq!(cache.insert(key(0), 101, now), None);
+        assert_eq!(cache.insert(key(1), 40, now), Some(vec![]));
+        assert_eq!(
+            cache.insert(key(2), 40, now + Duration::from_secs(1)),
+            Some(vec![])
+        );
+
+        // the first one is used since, so the second one makes room for the third
+        assert!(!cache.is_stale(&key(1), now + Duration::from_secs(2)));
+        assert_eq!(
+            cache.insert(key(3), 40, now + Duration::from_secs(3)),
+            Some(vec![key(2)])
+        );
+        assert_eq!(cache.used, 80);
+
+        // refreshing a contract replaces its size
+        assert_eq!(
+            cache.insert(key(1), 60, now + Duration::from_secs(4)),
+            Some(vec![])
+        );
+        assert_eq!(cache.used, 100);
+
+        assert!(!cache.is_stale(&key(4), now + MAX_AGE * 2));
+        assert!(cache.is_stale(&key(3), now + MAX_AGE * 2));
+    }
+
+    #[test]
+    fn refreshes_aging_contracts_near_the_node() {
+        let mut cache = ContractCache::new(100);
+        let now = Instant::now();
+        let (near, far) = (key(1), key(128));
+        let location = Location::from(&near);
+        assert!(location.distance(Location::from(&far)) > Distance::new(REFRESH_DISTANCE));
+        cache.insert(near, 10, now);
+        cache.insert(far, 10, now);
+        assert!(cache.to_refresh(location, now).is_empty());
+        assert_eq!(
+            cache.to_refresh(location, now + REFRESH_AGE * 2),
+            vec![near]
+        );
+
+        // refreshing isn't using it, so it's still evicted first
+        assert!(!cache.is_stale(&far, now + Duration::from_secs(1)));
+        cache.refresh(near, 10, now + Duration::from_secs(2));
+        assert!(cache.to_refresh(location, now + REFRESH_AGE).is_empty());
+        assert_eq!(
+            cache.insert(key(3), 85, now + Duration::from_secs(3)),
+            Some(vec![near])
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Replacement of the neighbors lost to churn before the neighborhood of the peer in the ring
+//! degrades.
+//!
+//! Neighbors which stop acknowledging the packets sent to them for longer than a keep-alive
+//! interval are likely gone well before their connections time out. While the responsive
+//! neighbors fall short of the minimum connections plus a headroom for the neighbors expected to
+//! be lost meanwhile, estimated from those lost recently, replacements are acquired close to the
+//! locations of the silent and lost neighbors, so the same regions of the ring stay covered.
+//! Requests back off exponentially for as long as they don't bring in new responsive neighbors,
+//! as when the network has no more peers to offer.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use super::Location;
+use crate::node::PeerId;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(5);
+
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 5);
+
+/// Weight of the latest check in the smoothed neighbors lost per check.
+const CHURN_WEIGHT: f64 = 0.2;
+
+/// Checks whose expected losses the headroom of responsive neighbors covers.
+const HEADROOM_CHECKS: f64 = 3.0;
+
+/// Locations of lost neighbors remembered until replaced at most.
+const MAX_LOST: usize = 32;
+
+pub(super) struct Neighbor {
+    pub peer: PeerId,
+    pub location: Location,
+    pub responsive: bool,
+}
+
+pub(super) struct NeighborMonitor {
+    /// Neighbors as of the last check.
+    known: HashMap<PeerId, Location>,
+    /// Locations of the lost neighbors yet to be replaced.
+    lost: VecDeque<Location>,
+    /// Silent neighbors already being replaced.
+    replacing: HashSet<PeerId>,
+    /// Neighbors lost per check, smoothed.
+    churn: f64,
+    backoff: Duration,
+    next_request: Option<Instant>,
+    /// Responsive neighbors when replacements were last requested.
+    responsive_at_request: usize,
+}
+
+impl NeighborMonitor {
+    pub fn new() -> Self {
+        Self {
+            known: HashMap::new(),
+            lost: VecDeque::new(),
+            replacing: HashSet::new(),
+            churn: 0.0,
+            backoff: MIN_BACKOFF,
+            next_request: None,
+            responsive_at_request: 0,
+        }
+    }
+
+    /// Responsive neighbors aimed for with the given minimum connections.
+    pub fn target(&self, min_connections: usize) -> usize {
+        min_connections + (self.churn * HEADROOM_CHECKS).ceil() as usize
+    }
+
+    /// Checks the current neighbors, returning the locations to acquire replacements close to.
+    pub fn check(
+        &mut self,
+        neighbors: &[Neighbor],
+        min_connections: usize,
+        now: Instant,
+    ) -> Vec<Location> {
+        let current: HashMap<_, _> = neighbors
+            .iter()
+            .map(|neighbor| (neighbor.peer.clone(), neighbor.location))
+            .collect();
+        let mut lost_now = 0;
+        for (peer, location) in &self.known {
+            if current.contains_key(peer) {
+                continue;
+            }
+            lost_now += 1;
+            // neighbors already replaced while silent are not replaced again
+            if self.replacing.remove(peer) {
+                continue;
+            }
+            if self.lost.len() == MAX_LOST {
+                self.lost.pop_front();
+            }
+            self.lost.push_back(*location);
+        }
+        self.churn = self.churn * (1.0 - CHURN_WEIGHT) + lost_now as f64 * CHURN_WEIGHT;
+        self.known = current;
+        self.replacing.retain(|peer| {
+            neighbors
+                .iter()
+                .any(|neighbor| &neighbor.peer == peer && !neighbor.responsive)
+        });
+
+        let responsive = neighbors
+            .iter()
+            .filter(|neighbor| neighbor.responsive)
+            .count();
+        let target = self.target(min_connections);
+        if responsive >= target {
+            self.lost.clear();
+            self.backoff = MIN_BACKOFF;
+            self.next_request = None;
+            return vec![];
+        }
+        if self.next_request.is_some_and(|next| now < next) {
+            return vec![];
+        }
+
+        let missing = target - responsive;
+        let silent: Vec<_> = neighbors
+            .iter()
+            .filter(|neighbor| !neighbor.responsive && !self.replacing.contains(&neighbor.peer))
+            .take(missing)
+            .collect();
+        let lost = (missing - silent.len()).min(self.lost.len());
+        if silent.is_empty() && lost == 0 {
+            return vec![];
+        }
+        if self.next_request.is_some() {
+            self.backoff = if responsive > self.responsive_at_request {
+                MIN_BACKOFF
+            } else {
+                (self.backoff * 2).min(MAX_BACKOFF)
+            };
+        }
+        self.next_request = Some(now + self.backoff);
+        self.responsive_at_request = responsive;
+
+        let mut replacements = Vec::with_capacity(silent.len() + lost);
+        for neighbor in silent {
+            self.replacing.insert(neighbor.peer.clone());
+            replacements.push(neighbor.location);
+        }
+        replacements.extend(self.lost.drain(..lost));
+        replacements
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn neighbor(peer: &PeerId, location: f64, responsive: bool) -> Neighbor {
+        Neighbor {
+            peer: peer.clone(),
+            location: Location::new(location),
+            responsive,
+        }
+    }
+
+    #[test]
+    fn replaces_silent_and_lost_neighbors() {
+        let start = Instant::now();
+        let peers: Vec<_> = (0..4).map(|_| PeerId::random()).collect();
+        let mut monitor = NeighborMonitor::new();
+        let healthy: Vec<_> = peers
+            .iter()
+            .enumerate()
+            .map(|(i, peer)| neighbor(peer, i as f64 / 10.0, true))
+            .collect();
+        assert!(monitor.check(&healthy, 3, start).is_empty());
+
+        // two neighbors go silent, one short of the minimum
+        let mut neighbors = healthy;
+        neighbors[0].responsive = false;
+        neighbors[1].responsive = false;
+        assert_eq!(
+            monitor.check(&neighbors, 3, start),
+            vec![Location::new(0.0)]
+        );
+
+        // three are lost, the headroom grows with the churn and the two not replaced yet are
+        // once the backoff passes
+        let neighbors = vec![neighbor(&peers[3], 0.3, true)];
+        assert!(monitor.check(&neighbors, 3, start).is_empty());
+        assert!(monitor.target(3) > 3);
+        let replacements = monitor.check(&neighbors, 3, start + MIN_BACKOFF);
+        assert_eq!(replacements.len(), 2);
+        assert!(replacements.contains(&Location::new(0.1)));
+        assert!(replacements.contains(&Location::new(0.2)));
+
+        // no new neighbors came in, so it backs off longer
+        assert_eq!(monitor.backoff, MIN_BACKOFF * 2);
+        assert!(monitor
+            .check(&neighbors, 3, start + MIN_BACKOFF * 2)
+            .is_empty());
+
+        // until the neighborhood recovers
+        let recovered: Vec<_> = (0..6)
+            .map(|i| neighbor(&PeerId::random(), i as f64 / 6.0, true))
+            .collect();
+        assert!(monitor
+            .check(&recovered, 3, start + MIN_BACKOFF * 3)
+            .is_empty());
+        assert_eq!(monitor.backoff, MIN_BACKOFF);
+    }
+}
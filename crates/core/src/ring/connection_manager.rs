@@ -1,7 +1,21 @@
+use std::collections::HashMap;
+use std::time::SystemTime;
+
 use parking_lot::Mutex;
 
+use crate::config::LocationStrategy;
+use crate::node::bans::PeerBans;
+use crate::node::gateway_health::GatewayHealth;
+use crate::node::peer_policy::PeerPolicy;
+use crate::router::Reputation;
+use crate::topology::meter::{AttributionSource, ResourceType};
 use crate::topology::{Limits, TopologyManager};
+use crate::transport::{
+    ConnectionTimeouts, NatMapping, PortMappingStatus, RoundTripTime, Traffic, TransportStats,
+};
 
+use super::partition::PartitionDetector;
+use super::placement::{self, PlacementStrategy};
 use super::*;
 
 #[derive(Clone)]
@@ -11,6 +25,28 @@ pub(crate) struct ConnectionManager {
     pub(super) location_for_peer: Arc<RwLock<BTreeMap<PeerId, Location>>>,
     pub(super) topology_manager: Arc<RwLock<TopologyManager>>,
     connections_by_location: Arc<RwLock<BTreeMap<Location, Vec<Connection>>>>,
+    /// Round-trip times measured by the connections to peers.
+    round_trip_times: Arc<RwLock<HashMap<SocketAddr, RoundTripTime>>>,
+    /// Traffic of the connections to peers, reported to the topology manager as their load.
+    traffic: Arc<RwLock<HashMap<SocketAddr, Traffic>>>,
+    /// How the NAT of this peer maps its socket, learnt from gateways.
+    nat_mapping: Arc<RwLock<NatMapping>>,
+    /// State of the forwarding of the port of this peer by its router.
+    port_mapping: Arc<RwLock<PortMappingStatus>>,
+    /// Peers connections are allowed with.
+    peer_policy: PeerPolicy,
+    /// Peers banned for violating the protocol.
+    bans: PeerBans,
+    /// Reliability of the peers, shared with the router.
+    reputation: Reputation,
+    /// Gateways last listed by each seed of the node, DNS names or signed lists.
+    seeded_gateways: Arc<RwLock<HashMap<String, Vec<PeerKeyLocation>>>>,
+    /// Health of the gateways, as last checked.
+    gateway_health: GatewayHealth,
+    /// How the location of this peer is chosen and adjusted.
+    placement: Arc<dyn PlacementStrategy>,
+    /// Whether the neighborhood of this peer is partitioned from the rest of the network.
+    partition: PartitionDetector,
     /// Interim connections ongoing handshake or successfully open connections
     /// Is important to keep track of this so no more connections are accepted prematurely.
     own_location: Arc<AtomicU64>,
@@ -90,18 +126,30 @@ impl ConnectionManager {
             AtomicU64::new(u64::from_le_bytes((-1f64).to_le_bytes()))
         };
 
-        Self::init(
-            max_upstream_bandwidth,
-            max_downstream_bandwidth,
-            min_connections,
-            max_connections,
-            rnd_if_htl_above,
-            (
-                config.key_pair.public().clone(),
-                config.peer_id.clone(),
-                own_location,
+        Self {
+            peer_policy: config.peer_policy.clone(),
+            bans: config.bans.clone(),
+            placement: placement::strategy(
+                config
+                    .config
+                    .network_api
+                    .location_strategy
+                    .unwrap_or_default(),
+                config.location,
             ),
-        )
+            ..Self::init(
+                max_upstream_bandwidth,
+                max_downstream_bandwidth,
+                min_connections,
+                max_connections,
+                rnd_if_htl_above,
+                (
+                    config.key_pair.public().clone(),
+                    config.peer_id.clone(),
+                    own_location,
+                ),
+            )
+        }
     }
 
     fn init(
@@ -112,16 +160,31 @@ impl ConnectionManager {
         rnd_if_htl_above: usize,
         (pub_key, peer_id, own_location): (TransportPublicKey, Option<PeerId>, AtomicU64),
     ) -> Self {
-        let topology_manager = Arc::new(RwLock::new(TopologyManager::new(Limits {
-            max_upstream_bandwidth,
-            max_downstream_bandwidth,
-            min_connections,
-            max_connections,
-        })));
+        let reputation = Reputation::default();
+        let topology_manager = Arc::new(RwLock::new(
+            TopologyManager::new(Limits {
+                max_upstream_bandwidth,
+                max_downstream_bandwidth,
+                min_connections,
+                max_connections,
+            })
+            .with_reputation(reputation.clone()),
+        ));
 
         Self {
             connections_by_location: Arc::new(RwLock::new(BTreeMap::new())),
             location_for_peer: Arc::new(RwLock::new(BTreeMap::new())),
+            round_trip_times: Arc::new(RwLock::new(HashMap::new())),
+            traffic: Arc::new(RwLock::new(HashMap::new())),
+            nat_mapping: Arc::new(RwLock::new(NatMapping::default())),
+            port_mapping: Arc::new(RwLock::new(PortMappingStatus::default())),
+            peer_policy: PeerPolicy::default(),
+            bans: PeerBans::default(),
+            reputation,
+            seeded_gateways: Arc::new(RwLock::new(HashMap::new())),
+            gateway_health: GatewayHealth::default(),
+            placement: placement::strategy(LocationStrategy::default(), None),
+            partition: PartitionDetector::default(),
             open_connections: Arc::new(AtomicUsize::new(0)),
             reserved_connections: Arc::new(AtomicUsize::new(0)),
             topology_manager,
@@ -141,6 +204,14 @@ impl ConnectionManager {
     /// Will panic if the node checking for this condition has no location assigned.
     pub fn should_accept(&self, location: Location, peer_id: &PeerId) -> bool {
         tracing::debug!("Checking if should accept connection");
+        if !self.peer_policy.is_allowed(peer_id) {
+            tracing::debug!(%peer_id, addr = %peer_id.addr, "Peer not allowed by local policy");
+            return false;
+        }
+        if self.bans.is_banned(peer_id, SystemTime::now()) {
+            tracing::debug!(%peer_id, addr = %peer_id.addr, "Peer banned");
+            return false;
+        }
         let open = self
             .open_connections
             .load(std::sync::atomic::Ordering::SeqCst);
@@ -211,6 +282,27 @@ impl ConnectionManager {
     /// # Panic
     ///
     /// Will panic if the node has no peer id assigned yet.
+    /// Location of this peer once its public address is known, as chosen by its strategy.
+    pub fn initial_location(&self, public_addr: &SocketAddr) -> Location {
+        self.placement.initial(public_addr)
+    }
+
+    /// Whether the location assigned to this peer by the gateway it joins through replaces its
+    /// own.
+    pub fn accepts_assigned_location(&self) -> bool {
+        self.placement.accepts_assigned()
+    }
+
+    /// Moves this peer to the location its strategy prefers given its neighbors, if any.
+    pub fn adjust_location(&self) -> Option<Location> {
+        let current = self.own_location().location?;
+        let neighbors: Vec<_> = self.location_for_peer.read().values().copied().collect();
+        let location = self.placement.adjust(current, &neighbors)?;
+        tracing::info!(from = %current, to = %location, "Adjusting own location");
+        self.update_location(Some(location));
+        Some(location)
+    }
+
     pub fn own_location(&self) -> PeerKeyLocation {
         let location = f64::from_le_bytes(
             self.own_location
@@ -277,8 +369,10 @@ impl ConnectionManager {
                 open_at: Instant::now(),
             });
         }
-        self.open_connections
+        let open = self
+            .open_connections
             .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        crate::metrics::set_connected_peers(open + 1);
         std::mem::drop(lop);
     }
 
@@ -286,6 +380,11 @@ impl ConnectionManager {
         let connection_type = if is_alive { "active" } else { "in transit" };
         tracing::debug!(%peer, "Pruning {} connection", connection_type);
 
+        self.round_trip_times.write().remove(&peer.addr);
+        self.traffic.write().remove(&peer.addr);
+        if is_alive {
+            self.topology_manager.write().remove_peer(peer);
+        }
         let mut locations_for_peer = self.location_for_peer.write();
 
         let Some(loc) = locations_for_peer.remove(peer) else {
@@ -307,8 +406,10 @@ impl ConnectionManager {
         }
 
         if is_alive {
-            self.open_connections
+            let open = self
+                .open_connections
                 .fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+            crate::metrics::set_connected_peers(open - 1);
         } else {
             self.reserved_connections
                 .fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
@@ -317,6 +418,165 @@ impl ConnectionManager {
         Some(loc)
     }
 
+    /// Tracks the round-trip time measured by the connection to the peer at `addr`, until the
+    /// connection is pruned.
+    pub fn track_round_trip_time(&self, addr: SocketAddr, round_trip_time: RoundTripTime) {
+        self.round_trip_times.write().insert(addr, round_trip_time);
+    }
+
+    /// Tracks the traffic of the connection to the peer at `addr`, until the connection is
+    /// pruned.
+    pub fn track_traffic(&self, addr: SocketAddr, traffic: Traffic) {
+        self.traffic.write().insert(addr, traffic);
+    }
+
+    /// Reports the traffic of the connections since last reported to the topology manager, as
+    /// the load of the peers they are with.
+    pub fn report_traffic(&self, at_time: Instant) {
+        let samples: Vec<_> = {
+            let traffic = self.traffic.read();
+            self.get_connections_by_location()
+                .into_values()
+                .flatten()
+                .filter_map(|conn| {
+                    let sample = traffic.get(&conn.location.peer.addr)?.take();
+                    Some((conn.location, sample))
+                })
+                .collect()
+        };
+        let mut topology_manager = self.topology_manager.write();
+        for (peer, (sent, received)) in samples {
+            let source = AttributionSource::Peer(peer);
+            topology_manager.report_resource_usage(
+                &source,
+                ResourceType::OutboundBandwidthBytes,
+                sent as f64,
+                at_time,
+            );
+            topology_manager.report_resource_usage(
+                &source,
+                ResourceType::InboundBandwidthBytes,
+                received as f64,
+                at_time,
+            );
+        }
+    }
+
+    /// Round-trip time to the peer at `addr`, `None` if not measured yet.
+    pub fn round_trip_time(&self, addr: &SocketAddr) -> Option<Duration> {
+        self.round_trip_times.read().get(addr)?.get()
+    }
+
+    /// Statistics of the connections to peers, by their address.
+    pub fn transport_stats(&self) -> HashMap<SocketAddr, TransportStats> {
+        let round_trip_times = self.round_trip_times.read();
+        let traffic = self.traffic.read();
+        self.get_connections_by_location()
+            .into_values()
+            .flatten()
+            .filter_map(|conn| {
+                let addr = conn.location.peer.addr;
+                let mut stats = round_trip_times.get(&addr)?.stats();
+                if let Some(traffic) = traffic.get(&addr) {
+                    stats = stats.with_traffic(traffic, conn.open_at.elapsed());
+                }
+                Some((addr, stats))
+            })
+            .collect()
+    }
+
+    /// Timeouts of the connection to the peer at `addr`, derived from its round-trip time.
+    pub fn connection_timeouts(&self, addr: &SocketAddr) -> ConnectionTimeouts {
+        self.round_trip_times
+            .read()
+            .get(addr)
+            .map(RoundTripTime::timeouts)
+            .unwrap_or_default()
+    }
+
+    /// Whether the peer at `addr` acknowledges the packets sent to it, having gone silent for
+    /// no longer than a keep-alive interval.
+    pub fn is_responsive(&self, addr: &SocketAddr) -> bool {
+        let round_trip_times = self.round_trip_times.read();
+        let Some(round_trip_time) = round_trip_times.get(addr) else {
+            return true;
+        };
+        round_trip_time.silent_for().map_or(true, |silent| {
+            silent <= round_trip_time.timeouts().keep_alive
+        })
+    }
+
+    pub fn nat_mapping(&self) -> NatMapping {
+        *self.nat_mapping.read()
+    }
+
+    pub fn set_nat_mapping(&self, mapping: NatMapping) {
+        *self.nat_mapping.write() = mapping;
+    }
+
+    pub fn port_mapping(&self) -> PortMappingStatus {
+        self.port_mapping.read().clone()
+    }
+
+    pub fn set_port_mapping(&self, status: PortMappingStatus) {
+        *self.port_mapping.write() = status;
+    }
+
+    pub fn peer_policy(&self) -> &PeerPolicy {
+        &self.peer_policy
+    }
+
+    pub fn bans(&self) -> &PeerBans {
+        &self.bans
+    }
+
+    pub fn reputation(&self) -> &Reputation {
+        &self.reputation
+    }
+
+    /// Addresses of the connected peers, the most reputable first, to relay the connection to
+    /// `target` through when its NAT can't be traversed.
+    pub fn relay_candidates(&self, target: &PeerId) -> Vec<SocketAddr> {
+        const MAX_RELAYS: usize = 4;
+        let mut candidates: Vec<_> = self
+            .connected_peers()
+            .filter(|peer| peer != target && peer.addr != target.addr)
+            .map(|peer| (self.reputation.score(&peer), peer.addr))
+            .collect();
+        candidates.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        candidates
+            .into_iter()
+            .take(MAX_RELAYS)
+            .map(|(_, addr)| addr)
+            .collect()
+    }
+
+    pub fn seeded_gateways(&self) -> Vec<PeerKeyLocation> {
+        let mut seen = HashSet::new();
+        self.seeded_gateways
+            .read()
+            .values()
+            .flatten()
+            .filter(|gw| seen.insert(gw.peer.addr))
+            .cloned()
+            .collect()
+    }
+
+    /// Replaces the gateways found through the given seed.
+    pub fn set_seeded_gateways(&self, seed: &str, gateways: Vec<PeerKeyLocation>) {
+        self.seeded_gateways
+            .write()
+            .insert(seed.to_owned(), gateways);
+    }
+
+    pub fn gateway_health(&self) -> &GatewayHealth {
+        &self.gateway_health
+    }
+
+    pub fn partition(&self) -> &PartitionDetector {
+        &self.partition
+    }
+
     pub(super) fn get_open_connections(&self) -> usize {
         self.open_connections
             .load(std::sync::atomic::Ordering::SeqCst)
@@ -382,6 +642,15 @@ impl ConnectionManager {
         self.connections_by_location.read().len()
     }
 
+    /// Currently connected peers and their location.
+    pub fn peer_locations(&self) -> Vec<(PeerId, Location)> {
+        self.location_for_peer
+            .read()
+            .iter()
+            .map(|(peer, loc)| (peer.clone(), *loc))
+            .collect()
+    }
+
     pub(super) fn connected_peers(&self) -> impl Iterator<Item = PeerId> {
         let read = self.location_for_peer.read();
         read.keys().cloned().collect::<Vec<_>>().into_iter()
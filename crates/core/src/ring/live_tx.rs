@@ -61,6 +61,15 @@ impl LiveTransactionTracker {
         self.tx_per_peer.contains_key(peer)
     }
 
+    /// Peers the transaction was sent to, while it is live.
+    pub(crate) fn peers_of(&self, tx: &Transaction) -> Vec<PeerId> {
+        self.tx_per_peer
+            .iter()
+            .filter(|entry| entry.value().contains(tx))
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
     pub(crate) fn still_alive(&self, tx: &Transaction) -> bool {
         self.tx_per_peer.iter().any(|e| e.value().contains(tx))
     }
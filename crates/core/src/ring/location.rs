@@ -15,7 +15,8 @@ impl Location {
     }
 
     fn deterministic_loc(addr: &std::net::SocketAddr) -> Self {
-        match addr.ip() {
+        // IPv4 peers seen by dual-stack sockets at their IPv4-mapped addresses
+        match addr.ip().to_canonical() {
             std::net::IpAddr::V4(ipv4) => {
                 let value: u32 = ipv4.into();
                 // Mask out the last byte for sybil mitigation
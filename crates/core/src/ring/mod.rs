@@ -21,7 +21,7 @@ use dashmap::mapref::one::Ref as DmRef;
 use either::Either;
 use freenet_stdlib::prelude::ContractKey;
 use itertools::Itertools;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use rand::Rng;
 
 use crate::message::TransactionType;
@@ -33,25 +33,33 @@ use crate::util::Contains;
 use crate::{
     config::GlobalExecutor,
     message::Transaction,
-    node::{self, EventLoopNotificationsSender, NodeConfig, PeerId},
+    node::{self, pins::PinnedContracts, EventLoopNotificationsSender, NodeConfig, PeerId},
     operations::connect,
     router::Router,
 };
 
+mod cache;
+mod churn;
 mod connection_manager;
 pub(crate) use connection_manager::ConnectionManager;
 mod connection;
 mod live_tx;
 mod location;
+mod partition;
 mod peer_key_location;
+mod placement;
 mod score;
 mod seeding;
 
+use self::cache::ContractCache;
+use self::churn::{Neighbor, NeighborMonitor};
 use self::score::Score;
 
 pub use self::live_tx::LiveTransactionTracker;
+pub(crate) use cache::CacheLimits;
 pub use connection::Connection;
 pub use location::{Distance, Location};
+pub(crate) use partition::PartitionStatus;
 pub use peer_key_location::PeerKeyLocation;
 
 /// Thread safe and friendly data structure to keep track of the local knowledge
@@ -66,7 +74,13 @@ pub(crate) struct Ring {
     pub router: Arc<RwLock<Router>>,
     pub live_tx_tracker: LiveTransactionTracker,
     seeding_manager: seeding::SeedingManager,
+    /// Contracts pinned by the operator, seeded regardless of their distance to this node.
+    pins: PinnedContracts,
+    /// States of the contracts routed through this node cached, if caching them is enabled.
+    contract_cache: Option<Mutex<ContractCache>>,
     event_register: Box<dyn NetEventRegister>,
+    /// Whether this node never writes contract states to disk, so it doesn't seed contracts.
+    storage_less: bool,
     /// Whether this peer is a gateway or not. This will affect behavior of the node when acquiring
     /// and dropping connections.
     #[allow(unused)]
@@ -111,17 +125,27 @@ impl Ring {
             Self::DEFAULT_MAX_HOPS_TO_LIVE
         };
 
-        let router = Arc::new(RwLock::new(Router::new(&[])));
+        let router = Arc::new(RwLock::new(
+            Router::new(&[]).with_reputation(connection_manager.reputation().clone()),
+        ));
         GlobalExecutor::spawn(Self::refresh_router(router.clone(), event_register.clone()));
 
+        let cache_limits = config.config.network_api.cache_limits();
+        let contract_cache = cache_limits
+            .enabled
+            .then(|| Mutex::new(ContractCache::new(cache_limits.budget)));
+
         // Just initialize with a fake location, this will be later updated when the peer has an actual location assigned.
         let ring = Ring {
             max_hops_to_live,
             router,
             connection_manager,
-            seeding_manager: seeding::SeedingManager::new(),
+            seeding_manager: seeding::SeedingManager::new(config.pins.clone()),
+            pins: config.pins.clone(),
+            contract_cache,
             live_tx_tracker: live_tx_tracker.clone(),
             event_register: Box::new(event_register),
+            storage_less: config.config.network_api.storage_less,
             is_gateway,
         };
 
@@ -140,9 +164,20 @@ impl Ring {
             tracing::info_span!(parent: current_span, "connection_maintenance")
         };
 
+        let (replacements_tx, replacements_rx) = mpsc::channel(16);
+        GlobalExecutor::spawn(
+            ring.clone()
+                .monitor_neighbors(replacements_tx)
+                .instrument(span.clone()),
+        );
         GlobalExecutor::spawn(
             ring.clone()
-                .connection_maintenance(event_loop_notifier, live_tx_tracker, missing_candidate_rx)
+                .connection_maintenance(
+                    event_loop_notifier,
+                    live_tx_tracker,
+                    missing_candidate_rx,
+                    replacements_rx,
+                )
                 .instrument(span),
         );
 
@@ -172,13 +207,17 @@ impl Ring {
                 .expect("todo: propagate this to main thread");
             if !history.is_empty() {
                 let router_ref = &mut *router.write();
-                *router_ref = Router::new(&history);
+                *router_ref =
+                    Router::new(&history).with_reputation(router_ref.reputation().clone());
             }
         }
     }
 
     /// Return if a contract is within appropiate seeding distance.
     pub fn should_seed(&self, key: &ContractKey) -> bool {
+        if self.storage_less {
+            return false;
+        }
         let own_loc = self
             .connection_manager
             .own_location()
@@ -194,6 +233,7 @@ impl Ring {
             .own_location()
             .location
             .expect("should be set");
+        self.uncache_contract(&key);
         self.seeding_manager.seed_contract(key, own_loc)
     }
 
@@ -203,6 +243,74 @@ impl Ring {
         self.seeding_manager.is_seeding_contract(key)
     }
 
+    /// Contracts this node is seeding, subscribed to their updates in the network.
+    pub fn seeding_contracts(&self) -> Vec<ContractKey> {
+        self.seeding_manager.seeding_contracts()
+    }
+
+    /// Whether to cache the state of a contract routed through this node, not seeded by it.
+    pub fn should_cache(&self, key: &ContractKey) -> bool {
+        self.contract_cache.is_some()
+            && !self.is_seeding_contract(key)
+            && !self.pins.is_pinned(key)
+    }
+
+    pub fn pins(&self) -> &PinnedContracts {
+        &self.pins
+    }
+
+    /// Records the state of the contract cached, returning the contracts to evict to fit it in
+    /// the cache budget, `None` if it shouldn't be cached.
+    pub fn cache_contract(&self, key: ContractKey, size: usize) -> Option<Vec<ContractKey>> {
+        self.contract_cache
+            .as_ref()?
+            .lock()
+            .insert(key, size as u64, Instant::now())
+    }
+
+    /// Records the state of the contract cached refreshed in the background, see
+    /// [`Self::cache_contract`].
+    pub fn refresh_cached_contract(
+        &self,
+        key: ContractKey,
+        size: usize,
+    ) -> Option<Vec<ContractKey>> {
+        self.contract_cache
+            .as_ref()?
+            .lock()
+            .refresh(key, size as u64, Instant::now())
+    }
+
+    pub fn is_cached(&self, key: &ContractKey) -> bool {
+        self.contract_cache
+            .as_ref()
+            .is_some_and(|cache| cache.lock().contains(key))
+    }
+
+    /// The contracts cached near this node due to be refreshed in the background.
+    pub fn cached_to_refresh(&self) -> Vec<ContractKey> {
+        let (Some(cache), Some(location)) = (
+            &self.contract_cache,
+            self.connection_manager.own_location().location,
+        ) else {
+            return vec![];
+        };
+        cache.lock().to_refresh(location, Instant::now())
+    }
+
+    pub fn uncache_contract(&self, key: &ContractKey) {
+        if let Some(cache) = &self.contract_cache {
+            cache.lock().remove(key);
+        }
+    }
+
+    /// Whether the state cached of the contract is too old to be served from this node.
+    pub fn is_cached_stale(&self, key: &ContractKey) -> bool {
+        self.contract_cache
+            .as_ref()
+            .is_some_and(|cache| cache.lock().is_stale(key, Instant::now()))
+    }
+
     pub fn record_request(
         &self,
         recipient: PeerKeyLocation,
@@ -264,6 +372,14 @@ impl Ring {
             .topology_manager
             .write()
             .report_outbound_request(event.peer.clone(), event.contract_location);
+        if let Some(own_location) = self.connection_manager.own_location().location {
+            let success = matches!(event.outcome, crate::router::RouteOutcome::Success { .. });
+            self.connection_manager.partition().record(
+                own_location.distance(event.contract_location),
+                success,
+                Instant::now(),
+            );
+        }
         self.router.write().add_event(event);
     }
 
@@ -276,6 +392,16 @@ impl Ring {
         self.seeding_manager.add_subscriber(contract, subscriber)
     }
 
+    /// Records the peer this node is subscribed to the contract through, so further
+    /// subscriptions to it are aggregated into this one.
+    pub fn set_upstream(&self, contract: &ContractKey, provider: PeerKeyLocation) {
+        self.seeding_manager.set_upstream(contract, provider)
+    }
+
+    pub fn is_subscribed(&self, contract: &ContractKey) -> bool {
+        self.seeding_manager.is_subscribed(contract)
+    }
+
     pub fn subscribers_of(
         &self,
         contract: &ContractKey,
@@ -283,6 +409,11 @@ impl Ring {
         self.seeding_manager.subscribers_of(contract)
     }
 
+    /// Peers known to hold replicas of the contract, which it is synchronized with.
+    pub fn replicas_of(&self, contract: &ContractKey) -> Vec<PeerKeyLocation> {
+        self.seeding_manager.replicas_of(contract)
+    }
+
     pub async fn prune_connection(&self, peer: PeerId) {
         tracing::debug!(%peer, "Removing connection");
         self.live_tx_tracker.prune_transactions_from_peer(&peer);
@@ -323,11 +454,49 @@ impl Ring {
             })
     }
 
+    /// Checks the liveness of the neighbors, sending the locations to acquire replacements close
+    /// to for those lost to churn to the connection maintenance task.
+    async fn monitor_neighbors(self: Arc<Self>, replacements: mpsc::Sender<Location>) {
+        const CHECK_TICK_DURATION: Duration = Duration::from_secs(10);
+
+        let mut check_interval = tokio::time::interval(CHECK_TICK_DURATION);
+        check_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        let mut monitor = NeighborMonitor::new();
+        loop {
+            check_interval.tick().await;
+            let connection_manager = &self.connection_manager;
+            let neighbors: Vec<_> = connection_manager
+                .get_connections_by_location()
+                .into_iter()
+                .flat_map(|(location, conns)| {
+                    conns.into_iter().map(move |conn| Neighbor {
+                        responsive: connection_manager.is_responsive(&conn.location.peer.addr),
+                        peer: conn.location.peer,
+                        location,
+                    })
+                })
+                .collect();
+            let locations = monitor.check(
+                &neighbors,
+                connection_manager.min_connections,
+                Instant::now(),
+            );
+            for location in locations {
+                tracing::debug!(%location, "Replacing neighbor lost to churn");
+                if replacements.send(location).await.is_err() {
+                    tracing::debug!("Shutting down neighbor monitoring");
+                    return;
+                }
+            }
+        }
+    }
+
     async fn connection_maintenance(
         self: Arc<Self>,
         notifier: EventLoopNotificationsSender,
         live_tx_tracker: LiveTransactionTracker,
         mut missing_candidates: mpsc::Receiver<PeerId>,
+        mut replacements: mpsc::Receiver<Location>,
     ) -> anyhow::Result<()> {
         tracing::debug!("Initializing connection maintenance task");
         #[cfg(not(test))]
@@ -336,11 +505,14 @@ impl Ring {
         const CONNECTION_AGE_THRESOLD: Duration = Duration::from_secs(5);
         const CHECK_TICK_DURATION: Duration = Duration::from_secs(60);
         const REGENERATE_DENSITY_MAP_INTERVAL: Duration = Duration::from_secs(60);
+        const ADJUST_LOCATION_INTERVAL: Duration = Duration::from_secs(30 * 60);
 
         let mut check_interval = tokio::time::interval(CHECK_TICK_DURATION);
         check_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
         let mut refresh_density_map = tokio::time::interval(REGENERATE_DENSITY_MAP_INTERVAL);
         refresh_density_map.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        let mut adjust_location = tokio::time::interval(ADJUST_LOCATION_INTERVAL);
+        adjust_location.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
 
         let mut missing = BTreeMap::new();
 
@@ -429,6 +601,7 @@ impl Ring {
                     .collect()
             };
 
+            self.connection_manager.report_traffic(Instant::now());
             let adjustment = self
                 .connection_manager
                 .topology_manager
@@ -467,6 +640,12 @@ impl Ring {
                 self.refresh_density_request_cache();
               }
               _ = check_interval.tick() => {}
+              _ = adjust_location.tick() => {
+                self.connection_manager.adjust_location();
+              }
+              Some(location) = replacements.recv() => {
+                pending_conn_adds.insert(location);
+              }
             }
         }
     }
@@ -520,6 +699,7 @@ impl Ring {
                 query_target,
                 ideal_location,
                 joiner,
+                joiner_nat: self.connection_manager.nat_mapping(),
                 max_hops_to_live: missing_connections,
                 skip_connections: new_skip_list,
                 skip_forwards: HashSet::new(),
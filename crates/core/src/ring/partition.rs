@@ -0,0 +1,202 @@
+//! Detection of the neighborhood of the peer being partitioned from the rest of the network.
+//!
+//! Routes to locations far from the peer go through other regions of the ring, so when most of
+//! them fail while the peer still has connections, its neighborhood is likely cut off from the
+//! rest of the network. While partitioned, the peer joins again through its gateways, bringing in
+//! connections outside of its neighborhood until the routes to distant locations recover.
+
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use parking_lot::RwLock;
+use serde::Serialize;
+
+use super::Distance;
+
+/// Routes to locations at least this far from the peer are considered distant.
+const DISTANT: f64 = 0.25;
+
+/// Distant routes the failure rate is computed over at most.
+const WINDOW: usize = 32;
+
+/// Distant routes older than this are not considered.
+const MAX_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// Distant routes needed before considering the peer partitioned.
+const MIN_ROUTES: usize = 12;
+
+/// Failure rate of the distant routes above which the peer is considered partitioned.
+const PARTITIONED_FAILURE_RATE: f64 = 0.8;
+
+/// Failure rate of the distant routes below which a partitioned peer is considered healed.
+const HEALED_FAILURE_RATE: f64 = 0.5;
+
+/// Detections and heals remembered.
+const MAX_EVENTS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct PartitionEvent {
+    /// Unix timestamp, in seconds, of the event.
+    pub at: u64,
+    /// Whether the partition was detected, otherwise healed.
+    pub partitioned: bool,
+    pub failure_rate: f64,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct PartitionStatus {
+    pub partitioned: bool,
+    /// Failure rate of the recent distant routes, `None` without any.
+    pub failure_rate: Option<f64>,
+    pub distant_routes: usize,
+    /// Detections and heals, the latest last.
+    pub events: Vec<PartitionEvent>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Distant routes as when they finished and whether they succeeded.
+    routes: VecDeque<(Instant, bool)>,
+    partitioned: bool,
+    events: VecDeque<PartitionEvent>,
+}
+
+impl State {
+    fn failure_rate(&self) -> Option<f64> {
+        if self.routes.is_empty() {
+            return None;
+        }
+        let failures = self.routes.iter().filter(|(_, success)| !success).count();
+        Some(failures as f64 / self.routes.len() as f64)
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while self
+            .routes
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > MAX_AGE)
+        {
+            self.routes.pop_front();
+        }
+    }
+}
+
+/// Outcomes of the recent routes to distant locations, shared by the ring and the join
+/// procedure.
+#[derive(Clone, Default)]
+pub(crate) struct PartitionDetector(Arc<RwLock<State>>);
+
+impl PartitionDetector {
+    /// Records the outcome of a route to a location at `distance` from the peer.
+    pub fn record(&self, distance: Distance, success: bool, now: Instant) {
+        if distance.as_f64() < DISTANT {
+            return;
+        }
+        let mut state = self.0.write();
+        state.expire(now);
+        if state.routes.len() == WINDOW {
+            state.routes.pop_front();
+        }
+        state.routes.push_back((now, success));
+        if state.routes.len() < MIN_ROUTES {
+            return;
+        }
+        let Some(failure_rate) = state.failure_rate() else {
+            return;
+        };
+        let partitioned = if state.partitioned {
+            failure_rate > HEALED_FAILURE_RATE
+        } else {
+            failure_rate >= PARTITIONED_FAILURE_RATE
+        };
+        if partitioned == state.partitioned {
+            return;
+        }
+        if partitioned {
+            tracing::warn!(
+                failure_rate,
+                "Routes to distant locations failing, neighborhood partitioned from the network"
+            );
+        } else {
+            tracing::info!(
+                failure_rate,
+                "Routes to distant locations recovered, partition healed"
+            );
+        }
+        state.partitioned = partitioned;
+        if state.events.len() == MAX_EVENTS {
+            state.events.pop_front();
+        }
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        state.events.push_back(PartitionEvent {
+            at,
+            partitioned,
+            failure_rate,
+        });
+    }
+
+    pub fn is_partitioned(&self) -> bool {
+        self.0.read().partitioned
+    }
+
+    pub fn status(&self) -> PartitionStatus {
+        let state = self.0.read();
+        PartitionStatus {
+            partitioned: state.partitioned,
+            failure_rate: state.failure_rate(),
+            distant_routes: state.routes.len(),
+            events: state.events.iter().copied().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_and_heals_partition() {
+        let detector = PartitionDetector::default();
+        let start = Instant::now();
+        let far = Distance::new(0.4);
+        // failing routes close by don't matter
+        for _ in 0..WINDOW {
+            detector.record(Distance::new(0.1), false, start);
+        }
+        assert_eq!(detector.status().distant_routes, 0);
+
+        for _ in 0..MIN_ROUTES - 1 {
+            detector.record(far, false, start);
+        }
+        assert!(!detector.is_partitioned());
+        detector.record(far, false, start);
+        assert!(detector.is_partitioned());
+
+        // a few successes are not enough to heal
+        for _ in 0..4 {
+            detector.record(far, true, start);
+        }
+        assert!(detector.is_partitioned());
+        for _ in 0..WINDOW / 2 {
+            detector.record(far, true, start);
+        }
+        let status = detector.status();
+        assert!(!status.partitioned);
+        assert_eq!(status.distant_routes, WINDOW);
+        assert_eq!(status.events.len(), 2);
+        assert!(status.events[0].partitioned);
+        assert!(!status.events[1].partitioned);
+
+        // old routes expire
+        detector.record(far, false, start + MAX_AGE * 2);
+        assert_eq!(detector.status().distant_routes, 1);
+    }
+}
@@ -0,0 +1,134 @@
+//! Strategies placing the node in the ring, choosing its location once its public address is
+//! known and adjusting it over time, configured with [`LocationStrategy`].
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use super::Location;
+use crate::config::LocationStrategy;
+
+/// Neighbor gaps wider than the one of the node by this factor make a keyspace balancing node
+/// move, so it doesn't move back and forth between gaps of similar width.
+const REBALANCE_FACTOR: f64 = 2.0;
+
+pub(crate) trait PlacementStrategy: Send + Sync {
+    /// Location of the node once its public address is known.
+    fn initial(&self, public_addr: &SocketAddr) -> Location;
+
+    /// Whether the location assigned by the gateway the node joins through replaces its own,
+    /// otherwise gateways are asked to take the location of the node as is.
+    fn accepts_assigned(&self) -> bool {
+        false
+    }
+
+    /// Location to move to given the current one and the locations of the neighbors, `None` to
+    /// stay.
+    fn adjust(&self, _current: Location, _neighbors: &[Location]) -> Option<Location> {
+        None
+    }
+}
+
+/// Derives the location from the public address, so peers can't pick where they join.
+struct AddressDerived;
+
+impl PlacementStrategy for AddressDerived {
+    fn initial(&self, public_addr: &SocketAddr) -> Location {
+        Location::from_address(public_addr)
+    }
+
+    fn accepts_assigned(&self) -> bool {
+        true
+    }
+}
+
+struct Random;
+
+impl PlacementStrategy for Random {
+    fn initial(&self, _public_addr: &SocketAddr) -> Location {
+        Location::random()
+    }
+}
+
+struct Pinned(Location);
+
+impl PlacementStrategy for Pinned {
+    fn initial(&self, _public_addr: &SocketAddr) -> Location {
+        self.0
+    }
+}
+
+/// Starts at the location derived from the address and moves to the middle of the widest gap
+/// between the neighbors, spreading the keyspace evenly across the nodes.
+struct KeyspaceBalancing;
+
+impl PlacementStrategy for KeyspaceBalancing {
+    fn initial(&self, public_addr: &SocketAddr) -> Location {
+        Location::from_address(public_addr)
+    }
+
+    fn adjust(&self, current: Location, neighbors: &[Location]) -> Option<Location> {
+        if neighbors.len() < 2 {
+            return None;
+        }
+        let mut locations: Vec<_> = neighbors.iter().map(Location::as_f64).collect();
+        locations.sort_by(f64::total_cmp);
+        // gaps as their start and width, the last wrapping around the ring
+        let gaps: Vec<_> = locations
+            .iter()
+            .zip(locations.iter().cycle().skip(1))
+            .map(|(start, end)| (*start, (end - start).rem_euclid(1.0)))
+            .collect();
+        let (start, width) = gaps
+            .iter()
+            .copied()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+        let current = current.as_f64();
+        let own_width = gaps
+            .iter()
+            .find(|(start, width)| (current - start).rem_euclid(1.0) < *width)
+            .map_or(0.0, |(_, width)| *width);
+        (width > own_width * REBALANCE_FACTOR)
+            .then(|| Location::new((start + width / 2.0).rem_euclid(1.0)))
+    }
+}
+
+/// The strategy of the given kind, pinning the location when one is configured.
+pub(crate) fn strategy(
+    kind: LocationStrategy,
+    pinned: Option<Location>,
+) -> Arc<dyn PlacementStrategy> {
+    if let Some(location) = pinned {
+        return Arc::new(Pinned(location));
+    }
+    match kind {
+        LocationStrategy::Address | LocationStrategy::Pinned => Arc::new(AddressDerived),
+        LocationStrategy::Random => Arc::new(Random),
+        LocationStrategy::KeyspaceBalancing => Arc::new(KeyspaceBalancing),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keyspace_balancing() {
+        let balancing = strategy(LocationStrategy::KeyspaceBalancing, None);
+        let neighbors: Vec<_> = [0.1, 0.2, 0.3, 0.4]
+            .into_iter()
+            .map(Location::new)
+            .collect();
+        assert_eq!(balancing.adjust(Location::new(0.15), &neighbors[..1]), None);
+
+        // crowded between 0.1 and 0.2 while 0.4 to 0.1 is empty
+        let moved = balancing.adjust(Location::new(0.15), &neighbors).unwrap();
+        assert!((moved.as_f64() - 0.75).abs() < 1e-9);
+        // and stays once there
+        assert_eq!(balancing.adjust(moved, &neighbors), None);
+
+        let pinned = strategy(LocationStrategy::KeyspaceBalancing, Some(moved));
+        assert_eq!(pinned.adjust(Location::new(0.15), &neighbors), None);
+        assert!(!pinned.accepts_assigned());
+        assert!(strategy(LocationStrategy::Address, None).accepts_assigned());
+    }
+}
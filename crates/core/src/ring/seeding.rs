@@ -1,7 +1,13 @@
 use super::{Location, PeerKeyLocation, Score};
+use crate::node::pins::PinnedContracts;
 use dashmap::{mapref::one::Ref as DmRef, DashMap};
 use freenet_stdlib::prelude::ContractKey;
 
+/// Contracts seeded by this node and the subscription tree of each one.
+///
+/// Subscriptions are aggregated along the tree: a peer is subscribed once to a contract however
+/// many subscriptions it forwards, so each update is forwarded once per peer, and a node already
+/// subscribed upstream to a contract doesn't subscribe again for new subscribers of its own.
 pub(crate) struct SeedingManager {
     /// The container for subscriber is a vec instead of something like a hashset
     /// that would allow for blind inserts of duplicate peers subscribing because
@@ -9,8 +15,12 @@ pub(crate) struct SeedingManager {
     /// of subscribers more often than inserting, and anyways is a relatively short sequence
     /// then is more optimal to just use a vector for it's compact memory layout.
     subscribers: DashMap<ContractKey, Vec<PeerKeyLocation>>,
+    /// Peers this node is subscribed to the updates of contracts through.
+    upstream: DashMap<ContractKey, PeerKeyLocation>,
     /// Contracts this peer is seeding.
     seeding_contract: DashMap<ContractKey, Score>,
+    /// Contracts always seeded, never dropped for others.
+    pins: PinnedContracts,
 }
 
 impl SeedingManager {
@@ -26,10 +36,12 @@ impl SeedingManager {
     /// Min number of seeding contracts.
     const MIN_SEEDING_CONTRACTS: usize = Self::MAX_SEEDING_CONTRACTS / 4;
 
-    pub fn new() -> Self {
+    pub fn new(pins: PinnedContracts) -> Self {
         Self {
             subscribers: DashMap::new(),
+            upstream: DashMap::new(),
             seeding_contract: DashMap::new(),
+            pins,
         }
     }
 
@@ -37,7 +49,7 @@ impl SeedingManager {
     pub fn should_seed(&self, key: &ContractKey, own_location: Location) -> bool {
         const CACHING_DISTANCE: f64 = 0.05;
         let caching_distance = super::Distance::new(CACHING_DISTANCE);
-        if self.seeding_contract.len() < Self::MIN_SEEDING_CONTRACTS {
+        if self.pins.is_pinned(key) || self.seeding_contract.len() < Self::MIN_SEEDING_CONTRACTS {
             return true;
         }
         let key_loc = Location::from(key);
@@ -46,11 +58,14 @@ impl SeedingManager {
         }
 
         let contract_score = self.calculate_seed_score(key, own_location);
-        let r = self
+        let Some(r) = self
             .seeding_contract
             .iter()
+            .filter(|v| !self.pins.is_pinned(v.key()))
             .min_by_key(|v| *v.value())
-            .unwrap();
+        else {
+            return false;
+        };
         let min_score = *r.value();
         contract_score > min_score
     }
@@ -69,10 +84,12 @@ impl SeedingManager {
             if let Some(dropped_contract) = self
                 .seeding_contract
                 .iter()
+                .filter(|v| !self.pins.is_pinned(v.key()))
                 .min_by_key(|v| *v.value())
                 .map(|entry| *entry.key())
             {
                 self.seeding_contract.remove(&dropped_contract);
+                self.upstream.remove(&dropped_contract);
                 if let Some((_, mut subscribers_of_contract)) =
                     self.subscribers.remove(&dropped_contract)
                 {
@@ -99,7 +116,16 @@ impl SeedingManager {
         self.seeding_contract.contains_key(key)
     }
 
+    pub fn seeding_contracts(&self) -> Vec<ContractKey> {
+        self.seeding_contract
+            .iter()
+            .map(|entry| *entry.key())
+            .collect()
+    }
+
     /// Will return an error in case the max number of subscribers has been added.
+    ///
+    /// Peers already subscribed aren't added again, only their location is updated.
     pub fn add_subscriber(
         &self,
         contract: &ContractKey,
@@ -109,20 +135,25 @@ impl SeedingManager {
             .subscribers
             .entry(*contract)
             .or_insert(Vec::with_capacity(Self::TOTAL_MAX_SUBSCRIPTIONS));
-        if subs.len() >= Self::MAX_SUBSCRIBERS {
-            return Err(());
-        }
-        if let Err(next_idx) = subs.value_mut().binary_search(&subscriber) {
-            let subs = subs.value_mut();
-            if subs.len() == Self::MAX_SUBSCRIBERS {
-                return Err(());
-            } else {
-                subs.insert(next_idx, subscriber);
-            }
+        let subs = subs.value_mut();
+        match subs.binary_search_by(|sub| sub.peer.cmp(&subscriber.peer)) {
+            Ok(idx) => subs[idx].location = subscriber.location,
+            Err(_) if subs.len() >= Self::MAX_SUBSCRIBERS => return Err(()),
+            Err(next_idx) => subs.insert(next_idx, subscriber),
         }
         Ok(())
     }
 
+    /// Records the peer this node is subscribed to the contract through.
+    pub fn set_upstream(&self, contract: &ContractKey, provider: PeerKeyLocation) {
+        self.upstream.insert(*contract, provider);
+    }
+
+    /// Whether this node is subscribed upstream to the updates of the contract.
+    pub fn is_subscribed(&self, contract: &ContractKey) -> bool {
+        self.upstream.contains_key(contract)
+    }
+
     pub fn subscribers_of(
         &self,
         contract: &ContractKey,
@@ -130,12 +161,91 @@ impl SeedingManager {
         self.subscribers.get(contract)
     }
 
+    /// Peers known to hold the contract: its subscribers and the peer this node is subscribed
+    /// to it through.
+    pub fn replicas_of(&self, contract: &ContractKey) -> Vec<PeerKeyLocation> {
+        let mut replicas = self
+            .subscribers
+            .get(contract)
+            .map(|subs| subs.clone())
+            .unwrap_or_default();
+        if let Some(upstream) = self.upstream.get(contract) {
+            if !replicas.iter().any(|replica| replica.peer == upstream.peer) {
+                replicas.push(upstream.clone());
+            }
+        }
+        replicas
+    }
+
     pub fn prune_subscriber(&self, loc: Location) {
         self.subscribers.alter_all(|_, mut subs| {
             if let Some(pos) = subs.iter().position(|l| l.location == Some(loc)) {
-                subs.swap_remove(pos);
+                subs.remove(pos);
             }
             subs
         });
+        // subscribe again through other peers the next time
+        self.upstream
+            .retain(|_, provider| provider.location != Some(loc));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use freenet_stdlib::prelude::ContractInstanceId;
+
+    use super::*;
+
+    #[test]
+    fn subscribes_peers_once() {
+        let seeding = SeedingManager::new(PinnedContracts::default());
+        let key = ContractKey::from(ContractInstanceId::new([1; 32]));
+        let peer = PeerKeyLocation::random();
+        let moved = PeerKeyLocation {
+            location: Some(Location::random()),
+            ..peer.clone()
+        };
+        seeding.add_subscriber(&key, peer.clone()).unwrap();
+        seeding.add_subscriber(&key, moved.clone()).unwrap();
+        assert_eq!(*seeding.subscribers_of(&key).unwrap(), vec![moved.clone()]);
+
+        for _ in 1..SeedingManager::MAX_SUBSCRIBERS {
+            seeding
+                .add_subscriber(&key, PeerKeyLocation::random())
+                .unwrap();
+        }
+        assert!(seeding
+            .add_subscriber(&key, PeerKeyLocation::random())
+            .is_err());
+        // subscribing again once full
+        assert!(seeding.add_subscriber(&key, moved.clone()).is_ok());
+
+        assert!(!seeding.is_subscribed(&key));
+        seeding.set_upstream(&key, moved.clone());
+        assert!(seeding.is_subscribed(&key));
+        seeding.prune_subscriber(moved.location.unwrap());
+        assert!(!seeding.is_subscribed(&key));
+        assert_eq!(
+            seeding.subscribers_of(&key).unwrap().len(),
+            SeedingManager::MAX_SUBSCRIBERS - 1
+        );
+    }
+
+    #[test]
+    fn lists_replicas() {
+        let seeding = SeedingManager::new(PinnedContracts::default());
+        let key = ContractKey::from(ContractInstanceId::new([1; 32]));
+        assert!(seeding.replicas_of(&key).is_empty());
+
+        let (subscriber, upstream) = (PeerKeyLocation::random(), PeerKeyLocation::random());
+        seeding.add_subscriber(&key, subscriber.clone()).unwrap();
+        seeding.set_upstream(&key, upstream.clone());
+        let replicas = seeding.replicas_of(&key);
+        assert_eq!(replicas.len(), 2);
+        assert!(replicas.contains(&subscriber) && replicas.contains(&upstream));
+
+        // peers both upstream and subscribed are listed once
+        seeding.set_upstream(&key, subscriber.clone());
+        assert_eq!(seeding.replicas_of(&key), vec![subscriber]);
     }
 }
@@ -1,4 +1,5 @@
 mod isotonic_estimator;
+mod reputation;
 mod util;
 
 use crate::ring::{Location, PeerKeyLocation};
@@ -7,6 +8,8 @@ use serde::{Deserialize, Serialize};
 use std::time::Duration;
 use util::{Mean, TransferSpeed};
 
+pub(crate) use reputation::{PeerEvent, PeerStats, Reputation};
+
 /// # Usage
 /// Important when using this type:
 /// Need to periodically rebuild the Router using `history` for better predictions.
@@ -17,6 +20,9 @@ pub(crate) struct Router {
     failure_estimator: IsotonicEstimator,
     mean_transfer_size: Mean,
     consider_n_closest_peers: usize,
+    /// Kept over rebuilds of the router, unlike the estimators.
+    #[serde(skip)]
+    reputation: Reputation,
 }
 
 impl Router {
@@ -108,9 +114,19 @@ impl Router {
             ),
             mean_transfer_size,
             consider_n_closest_peers: 2,
+            reputation: Reputation::default(),
         }
     }
 
+    pub fn with_reputation(mut self, reputation: Reputation) -> Self {
+        self.reputation = reputation;
+        self
+    }
+
+    pub fn reputation(&self) -> &Reputation {
+        &self.reputation
+    }
+
     #[allow(dead_code)]
     pub fn considering_n_closest_peers(mut self, n: u32) -> Self {
         self.consider_n_closest_peers = n as usize;
@@ -118,6 +134,11 @@ impl Router {
     }
 
     pub fn add_event(&mut self, event: RouteEvent) {
+        let peer_event = match event.outcome {
+            RouteOutcome::Success { .. } => PeerEvent::Success,
+            RouteOutcome::Failure => PeerEvent::Failure,
+        };
+        self.reputation.record(&event.peer.peer, peer_event);
         match event.outcome {
             RouteOutcome::Success {
                 time_to_response_start,
@@ -186,15 +207,17 @@ impl Router {
         target_location: Location,
     ) -> Option<&'a PeerKeyLocation> {
         if !self.has_sufficient_historical_data() {
-            // Find the peer with the minimum distance to the contract location,
-            // ignoring peers with no location
+            // Find the peer with the minimum distance to the contract location, as if unreliable
+            // peers were further away, ignoring peers with no location
             peers
                 .into_iter()
                 .filter_map(|peer| {
-                    peer.location
-                        .map(|loc| (peer, target_location.distance(loc)))
+                    peer.location.map(|loc| {
+                        let distance = target_location.distance(loc).as_f64();
+                        (peer, distance / self.reliability(peer))
+                    })
                 })
-                .min_by_key(|&(_, distance)| distance)
+                .min_by(|&(_, distance1), &(_, distance2)| distance1.total_cmp(&distance2))
                 .map(|(peer, _)| peer)
         } else {
             // Find the peer with the minimum predicted routing outcome time
@@ -204,7 +227,7 @@ impl Router {
                     let t = self.predict_routing_outcome(peer, target_location).expect(
                         "Should always be Ok when has_sufficient_historical_data() is true",
                     );
-                    (peer, t.time_to_response_start)
+                    (peer, t.time_to_response_start / self.reliability(peer))
                 })
                 // Required because f64 doesn't implement Ord
                 .min_by(|&(_, time1), &(_, time2)| {
@@ -216,6 +239,11 @@ impl Router {
         }
     }
 
+    /// Reputation score of the peer, floored so no peer is avoided entirely.
+    fn reliability(&self, peer: &PeerKeyLocation) -> f64 {
+        self.reputation.score(&peer.peer).max(0.05)
+    }
+
     fn predict_routing_outcome(
         &self,
         peer: &PeerKeyLocation,
@@ -345,6 +373,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn avoid_unreliable_peers() {
+        let router = Router::new(&[]);
+        let target = Location::new(0.5);
+        let peer_at = |location| PeerKeyLocation {
+            location: Some(Location::new(location)),
+            ..PeerKeyLocation::random()
+        };
+        let closest = peer_at(0.52);
+        let further = peer_at(0.55);
+        assert_eq!(
+            router.select_peer([&closest, &further], target),
+            Some(&closest)
+        );
+
+        for _ in 0..10 {
+            router
+                .reputation()
+                .record(&closest.peer, PeerEvent::Timeout);
+        }
+        assert_eq!(
+            router.select_peer([&closest, &further], target),
+            Some(&further)
+        );
+    }
+
     #[test]
     fn test_request_time() {
         // Define constants for the number of peers, number of events, and number of test iterations.
@@ -0,0 +1,149 @@
+//! Reputation of peers, from the outcome of the operations routed through them and the messages
+//! received from them, biasing routing and the connections kept toward reliable peers.
+
+use std::{collections::HashMap, sync::Arc};
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use crate::node::PeerId;
+
+/// Number of peers tracked above which those with the fewest events are forgotten.
+const MAX_TRACKED_PEERS: usize = 10_000;
+
+/// Weight of the successes every peer is assumed to start with, so a few failures of a peer
+/// without any history aren't enough for it to be avoided.
+const PRIOR_SUCCESSES: f64 = 5.0;
+
+/// Something a peer did affecting its reputation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PeerEvent {
+    Success,
+    Failure,
+    /// An operation sent to the peer didn't complete in time.
+    Timeout,
+    /// A message from the peer couldn't be decoded.
+    InvalidMessage,
+    /// The peer kept sending messages over its inbound budget.
+    Flooding,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct PeerStats {
+    pub successes: u64,
+    pub failures: u64,
+    pub timeouts: u64,
+    pub invalid_messages: u64,
+    pub floods: u64,
+}
+
+impl PeerStats {
+    /// Reliability of the peer, from 1 for peers which never failed down to 0, timeouts,
+    /// invalid messages and floods weighing more than failures.
+    pub fn score(&self) -> f64 {
+        let successes = self.successes as f64 + PRIOR_SUCCESSES;
+        let failures = self.failures as f64
+            + 2.0 * self.timeouts as f64
+            + 4.0 * (self.invalid_messages + self.floods) as f64;
+        successes / (successes + failures)
+    }
+
+    fn events(&self) -> u64 {
+        self.successes + self.failures + self.timeouts + self.invalid_messages + self.floods
+    }
+}
+
+/// Statistics of the peers this node interacted with, shared by the components reporting and
+/// using them.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Reputation(Arc<RwLock<HashMap<PeerId, PeerStats>>>);
+
+impl Reputation {
+    pub fn record(&self, peer: &PeerId, event: PeerEvent) {
+        let mut peers = self.0.write();
+        if peers.len() >= MAX_TRACKED_PEERS && !peers.contains_key(peer) {
+            let mut by_events: Vec<_> = peers
+                .iter()
+                .map(|(peer, stats)| (stats.events(), peer.clone()))
+                .collect();
+            by_events.sort_unstable_by_key(|(events, _)| *events);
+            for (_, peer) in by_events.into_iter().take(MAX_TRACKED_PEERS / 4) {
+                peers.remove(&peer);
+            }
+        }
+        let stats = peers.entry(peer.clone()).or_default();
+        match event {
+            PeerEvent::Success => stats.successes += 1,
+            PeerEvent::Failure => stats.failures += 1,
+            PeerEvent::Timeout => stats.timeouts += 1,
+            PeerEvent::InvalidMessage => stats.invalid_messages += 1,
+            PeerEvent::Flooding => stats.floods += 1,
+        }
+    }
+
+    /// Score of the peer as per [`PeerStats::score`], 1 for unknown peers.
+    pub fn score(&self, peer: &PeerId) -> f64 {
+        self.0.read().get(peer).map(PeerStats::score).unwrap_or(1.0)
+    }
+
+    pub fn stats(&self) -> Vec<(PeerId, PeerStats)> {
+        self.0
+            .read()
+            .iter()
+            .map(|(peer, stats)| (peer.clone(), *stats))
+            .collect()
+    }
+
+    /// Restores the statistics of peers saved previously, keeping those of peers already tracked.
+    pub fn restore(&self, stats: impl IntoIterator<Item = (PeerId, PeerStats)>) {
+        let mut peers = self.0.write();
+        for (peer, stats) in stats {
+            if peers.len() >= MAX_TRACKED_PEERS {
+                break;
+            }
+            peers.entry(peer).or_insert(stats);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ring::PeerKeyLocation;
+
+    use super::*;
+
+    #[test]
+    fn peer_scores() {
+        let reputation = Reputation::default();
+        let reliable = PeerKeyLocation::random().peer;
+        let flaky = PeerKeyLocation::random().peer;
+        let malicious = PeerKeyLocation::random().peer;
+        assert_eq!(reputation.score(&reliable), 1.0);
+
+        for _ in 0..10 {
+            reputation.record(&reliable, PeerEvent::Success);
+            reputation.record(&flaky, PeerEvent::Success);
+        }
+        reputation.record(&reliable, PeerEvent::Failure);
+        for _ in 0..5 {
+            reputation.record(&flaky, PeerEvent::Timeout);
+        }
+        for _ in 0..5 {
+            reputation.record(&malicious, PeerEvent::InvalidMessage);
+        }
+        assert!(reputation.score(&reliable) > 0.9);
+        assert!(reputation.score(&flaky) < reputation.score(&reliable));
+        assert!(reputation.score(&malicious) < reputation.score(&flaky));
+
+        let stats: HashMap<_, _> = reputation.stats().into_iter().collect();
+        assert_eq!(
+            stats[&flaky],
+            PeerStats {
+                successes: 10,
+                timeouts: 5,
+                ..Default::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,300 @@
+//! Access log of the requests served by the HTTP gateway and the operations requested through
+//! the websocket API, written as JSON lines apart from the tracing log.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use axum::{
+    body::HttpBody,
+    extract::{ConnectInfo, Request, State},
+    http::header,
+    middleware::Next,
+    response::Response,
+    Router,
+};
+use freenet_stdlib::client_api::{ClientRequest, ContractRequest};
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::config::AccessLogConfig;
+
+/// Entries waiting to be written, further entries are dropped while the writer catches up.
+const QUEUE_SIZE: usize = 4096;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct AccessLogEntry {
+    /// RFC 3339 time the request was received.
+    timestamp: String,
+    /// `http` for gateway requests, `ws` for operations requested through the websocket API.
+    kind: &'static str,
+    /// HTTP method, or operation (e.g. `GET`, `PUT`) for websocket requests.
+    method: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    contract: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    /// Size of the response body, or of the request message for websocket requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_ms: Option<f64>,
+    client: Option<IpAddr>,
+}
+
+/// Handle to the access log, entries are written by a dedicated thread.
+#[derive(Clone, Debug)]
+pub(crate) struct AccessLog(mpsc::Sender<AccessLogEntry>);
+
+impl AccessLog {
+    pub fn open(config: AccessLogConfig) -> io::Result<Self> {
+        let mut file = RotatingFile::open(config)?;
+        let (sender, mut receiver) = mpsc::channel::<AccessLogEntry>(QUEUE_SIZE);
+        std::thread::Builder::new()
+            .name("access-log".into())
+            .spawn(move || {
+                while let Some(entry) = receiver.blocking_recv() {
+                    if let Err(err) = file.write_entry(&entry) {
+                        tracing::error!("Failed writing to the access log: {err}");
+                    }
+                }
+            })?;
+        Ok(Self(sender))
+    }
+
+    fn log(&self, entry: AccessLogEntry) {
+        if self.0.try_send(entry).is_err() {
+            tracing::debug!("Access log queue full, dropping entry");
+        }
+    }
+
+    /// Access log of the operations requested by a websocket client.
+    pub fn for_client(&self, client: Option<SocketAddr>) -> ClientAccessLog {
+        ClientAccessLog {
+            log: self.clone(),
+            client: client.map(|addr| addr.ip()),
+        }
+    }
+}
+
+/// Logs the operations requested by a single websocket client.
+pub(crate) struct ClientAccessLog {
+    log: AccessLog,
+    client: Option<IpAddr>,
+}
+
+impl ClientAccessLog {
+    /// Logs a request of `bytes` bytes, other than contract and delegate operations are ignored.
+    pub fn operation(&self, req: &ClientRequest, bytes: usize) {
+        let (method, contract) = match req {
+            ClientRequest::ContractOp(ContractRequest::Put { contract, .. }) => {
+                ("PUT", Some(contract.key().id().to_string()))
+            }
+            ClientRequest::ContractOp(ContractRequest::Update { key, .. }) => {
+                ("UPDATE", Some(key.id().to_string()))
+            }
+            ClientRequest::ContractOp(ContractRequest::Get { key, .. }) => {
+                ("GET", Some(key.id().to_string()))
+            }
+            ClientRequest::ContractOp(ContractRequest::Subscribe { key, .. }) => {
+                ("SUBSCRIBE", Some(key.id().to_string()))
+            }
+            ClientRequest::DelegateOp(_) => ("DELEGATE", None),
+            _ => return,
+        };
+        self.log.log(AccessLogEntry {
+            timestamp: now(),
+            kind: "ws",
+            method: method.to_owned(),
+            path: None,
+            contract,
+            status: None,
+            bytes: Some(bytes as u64),
+            latency_ms: None,
+            client: self.client,
+        });
+    }
+}
+
+/// Logs every request served by the router, the log is available to handlers as an extension.
+pub(super) fn with_access_log(router: Router, log: AccessLog) -> Router {
+    router.layer(axum::middleware::from_fn_with_state(log, access_log))
+}
+
+async fn access_log(State(log): State<AccessLog>, mut req: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let timestamp = now();
+    let method = req.method().to_string();
+    let path = req.uri().path().to_owned();
+    let client = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    req.extensions_mut().insert(log.clone());
+
+    let response = next.run(req).await;
+
+    let bytes = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok()?.parse().ok())
+        .or_else(|| response.body().size_hint().exact());
+    log.log(AccessLogEntry {
+        timestamp,
+        kind: "http",
+        method,
+        contract: contract_in_path(&path).map(str::to_owned),
+        path: Some(path),
+        status: Some(response.status().as_u16()),
+        bytes,
+        latency_ms: Some(start.elapsed().as_secs_f64() * 1000.0),
+        client,
+    });
+    response
+}
+
+fn now() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+/// The contract addressed by `/v1/contract/<resource>/<contract>/...` paths.
+fn contract_in_path(path: &str) -> Option<&str> {
+    let mut segments = path.trim_start_matches('/').split('/');
+    if segments.next()? != "v1" || segments.next()? != "contract" {
+        return None;
+    }
+    segments.nth(1).filter(|key| !key.is_empty())
+}
+
+/// Log file rotated once it grows past its max size.
+struct RotatingFile {
+    config: AccessLogConfig,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(config: AccessLogConfig) -> io::Result<Self> {
+        if let Some(dir) = config
+            .path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+        {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { config, file, size })
+    }
+
+    fn write_entry(&mut self, entry: &AccessLogEntry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        if self.size > 0 && self.size + line.len() as u64 > self.config.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(&line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let path = &self.config.path;
+        if self.config.max_files == 0 {
+            self.file.set_len(0)?;
+        } else {
+            for n in (1..self.config.max_files).rev() {
+                let rotated = rotated_path(path, n);
+                if rotated.exists() {
+                    fs::rename(&rotated, rotated_path(path, n + 1))?;
+                }
+            }
+            fs::rename(path, rotated_path(path, 1))?;
+            self.file = OpenOptions::new().create(true).append(true).open(path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, n: u32) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{n}"));
+    PathBuf::from(rotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str) -> AccessLogEntry {
+        AccessLogEntry {
+            timestamp: now(),
+            kind: "http",
+            method: "GET".into(),
+            contract: contract_in_path(path).map(str::to_owned),
+            path: Some(path.into()),
+            status: Some(200),
+            bytes: Some(42),
+            latency_ms: Some(1.5),
+            client: Some([127, 0, 0, 1].into()),
+        }
+    }
+
+    #[test]
+    fn contract_paths() {
+        assert_eq!(
+            contract_in_path("/v1/contract/web/Cuj4LbFao/index.html"),
+            Some("Cuj4LbFao")
+        );
+        assert_eq!(
+            contract_in_path("/v1/contract/subscribe/Cuj4LbFao"),
+            Some("Cuj4LbFao")
+        );
+        assert_eq!(contract_in_path("/v1/contract/command"), None);
+        assert_eq!(contract_in_path("/v1/admin/peers/Cuj4LbFao"), None);
+    }
+
+    #[test]
+    fn rotate_log_file() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("logs").join("access.log");
+        let line_len = serde_json::to_vec(&entry("/v1"))?.len() as u64 + 1;
+        let mut file = RotatingFile::open(AccessLogConfig {
+            path: path.clone(),
+            max_size: line_len * 2,
+            max_files: 2,
+        })?;
+        for _ in 0..7 {
+            file.write_entry(&entry("/v1"))?;
+        }
+        assert_eq!(fs::read_to_string(&path)?.lines().count(), 1);
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 1))?.lines().count(),
+            2
+        );
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 2))?.lines().count(),
+            2
+        );
+        assert!(!rotated_path(&path, 3).exists());
+
+        let logged: serde_json::Value =
+            serde_json::from_str(fs::read_to_string(&path)?.lines().next().unwrap())?;
+        assert_eq!(logged["method"], "GET");
+        assert_eq!(logged["status"], 200);
+        assert_eq!(logged["client"], "127.0.0.1");
+        assert!(logged.get("contract").is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,326 @@
+//! Admin API to manage the node at runtime, only available to API tokens granted the
+//! [`ApiScope::Admin`] scope.
+
+use std::{
+    collections::HashSet,
+    net::SocketAddr,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Extension, Json, Router,
+};
+use dashmap::DashMap;
+use freenet_stdlib::prelude::ContractKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client_events::{AuthToken, ClientId},
+    node::{admin::NodeHandle, peer_policy::PeerLists},
+};
+
+use super::{errors::WebSocketApiError, ApiScope, ApiTokens, TokenGrant};
+
+/// Websocket clients currently connected to the node.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct OpenClients(Arc<DashMap<ClientId, ClientInfo>>);
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct ClientInfo {
+    id: ClientId,
+    remote_addr: Option<SocketAddr>,
+    /// Unix timestamp, in seconds, of the connection.
+    connected_at: u64,
+    #[serde(flatten)]
+    grant: Option<TokenGrant>,
+}
+
+/// Keeps a client listed in [`OpenClients`] until dropped.
+pub(crate) struct ClientRegistration {
+    clients: OpenClients,
+    id: ClientId,
+}
+
+impl Drop for ClientRegistration {
+    fn drop(&mut self) {
+        self.clients.0.remove(&self.id);
+    }
+}
+
+impl OpenClients {
+    pub fn register(
+        &self,
+        id: ClientId,
+        remote_addr: Option<SocketAddr>,
+        grant: Option<TokenGrant>,
+    ) -> ClientRegistration {
+        let connected_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.0.insert(
+            id,
+            ClientInfo {
+                id,
+                remote_addr,
+                connected_at,
+                grant,
+            },
+        );
+        ClientRegistration {
+            clients: self.clone(),
+            id,
+        }
+    }
+
+    pub fn list(&self) -> Vec<ClientInfo> {
+        self.0.iter().map(|client| client.value().clone()).collect()
+    }
+}
+
+/// Routes of the admin API.
+pub(super) fn router() -> Router {
+    Router::new()
+        .route("/v1/admin/clients", get(list_clients))
+        .route("/v1/admin/peers", get(list_peers))
+        .route("/v1/admin/peers/:addr", delete(drop_peer))
+        .route("/v1/admin/reputation", get(list_reputation))
+        .route("/v1/admin/bans", get(list_bans))
+        .route("/v1/admin/bans/:peer", delete(lift_ban))
+        .route("/v1/admin/gateways", get(list_gateways))
+        .route("/v1/admin/port-mapping", get(port_mapping))
+        .route("/v1/admin/partition", get(partition))
+        .route(
+            "/v1/admin/peer-policy",
+            get(get_peer_policy).put(set_peer_policy),
+        )
+        .route("/v1/admin/operations", get(list_operations))
+        .route("/v1/admin/contracts", get(list_contracts))
+        .route("/v1/admin/pins", get(list_pins))
+        .route(
+            "/v1/admin/pins/:key",
+            put(pin_contract).delete(unpin_contract),
+        )
+        .route("/v1/admin/storage/gc", post(storage_gc))
+        .route("/v1/admin/log-filter", put(set_log_filter))
+        .route(
+            "/v1/admin/tokens/:token",
+            put(grant_token).delete(revoke_token),
+        )
+        .route_layer(axum::middleware::from_fn(require_admin))
+}
+
+async fn require_admin(
+    Extension(auth_token): Extension<Option<AuthToken>>,
+    Extension(api_tokens): Extension<ApiTokens>,
+    req: axum::extract::Request,
+    next: Next,
+) -> Response {
+    let is_admin = auth_token
+        .and_then(|token| api_tokens.grant(&token))
+        .is_some_and(|grant| grant.has_scope(ApiScope::Admin));
+    if !is_admin {
+        return WebSocketApiError::Unauthorized {
+            error_cause: "Missing API token with the admin scope".into(),
+        }
+        .into_response();
+    }
+    next.run(req).await
+}
+
+fn running_node() -> Result<NodeHandle, WebSocketApiError> {
+    NodeHandle::running().ok_or_else(|| WebSocketApiError::NodeError {
+        error_cause: "No network node running".into(),
+    })
+}
+
+async fn list_clients(Extension(clients): Extension<OpenClients>) -> Json<Vec<ClientInfo>> {
+    Json(clients.list())
+}
+
+async fn list_peers() -> Result<Response, WebSocketApiError> {
+    Ok(Json(running_node()?.peers()).into_response())
+}
+
+async fn drop_peer(Path(addr): Path<SocketAddr>) -> Result<Response, WebSocketApiError> {
+    let dropped =
+        running_node()?
+            .drop_peer(addr)
+            .await
+            .map_err(|err| WebSocketApiError::NodeError {
+                error_cause: format!("{err}"),
+            })?;
+    if dropped {
+        Ok(StatusCode::NO_CONTENT.into_response())
+    } else {
+        Ok((StatusCode::NOT_FOUND, format!("Not connected to {addr}")).into_response())
+    }
+}
+
+async fn list_reputation() -> Result<Response, WebSocketApiError> {
+    Ok(Json(running_node()?.reputation()).into_response())
+}
+
+async fn list_bans() -> Result<Response, WebSocketApiError> {
+    Ok(Json(running_node()?.bans()).into_response())
+}
+
+/// Lifts the bans of the peer with the key fingerprint or IP address in the path.
+async fn lift_ban(Path(peer): Path<String>) -> Result<Response, WebSocketApiError> {
+    if running_node()?.lift_ban(&peer) {
+        Ok(StatusCode::NO_CONTENT.into_response())
+    } else {
+        Ok((StatusCode::NOT_FOUND, format!("{peer} not banned")).into_response())
+    }
+}
+
+async fn list_gateways() -> Result<Response, WebSocketApiError> {
+    Ok(Json(running_node()?.gateways()).into_response())
+}
+
+async fn port_mapping() -> Result<Response, WebSocketApiError> {
+    Ok(Json(running_node()?.port_mapping()).into_response())
+}
+
+async fn partition() -> Result<Response, WebSocketApiError> {
+    Ok(Json(running_node()?.partition()).into_response())
+}
+
+async fn get_peer_policy() -> Result<Response, WebSocketApiError> {
+    Ok(Json(running_node()?.peer_lists()).into_response())
+}
+
+/// Replaces the lists of allowed and denied peers with those in the request body.
+async fn set_peer_policy(Json(lists): Json<PeerLists>) -> Result<Response, WebSocketApiError> {
+    running_node()?
+        .set_peer_lists(lists)
+        .await
+        .map_err(|err| WebSocketApiError::InvalidParam {
+            error_cause: format!("{err}"),
+        })?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn list_operations() -> Result<Response, WebSocketApiError> {
+    Ok(Json(running_node()?.in_flight_ops()).into_response())
+}
+
+async fn list_contracts() -> Result<Response, WebSocketApiError> {
+    let contracts =
+        running_node()?
+            .contracts()
+            .await
+            .map_err(|err| WebSocketApiError::NodeError {
+                error_cause: format!("{err}"),
+            })?;
+    Ok(Json(contracts).into_response())
+}
+
+async fn list_pins() -> Result<Response, WebSocketApiError> {
+    Ok(Json(running_node()?.pins()).into_response())
+}
+
+fn contract_key(key: &str) -> Result<ContractKey, WebSocketApiError> {
+    ContractKey::from_id(key).map_err(|err| WebSocketApiError::InvalidParam {
+        error_cause: format!("{err}"),
+    })
+}
+
+/// Pins the contract in the path, so the node always seeds it.
+async fn pin_contract(Path(key): Path<String>) -> Result<Response, WebSocketApiError> {
+    let key = contract_key(&key)?;
+    if running_node()?.pin(key) {
+        Ok(StatusCode::CREATED.into_response())
+    } else {
+        Ok(StatusCode::NO_CONTENT.into_response())
+    }
+}
+
+async fn unpin_contract(Path(key): Path<String>) -> Result<Response, WebSocketApiError> {
+    let key = contract_key(&key)?;
+    if running_node()?.unpin(&key) {
+        Ok(StatusCode::NO_CONTENT.into_response())
+    } else {
+        Ok((StatusCode::NOT_FOUND, format!("{key} not pinned")).into_response())
+    }
+}
+
+async fn storage_gc() -> Result<Response, WebSocketApiError> {
+    let node = running_node()?;
+    tokio::task::spawn_blocking(move || node.compact_storage())
+        .await
+        .map_err(|err| WebSocketApiError::NodeError {
+            error_cause: format!("{err}"),
+        })?
+        .map_err(|err| WebSocketApiError::NodeError {
+            error_cause: format!("Failed compacting storage: {err}"),
+        })?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Replaces the log filter with the directives in the request body, e.g. `info,freenet::node=debug`.
+async fn set_log_filter(directives: String) -> Result<Response, WebSocketApiError> {
+    crate::tracing::set_log_filter(directives.trim()).map_err(|err| {
+        WebSocketApiError::InvalidParam {
+            error_cause: format!("{err}"),
+        }
+    })?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Body of a token grant, either the scopes granted on any contract or a [`TokenGrant`]
+/// restricting them to some contracts.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GrantRequest {
+    Scopes(HashSet<ApiScope>),
+    Grant(TokenGrant),
+}
+
+/// Registers the token with the grant in the request body, replacing any previous grant.
+async fn grant_token(
+    Path(token): Path<String>,
+    Extension(api_tokens): Extension<ApiTokens>,
+    Json(grant): Json<GrantRequest>,
+) -> Response {
+    let grant = match grant {
+        GrantRequest::Scopes(scopes) => TokenGrant::new(scopes),
+        GrantRequest::Grant(grant) => grant,
+    };
+    api_tokens.insert(AuthToken::from(token), grant);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn revoke_token(
+    Path(token): Path<String>,
+    Extension(api_tokens): Extension<ApiTokens>,
+) -> Response {
+    if api_tokens.revoke(&AuthToken::from(token)) {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        StatusCode::NOT_FOUND.into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_registration() {
+        let clients = OpenClients::default();
+        let id = ClientId::next();
+        let registration = clients.register(id, None, None);
+        assert_eq!(clients.list().len(), 1);
+        assert_eq!(clients.list()[0].id, id);
+        drop(registration);
+        assert!(clients.list().is_empty());
+    }
+}
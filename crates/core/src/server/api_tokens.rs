@@ -0,0 +1,311 @@
+//! Bearer tokens granting access to the websocket and admin APIs.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+};
+
+use freenet_stdlib::{
+    client_api::{ClientRequest, ContractRequest, DelegateRequest},
+    prelude::{ContractInstanceId, ContractKey, DelegateKey},
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::client_events::AuthToken;
+
+/// Operations a client of the websocket API can be allowed to perform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ApiScope {
+    /// Get and subscribe to contracts, and query the node.
+    Read,
+    /// Put and update contracts.
+    Publish,
+    /// Register and interact with delegates.
+    Delegate,
+    /// Manage the node through the admin API, never granted implicitly.
+    Admin,
+}
+
+impl ApiScope {
+    /// Scopes covering every operation of the websocket API.
+    pub const ALL: [ApiScope; 3] = [ApiScope::Read, ApiScope::Publish, ApiScope::Delegate];
+
+    /// Scope required to perform the request, `None` for requests any client can make.
+    pub fn required_by(req: &ClientRequest) -> Option<ApiScope> {
+        match req {
+            ClientRequest::ContractOp(ContractRequest::Put { .. })
+            | ClientRequest::ContractOp(ContractRequest::Update { .. }) => Some(ApiScope::Publish),
+            ClientRequest::ContractOp(_) | ClientRequest::NodeQueries(_) => Some(ApiScope::Read),
+            ClientRequest::DelegateOp(_) => Some(ApiScope::Delegate),
+            _ => None,
+        }
+    }
+}
+
+/// What an API token grants access to.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenGrant {
+    pub scopes: HashSet<ApiScope>,
+    /// Contracts the operations on contracts are restricted to, any contract when `None`, so
+    /// several untrusted applications can be given their own token on the same node.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_contracts",
+        deserialize_with = "deserialize_contracts"
+    )]
+    pub contracts: Option<HashSet<ContractInstanceId>>,
+    /// Delegates the delegate requests are restricted to. When `None`, any delegate unless the
+    /// token is restricted to some contracts, so applications restricted to their contracts
+    /// can't drive the delegates of others.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delegates: Option<HashSet<DelegateKey>>,
+}
+
+impl TokenGrant {
+    /// Grants the scopes on any contract.
+    pub fn new(scopes: impl IntoIterator<Item = ApiScope>) -> Self {
+        Self {
+            scopes: scopes.into_iter().collect(),
+            contracts: None,
+            delegates: None,
+        }
+    }
+
+    /// Grant of the tokens assigned to webapps served by the gateway, which can only read and
+    /// publish their own contract since serving a webapp requires no token.
+    pub fn attested(contract: ContractInstanceId) -> Self {
+        Self {
+            scopes: HashSet::from([ApiScope::Read, ApiScope::Publish]),
+            contracts: Some(HashSet::from([contract])),
+            delegates: Some(HashSet::new()),
+        }
+    }
+
+    pub fn has_scope(&self, scope: ApiScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    pub fn allows_contract(&self, contract: &ContractInstanceId) -> bool {
+        self.contracts
+            .as_ref()
+            .map_or(true, |contracts| contracts.contains(contract))
+    }
+
+    pub fn allows_delegate(&self, delegate: &DelegateKey) -> bool {
+        match &self.delegates {
+            Some(delegates) => delegates.contains(delegate),
+            None => self.contracts.is_none(),
+        }
+    }
+
+    /// Checks the request is granted, returning why it's not otherwise.
+    pub fn check(&self, req: &ClientRequest) -> Result<(), String> {
+        if let Some(required) = ApiScope::required_by(req) {
+            if !self.has_scope(required) {
+                return Err(format!("API token does not grant the {required:?} scope"));
+            }
+        }
+        if let Some(contract) = contract_of(req) {
+            if !self.allows_contract(contract.id()) {
+                return Err(format!(
+                    "API token does not grant access to contract {}",
+                    contract.id()
+                ));
+            }
+        }
+        match delegate_of(req) {
+            Some(delegate) if !self.allows_delegate(&delegate) => Err(format!(
+                "API token does not grant access to delegate {delegate}"
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The contract a request operates on.
+fn contract_of(req: &ClientRequest) -> Option<ContractKey> {
+    match req {
+        ClientRequest::ContractOp(ContractRequest::Put { contract, .. }) => Some(contract.key()),
+        ClientRequest::ContractOp(
+            ContractRequest::Update { key, .. }
+            | ContractRequest::Get { key, .. }
+            | ContractRequest::Subscribe { key, .. },
+        ) => Some(*key),
+        _ => None,
+    }
+}
+
+/// The delegate a request operates on.
+fn delegate_of(req: &ClientRequest) -> Option<DelegateKey> {
+    match req {
+        ClientRequest::DelegateOp(DelegateRequest::RegisterDelegate { delegate, .. }) => {
+            Some(delegate.key().clone())
+        }
+        ClientRequest::DelegateOp(
+            DelegateRequest::ApplicationMessages { key, .. }
+            | DelegateRequest::GetSecretRequest { key, .. }
+            | DelegateRequest::UnregisterDelegate(key),
+        ) => Some(key.clone()),
+        _ => None,
+    }
+}
+
+fn serialize_contracts<S: Serializer>(
+    contracts: &Option<HashSet<ContractInstanceId>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    contracts
+        .as_ref()
+        .map(|contracts| {
+            contracts
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+        })
+        .serialize(serializer)
+}
+
+fn deserialize_contracts<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<HashSet<ContractInstanceId>>, D::Error> {
+    let Some(contracts) = Option::<Vec<String>>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    contracts
+        .into_iter()
+        .map(|id| {
+            ContractKey::from_id(id)
+                .map(|key| *key.id())
+                .map_err(serde::de::Error::custom)
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+/// API tokens accepted by the websocket API, and what is granted to each of them.
+///
+/// While no token is registered the websocket API is open to any client able to reach it,
+/// the admin API is only available to tokens granted the [`ApiScope::Admin`] scope.
+#[derive(Clone, Debug, Default)]
+pub struct ApiTokens(Arc<RwLock<HashMap<AuthToken, TokenGrant>>>);
+
+impl ApiTokens {
+    pub fn new(tokens: impl IntoIterator<Item = (AuthToken, TokenGrant)>) -> Self {
+        Self(Arc::new(RwLock::new(tokens.into_iter().collect())))
+    }
+
+    /// Whether clients must present a token to use the API.
+    pub fn is_enabled(&self) -> bool {
+        !self.0.read().unwrap().is_empty()
+    }
+
+    pub fn grant(&self, token: &AuthToken) -> Option<TokenGrant> {
+        self.0.read().unwrap().get(token).cloned()
+    }
+
+    /// Registers a token, replacing what it had been granted before.
+    pub fn insert(&self, token: AuthToken, grant: TokenGrant) {
+        self.0.write().unwrap().insert(token, grant);
+    }
+
+    /// Revokes a token, returns whether it was registered.
+    pub fn revoke(&self, token: &AuthToken) -> bool {
+        self.0.write().unwrap().remove(token).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use freenet_stdlib::{
+        client_api::ConnectedPeers,
+        prelude::{CodeHash, StateDelta, UpdateData},
+    };
+
+    use super::*;
+
+    #[test]
+    fn token_scopes() {
+        let tokens = ApiTokens::default();
+        assert!(!tokens.is_enabled());
+
+        let token = AuthToken::from("secret".to_owned());
+        tokens.insert(token.clone(), TokenGrant::new([ApiScope::Read]));
+        assert!(tokens.is_enabled());
+        assert_eq!(
+            tokens.grant(&token),
+            Some(TokenGrant::new([ApiScope::Read]))
+        );
+        assert!(tokens.revoke(&token));
+        assert!(!tokens.revoke(&token));
+        assert!(tokens.grant(&token).is_none());
+
+        assert_eq!(
+            ApiScope::required_by(&ClientRequest::NodeQueries(ConnectedPeers {})),
+            Some(ApiScope::Read)
+        );
+        assert_eq!(
+            ApiScope::required_by(&ClientRequest::Disconnect { cause: None }),
+            None
+        );
+    }
+
+    #[test]
+    fn contract_restricted_grant() -> Result<(), Box<dyn std::error::Error>> {
+        let allowed = ContractInstanceId::new([1; 32]);
+        let other = ContractInstanceId::new([2; 32]);
+        let grant: TokenGrant = serde_json::from_value(serde_json::json!({
+            "scopes": ["read", "publish"],
+            "contracts": [allowed.to_string()],
+        }))?;
+        assert_eq!(grant.contracts, Some(HashSet::from([allowed])));
+        let update = |id| -> ClientRequest<'static> {
+            ContractRequest::Update {
+                key: ContractKey::from(id),
+                data: UpdateData::Delta(StateDelta::from(vec![1])),
+            }
+            .into()
+        };
+        assert!(grant.check(&update(allowed)).is_ok());
+        assert!(grant.check(&update(other)).is_err());
+        assert!(grant
+            .check(&ClientRequest::NodeQueries(ConnectedPeers {}))
+            .is_ok());
+
+        let unrestricted: TokenGrant = serde_json::from_value(serde_json::json!({
+            "scopes": ["read"],
+        }))?;
+        assert!(unrestricted.allows_contract(&other));
+        assert!(unrestricted.check(&update(other)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn delegate_restricted_grant() {
+        let allowed = DelegateKey::new([1; 32], CodeHash::new([1; 32]));
+        let other = DelegateKey::new([2; 32], CodeHash::new([2; 32]));
+        let unregister = |key: &DelegateKey| -> ClientRequest<'static> {
+            ClientRequest::DelegateOp(DelegateRequest::UnregisterDelegate(key.clone()))
+        };
+
+        let any = TokenGrant::new([ApiScope::Delegate]);
+        assert!(any.check(&unregister(&other)).is_ok());
+
+        let restricted = TokenGrant {
+            delegates: Some(HashSet::from([allowed.clone()])),
+            ..TokenGrant::new([ApiScope::Delegate])
+        };
+        assert!(restricted.check(&unregister(&allowed)).is_ok());
+        assert!(restricted.check(&unregister(&other)).is_err());
+
+        // tokens restricted to some contracts only use the delegates they are granted
+        let contract_restricted = TokenGrant {
+            contracts: Some(HashSet::from([ContractInstanceId::new([1; 32])])),
+            ..TokenGrant::new([ApiScope::Delegate])
+        };
+        assert!(contract_restricted.check(&unregister(&other)).is_err());
+        let attested = TokenGrant::attested(ContractInstanceId::new([1; 32]));
+        assert!(!attested.allows_delegate(&allowed));
+    }
+}
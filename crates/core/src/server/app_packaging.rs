@@ -1,13 +1,59 @@
 //! Helper functions and types for dealing with HTTP gateway compatible contracts.
 use std::{
-    io::{Cursor, Read},
+    cell::Cell,
+    collections::HashMap,
+    fs::File,
+    io::{Cursor, Read, Seek, SeekFrom},
     path::Path,
+    sync::{Arc, OnceLock},
+    time::UNIX_EPOCH,
 };
 use tracing::{debug, instrument};
 
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use freenet_stdlib::prelude::ContractInstanceId;
+use serde::{Deserialize, Serialize};
+use stretto::Cache;
 use tar::{Archive, Builder};
 use xz2::read::{XzDecoder, XzEncoder};
+use zstd::stream::raw::{CParameter, DParameter};
+
+mod cache;
+mod manifest;
+
+pub(crate) use cache::{state_changed, state_version};
+pub use cache::{CachedWebApp, WebAppCache};
+pub use manifest::WebAppManifest;
+
+/// Magic bytes prefixing a packed webapp state which carries a format header.
+///
+/// States without this prefix are interpreted using the legacy layout (XZ compressed
+/// web archive without header).
+const PACK_MAGIC: [u8; 4] = *b"FNWA";
+/// Current version of the packed webapp header.
+///
+/// - version 1: compression format
+/// - version 2: compression format and flags
+/// - version 3: compression format, flags and section checksums
+const PACK_VERSION: u8 = 3;
+
+/// The packed state carries an ed25519 signature after the web section.
+const FLAG_SIGNED: u8 = 0b0000_0001;
+/// The web section holds references to the contracts storing the web archive chunks.
+const FLAG_CHUNKED: u8 = 0b0000_0010;
+
+/// Size of the blake3 checksums of the metadata and web sections, stored after the web section.
+const CHECKSUMS_SIZE: usize = blake3::OUT_LEN * 2;
+
+/// Magic bytes prefixing a binary delta between two packed webapp states.
+const DELTA_MAGIC: [u8; 4] = *b"FNWD";
+/// Zstd window log used for deltas, large enough to reference any packed state.
+const DELTA_WINDOW_LOG: u32 = 27;
+
+const XZ_MAGIC: [u8; 6] = [0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
 
 #[derive(Debug, thiserror::Error)]
 pub enum WebContractError {
@@ -17,12 +63,173 @@ pub enum WebContractError {
     StoringError(std::io::Error),
     #[error("file not found: {0}")]
     FileNotFound(String),
+    #[error("invalid signature: {0}")]
+    InvalidSignature(String),
+    #[error("integrity check failed: {0}")]
+    IntegrityFailure(String),
+}
+
+/// Compression format used for the web archive of a [`WebApp`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[repr(u8)]
+pub enum CompressionFormat {
+    /// The web archive is stored as a plain tar file.
+    None = 0,
+    /// XZ compression, best compression ratio but slow to decode.
+    #[default]
+    Xz = 1,
+    /// Zstandard compression, trades some compression ratio for much faster decoding.
+    Zstd = 2,
+}
+
+impl CompressionFormat {
+    /// Detects the compression format of the given web archive from its magic bytes.
+    pub fn detect(web: &[u8]) -> Self {
+        if web.starts_with(&XZ_MAGIC) {
+            CompressionFormat::Xz
+        } else if web.starts_with(&ZSTD_MAGIC) {
+            CompressionFormat::Zstd
+        } else {
+            CompressionFormat::None
+        }
+    }
+
+    fn compress(self, mut tar: impl Read) -> std::io::Result<Vec<u8>> {
+        match self {
+            CompressionFormat::None => {
+                let mut uncompressed = vec![];
+                tar.read_to_end(&mut uncompressed)?;
+                Ok(uncompressed)
+            }
+            CompressionFormat::Xz => {
+                let mut encoder = XzEncoder::new(tar, 6);
+                let mut compressed = vec![];
+                encoder.read_to_end(&mut compressed)?;
+                Ok(compressed)
+            }
+            CompressionFormat::Zstd => zstd::stream::encode_all(tar, 19),
+        }
+    }
+
+    fn decoder<'a, R: Read + 'a>(self, web: R) -> std::io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            CompressionFormat::None => Box::new(web),
+            CompressionFormat::Xz => Box::new(XzDecoder::new(web)),
+            CompressionFormat::Zstd => Box::new(zstd::stream::read::Decoder::new(web)?),
+        })
+    }
+}
+
+impl TryFrom<u8> for CompressionFormat {
+    type Error = WebContractError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(CompressionFormat::None),
+            1 => Ok(CompressionFormat::Xz),
+            2 => Ok(CompressionFormat::Zstd),
+            other => Err(WebContractError::UnpackingError(anyhow::anyhow!(
+                "unknown compression format: {other}"
+            ))),
+        }
+    }
 }
 
 #[non_exhaustive]
 pub struct WebApp {
     pub metadata: Vec<u8>,
     pub web: Vec<u8>,
+    pub compression: CompressionFormat,
+    /// Publisher signature over the metadata and web sections.
+    pub signature: Option<Signature>,
+    /// Chunks of the web archive stored in other contracts, empty unless the web archive
+    /// was too large to fit in a single state.
+    pub chunks: Vec<WebAppChunkRef>,
+    /// Location of every file in the decoded web archive, built on first access.
+    index: OnceLock<HashMap<String, FileEntry>>,
+    /// Recently accessed decoded files.
+    file_cache: OnceLock<Cache<String, Arc<Vec<u8>>>>,
+}
+
+/// A file or directory in the web archive of a [`WebApp`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WebAppEntry {
+    pub path: String,
+    /// Size in bytes of the decoded file.
+    pub size: u64,
+    /// Modification time as seconds since the unix epoch.
+    pub mtime: u64,
+    pub is_dir: bool,
+}
+
+/// Reference to a chunk of a web archive stored as the state of another contract.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebAppChunkRef {
+    /// Contract whose state holds the chunk.
+    pub contract: ContractInstanceId,
+    /// Size in bytes of the chunk.
+    pub size: u64,
+    /// Blake3 hash of the chunk.
+    pub hash: [u8; blake3::OUT_LEN],
+}
+
+impl WebAppChunkRef {
+    pub fn new(contract: ContractInstanceId, chunk: &[u8]) -> Self {
+        Self {
+            contract,
+            size: chunk.len() as u64,
+            hash: *blake3::hash(chunk).as_bytes(),
+        }
+    }
+}
+
+/// Verifies the chunks of a chunked web archive in order, see [`WebApp::web_chunks`].
+#[derive(Debug)]
+pub struct WebChunks {
+    pending: std::vec::IntoIter<WebAppChunkRef>,
+    /// Total size in bytes of the chunks.
+    size: u64,
+}
+
+impl WebChunks {
+    /// Reference to the next chunk to fetch, `None` once all of them were verified.
+    pub fn next_ref(&self) -> Option<&WebAppChunkRef> {
+        self.pending.as_slice().first()
+    }
+
+    /// Verifies the next chunk against its reference.
+    pub fn verify(&mut self, chunk: &[u8]) -> Result<(), WebContractError> {
+        let chunk_ref = self.pending.next().ok_or_else(|| {
+            WebContractError::UnpackingError(anyhow::anyhow!("unexpected web chunk"))
+        })?;
+        if chunk.len() as u64 != chunk_ref.size || blake3::hash(chunk).as_bytes() != &chunk_ref.hash
+        {
+            return Err(WebContractError::UnpackingError(anyhow::anyhow!(
+                "corrupted web chunk from {}",
+                chunk_ref.contract
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks that every chunk was verified.
+    pub fn finish(self) -> Result<(), WebContractError> {
+        match self.next_ref() {
+            Some(chunk_ref) => Err(WebContractError::UnpackingError(anyhow::anyhow!(
+                "missing web chunk from {}",
+                chunk_ref.contract
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Position of a file within the decoded web archive.
+#[derive(Debug, Clone, Copy)]
+struct FileEntry {
+    offset: u64,
+    size: u64,
 }
 
 impl WebApp {
@@ -31,17 +238,142 @@ impl WebApp {
         metadata: Vec<u8>,
         web: Builder<Cursor<Vec<u8>>>,
     ) -> Result<Self, WebContractError> {
-        debug!("Creating WebApp from metadata ({} bytes)", metadata.len());
-        let buf = web.into_inner().unwrap().into_inner();
-        let mut encoder = XzEncoder::new(Cursor::new(buf), 6);
-        let mut compressed = vec![];
-        encoder.read_to_end(&mut compressed).unwrap();
+        Self::from_data_with_compression(metadata, web, CompressionFormat::default())
+    }
+
+    #[instrument(level = "debug", skip(web))]
+    pub fn from_data_with_compression(
+        metadata: Vec<u8>,
+        web: Builder<Cursor<Vec<u8>>>,
+        compression: CompressionFormat,
+    ) -> Result<Self, WebContractError> {
+        Self::from_data_with_progress(metadata, web, compression, |_, _| {})
+    }
+
+    /// Same as [`WebApp::from_data_with_compression`], reporting the progress of
+    /// compressing the web archive to `progress` as `(bytes processed, total bytes)`.
+    #[instrument(level = "debug", skip(web, progress))]
+    pub fn from_data_with_progress(
+        metadata: Vec<u8>,
+        web: Builder<Cursor<Vec<u8>>>,
+        compression: CompressionFormat,
+        progress: impl FnMut(u64, u64),
+    ) -> Result<Self, WebContractError> {
+        debug!(
+            "Creating WebApp from metadata ({} bytes) using {compression:?} compression",
+            metadata.len()
+        );
+        let buf = web
+            .into_inner()
+            .map_err(WebContractError::StoringError)?
+            .into_inner();
+        let total = buf.len() as u64;
+        let compressed = compression
+            .compress(ProgressReader::new(Cursor::new(buf), total, progress))
+            .map_err(WebContractError::StoringError)?;
         Ok(Self {
             metadata,
             web: compressed,
+            compression,
+            signature: None,
+            chunks: vec![],
+            index: OnceLock::new(),
+            file_cache: OnceLock::new(),
         })
     }
 
+    /// Creates a webapp from the contents of the `dir` directory, which must contain
+    /// an `index.html` file at its root.
+    ///
+    /// Entries are archived in lexicographic order (directories before their contents),
+    /// with normalized permissions: `0o755` for directories and `0o644` for files. Symbolic
+    /// links are skipped, so only files within `dir` are archived.
+    #[instrument(level = "debug", skip(metadata, dir))]
+    pub fn from_directory(
+        metadata: Vec<u8>,
+        dir: impl AsRef<Path>,
+        compression: CompressionFormat,
+    ) -> Result<Self, WebContractError> {
+        let dir = dir.as_ref();
+        debug!("Creating WebApp from directory {dir:?}");
+        if !dir.join("index.html").is_file() {
+            return Err(WebContractError::FileNotFound("index.html".into()));
+        }
+        let mut web = Builder::new(Cursor::new(Vec::new()));
+        append_dir(&mut web, dir, Path::new("")).map_err(WebContractError::StoringError)?;
+        Self::from_data_with_compression(metadata, web, compression)
+    }
+
+    /// Creates a webapp from an uploaded tar archive, optionally gzip compressed, which must
+    /// contain an `index.html` file at its root.
+    ///
+    /// Only files and directories are kept, with the same normalized permissions as
+    /// [`WebApp::from_directory`].
+    #[instrument(level = "debug", skip(metadata, archive))]
+    pub fn from_archive(
+        metadata: Vec<u8>,
+        archive: &[u8],
+        compression: CompressionFormat,
+    ) -> Result<Self, WebContractError> {
+        let decoded: Box<dyn Read + '_> = if archive.starts_with(&GZIP_MAGIC) {
+            Box::new(flate2::read::GzDecoder::new(archive))
+        } else {
+            Box::new(archive)
+        };
+        let mut web = Builder::new(Cursor::new(Vec::new()));
+        let mut has_index = false;
+        let mut archive = Archive::new(decoded);
+        for entry in archive
+            .entries()
+            .map_err(|e| WebContractError::UnpackingError(e.into()))?
+        {
+            let mut entry = entry.map_err(|e| WebContractError::UnpackingError(e.into()))?;
+            let path = entry
+                .path()
+                .map_err(|e| WebContractError::UnpackingError(e.into()))?
+                .components()
+                .filter(|c| !matches!(c, std::path::Component::CurDir))
+                .collect::<std::path::PathBuf>();
+            if path.as_os_str().is_empty() {
+                continue;
+            }
+            if !path
+                .components()
+                .all(|c| matches!(c, std::path::Component::Normal(_)))
+            {
+                return Err(WebContractError::UnpackingError(anyhow::anyhow!(
+                    "invalid path in web archive: {}",
+                    path.display()
+                )));
+            }
+            let mut header = tar::Header::new_gnu();
+            header.set_mtime(entry.header().mtime().unwrap_or_default());
+            match entry.header().entry_type() {
+                tar::EntryType::Directory => {
+                    header.set_entry_type(tar::EntryType::Directory);
+                    header.set_mode(0o755);
+                    header.set_size(0);
+                    web.append_data(&mut header, &path, std::io::empty())
+                        .map_err(WebContractError::StoringError)?;
+                }
+                tar::EntryType::Regular => {
+                    has_index |= path == Path::new("index.html");
+                    header.set_mode(0o644);
+                    header.set_size(entry.size());
+                    web.append_data(&mut header, &path, &mut entry)
+                        .map_err(WebContractError::StoringError)?;
+                }
+                _ => debug!("Skipping {} in web archive", path.display()),
+            }
+        }
+        if !has_index {
+            return Err(WebContractError::FileNotFound("index.html".into()));
+        }
+        Self::from_data_with_compression(metadata, web, compression)
+    }
+
+    /// Creates a webapp from an already compressed web archive, the compression format
+    /// is detected from the archive contents.
     pub fn from_compressed(
         metadata: Vec<u8>,
         compressed_web: Vec<u8>,
@@ -51,76 +383,586 @@ impl WebApp {
             metadata.len(),
             compressed_web.len()
         );
+        let compression = CompressionFormat::detect(&compressed_web);
         Ok(Self {
             metadata,
             web: compressed_web,
+            compression,
+            signature: None,
+            chunks: vec![],
+            index: OnceLock::new(),
+            file_cache: OnceLock::new(),
         })
     }
 
-    pub fn pack(mut self) -> std::io::Result<Vec<u8>> {
-        let mut output = Vec::with_capacity(
-            self.metadata.len() + self.web.len() + (std::mem::size_of::<u64>() * 2),
-        );
+    pub fn pack(self) -> std::io::Result<Vec<u8>> {
+        self.pack_with_progress(|_, _| {})
+    }
+
+    /// Same as [`WebApp::pack`], reporting the progress to `progress`
+    /// as `(bytes written, total bytes)`.
+    pub fn pack_with_progress(
+        mut self,
+        mut progress: impl FnMut(u64, u64),
+    ) -> std::io::Result<Vec<u8>> {
+        let web = self.web_section()?;
+        let total = PACK_MAGIC.len()
+            + 3
+            + self.metadata.len()
+            + web.len()
+            + (std::mem::size_of::<u64>() * 2)
+            + CHECKSUMS_SIZE
+            + self.signature.map_or(0, |_| Signature::BYTE_SIZE);
+        let mut output = Vec::with_capacity(total);
+        let metadata_checksum = blake3::hash(&self.metadata);
+        let web_checksum = blake3::hash(&web);
+        let mut flags = 0;
+        if self.signature.is_some() {
+            flags |= FLAG_SIGNED;
+        }
+        if self.is_chunked() {
+            flags |= FLAG_CHUNKED;
+        }
+        output.extend_from_slice(&PACK_MAGIC);
+        output.write_u8(PACK_VERSION)?;
+        output.write_u8(self.compression as u8)?;
+        output.write_u8(flags)?;
         output.write_u64::<BigEndian>(self.metadata.len() as u64)?;
         output.append(&mut self.metadata);
-        output.write_u64::<BigEndian>(self.web.len() as u64)?;
-        output.append(&mut self.web);
+        output.write_u64::<BigEndian>(web.len() as u64)?;
+        for web_part in web.chunks(PROGRESS_STEP) {
+            output.extend_from_slice(web_part);
+            progress(output.len() as u64, total as u64);
+        }
+        output.extend_from_slice(metadata_checksum.as_bytes());
+        output.extend_from_slice(web_checksum.as_bytes());
+        if let Some(signature) = self.signature {
+            output.extend_from_slice(&signature.to_bytes());
+        }
+        progress(output.len() as u64, total as u64);
         Ok(output)
     }
 
+    /// Signs the metadata and web sections with the publisher key.
+    pub fn sign(&mut self, key: &SigningKey) {
+        self.signature = Some(key.sign(self.signed_digest().as_bytes()));
+    }
+
+    /// Verifies that this webapp was signed by the publisher owning `pub_key`.
+    pub fn verify(&self, pub_key: &VerifyingKey) -> Result<(), WebContractError> {
+        let Some(signature) = &self.signature else {
+            return Err(WebContractError::InvalidSignature(
+                "webapp state is not signed".into(),
+            ));
+        };
+        pub_key
+            .verify(self.signed_digest().as_bytes(), signature)
+            .map_err(|e| WebContractError::InvalidSignature(e.to_string()))
+    }
+
+    fn signed_digest(&self) -> blake3::Hash {
+        // chunk references carry the hash of every chunk, so signing them covers the whole archive
+        let web = self.web_section().unwrap_or_default();
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&(self.metadata.len() as u64).to_be_bytes());
+        hasher.update(&self.metadata);
+        hasher.update(&(web.len() as u64).to_be_bytes());
+        hasher.update(&web);
+        hasher.finalize()
+    }
+
+    /// Contents of the web section of the packed state.
+    fn web_section(&self) -> std::io::Result<Vec<u8>> {
+        if self.is_chunked() {
+            bincode::serialize(&self.chunks).map_err(std::io::Error::other)
+        } else {
+            Ok(self.web.clone())
+        }
+    }
+
+    /// Creates the primary state of a webapp whose web archive is split across the states
+    /// of other contracts, see [`WebApp::split_web`].
+    pub fn from_chunks(
+        metadata: Vec<u8>,
+        compression: CompressionFormat,
+        chunks: Vec<WebAppChunkRef>,
+    ) -> Self {
+        Self {
+            metadata,
+            web: vec![],
+            compression,
+            signature: None,
+            chunks,
+            index: OnceLock::new(),
+            file_cache: OnceLock::new(),
+        }
+    }
+
+    /// Splits the compressed web archive into chunks of at most `chunk_size` bytes,
+    /// to be published as the states of separate contracts.
+    pub fn split_web(&self, chunk_size: usize) -> impl Iterator<Item = &[u8]> {
+        self.web.chunks(chunk_size)
+    }
+
+    /// Whether the web archive is stored across other contracts.
+    pub fn is_chunked(&self) -> bool {
+        !self.chunks.is_empty()
+    }
+
+    /// Whether the given packed state references web archive chunks stored in other contracts.
+    pub fn is_chunked_state(state: &[u8]) -> bool {
+        read_pack_header(&mut Cursor::new(state), &WebAppLimits::default())
+            .is_ok_and(|header| header.flags & FLAG_CHUNKED != 0)
+    }
+
+    /// Reassembles the web archive from the chunks fetched from the referenced contracts,
+    /// in the same order as [`WebApp::chunks`].
+    pub fn assemble<C: AsRef<[u8]>>(
+        &mut self,
+        chunks: impl IntoIterator<Item = C>,
+        limits: &WebAppLimits,
+    ) -> Result<(), WebContractError> {
+        let mut verifier = self.web_chunks(limits)?;
+        let mut web = Vec::with_capacity(verifier.size as usize);
+        for chunk in chunks.into_iter().take(self.chunks.len()) {
+            let chunk = chunk.as_ref();
+            verifier.verify(chunk)?;
+            web.extend_from_slice(chunk);
+        }
+        verifier.finish()?;
+        self.web = web;
+        Ok(())
+    }
+
+    /// Verifier of the chunks of the web archive, to reassemble it as the chunks are fetched
+    /// without holding it in memory. Fails if any chunk is larger than the max size of the web
+    /// archive, or if the chunks add up to more than the max size of reassembled archives.
+    pub fn web_chunks(&self, limits: &WebAppLimits) -> Result<WebChunks, WebContractError> {
+        if let Some(chunk) = self
+            .chunks
+            .iter()
+            .find(|chunk| chunk.size > limits.max_web_size)
+        {
+            return Err(WebContractError::UnpackingError(anyhow::anyhow!(
+                "web chunk of {} bytes exceeds max size of {} bytes",
+                chunk.size,
+                limits.max_web_size
+            )));
+        }
+        let size = self
+            .chunks
+            .iter()
+            .try_fold(0u64, |size, chunk| size.checked_add(chunk.size))
+            .filter(|size| *size <= limits.max_assembled_size)
+            .ok_or_else(|| {
+                WebContractError::UnpackingError(anyhow::anyhow!(
+                    "web chunks exceed max assembled size of {} bytes",
+                    limits.max_assembled_size
+                ))
+            })?;
+        Ok(WebChunks {
+            pending: self.chunks.clone().into_iter(),
+            size,
+        })
+    }
+
+    /// Parses the [`WebAppManifest`] carried in the metadata section.
+    pub fn manifest(&self) -> Result<WebAppManifest, WebContractError> {
+        WebAppManifest::from_metadata(&self.metadata)
+    }
+
+    /// Computes a binary delta which turns the `old` packed state into the `new` one.
+    ///
+    /// The new state is zstd compressed using the old state as dictionary, so publishing
+    /// small changes to a webapp only requires sending the changed bytes.
+    #[instrument(level = "debug", skip(old, new))]
+    pub fn diff(old: &[u8], new: &[u8]) -> Result<Vec<u8>, WebContractError> {
+        debug!(
+            "Computing delta between packed states ({} -> {} bytes)",
+            old.len(),
+            new.len()
+        );
+        let mut compressor = zstd::bulk::Compressor::with_dictionary(19, old)
+            .map_err(WebContractError::StoringError)?;
+        compressor
+            .set_parameter(CParameter::WindowLog(DELTA_WINDOW_LOG))
+            .and_then(|_| compressor.set_parameter(CParameter::EnableLongDistanceMatching(true)))
+            .map_err(WebContractError::StoringError)?;
+        let compressed = compressor
+            .compress(new)
+            .map_err(WebContractError::StoringError)?;
+
+        let mut delta = Vec::with_capacity(
+            DELTA_MAGIC.len() + blake3::OUT_LEN + std::mem::size_of::<u64>() + compressed.len(),
+        );
+        delta.extend_from_slice(&DELTA_MAGIC);
+        delta.extend_from_slice(blake3::hash(old).as_bytes());
+        delta
+            .write_u64::<BigEndian>(new.len() as u64)
+            .map_err(WebContractError::StoringError)?;
+        delta.extend_from_slice(&compressed);
+        Ok(delta)
+    }
+
+    /// Returns whether the given bytes are a delta produced by [`WebApp::diff`].
+    pub fn is_delta(delta: &[u8]) -> bool {
+        delta.starts_with(&DELTA_MAGIC)
+    }
+
+    /// Returns whether the given state is a packed webapp whose manifest asks nodes to apply
+    /// the deltas produced by [`WebApp::diff`] to it.
+    pub fn accepts_deltas(state: &[u8]) -> bool {
+        state.starts_with(&PACK_MAGIC)
+            && WebApp::try_from(state)
+                .and_then(|webapp| webapp.manifest())
+                .is_ok_and(|manifest| manifest.binary_deltas)
+    }
+
+    /// Applies a delta produced by [`WebApp::diff`] to the `old` packed state, returning
+    /// the new packed state.
+    #[instrument(level = "debug", skip(old, delta))]
+    pub fn apply_delta(
+        old: &[u8],
+        delta: &[u8],
+        limits: &WebAppLimits,
+    ) -> Result<Vec<u8>, WebContractError> {
+        let mut delta = Cursor::new(delta);
+        let mut magic = [0; DELTA_MAGIC.len()];
+        let mut base_hash = [0; blake3::OUT_LEN];
+        delta
+            .read_exact(&mut magic)
+            .and_then(|_| delta.read_exact(&mut base_hash))
+            .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+        if magic != DELTA_MAGIC {
+            return Err(WebContractError::UnpackingError(anyhow::anyhow!(
+                "not a webapp delta"
+            )));
+        }
+        if blake3::Hash::from(base_hash) != blake3::hash(old) {
+            return Err(WebContractError::UnpackingError(anyhow::anyhow!(
+                "delta does not apply to the current state"
+            )));
+        }
+        let new_size = delta
+            .read_u64::<BigEndian>()
+            .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+        if new_size > limits.max_state_size() {
+            return Err(WebContractError::UnpackingError(anyhow::anyhow!(
+                "Exceeded packed state size: {new_size} bytes"
+            )));
+        }
+
+        let mut decompressor = zstd::bulk::Decompressor::with_dictionary(old)
+            .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+        decompressor
+            .set_parameter(DParameter::WindowLogMax(DELTA_WINDOW_LOG))
+            .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+        let position = delta.position() as usize;
+        let new = decompressor
+            .decompress(&delta.into_inner()[position..], new_size as usize)
+            .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+        if new.len() as u64 != new_size {
+            return Err(WebContractError::UnpackingError(anyhow::anyhow!(
+                "delta produced {} bytes, expected {new_size}",
+                new.len()
+            )));
+        }
+        Ok(new)
+    }
+
     #[instrument(level = "debug", skip(self, dst))]
     pub fn unpack(&mut self, dst: impl AsRef<Path>) -> Result<(), WebContractError> {
         debug!("Unpacking web content to {:?}", dst.as_ref());
-        let mut decoded_web = self.decode_web();
+        let mut decoded_web = self.decode_web()?;
         decoded_web
             .unpack(dst)
             .map_err(WebContractError::StoringError)?;
         Ok(())
     }
 
+    /// Unpacks the web archive of a chunked webapp reassembled outside of it, e.g. on disk,
+    /// reading it incrementally from `web`.
+    #[instrument(level = "debug", skip(self, web, dst))]
+    pub fn unpack_assembled(
+        &self,
+        web: impl Read,
+        dst: impl AsRef<Path>,
+    ) -> Result<(), WebContractError> {
+        debug!("Unpacking assembled web content to {:?}", dst.as_ref());
+        let decoder = self
+            .compression
+            .decoder(web)
+            .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+        Archive::new(decoder)
+            .unpack(dst)
+            .map_err(WebContractError::StoringError)?;
+        Ok(())
+    }
+
+    /// Same as [`WebApp::unpack`], reporting the progress of decoding the compressed
+    /// web archive to `progress` as `(bytes processed, total bytes)`.
+    #[instrument(level = "debug", skip(self, dst, progress))]
+    pub fn unpack_with_progress(
+        &mut self,
+        dst: impl AsRef<Path>,
+        progress: impl FnMut(u64, u64),
+    ) -> Result<(), WebContractError> {
+        debug!("Unpacking web content to {:?}", dst.as_ref());
+        self.ensure_assembled()?;
+        let web = ProgressReader::new(self.web.as_slice(), self.web.len() as u64, progress);
+        let decoder = self
+            .compression
+            .decoder(web)
+            .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+        Archive::new(decoder)
+            .unpack(dst)
+            .map_err(WebContractError::StoringError)?;
+        Ok(())
+    }
+
+    /// Unpacks a packed webapp state into `dst` reading it incrementally from `state`.
+    ///
+    /// Unlike [`WebApp::try_from`] followed by [`WebApp::unpack`], neither the compressed
+    /// nor the decompressed web archive are ever fully held in memory. Returns the metadata.
+    ///
+    /// The checksums of the sections are verified before the archive is unpacked, so nothing
+    /// is written for corrupted states, and malformed archives fail with
+    /// [`WebContractError::IntegrityFailure`].
+    ///
+    /// The signature (if any) is not checked, use [`WebApp::verify`] beforehand if required.
+    #[instrument(level = "debug", skip(state, dst))]
+    pub fn unpack_streaming(
+        mut state: impl Read + Seek,
+        dst: impl AsRef<Path>,
+        limits: &WebAppLimits,
+    ) -> Result<Vec<u8>, WebContractError> {
+        debug!("Streaming web content to {:?}", dst.as_ref());
+        let header = read_pack_header(&mut state, limits)?;
+        if header.flags & FLAG_CHUNKED != 0 {
+            return Err(WebContractError::UnpackingError(anyhow::anyhow!(
+                "chunked webapps must be assembled before unpacking"
+            )));
+        }
+        let mut metadata = vec![0; header.metadata_size as usize];
+        state
+            .read_exact(&mut metadata)
+            .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+        let web_size = read_web_size(&mut state, limits)?;
+
+        if header.has_checksums {
+            let web_start = state
+                .stream_position()
+                .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+            let mut web = HashingReader::new((&mut state).take(web_size));
+            std::io::copy(&mut web, &mut std::io::sink())
+                .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+            let web_checksum = web.hasher.finalize();
+            let (expected_metadata, expected_web) = read_checksums(&mut state)?;
+            verify_checksum("metadata", &metadata, expected_metadata)?;
+            if web_checksum != expected_web {
+                return Err(WebContractError::IntegrityFailure(
+                    "web section checksum mismatch".into(),
+                ));
+            }
+            state
+                .seek(SeekFrom::Start(web_start))
+                .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+        }
+
+        let mut web = (&mut state).take(web_size);
+        let decoder = match header.compression {
+            Some(compression) => compression.decoder(&mut web),
+            None => {
+                // legacy state, peek into the archive to find the compression format
+                let mut magic = Vec::with_capacity(XZ_MAGIC.len());
+                (&mut web)
+                    .take(XZ_MAGIC.len() as u64)
+                    .read_to_end(&mut magic)
+                    .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+                let compression = CompressionFormat::detect(&magic);
+                compression.decoder(Cursor::new(magic).chain(&mut web))
+            }
+        }
+        .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+        let read_failed = Cell::new(false);
+        let mut archive = Archive::new(FlagReadErrors {
+            inner: decoder,
+            failed: &read_failed,
+        });
+        let corrupted = |e: std::io::Error| {
+            WebContractError::IntegrityFailure(format!("corrupted web archive: {e}"))
+        };
+        std::fs::create_dir_all(dst.as_ref()).map_err(WebContractError::StoringError)?;
+        let dst = dst
+            .as_ref()
+            .canonicalize()
+            .map_err(WebContractError::StoringError)?;
+        for entry in archive.entries().map_err(corrupted)? {
+            entry
+                .and_then(|mut entry| entry.unpack_in(&dst))
+                .map_err(|e| {
+                    if read_failed.get() {
+                        corrupted(e)
+                    } else {
+                        WebContractError::StoringError(e)
+                    }
+                })?;
+        }
+        Ok(metadata)
+    }
+
+    /// Returns a reader over the contents of the file at `path` without buffering
+    /// the decompressed file or archive in memory.
+    #[instrument(level = "debug", skip(self))]
+    pub fn get_file_reader(&self, path: &str) -> Result<impl Read + '_, WebContractError> {
+        debug!("Streaming file from web content: {}", path);
+        let entry = self.file_entry(path)?;
+        self.read_entry(entry)
+    }
+
+    /// Returns the contents of the file at `path`.
+    ///
+    /// The archive is only scanned once to build a file index, and recently accessed files
+    /// are kept in memory, so serving many small assets does not decode the archive each time.
     #[instrument(level = "debug", skip(self))]
     pub fn get_file(&mut self, path: &str) -> Result<Vec<u8>, WebContractError> {
         debug!("Retrieving file from web content: {}", path);
-        let mut decoded_web = self.decode_web();
+        if let Some(cached) = self.file_cache().get(path) {
+            return Ok(cached.value().as_ref().clone());
+        }
+        let entry = self.file_entry(path)?;
+        let mut bytes = Vec::with_capacity(entry.size as usize);
+        self.read_entry(entry)?
+            .read_to_end(&mut bytes)
+            .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+        self.file_cache()
+            .insert(path.to_owned(), Arc::new(bytes.clone()), bytes.len() as i64);
+        Ok(bytes)
+    }
+
+    /// Lists the entries of the web archive without extracting their contents.
+    #[instrument(level = "debug", skip(self))]
+    pub fn list_entries(&self) -> Result<Vec<WebAppEntry>, WebContractError> {
+        let mut decoded_web = self.decode_web()?;
+        let mut entries = vec![];
         for e in decoded_web
             .entries()
             .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?
         {
-            let mut e = e.map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
-            if e.path()
-                .ok()
-                .filter(|p| p.to_string_lossy() == path)
-                .is_some()
-            {
-                let mut bytes = vec![];
-                e.read_to_end(&mut bytes)
-                    .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
-                return Ok(bytes);
+            let e = e.map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+            let path = e
+                .path()
+                .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?
+                .to_string_lossy()
+                .into_owned();
+            entries.push(WebAppEntry {
+                path,
+                size: e.size(),
+                mtime: e.header().mtime().unwrap_or_default(),
+                is_dir: e.header().entry_type().is_dir(),
+            });
+        }
+        Ok(entries)
+    }
+
+    fn file_cache(&self) -> &Cache<String, Arc<Vec<u8>>> {
+        self.file_cache.get_or_init(|| {
+            Cache::new(FILE_CACHE_COUNTERS, FILE_CACHE_SIZE).expect("failed to build file cache")
+        })
+    }
+
+    fn file_entry(&self, path: &str) -> Result<FileEntry, WebContractError> {
+        let index = match self.index.get() {
+            Some(index) => index,
+            None => {
+                let index = self.build_index()?;
+                self.index.get_or_init(|| index)
             }
+        };
+        index
+            .get(path)
+            .copied()
+            .ok_or_else(|| WebContractError::FileNotFound(path.to_owned()))
+    }
+
+    fn build_index(&self) -> Result<HashMap<String, FileEntry>, WebContractError> {
+        let mut decoded_web = self.decode_web()?;
+        let mut index = HashMap::new();
+        for e in decoded_web
+            .entries()
+            .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?
+        {
+            let e = e.map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+            if let Ok(path) = e.path() {
+                index.insert(
+                    path.to_string_lossy().into_owned(),
+                    FileEntry {
+                        offset: e.raw_file_position(),
+                        size: e.size(),
+                    },
+                );
+            }
+        }
+        debug!("Indexed {} files from web content", index.len());
+        Ok(index)
+    }
+
+    /// Decodes the web archive up to the given entry and returns a reader over its contents.
+    fn read_entry(&self, entry: FileEntry) -> Result<impl Read + '_, WebContractError> {
+        let mut decoder = self
+            .compression
+            .decoder(self.web.as_slice())
+            .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+        std::io::copy(&mut (&mut decoder).take(entry.offset), &mut std::io::sink())
+            .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+        Ok(decoder.take(entry.size))
+    }
+
+    fn ensure_assembled(&self) -> Result<(), WebContractError> {
+        if self.is_chunked() && self.web.is_empty() {
+            return Err(WebContractError::UnpackingError(anyhow::anyhow!(
+                "web chunks have not been assembled"
+            )));
         }
-        Err(WebContractError::FileNotFound(path.to_owned()))
+        Ok(())
     }
 
-    fn decode_web(&self) -> Archive<XzDecoder<&[u8]>> {
-        debug!("Decoding compressed web content ({} bytes)", self.web.len());
-        let decoder = XzDecoder::new(self.web.as_slice());
+    fn decode_web(&self) -> Result<Archive<Box<dyn Read + '_>>, WebContractError> {
+        self.ensure_assembled()?;
+        debug!(
+            "Decoding {:?} compressed web content ({} bytes)",
+            self.compression,
+            self.web.len()
+        );
+        let decoder = self
+            .compression
+            .decoder(self.web.as_slice())
+            .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
         let mut archive = Archive::new(decoder);
 
         // Debug log the archive contents
-        match archive.entries() {
-            Ok(entries) => {
-                debug!("Archive contents:");
-                for entry in entries.flatten() {
-                    if let Ok(path) = entry.path() {
-                        debug!("  {}", path.display());
+        if tracing::enabled!(tracing::Level::DEBUG) {
+            match archive.entries() {
+                Ok(entries) => {
+                    debug!("Archive contents:");
+                    for entry in entries.flatten() {
+                        if let Ok(path) = entry.path() {
+                            debug!("  {}", path.display());
+                        }
                     }
                 }
+                Err(e) => debug!("Failed to read archive entries: {}", e),
             }
-            Err(e) => debug!("Failed to read archive entries: {}", e),
+
+            // Create a fresh archive since we consumed the entries
+            let decoder = self
+                .compression
+                .decoder(self.web.as_slice())
+                .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+            archive = Archive::new(decoder);
         }
 
-        // Create a fresh archive since we consumed the entries
-        Archive::new(XzDecoder::new(self.web.as_slice()))
+        Ok(archive)
     }
 }
 
@@ -128,43 +970,791 @@ impl<'a> TryFrom<&'a [u8]> for WebApp {
     type Error = WebContractError;
 
     fn try_from(state: &'a [u8]) -> Result<Self, Self::Error> {
+        Self::try_from_with_limits(state, &WebAppLimits::default())
+    }
+}
+
+impl WebApp {
+    /// Reads a packed webapp state, rejecting it if it exceeds the given limits.
+    pub fn try_from_with_limits(
+        state: &[u8],
+        limits: &WebAppLimits,
+    ) -> Result<Self, WebContractError> {
         debug!(
             "Attempting to create WebApp from {} bytes of state",
             state.len()
         );
-        const MAX_METADATA_SIZE: u64 = 1024;
-        const MAX_WEB_SIZE: u64 = 1024 * 1024 * 100;
         // Decompose the state and extract the compressed web interface
         let mut state = Cursor::new(state);
 
-        let metadata_size = state
-            .read_u64::<BigEndian>()
-            .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
-        if metadata_size > MAX_METADATA_SIZE {
-            return Err(WebContractError::UnpackingError(anyhow::anyhow!(
-                "Exceeded metadata size of 1kB: {} bytes",
-                metadata_size
-            )));
-        }
-        let mut metadata = vec![0; metadata_size as usize];
+        let header = read_pack_header(&mut state, limits)?;
+        let mut metadata = vec![0; header.metadata_size as usize];
         state
             .read_exact(&mut metadata)
             .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
 
-        let web_size = state
-            .read_u64::<BigEndian>()
+        let web_size = read_web_size(&mut state, limits)?;
+        let mut web = vec![0; web_size as usize];
+        state
+            .read_exact(&mut web)
+            .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+
+        if header.has_checksums {
+            let (expected_metadata, expected_web) = read_checksums(&mut state)?;
+            verify_checksum("metadata", &metadata, expected_metadata)?;
+            verify_checksum("web", &web, expected_web)?;
+        }
+
+        // legacy states carry no header, figure out the compression from the archive itself
+        let compression = header
+            .compression
+            .unwrap_or_else(|| CompressionFormat::detect(&web));
+
+        let chunks = if header.flags & FLAG_CHUNKED != 0 {
+            bincode::deserialize(&std::mem::take(&mut web))
+                .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?
+        } else {
+            vec![]
+        };
+
+        let signature = if header.flags & FLAG_SIGNED != 0 {
+            let mut signature = [0; Signature::BYTE_SIZE];
+            state
+                .read_exact(&mut signature)
+                .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+            Some(Signature::from_bytes(&signature))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            metadata,
+            web,
+            compression,
+            signature,
+            chunks,
+            index: OnceLock::new(),
+            file_cache: OnceLock::new(),
+        })
+    }
+}
+
+/// Number of files tracked by the decoded file cache of a webapp.
+const FILE_CACHE_COUNTERS: usize = 1_000;
+/// Max size in bytes of the decoded files cached for a webapp.
+const FILE_CACHE_SIZE: i64 = 16 * 1024 * 1024;
+
+/// Default max size in bytes of the metadata section of a packed webapp.
+pub const DEFAULT_MAX_METADATA_SIZE: u64 = 1024;
+/// Default max size in bytes of the compressed web archive of a packed webapp.
+pub const DEFAULT_MAX_WEB_SIZE: u64 = 1024 * 1024 * 100;
+/// Default max size in bytes of a web archive reassembled from chunks.
+pub const DEFAULT_MAX_ASSEMBLED_SIZE: u64 = 1024 * 1024 * 1024;
+/// Upper bound of the bytes used by the pack header, section sizes, checksums and signature.
+const MAX_HEADER_SIZE: u64 = 256;
+
+/// Size limits enforced when reading packed webapp states.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WebAppLimits {
+    /// Max size in bytes of the metadata section.
+    pub max_metadata_size: u64,
+    /// Max size in bytes of the compressed web archive, or of each of its chunks when stored
+    /// across other contracts.
+    pub max_web_size: u64,
+    /// Max size in bytes of a web archive reassembled from chunks.
+    pub max_assembled_size: u64,
+}
+
+impl Default for WebAppLimits {
+    fn default() -> Self {
+        Self {
+            max_metadata_size: DEFAULT_MAX_METADATA_SIZE,
+            max_web_size: DEFAULT_MAX_WEB_SIZE,
+            max_assembled_size: DEFAULT_MAX_ASSEMBLED_SIZE,
+        }
+    }
+}
+
+impl WebAppLimits {
+    /// Checks the section sizes declared by a packed webapp state against the limits.
+    ///
+    /// States which do not carry a packed webapp header are not checked.
+    pub fn check(&self, state: &[u8]) -> Result<(), WebContractError> {
+        if !state.starts_with(&PACK_MAGIC) {
+            return Ok(());
+        }
+        let mut state = Cursor::new(state);
+        let header = read_pack_header(&mut state, self)?;
+        state.set_position(state.position() + header.metadata_size);
+        read_web_size(&mut state, self)?;
+        Ok(())
+    }
+
+    fn max_state_size(&self) -> u64 {
+        self.max_metadata_size + self.max_web_size + MAX_HEADER_SIZE
+    }
+}
+
+struct PackHeader {
+    /// Compression format, not present for states using the legacy layout.
+    compression: Option<CompressionFormat>,
+    flags: u8,
+    /// Whether the sections are followed by their checksums (version 3 onwards).
+    has_checksums: bool,
+    metadata_size: u64,
+}
+
+/// Reads the header of a packed state up to (and including) the size of the metadata section.
+fn read_pack_header(
+    state: &mut impl Read,
+    limits: &WebAppLimits,
+) -> Result<PackHeader, WebContractError> {
+    let mut head = [0u8; 8];
+    state
+        .read_exact(&mut head[..PACK_MAGIC.len()])
+        .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+    let (compression, flags, has_checksums) = if head[..PACK_MAGIC.len()] == PACK_MAGIC {
+        let version = state
+            .read_u8()
             .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
-        if web_size > MAX_WEB_SIZE {
+        if version > PACK_VERSION {
             return Err(WebContractError::UnpackingError(anyhow::anyhow!(
-                "Exceeded packed web size of 100MB: {} bytes",
-                web_size
+                "Unsupported packed webapp version: {version}"
             )));
         }
-        let mut web = vec![0; web_size as usize];
+        let compression = state
+            .read_u8()
+            .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+        let flags = if version >= 2 {
+            state
+                .read_u8()
+                .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?
+        } else {
+            0
+        };
         state
-            .read_exact(&mut web)
+            .read_exact(&mut head)
+            .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+        (
+            Some(CompressionFormat::try_from(compression)?),
+            flags,
+            version >= 3,
+        )
+    } else {
+        // legacy layout, the bytes read so far are part of the metadata size
+        state
+            .read_exact(&mut head[PACK_MAGIC.len()..])
             .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+        (None, 0, false)
+    };
+
+    let metadata_size = u64::from_be_bytes(head);
+    if metadata_size > limits.max_metadata_size {
+        return Err(WebContractError::UnpackingError(anyhow::anyhow!(
+            "Exceeded metadata size of {} bytes: {} bytes",
+            limits.max_metadata_size,
+            metadata_size
+        )));
+    }
+    Ok(PackHeader {
+        compression,
+        flags,
+        has_checksums,
+        metadata_size,
+    })
+}
+
+/// Reads the checksums of the metadata and web sections.
+fn read_checksums(state: &mut impl Read) -> Result<(blake3::Hash, blake3::Hash), WebContractError> {
+    let mut checksums = [0; CHECKSUMS_SIZE];
+    state.read_exact(&mut checksums).map_err(|e| {
+        WebContractError::IntegrityFailure(format!("missing section checksums: {e}"))
+    })?;
+    let (metadata, web) = checksums.split_at(blake3::OUT_LEN);
+    let to_hash = |bytes: &[u8]| blake3::Hash::from_slice(bytes).expect("checksum size");
+    Ok((to_hash(metadata), to_hash(web)))
+}
+
+fn verify_checksum(
+    section: &str,
+    content: &[u8],
+    expected: blake3::Hash,
+) -> Result<(), WebContractError> {
+    if blake3::hash(content) != expected {
+        return Err(WebContractError::IntegrityFailure(format!(
+            "{section} section checksum mismatch"
+        )));
+    }
+    Ok(())
+}
+
+/// Bytes processed between progress reports when packing.
+const PROGRESS_STEP: usize = 64 * 1024;
+
+/// Reports the bytes read through it, out of `total`, to the progress callback.
+struct ProgressReader<R, F> {
+    inner: R,
+    processed: u64,
+    total: u64,
+    progress: F,
+}
+
+impl<R, F> ProgressReader<R, F> {
+    fn new(inner: R, total: u64, progress: F) -> Self {
+        Self {
+            inner,
+            processed: 0,
+            total,
+            progress,
+        }
+    }
+}
+
+impl<R: Read, F: FnMut(u64, u64)> Read for ProgressReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if read > 0 {
+            self.processed += read as u64;
+            (self.progress)(self.processed, self.total);
+        }
+        Ok(read)
+    }
+}
+
+/// Hashes the bytes read through it.
+struct HashingReader<R> {
+    inner: R,
+    hasher: blake3::Hasher,
+}
 
-        Ok(Self { metadata, web })
+impl<R> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: blake3::Hasher::new(),
+        }
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// Flags the errors reading through it, to tell malformed archives apart from failures writing
+/// their contents.
+struct FlagReadErrors<'a, R> {
+    inner: R,
+    failed: &'a Cell<bool>,
+}
+
+impl<R: Read> Read for FlagReadErrors<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf).inspect_err(|_| self.failed.set(true))
+    }
+}
+
+/// Recursively appends the contents of `root/relative` to the archive.
+fn append_dir(
+    web: &mut Builder<Cursor<Vec<u8>>>,
+    root: &Path,
+    relative: &Path,
+) -> std::io::Result<()> {
+    let mut entries = std::fs::read_dir(root.join(relative))?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let path = relative.join(entry.file_name());
+        // not following links, which may point outside of the root or loop back into it
+        let metadata = std::fs::symlink_metadata(entry.path())?;
+        if metadata.is_symlink() {
+            tracing::warn!("Skipping symbolic link {path:?}");
+            continue;
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_mtime(
+            metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs()),
+        );
+        if metadata.is_dir() {
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_mode(0o755);
+            header.set_size(0);
+            web.append_data(&mut header, &path, std::io::empty())?;
+            append_dir(web, root, &path)?;
+        } else if metadata.is_file() {
+            header.set_mode(0o644);
+            header.set_size(metadata.len());
+            web.append_data(&mut header, &path, File::open(entry.path())?)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_web_size(state: &mut impl Read, limits: &WebAppLimits) -> Result<u64, WebContractError> {
+    let web_size = state
+        .read_u64::<BigEndian>()
+        .map_err(|e| WebContractError::UnpackingError(anyhow::anyhow!(e)))?;
+    if web_size > limits.max_web_size {
+        return Err(WebContractError::UnpackingError(anyhow::anyhow!(
+            "Exceeded packed web size of {} bytes: {} bytes",
+            limits.max_web_size,
+            web_size
+        )));
+    }
+    Ok(web_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_archive() -> Builder<Cursor<Vec<u8>>> {
+        let mut builder = Builder::new(Cursor::new(Vec::new()));
+        let content = b"<html><body>hello</body></html>";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "index.html", content.as_slice())
+            .unwrap();
+        builder
+    }
+
+    #[test]
+    fn pack_roundtrip_all_formats() -> Result<(), Box<dyn std::error::Error>> {
+        for compression in [
+            CompressionFormat::None,
+            CompressionFormat::Xz,
+            CompressionFormat::Zstd,
+        ] {
+            let webapp = WebApp::from_data_with_compression(
+                b"metadata".to_vec(),
+                test_archive(),
+                compression,
+            )?;
+            let packed = webapp.pack()?;
+            let mut unpacked = WebApp::try_from(packed.as_slice())?;
+            assert_eq!(unpacked.compression, compression);
+            assert_eq!(unpacked.metadata, b"metadata");
+            let index = unpacked.get_file("index.html")?;
+            assert_eq!(index, b"<html><body>hello</body></html>");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_layout_is_detected() -> Result<(), Box<dyn std::error::Error>> {
+        let webapp = WebApp::from_data(vec![], test_archive())?;
+        let mut legacy = vec![];
+        legacy.write_u64::<BigEndian>(0)?;
+        legacy.write_u64::<BigEndian>(webapp.web.len() as u64)?;
+        legacy.extend_from_slice(&webapp.web);
+
+        let mut unpacked = WebApp::try_from(legacy.as_slice())?;
+        assert_eq!(unpacked.compression, CompressionFormat::Xz);
+        assert!(unpacked.get_file("index.html").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn signed_webapp() -> Result<(), Box<dyn std::error::Error>> {
+        let publisher = SigningKey::generate(&mut rand::rngs::OsRng);
+        let mut webapp = WebApp::from_data(b"metadata".to_vec(), test_archive())?;
+        assert!(webapp.verify(&publisher.verifying_key()).is_err());
+        webapp.sign(&publisher);
+
+        let packed = webapp.pack()?;
+        let unpacked = WebApp::try_from(packed.as_slice())?;
+        unpacked.verify(&publisher.verifying_key())?;
+
+        let other = SigningKey::generate(&mut rand::rngs::OsRng);
+        assert!(unpacked.verify(&other.verifying_key()).is_err());
+
+        let mut tampered = WebApp::try_from(packed.as_slice())?;
+        tampered.metadata = b"tampered".to_vec();
+        assert!(matches!(
+            tampered.verify(&publisher.verifying_key()),
+            Err(WebContractError::InvalidSignature(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn stream_files() -> Result<(), Box<dyn std::error::Error>> {
+        let webapp =
+            WebApp::from_data_with_compression(vec![], test_archive(), CompressionFormat::Zstd)?;
+        let mut content = vec![];
+        webapp
+            .get_file_reader("index.html")?
+            .read_to_end(&mut content)?;
+        assert_eq!(content, b"<html><body>hello</body></html>");
+        assert!(matches!(
+            webapp.get_file_reader("missing.html").err(),
+            Some(WebContractError::FileNotFound(_))
+        ));
+
+        let packed = webapp.pack()?;
+        let dst = tempfile::tempdir()?;
+        WebApp::unpack_streaming(
+            Cursor::new(packed.as_slice()),
+            dst.path(),
+            &WebAppLimits::default(),
+        )?;
+        assert_eq!(
+            std::fs::read(dst.path().join("index.html"))?,
+            b"<html><body>hello</body></html>"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn delta_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let manifest = WebAppManifest {
+            binary_deltas: true,
+            ..Default::default()
+        };
+        let old = WebApp::from_data(manifest.to_metadata(), test_archive())?.pack()?;
+        let mut archive = test_archive();
+        let content = b"body { color: red; }";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        archive.append_data(&mut header, "style.css", content.as_slice())?;
+        let new = WebApp::from_data(b"v2".to_vec(), archive)?.pack()?;
+
+        let delta = WebApp::diff(&old, &new)?;
+        assert!(WebApp::is_delta(&delta));
+        assert!(!WebApp::is_delta(&new));
+        assert_eq!(
+            WebApp::apply_delta(&old, &delta, &WebAppLimits::default())?,
+            new
+        );
+        assert!(WebApp::apply_delta(&new, &delta, &WebAppLimits::default()).is_err());
+
+        assert!(WebApp::accepts_deltas(&old));
+        assert!(!WebApp::accepts_deltas(&new));
+        assert!(!WebApp::accepts_deltas(&delta));
+        Ok(())
+    }
+
+    #[test]
+    fn indexed_file_access() -> Result<(), Box<dyn std::error::Error>> {
+        let mut webapp = WebApp::from_data(vec![], test_archive())?;
+        for _ in 0..2 {
+            assert_eq!(
+                webapp.get_file("index.html")?,
+                b"<html><body>hello</body></html>"
+            );
+            webapp.file_cache().wait()?;
+        }
+        assert_eq!(webapp.index.get().map(HashMap::len), Some(1));
+        assert!(matches!(
+            webapp.get_file("missing.html"),
+            Err(WebContractError::FileNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn size_limits() -> Result<(), Box<dyn std::error::Error>> {
+        let packed = WebApp::from_data(vec![0; 64], test_archive())?.pack()?;
+        let defaults = WebAppLimits::default();
+        defaults.check(&packed)?;
+        assert!(WebApp::try_from_with_limits(&packed, &defaults).is_ok());
+
+        let small_metadata = WebAppLimits {
+            max_metadata_size: 32,
+            ..defaults
+        };
+        assert!(small_metadata.check(&packed).is_err());
+        assert!(WebApp::try_from_with_limits(&packed, &small_metadata).is_err());
+
+        let small_web = WebAppLimits {
+            max_web_size: 16,
+            ..defaults
+        };
+        assert!(small_web.check(&packed).is_err());
+        assert!(WebApp::unpack_streaming(
+            Cursor::new(packed.as_slice()),
+            tempfile::tempdir()?.path(),
+            &small_web
+        )
+        .is_err());
+
+        // arbitrary contract states are left alone
+        small_web.check(b"not a webapp")?;
+        Ok(())
+    }
+
+    #[test]
+    fn integrity_checksums() -> Result<(), Box<dyn std::error::Error>> {
+        let packed = WebApp::from_data(b"metadata".to_vec(), test_archive())?.pack()?;
+
+        let mut corrupted_metadata = packed.clone();
+        corrupted_metadata[PACK_MAGIC.len() + 3 + 8] ^= 1;
+        assert!(matches!(
+            WebApp::try_from(corrupted_metadata.as_slice()),
+            Err(WebContractError::IntegrityFailure(_))
+        ));
+
+        let mut corrupted_web = packed.clone();
+        let last_web_byte = corrupted_web.len() - CHECKSUMS_SIZE - 1;
+        corrupted_web[last_web_byte] ^= 1;
+        assert!(matches!(
+            WebApp::try_from(corrupted_web.as_slice()),
+            Err(WebContractError::IntegrityFailure(_))
+        ));
+        let dst = tempfile::tempdir()?;
+        assert!(matches!(
+            WebApp::unpack_streaming(
+                Cursor::new(corrupted_web.as_slice()),
+                dst.path(),
+                &WebAppLimits::default()
+            ),
+            Err(WebContractError::IntegrityFailure(_))
+        ));
+        // nothing is written for corrupted states
+        assert_eq!(std::fs::read_dir(dst.path())?.count(), 0);
+
+        // the checksums match, but the archive itself is malformed
+        let mut malformed =
+            WebApp::from_chunks(b"metadata".to_vec(), CompressionFormat::Zstd, vec![]);
+        malformed.web = zstd::encode_all([1u8; 1024].as_slice(), 0)?;
+        assert!(matches!(
+            WebApp::unpack_streaming(
+                Cursor::new(malformed.pack()?),
+                tempfile::tempdir()?.path(),
+                &WebAppLimits::default()
+            ),
+            Err(WebContractError::IntegrityFailure(_))
+        ));
+
+        let truncated = &packed[..packed.len() - 1];
+        assert!(matches!(
+            WebApp::try_from(truncated),
+            Err(WebContractError::IntegrityFailure(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn progress_reporting() -> Result<(), Box<dyn std::error::Error>> {
+        let mut reports = vec![];
+        let webapp = WebApp::from_data_with_progress(
+            vec![],
+            test_archive(),
+            CompressionFormat::Zstd,
+            |processed, total| reports.push((processed, total)),
+        )?;
+        let (processed, total) = *reports.last().ok_or("no progress reported")?;
+        assert_eq!(processed, total);
+        assert!(reports.windows(2).all(|w| w[0].0 <= w[1].0));
+
+        reports.clear();
+        let packed =
+            webapp.pack_with_progress(|processed, total| reports.push((processed, total)))?;
+        assert_eq!(
+            reports.last(),
+            Some(&(packed.len() as u64, packed.len() as u64))
+        );
+
+        reports.clear();
+        let mut webapp = WebApp::try_from(packed.as_slice())?;
+        let dst = tempfile::tempdir()?;
+        webapp.unpack_with_progress(dst.path(), |processed, total| {
+            reports.push((processed, total))
+        })?;
+        assert!(dst.path().join("index.html").exists());
+        assert!(reports
+            .iter()
+            .all(|(processed, total)| processed <= total && *total == webapp.web.len() as u64));
+        Ok(())
+    }
+
+    #[test]
+    fn list_entries() -> Result<(), Box<dyn std::error::Error>> {
+        let webapp = WebApp::from_data(vec![], test_archive())?;
+        let entries = webapp.list_entries()?;
+        assert_eq!(
+            entries,
+            vec![WebAppEntry {
+                path: "index.html".into(),
+                size: 31,
+                mtime: 0,
+                is_dir: false,
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn from_directory() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir(dir.path().join("assets"))?;
+        std::fs::write(dir.path().join("index.html"), b"<html></html>")?;
+        std::fs::write(dir.path().join("assets").join("app.js"), b"main()")?;
+
+        let packed = WebApp::from_directory(vec![], dir.path(), CompressionFormat::Zstd)?.pack()?;
+        let mut webapp = WebApp::try_from(packed.as_slice())?;
+        let entries = webapp.list_entries()?;
+        assert_eq!(
+            entries.iter().map(|e| e.path.as_str()).collect::<Vec<_>>(),
+            ["assets", "assets/app.js", "index.html"]
+        );
+        assert!(entries[0].is_dir);
+        assert_eq!(webapp.get_file("assets/app.js")?, b"main()");
+
+        #[cfg(unix)]
+        {
+            let outside = tempfile::tempdir()?;
+            std::fs::write(outside.path().join("secret"), b"secret")?;
+            std::os::unix::fs::symlink(outside.path(), dir.path().join("outside"))?;
+            std::os::unix::fs::symlink(dir.path(), dir.path().join("assets").join("loop"))?;
+            let packed =
+                WebApp::from_directory(vec![], dir.path(), CompressionFormat::Zstd)?.pack()?;
+            let webapp = WebApp::try_from(packed.as_slice())?;
+            assert_eq!(
+                webapp.list_entries()?.len(),
+                3,
+                "only files within the directory are archived"
+            );
+        }
+
+        std::fs::remove_file(dir.path().join("index.html"))?;
+        assert!(matches!(
+            WebApp::from_directory(vec![], dir.path(), CompressionFormat::Zstd),
+            Err(WebContractError::FileNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn from_archive() -> Result<(), Box<dyn std::error::Error>> {
+        let mut upload = Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_size(0);
+        upload.append_data(&mut header, "./assets", std::io::empty())?;
+        for (path, content) in [
+            ("./index.html", "<html></html>"),
+            ("assets/app.js", "main()"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o600);
+            upload.append_data(&mut header, path, content.as_bytes())?;
+        }
+        let upload = upload.into_inner()?;
+        let mut gzipped = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut gzipped, &upload)?;
+        let gzipped = gzipped.finish()?;
+
+        for archive in [upload, gzipped] {
+            let packed = WebApp::from_archive(vec![], &archive, CompressionFormat::Zstd)?.pack()?;
+            let mut webapp = WebApp::try_from(packed.as_slice())?;
+            assert_eq!(
+                webapp
+                    .list_entries()?
+                    .iter()
+                    .map(|e| e.path.as_str())
+                    .collect::<Vec<_>>(),
+                ["assets", "index.html", "assets/app.js"]
+            );
+            assert_eq!(webapp.get_file("assets/app.js")?, b"main()");
+        }
+
+        let mut without_index = Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(6);
+        without_index.append_data(&mut header, "app.js", "main()".as_bytes())?;
+        assert!(matches!(
+            WebApp::from_archive(
+                vec![],
+                &without_index.into_inner()?,
+                CompressionFormat::Zstd
+            ),
+            Err(WebContractError::FileNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn chunked_webapp() -> Result<(), Box<dyn std::error::Error>> {
+        let webapp = WebApp::from_data(b"metadata".to_vec(), test_archive())?;
+        let chunks: Vec<Vec<u8>> = webapp.split_web(64).map(<[u8]>::to_vec).collect();
+        assert!(chunks.len() > 1);
+        let refs = chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| WebAppChunkRef::new(ContractInstanceId::new([i as u8; 32]), chunk))
+            .collect();
+
+        let mut primary = WebApp::from_chunks(b"metadata".to_vec(), webapp.compression, refs);
+        let publisher = SigningKey::generate(&mut rand::rngs::OsRng);
+        primary.sign(&publisher);
+        let packed = primary.pack()?;
+        assert!(WebApp::is_chunked_state(&packed));
+        assert!(WebApp::unpack_streaming(
+            Cursor::new(packed.as_slice()),
+            tempfile::tempdir()?.path(),
+            &WebAppLimits::default()
+        )
+        .is_err());
+
+        let mut unpacked = WebApp::try_from(packed.as_slice())?;
+        assert!(unpacked.get_file("index.html").is_err());
+        let limits = WebAppLimits::default();
+        let mut tampered = chunks.clone();
+        tampered[0][0] ^= 1;
+        assert!(unpacked.assemble(&tampered, &limits).is_err());
+        assert!(unpacked.assemble(&chunks[1..], &limits).is_err());
+        let web_size = chunks.iter().map(|c| c.len() as u64).sum::<u64>();
+        let too_small = WebAppLimits {
+            max_assembled_size: web_size - 1,
+            ..limits
+        };
+        assert!(unpacked.web_chunks(&too_small).is_err());
+        assert!(unpacked.assemble(&chunks, &too_small).is_err());
+        // chunks are bounded by the max size of the web archive, not the archive they add up to
+        let largest_chunk = chunks.iter().map(|c| c.len() as u64).max().unwrap();
+        let small_chunks = WebAppLimits {
+            max_web_size: largest_chunk,
+            ..limits
+        };
+        assert!(unpacked.web_chunks(&small_chunks).is_ok());
+        let too_small = WebAppLimits {
+            max_web_size: largest_chunk - 1,
+            ..limits
+        };
+        assert!(unpacked.web_chunks(&too_small).is_err());
+
+        // reassembled on disk
+        let mut web_chunks = unpacked.web_chunks(&limits)?;
+        let mut web = Vec::new();
+        for chunk in &chunks {
+            assert!(web_chunks.next_ref().is_some());
+            web_chunks.verify(chunk)?;
+            web.extend_from_slice(chunk);
+        }
+        web_chunks.finish()?;
+        let dst = tempfile::tempdir()?;
+        unpacked.unpack_assembled(web.as_slice(), dst.path())?;
+        assert!(dst.path().join("index.html").is_file());
+
+        unpacked.assemble(&chunks, &limits)?;
+        unpacked.verify(&publisher.verifying_key())?;
+        assert_eq!(
+            unpacked.get_file("index.html")?,
+            b"<html><body>hello</body></html>"
+        );
+        Ok(())
     }
 }
@@ -0,0 +1,383 @@
+//! Content addressed cache of extracted webapps.
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use bytes::Bytes;
+use dashmap::DashMap;
+use freenet_stdlib::prelude::ContractInstanceId;
+use once_cell::sync::Lazy;
+use stretto::Cache;
+
+use super::{WebAppManifest, WebContractError};
+
+/// File holding the hash of the state currently served for a contract.
+const CURRENT_FILE: &str = "current";
+/// Extension of the directories of states being extracted.
+const PARTIAL_EXT: &str = "partial";
+
+/// Number of counters used to track the access frequency of extracted files.
+const FILE_CACHE_COUNTERS: usize = 10_000;
+/// Max bytes of extracted files kept in memory.
+const FILE_CACHE_SIZE: i64 = 64 * 1024 * 1024;
+
+/// Number of state changes observed by the node for each contract, shared by every cache
+/// in the process.
+static STATE_VERSIONS: Lazy<DashMap<ContractInstanceId, u64>> = Lazy::new(DashMap::new);
+
+/// Records that the node observed a new state for the contract, invalidating the webapp
+/// extracted from the previous one.
+pub(crate) fn state_changed(contract: &ContractInstanceId) {
+    *STATE_VERSIONS.entry(*contract).or_default() += 1;
+}
+
+/// Current version of the contract state, to validate extractions against with
+/// [`WebAppCache::set_validated`].
+pub(crate) fn state_version(contract: &ContractInstanceId) -> u64 {
+    STATE_VERSIONS.get(contract).map(|v| *v).unwrap_or_default()
+}
+
+/// Extracted webapps on disk, keyed by contract and hash of the packed state.
+///
+/// A state is only ever extracted once, the directories of states which were already
+/// extracted (e.g. before a node restart) are served as is. The most requested files are
+/// also kept in memory.
+#[derive(Clone)]
+pub struct WebAppCache {
+    root: PathBuf,
+    files: Arc<Cache<String, Bytes>>,
+    /// Extractions of the latest state known to the node, with the state version they
+    /// were validated at.
+    validated: Arc<DashMap<ContractInstanceId, (CachedWebApp, u64)>>,
+}
+
+impl fmt::Debug for WebAppCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebAppCache")
+            .field("root", &self.root)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for WebAppCache {
+    fn default() -> Self {
+        Self::new(std::env::temp_dir().join("freenet").join("webapp_cache"))
+    }
+}
+
+/// A packed webapp state extracted in the [`WebAppCache`].
+#[derive(Debug, Clone)]
+pub struct CachedWebApp {
+    dir: PathBuf,
+}
+
+impl CachedWebApp {
+    /// Hex encoded hash of the packed state the files were extracted from.
+    pub fn state_hash(&self) -> &str {
+        self.dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default()
+    }
+
+    /// Directory holding the files of the web archive.
+    pub fn files_dir(&self) -> PathBuf {
+        self.dir.join("files")
+    }
+
+    pub fn manifest_path(&self) -> PathBuf {
+        self.dir.join("manifest.json")
+    }
+
+    /// Path of the JSON listing of the web archive, only present if the manifest allows it.
+    pub fn listing_path(&self) -> PathBuf {
+        self.dir.join("listing.json")
+    }
+
+    /// Reads the manifest stored alongside the extracted files.
+    pub async fn manifest(&self) -> Result<WebAppManifest, WebContractError> {
+        let metadata = tokio::fs::read(self.manifest_path())
+            .await
+            .map_err(WebContractError::StoringError)?;
+        WebAppManifest::from_metadata(&metadata)
+    }
+}
+
+impl WebAppCache {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            files: Arc::new(
+                Cache::new(FILE_CACHE_COUNTERS, FILE_CACHE_SIZE)
+                    .expect("failed to build extracted file cache"),
+            ),
+            validated: Arc::default(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the given state of the contract if it was already extracted, marking it
+    /// as the state currently served.
+    pub async fn get(
+        &self,
+        contract: &ContractInstanceId,
+        state: &[u8],
+    ) -> Result<Option<CachedWebApp>, WebContractError> {
+        let hash = blake3::hash(state).to_hex();
+        let dir = self.contract_dir(contract).join(hash.as_str());
+        if !is_dir(&dir).await {
+            return Ok(None);
+        }
+        tokio::fs::write(self.current_file(contract), hash.as_str())
+            .await
+            .map_err(WebContractError::StoringError)?;
+        Ok(Some(CachedWebApp { dir }))
+    }
+
+    /// The state of the contract currently served, if any was extracted.
+    pub async fn current(&self, contract: &ContractInstanceId) -> Option<CachedWebApp> {
+        let hash = tokio::fs::read_to_string(self.current_file(contract))
+            .await
+            .ok()?;
+        let dir = self.contract_dir(contract).join(hash.trim());
+        is_dir(&dir).await.then_some(CachedWebApp { dir })
+    }
+
+    /// The extracted state of the contract if no state change was observed since it was
+    /// validated, so it can be served without fetching the state again.
+    pub async fn validated(&self, contract: &ContractInstanceId) -> Option<CachedWebApp> {
+        let (cached, version) = self.validated.get(contract)?.value().clone();
+        (version == state_version(contract) && is_dir(&cached.dir).await).then_some(cached)
+    }
+
+    /// Marks the extraction as holding the latest state of the contract as of `version`,
+    /// which must be read before fetching the state.
+    pub fn set_validated(
+        &self,
+        contract: &ContractInstanceId,
+        cached: &CachedWebApp,
+        version: u64,
+    ) {
+        self.validated.insert(*contract, (cached.clone(), version));
+    }
+
+    /// Reads an extracted file, keeping it in memory for subsequent reads.
+    ///
+    /// Files are keyed by contract, state hash and path, so files of previous states are
+    /// never served and are evicted as they stop being requested.
+    pub async fn read_file(&self, cached: &CachedWebApp, path: &str) -> std::io::Result<Bytes> {
+        let file_path = cached.files_dir().join(path);
+        let key = file_path.to_string_lossy().into_owned();
+        let cached_contents = self.files.get(&key).map(|v| v.value().clone());
+        if let Some(contents) = cached_contents {
+            return Ok(contents);
+        }
+        let contents = Bytes::from(tokio::fs::read(&file_path).await?);
+        let cost = contents.len() as i64;
+        self.files.insert(key, contents.clone(), cost);
+        Ok(contents)
+    }
+
+    /// Extracts the given state of the contract and marks it as the state currently served,
+    /// removing any state of the contract extracted previously.
+    ///
+    /// `unpack` receives the directory to extract the web archive to and returns the webapp
+    /// metadata. The state is only visible in the cache once fully extracted. Each extraction
+    /// goes to its own directory, so concurrent extractions of a state don't interfere, the
+    /// first one to complete being kept.
+    ///
+    /// Blocks on file system operations.
+    pub fn insert(
+        &self,
+        contract: &ContractInstanceId,
+        state: &[u8],
+        unpack: impl FnOnce(&Path) -> Result<Vec<u8>, WebContractError>,
+    ) -> Result<CachedWebApp, WebContractError> {
+        let hash = blake3::hash(state).to_hex();
+        let contract_dir = self.contract_dir(contract);
+        let partial = CachedWebApp {
+            dir: contract_dir.join(format!(
+                "{hash}.{:016x}.{PARTIAL_EXT}",
+                rand::random::<u64>()
+            )),
+        };
+        std::fs::create_dir_all(partial.files_dir()).map_err(WebContractError::StoringError)?;
+        let extracted = unpack(&partial.files_dir()).and_then(|metadata| {
+            let manifest = WebAppManifest::from_metadata(&metadata)?;
+            std::fs::write(partial.manifest_path(), manifest.to_metadata())
+                .map_err(WebContractError::StoringError)
+        });
+        if let Err(err) = extracted {
+            let _ = std::fs::remove_dir_all(&partial.dir);
+            return Err(err);
+        }
+
+        let dir = contract_dir.join(hash.as_str());
+        if let Err(err) = std::fs::rename(&partial.dir, &dir) {
+            let _ = std::fs::remove_dir_all(&partial.dir);
+            // fails if the state was already extracted concurrently
+            if !dir.is_dir() {
+                return Err(WebContractError::StoringError(err));
+            }
+        }
+        std::fs::write(self.current_file(contract), hash.as_str())
+            .map_err(WebContractError::StoringError)?;
+
+        for entry in std::fs::read_dir(&contract_dir)
+            .map_err(WebContractError::StoringError)?
+            .flatten()
+        {
+            let path = entry.path();
+            let stale = path.is_dir()
+                && path != dir
+                && path.extension() != Some(std::ffi::OsStr::new(PARTIAL_EXT));
+            if stale {
+                tracing::debug!("Removing stale webapp extraction {path:?}");
+                let _ = std::fs::remove_dir_all(path);
+            }
+        }
+        Ok(CachedWebApp { dir })
+    }
+
+    /// Path of a new file to reassemble a chunked web archive of the contract in, before
+    /// extracting it. The file must be removed by the caller once done with it.
+    pub async fn scratch_file(
+        &self,
+        contract: &ContractInstanceId,
+    ) -> Result<PathBuf, WebContractError> {
+        let contract_dir = self.contract_dir(contract);
+        tokio::fs::create_dir_all(&contract_dir)
+            .await
+            .map_err(WebContractError::StoringError)?;
+        Ok(contract_dir.join(format!("{:016x}.web.{PARTIAL_EXT}", rand::random::<u64>())))
+    }
+
+    fn contract_dir(&self, contract: &ContractInstanceId) -> PathBuf {
+        self.root.join(contract.encode())
+    }
+
+    fn current_file(&self, contract: &ContractInstanceId) -> PathBuf {
+        self.contract_dir(contract).join(CURRENT_FILE)
+    }
+}
+
+async fn is_dir(path: &Path) -> bool {
+    tokio::fs::metadata(path)
+        .await
+        .is_ok_and(|metadata| metadata.is_dir())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn extract_once() -> Result<(), Box<dyn std::error::Error>> {
+        let root = tempfile::tempdir()?;
+        let cache = WebAppCache::new(root.path());
+        let contract = ContractInstanceId::new([1; 32]);
+        assert!(cache.get(&contract, b"v1").await?.is_none());
+        assert!(cache.current(&contract).await.is_none());
+
+        let unpack = |dir: &Path| {
+            std::fs::write(dir.join("index.html"), b"<html></html>")
+                .map_err(WebContractError::StoringError)?;
+            Ok::<_, WebContractError>(br#"{ "spa-fallback": "index.html" }"#.to_vec())
+        };
+        let v1 = cache.insert(&contract, b"v1", unpack)?;
+        assert!(v1.files_dir().join("index.html").is_file());
+        assert_eq!(
+            v1.manifest().await?.spa_fallback.as_deref(),
+            Some("index.html")
+        );
+
+        // a new cache over the same directory, as after a node restart
+        let cache = WebAppCache::new(root.path());
+        let cached = cache
+            .get(&contract, b"v1")
+            .await?
+            .ok_or("state not cached")?;
+        assert_eq!(cached.files_dir(), v1.files_dir());
+        assert_eq!(cached.state_hash(), blake3::hash(b"v1").to_hex().as_str());
+
+        let v2 = cache.insert(&contract, b"v2", unpack)?;
+        assert_eq!(
+            cache.current(&contract).await.map(|c| c.files_dir()),
+            Some(v2.files_dir())
+        );
+        assert!(!v1.files_dir().exists());
+        Ok(())
+    }
+
+    #[test]
+    fn concurrent_extractions() -> Result<(), Box<dyn std::error::Error>> {
+        let root = tempfile::tempdir()?;
+        let cache = WebAppCache::new(root.path());
+        let contract = ContractInstanceId::new([3; 32]);
+        let unpack = |dir: &Path| {
+            std::fs::write(dir.join("index.html"), b"<html></html>")
+                .map_err(WebContractError::StoringError)?;
+            Ok::<_, WebContractError>(b"{}".to_vec())
+        };
+        // an extraction of the same state completes while this one is in progress
+        let cached = cache.insert(&contract, b"v1", |dir| {
+            let concurrent = cache.insert(&contract, b"v1", unpack)?;
+            assert_ne!(concurrent.files_dir(), dir);
+            unpack(dir)
+        })?;
+        assert!(cached.files_dir().join("index.html").is_file());
+
+        // failed extractions leave nothing behind
+        assert!(cache
+            .insert(&contract, b"v2", |_| Err(
+                WebContractError::InvalidSignature("failed".into())
+            ))
+            .is_err());
+        let extractions = std::fs::read_dir(cache.contract_dir(&contract))?
+            .flatten()
+            .filter(|entry| entry.path().is_dir())
+            .count();
+        assert_eq!(extractions, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn validated_until_state_changes() -> Result<(), Box<dyn std::error::Error>> {
+        let root = tempfile::tempdir()?;
+        let cache = WebAppCache::new(root.path());
+        let contract = ContractInstanceId::new([2; 32]);
+        let version = state_version(&contract);
+        let cached = cache.insert(&contract, b"v1", |dir| {
+            std::fs::write(dir.join("index.html"), b"<html></html>")
+                .map_err(WebContractError::StoringError)?;
+            Ok(b"{}".to_vec())
+        })?;
+        assert!(cache.validated(&contract).await.is_none());
+        cache.set_validated(&contract, &cached, version);
+        assert_eq!(
+            cache.validated(&contract).await.map(|c| c.files_dir()),
+            Some(cached.files_dir())
+        );
+
+        assert_eq!(
+            cache.read_file(&cached, "index.html").await?.as_ref(),
+            b"<html></html>"
+        );
+        cache.files.wait()?;
+        // served from memory once read
+        std::fs::remove_file(cached.files_dir().join("index.html"))?;
+        assert_eq!(
+            cache.read_file(&cached, "index.html").await?.as_ref(),
+            b"<html></html>"
+        );
+
+        state_changed(&contract);
+        assert!(cache.validated(&contract).await.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+//! Structured webapp metadata, telling the HTTP gateway how to serve the packed files.
+use std::{
+    collections::HashMap,
+    path::{Component, Path},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::WebContractError;
+
+/// Webapp manifest, stored as JSON in the metadata section of a packed [`WebApp`](super::WebApp).
+///
+/// Per file settings are keyed either by the path of the file within the archive
+/// (e.g. `assets/app.wasm`) or by an extension pattern (e.g. `*.wasm`), exact paths
+/// take precedence.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct WebAppManifest {
+    /// Content types to serve files with, instead of guessing them from the extension.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub content_types: HashMap<String, String>,
+    /// Content coding (e.g. `br` or `gzip`) of files stored already compressed in the archive,
+    /// which are served as is with the matching `Content-Encoding`.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub content_encodings: HashMap<String, String>,
+    /// `Cache-Control` header values to serve files with.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub cache_control: HashMap<String, String>,
+    /// File served for any path not present in the archive, for single page applications
+    /// which handle routing client side. Must be a relative path within the archive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spa_fallback: Option<String>,
+    /// Allow the gateway to serve a JSON listing of the files in the archive.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub directory_listing: bool,
+    /// Files served instead of the gateway error for a status code, e.g. `404` for
+    /// paths missing from the archive or `500` for files which could not be read.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub error_pages: HashMap<u16, String>,
+    /// Have nodes apply the deltas produced by [`WebApp::diff`](super::WebApp::diff) to the
+    /// state before handing the update to the contract, which gets the full new state.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub binary_deltas: bool,
+}
+
+impl WebAppManifest {
+    /// Parses the manifest from the webapp metadata.
+    ///
+    /// Metadata which is not a JSON object is treated as opaque application data,
+    /// in which case the default manifest is returned.
+    pub fn from_metadata(metadata: &[u8]) -> Result<Self, WebContractError> {
+        if metadata.trim_ascii_start().first() != Some(&b'{') {
+            return Ok(Self::default());
+        }
+        let manifest: Self = serde_json::from_slice(metadata).map_err(|e| {
+            WebContractError::UnpackingError(anyhow::anyhow!("invalid webapp manifest: {e}"))
+        })?;
+        if let Some(fallback) = &manifest.spa_fallback {
+            if !is_within_archive(fallback) {
+                return Err(WebContractError::UnpackingError(anyhow::anyhow!(
+                    "invalid webapp manifest: SPA fallback `{fallback}` outside of the archive"
+                )));
+            }
+        }
+        Ok(manifest)
+    }
+
+    pub fn to_metadata(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("infallible serialization")
+    }
+
+    /// Content type override for the file at `path`, if any.
+    pub fn content_type(&self, path: &str) -> Option<&str> {
+        lookup(&self.content_types, path)
+    }
+
+    /// Content coding the file at `path` is stored with, if precompressed.
+    pub fn content_encoding(&self, path: &str) -> Option<&str> {
+        lookup(&self.content_encodings, path)
+    }
+
+    /// Path of the page to serve for the error `status`, if any.
+    ///
+    /// Pages outside of the archive are ignored.
+    pub fn error_page(&self, status: u16) -> Option<&str> {
+        let page = self.error_pages.get(&status)?.trim_start_matches('/');
+        (!page.split('/').any(|segment| segment == "..")).then_some(page)
+    }
+
+    /// `Cache-Control` hint for the file at `path`, if any.
+    pub fn cache_control(&self, path: &str) -> Option<&str> {
+        lookup(&self.cache_control, path)
+    }
+}
+
+/// Whether `path` is relative and stays within the directory it is joined to.
+fn is_within_archive(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+fn lookup<'a>(entries: &'a HashMap<String, String>, path: &str) -> Option<&'a str> {
+    let path = path.trim_start_matches('/');
+    entries
+        .get(path)
+        .or_else(|| {
+            let (_, ext) = path.rsplit_once('.')?;
+            entries.get(&format!("*.{ext}"))
+        })
+        .map(String::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_manifest() -> Result<(), Box<dyn std::error::Error>> {
+        let manifest = WebAppManifest::from_metadata(
+            br#"{
+                "content-types": { "*.wasm": "application/wasm", "data/feed": "application/rss+xml" },
+                "content-encodings": { "*.wasm": "br" },
+                "cache-control": { "*.js": "max-age=3600", "index.html": "no-cache" },
+                "spa-fallback": "index.html",
+                "directory-listing": true,
+                "binary-deltas": true,
+                "error-pages": { "404": "/errors/404.html", "500": "../secret" }
+            }"#,
+        )?;
+        assert_eq!(
+            manifest.content_type("/app/main.wasm"),
+            Some("application/wasm")
+        );
+        assert_eq!(
+            manifest.content_type("data/feed"),
+            Some("application/rss+xml")
+        );
+        assert_eq!(manifest.content_type("main.js"), None);
+        assert_eq!(manifest.content_encoding("app/main.wasm"), Some("br"));
+        assert_eq!(manifest.cache_control("index.html"), Some("no-cache"));
+        assert_eq!(manifest.spa_fallback.as_deref(), Some("index.html"));
+        assert!(manifest.directory_listing);
+        assert!(manifest.binary_deltas);
+        assert_eq!(manifest.error_page(404), Some("errors/404.html"));
+        assert_eq!(manifest.error_page(500), None);
+        assert_eq!(manifest.error_page(403), None);
+        assert_eq!(
+            WebAppManifest::from_metadata(&manifest.to_metadata())?,
+            manifest
+        );
+
+        assert_eq!(
+            WebAppManifest::from_metadata(b"opaque")?,
+            WebAppManifest::default()
+        );
+        assert!(WebAppManifest::from_metadata(b"{ not json").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn reject_fallback_outside_of_archive() {
+        for fallback in ["/etc/passwd", "../../x", "app/../../x"] {
+            let metadata = WebAppManifest {
+                spa_fallback: Some(fallback.to_owned()),
+                ..Default::default()
+            }
+            .to_metadata();
+            assert!(
+                WebAppManifest::from_metadata(&metadata).is_err(),
+                "{fallback} accepted"
+            );
+        }
+        let metadata = br#"{ "spa-fallback": "./app/index.html" }"#;
+        assert!(WebAppManifest::from_metadata(metadata).is_ok());
+    }
+}
@@ -0,0 +1,76 @@
+//! Cross origin access policy of the HTTP gateway and websocket API.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Router,
+};
+use tower_http::cors::{AllowHeaders, AllowOrigin, CorsLayer};
+
+use crate::config::CorsConfig;
+
+/// Applies the CORS policy to the router.
+///
+/// Browsers do not enforce CORS on websocket connections, so besides answering preflight
+/// requests, any request from an origin which is neither the gateway itself nor allowed
+/// by the policy is rejected.
+pub(super) fn with_cors(router: Router, cors: CorsConfig) -> Router {
+    let cors = Arc::new(cors);
+    let policy = cors.clone();
+    let cors_layer = CorsLayer::new()
+        .allow_origin(AllowOrigin::predicate(move |origin, _| {
+            origin
+                .to_str()
+                .is_ok_and(|origin| policy.is_allowed(origin))
+        }))
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_headers(AllowHeaders::mirror_request())
+        .allow_credentials(cors.allow_credentials);
+    router
+        .layer(cors_layer)
+        .layer(axum::middleware::from_fn_with_state(cors, enforce_origin))
+}
+
+async fn enforce_origin(State(cors): State<Arc<CorsConfig>>, req: Request, next: Next) -> Response {
+    if let Some(origin) = req.headers().get(header::ORIGIN) {
+        let allowed = origin
+            .to_str()
+            .is_ok_and(|origin| is_same_origin(origin, req.headers()) || cors.is_allowed(origin));
+        if !allowed {
+            tracing::debug!(?origin, uri = ?req.uri(), "Rejecting cross origin request");
+            return (StatusCode::FORBIDDEN, "Origin not allowed").into_response();
+        }
+    }
+    next.run(req).await
+}
+
+/// Whether the origin is the gateway itself, as for the webapps served through it.
+fn is_same_origin(origin: &str, headers: &HeaderMap) -> bool {
+    let Some(host) = headers.get(header::HOST).and_then(|h| h.to_str().ok()) else {
+        return false;
+    };
+    origin
+        .split_once("://")
+        .is_some_and(|(_, authority)| authority.eq_ignore_ascii_case(host))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn same_origin() {
+        let mut headers = HeaderMap::new();
+        assert!(!is_same_origin("http://127.0.0.1:50509", &headers));
+        headers.insert(header::HOST, HeaderValue::from_static("127.0.0.1:50509"));
+        assert!(is_same_origin("http://127.0.0.1:50509", &headers));
+        assert!(!is_same_origin("http://127.0.0.1:8080", &headers));
+        assert!(!is_same_origin("127.0.0.1:50509", &headers));
+    }
+}
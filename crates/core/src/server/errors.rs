@@ -1,9 +1,12 @@
 use axum::http::StatusCode;
-use axum::response::{Html, IntoResponse, Response};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
 use freenet_stdlib::client_api::ErrorKind;
 use freenet_stdlib::prelude::ContractKey;
 use std::fmt::{Display, Formatter};
 
+use crate::client_events::error_details::{ErrorCode, ErrorDetails, ErrorPhase};
+
 #[derive(Debug)]
 pub(super) enum WebSocketApiError {
     /// Something went wrong when calling the user repo.
@@ -19,15 +22,57 @@ pub(super) enum WebSocketApiError {
     MissingContract {
         key: ContractKey,
     },
+    Unauthorized {
+        error_cause: String,
+    },
 }
 
 impl WebSocketApiError {
     pub fn status_code(&self) -> StatusCode {
+        match self.code() {
+            ErrorCode::InvalidRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Rejected => StatusCode::FORBIDDEN,
+            ErrorCode::ContractNotFound | ErrorCode::DelegateNotFound => StatusCode::NOT_FOUND,
+            ErrorCode::MissingRelatedContract | ErrorCode::ContractFailed => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            ErrorCode::NodeUnavailable | ErrorCode::Shutdown => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn code(&self) -> ErrorCode {
+        self.details().code
+    }
+
+    /// Details of the error answered to clients, its message being the cause of the error.
+    pub fn details(&self) -> ErrorDetails {
         match self {
-            WebSocketApiError::InvalidParam { .. } => StatusCode::BAD_REQUEST,
-            WebSocketApiError::NodeError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
-            WebSocketApiError::AxumError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
-            WebSocketApiError::MissingContract { .. } => StatusCode::NOT_FOUND,
+            WebSocketApiError::InvalidParam { error_cause } => {
+                ErrorDetails::new(ErrorCode::InvalidRequest, error_cause)
+                    .with_phase(ErrorPhase::Validation)
+            }
+            WebSocketApiError::NodeError { error_cause }
+                if error_cause.starts_with("Contract not found") =>
+            {
+                ErrorDetails::new(ErrorCode::ContractNotFound, error_cause)
+            }
+            WebSocketApiError::NodeError { error_cause } => {
+                ErrorDetails::new(ErrorCode::Internal, error_cause)
+            }
+            WebSocketApiError::AxumError { error } => {
+                ErrorDetails::of_kind(error, format!("{error}"))
+            }
+            WebSocketApiError::MissingContract { key } => {
+                ErrorDetails::new(ErrorCode::ContractNotFound, self.error_message())
+                    .with_contract(key.id())
+            }
+            WebSocketApiError::Unauthorized { error_cause } => {
+                ErrorDetails::new(ErrorCode::Unauthorized, error_cause)
+                    .with_phase(ErrorPhase::Validation)
+            }
         }
     }
 
@@ -39,6 +84,9 @@ impl WebSocketApiError {
             WebSocketApiError::NodeError { error_cause } => format!("Node error: {}", error_cause),
             WebSocketApiError::AxumError { error } => format!("Server error: {}", error),
             WebSocketApiError::MissingContract { key } => format!("Missing contract {key}"),
+            WebSocketApiError::Unauthorized { error_cause } => {
+                format!("Unauthorized: {}", error_cause)
+            }
         }
     }
 }
@@ -51,35 +99,12 @@ impl Display for WebSocketApiError {
 
 impl From<WebSocketApiError> for Response {
     fn from(error: WebSocketApiError) -> Self {
-        let body = Html(error.error_message());
-        (error.status_code(), body).into_response()
+        error.into_response()
     }
 }
 
 impl IntoResponse for WebSocketApiError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            WebSocketApiError::InvalidParam { error_cause } => {
-                (StatusCode::BAD_REQUEST, error_cause)
-            }
-            WebSocketApiError::NodeError { error_cause }
-                if error_cause.starts_with("Contract not found") =>
-            {
-                (StatusCode::NOT_FOUND, error_cause)
-            }
-            WebSocketApiError::NodeError { error_cause } => {
-                (StatusCode::INTERNAL_SERVER_ERROR, error_cause)
-            }
-            err @ WebSocketApiError::MissingContract { .. } => {
-                (StatusCode::NOT_FOUND, err.error_message())
-            }
-            WebSocketApiError::AxumError { error } => {
-                (StatusCode::INTERNAL_SERVER_ERROR, format!("{error}"))
-            }
-        };
-
-        let body = Html(error_message);
-
-        (status, body).into_response()
+        (self.status_code(), Json(self.details())).into_response()
     }
 }
@@ -0,0 +1,148 @@
+//! Attribution of requests relayed by trusted reverse proxies to the actual client, based on
+//! the `X-Forwarded-*` headers.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+    Router,
+};
+use ipnet::IpNet;
+
+static X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+static X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+static X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+
+/// Scheme the client used to reach a trusted proxy in front of the gateway.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ForwardedProto(pub String);
+
+impl ForwardedProto {
+    pub fn is_https(&self) -> bool {
+        self.0.eq_ignore_ascii_case("https")
+    }
+}
+
+struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    fn contains(&self, addr: &IpAddr) -> bool {
+        self.0.iter().any(|net| net.contains(addr))
+    }
+
+    /// The client the request was forwarded for, the closest address which is not a trusted
+    /// proxy itself.
+    fn client(&self, headers: &HeaderMap) -> Option<IpAddr> {
+        let addrs = headers
+            .get_all(&X_FORWARDED_FOR)
+            .iter()
+            .map(|v| v.to_str().ok())
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .flat_map(|v| v.split(','))
+            .map(|addr| addr.trim().parse::<IpAddr>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        addrs
+            .iter()
+            .rev()
+            .find(|addr| !self.contains(addr))
+            .or(addrs.first())
+            .copied()
+    }
+}
+
+/// Serves requests from trusted proxies as if they came straight from the client, so rate
+/// limits, logs and the origin checks apply to it.
+///
+/// The client address replaces the [`ConnectInfo`] of the request, the forwarded host its
+/// `Host` header, and the forwarded scheme is available as [`ForwardedProto`].
+pub(super) fn with_trusted_proxies(router: Router, proxies: Vec<IpNet>) -> Router {
+    router.layer(axum::middleware::from_fn_with_state(
+        Arc::new(TrustedProxies(proxies)),
+        forwarded,
+    ))
+}
+
+async fn forwarded(
+    State(proxies): State<Arc<TrustedProxies>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let Some(ConnectInfo(peer)) = req.extensions().get::<ConnectInfo<SocketAddr>>().copied() else {
+        return next.run(req).await;
+    };
+    if !proxies.contains(&peer.ip()) {
+        return next.run(req).await;
+    }
+    if let Some(client) = proxies.client(req.headers()) {
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::new(client, 0)));
+    }
+    if let Some(host) = last_value(req.headers(), &X_FORWARDED_HOST) {
+        req.headers_mut().insert(header::HOST, host);
+    }
+    if let Some(proto) = last_value(req.headers(), &X_FORWARDED_PROTO)
+        .and_then(|v| v.to_str().ok().map(str::to_owned))
+    {
+        req.extensions_mut().insert(ForwardedProto(proto));
+    }
+    next.run(req).await
+}
+
+/// The value set by the proxy closest to the gateway, for headers proxies overwrite.
+fn last_value(headers: &HeaderMap, name: &HeaderName) -> Option<HeaderValue> {
+    let value = headers.get_all(name).iter().last()?.to_str().ok()?;
+    let last = value.rsplit(',').next()?.trim();
+    HeaderValue::from_str(last).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwarded_client() {
+        let proxies = TrustedProxies(vec![
+            "10.0.0.0/8".parse().unwrap(),
+            "127.0.0.1/32".parse().unwrap(),
+        ]);
+        let mut headers = HeaderMap::new();
+        assert_eq!(proxies.client(&headers), None);
+
+        headers.insert(
+            &X_FORWARDED_FOR,
+            HeaderValue::from_static("203.0.113.7, 198.51.100.1, 10.0.0.2"),
+        );
+        // addresses left of the first untrusted one may be spoofed by the client
+        assert_eq!(proxies.client(&headers), Some([198, 51, 100, 1].into()));
+
+        headers.insert(&X_FORWARDED_FOR, HeaderValue::from_static("10.0.0.3"));
+        assert_eq!(proxies.client(&headers), Some([10, 0, 0, 3].into()));
+
+        headers.insert(&X_FORWARDED_FOR, HeaderValue::from_static("unknown"));
+        assert_eq!(proxies.client(&headers), None);
+    }
+
+    #[test]
+    fn forwarded_host() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            &X_FORWARDED_HOST,
+            HeaderValue::from_static("spoofed.example"),
+        );
+        headers.append(
+            &X_FORWARDED_HOST,
+            HeaderValue::from_static("a.example, gateway.example"),
+        );
+        assert_eq!(
+            last_value(&headers, &X_FORWARDED_HOST),
+            Some(HeaderValue::from_static("gateway.example"))
+        );
+    }
+}
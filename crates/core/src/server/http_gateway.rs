@@ -1,12 +1,15 @@
 use std::collections::HashMap;
-use std::net::{IpAddr, SocketAddr};
+use std::net::SocketAddr;
 use std::sync::{Arc, RwLock};
 
 use axum::extract::Path;
 use axum::response::IntoResponse;
 use axum::routing::get;
 use axum::{Extension, Router};
-use freenet_stdlib::client_api::{ClientError, ErrorKind, HostResponse};
+use ed25519_dalek::VerifyingKey;
+use freenet_stdlib::client_api::{
+    ClientError, ClientRequest, ContractRequest, ErrorKind, HostResponse,
+};
 use freenet_stdlib::prelude::ContractInstanceId;
 use futures::future::BoxFuture;
 use futures::FutureExt;
@@ -16,12 +19,18 @@ use tracing::instrument;
 use crate::client_events::{ClientEventsProxy, ClientId, OpenRequest};
 use crate::server::HostCallbackResult;
 
-use super::{errors::WebSocketApiError, path_handlers, AuthToken, ClientConnection};
+use super::{
+    errors::WebSocketApiError, path_handlers, path_handlers::ResponseCompression, AuthToken,
+    ClientConnection, WebAppCache, WebAppLimits,
+};
 
+#[cfg(feature = "grpc")]
+mod grpc;
+mod rest;
 mod v1;
 
 #[derive(Clone)]
-pub(super) struct HttpGatewayRequest(mpsc::Sender<ClientConnection>);
+pub(super) struct HttpGatewayRequest(pub(super) mpsc::Sender<ClientConnection>);
 
 impl std::ops::Deref for HttpGatewayRequest {
     type Target = mpsc::Sender<ClientConnection>;
@@ -33,6 +42,20 @@ impl std::ops::Deref for HttpGatewayRequest {
 
 pub type AttestedContractMap = Arc<RwLock<HashMap<AuthToken, (ContractInstanceId, ClientId)>>>;
 
+/// Trusted webapp publishers, the gateway only serves states of those contracts if signed by them.
+pub type PublisherKeys = Arc<HashMap<ContractInstanceId, VerifyingKey>>;
+
+/// Rules applied by the gateway before serving a webapp.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct WebAppPolicy {
+    pub publisher_keys: PublisherKeys,
+    pub limits: WebAppLimits,
+    /// Where webapps are extracted to before being served.
+    pub cache: WebAppCache,
+    /// Compression of the served webapp files.
+    pub compression: ResponseCompression,
+}
+
 /// A gateway to access and interact with contracts through an HTTP interface.
 pub(crate) struct HttpGateway {
     pub attested_contracts: AttestedContractMap,
@@ -44,21 +67,44 @@ impl HttpGateway {
     /// Returns the uninitialized axum router to compose with other routing handling or websockets.
     pub fn as_router(socket: &SocketAddr) -> (Self, Router) {
         let attested_contracts = Arc::new(RwLock::new(HashMap::new()));
-        Self::as_router_with_attested_contracts(socket, attested_contracts)
+        // local nodes don't connect to other peers
+        Self::as_router_with_attested_contracts(
+            socket,
+            attested_contracts,
+            Default::default(),
+            0,
+            false,
+        )
     }
 
-    /// Returns the uninitialized axum router with a provided attested_contracts map.
+    /// Returns the uninitialized axum router with a provided attested_contracts map, `tls`
+    /// telling whether the gateway is served over TLS.
     pub fn as_router_with_attested_contracts(
         socket: &SocketAddr,
         attested_contracts: AttestedContractMap,
+        webapp_policy: WebAppPolicy,
+        ready_min_peers: usize,
+        tls: bool,
     ) -> (Self, Router) {
-        Self::create_router_v1_with_attested_contracts(socket, attested_contracts)
+        Self::create_router_v1_with_attested_contracts(
+            socket,
+            attested_contracts,
+            webapp_policy,
+            ready_min_peers,
+            tls,
+        )
     }
 }
 
 #[derive(Clone, Debug)]
 struct Config {
-    localhost: bool,
+    /// Whether the gateway is bound to a loopback address.
+    loopback: bool,
+    /// Whether the gateway is served over TLS.
+    tls: bool,
+    webapp_policy: WebAppPolicy,
+    /// Peers the node must be connected to before reporting itself as ready.
+    ready_min_peers: usize,
 }
 
 #[instrument(level = "debug")]
@@ -66,6 +112,46 @@ async fn home() -> axum::response::Response {
     axum::response::Response::default()
 }
 
+/// Liveness probe, answers as long as the node process is able to serve requests.
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// Readiness probe, the node is ready once the contract executor is initialized
+/// and it is connected to enough peers.
+async fn readyz(
+    axum::extract::State(config): axum::extract::State<Config>,
+) -> axum::response::Response {
+    let executor_ready = crate::metrics::executor_ready();
+    let connected_peers = crate::metrics::connected_peers();
+    let status = if executor_ready && connected_peers >= config.ready_min_peers {
+        axum::http::StatusCode::OK
+    } else {
+        axum::http::StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = serde_json::json!({
+        "executor-ready": executor_ready,
+        "connected-peers": connected_peers,
+        "min-peers": config.ready_min_peers,
+    });
+    (status, axum::Json(body)).into_response()
+}
+
+/// Node metrics in the Prometheus text format.
+async fn metrics() -> axum::response::Response {
+    if let Some(node) = crate::node::admin::NodeHandle::running() {
+        crate::metrics::set_peer_stats(&node.transport_stats());
+    }
+    (
+        [(
+            axum::http::header::CONTENT_TYPE,
+            crate::metrics::CONTENT_TYPE,
+        )],
+        crate::metrics::encode_metrics(),
+    )
+        .into_response()
+}
+
 impl ClientEventsProxy for HttpGateway {
     #[instrument(level = "debug", skip(self))]
     fn recv(&mut self) -> BoxFuture<Result<OpenRequest<'static>, ClientError>> {
@@ -75,11 +161,16 @@ impl ClientEventsProxy for HttpGateway {
                     ClientConnection::NewConnection {
                         callbacks,
                         assigned_token,
+                        ..
                     } => {
                         let cli_id = ClientId::next();
-                        callbacks
+                        if callbacks
                             .send(HostCallbackResult::NewId { id: cli_id })
-                            .map_err(|_e| ErrorKind::NodeUnavailable)?;
+                            .is_err()
+                        {
+                            tracing::debug!("client: {cli_id} went away before registering");
+                            continue;
+                        }
                         if let Some((assigned_token, contract)) = assigned_token {
                             self.attested_contracts
                                 .write()
@@ -100,10 +191,39 @@ impl ClientEventsProxy for HttpGateway {
                         req,
                         auth_token,
                         attested_contract,
+                        ..
                     } => {
-                        return Ok(OpenRequest::new(client_id, req)
+                        if matches!(*req, ClientRequest::Disconnect { .. }) {
+                            // the node doesn't answer disconnections
+                            self.response_channels.remove(&client_id);
+                        }
+                        let mut open_req = OpenRequest::new(client_id, req);
+                        if let ClientRequest::ContractOp(ContractRequest::Subscribe {
+                            key, ..
+                        }) = &*open_req.request
+                        {
+                            // subscriptions made through the REST API require a notification channel
+                            let (tx, rx) = mpsc::unbounded_channel();
+                            let Some(ch) = self.response_channels.get(&client_id) else {
+                                tracing::warn!("client: {client_id} not found");
+                                continue;
+                            };
+                            if ch
+                                .send(HostCallbackResult::SubscriptionChannel {
+                                    key: *key,
+                                    id: client_id,
+                                    callback: rx,
+                                })
+                                .is_err()
+                            {
+                                tracing::debug!("client: {client_id} went away");
+                                continue;
+                            }
+                            open_req = open_req.with_notification(tx);
+                        }
+                        return Ok(open_req
                             .with_token(auth_token)
-                            .with_attested_contract(attested_contract))
+                            .with_attested_contract(attested_contract));
                     }
                 }
             }
@@ -0,0 +1,427 @@
+//! gRPC service offering the operations on contracts of the websocket API, so backend services
+//! can integrate with the node through stubs generated from `schemas/proto/client_api.proto`.
+//!
+//! The service is served by the HTTP gateway next to the REST API, going through the same
+//! authentication and limits, over HTTP/2 connections.
+
+use std::sync::Arc;
+
+use freenet_stdlib::client_api::ContractResponse;
+use freenet_stdlib::prelude::{
+    ContractCode, ContractContainer, ContractKey, ContractWasmAPIVersion, Parameters,
+    RelatedContracts, State, StateDelta, StateSummary, UpdateData, WrappedContract, WrappedState,
+};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use tonic::metadata::MetadataValue;
+use tonic::server::NamedService;
+use tonic::{Code, Extensions, Request, Response, Status};
+
+use crate::client_events::error_details::ErrorCode;
+use crate::client_events::HostResult;
+use crate::server::{ApiScope, ApiTokens};
+
+use super::rest::{authorize, parse_key, RestClient};
+use super::*;
+
+mod proto {
+    tonic::include_proto!("freenet.client.v1");
+}
+
+use proto::client_api_server::{ClientApi, ClientApiServer};
+use proto::{
+    update_notification, update_request, GetRequest, GetResponse, PutRequest, PutResponse,
+    SubscribeRequest, UpdateNotification, UpdateRequest, UpdateResponse,
+};
+
+/// Routes of the gRPC service.
+pub(super) fn routes() -> Router {
+    Router::new().route_service(
+        &format!("/{}/*rpc", ClientApiServer::<GrpcApi>::NAME),
+        ClientApiServer::new(GrpcApi),
+    )
+}
+
+/// Metadata of failed calls carrying the details of the error as JSON (see
+/// [`crate::client_events::error_details`]).
+const ERROR_DETAILS: &str = "freenet-error-details";
+
+impl From<WebSocketApiError> for Status {
+    fn from(err: WebSocketApiError) -> Self {
+        let details = err.details();
+        let code = match details.code {
+            ErrorCode::InvalidRequest => Code::InvalidArgument,
+            ErrorCode::Unauthorized => Code::Unauthenticated,
+            ErrorCode::Rejected => Code::PermissionDenied,
+            ErrorCode::ContractNotFound | ErrorCode::DelegateNotFound => Code::NotFound,
+            ErrorCode::MissingRelatedContract | ErrorCode::ContractFailed => {
+                Code::FailedPrecondition
+            }
+            ErrorCode::Disconnected | ErrorCode::NodeUnavailable | ErrorCode::Shutdown => {
+                Code::Unavailable
+            }
+            ErrorCode::Timeout => Code::DeadlineExceeded,
+            _ => Code::Internal,
+        };
+        let mut status = Status::new(code, err.error_message());
+        // metadata is ASCII, details of errors with other characters are left out
+        if let Some(details) = serde_json::to_string(&details)
+            .ok()
+            .and_then(|details| MetadataValue::try_from(details).ok())
+        {
+            status.metadata_mut().insert(ERROR_DETAILS, details);
+        }
+        status
+    }
+}
+
+/// The client of a call, authorized for `scope` on `contract`.
+///
+/// Requests reach the service through the layers of the gateway, which provide the channel to
+/// the node and the token of the client, read from the `authorization` metadata.
+async fn connect(
+    extensions: &Extensions,
+    scope: ApiScope,
+    contract: &ContractKey,
+) -> Result<(RestClient, Option<AuthToken>), Status> {
+    let (Some(request_sender), Some(api_tokens), Some(attested_contracts)) = (
+        extensions.get::<HttpGatewayRequest>(),
+        extensions.get::<ApiTokens>(),
+        extensions.get::<AttestedContractMap>(),
+    ) else {
+        return Err(Status::internal("gRPC service not served by the gateway"));
+    };
+    let auth_token = extensions.get::<Option<AuthToken>>().cloned().flatten();
+    authorize(
+        auth_token.as_ref(),
+        api_tokens,
+        attested_contracts,
+        scope,
+        Some(contract.id()),
+    )?;
+    let client = RestClient::connect(request_sender.clone()).await?;
+    Ok((client, auth_token))
+}
+
+fn unexpected(response: HostResponse) -> Status {
+    Status::internal(format!("Unexpected response from the node: {response}"))
+}
+
+struct GrpcApi;
+
+#[tonic::async_trait]
+impl ClientApi for GrpcApi {
+    async fn put(&self, request: Request<PutRequest>) -> Result<Response<PutResponse>, Status> {
+        let (
+            _,
+            extensions,
+            PutRequest {
+                code,
+                parameters,
+                state,
+                subscribe,
+            },
+        ) = request.into_parts();
+        let contract = ContractContainer::Wasm(ContractWasmAPIVersion::V1(WrappedContract::new(
+            Arc::new(ContractCode::from(code)),
+            Parameters::from(parameters),
+        )));
+        let (mut client, auth_token) =
+            connect(&extensions, ApiScope::Publish, &contract.key()).await?;
+        client
+            .send(
+                ContractRequest::Put {
+                    contract,
+                    state: WrappedState::new(state),
+                    related_contracts: RelatedContracts::default(),
+                    subscribe,
+                },
+                auth_token,
+            )
+            .await?;
+        match client.response().await? {
+            HostResponse::ContractResponse(ContractResponse::PutResponse { key }) => {
+                Ok(Response::new(PutResponse {
+                    key: key.id().to_string(),
+                }))
+            }
+            other => Err(unexpected(other)),
+        }
+    }
+
+    async fn update(
+        &self,
+        request: Request<UpdateRequest>,
+    ) -> Result<Response<UpdateResponse>, Status> {
+        let (_, extensions, UpdateRequest { key, data }) = request.into_parts();
+        let key = parse_key(key)?;
+        let (mut client, auth_token) = connect(&extensions, ApiScope::Publish, &key).await?;
+        let data = match data {
+            Some(update_request::Data::State(state)) => UpdateData::State(State::from(state)),
+            Some(update_request::Data::Delta(delta)) => UpdateData::Delta(StateDelta::from(delta)),
+            None => return Err(Status::invalid_argument("Missing the state or delta")),
+        };
+        client
+            .send(ContractRequest::Update { key, data }, auth_token)
+            .await?;
+        match client.response().await? {
+            HostResponse::ContractResponse(ContractResponse::UpdateResponse { key, summary }) => {
+                Ok(Response::new(UpdateResponse {
+                    key: key.id().to_string(),
+                    summary: summary.into_bytes(),
+                }))
+            }
+            other => Err(unexpected(other)),
+        }
+    }
+
+    async fn get(&self, request: Request<GetRequest>) -> Result<Response<GetResponse>, Status> {
+        let (
+            _,
+            extensions,
+            GetRequest {
+                key,
+                return_contract_code,
+                subscribe,
+            },
+        ) = request.into_parts();
+        let key = parse_key(key)?;
+        let (mut client, auth_token) = connect(&extensions, ApiScope::Read, &key).await?;
+        client
+            .send(
+                ContractRequest::Get {
+                    key,
+                    return_contract_code,
+                    subscribe,
+                },
+                auth_token,
+            )
+            .await?;
+        match client.response().await? {
+            HostResponse::ContractResponse(ContractResponse::GetResponse {
+                key,
+                contract,
+                state,
+            }) => {
+                let (code, parameters) = match contract {
+                    Some(ContractContainer::Wasm(ContractWasmAPIVersion::V1(contract))) => (
+                        Some(contract.code().data().to_vec()),
+                        Some(contract.params().into_bytes()),
+                    ),
+                    _ => (None, None),
+                };
+                Ok(Response::new(GetResponse {
+                    key: key.id().to_string(),
+                    state: state.as_ref().to_vec(),
+                    code,
+                    parameters,
+                }))
+            }
+            other => Err(unexpected(other)),
+        }
+    }
+
+    type SubscribeStream = BoxStream<'static, Result<UpdateNotification, Status>>;
+
+    async fn subscribe(
+        &self,
+        request: Request<SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let (_, extensions, SubscribeRequest { key, summary }) = request.into_parts();
+        let key = parse_key(key)?;
+        let (mut client, auth_token) = connect(&extensions, ApiScope::Read, &key).await?;
+        let summary = summary.map(StateSummary::from);
+        client
+            .send(ContractRequest::Subscribe { key, summary }, auth_token)
+            .await?;
+        let notifications = match client.responses.recv().await {
+            Some(HostCallbackResult::SubscriptionChannel { callback, .. }) => callback,
+            _ => return Err(Status::internal("Couldn't subscribe to the contract")),
+        };
+        match client.response().await? {
+            HostResponse::ContractResponse(ContractResponse::SubscribeResponse {
+                subscribed: true,
+                ..
+            }) => {}
+            other => {
+                return Err(Status::internal(format!(
+                    "Couldn't subscribe to the contract: {other}"
+                )))
+            }
+        }
+        tracing::debug!(contract = %key, client = %client.id, "gRPC client subscribed");
+
+        // the client is kept along with the stream, the subscription ending once disconnected
+        let updates = futures::stream::unfold(
+            (client, notifications),
+            |(client, mut notifications)| async move {
+                let notification = notifications.recv().await?;
+                Some((notification, (client, notifications)))
+            },
+        )
+        .filter_map(|notification| futures::future::ready(streamed_update(notification)));
+        Ok(Response::new(updates.boxed()))
+    }
+}
+
+/// The message streamed for a notification of the node, `None` for those not carrying updates.
+fn streamed_update(notification: HostResult) -> Option<Result<UpdateNotification, Status>> {
+    match notification {
+        Ok(HostResponse::ContractResponse(ContractResponse::UpdateNotification {
+            key,
+            update,
+        })) => {
+            let update = match update {
+                UpdateData::State(state) | UpdateData::StateAndDelta { state, .. } => {
+                    update_notification::Update::State(state.into_bytes())
+                }
+                UpdateData::Delta(delta) => update_notification::Update::Delta(delta.into_bytes()),
+                _ => return None,
+            };
+            Some(Ok(UpdateNotification {
+                key: key.id().to_string(),
+                update: Some(update),
+            }))
+        }
+        Ok(other) => {
+            tracing::debug!("Not streaming notification to gRPC client: {other}");
+            None
+        }
+        Err(err) => Some(Err(WebSocketApiError::AxumError {
+            error: err.kind().clone(),
+        }
+        .into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use freenet_stdlib::client_api::ErrorKind;
+    use std::time::Duration;
+
+    use freenet_stdlib::prelude::ContractInstanceId;
+
+    use crate::client_events::error_details::ErrorDetails;
+
+    use super::*;
+
+    #[test]
+    fn streamed_notifications() {
+        let key = ContractKey::from(ContractInstanceId::new([1; 32]));
+        let notification = |update| {
+            streamed_update(Ok(
+                ContractResponse::UpdateNotification { key, update }.into()
+            ))
+        };
+        let Some(Ok(UpdateNotification { key: id, update })) =
+            notification(UpdateData::Delta(StateDelta::from(vec![1])))
+        else {
+            panic!("expected an update");
+        };
+        assert_eq!(id, key.id().to_string());
+        assert_eq!(update, Some(update_notification::Update::Delta(vec![1])));
+        assert!(matches!(
+            notification(UpdateData::State(State::from(vec![2]))),
+            Some(Ok(UpdateNotification {
+                update: Some(update_notification::Update::State(_)),
+                ..
+            }))
+        ));
+        assert!(streamed_update(Ok(HostResponse::Ok)).is_none());
+
+        let error = ClientError::from(ErrorKind::Disconnect);
+        let Some(Err(status)) = streamed_update(Err(error)) else {
+            panic!("expected an error");
+        };
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+    }
+
+    #[tokio::test]
+    async fn subscription_outlives_the_call() -> Result<(), Box<dyn std::error::Error>> {
+        let key = ContractKey::from(ContractInstanceId::new([1; 32]));
+        let (request_sender, mut requests) = mpsc::channel(8);
+        let mut extensions = Extensions::new();
+        extensions.insert(HttpGatewayRequest(request_sender));
+        extensions.insert(ApiTokens::default());
+        extensions.insert(AttestedContractMap::default());
+        extensions.insert(None::<AuthToken>);
+
+        let node = tokio::spawn(async move {
+            let Some(ClientConnection::NewConnection { callbacks, .. }) = requests.recv().await
+            else {
+                panic!("expected a new connection");
+            };
+            let id = ClientId::next();
+            callbacks.send(HostCallbackResult::NewId { id }).unwrap();
+            let Some(ClientConnection::Request { .. }) = requests.recv().await else {
+                panic!("expected the subscribe request");
+            };
+            let (notifications, callback) = mpsc::unbounded_channel();
+            callbacks
+                .send(HostCallbackResult::SubscriptionChannel { id, key, callback })
+                .unwrap();
+            let result = Ok(ContractResponse::SubscribeResponse {
+                key,
+                subscribed: true,
+            }
+            .into());
+            callbacks
+                .send(HostCallbackResult::Result { id, result })
+                .unwrap();
+            // the node unregisters the listeners of disconnected clients
+            match tokio::time::timeout(Duration::from_millis(100), requests.recv()).await {
+                Ok(_) => drop(notifications),
+                Err(_) => {
+                    let update = UpdateData::State(State::from(vec![1]));
+                    notifications
+                        .send(Ok(
+                            ContractResponse::UpdateNotification { key, update }.into()
+                        ))
+                        .unwrap();
+                    let Some(ClientConnection::Request { req, .. }) = requests.recv().await else {
+                        panic!("expected the client to disconnect");
+                    };
+                    assert!(matches!(*req, ClientRequest::Disconnect { .. }));
+                }
+            }
+        });
+
+        let request = Request::from_parts(
+            tonic::metadata::MetadataMap::new(),
+            extensions,
+            SubscribeRequest {
+                key: key.id().to_string(),
+                summary: None,
+            },
+        );
+        let mut updates = GrpcApi.subscribe(request).await?.into_inner();
+        let Some(Ok(UpdateNotification { update, .. })) = updates.next().await else {
+            panic!("subscription ended without notifications");
+        };
+        assert_eq!(update, Some(update_notification::Update::State(vec![1])));
+        drop(updates);
+        node.await?;
+        Ok(())
+    }
+
+    #[test]
+    fn error_status() -> Result<(), Box<dyn std::error::Error>> {
+        let status = Status::from(WebSocketApiError::Unauthorized {
+            error_cause: "Missing API token".into(),
+        });
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        let status = Status::from(WebSocketApiError::InvalidParam {
+            error_cause: "invalid key".into(),
+        });
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        let details = status
+            .metadata()
+            .get(ERROR_DETAILS)
+            .ok_or("missing error details")?
+            .to_str()?;
+        let details = serde_json::from_str::<ErrorDetails>(details)?;
+        assert_eq!(details.code, ErrorCode::InvalidRequest);
+        assert_eq!(details.message, "invalid key");
+        Ok(())
+    }
+}
@@ -0,0 +1,675 @@
+//! Plain HTTP endpoints to get, update and subscribe to contract states, for clients
+//! which don't implement the websocket protocol.
+
+use std::convert::Infallible;
+
+use axum::body::Bytes;
+use axum::extract::{Multipart, Query};
+use axum::http::{header, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::Response;
+use axum::Json;
+use base64::Engine;
+use freenet_stdlib::client_api::ContractResponse;
+use freenet_stdlib::prelude::{
+    ContractCode, ContractContainer, ContractKey, ContractWasmAPIVersion, Parameters,
+    RelatedContracts, State, StateSummary, UpdateData, WrappedContract, WrappedState,
+};
+use futures::Stream;
+
+use crate::client_events::{AckLevel, GetMode, HostResult, Replicas, SubscriptionMode};
+use crate::message::Priority;
+use crate::server::{ApiScope, ApiTokens, CompressionFormat, WebApp};
+
+use super::*;
+
+/// A client of the node registered for the duration of a REST request, disconnected from the
+/// node when dropped.
+pub(super) struct RestClient {
+    pub id: ClientId,
+    request_sender: HttpGatewayRequest,
+    pub responses: mpsc::UnboundedReceiver<HostCallbackResult>,
+}
+
+impl RestClient {
+    pub async fn connect(request_sender: HttpGatewayRequest) -> Result<Self, WebSocketApiError> {
+        let (callbacks, mut responses) = mpsc::unbounded_channel();
+        request_sender
+            .send(ClientConnection::NewConnection {
+                callbacks,
+                assigned_token: None,
+                subscription_mode: SubscriptionMode::default(),
+                ack_level: AckLevel::default(),
+                get_mode: GetMode::default(),
+                replicas: Replicas::default(),
+            })
+            .await
+            .map_err(|err| WebSocketApiError::NodeError {
+                error_cause: format!("{err}"),
+            })?;
+        match responses.recv().await {
+            Some(HostCallbackResult::NewId { id }) => Ok(Self {
+                id,
+                request_sender,
+                responses,
+            }),
+            _ => Err(WebSocketApiError::NodeError {
+                error_cause: "Couldn't register new client in the node".into(),
+            }),
+        }
+    }
+
+    pub async fn send(
+        &mut self,
+        req: ContractRequest<'static>,
+        auth_token: Option<AuthToken>,
+    ) -> Result<(), WebSocketApiError> {
+        self.request_sender
+            .send(ClientConnection::Request {
+                client_id: self.id,
+                req: Box::new(req.into()),
+                auth_token,
+                attested_contract: None,
+                expected_state: None,
+                retry_policy: None,
+                query: None,
+                priority: Priority::default(),
+            })
+            .await
+            .map_err(|err| WebSocketApiError::NodeError {
+                error_cause: format!("{err}"),
+            })
+    }
+
+    pub async fn response(&mut self) -> Result<HostResponse, WebSocketApiError> {
+        match self.responses.recv().await {
+            Some(HostCallbackResult::Result { result, .. }) => {
+                result.map_err(|err| WebSocketApiError::AxumError {
+                    error: err.kind().clone(),
+                })
+            }
+            Some(HostCallbackResult::StateConflict { error, .. }) => {
+                Err(WebSocketApiError::AxumError {
+                    error: error.kind().clone(),
+                })
+            }
+            _ => Err(WebSocketApiError::NodeError {
+                error_cause: "Node stopped handling the request".into(),
+            }),
+        }
+    }
+}
+
+impl Drop for RestClient {
+    fn drop(&mut self) {
+        let request_sender = self.request_sender.clone();
+        let client_id = self.id;
+        tokio::spawn(async move {
+            let _ = request_sender
+                .send(ClientConnection::Request {
+                    client_id,
+                    req: Box::new(ClientRequest::Disconnect { cause: None }),
+                    auth_token: None,
+                    attested_contract: None,
+                    expected_state: None,
+                    retry_policy: None,
+                    query: None,
+                    priority: Priority::default(),
+                })
+                .await;
+        });
+    }
+}
+
+/// Checks the request carries an API token granting `scope`, on `contract` when known, when API
+/// tokens are enabled.
+///
+/// As for the websocket API, tokens assigned to webapps served by the gateway are granted reading
+/// and publishing their own contract only.
+pub(super) fn authorize(
+    auth_token: Option<&AuthToken>,
+    api_tokens: &ApiTokens,
+    attested_contracts: &AttestedContractMap,
+    scope: ApiScope,
+    contract: Option<&ContractInstanceId>,
+) -> Result<(), WebSocketApiError> {
+    if !api_tokens.is_enabled() {
+        return Ok(());
+    }
+    let Some(token) = auth_token else {
+        return Err(WebSocketApiError::Unauthorized {
+            error_cause: "Missing API token".into(),
+        });
+    };
+    let grant = api_tokens.grant(token).or_else(|| {
+        let attested_contracts = attested_contracts.read().unwrap();
+        attested_contracts
+            .get(token)
+            .map(|(contract, _)| TokenGrant::attested(*contract))
+    });
+    match grant {
+        Some(grant) if !grant.has_scope(scope) => Err(WebSocketApiError::Unauthorized {
+            error_cause: format!("API token not granted the {scope:?} scope"),
+        }),
+        Some(grant) => match contract {
+            Some(contract) if !grant.allows_contract(contract) => {
+                Err(WebSocketApiError::Unauthorized {
+                    error_cause: format!("API token not granted access to contract {contract}"),
+                })
+            }
+            _ => Ok(()),
+        },
+        None => Err(WebSocketApiError::Unauthorized {
+            error_cause: "Invalid API token".into(),
+        }),
+    }
+}
+
+pub(super) fn parse_key(key: String) -> Result<ContractKey, WebSocketApiError> {
+    ContractKey::from_id(key).map_err(|err| WebSocketApiError::InvalidParam {
+        error_cause: format!("{err}"),
+    })
+}
+
+/// `GET /v1/contract/state/:key`, responds with the current state of the contract.
+pub(super) async fn get_state(
+    Path(key): Path<String>,
+    Extension(rs): Extension<HttpGatewayRequest>,
+    Extension(auth_token): Extension<Option<AuthToken>>,
+    Extension(api_tokens): Extension<ApiTokens>,
+    Extension(attested_contracts): Extension<AttestedContractMap>,
+) -> Result<Response, WebSocketApiError> {
+    let key = parse_key(key)?;
+    authorize(
+        auth_token.as_ref(),
+        &api_tokens,
+        &attested_contracts,
+        ApiScope::Read,
+        Some(key.id()),
+    )?;
+    let mut client = RestClient::connect(rs).await?;
+    client
+        .send(
+            ContractRequest::Get {
+                key,
+                return_contract_code: false,
+                subscribe: false,
+            },
+            auth_token,
+        )
+        .await?;
+    match client.response().await? {
+        HostResponse::ContractResponse(ContractResponse::GetResponse { state, .. }) => Ok((
+            [(header::CONTENT_TYPE, "application/octet-stream")],
+            state.as_ref().to_vec(),
+        )
+            .into_response()),
+        other => Err(WebSocketApiError::NodeError {
+            error_cause: format!("Unexpected response from the node: {other}"),
+        }),
+    }
+}
+
+/// `PUT /v1/contract/state/:key`, replaces the state of the contract with the request body.
+pub(super) async fn put_state(
+    Path(key): Path<String>,
+    Extension(rs): Extension<HttpGatewayRequest>,
+    Extension(auth_token): Extension<Option<AuthToken>>,
+    Extension(api_tokens): Extension<ApiTokens>,
+    Extension(attested_contracts): Extension<AttestedContractMap>,
+    body: Bytes,
+) -> Result<Response, WebSocketApiError> {
+    let key = parse_key(key)?;
+    authorize(
+        auth_token.as_ref(),
+        &api_tokens,
+        &attested_contracts,
+        ApiScope::Publish,
+        Some(key.id()),
+    )?;
+    let mut client = RestClient::connect(rs).await?;
+    client
+        .send(
+            ContractRequest::Update {
+                key,
+                data: UpdateData::State(State::from(body.to_vec())),
+            },
+            auth_token,
+        )
+        .await?;
+    match client.response().await? {
+        HostResponse::ContractResponse(ContractResponse::UpdateResponse { .. }) => {
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+        other => Err(WebSocketApiError::NodeError {
+            error_cause: format!("Unexpected response from the node: {other}"),
+        }),
+    }
+}
+
+/// Room left in publish uploads for the contract code and form encoding, on top of the webapp
+/// size limits.
+pub(super) const PUBLISH_UPLOAD_OVERHEAD: u64 = 16 * 1024 * 1024;
+
+/// Fields of a [`publish`] upload.
+#[derive(Default)]
+struct PublishForm {
+    web: Option<Bytes>,
+    metadata: Vec<u8>,
+    key: Option<String>,
+    contract: Option<Bytes>,
+    parameters: Vec<u8>,
+}
+
+impl PublishForm {
+    async fn read(mut multipart: Multipart) -> Result<Self, WebSocketApiError> {
+        let invalid =
+            |err: axum::extract::multipart::MultipartError| WebSocketApiError::InvalidParam {
+                error_cause: format!("{err}"),
+            };
+        let mut form = Self::default();
+        while let Some(field) = multipart.next_field().await.map_err(invalid)? {
+            let name = field.name().unwrap_or_default().to_owned();
+            let data = field.bytes().await.map_err(invalid)?;
+            match name.as_str() {
+                "web" => form.web = Some(data),
+                "metadata" => form.metadata = data.to_vec(),
+                "key" => {
+                    form.key = Some(String::from_utf8(data.to_vec()).map_err(|_| {
+                        WebSocketApiError::InvalidParam {
+                            error_cause: "Invalid contract key".into(),
+                        }
+                    })?)
+                }
+                "contract" => form.contract = Some(data),
+                "parameters" => form.parameters = data.to_vec(),
+                other => {
+                    return Err(WebSocketApiError::InvalidParam {
+                        error_cause: format!("Unknown field `{other}`"),
+                    })
+                }
+            }
+        }
+        Ok(form)
+    }
+}
+
+/// `POST /v1/contract/publish`, packs an uploaded web archive as a webapp state and publishes
+/// it, so webapps can be deployed with a plain HTTP client.
+///
+/// Takes a multipart form with the fields:
+/// - `web`: tar archive of the webapp, optionally gzip compressed, with an `index.html` at its root.
+/// - `metadata`: metadata of the webapp, empty if not set.
+/// - `key`: the webapp contract to update, or
+/// - `contract` and `parameters`: code and parameters of a new webapp contract to put.
+///
+/// Responds with the key of the contract. States published this way are not signed, so they
+/// are not served by gateways requiring a publisher signature for the contract.
+pub(super) async fn publish(
+    axum::extract::State(config): axum::extract::State<Config>,
+    Extension(rs): Extension<HttpGatewayRequest>,
+    Extension(auth_token): Extension<Option<AuthToken>>,
+    Extension(api_tokens): Extension<ApiTokens>,
+    Extension(attested_contracts): Extension<AttestedContractMap>,
+    multipart: Multipart,
+) -> Result<Response, WebSocketApiError> {
+    authorize(
+        auth_token.as_ref(),
+        &api_tokens,
+        &attested_contracts,
+        ApiScope::Publish,
+        None,
+    )?;
+    let form = PublishForm::read(multipart).await?;
+    let Some(web) = form.web else {
+        return Err(WebSocketApiError::InvalidParam {
+            error_cause: "Missing `web` archive".into(),
+        });
+    };
+    let metadata = form.metadata;
+    let state = tokio::task::spawn_blocking(move || {
+        WebApp::from_archive(metadata, &web, CompressionFormat::default())
+            .map_err(|err| format!("{err}"))
+            .and_then(|webapp| webapp.pack().map_err(|err| format!("{err}")))
+    })
+    .await
+    .map_err(|err| WebSocketApiError::NodeError {
+        error_cause: format!("{err}"),
+    })?
+    .map_err(|error_cause| WebSocketApiError::InvalidParam { error_cause })?;
+    config
+        .webapp_policy
+        .limits
+        .check(&state)
+        .map_err(|err| WebSocketApiError::InvalidParam {
+            error_cause: format!("{err}"),
+        })?;
+
+    let mut client = RestClient::connect(rs).await?;
+    match (form.key, form.contract) {
+        (Some(key), None) => {
+            let key = parse_key(key)?;
+            authorize(
+                auth_token.as_ref(),
+                &api_tokens,
+                &attested_contracts,
+                ApiScope::Publish,
+                Some(key.id()),
+            )?;
+            client
+                .send(
+                    ContractRequest::Update {
+                        key,
+                        data: UpdateData::State(State::from(state)),
+                    },
+                    auth_token,
+                )
+                .await?;
+            match client.response().await? {
+                HostResponse::ContractResponse(ContractResponse::UpdateResponse {
+                    key, ..
+                }) => {
+                    tracing::info!(contract = %key, "Webapp updated through the gateway");
+                    Ok(Json(serde_json::json!({ "key": key.id().to_string() })).into_response())
+                }
+                other => Err(WebSocketApiError::NodeError {
+                    error_cause: format!("Unexpected response from the node: {other}"),
+                }),
+            }
+        }
+        (None, Some(code)) => {
+            let contract =
+                ContractContainer::Wasm(ContractWasmAPIVersion::V1(WrappedContract::new(
+                    Arc::new(ContractCode::from(code.to_vec())),
+                    Parameters::from(form.parameters),
+                )));
+            authorize(
+                auth_token.as_ref(),
+                &api_tokens,
+                &attested_contracts,
+                ApiScope::Publish,
+                Some(contract.key().id()),
+            )?;
+            client
+                .send(
+                    ContractRequest::Put {
+                        contract,
+                        state: WrappedState::new(state),
+                        related_contracts: RelatedContracts::default(),
+                        subscribe: false,
+                    },
+                    auth_token,
+                )
+                .await?;
+            match client.response().await? {
+                HostResponse::ContractResponse(ContractResponse::PutResponse { key }) => {
+                    tracing::info!(contract = %key, "Webapp published through the gateway");
+                    Ok((
+                        StatusCode::CREATED,
+                        Json(serde_json::json!({ "key": key.id().to_string() })),
+                    )
+                        .into_response())
+                }
+                other => Err(WebSocketApiError::NodeError {
+                    error_cause: format!("Unexpected response from the node: {other}"),
+                }),
+            }
+        }
+        _ => Err(WebSocketApiError::InvalidParam {
+            error_cause:
+                "Either the `key` of the contract to update or its `contract` code is required"
+                    .into(),
+        }),
+    }
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+pub(super) struct SubscribeParams {
+    /// Base64 encoded summary of the state the client holds.
+    summary: Option<String>,
+}
+
+impl SubscribeParams {
+    fn summary(&self) -> Result<Option<StateSummary<'static>>, WebSocketApiError> {
+        self.summary
+            .as_deref()
+            .map(|summary| {
+                base64::engine::general_purpose::URL_SAFE_NO_PAD
+                    .decode(summary.trim_end_matches('='))
+                    .or_else(|_| base64::engine::general_purpose::STANDARD.decode(summary))
+                    .map(StateSummary::from)
+                    .map_err(|err| WebSocketApiError::InvalidParam {
+                        error_cause: format!("invalid state summary: {err}"),
+                    })
+            })
+            .transpose()
+    }
+}
+
+/// `GET /v1/contract/subscribe/:key`, streams updates of the contract as server-sent events.
+///
+/// Each update is sent as a `state` or `delta` event holding the base64 encoded bytes.
+/// Updates carry the whole state unless the `summary` query parameter holds the summary of
+/// the state the client has, in which case deltas from that state are sent when the
+/// contract supports them. The subscription ends when the client goes away, the node
+/// client subscribed being disconnected along with the stream.
+pub(super) async fn subscribe(
+    Path(key): Path<String>,
+    Query(params): Query<SubscribeParams>,
+    Extension(rs): Extension<HttpGatewayRequest>,
+    Extension(auth_token): Extension<Option<AuthToken>>,
+    Extension(api_tokens): Extension<ApiTokens>,
+    Extension(attested_contracts): Extension<AttestedContractMap>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, WebSocketApiError> {
+    let key = parse_key(key)?;
+    authorize(
+        auth_token.as_ref(),
+        &api_tokens,
+        &attested_contracts,
+        ApiScope::Read,
+        Some(key.id()),
+    )?;
+    let summary = params.summary()?;
+    let mut client = RestClient::connect(rs).await?;
+    client
+        .send(ContractRequest::Subscribe { key, summary }, auth_token)
+        .await?;
+    let notifications = match client.responses.recv().await {
+        Some(HostCallbackResult::SubscriptionChannel { callback, .. }) => callback,
+        _ => {
+            return Err(WebSocketApiError::NodeError {
+                error_cause: "Couldn't subscribe to the contract".into(),
+            })
+        }
+    };
+    match client.response().await? {
+        HostResponse::ContractResponse(ContractResponse::SubscribeResponse {
+            subscribed: true,
+            ..
+        }) => {}
+        other => {
+            return Err(WebSocketApiError::NodeError {
+                error_cause: format!("Couldn't subscribe to the contract: {other}"),
+            })
+        }
+    }
+    tracing::debug!(contract = %key, client = %client.id, "REST client subscribed");
+
+    let events = futures::stream::unfold(
+        (client, notifications, 0u64),
+        |(client, mut notifications, seq)| async move {
+            let notification = notifications.recv().await?;
+            let event = notification_event(notification).id(seq.to_string());
+            Some((Ok(event), (client, notifications, seq + 1)))
+        },
+    );
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+fn notification_event(notification: HostResult) -> Event {
+    let encode = |bytes: &[u8]| base64::engine::general_purpose::STANDARD.encode(bytes);
+    match notification {
+        Ok(HostResponse::ContractResponse(ContractResponse::UpdateNotification {
+            update, ..
+        })) => match update {
+            UpdateData::State(state) | UpdateData::StateAndDelta { state, .. } => {
+                Event::default().event("state").data(encode(state.as_ref()))
+            }
+            UpdateData::Delta(delta) => {
+                Event::default().event("delta").data(encode(delta.as_ref()))
+            }
+            _ => Event::default().comment("unsupported update notification"),
+        },
+        Ok(other) => Event::default().comment(format!("{other}")),
+        Err(err) => Event::default().event("error").data(format!("{err}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::server::TokenGrant;
+
+    use super::*;
+
+    #[test]
+    fn subscribe_summary() {
+        assert!(SubscribeParams::default().summary().unwrap().is_none());
+        for encoded in ["AQID", "AQID=="] {
+            let params = SubscribeParams {
+                summary: Some(encoded.to_owned()),
+            };
+            assert_eq!(params.summary().unwrap().unwrap().as_ref(), &[1, 2, 3]);
+        }
+        let invalid = SubscribeParams {
+            summary: Some("not base64!".to_owned()),
+        };
+        assert!(matches!(
+            invalid.summary(),
+            Err(WebSocketApiError::InvalidParam { .. })
+        ));
+    }
+
+    #[test]
+    fn authorization() -> Result<(), Box<dyn std::error::Error>> {
+        let api_tokens = ApiTokens::default();
+        let attested_contracts = AttestedContractMap::default();
+        assert!(authorize(
+            None,
+            &api_tokens,
+            &attested_contracts,
+            ApiScope::Publish,
+            None
+        )
+        .is_ok());
+
+        let reader = AuthToken::from("reader".to_owned());
+        api_tokens.insert(reader.clone(), TokenGrant::new([ApiScope::Read]));
+        assert!(authorize(None, &api_tokens, &attested_contracts, ApiScope::Read, None).is_err());
+        assert!(authorize(
+            Some(&reader),
+            &api_tokens,
+            &attested_contracts,
+            ApiScope::Read,
+            None
+        )
+        .is_ok());
+        assert!(matches!(
+            authorize(
+                Some(&reader),
+                &api_tokens,
+                &attested_contracts,
+                ApiScope::Publish,
+                None
+            ),
+            Err(WebSocketApiError::Unauthorized { .. })
+        ));
+
+        let webapp = AuthToken::from("webapp".to_owned());
+        assert!(authorize(
+            Some(&webapp),
+            &api_tokens,
+            &attested_contracts,
+            ApiScope::Publish,
+            None
+        )
+        .is_err());
+        let webapp_contract = ContractInstanceId::new([1; 32]);
+        attested_contracts
+            .write()
+            .unwrap()
+            .insert(webapp.clone(), (webapp_contract, ClientId::next()));
+        assert!(authorize(
+            Some(&webapp),
+            &api_tokens,
+            &attested_contracts,
+            ApiScope::Publish,
+            Some(&webapp_contract)
+        )
+        .is_ok());
+        // webapps are restricted to their own contract, without delegates
+        assert!(authorize(
+            Some(&webapp),
+            &api_tokens,
+            &attested_contracts,
+            ApiScope::Publish,
+            Some(&ContractInstanceId::new([4; 32]))
+        )
+        .is_err());
+        assert!(authorize(
+            Some(&webapp),
+            &api_tokens,
+            &attested_contracts,
+            ApiScope::Delegate,
+            None
+        )
+        .is_err());
+
+        let restricted = AuthToken::from("restricted".to_owned());
+        let granted = ContractInstanceId::new([2; 32]);
+        api_tokens.insert(
+            restricted.clone(),
+            TokenGrant {
+                contracts: Some(HashSet::from([granted])),
+                ..TokenGrant::new([ApiScope::Read])
+            },
+        );
+        assert!(authorize(
+            Some(&restricted),
+            &api_tokens,
+            &attested_contracts,
+            ApiScope::Read,
+            Some(&granted)
+        )
+        .is_ok());
+        assert!(matches!(
+            authorize(
+                Some(&restricted),
+                &api_tokens,
+                &attested_contracts,
+                ApiScope::Read,
+                Some(&ContractInstanceId::new([3; 32]))
+            ),
+            Err(WebSocketApiError::Unauthorized { .. })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rest_client_disconnects_when_dropped() {
+        let (request_sender, mut requests) = mpsc::channel(1);
+        let (_callbacks, responses) = mpsc::unbounded_channel();
+        let id = ClientId::next();
+        drop(RestClient {
+            id,
+            request_sender: HttpGatewayRequest(request_sender),
+            responses,
+        });
+        let Some(ClientConnection::Request { client_id, req, .. }) = requests.recv().await else {
+            panic!("expected a request");
+        };
+        assert_eq!(client_id, id);
+        assert!(matches!(*req, ClientRequest::Disconnect { .. }));
+    }
+}
@@ -1,28 +1,54 @@
+use axum::{extract::DefaultBodyLimit, routing::post};
+
 use super::*;
+use crate::server::{forwarded::ForwardedProto, subdomains::WebAppSubdomain};
 
 impl HttpGateway {
     /// Returns the uninitialized axum router with a provided attested_contracts map.
     pub fn create_router_v1_with_attested_contracts(
         socket: &SocketAddr,
         attested_contracts: AttestedContractMap,
+        webapp_policy: WebAppPolicy,
+        ready_min_peers: usize,
+        tls: bool,
     ) -> (Self, Router) {
-        let localhost = match socket.ip() {
-            IpAddr::V4(ip) if ip.is_loopback() || ip.is_unspecified() => true,
-            IpAddr::V6(ip) if ip.is_loopback() || ip.is_unspecified() => true,
-            _ => false,
-        };
+        let loopback = socket.ip().is_loopback();
         let contract_web_path = std::env::temp_dir().join("freenet").join("webs");
         std::fs::create_dir_all(contract_web_path).unwrap();
 
         let (proxy_request_sender, request_to_server) = mpsc::channel(1);
+        let publish_limit = (webapp_policy.limits.max_metadata_size
+            + webapp_policy.limits.max_web_size
+            + rest::PUBLISH_UPLOAD_OVERHEAD) as usize;
 
-        let config = Config { localhost };
+        let config = Config {
+            loopback,
+            tls,
+            webapp_policy,
+            ready_min_peers,
+        };
 
         let router = Router::new()
             .route("/v1", get(home))
+            .route("/metrics", get(metrics))
+            .route("/healthz", get(healthz))
+            .route("/readyz", get(readyz))
             .route("/v1/contract/web/:key/", get(web_home))
-            .with_state(config)
             .route("/v1/contract/web/:key/*path", get(web_subpages))
+            .route("/v1/contract/listing/:key", get(web_listing))
+            .route(
+                "/v1/contract/state/:key",
+                get(rest::get_state).put(rest::put_state),
+            )
+            .route("/v1/contract/subscribe/:key", get(rest::subscribe))
+            .route(
+                "/v1/contract/publish",
+                post(rest::publish).layer(DefaultBodyLimit::max(publish_limit)),
+            )
+            .with_state(config);
+        #[cfg(feature = "grpc")]
+        let router = router.merge(grpc::routes());
+        let router = router
             .layer(Extension(attested_contracts.clone()))
             .layer(Extension(HttpGatewayRequest(proxy_request_sender)));
 
@@ -40,28 +66,26 @@ impl HttpGateway {
 async fn web_home(
     Path(key): Path<String>,
     Extension(rs): Extension<HttpGatewayRequest>,
+    subdomain: Option<Extension<WebAppSubdomain>>,
+    forwarded_proto: Option<Extension<ForwardedProto>>,
     axum::extract::State(config): axum::extract::State<Config>,
+    req_headers: axum::http::HeaderMap,
 ) -> Result<axum::response::Response, WebSocketApiError> {
     use headers::{Header, HeaderMapExt};
 
-    let domain = config
-        .localhost
-        .then_some("localhost")
-        .expect("non-local connections not supported yet");
     let token = AuthToken::generate();
-
-    let auth_header = headers::Authorization::<headers::authorization::Bearer>::name().to_string();
-    let cookie = cookie::Cookie::build((auth_header, format!("Bearer {}", token.as_str())))
-        .domain(domain)
-        .path(format!("/v1/contract/web/{key}"))
-        .same_site(cookie::SameSite::Strict)
-        .max_age(cookie::time::Duration::days(1))
-        .secure(!config.localhost)
-        .http_only(false)
-        .build();
+    let cookie = auth_cookie(
+        &token,
+        &key,
+        &config,
+        subdomain.is_some(),
+        forwarded_proto.as_deref(),
+    );
 
     let token_header = headers::Authorization::bearer(token.as_str()).unwrap();
-    let contract_response = path_handlers::contract_home(key, rs, token.clone()).await?;
+    let contract_response =
+        path_handlers::contract_home(key, rs, token.clone(), config.webapp_policy, req_headers)
+            .await?;
 
     // FIXME: We may be able to store the token in attested_contracts here if we can get the ContractInstanceId
     // from the `key` but leaving it for now based on "if it ain't broke, don't fix it" principle.
@@ -76,12 +100,85 @@ async fn web_home(
     Ok(response)
 }
 
+/// Cookie handing the auth token of a webapp to the scripts of its pages.
+fn auth_cookie(
+    token: &AuthToken,
+    key: &str,
+    config: &Config,
+    subdomain: bool,
+    forwarded_proto: Option<&ForwardedProto>,
+) -> cookie::Cookie<'static> {
+    use headers::Header;
+
+    let auth_header = headers::Authorization::<headers::authorization::Bearer>::name().to_string();
+    let cookie = cookie::Cookie::build((auth_header, format!("Bearer {}", token.as_str())))
+        .same_site(cookie::SameSite::Strict)
+        .max_age(cookie::time::Duration::days(1))
+        .secure(forwarded_proto.map_or(config.tls, ForwardedProto::is_https))
+        .http_only(false);
+    if subdomain {
+        // the webapp has its own origin, so the cookie is only sent back to its host
+        cookie.path("/").build()
+    } else if forwarded_proto.is_some() || !config.loopback {
+        // only sent back to the host clients reach the gateway at, which may be a proxy
+        cookie.path(format!("/v1/contract/web/{key}")).build()
+    } else {
+        cookie
+            .domain("localhost")
+            .path(format!("/v1/contract/web/{key}"))
+            .build()
+    }
+}
+
 async fn web_subpages(
     Path((key, last_path)): Path<(String, String)>,
+    axum::extract::State(config): axum::extract::State<Config>,
+    headers: axum::http::HeaderMap,
 ) -> Result<axum::response::Response, WebSocketApiError> {
     let full_path: String = format!("/v1/contract/web/{}/{}", key, last_path);
-    path_handlers::variable_content(key, full_path)
+    path_handlers::variable_content(key, full_path, headers, &config.webapp_policy)
         .await
         .map_err(|e| *e)
         .map(|r| r.into_response())
 }
+
+async fn web_listing(
+    Path(key): Path<String>,
+    axum::extract::State(config): axum::extract::State<Config>,
+) -> Result<axum::response::Response, WebSocketApiError> {
+    path_handlers::contract_listing(key, &config.webapp_policy.cache).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auth_cookie_attributes() {
+        let token = AuthToken::generate();
+        let config = |loopback, tls| Config {
+            loopback,
+            tls,
+            webapp_policy: WebAppPolicy::default(),
+            ready_min_peers: 0,
+        };
+
+        let local = auth_cookie(&token, "key", &config(true, false), false, None);
+        assert_eq!(local.domain(), Some("localhost"));
+        assert_eq!(local.secure(), Some(false));
+
+        // bound to a public or unspecified address, as when serving a domain over TLS
+        let public = auth_cookie(&token, "key", &config(false, true), false, None);
+        assert_eq!(public.domain(), None);
+        assert_eq!(public.secure(), Some(true));
+        assert_eq!(public.path(), Some("/v1/contract/web/key"));
+
+        let proxied = ForwardedProto("https".to_owned());
+        let forwarded = auth_cookie(&token, "key", &config(true, false), false, Some(&proxied));
+        assert_eq!(forwarded.domain(), None);
+        assert_eq!(forwarded.secure(), Some(true));
+
+        let subdomain = auth_cookie(&token, "key", &config(false, true), true, None);
+        assert_eq!(subdomain.path(), Some("/"));
+    }
+}
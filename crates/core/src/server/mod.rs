@@ -6,10 +6,18 @@
 //!
 //! See [`../architecture.md`](../architecture.md) for its place in the overall architecture.
 
+pub(crate) mod access_log;
+pub(crate) mod admin;
+pub(crate) mod api_tokens;
 pub(crate) mod app_packaging;
+mod cors;
 pub(crate) mod errors;
+pub(crate) mod forwarded;
 pub(crate) mod http_gateway;
 pub(crate) mod path_handlers;
+mod rate_limit;
+pub(crate) mod subdomains;
+mod tls;
 
 use std::collections::HashMap;
 use std::net::SocketAddr;
@@ -20,16 +28,29 @@ use freenet_stdlib::{
     prelude::*,
 };
 
+use axum::Extension;
 use http_gateway::HttpGateway;
 use tower_http::trace::TraceLayer;
 
 use crate::{
-    client_events::{websocket::WebSocketProxy, AuthToken, BoxedClient, ClientId, HostResult},
-    config::WebsocketApiConfig,
+    client_events::{
+        session::Sessions,
+        websocket::{ClientLimits, OutboundSettings, WebSocketProxy},
+        AckLevel, AuthToken, BoxedClient, ClientId, GetMode, HostResult, Replicas,
+        SubscriptionMode,
+    },
+    config::{ListenAddress, RetryPolicy, WebsocketApiConfig},
+    message::Priority,
+    operations::get::GetQuery,
 };
 
-use crate::server::http_gateway::AttestedContractMap;
-pub use app_packaging::WebApp;
+use crate::server::http_gateway::{AttestedContractMap, WebAppPolicy};
+use crate::server::path_handlers::ResponseCompression;
+pub use api_tokens::{ApiScope, ApiTokens, TokenGrant};
+pub use app_packaging::{
+    CachedWebApp, CompressionFormat, WebApp, WebAppCache, WebAppChunkRef, WebAppEntry,
+    WebAppLimits, WebAppManifest,
+};
 
 #[derive(Debug)]
 #[allow(clippy::large_enum_variant)]
@@ -37,12 +58,24 @@ pub(crate) enum ClientConnection {
     NewConnection {
         callbacks: tokio::sync::mpsc::UnboundedSender<HostCallbackResult>,
         assigned_token: Option<(AuthToken, ContractInstanceId)>,
+        subscription_mode: SubscriptionMode,
+        ack_level: AckLevel,
+        get_mode: GetMode,
+        replicas: Replicas,
     },
     Request {
         client_id: ClientId,
         req: Box<ClientRequest<'static>>,
         auth_token: Option<AuthToken>,
         attested_contract: Option<ContractInstanceId>,
+        /// Hash of the state the contract of an update is expected to have, for conditional
+        /// updates.
+        expected_state: Option<blake3::Hash>,
+        /// Retries of the operation requested by the client, overriding those of the node.
+        retry_policy: Option<RetryPolicy>,
+        /// Query of a get, returning the answer computed by the contract instead of the state.
+        query: Option<GetQuery>,
+        priority: Priority,
     },
 }
 
@@ -55,6 +88,18 @@ pub(crate) enum HostCallbackResult {
         id: ClientId,
         result: Result<HostResponse, ClientError>,
     },
+    /// Response with a state stored by the node served while it's revalidated.
+    CachedResult {
+        id: ClientId,
+        result: Result<HostResponse, ClientError>,
+        age: Option<std::time::Duration>,
+    },
+    /// Error rejecting a conditional update of a contract whose state differs from the one
+    /// expected.
+    StateConflict {
+        id: ClientId,
+        error: ClientError,
+    },
     SubscriptionChannel {
         id: ClientId,
         key: ContractKey,
@@ -66,12 +111,70 @@ fn serve(socket: SocketAddr, router: axum::Router) {
     tokio::spawn(async move {
         tracing::info!("HTTP gateway listening on {}", socket);
         let listener = tokio::net::TcpListener::bind(socket).await.unwrap();
-        axum::serve(listener, router).await.map_err(|e| {
+        axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        .map_err(|e| {
             tracing::error!("Error while running HTTP gateway server: {e}");
         })
     });
 }
 
+#[cfg(unix)]
+fn serve_unix(path: std::path::PathBuf, router: axum::Router) {
+    use hyper_util::{
+        rt::{TokioExecutor, TokioIo},
+        server::conn::auto,
+        service::TowerToHyperService,
+    };
+
+    tokio::spawn(async move {
+        tracing::info!("HTTP gateway listening on {}", path.display());
+        // left behind by a previous run
+        if path.exists() {
+            if let Err(e) = std::fs::remove_file(&path) {
+                tracing::error!("Failed removing stale socket {}: {e}", path.display());
+                return;
+            }
+        }
+        let listener = match tokio::net::UnixListener::bind(&path) {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::error!("Failed binding HTTP gateway to {}: {e}", path.display());
+                return;
+            }
+        };
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::error!("Error accepting HTTP gateway connection: {e}");
+                    continue;
+                }
+            };
+            let service = TowerToHyperService::new(router.clone());
+            tokio::spawn(async move {
+                if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                    .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                    .await
+                {
+                    tracing::debug!("Error serving HTTP gateway connection: {e}");
+                }
+            });
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn serve_unix(path: std::path::PathBuf, _router: axum::Router) {
+    tracing::error!(
+        "Unix sockets are not supported on this platform, not listening on {}",
+        path.display()
+    );
+}
+
 pub mod local_node {
     use freenet_stdlib::client_api::{ClientRequest, ErrorKind};
     use std::net::{IpAddr, SocketAddr};
@@ -122,6 +225,7 @@ pub mod local_node {
                 client_id: id,
                 request,
                 notification_channel,
+                subscription_mode,
                 token,
                 ..
             } = req;
@@ -130,7 +234,7 @@ pub mod local_node {
             let res = match *request {
                 ClientRequest::ContractOp(op) => {
                     executor
-                        .contract_requests(op, id, notification_channel)
+                        .contract_requests(op, id, notification_channel, subscription_mode)
                         .await
                 }
                 ClientRequest::DelegateOp(op) => {
@@ -213,12 +317,95 @@ pub(crate) async fn serve_gateway_in(config: WebsocketApiConfig) -> (HttpGateway
         (ContractInstanceId, ClientId),
     >::new()));
 
+    let publisher_keys = config
+        .publisher_keys()
+        .expect("webapp publisher keys are validated when building the config");
+
+    let webapp_policy = WebAppPolicy {
+        publisher_keys: Arc::new(publisher_keys),
+        limits: config.webapp_limits(),
+        cache: config
+            .webapp_cache_dir
+            .clone()
+            .map(WebAppCache::new)
+            .unwrap_or_default(),
+        compression: ResponseCompression::new(config.compression_min_size),
+    };
+
     // Pass the shared map to both HttpGateway and WebSocketProxy
-    let (gw, gw_router) =
-        HttpGateway::as_router_with_attested_contracts(&ws_socket, attested_contracts.clone());
-    let (ws_proxy, ws_router) =
-        WebSocketProxy::create_router_with_attested_contracts(gw_router, attested_contracts);
+    let (gw, gw_router) = HttpGateway::as_router_with_attested_contracts(
+        &ws_socket,
+        attested_contracts.clone(),
+        webapp_policy,
+        config.ready_min_peers,
+        config.tls.is_some(),
+    );
+    let (ws_proxy, ws_router) = WebSocketProxy::create_router_with_attested_contracts(
+        gw_router.merge(admin::router()),
+        attested_contracts,
+        config.api_tokens(),
+        ClientLimits::new(config.client_limits),
+        Sessions::new(config.sessions, config.sessions_file.clone()),
+        OutboundSettings::from(&config),
+    );
 
-    serve(ws_socket, ws_router.layer(TraceLayer::new_for_http()));
+    let ws_router = match config.rate_limit {
+        Some(rate_limit) => rate_limit::with_rate_limit(ws_router, rate_limit),
+        None => ws_router,
+    };
+    let ws_router = match config.cors.clone() {
+        Some(cors) => cors::with_cors(ws_router, cors),
+        None => ws_router,
+    };
+    let ws_router = match config.access_log.clone().map(access_log::AccessLog::open) {
+        Some(Ok(log)) => access_log::with_access_log(ws_router, log),
+        Some(Err(err)) => {
+            tracing::error!("Failed opening the access log, requests will not be logged: {err}");
+            ws_router
+        }
+        None => ws_router,
+    };
+    let ws_router = match config.subdomains.clone() {
+        Some(subdomains) => subdomains::with_subdomains(ws_router, subdomains)
+            .expect("subdomain routing is validated when building the config"),
+        None => ws_router,
+    };
+    let router = ws_router.layer(TraceLayer::new_for_http().make_span_with(
+        |req: &axum::extract::Request| {
+            let client = req
+                .extensions()
+                .get::<axum::extract::ConnectInfo<SocketAddr>>()
+                .map(|info| info.0.ip());
+            tracing::debug_span!(
+                "request",
+                method = %req.method(),
+                uri = %req.uri(),
+                client = ?client,
+            )
+        },
+    ));
+    // outermost, so every other layer sees the client behind the proxies
+    let trusted_proxies = config
+        .trusted_proxy_networks()
+        .expect("trusted proxies are validated when building the config");
+    let router = if trusted_proxies.is_empty() {
+        router
+    } else {
+        forwarded::with_trusted_proxies(router, trusted_proxies)
+    };
+    for listener in &config.listeners {
+        let router = router.clone().layer(Extension(listener.auth));
+        match listener
+            .listen_address()
+            .expect("listener addresses are validated when building the config")
+        {
+            ListenAddress::Tcp(socket) => serve(socket, router),
+            ListenAddress::Unix(path) => serve_unix(path, router),
+        }
+    }
+    match config.tls {
+        Some(tls) => tls::serve_tls(ws_socket, router, tls),
+        None => serve(ws_socket, router),
+    }
     (gw, ws_proxy)
 }
@@ -1,31 +1,45 @@
 //! Handle the `web` part of the bundles.
 
-use std::path::{Path, PathBuf};
+use std::path::Path;
 
-use axum::response::{Html, IntoResponse};
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{Html, IntoResponse, Response},
+};
 use freenet_stdlib::{
     client_api::{ClientRequest, ContractRequest, ContractResponse, HostResponse},
     prelude::*,
 };
-use tokio::{fs::File, io::AsyncReadExt, sync::mpsc};
+use headers::{ETag, HeaderMapExt, IfNoneMatch, LastModified};
+use tokio::{io::AsyncWriteExt, sync::mpsc};
 
-use crate::client_events::AuthToken;
+use crate::client_events::{AckLevel, AuthToken, ClientId, GetMode, Replicas, SubscriptionMode};
+use crate::message::Priority;
 
 use super::{
-    app_packaging::{WebApp, WebContractError},
+    app_packaging::{
+        self, CachedWebApp, WebApp, WebAppCache, WebAppManifest, WebChunks, WebContractError,
+    },
     errors::WebSocketApiError,
-    http_gateway::HttpGatewayRequest,
+    http_gateway::{HttpGatewayRequest, WebAppPolicy},
     ClientConnection, HostCallbackResult,
 };
 use tracing::{debug, instrument};
 
+mod compression;
 mod v1;
 
-#[instrument(level = "debug", skip(request_sender))]
+pub(crate) use compression::{
+    ResponseCompression, DEFAULT_MIN_SIZE as DEFAULT_COMPRESSION_MIN_SIZE,
+};
+
+#[instrument(level = "debug", skip(request_sender, webapp_policy, req_headers))]
 pub(super) async fn contract_home(
     key: String,
     request_sender: HttpGatewayRequest,
     assigned_token: AuthToken,
+    webapp_policy: WebAppPolicy,
+    req_headers: HeaderMap,
 ) -> Result<impl IntoResponse, WebSocketApiError> {
     debug!(
         "contract_home: Converting string key to ContractKey: {}",
@@ -44,6 +58,10 @@ pub(super) async fn contract_home(
         .send(ClientConnection::NewConnection {
             callbacks: response_sender,
             assigned_token: Some((assigned_token, key.into())),
+            subscription_mode: SubscriptionMode::default(),
+            ack_level: AckLevel::default(),
+            get_mode: GetMode::default(),
+            replicas: Replicas::default(),
         })
         .await
         .map_err(|err| WebSocketApiError::NodeError {
@@ -56,6 +74,17 @@ pub(super) async fn contract_home(
             error_cause: "Couldn't register new client in the node".into(),
         });
     };
+    // disconnected however the request ends, releasing the subscription of the get
+    let _client = WebClient {
+        id: client_id,
+        request_sender: request_sender.clone(),
+    };
+    if let Some(cached) = webapp_policy.cache.validated(key.id()).await {
+        debug!("contract_home: No state change observed since the webapp was extracted");
+        return serve_index(&key, &cached, &webapp_policy, &req_headers).await;
+    }
+    // read before fetching the state, so changes observed meanwhile invalidate it
+    let state_version = app_packaging::state_version(key.id());
     debug!("contract_home: Sending GET request for contract");
     request_sender
         .send(ClientConnection::Request {
@@ -64,12 +93,17 @@ pub(super) async fn contract_home(
                 ContractRequest::Get {
                     key,
                     return_contract_code: true,
-                    subscribe: false,
+                    // so the node observes updates to the webapp and invalidates the extraction
+                    subscribe: true,
                 }
                 .into(),
             ),
             auth_token: None,
             attested_contract: None,
+            expected_state: None,
+            retry_policy: None,
+            query: None,
+            priority: Priority::default(),
         })
         .await
         .map_err(|err| WebSocketApiError::NodeError {
@@ -88,63 +122,133 @@ pub(super) async fn contract_home(
         }) => match contract {
             Some(contract) => {
                 let key = contract.key();
-                let path = contract_web_path(&key);
                 let state_bytes = state.as_ref();
-                let current_hash = hash_state(state_bytes);
-                let hash_path = state_hash_path(&key);
 
-                let needs_update = match tokio::fs::read(&hash_path).await {
-                    Ok(stored_hash_bytes) if stored_hash_bytes.len() == 8 => {
-                        let stored_hash = u64::from_be_bytes(stored_hash_bytes.try_into().unwrap());
-                        stored_hash != current_hash
+                fn err(err: WebContractError, contract: &ContractContainer) -> WebSocketApiError {
+                    let key = contract.key();
+                    tracing::error!("{err}");
+                    WebSocketApiError::InvalidParam {
+                        error_cause: format!("failed unpacking contract: {key}"),
                     }
-                    _ => true,
-                };
-
-                if needs_update {
-                    debug!("State changed or not cached, unpacking webapp");
-                    let state = State::from(state_bytes);
+                }
 
-                    fn err(
-                        err: WebContractError,
-                        contract: &ContractContainer,
-                    ) -> WebSocketApiError {
-                        let key = contract.key();
-                        tracing::error!("{err}");
+                // verified on every serve, the cache being keyed by the hash of the state
+                if let Some(pub_key) = webapp_policy.publisher_keys.get(key.id()).copied() {
+                    debug!("Verifying webapp signature against the trusted publisher");
+                    let limits = webapp_policy.limits;
+                    let signed_state = state.clone();
+                    tokio::task::spawn_blocking(move || {
+                        WebApp::try_from_with_limits(signed_state.as_ref(), &limits)
+                            .and_then(|webapp| webapp.verify(&pub_key))
+                    })
+                    .await
+                    .map_err(|e| WebSocketApiError::NodeError {
+                        error_cause: format!("Failed to verify webapp: {e}"),
+                    })?
+                    .map_err(|e| {
+                        tracing::error!("refusing to serve webapp of {key}: {e}");
                         WebSocketApiError::InvalidParam {
-                            error_cause: format!("failed unpacking contract: {key}"),
-                        }
-                    }
-
-                    // Clear existing cache if any
-                    let _ = tokio::fs::remove_dir_all(&path).await;
-                    tokio::fs::create_dir_all(&path).await.map_err(|e| {
-                        WebSocketApiError::NodeError {
-                            error_cause: format!("Failed to create cache dir: {e}"),
+                            error_cause: format!("untrusted webapp state for contract: {key}"),
                         }
                     })?;
-
-                    let mut web =
-                        WebApp::try_from(state.as_ref()).map_err(|e| err(e, &contract))?;
-                    web.unpack(&path).map_err(|e| err(e, &contract))?;
-
-                    // Store new hash
-                    tokio::fs::write(&hash_path, current_hash.to_be_bytes())
-                        .await
-                        .map_err(|e| WebSocketApiError::NodeError {
-                            error_cause: format!("Failed to write state hash: {e}"),
-                        })?;
                 }
 
-                match get_web_body(&path).await {
-                    Ok(b) => b.into_response(),
-                    Err(err) => {
-                        tracing::error!("Failed to read webapp after unpacking: {err}");
-                        return Err(WebSocketApiError::NodeError {
-                            error_cause: format!("Failed to read webapp: {err}"),
-                        });
+                let cache = webapp_policy.cache.clone();
+                let cached = match cache
+                    .get(key.id(), state_bytes)
+                    .await
+                    .map_err(|e| err(e, &contract))?
+                {
+                    Some(cached) => {
+                        debug!("Serving previously extracted webapp");
+                        cached
                     }
-                }
+                    None => {
+                        debug!("State changed or not cached, unpacking webapp");
+
+                        let limits = webapp_policy.limits;
+                        let contract_id = *key.id();
+                        let unpack_state = state.clone();
+                        let unpacked = if WebApp::is_chunked_state(state_bytes) {
+                            // the web archive is split across other contracts, fetch and
+                            // reassemble it on disk before unpacking
+                            let webapp = WebApp::try_from_with_limits(state_bytes, &limits)
+                                .map_err(|e| err(e, &contract))?;
+                            let web_chunks =
+                                webapp.web_chunks(&limits).map_err(|e| err(e, &contract))?;
+                            let web_path = cache
+                                .scratch_file(&contract_id)
+                                .await
+                                .map_err(|e| err(e, &contract))?;
+                            if let Err(e) = fetch_web_chunks(
+                                &request_sender,
+                                &mut response_recv,
+                                client_id,
+                                web_chunks,
+                                &web_path,
+                            )
+                            .await
+                            {
+                                let _ = tokio::fs::remove_file(&web_path).await;
+                                return Err(e);
+                            }
+                            let assembled_path = web_path.clone();
+                            let unpacked = tokio::task::spawn_blocking(move || {
+                                cache.insert(&contract_id, unpack_state.as_ref(), |dir| {
+                                    let web = std::fs::File::open(&assembled_path)
+                                        .map_err(WebContractError::StoringError)?;
+                                    webapp.unpack_assembled(std::io::BufReader::new(web), dir)?;
+                                    Ok(webapp.metadata.clone())
+                                })
+                            })
+                            .await;
+                            let _ = tokio::fs::remove_file(&web_path).await;
+                            unpacked
+                        } else {
+                            // decompress straight from the state into the cache dir, so large
+                            // apps are never fully decoded in memory
+                            tokio::task::spawn_blocking(move || {
+                                cache.insert(&contract_id, unpack_state.as_ref(), |dir| {
+                                    WebApp::unpack_streaming(
+                                        std::io::Cursor::new(unpack_state.as_ref()),
+                                        dir,
+                                        &limits,
+                                    )
+                                })
+                            })
+                            .await
+                        };
+                        let cached = unpacked
+                            .map_err(|e| WebSocketApiError::NodeError {
+                                error_cause: format!("Failed to unpack webapp: {e}"),
+                            })?
+                            .map_err(|e| err(e, &contract))?;
+                        let manifest = cached.manifest().await.map_err(|e| err(e, &contract))?;
+                        if manifest.directory_listing {
+                            let entries =
+                                WebApp::try_from_with_limits(state_bytes, &webapp_policy.limits)
+                                    .and_then(|webapp| webapp.list_entries())
+                                    .map_err(|e| err(e, &contract))?;
+                            let listing = serde_json::to_vec(&entries).map_err(|e| {
+                                WebSocketApiError::NodeError {
+                                    error_cause: format!(
+                                        "Failed to serialize directory listing: {e}"
+                                    ),
+                                }
+                            })?;
+                            tokio::fs::write(cached.listing_path(), listing)
+                                .await
+                                .map_err(|e| WebSocketApiError::NodeError {
+                                    error_cause: format!("Failed to write directory listing: {e}"),
+                                })?;
+                        }
+                        cached
+                    }
+                };
+                webapp_policy
+                    .cache
+                    .set_validated(key.id(), &cached, state_version);
+                serve_index(&key, &cached, &webapp_policy, &req_headers).await?
             }
             None => {
                 return Err(WebSocketApiError::MissingContract { key });
@@ -170,24 +274,88 @@ pub(super) async fn contract_home(
             });
         }
     };
-    request_sender
-        .send(ClientConnection::Request {
-            client_id,
-            req: Box::new(ClientRequest::Disconnect { cause: None }),
-            auth_token: None,
-            attested_contract: None,
-        })
-        .await
-        .map_err(|err| WebSocketApiError::NodeError {
-            error_cause: format!("{err}"),
-        })?;
     Ok(response)
 }
 
-#[instrument(level = "debug")]
+/// Serves the index of an extracted webapp.
+async fn serve_index(
+    key: &ContractKey,
+    cached: &CachedWebApp,
+    webapp_policy: &WebAppPolicy,
+    req_headers: &HeaderMap,
+) -> Result<Response, WebSocketApiError> {
+    let manifest = read_manifest(key, cached).await;
+    let etag = state_etag(cached);
+    if is_not_modified(req_headers, &etag) {
+        debug!("contract_home: Webapp not modified since last request");
+        return Ok(not_modified(etag));
+    }
+    let index_path = cached.files_dir().join("index.html");
+    match webapp_policy.cache.read_file(cached, "index.html").await {
+        Ok(body) => {
+            let last_modified = tokio::fs::metadata(&index_path)
+                .await
+                .and_then(|m| m.modified())
+                .ok();
+            let response = with_cache_headers(
+                with_manifest_headers(Html(body).into_response(), &manifest, "index.html"),
+                etag,
+                last_modified,
+            );
+            Ok(webapp_policy
+                .compression
+                .compress(response, req_headers, cached.state_hash(), "index.html")
+                .await)
+        }
+        Err(err) => {
+            tracing::error!("Failed to read webapp after unpacking: {err}");
+            error_page(
+                webapp_policy,
+                cached,
+                &manifest,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+            .await
+            .ok_or_else(|| WebSocketApiError::NodeError {
+                error_cause: format!("Failed to read webapp: {err}"),
+            })
+        }
+    }
+}
+
+/// A client of the node registered to serve a webapp, disconnected from the node when dropped.
+struct WebClient {
+    id: ClientId,
+    request_sender: HttpGatewayRequest,
+}
+
+impl Drop for WebClient {
+    fn drop(&mut self) {
+        let request_sender = self.request_sender.clone();
+        let client_id = self.id;
+        tokio::spawn(async move {
+            let _ = request_sender
+                .send(ClientConnection::Request {
+                    client_id,
+                    req: Box::new(ClientRequest::Disconnect { cause: None }),
+                    auth_token: None,
+                    attested_contract: None,
+                    expected_state: None,
+                    retry_policy: None,
+                    query: None,
+                    priority: Priority::default(),
+                })
+                .await;
+        });
+    }
+}
+
+#[instrument(level = "debug", skip(webapp_policy))]
 pub(super) async fn variable_content(
     key: String,
     req_path: String,
+    req_headers: HeaderMap,
+    webapp_policy: &WebAppPolicy,
 ) -> Result<impl IntoResponse, Box<WebSocketApiError>> {
     debug!(
         "variable_content: Processing request for key: {}, path: {}",
@@ -197,7 +365,11 @@ pub(super) async fn variable_content(
     let key = ContractKey::from_id(key).map_err(|err| WebSocketApiError::InvalidParam {
         error_cause: format!("{err}"),
     })?;
-    let base_path = contract_web_path(&key);
+    let Some(cached) = webapp_policy.cache.current(key.id()).await else {
+        debug!("variable_content: Webapp of {key} has not been extracted");
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let base_path = cached.files_dir();
     debug!("variable_content: Base path resolved to: {:?}", base_path);
 
     // Parse the full request path URI to extract the relative path using the v1 helper.
@@ -215,71 +387,437 @@ pub(super) async fn variable_content(
         relative_path
     );
 
-    let file_path = base_path.join(relative_path);
+    let manifest = read_manifest(&key, &cached).await;
+    let mut served_path = relative_path.as_str();
+    let mut file_path = base_path.join(served_path);
+    if !is_file(&file_path).await {
+        if let Some(fallback) = &manifest.spa_fallback {
+            debug!("variable_content: Serving SPA fallback {fallback} for {served_path}");
+            served_path = fallback.as_str();
+            file_path = base_path.join(fallback);
+        }
+    }
     debug!("variable_content: Full file path to serve: {:?}", file_path);
-    debug!(
-        "variable_content: Checking if file exists: {}",
-        file_path.exists()
+
+    // precompressed assets are served as is, so the client must be able to decode them
+    if let Some(encoding) = manifest.content_encoding(served_path) {
+        if !accepts_encoding(&req_headers, encoding) {
+            debug!("variable_content: Client does not accept {encoding} encoded {served_path}");
+            return Ok(StatusCode::NOT_ACCEPTABLE.into_response());
+        }
+    }
+
+    let etag = state_etag(&cached);
+    if is_not_modified(&req_headers, &etag) {
+        debug!("variable_content: {served_path} not modified since last request");
+        return Ok(not_modified(etag));
+    }
+
+    // serve the file, range requests are answered from disk and whole files from memory
+    let served = if req_headers.contains_key(header::RANGE) || !is_file(&file_path).await {
+        // ServeFile already sets `Last-Modified` from the extracted file
+        serve_file(&file_path, &req_headers).await.map(|response| {
+            with_cache_headers(
+                with_manifest_headers(response, &manifest, served_path),
+                etag,
+                None,
+            )
+        })
+    } else {
+        async {
+            let contents = webapp_policy.cache.read_file(&cached, served_path).await?;
+            let last_modified = tokio::fs::metadata(&file_path)
+                .await
+                .and_then(|m| m.modified())
+                .ok();
+            let content_type = mime_guess::from_path(&file_path).first_or_octet_stream();
+            let response =
+                ([(header::CONTENT_TYPE, content_type.as_ref())], contents).into_response();
+            Ok::<_, std::io::Error>(with_cache_headers(
+                with_manifest_headers(response, &manifest, served_path),
+                etag,
+                last_modified,
+            ))
+        }
+        .await
+    };
+    let response = match served {
+        Ok(response) if response.status() == StatusCode::NOT_FOUND => {
+            error_page(webapp_policy, &cached, &manifest, StatusCode::NOT_FOUND)
+                .await
+                .unwrap_or(response)
+        }
+        Ok(response) => response,
+        Err(err) => {
+            tracing::error!("Failed serving {served_path} of {key}: {err}");
+            return error_page(
+                webapp_policy,
+                &cached,
+                &manifest,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+            .await
+            .ok_or_else(|| {
+                Box::new(WebSocketApiError::NodeError {
+                    error_cause: format!("{err}"),
+                })
+            });
+        }
+    };
+    Ok(webapp_policy
+        .compression
+        .compress(response, &req_headers, cached.state_hash(), served_path)
+        .await)
+}
+
+/// Serves the page the manifest declares for the error `status`, if any.
+async fn error_page(
+    webapp_policy: &WebAppPolicy,
+    cached: &CachedWebApp,
+    manifest: &WebAppManifest,
+    status: StatusCode,
+) -> Option<Response> {
+    let page = manifest.error_page(status.as_u16())?;
+    let contents = webapp_policy
+        .cache
+        .read_file(cached, page)
+        .await
+        .inspect_err(|err| tracing::warn!("Failed reading {status} error page {page}: {err}"))
+        .ok()?;
+    let content_type = manifest.content_type(page).map_or_else(
+        || {
+            mime_guess::from_path(page)
+                .first_or_octet_stream()
+                .to_string()
+        },
+        str::to_owned,
     );
+    Some((status, [(header::CONTENT_TYPE, content_type)], contents).into_response())
+}
 
-    // serve the file
-    let mut serve_file = tower_http::services::fs::ServeFile::new(&file_path);
-    let fake_req = axum::http::Request::new(axum::body::Body::empty());
-    serve_file
-        .try_call(fake_req)
+/// Serves an extracted file, answering range requests with `206 Partial Content`.
+async fn serve_file(file_path: &Path, req_headers: &HeaderMap) -> std::io::Result<Response> {
+    let mut req = axum::http::Request::new(axum::body::Body::empty());
+    for name in [header::RANGE, header::IF_RANGE] {
+        if let Some(value) = req_headers.get(&name) {
+            req.headers_mut().insert(name, value.clone());
+        }
+    }
+    let response = tower_http::services::fs::ServeFile::new(file_path)
+        .try_call(req)
+        .await?;
+    Ok(response.into_response())
+}
+
+/// Fetches the states holding the chunks of a web archive through the given client connection,
+/// appending them to the file at `web_path` as they are verified.
+async fn fetch_web_chunks(
+    request_sender: &HttpGatewayRequest,
+    response_recv: &mut mpsc::UnboundedReceiver<HostCallbackResult>,
+    client_id: ClientId,
+    mut chunks: WebChunks,
+    web_path: &Path,
+) -> Result<(), WebSocketApiError> {
+    let storing_error = |err: std::io::Error| WebSocketApiError::NodeError {
+        error_cause: format!("Failed to store web chunk: {err}"),
+    };
+    let mut web = tokio::fs::File::create(web_path)
         .await
-        .map_err(|err| {
-            WebSocketApiError::NodeError {
+        .map_err(storing_error)?;
+    while let Some(chunk) = chunks.next_ref() {
+        let key = ContractKey::from(chunk.contract);
+        debug!("Fetching web chunk from contract {key}");
+        request_sender
+            .send(ClientConnection::Request {
+                client_id,
+                req: Box::new(
+                    ContractRequest::Get {
+                        key,
+                        return_contract_code: false,
+                        subscribe: false,
+                    }
+                    .into(),
+                ),
+                auth_token: None,
+                attested_contract: None,
+                expected_state: None,
+                retry_policy: None,
+                query: None,
+                priority: Priority::default(),
+            })
+            .await
+            .map_err(|err| WebSocketApiError::NodeError {
                 error_cause: format!("{err}"),
+            })?;
+        match response_recv.recv().await {
+            Some(HostCallbackResult::Result {
+                result:
+                    Ok(HostResponse::ContractResponse(ContractResponse::GetResponse { state, .. })),
+                ..
+            }) => {
+                chunks.verify(state.as_ref()).map_err(|err| {
+                    tracing::error!("invalid web chunk `{key}`: {err}");
+                    WebSocketApiError::InvalidParam {
+                        error_cause: format!("invalid web chunk: {key}"),
+                    }
+                })?;
+                web.write_all(state.as_ref()).await.map_err(storing_error)?;
             }
-            .into()
-        })
-        .map(|r| r.into_response())
+            Some(HostCallbackResult::Result {
+                result: Err(err), ..
+            }) => {
+                tracing::error!("error getting web chunk `{key}`: {err}");
+                return Err(WebSocketApiError::AxumError {
+                    error: err.kind().clone(),
+                });
+            }
+            other => {
+                return Err(WebSocketApiError::NodeError {
+                    error_cause: format!("Unexpected response fetching web chunk {key}: {other:?}"),
+                });
+            }
+        }
+    }
+    web.flush().await.map_err(storing_error)
 }
 
-#[instrument(level = "debug")]
-async fn get_web_body(path: &Path) -> Result<impl IntoResponse, WebSocketApiError> {
-    debug!(
-        "get_web_body: Attempting to read index.html from path: {:?}",
-        path
-    );
-    let web_path = path.join("index.html");
-    debug!("get_web_body: Full web path: {:?}", web_path);
-    let mut key_file = File::open(&web_path)
-        .await
-        .map_err(|err| WebSocketApiError::NodeError {
-            error_cause: format!("{err}"),
-        })?;
-    let mut buf = vec![];
-    key_file
-        .read_to_end(&mut buf)
-        .await
-        .map_err(|err| WebSocketApiError::NodeError {
-            error_cause: format!("{err}"),
-        })?;
-    let body = String::from_utf8(buf).map_err(|err| WebSocketApiError::NodeError {
+/// Serves the JSON listing of the files of a webapp, for webapps which opted in through
+/// their manifest.
+#[instrument(level = "debug", skip(cache))]
+pub(super) async fn contract_listing(
+    key: String,
+    cache: &WebAppCache,
+) -> Result<Response, WebSocketApiError> {
+    let key = ContractKey::from_id(key).map_err(|err| WebSocketApiError::InvalidParam {
         error_cause: format!("{err}"),
     })?;
-    Ok(Html(body))
+    let Some(cached) = cache.current(key.id()).await else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    match tokio::fs::read(cached.listing_path()).await {
+        Ok(listing) => Ok((
+            [(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            )],
+            listing,
+        )
+            .into_response()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            Ok(StatusCode::NOT_FOUND.into_response())
+        }
+        Err(err) => Err(WebSocketApiError::NodeError {
+            error_cause: format!("{err}"),
+        }),
+    }
+}
+
+/// Applies the per file headers requested by the webapp manifest to a successful response.
+fn with_manifest_headers(
+    mut response: Response,
+    manifest: &WebAppManifest,
+    path: &str,
+) -> Response {
+    if !response.status().is_success() {
+        return response;
+    }
+    let headers = response.headers_mut();
+    if let Some(content_type) = manifest
+        .content_type(path)
+        .and_then(|v| HeaderValue::from_str(v).ok())
+    {
+        headers.insert(header::CONTENT_TYPE, content_type);
+    }
+    if let Some(cache_control) = manifest
+        .cache_control(path)
+        .and_then(|v| HeaderValue::from_str(v).ok())
+    {
+        headers.insert(header::CACHE_CONTROL, cache_control);
+    }
+    if let Some(encoding) = manifest
+        .content_encoding(path)
+        .and_then(|v| HeaderValue::from_str(v).ok())
+    {
+        headers.insert(header::CONTENT_ENCODING, encoding);
+        headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+    }
+    response
+}
+
+/// Entity tag of the files extracted from a webapp state, which change along with the state.
+fn state_etag(cached: &CachedWebApp) -> ETag {
+    format!("\"{}\"", cached.state_hash())
+        .parse()
+        .expect("hex encoded hash is a valid entity tag")
+}
+
+/// Whether the client already holds the current version according to `If-None-Match`.
+fn is_not_modified(req_headers: &HeaderMap, etag: &ETag) -> bool {
+    req_headers
+        .typed_get::<IfNoneMatch>()
+        .is_some_and(|if_none_match| !if_none_match.precondition_passes(etag))
 }
 
-fn contract_web_path(key: &ContractKey) -> PathBuf {
-    std::env::temp_dir()
-        .join("freenet")
-        .join("webapp_cache")
-        .join(key.encoded_contract_id())
+fn not_modified(etag: ETag) -> Response {
+    let mut response = StatusCode::NOT_MODIFIED.into_response();
+    response.headers_mut().typed_insert(etag);
+    response
 }
 
-fn hash_state(state: &[u8]) -> u64 {
-    use std::hash::Hasher;
-    let mut hasher = ahash::AHasher::default();
-    hasher.write(state);
-    hasher.finish()
+/// Adds the validators of the webapp state to a successful response, so clients revalidate
+/// their copy instead of downloading it again. Unless the manifest requests otherwise,
+/// clients must revalidate before reusing their copy.
+fn with_cache_headers(
+    mut response: Response,
+    etag: ETag,
+    last_modified: Option<std::time::SystemTime>,
+) -> Response {
+    if !response.status().is_success() {
+        return response;
+    }
+    let headers = response.headers_mut();
+    headers.typed_insert(etag);
+    headers
+        .entry(header::CACHE_CONTROL)
+        .or_insert(HeaderValue::from_static("no-cache"));
+    if let Some(last_modified) = last_modified {
+        headers.typed_insert(LastModified::from(last_modified));
+    }
+    response
 }
 
-fn state_hash_path(key: &ContractKey) -> PathBuf {
-    std::env::temp_dir()
-        .join("freenet")
-        .join("webapp_cache")
-        .join(format!("{}.hash", key.encoded_contract_id()))
+/// Whether the `Accept-Encoding` header of the request allows the given content coding.
+fn accepts_encoding(req_headers: &HeaderMap, encoding: &str) -> bool {
+    req_headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|coding| {
+            let mut params = coding.split(';');
+            let name = params.next().unwrap_or_default().trim();
+            let rejected = params.any(|p| {
+                p.trim()
+                    .strip_prefix("q=")
+                    .and_then(|q| q.trim().parse::<f32>().ok())
+                    == Some(0.0)
+            });
+            (name.eq_ignore_ascii_case(encoding) || name == "*") && !rejected
+        })
+}
+
+async fn read_manifest(key: &ContractKey, cached: &CachedWebApp) -> WebAppManifest {
+    cached.manifest().await.unwrap_or_else(|err| {
+        tracing::warn!("ignoring invalid cached manifest for {key}: {err}");
+        WebAppManifest::default()
+    })
+}
+
+async fn is_file(path: &Path) -> bool {
+    tokio::fs::metadata(path)
+        .await
+        .is_ok_and(|metadata| metadata.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_encoding_negotiation() {
+        let mut headers = HeaderMap::new();
+        assert!(!accepts_encoding(&headers, "br"));
+
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            HeaderValue::from_static("gzip, deflate;q=0.5, br;q=0"),
+        );
+        assert!(accepts_encoding(&headers, "gzip"));
+        assert!(accepts_encoding(&headers, "GZIP"));
+        assert!(!accepts_encoding(&headers, "br"));
+
+        headers.insert(header::ACCEPT_ENCODING, HeaderValue::from_static("*"));
+        assert!(accepts_encoding(&headers, "br"));
+    }
+
+    #[test]
+    fn etag_revalidation() {
+        let etag: ETag = "\"abc\"".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        assert!(!is_not_modified(&headers, &etag));
+
+        headers.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_static("\"old\", \"abc\""),
+        );
+        assert!(is_not_modified(&headers, &etag));
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"old\""));
+        assert!(!is_not_modified(&headers, &etag));
+
+        let response = with_cache_headers(StatusCode::OK.into_response(), etag.clone(), None);
+        assert_eq!(response.headers().typed_get::<ETag>(), Some(etag));
+        assert_eq!(
+            response.headers().get(header::CACHE_CONTROL),
+            Some(&HeaderValue::from_static("no-cache"))
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_webapp_loads_disconnect() {
+        let (request_sender, mut requests) = mpsc::channel(8);
+        let key = ContractInstanceId::new([1; 32]);
+        let node = tokio::spawn(async move {
+            let Some(ClientConnection::NewConnection { callbacks, .. }) = requests.recv().await
+            else {
+                panic!("expected a new connection");
+            };
+            let id = ClientId::next();
+            callbacks.send(HostCallbackResult::NewId { id }).unwrap();
+            let Some(ClientConnection::Request { .. }) = requests.recv().await else {
+                panic!("expected the get request");
+            };
+            let result = Err(freenet_stdlib::client_api::ErrorKind::Disconnect.into());
+            callbacks
+                .send(HostCallbackResult::Result { id, result })
+                .unwrap();
+            let Some(ClientConnection::Request { client_id, req, .. }) = requests.recv().await
+            else {
+                panic!("expected the client to disconnect");
+            };
+            assert_eq!(client_id, id);
+            assert!(matches!(*req, ClientRequest::Disconnect { .. }));
+        });
+        let response = contract_home(
+            key.to_string(),
+            HttpGatewayRequest(request_sender),
+            AuthToken::generate(),
+            WebAppPolicy::default(),
+            HeaderMap::new(),
+        )
+        .await;
+        assert!(response.is_err());
+        node.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn range_requests() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let file_path = dir.path().join("media.bin");
+        std::fs::write(&file_path, b"0123456789")?;
+
+        let full = serve_file(&file_path, &HeaderMap::new()).await?;
+        assert_eq!(full.status(), StatusCode::OK);
+        assert_eq!(
+            full.headers().get(header::ACCEPT_RANGES),
+            Some(&HeaderValue::from_static("bytes"))
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, HeaderValue::from_static("bytes=2-4"));
+        let partial = serve_file(&file_path, &headers).await?;
+        assert_eq!(partial.status(), StatusCode::PARTIAL_CONTENT);
+        let body = axum::body::to_bytes(partial.into_body(), usize::MAX).await?;
+        assert_eq!(body.as_ref(), b"234");
+        Ok(())
+    }
 }
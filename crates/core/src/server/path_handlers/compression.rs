@@ -0,0 +1,293 @@
+//! Compression of webapp files served by the gateway, negotiated from the `Accept-Encoding`
+//! header of the request.
+
+use std::{fmt, io::Write, sync::Arc};
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::Response,
+};
+use stretto::Cache;
+
+use super::accepts_encoding;
+
+/// Responses smaller than this are served uncompressed unless configured otherwise.
+pub(crate) const DEFAULT_MIN_SIZE: u64 = 1024;
+
+/// Number of counters used to track the access frequency of encoded responses.
+const CACHE_COUNTERS: usize = 10_000;
+
+/// Max bytes of encoded responses kept in memory.
+const CACHE_SIZE: i64 = 32 * 1024 * 1024;
+
+/// Compression level favouring speed, since responses are encoded on the fly.
+const BROTLI_QUALITY: u32 = 5;
+
+const BROTLI_WINDOW_BITS: u32 = 22;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Coding {
+    Brotli,
+    Gzip,
+}
+
+impl Coding {
+    /// Content codings supported by the gateway, in order of preference.
+    const PREFERRED: [Coding; 2] = [Coding::Brotli, Coding::Gzip];
+
+    fn negotiate(req_headers: &HeaderMap) -> Option<Self> {
+        Self::PREFERRED
+            .into_iter()
+            .find(|coding| accepts_encoding(req_headers, coding.as_str()))
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Coding::Brotli => "br",
+            Coding::Gzip => "gzip",
+        }
+    }
+
+    fn encode(self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Coding::Brotli => {
+                let mut encoded = Vec::new();
+                {
+                    let mut writer = brotli::CompressorWriter::new(
+                        &mut encoded,
+                        4096,
+                        BROTLI_QUALITY,
+                        BROTLI_WINDOW_BITS,
+                    );
+                    writer.write_all(data)?;
+                }
+                Ok(encoded)
+            }
+            Coding::Gzip => {
+                let mut writer =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                writer.write_all(data)?;
+                writer.finish()
+            }
+        }
+    }
+}
+
+/// Compresses successful webapp responses, caching the encoded bodies by webapp state,
+/// path and content coding so each file is only compressed once per state.
+#[derive(Clone)]
+pub(crate) struct ResponseCompression {
+    min_size: u64,
+    cache: Arc<Cache<String, Bytes>>,
+}
+
+impl Default for ResponseCompression {
+    fn default() -> Self {
+        Self::new(DEFAULT_MIN_SIZE)
+    }
+}
+
+impl fmt::Debug for ResponseCompression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseCompression")
+            .field("min_size", &self.min_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ResponseCompression {
+    pub fn new(min_size: u64) -> Self {
+        Self {
+            min_size,
+            cache: Arc::new(
+                Cache::new(CACHE_COUNTERS, CACHE_SIZE)
+                    .expect("failed to build encoded response cache"),
+            ),
+        }
+    }
+
+    /// Encodes the body of the response with the preferred coding accepted by the client.
+    ///
+    /// Partial, already encoded, small or not compressible responses are returned as is.
+    pub async fn compress(
+        &self,
+        response: Response,
+        req_headers: &HeaderMap,
+        state_hash: &str,
+        path: &str,
+    ) -> Response {
+        if response.status() != StatusCode::OK
+            || response.headers().contains_key(header::CONTENT_ENCODING)
+            || !response
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(is_compressible)
+        {
+            return response;
+        }
+        let size = response
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok()?.parse().ok())
+            .or_else(|| response.body().size_hint().exact());
+        if !size.is_some_and(|size| size >= self.min_size) {
+            return response;
+        }
+
+        let (mut parts, body) = response.into_parts();
+        parts
+            .headers
+            .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+        let Some(coding) = Coding::negotiate(req_headers) else {
+            return Response::from_parts(parts, body);
+        };
+
+        let cache_key = format!("{state_hash}:{}:{path}", coding.as_str());
+        let cached = self.cache.get(&cache_key).map(|v| v.value().clone());
+        let encoded = match cached {
+            Some(encoded) => encoded,
+            None => {
+                let data = match axum::body::to_bytes(body, usize::MAX).await {
+                    Ok(data) => data,
+                    Err(err) => {
+                        tracing::error!("failed reading response of {path}: {err}");
+                        return Response::from_parts(parts, Body::empty());
+                    }
+                };
+                let to_encode = data.clone();
+                let encoded = tokio::task::spawn_blocking(move || coding.encode(&to_encode))
+                    .await
+                    .map_err(std::io::Error::other)
+                    .and_then(|encoded| encoded);
+                match encoded {
+                    Ok(encoded) if encoded.len() < data.len() => {
+                        let encoded = Bytes::from(encoded);
+                        self.cache
+                            .insert(cache_key, encoded.clone(), encoded.len() as i64);
+                        encoded
+                    }
+                    Ok(_) => return Response::from_parts(parts, Body::from(data)),
+                    Err(err) => {
+                        tracing::warn!("failed compressing {path}: {err}");
+                        return Response::from_parts(parts, Body::from(data));
+                    }
+                }
+            }
+        };
+
+        let headers = &mut parts.headers;
+        headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(coding.as_str()),
+        );
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(encoded.len()));
+        // ranges would refer to the encoded bytes
+        headers.remove(header::ACCEPT_RANGES);
+        // the encoded body is not byte for byte equal to the representation the tag identifies
+        if let Some(etag) = headers
+            .get(header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .filter(|etag| !etag.starts_with("W/"))
+            .and_then(|etag| HeaderValue::from_str(&format!("W/{etag}")).ok())
+        {
+            headers.insert(header::ETAG, etag);
+        }
+        Response::from_parts(parts, Body::from(encoded))
+    }
+}
+
+/// Whether compressing content of this type is worth it, media and archives are already compressed.
+fn is_compressible(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || matches!(
+            essence.as_str(),
+            "application/javascript"
+                | "application/json"
+                | "application/wasm"
+                | "application/xml"
+                | "image/svg+xml"
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use axum::response::IntoResponse;
+
+    use super::*;
+
+    fn response(body: String) -> Response {
+        (
+            [
+                (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+                (header::ETAG, "\"hash\""),
+            ],
+            body,
+        )
+            .into_response()
+    }
+
+    #[tokio::test]
+    async fn negotiated_compression() -> Result<(), Box<dyn std::error::Error>> {
+        let body = "<p>hello</p>".repeat(100);
+        let compression = ResponseCompression::new(256);
+        let mut req_headers = HeaderMap::new();
+        req_headers.insert(
+            header::ACCEPT_ENCODING,
+            HeaderValue::from_static("gzip, br;q=0"),
+        );
+
+        let compressed = compression
+            .compress(response(body.clone()), &req_headers, "hash", "index.html")
+            .await;
+        assert_eq!(compressed.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(compressed.headers()[header::VARY], "accept-encoding");
+        assert_eq!(compressed.headers()[header::ETAG], "W/\"hash\"");
+        let encoded = axum::body::to_bytes(compressed.into_body(), usize::MAX).await?;
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(encoded.as_ref()).read_to_string(&mut decoded)?;
+        assert_eq!(decoded, body);
+        compression.cache.wait()?;
+        assert!(compression.cache.get("hash:gzip:index.html").is_some());
+
+        // below the threshold
+        let small = compression
+            .compress(
+                response("<p>hello</p>".into()),
+                &req_headers,
+                "hash",
+                "small.html",
+            )
+            .await;
+        assert!(!small.headers().contains_key(header::CONTENT_ENCODING));
+
+        // not accepted by the client
+        let identity = compression
+            .compress(response(body), &HeaderMap::new(), "hash", "index.html")
+            .await;
+        assert!(!identity.headers().contains_key(header::CONTENT_ENCODING));
+        assert_eq!(identity.headers()[header::VARY], "accept-encoding");
+        Ok(())
+    }
+
+    #[test]
+    fn compressible_content_types() {
+        assert!(is_compressible("text/html; charset=utf-8"));
+        assert!(is_compressible("application/javascript"));
+        assert!(is_compressible("application/manifest+json"));
+        assert!(is_compressible("image/svg+xml"));
+        assert!(!is_compressible("image/png"));
+        assert!(!is_compressible("application/zip"));
+    }
+}
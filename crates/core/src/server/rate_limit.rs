@@ -0,0 +1,145 @@
+//! Token bucket rate limiting of the HTTP gateway and websocket API requests.
+
+use std::{
+    hash::Hash,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Instant,
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Router,
+};
+use dashmap::DashMap;
+
+use crate::{config::RateLimitConfig, util::token_bucket::TokenBucket};
+
+/// Number of tracked clients (or contracts) above which full buckets are dropped.
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+
+/// Per key token buckets, refilled at `rate` tokens per second up to `burst` tokens.
+struct RateLimiter<K> {
+    rate: f64,
+    burst: f64,
+    buckets: DashMap<K, TokenBucket>,
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    fn new(requests_per_second: u32, burst: u32) -> Self {
+        Self {
+            rate: requests_per_second as f64,
+            burst: burst.max(1) as f64,
+            buckets: DashMap::new(),
+        }
+    }
+
+    /// Takes a token from the bucket of `key`, returns false if it is empty.
+    fn check(&self, key: K, now: Instant) -> bool {
+        if self.buckets.len() > MAX_TRACKED_BUCKETS {
+            self.prune(now);
+        }
+        self.buckets
+            .entry(key)
+            .or_insert_with(|| TokenBucket::new(self.rate, self.burst, now))
+            .try_take(1, now)
+    }
+
+    /// Drops the buckets which are full again, those behave the same as new ones.
+    fn prune(&self, now: Instant) {
+        self.buckets.retain(|_, bucket| !bucket.is_full(now));
+    }
+}
+
+struct RateLimits {
+    per_ip: RateLimiter<IpAddr>,
+    per_contract: RateLimiter<String>,
+}
+
+/// Limits the rate of requests per client IP, and per contract for the requests addressing
+/// a contract in their path (e.g. `/v1/contract/web/<key>/`).
+///
+/// Websocket connections count as a single request when opened.
+pub(super) fn with_rate_limit(router: Router, config: RateLimitConfig) -> Router {
+    let limits = Arc::new(RateLimits {
+        per_ip: RateLimiter::new(config.ip_requests_per_second, config.ip_burst),
+        per_contract: RateLimiter::new(config.contract_requests_per_second, config.contract_burst),
+    });
+    router.layer(axum::middleware::from_fn_with_state(limits, rate_limit))
+}
+
+async fn rate_limit(State(limits): State<Arc<RateLimits>>, req: Request, next: Next) -> Response {
+    let now = Instant::now();
+    if let Some(ConnectInfo(addr)) = req.extensions().get::<ConnectInfo<SocketAddr>>() {
+        if !limits.per_ip.check(addr.ip(), now) {
+            tracing::debug!(client = %addr.ip(), "Rate limited client");
+            return too_many_requests();
+        }
+    }
+    if let Some(contract) = contract_from_path(req.uri().path()) {
+        if !limits.per_contract.check(contract.to_owned(), now) {
+            tracing::debug!(%contract, "Rate limited contract");
+            return too_many_requests();
+        }
+    }
+    next.run(req).await
+}
+
+fn too_many_requests() -> Response {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, HeaderValue::from_static("1"))],
+    )
+        .into_response()
+}
+
+/// Contract key addressed by the request path, if any.
+fn contract_from_path(path: &str) -> Option<&str> {
+    let mut segments = path.trim_start_matches('/').split('/');
+    match (segments.next(), segments.next(), segments.next()) {
+        (Some("v1"), Some("contract"), Some(_)) => segments.next().filter(|key| !key.is_empty()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn token_bucket() {
+        let limiter = RateLimiter::new(2, 3);
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter.check("client", start));
+        }
+        assert!(!limiter.check("client", start));
+        assert!(limiter.check("other", start));
+
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.check("client", later));
+        assert!(!limiter.check("client", later));
+
+        limiter.prune(start + Duration::from_secs(10));
+        assert!(limiter.buckets.is_empty());
+    }
+
+    #[test]
+    fn contract_paths() {
+        assert_eq!(
+            contract_from_path("/v1/contract/web/HjpgVdSz/index.html"),
+            Some("HjpgVdSz")
+        );
+        assert_eq!(
+            contract_from_path("/v1/contract/listing/HjpgVdSz"),
+            Some("HjpgVdSz")
+        );
+        assert_eq!(contract_from_path("/v1/contract/command"), None);
+        assert_eq!(contract_from_path("/v1"), None);
+    }
+}
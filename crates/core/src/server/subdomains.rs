@@ -0,0 +1,151 @@
+//! Routing of `<contract>.<domain>` hostnames to the webapp of the contract, so every webapp
+//! is served from its own origin instead of sharing the one of the gateway.
+
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Router,
+};
+use data_encoding::BASE32_NOPAD;
+use freenet_stdlib::prelude::ContractInstanceId;
+
+use crate::config::SubdomainConfig;
+
+/// Marks requests for a webapp addressed through its subdomain.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct WebAppSubdomain;
+
+struct SubdomainRouting {
+    /// Lowercase domain, without leading or trailing dots.
+    domain: String,
+    aliases: HashMap<String, ContractInstanceId>,
+}
+
+impl SubdomainRouting {
+    /// Resolves the contract addressed by the host, `None` if the host is not a subdomain,
+    /// `Some(None)` if it is one but addresses no known contract.
+    fn contract(&self, host: &str) -> Option<Option<ContractInstanceId>> {
+        let hostname = host
+            .rsplit_once(':')
+            .filter(|(_, port)| port.chars().all(|c| c.is_ascii_digit()))
+            .map_or(host, |(hostname, _)| hostname)
+            .trim_end_matches('.')
+            .to_ascii_lowercase();
+        let label = hostname
+            .strip_suffix(self.domain.as_str())?
+            .strip_suffix('.')?;
+        if label.is_empty() || label.contains('.') {
+            return Some(None);
+        }
+        if let Some(contract) = self.aliases.get(label) {
+            return Some(Some(*contract));
+        }
+        let contract = BASE32_NOPAD
+            .decode(label.to_ascii_uppercase().as_bytes())
+            .ok()
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .map(ContractInstanceId::new);
+        Some(contract)
+    }
+}
+
+/// Serves webapps from their subdomains, requests to other hosts are routed as usual.
+///
+/// Paths under `/v1/` are not rewritten, so webapps reach the websocket API from their
+/// own origin.
+pub(super) fn with_subdomains(router: Router, config: SubdomainConfig) -> anyhow::Result<Router> {
+    let routing = Arc::new(SubdomainRouting {
+        domain: config.domain.trim_matches('.').to_ascii_lowercase(),
+        aliases: config.contract_aliases()?,
+    });
+    // routing happens in the inner router, after the path was rewritten
+    Ok(Router::new()
+        .fallback_service(router)
+        .layer(axum::middleware::from_fn_with_state(
+            routing,
+            route_subdomain,
+        )))
+}
+
+async fn route_subdomain(
+    State(routing): State<Arc<SubdomainRouting>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let contract = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| routing.contract(host));
+    let contract = match contract {
+        None => return next.run(req).await,
+        Some(None) => return (StatusCode::NOT_FOUND, "Unknown webapp").into_response(),
+        Some(Some(contract)) => contract,
+    };
+    if req.uri().path().starts_with("/v1/") {
+        return next.run(req).await;
+    }
+    let Ok(uri) = webapp_uri(&contract, req.uri()) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    tracing::debug!(%contract, from = %req.uri(), to = %uri, "Routing webapp subdomain");
+    *req.uri_mut() = uri;
+    req.extensions_mut().insert(WebAppSubdomain);
+    next.run(req).await
+}
+
+fn webapp_uri(contract: &ContractInstanceId, uri: &Uri) -> Result<Uri, axum::http::Error> {
+    let path_and_query = match uri.query() {
+        Some(query) => format!("/v1/contract/web/{contract}{}?{query}", uri.path()),
+        None => format!("/v1/contract/web/{contract}{}", uri.path()),
+    };
+    Ok(Uri::builder().path_and_query(path_and_query).build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_subdomains() {
+        let chat = ContractInstanceId::new([1; 32]);
+        let other = ContractInstanceId::new([2; 32]);
+        let routing = SubdomainRouting {
+            domain: "gateway.example".into(),
+            aliases: HashMap::from([("chat".to_owned(), chat)]),
+        };
+        assert_eq!(routing.contract("chat.gateway.example"), Some(Some(chat)));
+        assert_eq!(
+            routing.contract("Chat.Gateway.Example:8080"),
+            Some(Some(chat))
+        );
+        let label = BASE32_NOPAD.encode(other.as_bytes()).to_ascii_lowercase();
+        assert_eq!(
+            routing.contract(&format!("{label}.gateway.example")),
+            Some(Some(other))
+        );
+        assert_eq!(routing.contract("unknown.gateway.example"), Some(None));
+        assert_eq!(routing.contract("a.chat.gateway.example"), Some(None));
+        assert_eq!(routing.contract("gateway.example"), None);
+        assert_eq!(routing.contract("chat.evilgateway.example"), None);
+        assert_eq!(routing.contract("127.0.0.1:50509"), None);
+    }
+
+    #[test]
+    fn rewrite_uri() -> Result<(), Box<dyn std::error::Error>> {
+        let contract = ContractInstanceId::new([1; 32]);
+        let uri = webapp_uri(&contract, &"/".parse()?)?;
+        assert_eq!(uri.path(), format!("/v1/contract/web/{contract}/"));
+        let uri = webapp_uri(&contract, &"/assets/app.js?v=2".parse()?)?;
+        assert_eq!(
+            uri.path(),
+            format!("/v1/contract/web/{contract}/assets/app.js")
+        );
+        assert_eq!(uri.query(), Some("v=2"));
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+//! TLS termination for the HTTP gateway and websocket API.
+
+use std::net::SocketAddr;
+
+use axum_server::tls_rustls::RustlsConfig;
+use futures::StreamExt;
+use rustls_acme::{caches::DirCache, AcmeConfig};
+
+use crate::config::TlsConfig;
+
+/// Serves the router over TLS, either with the configured certificate or with certificates
+/// provisioned through ACME.
+pub(super) fn serve_tls(socket: SocketAddr, router: axum::Router, tls: TlsConfig) {
+    tokio::spawn(async move {
+        tracing::info!("HTTP gateway listening on {} (TLS)", socket);
+        let served = match (&tls.cert, &tls.key) {
+            (Some(cert), Some(key)) => {
+                let rustls_config = match RustlsConfig::from_pem_file(cert, key).await {
+                    Ok(config) => config,
+                    Err(e) => {
+                        tracing::error!("Failed loading TLS certificate {cert:?}: {e}");
+                        return;
+                    }
+                };
+                axum_server::bind_rustls(socket, rustls_config)
+                    .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+                    .await
+            }
+            _ => {
+                let mut acme = AcmeConfig::new(tls.acme_domains.clone())
+                    .contact(tls.acme_contact.iter().map(|c| format!("mailto:{c}")))
+                    .cache_option(tls.acme_cache_dir.clone().map(DirCache::new))
+                    .directory_lets_encrypt(!tls.acme_staging)
+                    .state();
+                let acceptor = acme.axum_acceptor(acme.default_rustls_config());
+                tokio::spawn(async move {
+                    while let Some(event) = acme.next().await {
+                        match event {
+                            Ok(event) => tracing::info!("ACME event: {event:?}"),
+                            Err(e) => tracing::error!("ACME certificate provisioning error: {e}"),
+                        }
+                    }
+                });
+                axum_server::bind(socket)
+                    .acceptor(acceptor)
+                    .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+                    .await
+            }
+        };
+        if let Err(e) = served {
+            tracing::error!("Error while running HTTP gateway server: {e}");
+        }
+    });
+}
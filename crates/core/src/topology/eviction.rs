@@ -0,0 +1,127 @@
+//! Eviction policy of the connection pool, choosing the connections to drop when the peer has
+//! more than its maximum: those least useful to routing, least reliable and idle the longest.
+//!
+//! Reliability comes from the [reputation](crate::router::Reputation) of the peers.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    node::PeerId,
+    ring::{Connection, PeerKeyLocation},
+};
+
+/// Idle time after which the score of a connection is halved.
+const IDLE_HALF_SCORE: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Default)]
+pub(crate) struct EvictionPolicy {
+    last_active: HashMap<PeerId, Instant>,
+}
+
+impl EvictionPolicy {
+    /// Report a request sent to the peer.
+    pub fn record_activity(&mut self, peer: &PeerId, now: Instant) {
+        self.last_active.insert(peer.clone(), now);
+    }
+
+    pub fn remove_peer(&mut self, peer: &PeerId) {
+        self.last_active.remove(peer);
+    }
+
+    /// Score of a connection the peer sent `requests` requests through recently, to a peer of
+    /// the given `reliability` (from 0 to 1), higher for the connections more worth keeping.
+    pub fn score(
+        &self,
+        connection: &Connection,
+        requests: usize,
+        reliability: f64,
+        now: Instant,
+    ) -> f64 {
+        let last_active = self
+            .last_active
+            .get(&connection.location.peer)
+            .copied()
+            .unwrap_or(connection.open_at);
+        let idle = now.saturating_duration_since(last_active).as_secs_f64();
+        let freshness = 1.0 / (1.0 + idle / IDLE_HALF_SCORE.as_secs_f64());
+        let usefulness = 1.0 + requests as f64;
+        usefulness * reliability * freshness
+    }
+
+    /// The `count` connections of the lowest scores.
+    pub fn select<'a>(
+        &self,
+        connections: impl IntoIterator<Item = &'a Connection>,
+        count: usize,
+        requests: impl Fn(&PeerKeyLocation) -> usize,
+        reliability: impl Fn(&PeerId) -> f64,
+        now: Instant,
+    ) -> Vec<PeerKeyLocation> {
+        let mut scored: Vec<_> = connections
+            .into_iter()
+            .map(|conn| {
+                let requests = requests(&conn.location);
+                let reliability = reliability(&conn.location.peer);
+                (self.score(conn, requests, reliability, now), conn)
+            })
+            .collect();
+        scored.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        scored
+            .into_iter()
+            .take(count)
+            .map(|(_, conn)| conn.location.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ring::Location;
+
+    use super::*;
+
+    #[test]
+    fn evicts_lowest_scores() {
+        let now = Instant::now();
+        let connection = |open_for: u64| Connection {
+            open_at: now - Duration::from_secs(open_for),
+            ..Connection::new(PeerKeyLocation::random().peer, Location::random())
+        };
+        let idle = connection(60 * 60);
+        let unreliable = connection(60);
+        let useful = connection(60 * 60);
+        let reliable = connection(60);
+
+        let mut policy = EvictionPolicy::default();
+        policy.record_activity(&useful.location.peer, now);
+        policy.record_activity(&reliable.location.peer, now);
+        assert!(policy.score(&idle, 0, 1.0, now) < policy.score(&useful, 0, 1.0, now));
+        assert!(policy.score(&reliable, 0, 0.2, now) < policy.score(&reliable, 0, 1.0, now));
+        assert!(policy.score(&reliable, 0, 1.0, now) < policy.score(&reliable, 10, 1.0, now));
+
+        let requests = |peer: &PeerKeyLocation| usize::from(*peer == useful.location) * 10;
+        let reliability = |peer: &PeerId| {
+            if *peer == unreliable.location.peer {
+                0.2
+            } else {
+                1.0
+            }
+        };
+        let evicted = policy.select(
+            [&useful, &reliable, &idle, &unreliable],
+            2,
+            requests,
+            reliability,
+            now,
+        );
+        assert_eq!(evicted.len(), 2);
+        assert!(evicted.contains(&idle.location));
+        assert!(evicted.contains(&unreliable.location));
+
+        policy.remove_peer(&reliable.location.peer);
+        assert!(policy.score(&reliable, 0, 1.0, now) < 1.0);
+    }
+}
@@ -167,7 +167,6 @@ impl Meter {
     /// Report the use of a resource. This should be done in the lowest-level
     /// functions that consume the resource, taking an AttributionMeter
     /// as a parameter.
-    #[allow(dead_code)] // fixme: use this
     pub(crate) fn report(
         &mut self,
         attribution: &AttributionSource,
@@ -186,6 +185,11 @@ impl Meter {
             .or_insert_with(|| RunningAverage::new(self.running_average_window_size));
         resource_value.insert_with_time(at_time, value);
     }
+
+    /// Forget the usage attributed to a source, such as a peer no longer connected.
+    pub(crate) fn remove(&mut self, attribution: &AttributionSource) {
+        self.attribution_meters.remove(attribution);
+    }
 }
 
 #[allow(dead_code)] // todo use this
@@ -13,6 +13,7 @@ use tracing::{debug, error, event, info, span, Level};
 
 pub mod connection_evaluator;
 mod constants;
+mod eviction;
 pub(crate) mod meter;
 pub(crate) mod outbound_request_counter;
 pub(crate) mod rate;
@@ -20,10 +21,13 @@ pub mod request_density_tracker;
 pub(crate) mod running_average;
 mod small_world_rand;
 
+use crate::node::PeerId;
 use crate::ring::{Connection, PeerKeyLocation};
+use crate::router::Reputation;
 use crate::topology::meter::{AttributionSource, ResourceType};
 use crate::topology::rate::{Rate, RateProportion};
 use constants::*;
+use eviction::EvictionPolicy;
 use request_density_tracker::DensityMapError;
 
 /// The goal of `TopologyManager` is to select new connections such that the
@@ -55,6 +59,8 @@ pub(crate) struct TopologyManager {
     /// Must be updated when new neightbors are discovered.
     cached_density_map: CachedDensityMap,
     connection_acquisition_strategy: ConnectionAcquisitionStrategy,
+    eviction_policy: EvictionPolicy,
+    reputation: Reputation,
 }
 
 impl TopologyManager {
@@ -78,9 +84,17 @@ impl TopologyManager {
                 OUTBOUND_REQUEST_COUNTER_WINDOW_SIZE,
             ),
             connection_acquisition_strategy: ConnectionAcquisitionStrategy::Fast,
+            eviction_policy: EvictionPolicy::default(),
+            reputation: Reputation::default(),
         }
     }
 
+    /// Uses the reputation of peers to choose the connections to evict.
+    pub(crate) fn with_reputation(mut self, reputation: Reputation) -> Self {
+        self.reputation = reputation;
+        self
+    }
+
     pub(crate) fn refresh_cache(
         &mut self,
         neighbor_locations: &BTreeMap<Location, Vec<Connection>>,
@@ -100,12 +114,30 @@ impl TopologyManager {
         debug!(%request_type, %recipient, "Recording request sent to peer");
 
         self.request_density_tracker.sample(target);
+        self.eviction_policy
+            .record_activity(&recipient.peer, Instant::now());
         self.outbound_request_counter.record_request(recipient);
     }
 
+    /// Forget about a peer once its connection is closed.
+    pub(crate) fn remove_peer(&mut self, peer: &PeerId) {
+        self.eviction_policy.remove_peer(peer);
+        let sources: Vec<_> = self
+            .source_creation_times
+            .keys()
+            .filter(|source| matches!(source, AttributionSource::Peer(p) if &p.peer == peer))
+            .cloned()
+            .collect();
+        for source in sources {
+            self.source_creation_times.remove(&source);
+            self.meter.remove(&source);
+        }
+    }
+
     /// Decide whether to accept a connection from a new candidate peer based on its location
     /// and current neighbors and request density, along with how it compares to other
-    /// recent candidates.
+    /// recent candidates. Peers are rejected while the load of the current connections is
+    /// above the desired usage of resources.
     pub(crate) fn evaluate_new_connection(
         &mut self,
         candidate_location: Location,
@@ -115,6 +147,15 @@ impl TopologyManager {
             "Evaluating new connection for candidate location: {:?}",
             candidate_location
         );
+        let (resource_type, usage_proportion) = self.calculate_usage_proportion(current_time);
+        if usage_proportion > RateProportion::new(MAXIMUM_DESIRED_RESOURCE_USAGE_PROPORTION) {
+            debug!(
+                ?resource_type,
+                ?usage_proportion,
+                "Rejecting new connection, overloaded"
+            );
+            return Ok(false);
+        }
         let density_map = self
             .cached_density_map
             .get()
@@ -189,7 +230,6 @@ impl TopologyManager {
         }
     }
 
-    #[allow(dead_code)] // fixme: use this
     pub(crate) fn report_resource_usage(
         &mut self,
         attribution: &AttributionSource,
@@ -363,7 +403,13 @@ impl TopologyManager {
 
                 self.update_connection_acquisition_strategy(ConnectionAcquisitionStrategy::Slow);
 
-                Ok(self.select_connections_to_remove(&resource_type, at_time))
+                let excess = neighbor_locations.len() - self.limits.max_connections;
+                match self.select_connections_to_evict(neighbor_locations, excess, at_time) {
+                    TopologyAdjustment::NoChange => {
+                        Ok(self.select_connections_to_remove(&resource_type, at_time))
+                    }
+                    adjustment => Ok(adjustment),
+                }
             } else if usage_proportion < increase_usage_if_below {
                 debug!(
                     "{:?} resource usage ({:?}) is below threshold ({:?}), adding connections",
@@ -458,6 +504,28 @@ impl TopologyManager {
         ]))
     }
 
+    /// Selects the `count` connections least worth keeping according to the eviction policy.
+    fn select_connections_to_evict(
+        &self,
+        neighbor_locations: &BTreeMap<Location, Vec<Connection>>,
+        count: usize,
+        at_time: Instant,
+    ) -> TopologyAdjustment {
+        let evicted = self.eviction_policy.select(
+            neighbor_locations.values().flatten(),
+            count,
+            |peer| self.outbound_request_counter.get_request_count(peer),
+            |peer| self.reputation.score(peer),
+            at_time,
+        );
+        if evicted.is_empty() {
+            TopologyAdjustment::NoChange
+        } else {
+            info!(?evicted, "Evicting connections above the maximum");
+            TopologyAdjustment::RemoveConnections(evicted)
+        }
+    }
+
     fn select_connections_to_remove(
         &mut self,
         exceeded_usage_for_resource_type: &ResourceType,
@@ -695,6 +763,31 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_overloaded_rejects_connections() {
+        with_tracing(|| {
+            let mut resource_manager = setup_topology_manager(1000.0);
+            let peers = generate_random_peers(5);
+            // Total bw usage of 2000 is above the limit of 1000
+            let report_time = Instant::now() - SOURCE_RAMP_UP_DURATION - Duration::from_secs(30);
+            report_resource_usage(&mut resource_manager, &peers, &[400; 5], report_time);
+            let candidate = Location::new(0.5);
+            assert!(!resource_manager
+                .evaluate_new_connection(candidate, Instant::now())
+                .unwrap());
+
+            // The load goes away along with the peers
+            for peer in &peers {
+                resource_manager.remove_peer(&peer.peer);
+            }
+            assert!(resource_manager.source_creation_times.is_empty());
+            assert!(matches!(
+                resource_manager.evaluate_new_connection(candidate, Instant::now()),
+                Err(DensityMapError::EmptyNeighbors)
+            ));
+        });
+    }
+
     #[test]
     fn test_add_connections() {
         with_tracing(|| {
@@ -232,6 +232,7 @@ impl<'a> NetEventLog<'a> {
                 target,
                 key,
                 sender,
+                ..
             }) => EventKind::Put(PutEvent::PutSuccess {
                 id: *id,
                 requester: sender.clone(),
@@ -287,6 +288,19 @@ impl<'a> NetEventLog<'a> {
                 requester: target.clone(),
                 target: sender.clone(),
             },
+            NetMessageV1::Get(GetMsg::ReturnQuery {
+                id,
+                key,
+                sender,
+                target,
+                ..
+            }) => EventKind::Get {
+                id: *id,
+                key: *key,
+                timestamp: chrono::Utc::now().timestamp() as u64,
+                requester: target.clone(),
+                target: sender.clone(),
+            },
             NetMessageV1::Subscribe(SubscribeMsg::ReturnSub {
                 id,
                 subscribed: true,
@@ -1077,10 +1091,39 @@ enum PutEvent {
     },
 }
 
+/// Replaces the log filter directives (e.g. `info,freenet::node=debug`) of the running process.
+#[cfg_attr(not(feature = "websocket"), allow(dead_code))]
+pub(crate) fn set_log_filter(directives: &str) -> anyhow::Result<()> {
+    #[cfg(feature = "trace")]
+    {
+        tracer::set_log_filter(directives)
+    }
+    #[cfg(not(feature = "trace"))]
+    {
+        let _ = directives;
+        anyhow::bail!("built without logging support")
+    }
+}
+
 #[cfg(feature = "trace")]
 pub(crate) mod tracer {
+    use std::sync::OnceLock;
+
     use tracing::level_filters::LevelFilter;
-    use tracing_subscriber::{Layer, Registry};
+    use tracing_subscriber::{reload, EnvFilter, Layer, Registry};
+
+    /// Handle to swap the filter of the logger at runtime.
+    static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+    pub(super) fn set_log_filter(directives: &str) -> anyhow::Result<()> {
+        let filter = EnvFilter::try_new(directives)?;
+        LOG_FILTER
+            .get()
+            .ok_or_else(|| anyhow::anyhow!("logger not initialized"))?
+            .reload(filter)?;
+        tracing::info!(%directives, "Changed log filter");
+        Ok(())
+    }
 
     pub fn init_tracer(level: Option<LevelFilter>, endpoint: Option<String>) -> anyhow::Result<()> {
         let default_filter = if cfg!(any(test, debug_assertions)) {
@@ -1162,6 +1205,8 @@ pub(crate) mod tracer {
                 fmt_layer.boxed()
             }
         };
+        let (filter_layer, filter_handle) = reload::Layer::new(filter_layer);
+        let _ = LOG_FILTER.set(filter_handle);
         let filtered = layers.with_filter(filter_layer);
         // Create a subscriber which includes the tracing Jaeger OT layer and a fmt layer
         let subscriber = Registry::default().with(filtered);
@@ -0,0 +1,105 @@
+//! Throttling of the bandwidth used by the transport, in total and per peer, with token buckets
+//! holding up to a second worth of traffic so short bursts aren't delayed.
+//!
+//! Outbound packets over the limits are delayed, while inbound ones are delayed when over the
+//! limit of the node, leaving them in the socket buffer, and dropped when over the limit of the
+//! peer they are from, which then retransmits them slower as they aren't acknowledged.
+
+use std::time::Instant;
+
+use super::{
+    packet_data::MAX_PACKET_SIZE,
+    priority::{self, OutboundQueues, PacketClass},
+};
+use crate::util::token_bucket::TokenBucket;
+
+/// Bandwidth limits of the transport, unlimited when unset.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct BandwidthLimits {
+    /// Hard limit of the upstream traffic of the node, in bytes per window of the
+    /// [rate limiter](super::rate_limiter::PacketRateLimiter).
+    pub upload: Option<usize>,
+    /// The other limits are in bytes per second.
+    pub download: Option<usize>,
+    pub peer_upload: Option<usize>,
+    pub peer_download: Option<usize>,
+}
+
+/// Bucket of `bytes_per_second` tokens (bytes) per second, up to a second worth of them.
+pub(super) fn bucket(bytes_per_second: usize, now: Instant) -> TokenBucket {
+    let rate = bytes_per_second.max(1) as f64;
+    // a packet must always fit, or it would never be sent
+    TokenBucket::new(rate, rate.max(MAX_PACKET_SIZE as f64), now)
+}
+
+/// Queues of the packets of a peer delaying them over `bytes_per_second` before passing them on
+/// to `outbound_packets`, the queues of the node. Control packets aren't counted against the
+/// limit, so connections are kept up however much else is queued.
+pub(super) fn throttled_queues(
+    outbound_packets: OutboundQueues,
+    bytes_per_second: usize,
+) -> OutboundQueues {
+    let (queues, mut packets) = priority::channels(100);
+    tokio::spawn(async move {
+        let mut bucket = bucket(bytes_per_second, Instant::now());
+        while let Some((class, (remote_addr, packet))) = packets.recv().await {
+            if class != PacketClass::Control {
+                if let Some(wait) = bucket.take(packet.len(), Instant::now()) {
+                    tokio::time::sleep(wait).await;
+                }
+            }
+            if outbound_packets
+                .send(class, (remote_addr, packet))
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+    queues
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn bandwidth_bucket() {
+        let start = Instant::now();
+        // refilled up to a second worth of traffic only
+        let mut bandwidth = bucket(10_000, start);
+        assert!(!bandwidth.try_take(10_001, start + Duration::from_secs(60)));
+        assert!(bandwidth.try_take(10_000, start + Duration::from_secs(60)));
+
+        // packets fit in buckets of lower rates
+        let mut bandwidth = bucket(100, start);
+        assert!(bandwidth.try_take(MAX_PACKET_SIZE, start));
+    }
+
+    #[tokio::test]
+    async fn throttled_packets() -> Result<(), Box<dyn std::error::Error>> {
+        let (outbound, mut sent) = priority::channels(10);
+        let throttled = throttled_queues(outbound, MAX_PACKET_SIZE * 10);
+        let remote: std::net::SocketAddr = ([127, 0, 0, 1], 31337).into();
+        let start = Instant::now();
+        for _ in 0..15 {
+            throttled
+                .send(PacketClass::Bulk, (remote, vec![0; MAX_PACKET_SIZE].into()))
+                .await?;
+        }
+        throttled
+            .send(PacketClass::Control, (remote, [0].into()))
+            .await?;
+        let mut classes = vec![];
+        for _ in 0..16 {
+            classes.push(sent.recv().await.ok_or("closed")?.0);
+        }
+        assert!(start.elapsed() >= Duration::from_millis(400));
+        // not held up behind the packets over the limit
+        assert_ne!(classes.last(), Some(&PacketClass::Control));
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+//! Congestion control of the streams of large messages, such as states of contracts, so they are
+//! sent as fast as the path to the remote allows without flooding it.
+//!
+//! The packets in flight, sent and not acknowledged yet, are limited by a congestion window
+//! growing exponentially from [`INITIAL_WINDOW`] until the first loss, then by a packet per
+//! round trip (additive increase), and halved on loss, at most once per round trip
+//! (multiplicative decrease). As queues build up before packets are dropped, the window stops
+//! growing while the round-trip time is well over the lowest one observed.
+
+use std::time::{Duration, Instant};
+
+/// Packets in flight allowed on new connections.
+const INITIAL_WINDOW: f64 = 10.0;
+
+const MIN_WINDOW: f64 = 2.0;
+
+const MAX_WINDOW: f64 = 4096.0;
+
+/// Factor of the lowest round-trip time over which the path is considered congested.
+const QUEUEING_THRESHOLD: f64 = 1.5;
+
+/// Round-trip time assumed until one is measured.
+const DEFAULT_ROUND_TRIP_TIME: Duration = Duration::from_millis(100);
+
+pub(super) struct CongestionController {
+    /// Packets allowed in flight.
+    window: f64,
+    /// Window the exponential growth stops at, the maximum until the first loss.
+    slow_start_threshold: f64,
+    min_round_trip_time: Option<Duration>,
+    /// Losses until then are part of the last decrease.
+    recovery_until: Option<Instant>,
+}
+
+impl Default for CongestionController {
+    fn default() -> Self {
+        Self {
+            window: INITIAL_WINDOW,
+            slow_start_threshold: MAX_WINDOW,
+            min_round_trip_time: None,
+            recovery_until: None,
+        }
+    }
+}
+
+impl CongestionController {
+    pub fn window(&self) -> usize {
+        self.window as usize
+    }
+
+    /// Reports a packet acknowledged, with the round-trip time measured for it if any and the
+    /// smoothed one.
+    pub fn on_ack(&mut self, sample: Option<Duration>, round_trip_time: Option<Duration>) {
+        if let Some(sample) = sample {
+            self.min_round_trip_time = Some(
+                self.min_round_trip_time
+                    .map_or(sample, |min_rtt| min_rtt.min(sample)),
+            );
+        }
+        let queueing = match (round_trip_time, self.min_round_trip_time) {
+            (Some(rtt), Some(min_rtt)) => {
+                rtt.as_secs_f64() > min_rtt.as_secs_f64() * QUEUEING_THRESHOLD
+            }
+            _ => false,
+        };
+        if queueing {
+            return;
+        }
+        self.window += if self.window < self.slow_start_threshold {
+            1.0
+        } else {
+            1.0 / self.window
+        };
+        self.window = self.window.min(MAX_WINDOW);
+    }
+
+    /// Reports a packet lost, given the smoothed round-trip time.
+    pub fn on_loss(&mut self, now: Instant, round_trip_time: Option<Duration>) {
+        if self.recovery_until.is_some_and(|until| now < until) {
+            return;
+        }
+        self.window = (self.window / 2.0).max(MIN_WINDOW);
+        self.slow_start_threshold = self.window;
+        self.recovery_until = Some(now + round_trip_time.unwrap_or(DEFAULT_ROUND_TRIP_TIME));
+    }
+
+    /// Time to wait before sending another packet with `in_flight` packets in flight, `None` if
+    /// it can be sent now. Waits are spread over the round trip so packets are paced out as
+    /// receipts come back.
+    pub fn wait(&self, in_flight: usize, round_trip_time: Option<Duration>) -> Option<Duration> {
+        (in_flight >= self.window()).then(|| {
+            round_trip_time
+                .unwrap_or(DEFAULT_ROUND_TRIP_TIME)
+                .div_f64(self.window)
+                .max(Duration::from_millis(1))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aimd_window() {
+        let start = Instant::now();
+        let rtt = Some(Duration::from_millis(100));
+        let mut controller = CongestionController::default();
+        assert_eq!(controller.window(), 10);
+        assert_eq!(controller.wait(9, rtt), None);
+        assert_eq!(controller.wait(10, rtt), Some(Duration::from_millis(10)));
+
+        // slow start
+        for _ in 0..10 {
+            controller.on_ack(rtt, rtt);
+        }
+        assert_eq!(controller.window(), 20);
+
+        // halved once per round trip
+        controller.on_loss(start, rtt);
+        controller.on_loss(start + Duration::from_millis(50), rtt);
+        assert_eq!(controller.window(), 10);
+        controller.on_loss(start + Duration::from_millis(100), rtt);
+        assert_eq!(controller.window(), 5);
+
+        // congestion avoidance
+        for _ in 0..5 {
+            controller.on_ack(rtt, rtt);
+        }
+        assert_eq!(controller.window(), 5);
+        for _ in 0..6 {
+            controller.on_ack(rtt, rtt);
+        }
+        assert_eq!(controller.window(), 6);
+
+        // not grown while queues build up
+        let window = controller.window;
+        controller.on_ack(
+            Some(Duration::from_millis(300)),
+            Some(Duration::from_millis(200)),
+        );
+        assert_eq!(controller.window, window);
+
+        for i in 0..10 {
+            controller.on_loss(start + Duration::from_secs(i + 1), rtt);
+        }
+        assert_eq!(controller.window(), MIN_WINDOW as usize);
+    }
+}
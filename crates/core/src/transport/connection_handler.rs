@@ -10,6 +10,7 @@ use crate::config::PCK_VERSION;
 use crate::transport::crypto::TransportSecretKey;
 use crate::transport::packet_data::{AssymetricRSA, UnknownEncryption};
 use crate::transport::symmetric_message::OutboundConnection;
+use crate::util::token_bucket::TokenBucket;
 use aes_gcm::{Aes128Gcm, KeyInit};
 use futures::{
     future::BoxFuture,
@@ -27,11 +28,21 @@ use tracing::{span, Instrument};
 use version_cmp::PROTOC_VERSION;
 
 use super::{
+    bandwidth::{self, BandwidthLimits},
     crypto::{TransportKeypair, TransportPublicKey},
+    migration::{self, Migrated, MigrationHandle, TrialBudget},
+    nat,
+    negotiation::{self, Capabilities, Intro},
     packet_data::{PacketData, SymmetricAES, MAX_PACKET_SIZE},
     peer_connection::{PeerConnection, RemoteConnection},
+    priority::{self, OutboundQueues, PacketClass},
+    quic::QuicEndpoint,
+    rekey::RekeyLimits,
+    relay::{self, RelayLimits, RelayRoutes},
+    replay::ReplayLimits,
     sent_packet_tracker::SentPacketTracker,
     symmetric_message::{SymmetricMessage, SymmetricMessagePayload},
+    tcp_tunnel::TcpTunnels,
     Socket, TransportError,
 };
 
@@ -64,16 +75,31 @@ pub(crate) async fn create_connection_handler<S: Socket>(
     listen_host: IpAddr,
     listen_port: u16,
     is_gateway: bool,
-    bandwith_limit: Option<usize>,
+    limits: BandwidthLimits,
+    rekey: RekeyLimits,
+    replay: ReplayLimits,
+    relay: RelayLimits,
+    quic_port: Option<u16>,
 ) -> Result<(OutboundConnectionHandler, InboundConnectionHandler), TransportError> {
     // Bind the UDP socket to the specified port
     let socket = S::bind((listen_host, listen_port).into()).await?;
+    let quic = quic_port.and_then(|port| {
+        QuicEndpoint::bind((listen_host, port).into())
+            .inspect_err(|error| {
+                tracing::warn!(%error, %port, "Failed to bind QUIC endpoint, only using UDP");
+            })
+            .ok()
+    });
     let (och, new_connection_notifier) = OutboundConnectionHandler::config_listener(
         Arc::new(socket),
         keypair,
         is_gateway,
         (listen_host, listen_port).into(),
-        bandwith_limit,
+        limits,
+        rekey,
+        replay,
+        relay,
+        quic,
     )?;
     Ok((
         och,
@@ -107,12 +133,26 @@ impl InboundConnectionHandler {
 #[derive(Clone)]
 pub(crate) struct OutboundConnectionHandler {
     send_queue: mpsc::Sender<(SocketAddr, ConnectionEvent)>,
+    quic: Option<QuicEndpoint>,
+    tunnels: Option<TcpTunnels>,
+    relay_routes: RelayRoutes,
+    limits: BandwidthLimits,
+    rekey: RekeyLimits,
+    replay: ReplayLimits,
 }
 
 #[cfg(test)]
 impl OutboundConnectionHandler {
     pub fn new(send_queue: mpsc::Sender<(SocketAddr, ConnectionEvent)>) -> Self {
-        OutboundConnectionHandler { send_queue }
+        OutboundConnectionHandler {
+            send_queue,
+            quic: None,
+            tunnels: None,
+            relay_routes: RelayRoutes::default(),
+            limits: BandwidthLimits::default(),
+            rekey: RekeyLimits::default(),
+            replay: ReplayLimits::default(),
+        }
     }
 }
 
@@ -122,14 +162,21 @@ impl OutboundConnectionHandler {
         keypair: TransportKeypair,
         is_gateway: bool,
         socket_addr: SocketAddr,
-        bandwith_limit: Option<usize>,
+        limits: BandwidthLimits,
+        rekey: RekeyLimits,
+        replay: ReplayLimits,
+        relay: RelayLimits,
+        quic: Option<QuicEndpoint>,
     ) -> Result<(Self, mpsc::Receiver<PeerConnection>), TransportError> {
         // Channel buffer is one so senders will await until the receiver is ready, important for bandwidth limiting
         let (conn_handler_sender, conn_handler_receiver) = mpsc::channel(100);
         let (new_connection_sender, new_connection_notifier) = mpsc::channel(100);
 
         // Channel buffer is one so senders will await until the receiver is ready, important for bandwidth limiting
-        let (outbound_sender, outbound_recv) = mpsc::channel(10000);
+        let (outbound_sender, outbound_recv) = priority::channels(10000);
+        let tunnels = socket.tunnels();
+        let relay_routes = RelayRoutes::default();
+        let (migrated, migrations) = mpsc::channel(100);
         let transport = UdpPacketsListener {
             is_gateway,
             socket_listener: socket.clone(),
@@ -139,6 +186,14 @@ impl OutboundConnectionHandler {
             new_connection_notifier: new_connection_sender,
             outbound_packets: outbound_sender,
             this_addr: socket_addr,
+            quic: quic.clone(),
+            limits,
+            rekey,
+            replay,
+            relay,
+            relay_routes: relay_routes.clone(),
+            migrations,
+            migrated,
         };
         let bw_tracker = super::rate_limiter::PacketRateLimiter::new(
             DEFAULT_BW_TRACKER_WINDOW_SIZE,
@@ -146,9 +201,15 @@ impl OutboundConnectionHandler {
         );
         let connection_handler = OutboundConnectionHandler {
             send_queue: conn_handler_sender,
+            quic,
+            tunnels,
+            relay_routes: relay_routes.clone(),
+            limits,
+            rekey,
+            replay,
         };
 
-        task::spawn(bw_tracker.rate_limiter(bandwith_limit, socket));
+        task::spawn(bw_tracker.rate_limiter(limits.upload, socket, relay_routes));
         task::spawn(RANDOM_U64.scope(StdRng::from_entropy().gen(), transport.listen()));
 
         Ok((connection_handler, new_connection_notifier))
@@ -161,7 +222,17 @@ impl OutboundConnectionHandler {
         keypair: TransportKeypair,
         is_gateway: bool,
     ) -> Result<(Self, mpsc::Receiver<PeerConnection>), TransportError> {
-        Self::config_listener(socket, keypair, is_gateway, socket_addr, None)
+        Self::config_listener(
+            socket,
+            keypair,
+            is_gateway,
+            socket_addr,
+            BandwidthLimits::default(),
+            RekeyLimits::default(),
+            ReplayLimits::default(),
+            RelayLimits::default(),
+            None,
+        )
     }
 
     pub async fn connect(
@@ -184,9 +255,17 @@ impl OutboundConnectionHandler {
         {
             return async { Err(TransportError::ChannelClosed) }.boxed();
         }
+        let quic = self.quic.clone();
+        let upload_limit = self.limits.peer_upload;
+        let rekey = self.rekey;
+        let replay = self.replay;
         recv_connection
-            .map(|res| match res {
-                Ok(Ok(remote_conn)) => Ok(PeerConnection::new(remote_conn)),
+            .map(move |res| match res {
+                Ok(Ok(remote_conn)) => Ok(PeerConnection::new(remote_conn)
+                    .with_quic(quic)
+                    .with_upload_limit(upload_limit)
+                    .with_rekey(rekey)
+                    .with_replay_protection(replay)),
                 Ok(Err(e)) => Err(e),
                 Err(_) => Err(TransportError::ConnectionEstablishmentFailure {
                     cause: "Failed to establish connection".into(),
@@ -194,6 +273,101 @@ impl OutboundConnectionHandler {
             })
             .boxed()
     }
+
+    /// Asks the gateway at `gateway` for the reflexive address of this peer, the address it
+    /// sees this peer at.
+    pub async fn reflexive_address(
+        &self,
+        gateway: SocketAddr,
+    ) -> Result<SocketAddr, TransportError> {
+        const BINDING_ATTEMPTS: usize = 3;
+        const BINDING_TIMEOUT: Duration = Duration::from_secs(2);
+        for _ in 0..BINDING_ATTEMPTS {
+            let (binding, reflexive) = oneshot::channel();
+            self.send_queue
+                .send((
+                    gateway,
+                    ConnectionEvent::BindingRequest { reflexive: binding },
+                ))
+                .await
+                .map_err(|_| TransportError::ChannelClosed)?;
+            if let Ok(Ok(reflexive)) = tokio::time::timeout(BINDING_TIMEOUT, reflexive).await {
+                return Ok(reflexive);
+            }
+        }
+        Err(TransportError::ConnectionEstablishmentFailure {
+            cause: "no binding response from the gateway".into(),
+        })
+    }
+
+    /// Connects to a gateway, tunneling the connection over TCP when the gateway can't be
+    /// reached over UDP.
+    pub async fn connect_gateway(
+        &mut self,
+        remote_public_key: TransportPublicKey,
+        remote_addr: SocketAddr,
+    ) -> Pin<Box<dyn Future<Output = Result<PeerConnection, TransportError>> + Send>> {
+        let over_udp = self.connect(remote_public_key.clone(), remote_addr).await;
+        let Some(tunnels) = self.tunnels.clone() else {
+            return over_udp;
+        };
+        let mut handler = self.clone();
+        async move {
+            match over_udp.await {
+                Err(
+                    error @ (TransportError::ChannelClosed
+                    | TransportError::ProtocolVersionMismatch { .. }
+                    | TransportError::IncompatibleProtocol { .. }),
+                ) => Err(error),
+                Err(error) => {
+                    tracing::warn!(%remote_addr, %error, "Failed to reach gateway over UDP, falling back to TCP");
+                    tunnels.open(remote_addr).await?;
+                    handler.connect(remote_public_key, remote_addr).await.await
+                }
+                connected => connected,
+            }
+        }
+        .boxed()
+    }
+
+    /// Connects to a peer, relaying the connection through `relays`, peers connected to both
+    /// ends, when its NAT can't be traversed.
+    pub async fn connect_relayed(
+        &mut self,
+        remote_public_key: TransportPublicKey,
+        remote_addr: SocketAddr,
+        relays: Vec<SocketAddr>,
+    ) -> Pin<Box<dyn Future<Output = Result<PeerConnection, TransportError>> + Send>> {
+        self.relay_routes.remove(&remote_addr);
+        let direct = self.connect(remote_public_key.clone(), remote_addr).await;
+        if relays.is_empty() {
+            return direct;
+        }
+        let mut handler = self.clone();
+        async move {
+            match direct.await {
+                Err(
+                    error @ (TransportError::ChannelClosed
+                    | TransportError::ProtocolVersionMismatch { .. }
+                    | TransportError::IncompatibleProtocol { .. }),
+                ) => Err(error),
+                Err(error) => {
+                    tracing::warn!(%remote_addr, %error, relays = relays.len(), "Failed to reach peer directly, falling back to relays");
+                    handler.relay_routes.relay_through(remote_addr, relays);
+                    let relayed = handler
+                        .connect(remote_public_key, remote_addr)
+                        .await
+                        .await;
+                    if relayed.is_err() {
+                        handler.relay_routes.remove(&remote_addr);
+                    }
+                    relayed
+                }
+                connected => connected,
+            }
+        }
+        .boxed()
+    }
 }
 
 /// Handles UDP transport internally.
@@ -204,8 +378,17 @@ struct UdpPacketsListener<S = UdpSocket> {
     this_peer_keypair: TransportKeypair,
     is_gateway: bool,
     new_connection_notifier: mpsc::Sender<PeerConnection>,
-    outbound_packets: mpsc::Sender<(SocketAddr, Arc<[u8]>)>,
+    outbound_packets: OutboundQueues,
     this_addr: SocketAddr,
+    quic: Option<QuicEndpoint>,
+    limits: BandwidthLimits,
+    rekey: RekeyLimits,
+    replay: ReplayLimits,
+    relay: RelayLimits,
+    relay_routes: RelayRoutes,
+    /// Connections migrated to the new address of their remote, reported by each connection.
+    migrations: mpsc::Receiver<Migrated>,
+    migrated: mpsc::Sender<Migrated>,
 }
 
 type OngoingConnection = (
@@ -255,7 +438,7 @@ impl<S: Socket> UdpPacketsListener<S> {
     #[tracing::instrument(level = "debug", name = "transport_listener", fields(peer = %self.this_peer_keypair.public), skip_all)]
     async fn listen(mut self) -> Result<(), TransportError> {
         tracing::debug!(%self.this_addr, "listening for packets");
-        let mut buf = [0u8; MAX_PACKET_SIZE];
+        let mut buf = [0u8; MAX_PACKET_SIZE + relay::MAX_HEADER_SIZE];
         let mut ongoing_connections: BTreeMap<SocketAddr, OngoingConnection> = BTreeMap::new();
         let mut ongoing_gw_connections: BTreeMap<
             SocketAddr,
@@ -265,6 +448,17 @@ impl<S: Socket> UdpPacketsListener<S> {
         let mut gw_connection_tasks = FuturesUnordered::new();
         let mut pending_connections = vec![];
         let mut outdated_peer: HashMap<SocketAddr, Instant> = HashMap::new();
+        let mut pending_bindings: HashMap<(SocketAddr, u64), oneshot::Sender<SocketAddr>> =
+            HashMap::new();
+        let mut download = self
+            .limits
+            .download
+            .map(|limit| bandwidth::bucket(limit, Instant::now()));
+        let mut peer_downloads: HashMap<SocketAddr, TokenBucket> = HashMap::new();
+        let mut relayed = bandwidth::bucket(self.relay.bytes_per_second, Instant::now());
+        // new addresses of remotes being validated, and the address of their connection
+        let mut candidate_paths: HashMap<SocketAddr, SocketAddr> = HashMap::new();
+        let mut migration_trials = TrialBudget::new(Instant::now());
 
         'outer: loop {
             'inner: loop {
@@ -294,6 +488,24 @@ impl<S: Socket> UdpPacketsListener<S> {
                 recv_result = self.socket_listener.recv_from(&mut buf) => {
                     match recv_result {
                         Ok((size, remote_addr)) => {
+                            let now = Instant::now();
+                            if let Some(wait) = download.as_mut().and_then(|bucket| bucket.take(size, now)) {
+                                tokio::time::sleep(wait).await;
+                            }
+                            if let Some(limit) = self.limits.peer_download {
+                                if self.remote_connections.contains_key(&remote_addr) {
+                                    if peer_downloads.len() > self.remote_connections.len() * 2 {
+                                        peer_downloads.retain(|addr, _| self.remote_connections.contains_key(addr));
+                                    }
+                                    let bucket = peer_downloads
+                                        .entry(remote_addr)
+                                        .or_insert_with(|| bandwidth::bucket(limit, now));
+                                    if !bucket.try_take(size, now) {
+                                        tracing::trace!(%remote_addr, "peer over its download limit, dropping packet");
+                                        continue;
+                                    }
+                                }
+                            }
                             if let Some(time) = outdated_peer.get(&remote_addr) {
                                 if time.elapsed() < Duration::from_secs(60 * 10) {
                                     continue;
@@ -301,7 +513,51 @@ impl<S: Socket> UdpPacketsListener<S> {
                                     outdated_peer.remove(&remote_addr);
                                 }
                             }
-                            let packet_data = PacketData::from_buf(&buf[..size]);
+                            if let Some(id) = nat::parse_binding_request(&buf[..size]) {
+                                if self.is_gateway {
+                                    tracing::trace!(%remote_addr, "answering binding request");
+                                    let response = nat::binding_response(id, remote_addr);
+                                    if self.outbound_packets.send(PacketClass::Control, (remote_addr, response.into())).await.is_err() {
+                                        break 'outer Err(TransportError::ChannelClosed);
+                                    }
+                                }
+                                continue;
+                            }
+                            if let Some((id, reflexive)) = nat::parse_binding_response(&buf[..size]) {
+                                if let Some(binding) = pending_bindings.remove(&(remote_addr, id)) {
+                                    let _ = binding.send(reflexive);
+                                    continue;
+                                }
+                            }
+                            if let Some((target, packet)) = relay::parse_forward(&buf[..size]) {
+                                if self.relay.enabled
+                                    && self.remote_connections.contains_key(&remote_addr)
+                                    && self.remote_connections.contains_key(&target)
+                                    && relayed.try_take(size, now)
+                                {
+                                    let delivery = relay::delivery_packet(remote_addr, packet);
+                                    if self.outbound_packets.send(PacketClass::Message, (target, delivery.into())).await.is_err() {
+                                        break 'outer Err(TransportError::ChannelClosed);
+                                    }
+                                } else {
+                                    tracing::trace!(%remote_addr, %target, "not relaying packet");
+                                }
+                                continue;
+                            }
+                            let (remote_addr, packet) = match relay::parse_delivery(&buf[..size]) {
+                                Some((sender, packet)) if self.relay_routes.delivered(sender, remote_addr) => (sender, packet),
+                                Some((sender, _)) => {
+                                    tracing::trace!(%sender, relay = %remote_addr, "unexpected relayed packet");
+                                    continue;
+                                }
+                                None => (remote_addr, &buf[..size]),
+                            };
+                            if packet.len() > MAX_PACKET_SIZE {
+                                tracing::trace!(%remote_addr, size = packet.len(), "packet too large");
+                                continue;
+                            }
+                            let size = packet.len();
+                            let packet_data = PacketData::from_buf(packet);
 
                             tracing::trace!(
                                 %remote_addr,
@@ -355,6 +611,36 @@ impl<S: Socket> UdpPacketsListener<S> {
                                 continue;
                             }
 
+                            if let Some(current) = candidate_paths.get(&remote_addr).copied() {
+                                let delivered = match self.remote_connections.get(&current) {
+                                    Some(remote_conn) => remote_conn.inbound_packet_sender.send(packet_data).await.is_ok(),
+                                    None => false,
+                                };
+                                if !delivered {
+                                    candidate_paths.remove(&remote_addr);
+                                }
+                                continue;
+                            }
+
+                            if !self.remote_connections.is_empty() && migration_trials.try_take(now) {
+                                let migrating = self
+                                    .remote_connections
+                                    .iter()
+                                    .find(|(_, remote_conn)| remote_conn.migration.recognizes(&packet_data))
+                                    .map(|(addr, _)| *addr);
+                                if let Some(current) = migrating {
+                                    tracing::debug!(%remote_addr, %current, "packet of connection from new address");
+                                    if candidate_paths.len() > self.remote_connections.len() * 2 {
+                                        candidate_paths.retain(|_, current| self.remote_connections.contains_key(current));
+                                    }
+                                    candidate_paths.insert(remote_addr, current);
+                                    let remote_conn = &self.remote_connections[&current];
+                                    remote_conn.migration.offer(remote_addr);
+                                    let _ = remote_conn.inbound_packet_sender.send(packet_data).await;
+                                    continue;
+                                }
+                            }
+
                             if !self.is_gateway {
                                 tracing::debug!(
                                     %remote_addr,
@@ -394,7 +680,7 @@ impl<S: Socket> UdpPacketsListener<S> {
                             self.remote_connections.insert(remote_addr, inbound_remote_connection);
 
                             match self.new_connection_notifier
-                            .try_send(PeerConnection::new(outbound_remote_conn)) {
+                            .try_send(PeerConnection::new(outbound_remote_conn).with_quic(self.quic.clone()).with_upload_limit(self.limits.peer_upload).with_rekey(self.rekey).with_replay_protection(self.replay)) {
                                 Ok(_) => {}
                                 Err(mpsc::error::TrySendError::Full(pending_conn)) => {
                                     tracing::error!(%remote_addr, "gateway connection established but channel is full");
@@ -446,6 +732,17 @@ impl<S: Socket> UdpPacketsListener<S> {
                         }
                     }
                 }
+                migrated = self.migrations.recv() => {
+                    let Some(Migrated { from, to }) = migrated else {
+                        unreachable!("the listener holds a sender");
+                    };
+                    if let Some(remote_conn) = self.remote_connections.remove(&from) {
+                        tracing::debug!(%from, %to, "connection migrated to new address of remote");
+                        self.remote_connections.insert(to, remote_conn);
+                    }
+                    candidate_paths.retain(|_, current| *current != from);
+                    peer_downloads.remove(&from);
+                }
                 // Handling of connection events
                 connection_event = self.connection_handler.recv() => {
                     let Some((remote_addr, event)) = connection_event else {
@@ -453,10 +750,24 @@ impl<S: Socket> UdpPacketsListener<S> {
                         return Ok(());
                     };
                     tracing::debug!(%remote_addr, "received connection event");
+                    let (remote_public_key, open_connection) = match event {
+                        ConnectionEvent::ConnectionStart { remote_public_key, open_connection } => {
+                            (remote_public_key, open_connection)
+                        }
+                        ConnectionEvent::BindingRequest { reflexive } => {
+                            pending_bindings.retain(|_, binding| !binding.is_closed());
+                            let id = rand::random();
+                            pending_bindings.insert((remote_addr, id), reflexive);
+                            let request = nat::binding_request(id);
+                            if self.outbound_packets.send(PacketClass::Control, (remote_addr, request.as_slice().into())).await.is_err() {
+                                break 'outer Err(TransportError::ChannelClosed);
+                            }
+                            continue;
+                        }
+                    };
                     if let Some(_conn) = self.remote_connections.remove(&remote_addr) {
                         tracing::warn!(%remote_addr, "connection already established, dropping old connection");
                     }
-                    let ConnectionEvent::ConnectionStart { remote_public_key, open_connection } = event;
                     tracing::debug!(%remote_addr, "attempting to establish connection");
                     let (ongoing_connection, packets_sender) = self.traverse_nat(
                         remote_addr,  remote_public_key,
@@ -484,6 +795,7 @@ impl<S: Socket> UdpPacketsListener<S> {
     ) {
         let secret = self.this_peer_keypair.secret.clone();
         let outbound_packets = self.outbound_packets.clone();
+        let migrated = self.migrated.clone();
 
         let (inbound_from_remote, mut next_inbound) =
             mpsc::channel::<PacketData<UnknownEncryption>>(1);
@@ -494,36 +806,15 @@ impl<S: Socket> UdpPacketsListener<S> {
                     err
                 })?;
 
-            let protoc = decrypted_intro_packet
-                .get(..PROTOC_VERSION.len())
-                .ok_or_else(|| TransportError::ConnectionEstablishmentFailure {
-                    cause: "Packet too small to contain protocol version".into(),
-                })?;
-
-            let outbound_key_bytes = decrypted_intro_packet
-                .get(PROTOC_VERSION.len()..PROTOC_VERSION.len() + 16)
-                .ok_or_else(|| TransportError::ConnectionEstablishmentFailure {
-                    cause: "Packet too small to contain outbound key bytes".into(),
-                })?;
-
-            let outbound_key = Aes128Gcm::new_from_slice(outbound_key_bytes).map_err(|_| {
-                TransportError::ConnectionEstablishmentFailure {
-                    cause: "invalid symmetric key".into(),
-                }
-            })?;
-            if protoc != PROTOC_VERSION {
+            let intro = Intro::decode(&decrypted_intro_packet)?;
+            let outbound_key = Aes128Gcm::new(&intro.key.into());
+            if let Err(error) = negotiation::check_compatible(PROTOC_VERSION, intro.version) {
                 let packet = SymmetricMessage::ack_error(&outbound_key)?;
                 outbound_packets
-                    .send((remote_addr, packet.prepared_send()))
+                    .send(PacketClass::Control, (remote_addr, packet.prepared_send()))
                     .await
                     .map_err(|_| TransportError::ChannelClosed)?;
-                return Err(TransportError::ConnectionEstablishmentFailure {
-                    cause: format!(
-                        "remote is using a different protocol version: {:?}",
-                        String::from_utf8_lossy(protoc)
-                    )
-                    .into(),
-                });
+                return Err(error);
             }
 
             let inbound_key = Aes128Gcm::new(&inbound_key_bytes.into());
@@ -533,7 +824,10 @@ impl<S: Socket> UdpPacketsListener<S> {
             tracing::debug!(%remote_addr, "Sending outbound ack packet: {:?}", outbound_ack_packet.data());
 
             outbound_packets
-                .send((remote_addr, outbound_ack_packet.clone().prepared_send()))
+                .send(
+                    PacketClass::Control,
+                    (remote_addr, outbound_ack_packet.clone().prepared_send()),
+                )
                 .await
                 .map_err(|_| TransportError::ChannelClosed)?;
 
@@ -563,6 +857,7 @@ impl<S: Socket> UdpPacketsListener<S> {
             let sent_tracker = Arc::new(parking_lot::Mutex::new(SentPacketTracker::new()));
 
             let (inbound_packet_tx, inbound_packet_rx) = mpsc::channel(100);
+            let (migration, migration_handle) = migration::path_migration(&inbound_key, migrated);
             let remote_conn = RemoteConnection {
                 outbound_packets,
                 outbound_symmetric_key: outbound_key,
@@ -573,10 +868,13 @@ impl<S: Socket> UdpPacketsListener<S> {
                 inbound_symmetric_key: inbound_key,
                 inbound_symmetric_key_bytes: inbound_key_bytes,
                 my_address: None,
+                migration,
+                capabilities: intro.capabilities.common(),
             };
 
             let inbound_conn = InboundRemoteConnection {
                 inbound_packet_sender: inbound_packet_tx,
+                migration: migration_handle,
             };
 
             tracing::debug!("returning connection at gw");
@@ -614,39 +912,26 @@ impl<S: Socket> UdpPacketsListener<S> {
             RemoteInbound {
                 /// Encrypted intro packet for comparison
                 intro_packet: PacketData<AssymetricRSA>,
+                /// Optional features of the protocol supported by both ends
+                capabilities: Capabilities,
             },
         }
 
-        fn decrypt_asym(
+        fn decrypt_intro(
             remote_addr: SocketAddr,
             packet: &PacketData<UnknownEncryption>,
             transport_secret_key: &TransportSecretKey,
-            outbound_sym_key: &mut Option<Aes128Gcm>,
-            state: &mut ConnectionState,
-        ) -> Result<(), ()> {
-            // probably the first packet to punch through the NAT
-            if let Ok(decrypted_intro_packet) = packet.try_decrypt_asym(transport_secret_key) {
-                tracing::debug!(%remote_addr, "received intro packet");
-                let protoc = &decrypted_intro_packet.data()[..PROTOC_VERSION.len()];
-                if protoc != PROTOC_VERSION {
-                    tracing::debug!(%remote_addr, ?protoc, this_protoc = ?PROTOC_VERSION, "remote is using a different protocol version");
-                    return Err(());
-                }
-                let outbound_key_bytes =
-                    &decrypted_intro_packet.data()[PROTOC_VERSION.len()..PROTOC_VERSION.len() + 16];
-                let outbound_key =
-                    Aes128Gcm::new_from_slice(outbound_key_bytes).expect("correct length");
-                *outbound_sym_key = Some(outbound_key.clone());
-                *state = ConnectionState::RemoteInbound {
-                    intro_packet: packet.assert_assymetric(),
-                };
-                return Ok(());
-            }
-            tracing::debug!(%remote_addr, "failed to decrypt packet");
-            Err(())
+        ) -> Option<Intro> {
+            let Ok(decrypted_intro_packet) = packet.try_decrypt_asym(transport_secret_key) else {
+                tracing::debug!(%remote_addr, "failed to decrypt packet");
+                return None;
+            };
+            tracing::debug!(%remote_addr, "received intro packet");
+            Intro::decode(decrypted_intro_packet.data()).ok()
         }
 
         let outbound_packets = self.outbound_packets.clone();
+        let migrated = self.migrated.clone();
         let transport_secret_key = self.this_peer_keypair.secret.clone();
         let (inbound_from_remote, mut next_inbound) =
             mpsc::channel::<PacketData<UnknownEncryption>>(1);
@@ -665,9 +950,7 @@ impl<S: Socket> UdpPacketsListener<S> {
 
             let mut outbound_sym_key: Option<Aes128Gcm> = None;
             let outbound_intro_packet = {
-                let mut data = [0u8; { 16 + PROTOC_VERSION.len() }];
-                data[..PROTOC_VERSION.len()].copy_from_slice(&PROTOC_VERSION);
-                data[PROTOC_VERSION.len()..].copy_from_slice(&inbound_sym_key_bytes);
+                let data = Intro::new(PROTOC_VERSION, inbound_sym_key_bytes).encode();
                 PacketData::<_, MAX_PACKET_SIZE>::encrypt_with_pubkey(&data, &remote_public_key)
             };
 
@@ -678,7 +961,10 @@ impl<S: Socket> UdpPacketsListener<S> {
                     ConnectionState::StartOutbound => {
                         tracing::debug!(%remote_addr, "sending protocol version and inbound key");
                         outbound_packets
-                            .send((remote_addr, outbound_intro_packet.data().into()))
+                            .send(
+                                PacketClass::Control,
+                                (remote_addr, outbound_intro_packet.data().into()),
+                            )
                             .await
                             .map_err(|_| TransportError::ChannelClosed)?;
                     }
@@ -690,7 +976,10 @@ impl<S: Socket> UdpPacketsListener<S> {
                             remote_addr,
                         )?;
                         outbound_packets
-                            .send((remote_addr, our_inbound.data().into()))
+                            .send(
+                                PacketClass::Control,
+                                (remote_addr, our_inbound.data().into()),
+                            )
                             .await
                             .map_err(|_| TransportError::ChannelClosed)?;
                         sent_tracker.report_sent_packet(
@@ -730,6 +1019,7 @@ impl<S: Socket> UdpPacketsListener<S> {
                                                 Ok(OutboundConnection {
                                                     key,
                                                     remote_addr: my_address,
+                                                    capabilities,
                                                 }),
                                         } => {
                                             let outbound_sym_key = Aes128Gcm::new_from_slice(&key)
@@ -740,19 +1030,27 @@ impl<S: Socket> UdpPacketsListener<S> {
                                                 })?;
                                             tracing::debug!(%remote_addr, "Sending back ack connection: {:?}", key);
                                             outbound_packets
-                                                .send((
-                                                    remote_addr,
-                                                    SymmetricMessage::ack_ok(
-                                                        &outbound_sym_key,
-                                                        inbound_sym_key_bytes,
+                                                .send(
+                                                    PacketClass::Control,
+                                                    (
                                                         remote_addr,
-                                                    )?
-                                                    .data()
-                                                    .into(),
-                                                ))
+                                                        SymmetricMessage::ack_ok(
+                                                            &outbound_sym_key,
+                                                            inbound_sym_key_bytes,
+                                                            remote_addr,
+                                                        )?
+                                                        .data()
+                                                        .into(),
+                                                    ),
+                                                )
                                                 .await
                                                 .map_err(|_| TransportError::ChannelClosed)?;
                                             let (inbound_sender, inbound_recv) = mpsc::channel(100);
+                                            let (migration, migration_handle) =
+                                                migration::path_migration(
+                                                    &inbound_sym_key,
+                                                    migrated.clone(),
+                                                );
                                             tracing::debug!(%remote_addr, "connection established");
                                             return Ok((
                                                 RemoteConnection {
@@ -768,9 +1066,12 @@ impl<S: Socket> UdpPacketsListener<S> {
                                                     inbound_symmetric_key_bytes:
                                                         inbound_sym_key_bytes,
                                                     my_address: Some(my_address),
+                                                    migration,
+                                                    capabilities: capabilities.common(),
                                                 },
                                                 InboundRemoteConnection {
                                                     inbound_packet_sender: inbound_sender,
+                                                    migration: migration_handle,
                                                 },
                                             ));
                                         }
@@ -788,15 +1089,29 @@ impl<S: Socket> UdpPacketsListener<S> {
                                 }
 
                                 // probably the first packet to punch through the NAT
-                                if decrypt_asym(
-                                    remote_addr,
-                                    &packet,
-                                    &transport_secret_key,
-                                    &mut outbound_sym_key,
-                                    &mut state,
-                                )
-                                .is_ok()
+                                if let Some(intro) =
+                                    decrypt_intro(remote_addr, &packet, &transport_secret_key)
                                 {
+                                    let outbound_key = Aes128Gcm::new(&intro.key.into());
+                                    if let Err(error) =
+                                        negotiation::check_compatible(PROTOC_VERSION, intro.version)
+                                    {
+                                        tracing::debug!(%remote_addr, %error, "refusing connection");
+                                        let packet = SymmetricMessage::ack_error(&outbound_key)?;
+                                        outbound_packets
+                                            .send(
+                                                PacketClass::Control,
+                                                (remote_addr, packet.prepared_send()),
+                                            )
+                                            .await
+                                            .map_err(|_| TransportError::ChannelClosed)?;
+                                        return Err(error);
+                                    }
+                                    outbound_sym_key = Some(outbound_key);
+                                    state = ConnectionState::RemoteInbound {
+                                        intro_packet: packet.assert_assymetric(),
+                                        capabilities: intro.capabilities.common(),
+                                    };
                                     continue;
                                 }
 
@@ -807,7 +1122,7 @@ impl<S: Socket> UdpPacketsListener<S> {
                             ConnectionState::RemoteInbound {
                                 // this is the packet encrypted with out RSA pub key
                                 ref intro_packet,
-                                ..
+                                capabilities,
                             } => {
                                 // next packet should be an acknowledgement packet, but might also be a repeated
                                 // intro packet so we need to handle that
@@ -819,6 +1134,8 @@ impl<S: Socket> UdpPacketsListener<S> {
                                 }
                                 // if is not an intro packet, the connection is successful and we can proceed
                                 let (inbound_sender, inbound_recv) = mpsc::channel(1);
+                                let (migration, migration_handle) =
+                                    migration::path_migration(&inbound_sym_key, migrated.clone());
                                 return Ok((
                                     RemoteConnection {
                                         outbound_packets: outbound_packets.clone(),
@@ -833,9 +1150,12 @@ impl<S: Socket> UdpPacketsListener<S> {
                                         inbound_symmetric_key: inbound_sym_key,
                                         inbound_symmetric_key_bytes: inbound_sym_key_bytes,
                                         my_address: None,
+                                        migration,
+                                        capabilities,
                                     },
                                     InboundRemoteConnection {
                                         inbound_packet_sender: inbound_sender,
+                                        migration: migration_handle,
                                     },
                                 ));
                             }
@@ -932,10 +1252,15 @@ pub(crate) enum ConnectionEvent {
         remote_public_key: TransportPublicKey,
         open_connection: oneshot::Sender<Result<RemoteConnection, TransportError>>,
     },
+    /// Asks a gateway for the reflexive address of this peer.
+    BindingRequest {
+        reflexive: oneshot::Sender<SocketAddr>,
+    },
 }
 
 struct InboundRemoteConnection {
     inbound_packet_sender: mpsc::Sender<PacketData<UnknownEncryption>>,
+    migration: MigrationHandle,
 }
 
 mod version_cmp {
@@ -1,7 +1,9 @@
 use std::path::Path;
 
 use rand::rngs::OsRng;
-use rsa::{pkcs8, rand_core::CryptoRngCore, Pkcs1v15Encrypt, RsaPrivateKey, RsaPublicKey};
+use rsa::{
+    pkcs8, rand_core::CryptoRngCore, Pkcs1v15Encrypt, Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
@@ -64,6 +66,15 @@ impl TransportKeypair {
         &self.public
     }
 
+    /// Signs the blake3 digest of `msg` with PKCS#1 v1.5 padding.
+    pub fn sign(&self, msg: &[u8]) -> Vec<u8> {
+        let digest = blake3::hash(msg);
+        self.secret
+            .0
+            .sign(Pkcs1v15Sign::new_unprefixed(), digest.as_bytes())
+            .expect("failed to sign")
+    }
+
     #[cfg(test)]
     pub(crate) fn secret(&self) -> &TransportSecretKey {
         &self.secret
@@ -82,6 +93,29 @@ impl TransportPublicKey {
             .expect("failed to encrypt")
     }
 
+    /// Verifies a signature made with [`TransportKeypair::sign`].
+    pub fn verify(&self, msg: &[u8], signature: &[u8]) -> bool {
+        let digest = blake3::hash(msg);
+        self.0
+            .verify(Pkcs1v15Sign::new_unprefixed(), digest.as_bytes(), signature)
+            .is_ok()
+    }
+
+    /// The key encoded as a DER SubjectPublicKeyInfo.
+    pub fn to_der(&self) -> Vec<u8> {
+        use pkcs8::EncodePublicKey;
+        self.0
+            .to_public_key_der()
+            .expect("failed to encode the key")
+            .into_vec()
+    }
+
+    /// Hash of the key (BLAKE3 of its DER encoding) encoded in base58, identifying it unlike
+    /// its abbreviated display.
+    pub fn fingerprint(&self) -> String {
+        bs58::encode(blake3::hash(&self.to_der()).as_bytes()).into_string()
+    }
+
     /// Save the public key to a file in PEM format.
     pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
         use pkcs8::EncodePublicKey;
@@ -159,3 +193,15 @@ fn key_sizes_and_decryption() {
     let bytes = pair.secret.decrypt(&encrypted).unwrap();
     assert_eq!(bytes, sym_key_bytes.as_slice());
 }
+
+#[cfg(test)]
+#[test]
+fn signatures() {
+    let pair = TransportKeypair::new();
+    let signature = pair.sign(b"attested");
+    assert!(pair.public.verify(b"attested", &signature));
+    assert!(!pair.public.verify(b"tampered", &signature));
+    assert!(!TransportKeypair::new()
+        .public
+        .verify(b"attested", &signature));
+}
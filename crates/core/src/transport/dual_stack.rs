@@ -0,0 +1,129 @@
+//! Sockets bound to the unspecified IPv6 address accepting IPv4 traffic too, so peers are
+//! reachable over both families with a single socket.
+//!
+//! Such sockets see IPv4 remotes at their IPv4-mapped IPv6 addresses (`::ffff:a.b.c.d`), which
+//! are converted back to IPv4 addresses so every peer is known at the same address whatever the
+//! family of the socket of the node, and reached at them when sending.
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::{TcpListener, UdpSocket};
+
+/// Binds an UDP socket to `addr`, falling back to the unspecified IPv4 address on hosts without
+/// IPv6 when it is the unspecified IPv6 address.
+pub(crate) fn bind_udp(addr: SocketAddr) -> io::Result<UdpSocket> {
+    UdpSocket::from_std(bind_std_udp(addr)?)
+}
+
+/// Binds a standard UDP socket as [`bind_udp`] does, for the runtimes of other crates.
+pub(crate) fn bind_std_udp(addr: SocketAddr) -> io::Result<std::net::UdpSocket> {
+    Ok(bind_fallback(addr, Type::DGRAM, Protocol::UDP)?.into())
+}
+
+/// Listens for TCP connections at `addr`, as [`bind_udp`] binds sockets.
+pub(crate) fn listen_tcp(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = bind_fallback(addr, Type::STREAM, Protocol::TCP)?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
+}
+
+fn bind_fallback(addr: SocketAddr, ty: Type, protocol: Protocol) -> io::Result<Socket> {
+    match bind(addr, ty, protocol) {
+        Err(error) if is_unspecified_v6(&addr) => {
+            tracing::debug!(%error, "IPv6 unavailable, binding to IPv4 only");
+            bind((Ipv4Addr::UNSPECIFIED, addr.port()).into(), ty, protocol)
+        }
+        bound => bound,
+    }
+}
+
+fn bind(addr: SocketAddr, ty: Type, protocol: Protocol) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(addr), ty, Some(protocol))?;
+    if is_unspecified_v6(&addr) {
+        // not the default on every platform
+        socket.set_only_v6(false)?;
+    }
+    if ty == Type::STREAM {
+        socket.set_reuse_address(true)?;
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    Ok(socket)
+}
+
+fn is_unspecified_v6(addr: &SocketAddr) -> bool {
+    matches!(addr.ip(), IpAddr::V6(ip) if ip.is_unspecified())
+}
+
+/// The address of the remote at `addr`, IPv4 for IPv4-mapped addresses.
+pub(crate) fn canonical(addr: SocketAddr) -> SocketAddr {
+    SocketAddr::new(addr.ip().to_canonical(), addr.port())
+}
+
+/// The address to send to `target` at from a socket bound to `local`.
+pub(crate) fn target_for(local: SocketAddr, target: SocketAddr) -> SocketAddr {
+    match (local, target) {
+        (SocketAddr::V6(_), SocketAddr::V4(v4)) => {
+            SocketAddr::new(v4.ip().to_ipv6_mapped().into(), v4.port())
+        }
+        _ => target,
+    }
+}
+
+/// Whether the host has a route to `addr`, which isn't the case for the addresses of a family
+/// it isn't connected to the internet with. No packet is sent.
+pub(crate) fn has_route(addr: &SocketAddr) -> bool {
+    if addr.ip().is_loopback() {
+        return true;
+    }
+    let unspecified: IpAddr = match addr {
+        SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+    std::net::UdpSocket::bind((unspecified, 0))
+        .and_then(|socket| socket.connect(addr))
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn dual_stack_socket() -> Result<(), Box<dyn std::error::Error>> {
+        let socket = bind_udp((Ipv6Addr::UNSPECIFIED, 0).into())?;
+        let local = socket.local_addr()?;
+        let peer = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        peer.send_to(b"hello", (Ipv4Addr::LOCALHOST, local.port()))
+            .await?;
+
+        let mut buf = [0; 5];
+        let (_, remote) = socket.recv_from(&mut buf).await?;
+        let remote = canonical(remote);
+        assert_eq!(remote, peer.local_addr()?);
+
+        socket.send_to(b"world", target_for(local, remote)).await?;
+        let (_, from) = peer.recv_from(&mut buf).await?;
+        assert_eq!(&buf, b"world");
+        assert_eq!(from.port(), local.port());
+        Ok(())
+    }
+
+    #[test]
+    fn mapped_addresses() {
+        let v4 = SocketAddr::from((Ipv4Addr::new(203, 0, 113, 5), 31337));
+        let mapped = target_for((Ipv6Addr::UNSPECIFIED, 0).into(), v4);
+        assert_eq!(
+            mapped,
+            SocketAddr::from((Ipv4Addr::new(203, 0, 113, 5).to_ipv6_mapped(), 31337))
+        );
+        assert_eq!(canonical(mapped), v4);
+        assert_eq!(target_for((Ipv4Addr::UNSPECIFIED, 0).into(), v4), v4);
+        let v6 = SocketAddr::from((Ipv6Addr::LOCALHOST, 31337));
+        assert_eq!(canonical(v6), v6);
+    }
+}
@@ -0,0 +1,268 @@
+//! Migration of connections to the new address of remotes whose address changed, roaming between
+//! networks or renewing their DHCP lease, so their sessions survive instead of timing out and
+//! being established again from scratch.
+//!
+//! Packets from addresses without a connection are tried against the inbound key of the
+//! connections open, [`MAX_TRIALS_PER_SECOND`] of them at most. A packet decrypting with the key
+//! of a connection is delivered to it, and the connection sends a challenge to the new address,
+//! encrypted with the session key so only the remote can answer it. Once it does, the connection
+//! sends its packets to the new address and the packets from it are routed to the connection.
+//! Until then the old address is kept, so replaying packets of a remote from elsewhere doesn't
+//! redirect its connection.
+
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use aes_gcm::Aes128Gcm;
+use parking_lot::RwLock;
+use tokio::sync::mpsc;
+
+use super::packet_data::{PacketData, UnknownEncryption};
+
+/// Packets from unknown addresses tried against the keys of the connections per second.
+const MAX_TRIALS_PER_SECOND: u32 = 64;
+
+/// Time for a challenge to be answered before it is sent again.
+const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Challenges sent to an address before giving up on validating it.
+const CHALLENGE_ATTEMPTS: u32 = 3;
+
+/// A connection moved from the old address of its remote to the new one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct Migrated {
+    pub from: SocketAddr,
+    pub to: SocketAddr,
+}
+
+/// Creates the halves of the migration of a connection, the remote encrypting its packets with
+/// `inbound_key`, reporting the migrations validated to the listener through `migrated`.
+pub(super) fn path_migration(
+    inbound_key: &Aes128Gcm,
+    migrated: mpsc::Sender<Migrated>,
+) -> (PathValidation, MigrationHandle) {
+    let key = Arc::new(RwLock::new(inbound_key.clone()));
+    let (candidates_sender, candidates) = mpsc::channel(4);
+    (
+        PathValidation {
+            key: key.clone(),
+            candidates,
+            migrated,
+            challenge: None,
+        },
+        MigrationHandle {
+            key,
+            candidates: candidates_sender,
+        },
+    )
+}
+
+/// Half of the migration held by the listener, recognizing the packets of the remote arriving
+/// from a new address.
+pub(super) struct MigrationHandle {
+    key: Arc<RwLock<Aes128Gcm>>,
+    candidates: mpsc::Sender<SocketAddr>,
+}
+
+impl MigrationHandle {
+    pub fn recognizes(&self, packet: &PacketData<UnknownEncryption>) -> bool {
+        packet.try_decrypt_sym(&self.key.read()).is_ok()
+    }
+
+    /// Asks the connection to validate the new address of its remote.
+    pub fn offer(&self, addr: SocketAddr) {
+        // when full the connection is busy validating addresses already
+        let _ = self.candidates.try_send(addr);
+    }
+}
+
+/// Bounds the packets from unknown addresses tried against the keys of the connections, as
+/// every trial costs a decryption per connection.
+pub(super) struct TrialBudget {
+    window_start: Instant,
+    trials: u32,
+}
+
+impl TrialBudget {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            window_start: now,
+            trials: 0,
+        }
+    }
+
+    pub fn try_take(&mut self, now: Instant) -> bool {
+        if now.saturating_duration_since(self.window_start) >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.trials = 0;
+        }
+        if self.trials >= MAX_TRIALS_PER_SECOND {
+            return false;
+        }
+        self.trials += 1;
+        true
+    }
+}
+
+struct Challenge {
+    addr: SocketAddr,
+    token: [u8; 16],
+    sent_at: Instant,
+    attempts: u32,
+}
+
+/// Half of the migration held by the connection, validating the new addresses of its remote.
+pub(super) struct PathValidation {
+    key: Arc<RwLock<Aes128Gcm>>,
+    candidates: mpsc::Receiver<SocketAddr>,
+    migrated: mpsc::Sender<Migrated>,
+    challenge: Option<Challenge>,
+}
+
+impl PathValidation {
+    /// Shares the key the remote encrypts its packets with once rotated.
+    pub fn set_key(&self, key: &Aes128Gcm) {
+        *self.key.write() = key.clone();
+    }
+
+    /// Next address of the remote offered by the listener.
+    pub async fn candidate(&mut self) -> SocketAddr {
+        match self.candidates.recv().await {
+            Some(addr) => addr,
+            // the listener dropped the connection
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Starts validating `addr`, returning the token of the challenge to send to it, `None` if
+    /// it is the address in use or already being validated.
+    pub fn challenge(
+        &mut self,
+        addr: SocketAddr,
+        remote_addr: SocketAddr,
+        now: Instant,
+    ) -> Option<[u8; 16]> {
+        if addr == remote_addr
+            || self
+                .challenge
+                .as_ref()
+                .is_some_and(|challenge| challenge.addr == addr)
+        {
+            return None;
+        }
+        let token = rand::random();
+        self.challenge = Some(Challenge {
+            addr,
+            token,
+            sent_at: now,
+            attempts: 1,
+        });
+        Some(token)
+    }
+
+    /// When the pending challenge times out, if any.
+    pub fn next_check(&self) -> Option<Instant> {
+        self.challenge
+            .as_ref()
+            .map(|challenge| challenge.sent_at + CHALLENGE_TIMEOUT)
+    }
+
+    /// Challenge to send again, if timed out, giving up on the address after a few attempts.
+    pub fn due(&mut self, now: Instant) -> Option<(SocketAddr, [u8; 16])> {
+        let challenge = self.challenge.as_mut()?;
+        if now < challenge.sent_at + CHALLENGE_TIMEOUT {
+            return None;
+        }
+        if challenge.attempts >= CHALLENGE_ATTEMPTS {
+            tracing::debug!(addr = %challenge.addr, "new address of remote not validated");
+            self.challenge = None;
+            return None;
+        }
+        challenge.attempts += 1;
+        challenge.sent_at = now;
+        Some((challenge.addr, challenge.token))
+    }
+
+    /// Reports the answer to a challenge, returning the address it validates.
+    pub fn answered(&mut self, token: &[u8; 16]) -> Option<SocketAddr> {
+        if self
+            .challenge
+            .as_ref()
+            .is_some_and(|challenge| challenge.token == *token)
+        {
+            return self.challenge.take().map(|challenge| challenge.addr);
+        }
+        None
+    }
+
+    /// Moves the packets from the new address of the remote to the connection.
+    pub async fn migrated(&self, from: SocketAddr, to: SocketAddr) {
+        let _ = self.migrated.send(Migrated { from, to }).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aes_gcm::KeyInit;
+
+    use crate::transport::packet_data::Plaintext;
+
+    use super::*;
+
+    fn packet(key: &Aes128Gcm) -> PacketData<UnknownEncryption> {
+        let plain: PacketData<Plaintext> = PacketData::from_buf_plain(b"packet");
+        PacketData::from_buf(plain.encrypt_symmetric(key).data())
+    }
+
+    #[test]
+    fn validates_new_addresses() {
+        let key = Aes128Gcm::new(&rand::random::<[u8; 16]>().into());
+        let (migrated, _) = mpsc::channel(1);
+        let (mut validation, handle) = path_migration(&key, migrated);
+        let old: SocketAddr = "10.0.0.1:1000".parse().unwrap();
+        let new: SocketAddr = "10.0.0.2:1000".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(handle.recognizes(&packet(&key)));
+        let rotated = Aes128Gcm::new(&rand::random::<[u8; 16]>().into());
+        assert!(!handle.recognizes(&packet(&rotated)));
+        validation.set_key(&rotated);
+        assert!(handle.recognizes(&packet(&rotated)));
+
+        assert_eq!(validation.challenge(old, old, now), None);
+        let token = validation.challenge(new, old, now).unwrap();
+        assert_eq!(validation.challenge(new, old, now), None);
+        assert_eq!(validation.next_check(), Some(now + CHALLENGE_TIMEOUT));
+
+        // resent until answered
+        assert_eq!(validation.due(now), None);
+        assert_eq!(validation.due(now + CHALLENGE_TIMEOUT), Some((new, token)));
+        assert_eq!(validation.answered(&[0; 16]), None);
+        assert_eq!(validation.answered(&token), Some(new));
+        assert_eq!(validation.next_check(), None);
+
+        // given up on after a few attempts
+        validation.challenge(new, old, now).unwrap();
+        let mut at = now;
+        for _ in 1..CHALLENGE_ATTEMPTS {
+            at += CHALLENGE_TIMEOUT;
+            assert!(validation.due(at).is_some());
+        }
+        assert_eq!(validation.due(at + CHALLENGE_TIMEOUT), None);
+        assert_eq!(validation.next_check(), None);
+    }
+
+    #[test]
+    fn bounds_trials() {
+        let now = Instant::now();
+        let mut budget = TrialBudget::new(now);
+        for _ in 0..MAX_TRIALS_PER_SECOND {
+            assert!(budget.try_take(now));
+        }
+        assert!(!budget.try_take(now));
+        assert!(budget.try_take(now + Duration::from_secs(1)));
+    }
+}
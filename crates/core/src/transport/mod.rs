@@ -11,15 +11,30 @@ use std::{borrow::Cow, io, net::SocketAddr};
 use futures::Future;
 use tokio::net::UdpSocket;
 
+mod bandwidth;
+mod congestion;
 mod connection_handler;
 mod crypto;
+mod dual_stack;
+mod migration;
+mod mtu;
+mod nat;
+mod negotiation;
 mod packet_data;
 mod peer_connection;
+mod port_mapping;
+mod priority;
+mod quic;
 mod rate_limiter;
 // todo: optimize trackers
 mod received_packet_tracker;
+mod rekey;
+mod relay;
+mod replay;
 mod sent_packet_tracker;
 mod symmetric_message;
+mod tcp_tunnel;
+mod timeouts;
 
 type MessagePayload = Vec<u8>;
 
@@ -34,10 +49,20 @@ pub(crate) use self::{
     symmetric_message::{SymmetricMessage, SymmetricMessagePayload},
 };
 pub(crate) use self::{
+    bandwidth::BandwidthLimits,
     connection_handler::{
         create_connection_handler, InboundConnectionHandler, OutboundConnectionHandler,
     },
-    peer_connection::PeerConnection,
+    dual_stack::has_route,
+    nat::NatMapping,
+    peer_connection::{PeerConnection, RoundTripTime, Traffic, TransportStats},
+    port_mapping::{maintain_port_mapping, PortMappingStatus},
+    quic::QuicEndpoint,
+    rekey::RekeyLimits,
+    relay::RelayLimits,
+    replay::{Replay, ReplayLimits},
+    tcp_tunnel::{TcpTunnels, TunneledSocket},
+    timeouts::ConnectionTimeouts,
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -46,6 +71,8 @@ pub(crate) enum TransportError {
     ChannelClosed,
     #[error("connection to remote closed")]
     ConnectionClosed(SocketAddr),
+    #[error("packets from remote kept failing authentication")]
+    Unauthenticated(SocketAddr),
     #[error("failed while establishing connection, reason: {cause}")]
     ConnectionEstablishmentFailure { cause: Cow<'static, str> },
     #[error("wrong version of the protocol for gateway, expected {expected}, got {actual}")]
@@ -53,6 +80,11 @@ pub(crate) enum TransportError {
         expected: String,
         actual: &'static str,
     },
+    #[error("remote speaks version {remote} of the protocol, incompatible with {local}")]
+    IncompatibleProtocol {
+        remote: String,
+        local: &'static str,
+    },
     #[error(transparent)]
     IO(#[from] std::io::Error),
     #[error(transparent)]
@@ -75,19 +107,29 @@ pub(crate) trait Socket: Sized + Send + Sync + 'static {
         buf: &[u8],
         target: SocketAddr,
     ) -> impl Future<Output = io::Result<usize>> + Send;
+
+    /// Tunnels to fall back to for the remotes that can't be reached over UDP, if supported.
+    fn tunnels(&self) -> Option<TcpTunnels> {
+        None
+    }
 }
 
 impl Socket for UdpSocket {
     async fn bind(addr: SocketAddr) -> io::Result<Self> {
-        Self::bind(addr).await
+        dual_stack::bind_udp(addr)
     }
 
     async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
-        self.recv_from(buf).await
+        let (size, addr) = self.recv_from(buf).await?;
+        crate::metrics::bytes_received(size);
+        Ok((size, dual_stack::canonical(addr)))
     }
 
     async fn send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
-        self.send_to(buf, target).await
+        let target = dual_stack::target_for(self.local_addr()?, target);
+        let size = self.send_to(buf, target).await?;
+        crate::metrics::bytes_sent(size);
+        Ok(size)
     }
 }
 
@@ -0,0 +1,223 @@
+//! Discovery of the largest packets the path to a remote carries, so messages are fragmented to
+//! fit networks with a smaller MTU than Ethernet (VPNs, PPPoE) instead of being blackholed.
+//!
+//! Connections start sending packets of [`MIN_PACKET_SIZE`], which virtually every path carries,
+//! and probe larger sizes with padded packets, tried [`PROBE_ATTEMPTS`] times: each size
+//! acknowledged by the remote becomes the size of the packets sent, until the largest one is or a
+//! probe goes unacknowledged. The path is probed again periodically, starting with the size in use so the
+//! connection falls back to the minimum when the path changed and no longer carries it.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use super::{packet_data, PacketId};
+
+/// Size of the packets sent until larger ones are acknowledged, the largest UDP payload carried
+/// by paths with the minimum MTU of IPv6 once tunneled.
+pub(super) const MIN_PACKET_SIZE: usize = 1200;
+
+/// Sizes probed, by increasing size.
+const PROBE_SIZES: [usize; 3] = [1280, 1400, packet_data::MAX_PACKET_SIZE];
+
+/// Time for a probe to be acknowledged before it is considered lost.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Probes of a size lost before the path is considered not to carry it.
+const PROBE_ATTEMPTS: u32 = 2;
+
+const REPROBE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Size of the packets sent to a remote, shared by its connection and its outbound streams.
+#[derive(Clone, Debug)]
+pub(crate) struct PathMtu(Arc<AtomicUsize>);
+
+impl Default for PathMtu {
+    fn default() -> Self {
+        Self(Arc::new(AtomicUsize::new(MIN_PACKET_SIZE)))
+    }
+}
+
+impl PathMtu {
+    pub fn packet_size(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Size of the messages fitting in the packets sent.
+    pub fn max_data_size(&self) -> usize {
+        packet_data::data_size(self.packet_size())
+    }
+
+    fn set(&self, packet_size: usize) {
+        self.0.store(packet_size, Ordering::Relaxed);
+    }
+}
+
+struct Probe {
+    size: usize,
+    /// Packets the probe was sent as, one per attempt.
+    packet_ids: Vec<PacketId>,
+    sent_at: Instant,
+}
+
+/// Probing of the path to a remote, driven by its connection.
+pub(super) struct MtuProbing {
+    mtu: PathMtu,
+    in_flight: Option<Probe>,
+    /// Whether the size in use was verified this round.
+    verified: bool,
+    next_round: Option<Instant>,
+}
+
+impl MtuProbing {
+    pub fn new(mtu: PathMtu) -> Self {
+        Self {
+            mtu,
+            in_flight: None,
+            verified: false,
+            next_round: None,
+        }
+    }
+
+    /// When a probe is due or times out next.
+    pub fn next_check(&self, now: Instant) -> Instant {
+        match (&self.in_flight, self.next_round) {
+            (Some(probe), _) => probe.sent_at + PROBE_TIMEOUT,
+            (None, Some(next_round)) => next_round,
+            (None, None) => now,
+        }
+    }
+
+    /// Size of the probe to send now, if any.
+    pub fn due(&mut self, now: Instant) -> Option<usize> {
+        if let Some(probe) = &self.in_flight {
+            if now < probe.sent_at + PROBE_TIMEOUT {
+                return None;
+            }
+            if (probe.packet_ids.len() as u32) < PROBE_ATTEMPTS {
+                return Some(probe.size);
+            }
+            let size = probe.size;
+            self.in_flight = None;
+            if !self.verified && size == self.mtu.packet_size() {
+                tracing::debug!(size, "path no longer carries packets of the size in use");
+                self.mtu.set(MIN_PACKET_SIZE);
+                self.verified = true;
+            } else {
+                tracing::debug!(packet_size = self.mtu.packet_size(), "path MTU probed");
+                self.finish_round(now);
+                return None;
+            }
+        }
+        if self.next_round.is_some_and(|next_round| now < next_round) {
+            return None;
+        }
+        self.next_round = None;
+        let current = self.mtu.packet_size();
+        if !self.verified && current > MIN_PACKET_SIZE {
+            return Some(current);
+        }
+        self.verified = true;
+        let next = PROBE_SIZES.into_iter().find(|size| *size > current);
+        if next.is_none() {
+            self.finish_round(now);
+        }
+        next
+    }
+
+    /// Reports the probe of `size` sent as the packet `packet_id`.
+    pub fn sent(&mut self, size: usize, packet_id: PacketId, now: Instant) {
+        match &mut self.in_flight {
+            Some(probe) if probe.size == size => {
+                probe.packet_ids.push(packet_id);
+                probe.sent_at = now;
+            }
+            _ => {
+                self.in_flight = Some(Probe {
+                    size,
+                    packet_ids: vec![packet_id],
+                    sent_at: now,
+                });
+            }
+        }
+    }
+
+    /// Reports the receipts received, raising the size of the packets sent when they acknowledge
+    /// the probe in flight.
+    pub fn acknowledged(&mut self, receipts: &[PacketId]) {
+        let Some(probe) = &self.in_flight else {
+            return;
+        };
+        if !receipts.iter().any(|id| probe.packet_ids.contains(id)) {
+            return;
+        }
+        if probe.size > self.mtu.packet_size() {
+            tracing::trace!(size = probe.size, "path carries larger packets");
+            self.mtu.set(probe.size);
+        }
+        self.verified = true;
+        self.in_flight = None;
+    }
+
+    /// Probes the path from the minimum size again, the remote having moved to another one.
+    pub fn restart(&mut self) {
+        self.mtu.set(MIN_PACKET_SIZE);
+        self.in_flight = None;
+        self.verified = false;
+        self.next_round = None;
+    }
+
+    fn finish_round(&mut self, now: Instant) {
+        self.verified = false;
+        self.next_round = Some(now + REPROBE_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probes_up_to_the_path_mtu() {
+        let mtu = PathMtu::default();
+        let mut probing = MtuProbing::new(mtu.clone());
+        let now = Instant::now();
+        assert_eq!(mtu.packet_size(), MIN_PACKET_SIZE);
+
+        // the path carries the first size probed but not the next
+        assert_eq!(probing.due(now), Some(PROBE_SIZES[0]));
+        probing.sent(PROBE_SIZES[0], 1, now);
+        assert_eq!(probing.due(now), None);
+        probing.acknowledged(&[1]);
+        assert_eq!(mtu.packet_size(), PROBE_SIZES[0]);
+        assert!(mtu.max_data_size() < PROBE_SIZES[0]);
+
+        let mut packet_id = 2;
+        for attempt in 0..PROBE_ATTEMPTS {
+            let at = now + PROBE_TIMEOUT * attempt;
+            assert_eq!(probing.due(at), Some(PROBE_SIZES[1]));
+            probing.sent(PROBE_SIZES[1], packet_id, at);
+            packet_id += 1;
+        }
+        let timed_out = now + PROBE_TIMEOUT * PROBE_ATTEMPTS;
+        assert_eq!(probing.due(timed_out), None);
+        assert_eq!(mtu.packet_size(), PROBE_SIZES[0]);
+        assert_eq!(probing.next_check(now), timed_out + REPROBE_INTERVAL);
+
+        // the path changed and no longer carries the size in use
+        let reprobe = timed_out + REPROBE_INTERVAL;
+        for attempt in 0..PROBE_ATTEMPTS {
+            let at = reprobe + PROBE_TIMEOUT * attempt;
+            assert_eq!(probing.due(at), Some(PROBE_SIZES[0]));
+            probing.sent(PROBE_SIZES[0], packet_id, at);
+            packet_id += 1;
+        }
+        let fallback = reprobe + PROBE_TIMEOUT * PROBE_ATTEMPTS;
+        assert_eq!(probing.due(fallback), Some(PROBE_SIZES[0]));
+        assert_eq!(mtu.packet_size(), MIN_PACKET_SIZE);
+    }
+}
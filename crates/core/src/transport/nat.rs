@@ -0,0 +1,160 @@
+//! Discovery of the reflexive addresses of the peer, the addresses its NAT maps its socket to,
+//! and of the type of this mapping through STUN-like binding exchanges with gateways.
+//!
+//! Peers send gateways binding requests of the `FNBR` magic bytes, an id (u64) and padding, to
+//! which gateways answer, without establishing any connection, binding responses of the `FNBS`
+//! magic bytes, the id of the request and the address the request came from: the tag of the
+//! address (4 or 6), its IP and its port, integers being big endian. Requests are padded to
+//! the size of the largest response so gateways can't be used to amplify traffic.
+//!
+//! Comparing the reflexive addresses reported by different gateways tells whether the NAT of
+//! the peer keeps mapping its socket to the same address whatever the destination, which hole
+//! punching between peers relies on.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use serde::{Deserialize, Serialize};
+
+const REQUEST_MAGIC: [u8; 4] = *b"FNBR";
+const RESPONSE_MAGIC: [u8; 4] = *b"FNBS";
+
+/// Size of binding requests, that of the largest binding response.
+const REQUEST_SIZE: usize = RESPONSE_MAGIC.len() + 8 + 1 + 16 + 2;
+
+/// How the NAT of a peer maps its socket to public addresses.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum NatMapping {
+    /// Not known yet, e.g. with a single gateway to compare reflexive addresses from.
+    #[default]
+    Unknown,
+    /// The socket is mapped to the same address for every destination, or isn't behind a NAT.
+    EndpointIndependent,
+    /// The socket is mapped to different addresses depending on the destination, as by
+    /// symmetric NATs, so remotes can't reach it at the address learnt from others.
+    AddressDependent,
+}
+
+impl NatMapping {
+    /// The mapping given the reflexive addresses of a socket reported by different gateways.
+    pub fn from_reflexive(reflexive: &[SocketAddr]) -> Self {
+        match reflexive {
+            [] | [_] => Self::Unknown,
+            [first, rest @ ..] if rest.iter().all(|addr| addr == first) => {
+                Self::EndpointIndependent
+            }
+            _ => Self::AddressDependent,
+        }
+    }
+
+    /// Whether hole punching between peers behind these mappings may succeed, which requires
+    /// at least one of them to be reachable at the address learnt from others.
+    pub fn can_traverse(self, other: Self) -> bool {
+        !(self == Self::AddressDependent && other == Self::AddressDependent)
+    }
+}
+
+pub(super) fn binding_request(id: u64) -> [u8; REQUEST_SIZE] {
+    let mut request = [0; REQUEST_SIZE];
+    request[..4].copy_from_slice(&REQUEST_MAGIC);
+    request[4..12].copy_from_slice(&id.to_be_bytes());
+    request
+}
+
+/// Id of the binding request in `packet`, if it is one.
+pub(super) fn parse_binding_request(packet: &[u8]) -> Option<u64> {
+    if packet.len() != REQUEST_SIZE || !packet.starts_with(&REQUEST_MAGIC) {
+        return None;
+    }
+    Some(u64::from_be_bytes(packet[4..12].try_into().ok()?))
+}
+
+pub(super) fn binding_response(id: u64, reflexive: SocketAddr) -> Vec<u8> {
+    let mut response = Vec::with_capacity(REQUEST_SIZE);
+    response.extend_from_slice(&RESPONSE_MAGIC);
+    response.extend_from_slice(&id.to_be_bytes());
+    encode_addr(&mut response, reflexive);
+    response
+}
+
+/// Appends the tag of the address (4 or 6), its IP and its port to `buf`.
+pub(super) fn encode_addr(buf: &mut Vec<u8>, addr: SocketAddr) {
+    match addr.ip() {
+        IpAddr::V4(ip) => {
+            buf.push(4);
+            buf.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            buf.push(6);
+            buf.extend_from_slice(&ip.octets());
+        }
+    }
+    buf.extend_from_slice(&addr.port().to_be_bytes());
+}
+
+/// The address encoded at the start of `bytes` by [`encode_addr`] and the bytes following it.
+pub(super) fn decode_addr(bytes: &[u8]) -> Option<(SocketAddr, &[u8])> {
+    let (ip, rest): (IpAddr, _) = match bytes {
+        [4, rest @ ..] if rest.len() >= 6 => (
+            Ipv4Addr::from(<[u8; 4]>::try_from(&rest[..4]).ok()?).into(),
+            &rest[4..],
+        ),
+        [6, rest @ ..] if rest.len() >= 18 => (
+            Ipv6Addr::from(<[u8; 16]>::try_from(&rest[..16]).ok()?).into(),
+            &rest[16..],
+        ),
+        _ => return None,
+    };
+    let port = u16::from_be_bytes(rest[..2].try_into().ok()?);
+    Some((SocketAddr::new(ip, port), &rest[2..]))
+}
+
+/// Id of the binding request answered by the binding response in `packet` and the reflexive
+/// address reported, if it is one.
+pub(super) fn parse_binding_response(packet: &[u8]) -> Option<(u64, SocketAddr)> {
+    let packet = packet.strip_prefix(&RESPONSE_MAGIC)?;
+    let id = u64::from_be_bytes(packet.get(..8)?.try_into().ok()?);
+    match decode_addr(packet.get(8..)?)? {
+        (reflexive, []) => Some((id, reflexive)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binding_framing() {
+        let request = binding_request(7);
+        assert_eq!(parse_binding_request(&request), Some(7));
+        assert_eq!(parse_binding_request(&request[..12]), None);
+        assert_eq!(parse_binding_response(&request), None);
+
+        for reflexive in [
+            SocketAddr::from((Ipv4Addr::new(203, 0, 113, 5), 40000)),
+            SocketAddr::from((Ipv6Addr::LOCALHOST, 31337)),
+        ] {
+            let response = binding_response(7, reflexive);
+            assert!(response.len() <= request.len());
+            assert_eq!(parse_binding_response(&response), Some((7, reflexive)));
+            assert_eq!(parse_binding_request(&response), None);
+        }
+    }
+
+    #[test]
+    fn nat_mapping() {
+        let addr = |port| SocketAddr::from((Ipv4Addr::new(203, 0, 113, 5), port));
+        assert_eq!(NatMapping::from_reflexive(&[addr(1)]), NatMapping::Unknown);
+        assert_eq!(
+            NatMapping::from_reflexive(&[addr(1), addr(1)]),
+            NatMapping::EndpointIndependent
+        );
+        assert_eq!(
+            NatMapping::from_reflexive(&[addr(1), addr(1), addr(2)]),
+            NatMapping::AddressDependent
+        );
+        assert!(NatMapping::AddressDependent.can_traverse(NatMapping::EndpointIndependent));
+        assert!(NatMapping::Unknown.can_traverse(NatMapping::AddressDependent));
+        assert!(!NatMapping::AddressDependent.can_traverse(NatMapping::AddressDependent));
+    }
+}
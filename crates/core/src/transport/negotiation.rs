@@ -0,0 +1,161 @@
+//! Negotiation of the protocol spoken over a connection, so peers running adjacent versions keep
+//! interoperating while the network upgrades, and incompatible peers fail right away with a
+//! clear error instead of timing out.
+//!
+//! Peers exchange their protocol version and the optional features of the protocol they support
+//! when establishing a connection. Versions with the same major version and pre-release, and
+//! minor versions at most one apart, are compatible; the connection then only uses the features
+//! supported by both ends. Connections with incompatible peers are refused, the remote being
+//! answered with the version expected so it can report the mismatch instead of retrying.
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::PCK_VERSION;
+
+use super::TransportError;
+
+/// Optional features of the protocol supported by a peer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Capabilities(u32);
+
+impl Capabilities {
+    pub const NONE: Self = Self(0);
+    /// Carrying messages over a QUIC link, see [`super::quic`].
+    pub const QUIC: Self = Self(1);
+    /// Rotation of the session keys, see [`super::rekey`].
+    pub const REKEY: Self = Self(1 << 1);
+    /// Probing the size of the packets the path carries, see [`super::mtu`].
+    pub const MTU_PROBES: Self = Self(1 << 2);
+    /// Migration of connections to new addresses, see [`super::migration`].
+    pub const PATH_MIGRATION: Self = Self(1 << 3);
+    /// The features supported by this peer.
+    pub const LOCAL: Self =
+        Self(Self::QUIC.0 | Self::REKEY.0 | Self::MTU_PROBES.0 | Self::PATH_MIGRATION.0);
+
+    pub fn contains(self, feature: Self) -> bool {
+        self.0 & feature.0 == feature.0
+    }
+
+    /// The features supported both by this peer and a remote supporting `self`.
+    pub fn common(self) -> Self {
+        Self(self.0 & Self::LOCAL.0)
+    }
+}
+
+/// Length of the protocol version sent in the handshake.
+const VERSION_LEN: usize = 8;
+
+/// The first packet sent to a remote, encrypted with its public key.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct Intro {
+    pub version: [u8; VERSION_LEN],
+    /// Key the remote encrypts its packets with.
+    pub key: [u8; 16],
+    pub capabilities: Capabilities,
+}
+
+impl Intro {
+    pub const LEN: usize = VERSION_LEN + 16 + 4;
+
+    pub fn new(version: [u8; VERSION_LEN], key: [u8; 16]) -> Self {
+        Self {
+            version,
+            key,
+            capabilities: Capabilities::LOCAL,
+        }
+    }
+
+    pub fn encode(&self) -> [u8; Self::LEN] {
+        let mut data = [0; Self::LEN];
+        data[..VERSION_LEN].copy_from_slice(&self.version);
+        data[VERSION_LEN..VERSION_LEN + 16].copy_from_slice(&self.key);
+        data[VERSION_LEN + 16..].copy_from_slice(&self.capabilities.0.to_le_bytes());
+        data
+    }
+
+    /// Decodes the intro of a remote, without capabilities if its version predates them.
+    pub fn decode(data: &[u8]) -> Result<Self, TransportError> {
+        let too_small = || TransportError::ConnectionEstablishmentFailure {
+            cause: "intro packet too small to contain protocol version and key".into(),
+        };
+        let version = data
+            .get(..VERSION_LEN)
+            .ok_or_else(too_small)?
+            .try_into()
+            .expect("correct length");
+        let key = data
+            .get(VERSION_LEN..VERSION_LEN + 16)
+            .ok_or_else(too_small)?
+            .try_into()
+            .expect("correct length");
+        let capabilities = data
+            .get(VERSION_LEN + 16..Self::LEN)
+            .map(|bytes| {
+                Capabilities(u32::from_le_bytes(
+                    bytes.try_into().expect("correct length"),
+                ))
+            })
+            .unwrap_or(Capabilities::NONE);
+        Ok(Self {
+            version,
+            key,
+            capabilities,
+        })
+    }
+}
+
+/// Checks the version of the protocol spoken by a remote is compatible with `ours`.
+pub(super) fn check_compatible(
+    ours: [u8; VERSION_LEN],
+    theirs: [u8; VERSION_LEN],
+) -> Result<(), TransportError> {
+    // major, minor and patch versions, then the hash of the pre-release
+    let compatible =
+        ours[0] == theirs[0] && ours[3..7] == theirs[3..7] && ours[1].abs_diff(theirs[1]) <= 1;
+    if compatible {
+        return Ok(());
+    }
+    Err(TransportError::IncompatibleProtocol {
+        remote: format!("{}.{}.{}", theirs[0], theirs[1], theirs[2]),
+        local: PCK_VERSION,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(major: u8, minor: u8, patch: u8) -> [u8; VERSION_LEN] {
+        [major, minor, patch, 0, 0, 0, 0, 0]
+    }
+
+    #[test]
+    fn accepts_adjacent_versions() {
+        let ours = version(1, 4, 2);
+        assert!(check_compatible(ours, version(1, 4, 0)).is_ok());
+        assert!(check_compatible(ours, version(1, 3, 7)).is_ok());
+        assert!(check_compatible(ours, version(1, 5, 0)).is_ok());
+        assert!(check_compatible(ours, version(1, 6, 0)).is_err());
+        assert!(check_compatible(ours, version(2, 4, 2)).is_err());
+        let mut pre_release = ours;
+        pre_release[6] = 1;
+        assert!(check_compatible(ours, pre_release).is_err());
+    }
+
+    #[test]
+    fn negotiates_capabilities() {
+        let intro = Intro::new(version(1, 4, 2), [7; 16]);
+        assert_eq!(Intro::decode(&intro.encode()).unwrap(), intro);
+
+        // remotes predating capabilities support none of them
+        let legacy = Intro::decode(&intro.encode()[..VERSION_LEN + 16]).unwrap();
+        assert_eq!(legacy.capabilities, Capabilities::NONE);
+        assert!(Intro::decode(&intro.encode()[..VERSION_LEN + 8]).is_err());
+
+        let remote = Capabilities(Capabilities::REKEY.0 | 1 << 31);
+        let common = remote.common();
+        assert!(common.contains(Capabilities::REKEY));
+        assert!(!common.contains(Capabilities::QUIC));
+        assert!(!common.contains(Capabilities(1 << 31)));
+    }
+}
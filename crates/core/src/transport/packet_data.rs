@@ -20,9 +20,14 @@ pub(in crate::transport) const MAX_PACKET_SIZE: usize = 1500 - UDP_HEADER_SIZE;
 const NONCE_SIZE: usize = 12;
 const TAG_SIZE: usize = 16;
 
-pub(super) const MAX_DATA_SIZE: usize = MAX_PACKET_SIZE - NONCE_SIZE - TAG_SIZE;
+pub(super) const MAX_DATA_SIZE: usize = data_size(MAX_PACKET_SIZE);
 const UDP_HEADER_SIZE: usize = 8;
 
+/// Size of the data fitting in encrypted packets of `packet_size`.
+pub(super) const fn data_size(packet_size: usize) -> usize {
+    packet_size - NONCE_SIZE - TAG_SIZE
+}
+
 thread_local! {
     // This must be very fast, but doesn't need to be cryptographically secure.
     static RNG: RefCell<SmallRng> = RefCell::new(
@@ -1,5 +1,5 @@
 use std::net::SocketAddr;
-use std::sync::atomic::AtomicU32;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use std::{collections::HashMap, time::Instant};
@@ -7,7 +7,7 @@ use std::{collections::HashMap, time::Instant};
 use crate::transport::connection_handler::NAT_TRAVERSAL_MAX_ATTEMPTS;
 use crate::transport::packet_data::UnknownEncryption;
 use crate::transport::sent_packet_tracker::MESSAGE_CONFIRMATION_TIMEOUT;
-use aes_gcm::Aes128Gcm;
+use aes_gcm::{Aes128Gcm, KeyInit};
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
@@ -19,26 +19,30 @@ mod inbound_stream;
 mod outbound_stream;
 
 use super::{
+    bandwidth,
     connection_handler::SerializedMessage,
-    packet_data::{self, PacketData},
+    migration::PathValidation,
+    mtu::{MtuProbing, PathMtu},
+    negotiation::Capabilities,
+    packet_data::PacketData,
+    priority::{OutboundQueues, PacketClass},
+    quic::{QuicEndpoint, QuicLink},
     received_packet_tracker::ReceivedPacketTracker,
     received_packet_tracker::ReportResult,
+    rekey::{KeyRotation, RekeyLimits},
+    replay::{ReplayLimits, ReplayWindow},
     sent_packet_tracker::{ResendAction, SentPacketTracker},
     symmetric_message::{self, SymmetricMessage, SymmetricMessagePayload},
+    timeouts::ConnectionTimeouts,
     TransportError,
 };
 use crate::util::time_source::InstantTimeSrc;
 
 type Result<T = (), E = TransportError> = std::result::Result<T, E>;
 
-// TODO: measure the space overhead of SymmetricMessage::ShortMessage since is likely less than 100
-/// The max payload we can send in a single fragment, this MUST be less than packet_data::MAX_DATA_SIZE
-/// since we need to account for the space overhead of SymmetricMessage::LongMessage metadata
-const MAX_DATA_SIZE: usize = packet_data::MAX_DATA_SIZE - 100;
-
 #[must_use]
 pub(crate) struct RemoteConnection {
-    pub(super) outbound_packets: mpsc::Sender<(SocketAddr, Arc<[u8]>)>,
+    pub(super) outbound_packets: OutboundQueues,
     pub(super) outbound_symmetric_key: Aes128Gcm,
     pub(super) remote_addr: SocketAddr,
     pub(super) sent_tracker: Arc<parking_lot::Mutex<SentPacketTracker<InstantTimeSrc>>>,
@@ -47,6 +51,110 @@ pub(crate) struct RemoteConnection {
     pub(super) inbound_symmetric_key: Aes128Gcm,
     pub(super) inbound_symmetric_key_bytes: [u8; 16],
     pub(super) my_address: Option<SocketAddr>,
+    /// Validation of the new addresses of the remote, see [`super::migration`].
+    pub(super) migration: PathValidation,
+    /// Optional features of the protocol supported by both ends, see [`super::negotiation`].
+    pub(super) capabilities: Capabilities,
+}
+
+/// Round-trip time measured for a connection, updated for as long as it is open.
+#[derive(Clone)]
+pub(crate) struct RoundTripTime(Arc<parking_lot::Mutex<SentPacketTracker<InstantTimeSrc>>>);
+
+impl RoundTripTime {
+    pub fn get(&self) -> Option<Duration> {
+        self.0.lock().round_trip_time()
+    }
+
+    pub fn timeouts(&self) -> ConnectionTimeouts {
+        self.0.lock().timeouts()
+    }
+
+    /// Time the remote has not acknowledged the packets sent to it for, `None` if none are
+    /// pending.
+    pub fn silent_for(&self) -> Option<Duration> {
+        self.0.lock().silent_for()
+    }
+
+    /// Statistics of the packets sent through the connection, without its traffic.
+    pub fn stats(&self) -> TransportStats {
+        let tracker = self.0.lock();
+        let (packets_sent, retransmissions) = tracker.packets_sent();
+        TransportStats {
+            round_trip_ms: tracker.round_trip_time().map(|rtt| rtt.as_millis() as u64),
+            packet_loss: tracker.packet_loss(),
+            packets_sent,
+            retransmissions,
+            ..Default::default()
+        }
+    }
+}
+
+/// Statistics of a connection since it was opened, for operators to tell which peers hurt the
+/// performance of the node.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct TransportStats {
+    /// Round-trip time in milliseconds, `None` until measured.
+    pub round_trip_ms: Option<u64>,
+    /// Smoothed proportion of the packets sent which were lost, from 0 to 1.
+    pub packet_loss: f64,
+    pub packets_sent: u64,
+    /// Packets resent for not being acknowledged in time.
+    pub retransmissions: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Average bytes sent per second since the connection was opened.
+    pub sent_bytes_per_second: f64,
+    /// Average bytes received per second since the connection was opened.
+    pub received_bytes_per_second: f64,
+}
+
+impl TransportStats {
+    /// Adds the traffic of a connection open for `open_for`.
+    pub fn with_traffic(self, traffic: &Traffic, open_for: Duration) -> Self {
+        let (bytes_sent, bytes_received) = traffic.totals();
+        let secs = open_for.as_secs_f64().max(1.0);
+        Self {
+            bytes_sent,
+            bytes_received,
+            sent_bytes_per_second: bytes_sent as f64 / secs,
+            received_bytes_per_second: bytes_received as f64 / secs,
+            ..self
+        }
+    }
+}
+
+/// Bytes of the messages sent to and received from the remote, counted for as long as the
+/// connection is open.
+#[derive(Clone, Default)]
+pub(crate) struct Traffic(Arc<[AtomicU64; 4]>);
+
+impl Traffic {
+    const SENT: usize = 0;
+    const RECEIVED: usize = 1;
+    const TOTAL: usize = 2;
+
+    /// Bytes sent and received since last taken.
+    pub fn take(&self) -> (u64, u64) {
+        (
+            self.0[Self::SENT].swap(0, Ordering::Relaxed),
+            self.0[Self::RECEIVED].swap(0, Ordering::Relaxed),
+        )
+    }
+
+    /// Bytes sent and received since the connection was opened.
+    pub fn totals(&self) -> (u64, u64) {
+        (
+            self.0[Self::TOTAL + Self::SENT].load(Ordering::Relaxed),
+            self.0[Self::TOTAL + Self::RECEIVED].load(Ordering::Relaxed),
+        )
+    }
+
+    fn record(&self, direction: usize, bytes: usize) {
+        self.0[direction].fetch_add(bytes as u64, Ordering::Relaxed);
+        self.0[Self::TOTAL + direction].fetch_add(bytes as u64, Ordering::Relaxed);
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -99,6 +207,13 @@ pub(crate) struct PeerConnection {
     failure_count: usize,
     first_failure_time: Option<std::time::Instant>,
     last_packet_report_time: Instant,
+    quic: QuicLink,
+    key_rotation: KeyRotation,
+    replay_window: ReplayWindow,
+    traffic: Traffic,
+    /// Size of the packets sent to the remote, probed with `mtu_probing`.
+    path_mtu: PathMtu,
+    mtu_probing: MtuProbing,
 }
 
 impl std::fmt::Debug for PeerConnection {
@@ -125,6 +240,7 @@ type RemoteConnectionMock = (
 
 impl PeerConnection {
     pub(super) fn new(remote_conn: RemoteConnection) -> Self {
+        let path_mtu = PathMtu::default();
         Self {
             remote_conn,
             received_tracker: ReceivedPacketTracker::new(),
@@ -134,7 +250,48 @@ impl PeerConnection {
             failure_count: 0,
             first_failure_time: None,
             last_packet_report_time: Instant::now(),
+            quic: QuicLink::new(None),
+            key_rotation: KeyRotation::new(RekeyLimits::default(), Instant::now()),
+            replay_window: ReplayWindow::new(ReplayLimits::default()),
+            traffic: Traffic::default(),
+            path_mtu: path_mtu.clone(),
+            mtu_probing: MtuProbing::new(path_mtu),
+        }
+    }
+
+    /// Offers the remote peer to carry messages over a QUIC link to `endpoint` when given.
+    pub(super) fn with_quic(mut self, endpoint: Option<QuicEndpoint>) -> Self {
+        let supported = self.supports(Capabilities::QUIC);
+        self.quic = QuicLink::new(endpoint.filter(|_| supported));
+        self
+    }
+
+    /// Whether both ends of the connection support the optional feature of the protocol.
+    fn supports(&self, feature: Capabilities) -> bool {
+        self.remote_conn.capabilities.contains(feature)
+    }
+
+    /// Limits the upstream traffic to the remote peer to `bytes_per_second` when given.
+    pub(super) fn with_upload_limit(mut self, bytes_per_second: Option<usize>) -> Self {
+        if let Some(bytes_per_second) = bytes_per_second {
+            self.remote_conn.outbound_packets = bandwidth::throttled_queues(
+                self.remote_conn.outbound_packets.clone(),
+                bytes_per_second,
+            );
         }
+        self
+    }
+
+    /// Rotates the key the remote peer encrypts its packets with within the given limits.
+    pub(super) fn with_rekey(mut self, limits: RekeyLimits) -> Self {
+        self.key_rotation = KeyRotation::new(limits, Instant::now());
+        self
+    }
+
+    /// Rejects the packets replayed to the connection within the given limits.
+    pub(super) fn with_replay_protection(mut self, limits: ReplayLimits) -> Self {
+        self.replay_window = ReplayWindow::new(limits);
+        self
     }
 
     #[cfg(test)]
@@ -147,8 +304,10 @@ impl PeerConnection {
         use parking_lot::Mutex;
         let (outbound_packets, outbound_packets_recv) = mpsc::channel(1);
         let (inbound_packet_sender, inbound_packet_recv) = mpsc::channel(1);
+        let (migration, _) =
+            super::migration::path_migration(&inbound_symmetric_key, mpsc::channel(1).0);
         let remote = RemoteConnection {
-            outbound_packets,
+            outbound_packets: OutboundQueues::single(outbound_packets),
             outbound_symmetric_key,
             remote_addr,
             sent_tracker: Arc::new(Mutex::new(SentPacketTracker::new())),
@@ -157,6 +316,8 @@ impl PeerConnection {
             inbound_symmetric_key,
             inbound_symmetric_key_bytes: [1; 16],
             my_address: Some(my_address),
+            migration,
+            capabilities: Capabilities::LOCAL,
         };
         (
             Self::new(remote),
@@ -175,9 +336,11 @@ impl PeerConnection {
         use parking_lot::Mutex;
         let (outbound_packets, outbound_packets_recv) = mpsc::channel(1);
         let (inbound_packet_sender, inbound_packet_recv) = mpsc::channel(1);
+        let (migration, _) =
+            super::migration::path_migration(&inbound_symmetric_key, mpsc::channel(1).0);
         (
             RemoteConnection {
-                outbound_packets,
+                outbound_packets: OutboundQueues::single(outbound_packets),
                 outbound_symmetric_key,
                 remote_addr,
                 sent_tracker: Arc::new(Mutex::new(SentPacketTracker::new())),
@@ -186,49 +349,83 @@ impl PeerConnection {
                 inbound_symmetric_key,
                 inbound_symmetric_key_bytes: [1; 16],
                 my_address: Some(my_address),
+                migration,
+                capabilities: Capabilities::LOCAL,
             },
             inbound_packet_sender,
             outbound_packets_recv,
         )
     }
 
-    #[instrument(name = "peer_connection", skip_all)]
     pub async fn send<T>(&mut self, data: T) -> Result
+    where
+        T: Serialize + Send + std::fmt::Debug + 'static,
+    {
+        self.send_with_priority(data, false).await
+    }
+
+    /// Sends a message of a background operation, after the packets of others queued.
+    pub async fn send_background<T>(&mut self, data: T) -> Result
+    where
+        T: Serialize + Send + std::fmt::Debug + 'static,
+    {
+        self.send_with_priority(data, true).await
+    }
+
+    #[instrument(name = "peer_connection", skip_all)]
+    async fn send_with_priority<T>(&mut self, data: T, background: bool) -> Result
     where
         T: Serialize + Send + std::fmt::Debug + 'static,
     {
         let data = tokio::task::spawn_blocking(move || bincode::serialize(&data).unwrap())
             .await
             .unwrap();
-        if data.len() + SymmetricMessage::short_message_overhead() > MAX_DATA_SIZE {
+        self.traffic.record(Traffic::SENT, data.len());
+        let data = match self.quic.send(data).await {
+            Ok(()) => {
+                tracing::trace!("sent over QUIC link");
+                return Ok(());
+            }
+            Err(data) => data,
+        };
+        let max_data_size = self.path_mtu.max_data_size() - outbound_stream::FRAGMENT_OVERHEAD;
+        let (message_class, stream_class) = if background {
+            (PacketClass::Background, PacketClass::Background)
+        } else {
+            (PacketClass::Message, PacketClass::Bulk)
+        };
+        if data.len() + SymmetricMessage::short_message_overhead() > max_data_size {
             tracing::trace!(total_size = data.len(), "sending as stream");
-            self.outbound_stream(data).await;
+            self.outbound_stream(data, stream_class).await;
         } else {
             tracing::trace!("sending as short message");
-            self.outbound_short_message(data).await?;
+            self.outbound_message(data, message_class).await?;
         }
         Ok(())
     }
 
-    #[instrument(name = "peer_connection", skip(self))]
     pub async fn recv(&mut self) -> Result<Vec<u8>> {
+        let msg = self.recv_message().await?;
+        self.traffic.record(Traffic::RECEIVED, msg.len());
+        Ok(msg)
+    }
+
+    #[instrument(name = "peer_connection", skip(self))]
+    async fn recv_message(&mut self) -> Result<Vec<u8>> {
         // listen for incoming messages or receipts or wait until is time to do anything else again
         let mut resend_check = Some(tokio::time::sleep(tokio::time::Duration::from_millis(10)));
 
-        #[cfg(debug_assertions)]
-        const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(2);
-        #[cfg(not(debug_assertions))]
-        const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(20);
-        #[cfg(debug_assertions)]
-        const KILL_CONNECTION_AFTER: Duration = Duration::from_secs(6);
-        #[cfg(not(debug_assertions))]
-        const KILL_CONNECTION_AFTER: Duration = Duration::from_secs(60);
-
-        let mut keep_alive = tokio::time::interval(KEEP_ALIVE_INTERVAL);
+        let mut timeouts = self.remote_conn.sent_tracker.lock().timeouts();
+        let mut keep_alive = tokio::time::interval(timeouts.keep_alive);
         keep_alive.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
         keep_alive.tick().await;
         let mut last_received = std::time::Instant::now();
 
+        if let Some(offer) = self.quic.offer() {
+            tracing::debug!(remote = ?self.remote_conn.remote_addr, "offering QUIC link");
+            self.outbound_quic_offer(offer).await?;
+        }
+
         const FAILURE_TIME_WINDOW: Duration = Duration::from_secs(30);
         loop {
             // tracing::trace!(remote = ?self.remote_conn.remote_addr, "waiting for inbound messages");
@@ -236,7 +433,14 @@ impl PeerConnection {
                 inbound = self.remote_conn.inbound_packet_recv.recv() => {
                     let packet_data = inbound.ok_or(TransportError::ConnectionClosed(self.remote_addr()))?;
                     last_received = std::time::Instant::now();
-                    let Ok(decrypted) = packet_data.try_decrypt_sym(&self.remote_conn.inbound_symmetric_key).inspect_err(|error| {
+                    let decrypted = match packet_data.try_decrypt_sym(&self.remote_conn.inbound_symmetric_key) {
+                        Ok(decrypted) => {
+                            self.key_rotation.received(decrypted.data().len());
+                            Ok(decrypted)
+                        }
+                        Err(error) => self.key_rotation.decrypt_previous(&packet_data).ok_or(error),
+                    };
+                    let Ok(decrypted) = decrypted.inspect_err(|error| {
                         tracing::warn!(%error, remote = ?self.remote_conn.remote_addr, "Failed to decrypt packet, might be an intro packet or a partial packet");
                     }) else {
                         let now = Instant::now();
@@ -256,19 +460,30 @@ impl PeerConnection {
 
                         if self.failure_count > NAT_TRAVERSAL_MAX_ATTEMPTS {
                             tracing::warn!(remote = ?self.remote_conn.remote_addr, "Dropping connection due to repeated decryption failures");
-                            // Drop the connection (implement the logic to drop the connection here)
-                            return Err(TransportError::ConnectionClosed(self.remote_addr()));
+                            return Err(TransportError::Unauthenticated(self.remote_addr()));
                         }
 
                         tracing::trace!(remote = ?self.remote_conn.remote_addr, "ignoring packet");
                         continue;
                     };
-                    let msg = SymmetricMessage::deser(decrypted.data()).unwrap();
+                    let msg = match SymmetricMessage::deser(decrypted.data()) {
+                        Ok(msg) => msg,
+                        Err(error) => {
+                            // might carry a feature of the protocol this version doesn't know about
+                            tracing::debug!(%error, remote = %self.remote_conn.remote_addr, "ignoring undecodable packet");
+                            continue;
+                        }
+                    };
                     let SymmetricMessage {
                         packet_id,
                         confirm_receipt,
                         payload,
                     } = msg;
+                    if let Err(replay) = self.replay_window.check(packet_id) {
+                        tracing::trace!(%packet_id, ?replay, remote = %self.remote_conn.remote_addr, "rejected replayed packet");
+                        crate::metrics::packet_replayed(replay);
+                        continue;
+                    }
                     {
                         tracing::trace!(
                             remote = %self.remote_conn.remote_addr,
@@ -297,6 +512,7 @@ impl PeerConnection {
                         .sent_tracker
                         .lock()
                         .report_received_receipts(&confirm_receipt);
+                    self.mtu_probing.acknowledged(&confirm_receipt);
 
                     let report_result = self.received_tracker.report_received_packet(packet_id);
                     match (report_result, should_send_receipts) {
@@ -312,6 +528,9 @@ impl PeerConnection {
                             continue;
                         }
                     }
+                    if self.supports(Capabilities::REKEY) {
+                        self.rekey(current_time).await?;
+                    }
                     if let Some(msg) = self.process_inbound(payload).await.map_err(|error| {
                         tracing::error!(%error, %packet_id, remote = %self.remote_conn.remote_addr, "error processing inbound packet");
                         error
@@ -341,13 +560,46 @@ impl PeerConnection {
                     };
                     res.map_err(|e| TransportError::Other(e.into()))??
                 }
+                msg = self.quic.recv() => {
+                    return Ok(msg);
+                }
+                _ = tokio::time::sleep_until(self.mtu_probing.next_check(Instant::now()).into()),
+                    if self.supports(Capabilities::MTU_PROBES) => {
+                    self.probe_mtu().await?;
+                }
+                _ = tokio::time::sleep_until(self.remote_conn.migration.next_check().unwrap_or_else(Instant::now).into()),
+                    if self.remote_conn.migration.next_check().is_some() => {
+                    if let Some((addr, token)) = self.remote_conn.migration.due(Instant::now()) {
+                        self.send_path_message(addr, symmetric_message::PathMessage::Challenge(token)).await?;
+                    }
+                }
+                addr = self.remote_conn.migration.candidate(), if self.supports(Capabilities::PATH_MIGRATION) => {
+                    let remote_addr = self.remote_conn.remote_addr;
+                    if let Some(token) = self.remote_conn.migration.challenge(addr, remote_addr, Instant::now()) {
+                        tracing::debug!(remote = %remote_addr, %addr, "validating new address of remote");
+                        self.send_path_message(addr, symmetric_message::PathMessage::Challenge(token)).await?;
+                    }
+                }
                 _ = keep_alive.tick() => {
-                    if last_received.elapsed() > KILL_CONNECTION_AFTER {
+                    let measured = self.remote_conn.sent_tracker.lock().timeouts();
+                    if measured.keep_alive != timeouts.keep_alive {
+                        tracing::trace!(remote = ?self.remote_conn.remote_addr, ?measured, "adjusting timeouts");
+                        keep_alive = tokio::time::interval_at(
+                            tokio::time::Instant::now() + measured.keep_alive,
+                            measured.keep_alive,
+                        );
+                        keep_alive.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+                    }
+                    timeouts = measured;
+                    if last_received.elapsed() > timeouts.idle {
                         tracing::warn!(remote = ?self.remote_conn.remote_addr, "connection timed out");
                         return Err(TransportError::ConnectionClosed(self.remote_addr()));
                     }
                     tracing::trace!(remote = ?self.remote_conn.remote_addr, "sending keep-alive");
                     self.noop(vec![]).await?;
+                    if self.supports(Capabilities::REKEY) {
+                        self.rekey(Instant::now()).await?;
+                    }
                 }
                 _ = resend_check.take().unwrap_or(tokio::time::sleep(Duration::from_millis(10))) => {
                     loop {
@@ -364,7 +616,7 @@ impl PeerConnection {
                             ResendAction::Resend(idx, packet) => {
                                 self.remote_conn
                                     .outbound_packets
-                                    .send((self.remote_conn.remote_addr, packet.clone()))
+                                    .send(PacketClass::Message, (self.remote_conn.remote_addr, packet.clone()))
                                     .await
                                     .map_err(|_| TransportError::ConnectionClosed(self.remote_addr()))?;
                                 self.remote_conn.sent_tracker.lock().report_sent_packet(idx, packet);
@@ -385,6 +637,14 @@ impl PeerConnection {
         self.remote_conn.remote_addr
     }
 
+    pub fn round_trip_time(&self) -> RoundTripTime {
+        RoundTripTime(self.remote_conn.sent_tracker.clone())
+    }
+
+    pub fn traffic(&self) -> Traffic {
+        self.traffic.clone()
+    }
+
     async fn process_inbound(
         &mut self,
         payload: SymmetricMessagePayload,
@@ -403,7 +663,10 @@ impl PeerConnection {
                 )?;
                 self.remote_conn
                     .outbound_packets
-                    .send((self.remote_conn.remote_addr, packet.data().into()))
+                    .send(
+                        PacketClass::Control,
+                        (self.remote_conn.remote_addr, packet.data().into()),
+                    )
                     .await
                     .map_err(|_| TransportError::ConnectionClosed(self.remote_addr()))?;
                 Ok(None)
@@ -438,6 +701,58 @@ impl PeerConnection {
                 Ok(None)
             }
             NoOp => Ok(None),
+            MtuProbe { .. } => {
+                // acknowledged right away so the probe doesn't time out waiting for receipts to
+                // be batched
+                let receipts = self.received_tracker.get_receipts();
+                self.noop(receipts).await?;
+                Ok(None)
+            }
+            QuicOffer {
+                port,
+                certificate,
+                token,
+                nonce,
+            } => {
+                let offer = symmetric_message::QuicOffer {
+                    port,
+                    certificate,
+                    token,
+                    nonce,
+                };
+                self.quic.accept_offer(self.remote_conn.remote_addr, offer);
+                Ok(None)
+            }
+            Rekey { public } => {
+                let Some(key) = self.key_rotation.accept(public) else {
+                    tracing::warn!(remote = %self.remote_conn.remote_addr, "remote rotated its key without an offer");
+                    return Ok(None);
+                };
+                tracing::debug!(remote = %self.remote_conn.remote_addr, "remote rotated its key");
+                self.remote_conn.outbound_symmetric_key = Aes128Gcm::new(&(*key).into());
+                Ok(None)
+            }
+            RekeyOffer { public } => {
+                self.key_rotation.offered(public);
+                Ok(None)
+            }
+            PathChallenge { token } => {
+                self.send_path_message(
+                    self.remote_conn.remote_addr,
+                    symmetric_message::PathMessage::Response(token),
+                )
+                .await?;
+                Ok(None)
+            }
+            PathResponse { token } => {
+                if let Some(addr) = self.remote_conn.migration.answered(&token) {
+                    let previous = std::mem::replace(&mut self.remote_conn.remote_addr, addr);
+                    tracing::info!(%previous, remote = %addr, "remote moved, connection migrated");
+                    self.remote_conn.migration.migrated(previous, addr).await;
+                    self.mtu_probing.restart();
+                }
+                Ok(None)
+            }
         }
     }
 
@@ -445,7 +760,9 @@ impl PeerConnection {
     async fn noop(&mut self, receipts: Vec<u32>) -> Result<()> {
         packet_sending(
             self.remote_conn.remote_addr,
-            &self.remote_conn.outbound_packets,
+            self.remote_conn
+                .outbound_packets
+                .sender(PacketClass::Control),
             self.remote_conn
                 .last_packet_id
                 .fetch_add(1, std::sync::atomic::Ordering::Release),
@@ -457,8 +774,122 @@ impl PeerConnection {
         .await
     }
 
+    /// Sends the probe of the size of the packets the path to the remote carries due, if any.
+    async fn probe_mtu(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let Some(size) = self.mtu_probing.due(now) else {
+            return Ok(());
+        };
+        let packet_id = self
+            .remote_conn
+            .last_packet_id
+            .fetch_add(1, std::sync::atomic::Ordering::Release);
+        tracing::trace!(remote = %self.remote_conn.remote_addr, size, "probing path MTU");
+        let packet =
+            SymmetricMessage::mtu_probe(packet_id, size, &self.remote_conn.outbound_symmetric_key)?;
+        self.remote_conn
+            .outbound_packets
+            .send(
+                PacketClass::Control,
+                (self.remote_conn.remote_addr, packet.prepared_send()),
+            )
+            .await
+            .map_err(|_| TransportError::ConnectionClosed(self.remote_addr()))?;
+        self.mtu_probing.sent(size, packet_id, now);
+        Ok(())
+    }
+
+    /// Offers the remote a key pair for its next rotation once it used the last one, and rotates
+    /// the key the remote encrypts its packets with when due.
+    async fn rekey(&mut self, now: Instant) -> Result<()> {
+        if let Some(public) = self.key_rotation.offer() {
+            self.send_control(symmetric_message::RekeyOffer(public)).await?;
+        }
+        if !self.key_rotation.is_due(now) {
+            return Ok(());
+        }
+        let Some((key, public)) = self
+            .key_rotation
+            .rotate(&mut self.remote_conn.inbound_symmetric_key, now)
+        else {
+            return Ok(());
+        };
+        self.remote_conn.inbound_symmetric_key_bytes = *key;
+        self.remote_conn
+            .migration
+            .set_key(&self.remote_conn.inbound_symmetric_key);
+        tracing::debug!(remote = %self.remote_conn.remote_addr, "rotating inbound key");
+        self.send_control(symmetric_message::Rekey(public)).await
+    }
+
+    async fn send_control(&mut self, msg: impl Into<SymmetricMessagePayload>) -> Result<()> {
+        packet_sending(
+            self.remote_conn.remote_addr,
+            self.remote_conn
+                .outbound_packets
+                .sender(PacketClass::Control),
+            self.remote_conn
+                .last_packet_id
+                .fetch_add(1, std::sync::atomic::Ordering::Release),
+            &self.remote_conn.outbound_symmetric_key,
+            vec![],
+            msg,
+            &self.remote_conn.sent_tracker,
+        )
+        .await
+    }
+
+    /// Sends a challenge of the new address of the remote, or the answer to one, to `addr`.
+    /// Not tracked to be resent, as it is for the path it is sent through.
+    async fn send_path_message(
+        &mut self,
+        addr: SocketAddr,
+        message: symmetric_message::PathMessage,
+    ) -> Result<()> {
+        let packet_id = self
+            .remote_conn
+            .last_packet_id
+            .fetch_add(1, std::sync::atomic::Ordering::Release);
+        let packet = SymmetricMessage::serialize_msg_to_packet_data(
+            packet_id,
+            message,
+            &self.remote_conn.outbound_symmetric_key,
+            vec![],
+        )?;
+        self.remote_conn
+            .outbound_packets
+            .send(PacketClass::Control, (addr, packet.prepared_send()))
+            .await
+            .map_err(|_| TransportError::ConnectionClosed(self.remote_addr()))
+    }
+
+    async fn outbound_quic_offer(&mut self, offer: symmetric_message::QuicOffer) -> Result<()> {
+        packet_sending(
+            self.remote_conn.remote_addr,
+            self.remote_conn
+                .outbound_packets
+                .sender(PacketClass::Control),
+            self.remote_conn
+                .last_packet_id
+                .fetch_add(1, std::sync::atomic::Ordering::Release),
+            &self.remote_conn.outbound_symmetric_key,
+            vec![],
+            offer,
+            &self.remote_conn.sent_tracker,
+        )
+        .await
+    }
+
     #[inline]
     pub(crate) async fn outbound_short_message(&mut self, data: SerializedMessage) -> Result<()> {
+        self.outbound_message(data, PacketClass::Message).await
+    }
+
+    async fn outbound_message(
+        &mut self,
+        data: SerializedMessage,
+        class: PacketClass,
+    ) -> Result<()> {
         let receipts = self.received_tracker.get_receipts();
         let packet_id = self
             .remote_conn
@@ -466,7 +897,7 @@ impl PeerConnection {
             .fetch_add(1, std::sync::atomic::Ordering::Release);
         packet_sending(
             self.remote_conn.remote_addr,
-            &self.remote_conn.outbound_packets,
+            self.remote_conn.outbound_packets.sender(class),
             packet_id,
             &self.remote_conn.outbound_symmetric_key,
             receipts,
@@ -477,17 +908,18 @@ impl PeerConnection {
         Ok(())
     }
 
-    async fn outbound_stream(&mut self, data: SerializedMessage) {
+    async fn outbound_stream(&mut self, data: SerializedMessage, class: PacketClass) {
         let stream_id = StreamId::next();
         let task = tokio::spawn(
             outbound_stream::send_stream(
                 stream_id,
                 self.remote_conn.last_packet_id.clone(),
-                self.remote_conn.outbound_packets.clone(),
+                self.remote_conn.outbound_packets.sender(class).clone(),
                 self.remote_conn.remote_addr,
                 data,
                 self.remote_conn.outbound_symmetric_key.clone(),
                 self.remote_conn.sent_tracker.clone(),
+                self.path_mtu.clone(),
             )
             .instrument(span!(tracing::Level::DEBUG, "outbound_stream")),
         );
@@ -640,6 +1072,7 @@ mod tests {
             message.clone(),
             cipher.clone(),
             sent_tracker,
+            PathMtu::default(),
         ))
         .map_err(|e| e.into());
 
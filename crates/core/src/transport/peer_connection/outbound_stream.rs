@@ -8,7 +8,7 @@ use tokio::sync::mpsc;
 
 use crate::{
     transport::{
-        packet_data,
+        mtu::PathMtu,
         sent_packet_tracker::SentPacketTracker,
         symmetric_message::{self},
         TransportError,
@@ -21,9 +21,9 @@ use super::StreamId;
 pub(crate) type SerializedStream = Vec<u8>;
 
 // TODO: measure the space overhead of SymmetricMessage::LongMessage since is likely less than 100
-/// The max payload we can send in a single fragment, this MUST be less than packet_data::MAX_DATA_SIZE
-/// since we need to account for the space overhead of SymmetricMessage::LongMessage metadata
-const MAX_DATA_SIZE: usize = packet_data::MAX_DATA_SIZE - 100;
+/// Space left in the packets for the metadata of SymmetricMessage::LongMessage, the max payload we
+/// can send in a single fragment is the max data size of the path MTU minus this
+pub(in crate::transport) const FRAGMENT_OVERHEAD: usize = 100;
 
 // TODO: unit test
 /// Handles sending a stream that is *not piped*. In the future this will be replaced by
@@ -37,25 +37,23 @@ pub(super) async fn send_stream(
     mut stream_to_send: SerializedStream,
     outbound_symmetric_key: Aes128Gcm,
     sent_packet_tracker: Arc<parking_lot::Mutex<SentPacketTracker<InstantTimeSrc>>>,
+    path_mtu: PathMtu,
 ) -> Result<(), TransportError> {
     tracing::debug!(stream_id = %stream_id.0, length = stream_to_send.len(), "sending stream");
     let total_length_bytes = stream_to_send.len() as u32;
-    let mut total_packets = stream_to_send.len() / MAX_DATA_SIZE;
-    total_packets += if stream_to_send.len() % MAX_DATA_SIZE == 0 {
-        0
-    } else {
-        1
-    };
-    let mut sent_so_far = 0;
     let mut next_fragment_number = 1; // Fragment numbers are 1-indexed
 
-    loop {
-        if sent_so_far == total_packets {
-            break;
+    // fragments are as large as the path MTU when sent, which may change meanwhile
+    while !stream_to_send.is_empty() {
+        let wait = sent_packet_tracker.lock().congestion_wait();
+        if let Some(wait) = wait {
+            tokio::time::sleep(wait).await;
+            continue;
         }
+        let max_data_size = path_mtu.max_data_size() - FRAGMENT_OVERHEAD;
         let rest = {
-            if stream_to_send.len() > MAX_DATA_SIZE {
-                let mut rest = stream_to_send.split_off(MAX_DATA_SIZE);
+            if stream_to_send.len() > max_data_size {
+                let mut rest = stream_to_send.split_off(max_data_size);
                 std::mem::swap(&mut stream_to_send, &mut rest);
                 rest
             } else {
@@ -79,7 +77,6 @@ pub(super) async fn send_stream(
         )
         .await?;
         next_fragment_number += 1;
-        sent_so_far += 1;
     }
 
     // tracing::trace!(stream_id = %stream_id.0, total_packets = %sent_so_far, "stream sent");
@@ -89,9 +86,9 @@ pub(super) async fn send_stream(
 
 #[cfg(test)]
 mod tests {
+    use crate::transport::packet_data::MAX_PACKET_SIZE;
     use aes_gcm::KeyInit;
     use std::net::Ipv4Addr;
-    use tests::packet_data::MAX_PACKET_SIZE;
 
     use super::{
         symmetric_message::{SymmetricMessage, SymmetricMessagePayload},
@@ -120,7 +117,8 @@ mod tests {
             remote_addr,
             message.clone(),
             cipher.clone(),
-            sent_tracker,
+            sent_tracker.clone(),
+            PathMtu::default(),
         ));
 
         let mut inbound_bytes = Vec::new();
@@ -134,6 +132,10 @@ mod tests {
                 panic!("Expected a StreamFragment, got {:?}", deserialized.payload);
             };
             inbound_bytes.extend_from_slice(payload.as_ref());
+            // acknowledged so the congestion window opens up
+            sent_tracker
+                .lock()
+                .report_received_receipts(&[deserialized.packet_id]);
         }
 
         let result = background_task.await?;
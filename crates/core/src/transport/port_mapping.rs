@@ -0,0 +1,319 @@
+//! Automatic forwarding of the port of the transport by the router of home networks, so peers
+//! behind it are directly reachable without configuring it manually.
+//!
+//! Mappings are requested with NAT-PMP from the default gateway of the host and, failing that,
+//! with UPnP from the internet gateway device discovered in the local network. They are leased
+//! for [`LEASE`] and renewed halfway through it.
+//!
+//! NAT-PMP requests are sent over UDP to port 5351 of the gateway: the version (0) and opcode
+//! (1 to map a UDP port) bytes, two reserved bytes, the internal and suggested external ports
+//! (u16) and the lifetime of the mapping in seconds (u32). Responses are the version, the opcode
+//! plus 128, the result code (u16, 0 on success), the seconds since the gateway started (u32),
+//! the internal and mapped external ports and the lifetime granted, integers being big endian.
+//! The external address is queried likewise with opcode 0, the response ending with it instead.
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use serde::Serialize;
+use tokio::net::UdpSocket;
+
+/// Duration mappings are requested for.
+const LEASE: Duration = Duration::from_secs(60 * 60);
+
+/// Delay before trying to map the port again after failing.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+const NAT_PMP_PORT: u16 = 5351;
+const NAT_PMP_VERSION: u8 = 0;
+const NAT_PMP_EXTERNAL_ADDRESS: u8 = 0;
+const NAT_PMP_MAP_UDP: u8 = 1;
+const NAT_PMP_RESPONSE: u8 = 128;
+
+/// Attempts of NAT-PMP requests, the timeout doubling from [`NAT_PMP_TIMEOUT`] after each.
+const NAT_PMP_ATTEMPTS: u32 = 4;
+const NAT_PMP_TIMEOUT: Duration = Duration::from_millis(250);
+
+const UPNP_DESCRIPTION: &str = "Freenet";
+
+/// State of the forwarding of the port of the transport.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case", tag = "state")]
+pub(crate) enum PortMappingStatus {
+    /// Not requested, as configured or because the node is a gateway.
+    #[default]
+    Disabled,
+    /// Being requested for the first time.
+    Pending,
+    Mapped {
+        protocol: MappingProtocol,
+        /// Address remotes can reach the transport at.
+        external_addr: SocketAddr,
+        /// Seconds the mapping was granted for from its last renewal.
+        lease_secs: u64,
+    },
+    /// The last attempt failed, it is retried periodically.
+    Failed { error: String },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum MappingProtocol {
+    NatPmp,
+    Upnp,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum PortMappingError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("no default gateway found")]
+    NoGateway,
+    #[error("no answer from the gateway")]
+    Timeout,
+    #[error("invalid response from the gateway")]
+    InvalidResponse,
+    #[error("NAT-PMP request refused with result code {0}")]
+    Refused(u16),
+    #[error("UPnP: {0}")]
+    Upnp(String),
+}
+
+/// A port mapped by the gateway.
+#[derive(Debug, PartialEq, Eq)]
+struct Mapping {
+    protocol: MappingProtocol,
+    external_addr: SocketAddr,
+    lease: Duration,
+}
+
+/// Keeps the UDP `port` of the transport forwarded, reporting every change of the state of the
+/// mapping.
+pub(crate) async fn maintain_port_mapping(port: u16, report: impl Fn(PortMappingStatus)) {
+    report(PortMappingStatus::Pending);
+    loop {
+        let renew_in = match map_port(port).await {
+            Ok(mapping) => {
+                tracing::info!(
+                    protocol = ?mapping.protocol,
+                    external_addr = %mapping.external_addr,
+                    lease = ?mapping.lease,
+                    "Port of the transport forwarded by the gateway"
+                );
+                let renew_in = mapping.lease / 2;
+                report(PortMappingStatus::Mapped {
+                    protocol: mapping.protocol,
+                    external_addr: mapping.external_addr,
+                    lease_secs: mapping.lease.as_secs(),
+                });
+                renew_in
+            }
+            Err(error) => {
+                tracing::warn!(%error, %port, "Failed forwarding the port of the transport");
+                report(PortMappingStatus::Failed {
+                    error: error.to_string(),
+                });
+                RETRY_INTERVAL
+            }
+        };
+        tokio::time::sleep(renew_in).await;
+    }
+}
+
+async fn map_port(port: u16) -> Result<Mapping, PortMappingError> {
+    let nat_pmp_error = match default_gateway() {
+        Ok(gateway) => match map_nat_pmp((gateway, NAT_PMP_PORT).into(), port, LEASE).await {
+            Ok(mapping) => return Ok(mapping),
+            Err(error) => error,
+        },
+        Err(error) => error,
+    };
+    tracing::debug!(error = %nat_pmp_error, "NAT-PMP port mapping failed, trying UPnP");
+    map_upnp(port, LEASE).await
+}
+
+fn default_gateway() -> Result<Ipv4Addr, PortMappingError> {
+    netdev::get_default_gateway()
+        .ok()
+        .and_then(|gateway| gateway.ipv4.first().copied())
+        .ok_or(PortMappingError::NoGateway)
+}
+
+async fn map_nat_pmp(
+    gateway: SocketAddr,
+    port: u16,
+    lease: Duration,
+) -> Result<Mapping, PortMappingError> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.connect(gateway).await?;
+
+    let response = nat_pmp_exchange(&socket, &[NAT_PMP_VERSION, NAT_PMP_EXTERNAL_ADDRESS]).await?;
+    let external_ip = parse_external_address(&response)?;
+
+    let lifetime = u32::try_from(lease.as_secs()).unwrap_or(u32::MAX);
+    let response = nat_pmp_exchange(&socket, &map_request(port, port, lifetime)).await?;
+    let (external_port, lifetime) = parse_map_response(&response, port)?;
+    Ok(Mapping {
+        protocol: MappingProtocol::NatPmp,
+        external_addr: (external_ip, external_port).into(),
+        lease: Duration::from_secs(lifetime.into()),
+    })
+}
+
+/// Sends the request until the gateway answers it, returns the response.
+async fn nat_pmp_exchange(socket: &UdpSocket, request: &[u8]) -> Result<Vec<u8>, PortMappingError> {
+    let mut buf = [0; 16];
+    let mut wait = NAT_PMP_TIMEOUT;
+    for _ in 0..NAT_PMP_ATTEMPTS {
+        socket.send(request).await?;
+        if let Ok(received) = tokio::time::timeout(wait, socket.recv(&mut buf)).await {
+            let size = received?;
+            if buf[..size].get(1) == Some(&(request[1] + NAT_PMP_RESPONSE)) {
+                return Ok(buf[..size].to_vec());
+            }
+        }
+        wait *= 2;
+    }
+    Err(PortMappingError::Timeout)
+}
+
+fn map_request(internal_port: u16, external_port: u16, lifetime: u32) -> [u8; 12] {
+    let mut request = [0; 12];
+    request[0] = NAT_PMP_VERSION;
+    request[1] = NAT_PMP_MAP_UDP;
+    request[4..6].copy_from_slice(&internal_port.to_be_bytes());
+    request[6..8].copy_from_slice(&external_port.to_be_bytes());
+    request[8..12].copy_from_slice(&lifetime.to_be_bytes());
+    request
+}
+
+/// Result code of the NAT-PMP response, checking it answers a request with `opcode`.
+fn result_code(response: &[u8], opcode: u8, size: usize) -> Result<(), PortMappingError> {
+    if response.len() != size
+        || response[0] != NAT_PMP_VERSION
+        || response[1] != opcode + NAT_PMP_RESPONSE
+    {
+        return Err(PortMappingError::InvalidResponse);
+    }
+    match u16::from_be_bytes([response[2], response[3]]) {
+        0 => Ok(()),
+        code => Err(PortMappingError::Refused(code)),
+    }
+}
+
+fn parse_external_address(response: &[u8]) -> Result<IpAddr, PortMappingError> {
+    result_code(response, NAT_PMP_EXTERNAL_ADDRESS, 12)?;
+    let octets: [u8; 4] = response[8..12].try_into().expect("length checked");
+    Ok(Ipv4Addr::from(octets).into())
+}
+
+/// External port and lifetime of the mapping of `internal_port` granted in the response.
+fn parse_map_response(response: &[u8], internal_port: u16) -> Result<(u16, u32), PortMappingError> {
+    result_code(response, NAT_PMP_MAP_UDP, 16)?;
+    if u16::from_be_bytes([response[8], response[9]]) != internal_port {
+        return Err(PortMappingError::InvalidResponse);
+    }
+    let external_port = u16::from_be_bytes([response[10], response[11]]);
+    let lifetime = u32::from_be_bytes(response[12..16].try_into().expect("length checked"));
+    Ok((external_port, lifetime))
+}
+
+async fn map_upnp(port: u16, lease: Duration) -> Result<Mapping, PortMappingError> {
+    use igd_next::{aio::tokio::search_gateway, PortMappingProtocol, SearchOptions};
+
+    let upnp_error = |error: &dyn std::fmt::Display| PortMappingError::Upnp(error.to_string());
+    let gateway = search_gateway(SearchOptions::default())
+        .await
+        .map_err(|error| upnp_error(&error))?;
+    let local_ip = local_ip_towards(gateway.addr).await?;
+    let lifetime = u32::try_from(lease.as_secs()).unwrap_or(u32::MAX);
+    gateway
+        .add_port(
+            PortMappingProtocol::UDP,
+            port,
+            (local_ip, port).into(),
+            lifetime,
+            UPNP_DESCRIPTION,
+        )
+        .await
+        .map_err(|error| upnp_error(&error))?;
+    let external_ip = gateway
+        .get_external_ip()
+        .await
+        .map_err(|error| upnp_error(&error))?;
+    Ok(Mapping {
+        protocol: MappingProtocol::Upnp,
+        external_addr: (external_ip, port).into(),
+        lease,
+    })
+}
+
+/// Address of the interface of the host routing to `remote`, which the gateway must forward to.
+async fn local_ip_towards(remote: SocketAddr) -> io::Result<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.connect(remote).await?;
+    Ok(socket.local_addr()?.ip())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers NAT-PMP requests as a gateway with external address `203.0.113.5` mapping every
+    /// port to the next one.
+    async fn fake_gateway() -> io::Result<SocketAddr> {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let addr = socket.local_addr()?;
+        tokio::spawn(async move {
+            let mut buf = [0; 12];
+            while let Ok((size, remote)) = socket.recv_from(&mut buf).await {
+                let mut response = vec![NAT_PMP_VERSION, buf[1] + NAT_PMP_RESPONSE, 0, 0];
+                response.extend_from_slice(&42u32.to_be_bytes());
+                match &buf[..size] {
+                    [_, NAT_PMP_EXTERNAL_ADDRESS] => response.extend_from_slice(&[203, 0, 113, 5]),
+                    [_, NAT_PMP_MAP_UDP, ..] if size == 12 => {
+                        let port = u16::from_be_bytes([buf[4], buf[5]]);
+                        response.extend_from_slice(&port.to_be_bytes());
+                        response.extend_from_slice(&(port + 1).to_be_bytes());
+                        response.extend_from_slice(&buf[8..12]);
+                    }
+                    _ => continue,
+                }
+                let _ = socket.send_to(&response, remote).await;
+            }
+        });
+        Ok(addr)
+    }
+
+    #[tokio::test]
+    async fn nat_pmp_mapping() -> Result<(), Box<dyn std::error::Error>> {
+        let gateway = fake_gateway().await?;
+        let mapping = map_nat_pmp(gateway, 31337, LEASE).await?;
+        assert_eq!(
+            mapping,
+            Mapping {
+                protocol: MappingProtocol::NatPmp,
+                external_addr: (Ipv4Addr::new(203, 0, 113, 5), 31338).into(),
+                lease: LEASE,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn nat_pmp_refused() {
+        let mut response = vec![NAT_PMP_VERSION, NAT_PMP_MAP_UDP + NAT_PMP_RESPONSE, 0, 2];
+        response.extend_from_slice(&[0; 12]);
+        assert!(matches!(
+            parse_map_response(&response, 31337),
+            Err(PortMappingError::Refused(2))
+        ));
+        assert!(matches!(
+            parse_map_response(&response[..12], 31337),
+            Err(PortMappingError::InvalidResponse)
+        ));
+    }
+}
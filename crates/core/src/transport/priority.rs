@@ -0,0 +1,166 @@
+//! Classes of the outbound packets, queued apart and sent by a weighted round-robin scheduler
+//! so bulk transfers of states can't delay the keep-alives and acknowledgements of connections
+//! until they time out, nor starve the messages of operations. Packets of background operations
+//! come last, so they don't hold up those of the operations users are waiting on.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use tokio::sync::mpsc;
+
+pub(super) type OutboundPacket = (SocketAddr, Arc<[u8]>);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum PacketClass {
+    /// Handshakes, keep-alives, receipts and other packets keeping connections up.
+    Control,
+    /// Messages of operations, and the packets resent.
+    Message,
+    /// Fragments of the streams of large messages, such as states of contracts.
+    Bulk,
+    /// Messages and streams of background operations.
+    Background,
+}
+
+impl PacketClass {
+    const ALL: [Self; 4] = [Self::Control, Self::Message, Self::Bulk, Self::Background];
+
+    /// Packets sent of the class per round of the scheduler when others are queued.
+    fn weight(self) -> u32 {
+        match self {
+            Self::Control => 16,
+            Self::Message => 4,
+            Self::Bulk => 2,
+            Self::Background => 1,
+        }
+    }
+}
+
+/// Senders of the queues of each class of outbound packets.
+#[derive(Clone)]
+pub(super) struct OutboundQueues([mpsc::Sender<OutboundPacket>; 4]);
+
+impl OutboundQueues {
+    /// Queues of every class sharing the given sender, unprioritized.
+    #[cfg(test)]
+    pub fn single(sender: mpsc::Sender<OutboundPacket>) -> Self {
+        Self([sender.clone(), sender.clone(), sender.clone(), sender])
+    }
+
+    pub fn sender(&self, class: PacketClass) -> &mpsc::Sender<OutboundPacket> {
+        &self.0[class as usize]
+    }
+
+    pub async fn send(
+        &self,
+        class: PacketClass,
+        packet: OutboundPacket,
+    ) -> Result<(), mpsc::error::SendError<OutboundPacket>> {
+        self.sender(class).send(packet).await
+    }
+}
+
+/// Receiving end of the queues, taking packets from each in turn up to their weight.
+pub(super) struct PriorityScheduler {
+    queues: [mpsc::Receiver<OutboundPacket>; 4],
+    /// Packets left to send of each class in the current round.
+    credits: [u32; 4],
+}
+
+/// Queues of `capacity` packets of each class.
+pub(super) fn channels(capacity: usize) -> (OutboundQueues, PriorityScheduler) {
+    let (control, control_recv) = mpsc::channel(capacity);
+    let (message, message_recv) = mpsc::channel(capacity);
+    let (bulk, bulk_recv) = mpsc::channel(capacity);
+    let (background, background_recv) = mpsc::channel(capacity);
+    (
+        OutboundQueues([control, message, bulk, background]),
+        PriorityScheduler {
+            queues: [control_recv, message_recv, bulk_recv, background_recv],
+            credits: PacketClass::ALL.map(PacketClass::weight),
+        },
+    )
+}
+
+impl PriorityScheduler {
+    /// The next packet to send, `None` once every sender is dropped.
+    pub async fn recv(&mut self) -> Option<(PacketClass, OutboundPacket)> {
+        // the current round, then a new one if the classes queued ran out of credits
+        for _ in 0..2 {
+            for class in PacketClass::ALL {
+                let credits = &mut self.credits[class as usize];
+                if *credits == 0 {
+                    continue;
+                }
+                if let Ok(packet) = self.queues[class as usize].try_recv() {
+                    *credits -= 1;
+                    return Some((class, packet));
+                }
+            }
+            self.credits = PacketClass::ALL.map(PacketClass::weight);
+        }
+        let [control, message, bulk, background] = &mut self.queues;
+        tokio::select! {
+            biased;
+            Some(packet) = control.recv() => Some((PacketClass::Control, packet)),
+            Some(packet) = message.recv() => Some((PacketClass::Message, packet)),
+            Some(packet) = bulk.recv() => Some((PacketClass::Bulk, packet)),
+            Some(packet) = background.recv() => Some((PacketClass::Background, packet)),
+            else => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn weighted_scheduling() -> Result<(), Box<dyn std::error::Error>> {
+        let (queues, mut scheduler) = channels(100);
+        let remote: SocketAddr = ([127, 0, 0, 1], 31337).into();
+        for _ in 0..50 {
+            queues.send(PacketClass::Bulk, (remote, [0].into())).await?;
+        }
+        for _ in 0..10 {
+            queues
+                .send(PacketClass::Message, (remote, [0].into()))
+                .await?;
+        }
+        for _ in 0..5 {
+            queues
+                .send(PacketClass::Background, (remote, [0].into()))
+                .await?;
+        }
+        queues
+            .send(PacketClass::Control, (remote, [0].into()))
+            .await?;
+
+        let mut sent = vec![];
+        for _ in 0..17 {
+            sent.push(scheduler.recv().await.ok_or("closed")?.0);
+        }
+        assert_eq!(sent[0], PacketClass::Control);
+        assert_eq!(
+            sent.iter().filter(|c| **c == PacketClass::Message).count(),
+            10
+        );
+        // bulk packets keep flowing while others are queued
+        assert!(sent[..6].contains(&PacketClass::Bulk));
+        // background packets come last, without starving
+        assert_eq!(sent[7], PacketClass::Background);
+        assert_eq!(
+            sent.iter()
+                .filter(|c| **c == PacketClass::Background)
+                .count(),
+            2
+        );
+
+        drop(queues);
+        let mut rest = 0;
+        while scheduler.recv().await.is_some() {
+            rest += 1;
+        }
+        assert_eq!(rest, 49);
+        Ok(())
+    }
+}
@@ -0,0 +1,545 @@
+//! QUIC links between peers, carrying the messages of a [`PeerConnection`] once both peers
+//! agree on it, for the stream multiplexing, encryption and loss recovery of QUIC.
+//!
+//! Links are negotiated per peer over the connection established by the UDP transport, which
+//! already authenticates the peers: when the connection starts, each peer with a QUIC endpoint
+//! offers the port it listens on, its self-signed certificate and a secret token. The peer with
+//! the highest nonce dials the endpoint of the other, pinning its certificate and presenting
+//! its token on the first stream of the link, every message being then sent on a stream of its
+//! own. Peers built without the `quic` feature ignore offers, and messages fall back to UDP
+//! whenever there is no link, e.g. when the endpoint of a peer behind a NAT can't be reached,
+//! so the UDP connection is kept alive for as long as the link.
+//!
+//! [`PeerConnection`]: super::PeerConnection
+
+#[cfg(feature = "quic")]
+pub(crate) use self::enabled::QuicEndpoint;
+#[cfg(feature = "quic")]
+pub(super) use self::enabled::QuicLink;
+
+#[cfg(not(feature = "quic"))]
+pub(crate) use self::disabled::QuicEndpoint;
+#[cfg(not(feature = "quic"))]
+pub(super) use self::disabled::QuicLink;
+
+#[cfg(feature = "quic")]
+mod enabled {
+    use std::{cmp::Ordering, collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+
+    use parking_lot::Mutex;
+    use quinn::{
+        crypto::rustls::{QuicClientConfig, QuicServerConfig},
+        ClientConfig, Connection, Endpoint, EndpointConfig, ServerConfig, TokioRuntime,
+        TransportConfig, VarInt,
+    };
+    use rustls::{
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        crypto::CryptoProvider,
+        pki_types::{CertificateDer, PrivatePkcs8KeyDer, ServerName, UnixTime},
+        CertificateError, DigitallySignedStruct, SignatureScheme,
+    };
+    use tokio::sync::{mpsc, oneshot};
+
+    use crate::transport::{dual_stack, symmetric_message::QuicOffer, TransportError};
+
+    /// Name certificates are issued for, endpoints are identified by their certificate instead.
+    const SERVER_NAME: &str = "freenet";
+
+    /// Max time to establish a link, including the token of the dialing peer.
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Keeps the NAT mappings of links open.
+    const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(10);
+
+    /// Max size of a message received over a link.
+    const MAX_MESSAGE_SIZE: usize = 1 << 28;
+
+    /// Links waiting to be dialed, by the token offered to the remote peer.
+    type PendingLinks = HashMap<[u8; 32], oneshot::Sender<Connection>>;
+
+    /// QUIC endpoint of this peer, shared by all its links.
+    #[derive(Clone)]
+    pub(crate) struct QuicEndpoint {
+        endpoint: Endpoint,
+        port: u16,
+        certificate: CertificateDer<'static>,
+        provider: Arc<CryptoProvider>,
+        pending: Arc<Mutex<PendingLinks>>,
+    }
+
+    impl QuicEndpoint {
+        pub fn bind(addr: SocketAddr) -> Result<Self, TransportError> {
+            let provider = Arc::new(rustls::crypto::ring::default_provider());
+            let rcgen::CertifiedKey { cert, key_pair } =
+                rcgen::generate_simple_self_signed(vec![SERVER_NAME.into()]).map_err(other)?;
+            let certificate = cert.der().clone();
+            let key = PrivatePkcs8KeyDer::from(key_pair.serialize_der());
+            let tls = rustls::ServerConfig::builder_with_provider(provider.clone())
+                .with_protocol_versions(&[&rustls::version::TLS13])
+                .map_err(other)?
+                .with_no_client_auth()
+                .with_single_cert(vec![certificate.clone()], key.into())
+                .map_err(other)?;
+            let config = ServerConfig::with_crypto(Arc::new(
+                QuicServerConfig::try_from(tls).map_err(other)?,
+            ));
+            let endpoint = Endpoint::new(
+                EndpointConfig::default(),
+                Some(config),
+                dual_stack::bind_std_udp(addr)?,
+                Arc::new(TokioRuntime),
+            )?;
+            let port = endpoint.local_addr()?.port();
+            let pending = Arc::new(Mutex::new(HashMap::new()));
+            tokio::spawn(accept(endpoint.clone(), pending.clone()));
+            tracing::info!(%port, "Listening for QUIC links");
+            Ok(Self {
+                endpoint,
+                port,
+                certificate,
+                provider,
+                pending,
+            })
+        }
+
+        /// Waits for the remote peer given `token` to dial this endpoint.
+        fn expect(&self, token: [u8; 32]) -> oneshot::Receiver<Connection> {
+            let (sender, receiver) = oneshot::channel();
+            self.pending.lock().insert(token, sender);
+            receiver
+        }
+
+        fn forget(&self, token: &[u8; 32]) {
+            self.pending.lock().remove(token);
+        }
+
+        /// Dials the endpoint offered by the remote peer at `addr`.
+        fn dial(&self, addr: SocketAddr, offer: QuicOffer) -> oneshot::Receiver<Connection> {
+            let (sender, receiver) = oneshot::channel();
+            let endpoint = self.endpoint.clone();
+            let provider = self.provider.clone();
+            tokio::spawn(async move {
+                match tokio::time::timeout(
+                    HANDSHAKE_TIMEOUT,
+                    connect(endpoint, provider, addr, offer),
+                )
+                .await
+                {
+                    Ok(Ok(connection)) => {
+                        let _ = sender.send(connection);
+                    }
+                    Ok(Err(error)) => {
+                        tracing::debug!(%error, %addr, "Failed to dial QUIC endpoint");
+                    }
+                    Err(_) => tracing::debug!(%addr, "Timed out dialing QUIC endpoint"),
+                }
+            });
+            receiver
+        }
+    }
+
+    fn other(error: impl std::error::Error + Send + Sync + 'static) -> TransportError {
+        TransportError::Other(error.into())
+    }
+
+    async fn accept(endpoint: Endpoint, pending: Arc<Mutex<PendingLinks>>) {
+        while let Some(incoming) = endpoint.accept().await {
+            let pending = pending.clone();
+            tokio::spawn(async move {
+                let handshake = async {
+                    let connection = incoming.await.map_err(other)?;
+                    let mut stream = connection.accept_uni().await.map_err(other)?;
+                    let token = stream.read_to_end(32).await.map_err(other)?;
+                    Ok::<_, TransportError>((connection, token))
+                };
+                let (connection, token) =
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+                        Ok(Ok(accepted)) => accepted,
+                        Ok(Err(error)) => {
+                            tracing::debug!(%error, "Failed to accept QUIC link");
+                            return;
+                        }
+                        Err(_) => {
+                            tracing::debug!("Timed out accepting QUIC link");
+                            return;
+                        }
+                    };
+                let link = <[u8; 32]>::try_from(token)
+                    .ok()
+                    .and_then(|token| pending.lock().remove(&token));
+                match link {
+                    Some(link) => {
+                        let _ = link.send(connection);
+                    }
+                    None => {
+                        tracing::debug!(remote = %connection.remote_address(), "QUIC link with unknown token");
+                        connection.close(VarInt::from_u32(1), b"unknown token");
+                    }
+                }
+            });
+        }
+    }
+
+    async fn connect(
+        endpoint: Endpoint,
+        provider: Arc<CryptoProvider>,
+        addr: SocketAddr,
+        offer: QuicOffer,
+    ) -> Result<Connection, TransportError> {
+        let verifier = PinnedCertificate {
+            certificate: CertificateDer::from(offer.certificate),
+            provider: provider.clone(),
+        };
+        let tls = rustls::ClientConfig::builder_with_provider(provider)
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .map_err(other)?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth();
+        let mut config =
+            ClientConfig::new(Arc::new(QuicClientConfig::try_from(tls).map_err(other)?));
+        let mut transport = TransportConfig::default();
+        transport.keep_alive_interval(Some(KEEP_ALIVE_INTERVAL));
+        config.transport_config(Arc::new(transport));
+        let connection = endpoint
+            .connect_with(config, addr, SERVER_NAME)
+            .map_err(other)?
+            .await
+            .map_err(other)?;
+        let mut stream = connection.open_uni().await.map_err(other)?;
+        stream.write_all(&offer.token).await.map_err(other)?;
+        stream.finish().map_err(other)?;
+        Ok(connection)
+    }
+
+    /// Only trusts the certificate offered by the remote peer.
+    #[derive(Debug)]
+    struct PinnedCertificate {
+        certificate: CertificateDer<'static>,
+        provider: Arc<CryptoProvider>,
+    }
+
+    impl ServerCertVerifier for PinnedCertificate {
+        fn verify_server_cert(
+            &self,
+            end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            if end_entity.as_ref() == self.certificate.as_ref() {
+                Ok(ServerCertVerified::assertion())
+            } else {
+                Err(rustls::Error::InvalidCertificate(
+                    CertificateError::ApplicationVerificationFailure,
+                ))
+            }
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            rustls::crypto::verify_tls12_signature(
+                message,
+                cert,
+                dss,
+                &self.provider.signature_verification_algorithms,
+            )
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            rustls::crypto::verify_tls13_signature(
+                message,
+                cert,
+                dss,
+                &self.provider.signature_verification_algorithms,
+            )
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.provider
+                .signature_verification_algorithms
+                .supported_schemes()
+        }
+    }
+
+    /// QUIC link of a connection with a remote peer.
+    pub(in crate::transport) struct QuicLink {
+        endpoint: Option<QuicEndpoint>,
+        state: LinkState,
+    }
+
+    enum LinkState {
+        /// No link offered yet.
+        Idle,
+        /// Waiting for the offer of the remote peer, or for it to dial this endpoint.
+        Offered {
+            token: [u8; 32],
+            nonce: u64,
+            accepted: oneshot::Receiver<Connection>,
+        },
+        Dialing(oneshot::Receiver<Connection>),
+        Connected {
+            connection: Connection,
+            messages: mpsc::Receiver<Vec<u8>>,
+        },
+        /// The link failed or can't be established, messages go over UDP.
+        Closed,
+    }
+
+    impl QuicLink {
+        pub fn new(endpoint: Option<QuicEndpoint>) -> Self {
+            Self {
+                endpoint,
+                state: LinkState::Idle,
+            }
+        }
+
+        /// The link to offer to the remote peer, only once.
+        pub fn offer(&mut self) -> Option<QuicOffer> {
+            let endpoint = self.endpoint.as_ref()?;
+            if !matches!(self.state, LinkState::Idle) {
+                return None;
+            }
+            let token = rand::random();
+            let nonce = rand::random();
+            self.state = LinkState::Offered {
+                token,
+                nonce,
+                accepted: endpoint.expect(token),
+            };
+            Some(QuicOffer {
+                port: endpoint.port,
+                certificate: endpoint.certificate.to_vec(),
+                token,
+                nonce,
+            })
+        }
+
+        /// Handles the link offered by the remote peer at `remote_addr`, dialing it when this
+        /// peer has the highest nonce.
+        pub fn accept_offer(&mut self, remote_addr: SocketAddr, offer: QuicOffer) {
+            let Some(endpoint) = &self.endpoint else {
+                return;
+            };
+            let LinkState::Offered { token, nonce, .. } = &self.state else {
+                return;
+            };
+            let token = *token;
+            match nonce.cmp(&offer.nonce) {
+                Ordering::Greater => {
+                    endpoint.forget(&token);
+                    let addr = SocketAddr::new(remote_addr.ip(), offer.port);
+                    tracing::debug!(%addr, "Dialing QUIC endpoint");
+                    self.state = LinkState::Dialing(endpoint.dial(addr, offer));
+                }
+                Ordering::Less => {}
+                Ordering::Equal => {
+                    endpoint.forget(&token);
+                    self.state = LinkState::Closed;
+                }
+            }
+        }
+
+        /// Sends `data` over the link, handing it back when there is no link to send it over.
+        pub async fn send(&mut self, data: Vec<u8>) -> Result<(), Vec<u8>> {
+            let LinkState::Connected { connection, .. } = &self.state else {
+                return Err(data);
+            };
+            let sent = async {
+                let mut stream = connection.open_uni().await.map_err(other)?;
+                stream.write_all(&data).await.map_err(other)?;
+                stream.finish().map_err(other)?;
+                Ok::<_, TransportError>(())
+            };
+            match sent.await {
+                Ok(()) => Ok(()),
+                Err(error) => {
+                    tracing::debug!(%error, remote = %connection.remote_address(), "QUIC link failed, falling back to UDP");
+                    self.close();
+                    Err(data)
+                }
+            }
+        }
+
+        /// The next message received over the link, pending while there is none. Cancel safe.
+        pub async fn recv(&mut self) -> Vec<u8> {
+            loop {
+                match &mut self.state {
+                    LinkState::Offered {
+                        accepted: pending, ..
+                    }
+                    | LinkState::Dialing(pending) => match pending.await {
+                        Ok(connection) => self.connected(connection),
+                        Err(_) => self.state = LinkState::Closed,
+                    },
+                    LinkState::Connected { messages, .. } => match messages.recv().await {
+                        Some(msg) => return msg,
+                        None => {
+                            tracing::debug!("QUIC link closed, falling back to UDP");
+                            self.close();
+                        }
+                    },
+                    LinkState::Idle | LinkState::Closed => std::future::pending().await,
+                }
+            }
+        }
+
+        fn connected(&mut self, connection: Connection) {
+            tracing::debug!(remote = %connection.remote_address(), "QUIC link established");
+            let (sender, messages) = mpsc::channel(100);
+            tokio::spawn(receive(connection.clone(), sender));
+            self.state = LinkState::Connected {
+                connection,
+                messages,
+            };
+        }
+
+        fn close(&mut self) {
+            if let LinkState::Connected { connection, .. } =
+                std::mem::replace(&mut self.state, LinkState::Closed)
+            {
+                connection.close(VarInt::from_u32(0), b"closed");
+            }
+        }
+    }
+
+    impl Drop for QuicLink {
+        fn drop(&mut self) {
+            if let (Some(endpoint), LinkState::Offered { token, .. }) =
+                (&self.endpoint, &self.state)
+            {
+                endpoint.forget(token);
+            }
+            self.close();
+        }
+    }
+
+    /// Reads the messages of the streams opened by the remote peer until the link closes.
+    async fn receive(connection: Connection, messages: mpsc::Sender<Vec<u8>>) {
+        while let Ok(mut stream) = connection.accept_uni().await {
+            let messages = messages.clone();
+            tokio::spawn(async move {
+                match stream.read_to_end(MAX_MESSAGE_SIZE).await {
+                    Ok(msg) => {
+                        let _ = messages.send(msg).await;
+                    }
+                    Err(error) => tracing::debug!(%error, "Failed to read QUIC stream"),
+                }
+            });
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::net::Ipv4Addr;
+
+        use super::*;
+
+        fn localhost() -> SocketAddr {
+            (Ipv4Addr::LOCALHOST, 0).into()
+        }
+
+        /// Drives both links until established.
+        async fn establish(a: &mut QuicLink, b: &mut QuicLink) {
+            let wait = Duration::from_millis(500);
+            let _ = tokio::join!(
+                tokio::time::timeout(wait, a.recv()),
+                tokio::time::timeout(wait, b.recv())
+            );
+        }
+
+        #[tokio::test]
+        async fn link_messages() -> Result<(), Box<dyn std::error::Error>> {
+            let mut a = QuicLink::new(Some(QuicEndpoint::bind(localhost())?));
+            let mut b = QuicLink::new(Some(QuicEndpoint::bind(localhost())?));
+            let offer_a = a.offer().ok_or("missing offer")?;
+            let offer_b = b.offer().ok_or("missing offer")?;
+            assert!(a.offer().is_none());
+            a.accept_offer(localhost(), offer_b);
+            b.accept_offer(localhost(), offer_a);
+            establish(&mut a, &mut b).await;
+
+            a.send(b"ping".to_vec()).await.map_err(|_| "not linked")?;
+            assert_eq!(b.recv().await, b"ping");
+            b.send(b"pong".to_vec()).await.map_err(|_| "not linked")?;
+            assert_eq!(a.recv().await, b"pong");
+
+            drop(b);
+            establish(&mut a, &mut QuicLink::new(None)).await;
+            assert!(a.send(b"ping".to_vec()).await.is_err());
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn rejected_links() -> Result<(), Box<dyn std::error::Error>> {
+            let mut a = QuicLink::new(Some(QuicEndpoint::bind(localhost())?));
+            let mut b = QuicLink::new(Some(QuicEndpoint::bind(localhost())?));
+            let offer_a = a.offer().ok_or("missing offer")?;
+            let mut offer_b = b.offer().ok_or("missing offer")?;
+            // the certificate offered doesn't match the one of the endpoint
+            offer_b.certificate = offer_a.certificate.clone();
+            offer_b.nonce = offer_a.nonce.wrapping_sub(1);
+            a.accept_offer(localhost(), offer_b);
+            b.accept_offer(localhost(), offer_a);
+            establish(&mut a, &mut b).await;
+            assert!(a.send(b"ping".to_vec()).await.is_err());
+
+            let mut unlinked = QuicLink::new(None);
+            assert!(unlinked.offer().is_none());
+            assert!(unlinked.send(b"ping".to_vec()).await.is_err());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(feature = "quic"))]
+mod disabled {
+    use std::net::SocketAddr;
+
+    use crate::transport::{symmetric_message::QuicOffer, TransportError};
+
+    /// QUIC endpoint of this peer, which can't be bound when built without the `quic` feature.
+    #[derive(Clone)]
+    pub(crate) enum QuicEndpoint {}
+
+    impl QuicEndpoint {
+        pub fn bind(_addr: SocketAddr) -> Result<Self, TransportError> {
+            Err(TransportError::Other(anyhow::anyhow!(
+                "built without the `quic` feature"
+            )))
+        }
+    }
+
+    /// QUIC link of a connection with a remote peer, never established.
+    pub(in crate::transport) struct QuicLink;
+
+    impl QuicLink {
+        pub fn new(_endpoint: Option<QuicEndpoint>) -> Self {
+            Self
+        }
+
+        pub fn offer(&mut self) -> Option<QuicOffer> {
+            None
+        }
+
+        pub fn accept_offer(&mut self, _remote_addr: SocketAddr, _offer: QuicOffer) {}
+
+        pub async fn send(&mut self, data: Vec<u8>) -> Result<(), Vec<u8>> {
+            Err(data)
+        }
+
+        pub async fn recv(&mut self) -> Vec<u8> {
+            std::future::pending().await
+        }
+    }
+}
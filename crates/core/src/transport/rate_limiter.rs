@@ -1,11 +1,13 @@
-use tokio::sync::mpsc;
-
 use std::collections::VecDeque;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use super::Socket;
+use super::{
+    priority::PriorityScheduler,
+    relay::{self, RelayRoutes},
+    Socket,
+};
 use crate::util::time_source::{InstantTimeSrc, TimeSource};
 
 /// Keeps track of the bandwidth used in the last window_size. Recommend a `window_size` of
@@ -14,15 +16,12 @@ pub(super) struct PacketRateLimiter<T: TimeSource> {
     packets: VecDeque<(usize, Instant)>,
     window_size: Duration,
     current_bandwidth: usize,
-    outbound_packets: mpsc::Receiver<(SocketAddr, Arc<[u8]>)>,
+    outbound_packets: PriorityScheduler,
     time_source: T,
 }
 
 impl PacketRateLimiter<InstantTimeSrc> {
-    pub(super) fn new(
-        window_size: Duration,
-        outbound_packets: mpsc::Receiver<(SocketAddr, Arc<[u8]>)>,
-    ) -> Self {
+    pub(super) fn new(window_size: Duration, outbound_packets: PriorityScheduler) -> Self {
         PacketRateLimiter {
             packets: VecDeque::new(),
             window_size,
@@ -38,12 +37,17 @@ impl<T: TimeSource> PacketRateLimiter<T> {
         mut self,
         bandwidth_limit: Option<usize>,
         socket: Arc<S>,
+        relay_routes: RelayRoutes,
     ) {
         tracing::info!(bandwidth_limit, "Rate limiter task started");
-        while let Some((socket_addr, packet)) = self.outbound_packets.recv().await {
+        while let Some((_, (socket_addr, packet))) = self.outbound_packets.recv().await {
             // tracing::trace!(%socket_addr, packet_len = %packet.len(), "Sending outbound packet");
+            let socket = RelayedSocket {
+                socket: &*socket,
+                routes: &relay_routes,
+            };
             if let Some(bandwidth_limit) = bandwidth_limit {
-                self.rate_limiting(bandwidth_limit, &*socket, packet, socket_addr)
+                self.rate_limiting(bandwidth_limit, &socket, packet, socket_addr)
                     .await;
             } else if let Err(error) = socket.send_to(&packet, socket_addr).await {
                 tracing::error!(%socket_addr, "Error sending packet: {}", error);
@@ -57,7 +61,7 @@ impl<T: TimeSource> PacketRateLimiter<T> {
     async fn rate_limiting<S: Socket>(
         &mut self,
         bandwidth_limit: usize,
-        socket: &S,
+        socket: &RelayedSocket<'_, S>,
         packet: Arc<[u8]>,
         socket_addr: SocketAddr,
     ) {
@@ -128,9 +132,31 @@ impl<T: TimeSource> PacketRateLimiter<T> {
     }
 }
 
+/// Socket sending the packets to the remotes relayed to through their relays.
+struct RelayedSocket<'a, S> {
+    socket: &'a S,
+    routes: &'a RelayRoutes,
+}
+
+impl<S: Socket> RelayedSocket<'_, S> {
+    async fn send_to(&self, packet: &[u8], remote: SocketAddr) -> std::io::Result<()> {
+        let relays = self.routes.relays(&remote);
+        if relays.is_empty() {
+            self.socket.send_to(packet, remote).await?;
+            return Ok(());
+        }
+        let forward = relay::forward_packet(remote, packet);
+        for relay in relays {
+            self.socket.send_to(&forward, relay).await?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::transport::priority;
     use crate::util::time_source::MockTimeSource;
 
     fn mock_tracker(window_size: Duration) -> PacketRateLimiter<MockTimeSource> {
@@ -138,7 +164,7 @@ mod tests {
             packets: VecDeque::new(),
             window_size,
             current_bandwidth: 0,
-            outbound_packets: mpsc::channel(1).1,
+            outbound_packets: priority::channels(1).1,
             time_source: MockTimeSource::new(Instant::now()),
         }
     }
@@ -153,7 +179,7 @@ mod tests {
 
     #[test]
     fn test_adding_packets() {
-        let mut tracker = PacketRateLimiter::new(Duration::from_secs(1), mpsc::channel(1).1);
+        let mut tracker = PacketRateLimiter::new(Duration::from_secs(1), priority::channels(1).1);
         verify_bandwidth_match(&tracker);
         tracker.add_packet(1500);
         verify_bandwidth_match(&tracker);
@@ -162,7 +188,7 @@ mod tests {
 
     #[test]
     fn test_bandwidth_calculation() {
-        let mut tracker = PacketRateLimiter::new(Duration::from_secs(1), mpsc::channel(1).1);
+        let mut tracker = PacketRateLimiter::new(Duration::from_secs(1), priority::channels(1).1);
         tracker.add_packet(1500);
         tracker.add_packet(2500);
         verify_bandwidth_match(&tracker);
@@ -199,7 +225,8 @@ mod tests {
 
     #[test]
     fn test_immediate_send() {
-        let mut tracker = PacketRateLimiter::new(Duration::from_millis(10), mpsc::channel(1).1);
+        let mut tracker =
+            PacketRateLimiter::new(Duration::from_millis(10), priority::channels(1).1);
         tracker.add_packet(3000);
         assert_eq!(tracker.can_send_packet(10000, 2000), None);
     }
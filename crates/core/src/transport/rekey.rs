@@ -0,0 +1,197 @@
+//! Rotation of the symmetric keys of established connections, so a key compromised later on
+//! only exposes the traffic since the last rotation.
+//!
+//! Each peer rotates the key the remote encrypts the packets sent to it with, once the key has
+//! been used for [`RekeyLimits::interval`] or to receive [`RekeyLimits::bytes`]. Keys are agreed
+//! upon through an ephemeral X25519 exchange: each peer offers the remote an ephemeral public key
+//! beforehand, and the peer rotating draws its own ephemeral key pair, sends the public key over
+//! and derives the new key from the shared secret with HKDF, as the remote does once it gets the
+//! public key. The ephemeral secrets are dropped, and zeroized, as soon as the key is derived, so
+//! the keys can't be recovered from the recorded traffic even with the secret key of the
+//! transport. The peer rotating keeps the previous key to decrypt the packets the remote sends
+//! until it switches. Keys aren't rotated again until the remote is seen using the last one, so
+//! at most two keys are accepted at any time.
+
+use std::time::{Duration, Instant};
+
+use aes_gcm::{Aes128Gcm, KeyInit};
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey, SharedSecret};
+use zeroize::Zeroizing;
+
+use super::packet_data::{PacketData, SymmetricAES, UnknownEncryption};
+
+/// Binds the keys derived to their use.
+const KEY_INFO: &[u8] = b"freenet transport rekey";
+
+/// When the keys of connections are rotated.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RekeyLimits {
+    pub interval: Duration,
+    pub bytes: u64,
+}
+
+impl Default for RekeyLimits {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60 * 60),
+            bytes: 1 << 30,
+        }
+    }
+}
+
+/// State of the rotation of the keys of a connection, the current keys being held by the
+/// connection.
+pub(super) struct KeyRotation {
+    limits: RekeyLimits,
+    previous: Option<Aes128Gcm>,
+    since: Instant,
+    bytes: u64,
+    /// Whether the remote was seen using the current key.
+    confirmed: bool,
+    /// Secret of the public key offered to the remote for its next rotation, `None` once used.
+    offer: Option<EphemeralSecret>,
+    /// Public key offered by the remote for the next rotation of this peer.
+    remote_offer: Option<PublicKey>,
+}
+
+impl KeyRotation {
+    pub fn new(limits: RekeyLimits, now: Instant) -> Self {
+        Self {
+            limits,
+            previous: None,
+            since: now,
+            bytes: 0,
+            // agreed upon when establishing the connection
+            confirmed: true,
+            offer: None,
+            remote_offer: None,
+        }
+    }
+
+    /// Reports a packet of `size` bytes decrypted with the current key.
+    pub fn received(&mut self, size: usize) {
+        self.bytes += size as u64;
+        self.confirmed = true;
+    }
+
+    /// Decrypts a packet with the previous key, for those the remote sent before switching.
+    pub fn decrypt_previous(
+        &self,
+        packet: &PacketData<UnknownEncryption>,
+    ) -> Option<PacketData<SymmetricAES>> {
+        packet.try_decrypt_sym(self.previous.as_ref()?).ok()
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.confirmed
+            && self.remote_offer.is_some()
+            && (now.saturating_duration_since(self.since) >= self.limits.interval
+                || self.bytes >= self.limits.bytes)
+    }
+
+    /// Draws the key pair to offer the remote for its next rotation, returning the public key
+    /// to be sent to it. `None` while the last one offered wasn't used.
+    pub fn offer(&mut self) -> Option<[u8; 32]> {
+        if self.offer.is_some() {
+            return None;
+        }
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let public = PublicKey::from(&secret);
+        self.offer = Some(secret);
+        Some(public.to_bytes())
+    }
+
+    /// Keeps the public key offered by the remote for the next rotation.
+    pub fn offered(&mut self, public: [u8; 32]) {
+        self.remote_offer = Some(PublicKey::from(public));
+    }
+
+    /// Derives the key replacing `current` from the public key offered by the remote, returning
+    /// the key and the public key to be sent to the remote. `None` if the remote offered none.
+    pub fn rotate(
+        &mut self,
+        current: &mut Aes128Gcm,
+        now: Instant,
+    ) -> Option<(Zeroizing<[u8; 16]>, [u8; 32])> {
+        let remote = self.remote_offer.take()?;
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let public = PublicKey::from(&secret);
+        let key = derive_key(secret.diffie_hellman(&remote));
+        self.previous = Some(std::mem::replace(current, Aes128Gcm::new(&(*key).into())));
+        self.since = now;
+        self.bytes = 0;
+        self.confirmed = false;
+        Some((key, public.to_bytes()))
+    }
+
+    /// Derives the key the remote rotated to from the public key it sent, `None` if this peer
+    /// offered none.
+    pub fn accept(&mut self, public: [u8; 32]) -> Option<Zeroizing<[u8; 16]>> {
+        let secret = self.offer.take()?;
+        Some(derive_key(secret.diffie_hellman(&PublicKey::from(public))))
+    }
+}
+
+fn derive_key(shared: SharedSecret) -> Zeroizing<[u8; 16]> {
+    let mut key = Zeroizing::new([0; 16]);
+    Hkdf::<Sha256>::new(None, shared.as_bytes())
+        .expand(KEY_INFO, key.as_mut())
+        .expect("valid key length");
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::transport::packet_data::Plaintext;
+
+    use super::*;
+
+    fn packet(key: &Aes128Gcm) -> PacketData<UnknownEncryption> {
+        let plain: PacketData<Plaintext> = PacketData::from_buf_plain(b"packet");
+        PacketData::from_buf(plain.encrypt_symmetric(key).data())
+    }
+
+    #[test]
+    fn key_rotation() {
+        let start = Instant::now();
+        let limits = RekeyLimits {
+            interval: Duration::from_secs(60),
+            bytes: 1000,
+        };
+        let initial = Aes128Gcm::new(&rand::random::<[u8; 16]>().into());
+        let mut current = initial.clone();
+        let mut rotation = KeyRotation::new(limits, start);
+        let mut remote = KeyRotation::new(limits, start);
+        rotation.received(1000);
+        // not rotated until the remote offers a key
+        assert!(!rotation.is_due(start));
+        rotation.offered(remote.offer().unwrap());
+        assert!(remote.offer().is_none());
+        assert!(rotation.is_due(start));
+
+        let (key, public) = rotation.rotate(&mut current, start).unwrap();
+        let rotated = Aes128Gcm::new(&(*remote.accept(public).unwrap()).into());
+        assert!(packet(&Aes128Gcm::new(&(*key).into()))
+            .try_decrypt_sym(&current)
+            .is_ok());
+        assert!(packet(&rotated).try_decrypt_sym(&current).is_ok());
+        // packets sent before the remote switched
+        assert!(packet(&initial).try_decrypt_sym(&current).is_err());
+        assert!(rotation.decrypt_previous(&packet(&initial)).is_some());
+        // the secret offered is only used once
+        assert!(remote.accept(public).is_none());
+        // not rotated again until the remote uses the new key and offers a new one
+        rotation.offered(remote.offer().unwrap());
+        assert!(!rotation.is_due(start + Duration::from_secs(60)));
+        rotation.received(10);
+        assert!(rotation.is_due(start + Duration::from_secs(60)));
+
+        rotation.rotate(&mut current, start + Duration::from_secs(60));
+        assert!(rotation.decrypt_previous(&packet(&initial)).is_none());
+        assert!(rotation.decrypt_previous(&packet(&rotated)).is_some());
+        assert!(!rotation.is_due(start + Duration::from_secs(120)));
+    }
+}
@@ -0,0 +1,148 @@
+//! Relaying of the packets of peers which can't reach each other directly, e.g. both being
+//! behind NATs mapping their sockets depending on the destination, through peers they are both
+//! connected to.
+//!
+//! When traversing the NAT of a remote fails, peers retry through relays: the packets to the
+//! remote are sent to every candidate relay wrapped in forward packets, of the `FNRF` magic
+//! bytes and the address of the remote (the tag of the address, 4 or 6, its IP and its port, big
+//! endian) followed by the packet. Relays, opted into with [`RelayLimits`], pass them on to the
+//! remote wrapped in delivery packets, of the `FNRD` magic bytes and the address of the sender
+//! followed by the packet, as long as they are connected to both peers and within their rate
+//! limit. Peers only accept deliveries from the remotes they are relaying to, and keep relaying
+//! through the first relay a delivery from the remote came through.
+//!
+//! Relayed packets are larger than the maximum size of packets by their header, so they may be
+//! fragmented on the way.
+
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+
+use parking_lot::RwLock;
+
+use super::nat;
+
+const FORWARD_MAGIC: [u8; 4] = *b"FNRF";
+const DELIVERY_MAGIC: [u8; 4] = *b"FNRD";
+
+/// Size of the header of relayed packets with IPv6 addresses.
+pub(super) const MAX_HEADER_SIZE: usize = FORWARD_MAGIC.len() + 1 + 16 + 2;
+
+/// Relaying done by the node for other peers.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RelayLimits {
+    pub enabled: bool,
+    /// Bytes per second relayed at most.
+    pub bytes_per_second: usize,
+}
+
+impl Default for RelayLimits {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bytes_per_second: 1 << 20,
+        }
+    }
+}
+
+fn wrap(magic: [u8; 4], addr: SocketAddr, packet: &[u8]) -> Vec<u8> {
+    let mut wrapped = Vec::with_capacity(MAX_HEADER_SIZE + packet.len());
+    wrapped.extend_from_slice(&magic);
+    nat::encode_addr(&mut wrapped, addr);
+    wrapped.extend_from_slice(packet);
+    wrapped
+}
+
+/// Packet asking a relay to pass `packet` on to `remote`.
+pub(super) fn forward_packet(remote: SocketAddr, packet: &[u8]) -> Vec<u8> {
+    wrap(FORWARD_MAGIC, remote, packet)
+}
+
+/// The remote to pass the packet in `packet` on to, if it is a forward packet.
+pub(super) fn parse_forward(packet: &[u8]) -> Option<(SocketAddr, &[u8])> {
+    nat::decode_addr(packet.strip_prefix(&FORWARD_MAGIC)?)
+}
+
+/// Packet delivering `packet` from `sender` through a relay.
+pub(super) fn delivery_packet(sender: SocketAddr, packet: &[u8]) -> Vec<u8> {
+    wrap(DELIVERY_MAGIC, sender, packet)
+}
+
+/// The sender of the packet in `packet`, if it is a delivery packet.
+pub(super) fn parse_delivery(packet: &[u8]) -> Option<(SocketAddr, &[u8])> {
+    nat::decode_addr(packet.strip_prefix(&DELIVERY_MAGIC)?)
+}
+
+/// Relays the packets to remotes are sent through, shared by the listener and the sender of the
+/// transport.
+#[derive(Clone, Default)]
+pub(super) struct RelayRoutes(Arc<RwLock<HashMap<SocketAddr, Vec<SocketAddr>>>>);
+
+impl RelayRoutes {
+    /// Sends the packets to `remote` through each of `relays` until one delivers a packet from
+    /// it.
+    pub fn relay_through(&self, remote: SocketAddr, relays: Vec<SocketAddr>) {
+        self.0.write().insert(remote, relays);
+    }
+
+    /// Relays the packets to `remote` are sent through, none if sent directly.
+    pub fn relays(&self, remote: &SocketAddr) -> Vec<SocketAddr> {
+        self.0.read().get(remote).cloned().unwrap_or_default()
+    }
+
+    /// Reports a packet from `remote` delivered by `relay`, returning whether it is accepted.
+    pub fn delivered(&self, remote: SocketAddr, relay: SocketAddr) -> bool {
+        match self.0.write().get_mut(&remote) {
+            Some(relays) if relays.contains(&relay) => {
+                if relays.len() > 1 {
+                    tracing::debug!(%remote, %relay, "Relaying through");
+                    *relays = vec![relay];
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn remove(&self, remote: &SocketAddr) {
+        self.0.write().remove(remote);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+
+    #[test]
+    fn relay_framing() {
+        let remote = SocketAddr::from((Ipv6Addr::LOCALHOST, 31337));
+        let forward = forward_packet(remote, b"packet");
+        assert_eq!(forward.len(), MAX_HEADER_SIZE + 6);
+        assert_eq!(parse_forward(&forward), Some((remote, &b"packet"[..])));
+        assert_eq!(parse_delivery(&forward), None);
+
+        let sender = SocketAddr::from((Ipv4Addr::new(203, 0, 113, 5), 40000));
+        let delivery = delivery_packet(sender, b"packet");
+        assert_eq!(parse_delivery(&delivery), Some((sender, &b"packet"[..])));
+        assert_eq!(parse_forward(&delivery), None);
+        assert_eq!(parse_delivery(&delivery[..8]), None);
+    }
+
+    #[test]
+    fn relay_routes() {
+        let remote = SocketAddr::from((Ipv4Addr::new(203, 0, 113, 5), 40000));
+        let relay = |port| SocketAddr::from((Ipv4Addr::new(198, 51, 100, 1), port));
+        let routes = RelayRoutes::default();
+        assert!(routes.relays(&remote).is_empty());
+        assert!(!routes.delivered(remote, relay(1)));
+
+        routes.relay_through(remote, vec![relay(1), relay(2)]);
+        assert!(!routes.delivered(remote, relay(3)));
+        assert!(routes.delivered(remote, relay(2)));
+        assert_eq!(routes.relays(&remote), vec![relay(2)]);
+        assert!(!routes.delivered(remote, relay(1)));
+
+        routes.remove(&remote);
+        assert!(routes.relays(&remote).is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+//! Rejection of the packets of connections replayed by whoever captured them on the way.
+//!
+//! Packets carry an id increasing with every packet sent, authenticated along with their payload
+//! so it can't be altered. Each connection tracks the ids received within a window of
+//! [`ReplayLimits::window`] packets behind the highest one: packets with an id received already
+//! are rejected as duplicates, and those behind the window as stale, as they can't be told apart
+//! from replays anymore. The window has to span the packets the remote may still resend or
+//! deliver out of order, the ones it misses being lost for good.
+
+use super::PacketId;
+
+/// How far behind the latest packet of a connection packets are still accepted.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ReplayLimits {
+    /// Packets accepted behind the highest id received, rounded up to a multiple of 64.
+    pub window: u32,
+}
+
+impl Default for ReplayLimits {
+    fn default() -> Self {
+        Self { window: 8192 }
+    }
+}
+
+/// Why a packet was rejected as replayed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Replay {
+    /// Received already, as are packets resent by the remote when their receipts get lost.
+    Duplicate,
+    /// Too far behind the latest packet to tell whether it was received already.
+    Stale,
+}
+
+/// The ids of the packets received by a connection within the window.
+pub(super) struct ReplayWindow {
+    size: u32,
+    highest: Option<PacketId>,
+    /// Whether each id of the window was received, the bit of an id at its remainder by `size`.
+    received: Vec<u64>,
+}
+
+impl ReplayWindow {
+    pub fn new(limits: ReplayLimits) -> Self {
+        let words = limits.window.div_ceil(64).max(1);
+        Self {
+            size: words * 64,
+            highest: None,
+            received: vec![0; words as usize],
+        }
+    }
+
+    /// Records the packet received with the id, rejecting it if replayed.
+    pub fn check(&mut self, packet_id: PacketId) -> Result<(), Replay> {
+        let Some(highest) = self.highest else {
+            self.highest = Some(packet_id);
+            self.set(packet_id);
+            return Ok(());
+        };
+        if packet_id > highest {
+            if packet_id - highest >= self.size {
+                self.received.fill(0);
+            } else {
+                // the ids skipped become part of the window, not received yet
+                for skipped in highest + 1..packet_id {
+                    self.clear(skipped);
+                }
+            }
+            self.highest = Some(packet_id);
+            self.set(packet_id);
+            return Ok(());
+        }
+        if highest - packet_id >= self.size {
+            return Err(Replay::Stale);
+        }
+        if self.is_set(packet_id) {
+            return Err(Replay::Duplicate);
+        }
+        self.set(packet_id);
+        Ok(())
+    }
+
+    fn position(&self, packet_id: PacketId) -> (usize, u64) {
+        let bit = packet_id % self.size;
+        ((bit / 64) as usize, 1 << (bit % 64))
+    }
+
+    fn set(&mut self, packet_id: PacketId) {
+        let (word, mask) = self.position(packet_id);
+        self.received[word] |= mask;
+    }
+
+    fn clear(&mut self, packet_id: PacketId) {
+        let (word, mask) = self.position(packet_id);
+        self.received[word] &= !mask;
+    }
+
+    fn is_set(&self, packet_id: PacketId) -> bool {
+        let (word, mask) = self.position(packet_id);
+        self.received[word] & mask != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_replayed_packets() {
+        let mut window = ReplayWindow::new(ReplayLimits { window: 100 });
+        assert_eq!(window.size, 128);
+
+        assert_eq!(window.check(10), Ok(()));
+        assert_eq!(window.check(10), Err(Replay::Duplicate));
+        // delivered out of order
+        assert_eq!(window.check(5), Ok(()));
+        assert_eq!(window.check(12), Ok(()));
+        assert_eq!(window.check(11), Ok(()));
+        assert_eq!(window.check(5), Err(Replay::Duplicate));
+
+        // the window slides, forgetting the ids left behind
+        assert_eq!(window.check(10 + 128), Ok(()));
+        assert_eq!(window.check(10), Err(Replay::Stale));
+        assert_eq!(window.check(11), Err(Replay::Duplicate));
+        assert_eq!(window.check(13), Ok(()));
+        assert_eq!(window.check(12 + 128), Ok(()));
+        assert_eq!(window.check(12), Err(Replay::Stale));
+        assert_eq!(window.check(13), Err(Replay::Duplicate));
+
+        // jumping past the window entirely
+        assert_eq!(window.check(1000), Ok(()));
+        assert_eq!(window.check(1000 - 127), Ok(()));
+        assert_eq!(window.check(1000 - 128), Err(Replay::Stale));
+        assert_eq!(window.check(1000), Err(Replay::Duplicate));
+    }
+}
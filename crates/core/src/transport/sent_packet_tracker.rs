@@ -1,6 +1,6 @@
-use super::PacketId;
+use super::{congestion::CongestionController, timeouts::ConnectionTimeouts, PacketId};
 use crate::util::time_source::{InstantTimeSrc, TimeSource};
-use std::collections::{HashMap, VecDeque};
+use std::collections::{hash_map::Entry, HashMap, VecDeque};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -29,6 +29,12 @@ pub(super) const MESSAGE_CONFIRMATION_TIMEOUT: Duration = {
 /// in a more accurate estimate, but it will take longer to converge to the true value.
 const PACKET_LOSS_DECAY_FACTOR: f64 = 1.0 / 1000.0;
 
+/// Weight of every new sample in the smoothed round-trip time, as in TCP (RFC 6298).
+const ROUND_TRIP_TIME_WEIGHT: u32 = 8;
+
+/// Weight of every new sample in the variation of the round-trip time, as in TCP (RFC 6298).
+const ROUND_TRIP_TIME_VARIATION_WEIGHT: u32 = 4;
+
 /// This struct is responsible for tracking packets that have been sent but not yet acknowledged.
 /// It is also responsible for deciding when to resend packets that have not been acknowledged.
 ///
@@ -60,6 +66,26 @@ pub(super) struct SentPacketTracker<T: TimeSource> {
 
     packet_loss_proportion: f64,
 
+    /// Packets sent, including those resent.
+    packets_sent: u64,
+
+    /// Packets resent for not being acknowledged in time.
+    retransmissions: u64,
+
+    /// When packets pending a receipt were sent, `None` for those resent since their receipt
+    /// can't be told apart from the receipt of the original.
+    sent_at: HashMap<PacketId, Option<Instant>>,
+
+    round_trip_time: Option<Duration>,
+
+    /// Smoothed deviation of the round-trip time samples.
+    round_trip_time_variation: Option<Duration>,
+
+    congestion: CongestionController,
+
+    /// When the last receipt was received, or the tracker created.
+    last_receipt: Instant,
+
     pub(super) time_source: T,
 }
 
@@ -69,6 +95,13 @@ impl SentPacketTracker<InstantTimeSrc> {
             pending_receipts: HashMap::new(),
             resend_queue: VecDeque::new(),
             packet_loss_proportion: 0.0,
+            packets_sent: 0,
+            retransmissions: 0,
+            sent_at: HashMap::new(),
+            round_trip_time: None,
+            round_trip_time_variation: None,
+            congestion: CongestionController::default(),
+            last_receipt: Instant::now(),
             time_source: InstantTimeSrc::new(),
         }
     }
@@ -76,15 +109,55 @@ impl SentPacketTracker<InstantTimeSrc> {
 
 impl<T: TimeSource> SentPacketTracker<T> {
     pub(super) fn report_sent_packet(&mut self, packet_id: PacketId, payload: Arc<[u8]>) {
+        let now = self.time_source.now();
         self.pending_receipts.insert(packet_id, payload);
+        self.packets_sent += 1;
+        match self.sent_at.entry(packet_id) {
+            Entry::Vacant(entry) => {
+                entry.insert(Some(now));
+            }
+            Entry::Occupied(mut entry) => {
+                entry.insert(None);
+                self.retransmissions += 1;
+            }
+        }
         self.resend_queue.push_back(ResendQueueEntry {
-            timeout_at: self.time_source.now() + MESSAGE_CONFIRMATION_TIMEOUT,
+            timeout_at: now + MESSAGE_CONFIRMATION_TIMEOUT,
             packet_id,
         });
     }
 
     pub(super) fn report_received_receipts(&mut self, packet_ids: &[PacketId]) {
+        let now = self.time_source.now();
+        if !packet_ids.is_empty() {
+            self.last_receipt = now;
+        }
         for packet_id in packet_ids {
+            let sample = self
+                .sent_at
+                .remove(packet_id)
+                .flatten()
+                .map(|sent_at| now.saturating_duration_since(sent_at));
+            if let Some(sample) = sample {
+                self.round_trip_time_variation = Some(match self.round_trip_time {
+                    Some(rtt) => {
+                        let deviation = rtt.abs_diff(sample);
+                        let variation = self.round_trip_time_variation.unwrap_or(deviation);
+                        (variation * (ROUND_TRIP_TIME_VARIATION_WEIGHT - 1) + deviation)
+                            / ROUND_TRIP_TIME_VARIATION_WEIGHT
+                    }
+                    None => sample / 2,
+                });
+                self.round_trip_time = Some(match self.round_trip_time {
+                    Some(rtt) => {
+                        (rtt * (ROUND_TRIP_TIME_WEIGHT - 1) + sample) / ROUND_TRIP_TIME_WEIGHT
+                    }
+                    None => sample,
+                });
+            }
+            if self.pending_receipts.contains_key(packet_id) {
+                self.congestion.on_ack(sample, self.round_trip_time);
+            }
             // This can be simplified but I'm leaving it like this for readability.
             self.packet_loss_proportion = self.packet_loss_proportion
                 * (1.0 - PACKET_LOSS_DECAY_FACTOR)
@@ -93,6 +166,43 @@ impl<T: TimeSource> SentPacketTracker<T> {
         }
     }
 
+    /// Smoothed time between sending packets and receiving their receipts, `None` until a
+    /// receipt is received. Receipts are batched, so it includes the time they are held for.
+    pub(super) fn round_trip_time(&self) -> Option<Duration> {
+        self.round_trip_time
+    }
+
+    /// Smoothed proportion of the packets sent which were lost, from 0 to 1.
+    pub(super) fn packet_loss(&self) -> f64 {
+        self.packet_loss_proportion
+    }
+
+    /// Packets sent and how many of them were resent.
+    pub(super) fn packets_sent(&self) -> (u64, u64) {
+        (self.packets_sent, self.retransmissions)
+    }
+
+    /// Time since the last receipt while packets are waiting for one, `None` if none are.
+    pub(super) fn silent_for(&self) -> Option<Duration> {
+        (!self.pending_receipts.is_empty()).then(|| {
+            self.time_source
+                .now()
+                .saturating_duration_since(self.last_receipt)
+        })
+    }
+
+    /// Timeouts of the connection derived from its round-trip time.
+    pub(super) fn timeouts(&self) -> ConnectionTimeouts {
+        ConnectionTimeouts::new(self.round_trip_time, self.round_trip_time_variation)
+    }
+
+    /// Time to wait before sending another packet of a stream so the packets in flight stay
+    /// within the congestion window, `None` if it can be sent now.
+    pub(super) fn congestion_wait(&self) -> Option<Duration> {
+        self.congestion
+            .wait(self.pending_receipts.len(), self.round_trip_time)
+    }
+
     /// Either get a packet that needs to be resent, or how long the caller should wait until
     /// calling this function again. If a packet is resent you **must** call
     /// `report_sent_packet` again with the same packet_id.
@@ -113,6 +223,7 @@ impl<T: TimeSource> SentPacketTracker<T> {
                 self.packet_loss_proportion = self.packet_loss_proportion
                     * (1.0 - PACKET_LOSS_DECAY_FACTOR)
                     + PACKET_LOSS_DECAY_FACTOR;
+                self.congestion.on_loss(now, self.round_trip_time);
 
                 return ResendAction::Resend(entry.packet_id, packet);
             }
@@ -143,12 +254,20 @@ pub(in crate::transport) mod tests {
     use crate::util::time_source::MockTimeSource;
 
     pub(in crate::transport) fn mock_sent_packet_tracker() -> SentPacketTracker<MockTimeSource> {
-        let time_source = MockTimeSource::new(Instant::now());
+        let start = Instant::now();
+        let time_source = MockTimeSource::new(start);
 
         SentPacketTracker {
             pending_receipts: HashMap::new(),
             resend_queue: VecDeque::new(),
             packet_loss_proportion: 0.0,
+            packets_sent: 0,
+            retransmissions: 0,
+            sent_at: HashMap::new(),
+            round_trip_time: None,
+            round_trip_time_variation: None,
+            congestion: CongestionController::default(),
+            last_receipt: start,
             time_source,
         }
     }
@@ -172,6 +291,59 @@ pub(in crate::transport) mod tests {
         assert_eq!(tracker.packet_loss_proportion, 0.0);
     }
 
+    #[test]
+    fn test_silent_for() {
+        let mut tracker = mock_sent_packet_tracker();
+        tracker.time_source.advance_time(Duration::from_secs(1));
+        assert_eq!(tracker.silent_for(), None);
+        tracker.report_sent_packet(1, vec![1].into());
+        tracker.report_sent_packet(2, vec![2].into());
+        assert_eq!(tracker.silent_for(), Some(Duration::from_secs(1)));
+        tracker.time_source.advance_time(Duration::from_millis(500));
+        tracker.report_received_receipts(&[1]);
+        assert_eq!(tracker.silent_for(), Some(Duration::ZERO));
+        tracker.time_source.advance_time(Duration::from_millis(300));
+        assert_eq!(tracker.silent_for(), Some(Duration::from_millis(300)));
+        tracker.report_received_receipts(&[2]);
+        assert_eq!(tracker.silent_for(), None);
+    }
+
+    #[test]
+    fn test_round_trip_time() {
+        let mut tracker = mock_sent_packet_tracker();
+        assert_eq!(tracker.round_trip_time(), None);
+        tracker.report_sent_packet(1, vec![1].into());
+        tracker.time_source.advance_time(Duration::from_millis(80));
+        tracker.report_received_receipts(&[1]);
+        assert_eq!(tracker.round_trip_time(), Some(Duration::from_millis(80)));
+        assert_eq!(
+            tracker.round_trip_time_variation,
+            Some(Duration::from_millis(40))
+        );
+
+        tracker.report_sent_packet(2, vec![2].into());
+        tracker.time_source.advance_time(Duration::from_millis(160));
+        tracker.report_received_receipts(&[2, 2]);
+        assert_eq!(tracker.round_trip_time(), Some(Duration::from_millis(90)));
+        assert_eq!(
+            tracker.round_trip_time_variation,
+            Some(Duration::from_millis(50))
+        );
+
+        // receipts of resent packets are not sampled
+        tracker.report_sent_packet(3, vec![3].into());
+        tracker
+            .time_source
+            .advance_time(MESSAGE_CONFIRMATION_TIMEOUT);
+        let ResendAction::Resend(3, packet) = tracker.get_resend() else {
+            panic!("expected packet 3 to be resent");
+        };
+        tracker.report_sent_packet(3, packet);
+        tracker.time_source.advance_time(Duration::from_millis(10));
+        tracker.report_received_receipts(&[3]);
+        assert_eq!(tracker.round_trip_time(), Some(Duration::from_millis(90)));
+    }
+
     #[test]
     fn test_packet_lost() {
         let mut tracker = mock_sent_packet_tracker();
@@ -184,6 +356,8 @@ pub(in crate::transport) mod tests {
         assert_eq!(tracker.pending_receipts.len(), 0);
         assert_eq!(tracker.resend_queue.len(), 0);
         assert_eq!(tracker.packet_loss_proportion, PACKET_LOSS_DECAY_FACTOR);
+        tracker.report_sent_packet(1, vec![1, 2, 3].into());
+        assert_eq!(tracker.packets_sent(), (2, 1));
     }
 
     #[test]
@@ -7,8 +7,10 @@ use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 
 use super::{
-    packet_data::PacketData, packet_data::MAX_DATA_SIZE, peer_connection::StreamId, MessagePayload,
-    PacketId,
+    negotiation::Capabilities,
+    packet_data::{self, PacketData, MAX_DATA_SIZE},
+    peer_connection::StreamId,
+    MessagePayload, PacketId,
 };
 
 #[serde_as]
@@ -68,6 +70,32 @@ impl SymmetricMessage {
         *OVERHEAD
     }
 
+    /// Probe of whether the path to the remote carries packets of `packet_size`, see
+    /// [`super::mtu`].
+    pub fn mtu_probe(
+        packet_id: PacketId,
+        packet_size: usize,
+        outbound_sym_key: &Aes128Gcm,
+    ) -> Result<PacketData<SymmetricAES>, bincode::Error> {
+        static OVERHEAD: Lazy<usize> = Lazy::new(|| {
+            let blank = SymmetricMessage {
+                packet_id: u32::MAX,
+                confirm_receipt: vec![],
+                payload: SymmetricMessagePayload::MtuProbe { padding: vec![] },
+            };
+            bincode::serialized_size(&blank).unwrap() as usize
+        });
+        let padding = packet_data::data_size(packet_size).saturating_sub(*OVERHEAD);
+        let message = Self {
+            packet_id,
+            confirm_receipt: vec![],
+            payload: SymmetricMessagePayload::MtuProbe {
+                padding: vec![0; padding],
+            },
+        };
+        message.to_packet_data(outbound_sym_key)
+    }
+
     pub(crate) fn max_num_of_confirm_receipts_of_noop_message() -> usize {
         static MAX_NUM_CONFIRM_RECEIPTS: Lazy<usize> = Lazy::new(|| {
             let overhead = SymmetricMessage::noop_message_overhead() as u64;
@@ -104,6 +132,7 @@ impl SymmetricMessage {
                 result: Ok(OutboundConnection {
                     key: our_inbound_key,
                     remote_addr,
+                    capabilities: Capabilities::LOCAL,
                 }),
             },
         };
@@ -215,11 +244,71 @@ impl From<StreamFragment> for SymmetricMessagePayload {
     }
 }
 
+/// Offer of a QUIC link to the remote peer, see [`super::quic`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct QuicOffer {
+    /// Port the QUIC endpoint of the peer offering the link listens on.
+    pub port: u16,
+    /// Self-signed certificate of the endpoint, in DER.
+    pub certificate: Vec<u8>,
+    /// Secret authenticating the peer dialing the endpoint.
+    pub token: [u8; 32],
+    /// Decides which peer dials, the one with the highest nonce.
+    pub nonce: u64,
+}
+
+/// Ephemeral public key the new key to encrypt the packets sent to the peer rotating it with is
+/// derived from, see [`super::rekey`].
+pub(super) struct Rekey(pub [u8; 32]);
+
+impl From<Rekey> for SymmetricMessagePayload {
+    fn from(rekey: Rekey) -> Self {
+        Self::Rekey { public: rekey.0 }
+    }
+}
+
+/// Ephemeral public key offered to the remote for its next rotation, see [`super::rekey`].
+pub(super) struct RekeyOffer(pub [u8; 32]);
+
+impl From<RekeyOffer> for SymmetricMessagePayload {
+    fn from(offer: RekeyOffer) -> Self {
+        Self::RekeyOffer { public: offer.0 }
+    }
+}
+
+/// Challenge of the new address of the remote, or the answer to one, with its token.
+pub(super) enum PathMessage {
+    Challenge([u8; 16]),
+    Response([u8; 16]),
+}
+
+impl From<PathMessage> for SymmetricMessagePayload {
+    fn from(message: PathMessage) -> Self {
+        match message {
+            PathMessage::Challenge(token) => Self::PathChallenge { token },
+            PathMessage::Response(token) => Self::PathResponse { token },
+        }
+    }
+}
+
+impl From<QuicOffer> for SymmetricMessagePayload {
+    fn from(offer: QuicOffer) -> Self {
+        Self::QuicOffer {
+            port: offer.port,
+            certificate: offer.certificate,
+            token: offer.token,
+            nonce: offer.nonce,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[cfg_attr(test, derive(PartialEq, Debug, Clone))]
 pub(crate) struct OutboundConnection {
     pub(super) key: [u8; 16],
     pub(super) remote_addr: SocketAddr,
+    /// Optional features of the protocol supported by the peer acknowledging the connection.
+    pub(super) capabilities: Capabilities,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -240,6 +329,29 @@ pub(crate) enum SymmetricMessagePayload {
         payload: MessagePayload,
     },
     NoOp,
+    QuicOffer {
+        port: u16,
+        certificate: Vec<u8>,
+        token: [u8; 32],
+        nonce: u64,
+    },
+    Rekey {
+        public: [u8; 32],
+    },
+    /// Padding making the packet as large as the size probed.
+    MtuProbe {
+        padding: Vec<u8>,
+    },
+    /// Challenge to the new address of the remote, see [`super::migration`].
+    PathChallenge {
+        token: [u8; 16],
+    },
+    PathResponse {
+        token: [u8; 16],
+    },
+    RekeyOffer {
+        public: [u8; 32],
+    },
 }
 
 #[cfg(test)]
@@ -266,6 +378,16 @@ impl std::fmt::Display for SymmetricMessagePayload {
                 stream_id, fragment_number
             ),
             SymmetricMessagePayload::NoOp => write!(f, "NoOp"),
+            SymmetricMessagePayload::QuicOffer { port, .. } => {
+                write!(f, "QuicOffer: (port: {port})")
+            }
+            SymmetricMessagePayload::Rekey { .. } => write!(f, "Rekey"),
+            SymmetricMessagePayload::MtuProbe { padding } => {
+                write!(f, "MtuProbe: (padding: {})", padding.len())
+            }
+            SymmetricMessagePayload::PathChallenge { .. } => write!(f, "PathChallenge"),
+            SymmetricMessagePayload::PathResponse { .. } => write!(f, "PathResponse"),
+            SymmetricMessagePayload::RekeyOffer { .. } => write!(f, "RekeyOffer"),
         }
     }
 }
@@ -302,6 +424,7 @@ mod test {
                 result: Ok(OutboundConnection {
                     key: [0; 16],
                     remote_addr: (Ipv4Addr::LOCALHOST, 1234).into(),
+                    capabilities: Capabilities::LOCAL,
                 }),
             },
             SymmetricMessagePayload::AckConnection {
@@ -323,6 +446,24 @@ mod test {
                     .collect(),
             },
             SymmetricMessagePayload::NoOp,
+            SymmetricMessagePayload::QuicOffer {
+                port: 31337,
+                certificate: vec![1; 400],
+                token: rand::random(),
+                nonce: rand::random(),
+            },
+            SymmetricMessagePayload::Rekey {
+                public: rand::random(),
+            },
+            SymmetricMessagePayload::PathChallenge {
+                token: rand::random(),
+            },
+            SymmetricMessagePayload::PathResponse {
+                token: rand::random(),
+            },
+            SymmetricMessagePayload::RekeyOffer {
+                public: rand::random(),
+            },
         ];
         let key = gen_key();
 
@@ -354,6 +495,7 @@ mod test {
                 result: Ok(OutboundConnection {
                     key: [0; 16],
                     remote_addr: (Ipv4Addr::LOCALHOST, 1234).into(),
+                    capabilities: Capabilities::LOCAL,
                 }),
             },
         })?;
@@ -370,6 +512,20 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn mtu_probe_msg() -> Result<(), Box<dyn std::error::Error>> {
+        let key = gen_key();
+        let packet = SymmetricMessage::mtu_probe(1, 1400, &key)?;
+        assert_eq!(packet.data().len(), 1400);
+        let data = packet.decrypt(&key).unwrap();
+        let deser = SymmetricMessage::deser(data.data())?;
+        assert!(matches!(
+            deser.payload,
+            SymmetricMessagePayload::MtuProbe { .. }
+        ));
+        Ok(())
+    }
+
     #[test]
     fn max_confirm_receipts_of_noop_message() {
         let num = SymmetricMessage::max_num_of_confirm_receipts_of_noop_message();
@@ -0,0 +1,206 @@
+//! TCP tunnels carrying the packets of the transport for peers on networks blocking or
+//! throttling UDP.
+//!
+//! Sockets of the transport also listen for TCP connections on the port of their UDP socket.
+//! When a gateway can't be reached over UDP, peers open a tunnel to it over TCP and retry the
+//! connection. Packets to and from the remote of a tunnel are then framed over it, prefixed by
+//! their size (u16, big endian), instead of sent as datagrams, so the protocol is the same
+//! whatever carries it.
+
+use std::{collections::HashMap, io, net::SocketAddr, sync::Arc};
+
+use parking_lot::Mutex;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpListener, TcpStream, UdpSocket,
+    },
+    sync::mpsc,
+};
+
+use super::{dual_stack, packet_data::MAX_PACKET_SIZE, Socket};
+
+type TunneledPacket = (Vec<u8>, SocketAddr);
+
+/// UDP socket sending the packets of the remotes it has a tunnel with through the tunnel.
+pub(crate) struct TunneledSocket {
+    udp: UdpSocket,
+    local_addr: SocketAddr,
+    tunnels: TcpTunnels,
+    inbound: tokio::sync::Mutex<mpsc::Receiver<TunneledPacket>>,
+}
+
+/// Tunnels of a socket, by the address of their remote.
+#[derive(Clone)]
+pub(crate) struct TcpTunnels {
+    outbound: Arc<Mutex<HashMap<SocketAddr, mpsc::Sender<Vec<u8>>>>>,
+    inbound: mpsc::Sender<TunneledPacket>,
+}
+
+impl TcpTunnels {
+    /// Opens a tunnel to `remote`, unless there is one already.
+    pub async fn open(&self, remote: SocketAddr) -> io::Result<()> {
+        if self.outbound.lock().contains_key(&remote) {
+            return Ok(());
+        }
+        let stream = TcpStream::connect(remote).await?;
+        tracing::debug!(%remote, "Opened TCP tunnel");
+        self.add(stream, remote);
+        Ok(())
+    }
+
+    fn add(&self, stream: TcpStream, remote: SocketAddr) {
+        let _ = stream.set_nodelay(true);
+        let (reader, writer) = stream.into_split();
+        let (sender, packets) = mpsc::channel(100);
+        self.outbound.lock().insert(remote, sender);
+        tokio::spawn(write_packets(writer, packets));
+        let tunnels = self.clone();
+        tokio::spawn(async move {
+            if let Err(error) = read_packets(reader, remote, &tunnels.inbound).await {
+                tracing::debug!(%error, %remote, "TCP tunnel closed");
+            }
+            tunnels.outbound.lock().remove(&remote);
+        });
+    }
+
+    fn get(&self, remote: &SocketAddr) -> Option<mpsc::Sender<Vec<u8>>> {
+        self.outbound.lock().get(remote).cloned()
+    }
+}
+
+async fn accept_tunnels(listener: TcpListener, tunnels: TcpTunnels) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, remote)) => {
+                let remote = dual_stack::canonical(remote);
+                tracing::debug!(%remote, "Accepted TCP tunnel");
+                tunnels.add(stream, remote);
+            }
+            Err(error) => tracing::warn!(%error, "Failed to accept TCP tunnel"),
+        }
+    }
+}
+
+async fn write_packets(
+    mut writer: OwnedWriteHalf,
+    mut packets: mpsc::Receiver<Vec<u8>>,
+) -> io::Result<()> {
+    while let Some(packet) = packets.recv().await {
+        writer.write_u16(packet.len() as u16).await?;
+        writer.write_all(&packet).await?;
+    }
+    Ok(())
+}
+
+async fn read_packets(
+    mut reader: OwnedReadHalf,
+    remote: SocketAddr,
+    inbound: &mpsc::Sender<TunneledPacket>,
+) -> io::Result<()> {
+    loop {
+        let size = reader.read_u16().await? as usize;
+        if size > MAX_PACKET_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("tunneled packet of {size} bytes"),
+            ));
+        }
+        let mut packet = vec![0; size];
+        reader.read_exact(&mut packet).await?;
+        if inbound.send((packet, remote)).await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
+impl Socket for TunneledSocket {
+    async fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let udp = dual_stack::bind_udp(addr)?;
+        let (inbound_sender, inbound) = mpsc::channel(1000);
+        let tunnels = TcpTunnels {
+            outbound: Default::default(),
+            inbound: inbound_sender,
+        };
+        let local_addr = udp.local_addr()?;
+        match dual_stack::listen_tcp(local_addr) {
+            Ok(listener) => {
+                tokio::spawn(accept_tunnels(listener, tunnels.clone()));
+            }
+            Err(error) => tracing::warn!(%error, %addr, "Failed to listen for TCP tunnels"),
+        }
+        Ok(Self {
+            udp,
+            local_addr,
+            tunnels,
+            inbound: tokio::sync::Mutex::new(inbound),
+        })
+    }
+
+    async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let mut inbound = self.inbound.lock().await;
+        let (packet, remote) = tokio::select! {
+            received = self.udp.recv_from(buf) => {
+                let (size, remote) = received?;
+                crate::metrics::bytes_received(size);
+                return Ok((size, dual_stack::canonical(remote)));
+            }
+            Some(tunneled) = inbound.recv() => tunneled,
+        };
+        let size = packet.len().min(buf.len());
+        buf[..size].copy_from_slice(&packet[..size]);
+        crate::metrics::bytes_received(size);
+        Ok((size, remote))
+    }
+
+    async fn send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        let size = match self.tunnels.get(&target) {
+            Some(tunnel) => {
+                tunnel
+                    .send(buf.to_vec())
+                    .await
+                    .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "TCP tunnel closed"))?;
+                buf.len()
+            }
+            None => {
+                let target = dual_stack::target_for(self.local_addr, target);
+                self.udp.send_to(buf, target).await?
+            }
+        };
+        crate::metrics::bytes_sent(size);
+        Ok(size)
+    }
+
+    fn tunnels(&self) -> Option<TcpTunnels> {
+        Some(self.tunnels.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn tunneled_packets() -> Result<(), Box<dyn std::error::Error>> {
+        let gateway = TunneledSocket::bind((Ipv4Addr::LOCALHOST, 0).into()).await?;
+        let peer = TunneledSocket::bind((Ipv4Addr::LOCALHOST, 0).into()).await?;
+        let gateway_addr = gateway.udp.local_addr()?;
+        peer.tunnels.open(gateway_addr).await?;
+
+        let mut buf = [0; MAX_PACKET_SIZE];
+        peer.send_to(b"hello", gateway_addr).await?;
+        let (size, peer_addr) = gateway.recv_from(&mut buf).await?;
+        assert_eq!(&buf[..size], b"hello");
+        // the tunnel is identified by the address of its TCP connection
+        assert_ne!(peer_addr, peer.udp.local_addr()?);
+
+        gateway.send_to(b"world", peer_addr).await?;
+        let (size, remote) = peer.recv_from(&mut buf).await?;
+        assert_eq!(&buf[..size], b"world");
+        assert_eq!(remote, gateway_addr);
+        Ok(())
+    }
+}
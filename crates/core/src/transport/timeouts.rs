@@ -0,0 +1,105 @@
+//! Timeouts of connections derived from their round-trip time and its variation, so connections
+//! over slow links aren't dropped while packets are still on their way, and dead peers of fast
+//! links, such as those of the local network, are detected sooner.
+
+use std::time::Duration;
+
+#[cfg(debug_assertions)]
+const MIN_IDLE: Duration = Duration::from_secs(6);
+#[cfg(not(debug_assertions))]
+const MIN_IDLE: Duration = Duration::from_secs(10);
+#[cfg(debug_assertions)]
+const MAX_IDLE: Duration = Duration::from_secs(20);
+#[cfg(not(debug_assertions))]
+const MAX_IDLE: Duration = Duration::from_secs(120);
+
+/// Idle time until the connection is dropped, as retransmission timeouts.
+const IDLE_RETRANSMISSION_TIMEOUTS: u32 = 40;
+
+/// Keep-alives sent within the idle time, so it takes several lost in a row to drop a connection.
+const KEEP_ALIVES_PER_IDLE: u32 = 3;
+
+const MIN_REQUEST: Duration = Duration::from_secs(1);
+const MAX_REQUEST: Duration = Duration::from_secs(10);
+
+/// Time for requests to the peer to be handed over, as retransmission timeouts.
+const REQUEST_RETRANSMISSION_TIMEOUTS: u32 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ConnectionTimeouts {
+    /// Interval keep-alives are sent at.
+    pub keep_alive: Duration,
+    /// Time without receiving anything after which the connection is dropped.
+    pub idle: Duration,
+    /// Time to wait for requests to the peer to be handed over.
+    pub request: Duration,
+}
+
+impl Default for ConnectionTimeouts {
+    fn default() -> Self {
+        #[cfg(debug_assertions)]
+        const IDLE: Duration = Duration::from_secs(6);
+        #[cfg(not(debug_assertions))]
+        const IDLE: Duration = Duration::from_secs(60);
+        Self {
+            keep_alive: IDLE / KEEP_ALIVES_PER_IDLE,
+            idle: IDLE,
+            request: MIN_REQUEST,
+        }
+    }
+}
+
+impl ConnectionTimeouts {
+    /// Timeouts of a connection with the given smoothed round-trip time and variation, the
+    /// defaults until it is measured.
+    pub fn new(round_trip_time: Option<Duration>, variation: Option<Duration>) -> Self {
+        let Some(round_trip_time) = round_trip_time else {
+            return Self::default();
+        };
+        // as in TCP (RFC 6298)
+        let retransmission_timeout = round_trip_time + 4 * variation.unwrap_or(round_trip_time / 2);
+        let idle =
+            (retransmission_timeout * IDLE_RETRANSMISSION_TIMEOUTS).clamp(MIN_IDLE, MAX_IDLE);
+        Self {
+            keep_alive: idle / KEEP_ALIVES_PER_IDLE,
+            idle,
+            request: (retransmission_timeout * REQUEST_RETRANSMISSION_TIMEOUTS)
+                .clamp(MIN_REQUEST, MAX_REQUEST),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derived_timeouts() {
+        assert_eq!(
+            ConnectionTimeouts::new(None, None),
+            ConnectionTimeouts::default()
+        );
+
+        let lan = ConnectionTimeouts::new(
+            Some(Duration::from_millis(1)),
+            Some(Duration::from_micros(500)),
+        );
+        assert_eq!(lan.idle, MIN_IDLE);
+        assert_eq!(lan.keep_alive, MIN_IDLE / KEEP_ALIVES_PER_IDLE);
+        assert_eq!(lan.request, MIN_REQUEST);
+        assert!(lan.idle <= ConnectionTimeouts::default().idle);
+
+        let jittery = ConnectionTimeouts::new(
+            Some(Duration::from_millis(150)),
+            Some(Duration::from_millis(100)),
+        );
+        assert_eq!(jittery.request, Duration::from_millis(2200));
+        let steady = ConnectionTimeouts::new(Some(Duration::from_millis(150)), None);
+        assert_eq!(steady.request, Duration::from_millis(1800));
+
+        let satellite = ConnectionTimeouts::new(Some(Duration::from_secs(2)), None);
+        assert_eq!(satellite.idle, MAX_IDLE);
+        assert_eq!(satellite.request, MAX_REQUEST);
+        assert!(satellite.idle >= ConnectionTimeouts::default().idle);
+    }
+}
@@ -1,4 +1,5 @@
 pub(crate) mod time_source;
+pub(crate) mod token_bucket;
 
 use std::{
     borrow::Borrow,
@@ -328,6 +329,9 @@ pub enum EncodingProtocol {
     Flatbuffers,
     /// Rust native types
     Native,
+    /// MessagePack, with structs encoded as maps keyed by field name
+    #[serde(alias = "msgpack")]
+    MessagePack,
 }
 
 impl std::fmt::Display for EncodingProtocol {
@@ -335,6 +339,7 @@ impl std::fmt::Display for EncodingProtocol {
         match self {
             EncodingProtocol::Flatbuffers => write!(f, "flatbuffers"),
             EncodingProtocol::Native => write!(f, "native"),
+            EncodingProtocol::MessagePack => write!(f, "messagepack"),
         }
     }
 }
@@ -0,0 +1,81 @@
+//! Token bucket shared by the limits of the node: requests to the API, messages from peers and
+//! bandwidth of the transport.
+
+use std::time::{Duration, Instant};
+
+/// Bucket refilled continuously at `rate` tokens per second, holding up to `burst` tokens.
+pub(crate) struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket.
+    pub fn new(rate: f64, burst: f64, now: Instant) -> Self {
+        Self {
+            rate,
+            burst,
+            tokens: burst,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.burst);
+        self.last_refill = now;
+    }
+
+    /// Whether the bucket has `amount` tokens, without taking them.
+    pub fn has(&mut self, amount: usize, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= amount as f64
+    }
+
+    /// Whether the bucket is full again, so it behaves the same as a new one.
+    pub fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.burst
+    }
+
+    /// Takes `amount` tokens, returns how long to wait for the bucket to be out of debt when it
+    /// is short of them.
+    pub fn take(&mut self, amount: usize, now: Instant) -> Option<Duration> {
+        self.refill(now);
+        self.tokens -= amount as f64;
+        (self.tokens < 0.0).then(|| Duration::from_secs_f64(-self.tokens / self.rate))
+    }
+
+    /// Takes `amount` tokens if the bucket has them.
+    pub fn try_take(&mut self, amount: usize, now: Instant) -> bool {
+        let available = self.has(amount, now);
+        if available {
+            self.tokens -= amount as f64;
+        }
+        available
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(10.0, 20.0, start);
+        assert!(bucket.is_full(start));
+        assert_eq!(bucket.take(20, start), None);
+        assert_eq!(bucket.take(5, start), Some(Duration::from_millis(500)));
+        assert!(!bucket.has(1, start + Duration::from_millis(500)));
+        assert!(!bucket.try_take(1, start + Duration::from_millis(500)));
+        assert!(bucket.try_take(1, start + Duration::from_millis(600)));
+        // refilled up to the burst only
+        assert!(!bucket.try_take(21, start + Duration::from_secs(60)));
+        assert!(bucket.is_full(start + Duration::from_secs(60)));
+        assert!(bucket.try_take(20, start + Duration::from_secs(60)));
+        assert!(!bucket.is_full(start + Duration::from_secs(60)));
+    }
+}
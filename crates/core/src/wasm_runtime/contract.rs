@@ -58,6 +58,21 @@ pub(crate) trait ContractRuntimeInterface {
         state: &WrappedState,
         delta_to: &StateSummary<'_>,
     ) -> RuntimeResult<StateDelta<'static>>;
+
+    /// Compute the projection of a state answering the query of a client, so only the answer is
+    /// sent back instead of the whole state.
+    ///
+    /// Contracts answering queries export a `query_state` entry point, with the same signature
+    /// and result encoding as `get_state_delta` but taking the query in place of the summary
+    /// and returning the projection in place of the delta. Contracts not exporting it fail with
+    /// [`ContractExecError::MissingEntryPoint`].
+    fn query_state(
+        &mut self,
+        key: &ContractKey,
+        parameters: &Parameters<'_>,
+        state: &WrappedState,
+        query: &StateSummary<'_>,
+    ) -> RuntimeResult<StateDelta<'static>>;
 }
 
 impl ContractRuntimeInterface for super::Runtime {
@@ -252,6 +267,31 @@ impl ContractRuntimeInterface for super::Runtime {
         parameters: &Parameters<'a>,
         state: &WrappedState,
         summary: &StateSummary<'a>,
+    ) -> RuntimeResult<StateDelta<'static>> {
+        self.call_state_delta(key, parameters, state, summary, "get_state_delta")
+    }
+
+    fn query_state(
+        &mut self,
+        key: &ContractKey,
+        parameters: &Parameters<'_>,
+        state: &WrappedState,
+        query: &StateSummary<'_>,
+    ) -> RuntimeResult<StateDelta<'static>> {
+        self.call_state_delta(key, parameters, state, query, "query_state")
+    }
+}
+
+impl super::Runtime {
+    /// Calls an entry point computing a delta of the state from a summary, or an input of the
+    /// same shape.
+    fn call_state_delta(
+        &mut self,
+        key: &ContractKey,
+        parameters: &Parameters<'_>,
+        state: &WrappedState,
+        summary: &StateSummary<'_>,
+        entry_point: &'static str,
     ) -> RuntimeResult<StateDelta<'static>> {
         let req_bytes = parameters.size() + state.size() + summary.size();
         let running = self.prepare_contract_call(key, parameters, req_bytes)?;
@@ -274,10 +314,21 @@ impl ContractRuntimeInterface for super::Runtime {
         };
 
         let mut wasm_store = self.wasm_store.take().unwrap();
-        let get_state_delta_func: TypedFunction<(i64, i64, i64), FfiReturnTy> = running
+        let get_state_delta_func: TypedFunction<(i64, i64, i64), FfiReturnTy> = match running
             .instance
             .exports
-            .get_typed_function(&wasm_store, "get_state_delta")?;
+            .get_typed_function(&wasm_store, entry_point)
+        {
+            Ok(f) => f,
+            Err(wasmer::ExportError::Missing(_)) => {
+                self.wasm_store = Some(wasm_store);
+                return Err(ContractExecError::MissingEntryPoint(entry_point).into());
+            }
+            Err(e) => {
+                self.wasm_store = Some(wasm_store);
+                return Err(e.into());
+            }
+        };
 
         let param_buf_ptr = param_buf_ptr as i64;
         let state_buf_ptr = state_buf_ptr as i64;
@@ -1,4 +1,4 @@
-use std::{fs::File, io::Write, path::PathBuf, sync::Arc};
+use std::{collections::HashMap, fs::File, io::Write, path::PathBuf, sync::Arc};
 
 use dashmap::DashMap;
 use freenet_stdlib::prelude::*;
@@ -12,11 +12,20 @@ use super::{
 
 /// Handle contract blob storage on the file system.
 pub struct ContractStore {
-    contracts_dir: PathBuf,
-    key_file: PathBuf,
     contract_cache: Cache<CodeHash, Arc<ContractCode<'static>>>,
     key_to_code_part: Arc<DashMap<ContractInstanceId, (u64, CodeHash)>>,
-    index_file: SafeWriter<Self>,
+    storage: CodeStorage,
+}
+
+/// Where the code of the contracts is kept.
+enum CodeStorage {
+    Disk {
+        contracts_dir: PathBuf,
+        key_file: PathBuf,
+        index_file: SafeWriter<ContractStore>,
+    },
+    /// Never written to disk, for nodes running without storage.
+    Memory(HashMap<CodeHash, Arc<ContractCode<'static>>>),
 }
 // TODO: add functionality to delete old contracts which have not been used for a while
 //       to keep the total space used under a configured threshold
@@ -56,13 +65,24 @@ impl ContractStore {
         let index_file = SafeWriter::new(&key_file, false)?;
         Ok(Self {
             contract_cache: Cache::new(100, max_size).expect(ERR),
-            contracts_dir,
-            key_file,
             key_to_code_part,
-            index_file,
+            storage: CodeStorage::Disk {
+                contracts_dir,
+                key_file,
+                index_file,
+            },
         })
     }
 
+    /// A store keeping the contracts in memory, never written to disk.
+    pub fn in_memory(max_size: i64) -> Self {
+        Self {
+            contract_cache: Cache::new(100, max_size).expect("failed to build mem cache"),
+            key_to_code_part: Arc::new(DashMap::new()),
+            storage: CodeStorage::Memory(HashMap::new()),
+        }
+    }
+
     /// Returns a copy of the contract bytes if available, none otherwise.
     // todo: instead return Result<Option<_>, _> to handle IO errors upstream
     pub fn fetch_contract(
@@ -86,8 +106,17 @@ impl ContractStore {
 
         self.key_to_code_part.get(key.id()).and_then(|key| {
             let code_hash = key.value().1;
+            let contracts_dir = match &self.storage {
+                CodeStorage::Disk { contracts_dir, .. } => contracts_dir,
+                CodeStorage::Memory(contracts) => {
+                    let data = contracts.get(&code_hash)?.clone();
+                    return Some(ContractContainer::Wasm(ContractWasmAPIVersion::V1(
+                        WrappedContract::new(data, params.clone().into_owned()),
+                    )));
+                }
+            };
             let path = code_hash.encode();
-            let key_path = self.contracts_dir.join(path).with_extension("wasm");
+            let key_path = contracts_dir.join(path).with_extension("wasm");
             let ContractContainer::Wasm(ContractWasmAPIVersion::V1(WrappedContract {
                 data,
                 params,
@@ -125,8 +154,22 @@ impl ContractStore {
         if self.contract_cache.get(code_hash).is_some() {
             return Ok(());
         }
+        let (contracts_dir, key_file, index_file) = match &mut self.storage {
+            CodeStorage::Disk {
+                contracts_dir,
+                key_file,
+                index_file,
+            } => (contracts_dir, key_file, index_file),
+            CodeStorage::Memory(contracts) => {
+                let size = code.data().len() as i64;
+                self.contract_cache.insert(*code_hash, code.clone(), size);
+                contracts.insert(*code_hash, code);
+                self.key_to_code_part.insert(*key.id(), (0, *code_hash));
+                return Ok(());
+            }
+        };
         let key_path = code_hash.encode();
-        let key_path = self.contracts_dir.join(key_path).with_extension("wasm");
+        let key_path = contracts_dir.join(key_path).with_extension("wasm");
         if let Ok((code, _ver)) = ContractCode::load_versioned_from_path(&key_path) {
             let size = code.data().len() as i64;
             self.contract_cache.insert(*code_hash, Arc::new(code), size);
@@ -154,13 +197,13 @@ impl ContractStore {
                 let current_version_offset = v.get().0;
                 let prev_val = &mut v.get_mut().1;
                 // first mark the old entry (if it exists) as removed
-                Self::remove(&self.key_file, current_version_offset)?;
-                let new_offset = Self::insert(&mut self.index_file, *key.id(), code_hash)?;
+                Self::remove(key_file, current_version_offset)?;
+                let new_offset = Self::insert(index_file, *key.id(), code_hash)?;
                 *prev_val = *code_hash;
                 v.get_mut().0 = new_offset;
             }
             dashmap::mapref::entry::Entry::Vacant(v) => {
-                let offset = Self::insert(&mut self.index_file, *key.id(), code_hash)?;
+                let offset = Self::insert(index_file, *key.id(), code_hash)?;
                 v.insert((offset, *code_hash));
             }
         }
@@ -176,8 +219,11 @@ impl ContractStore {
                 RuntimeInnerError::UnwrapContract
             })?,
         };
+        let CodeStorage::Disk { contracts_dir, .. } = &self.storage else {
+            return Err(anyhow::anyhow!("contract `{key}` is kept in memory").into());
+        };
         let key_path = contract_hash.encode();
-        Ok(self.contracts_dir.join(key_path).with_extension("wasm"))
+        Ok(contracts_dir.join(key_path).with_extension("wasm"))
     }
 
     pub fn remove_contract(&mut self, key: &ContractKey) -> RuntimeResult<()> {
@@ -188,17 +234,47 @@ impl ContractStore {
                 RuntimeInnerError::UnwrapContract
             })?,
         };
-        if let Some((_, (offset, _))) = self.key_to_code_part.remove(key.id()) {
-            Self::remove(&self.key_file, offset)?;
+        let removed = self.key_to_code_part.remove(key.id());
+        match &mut self.storage {
+            CodeStorage::Disk {
+                contracts_dir,
+                key_file,
+                ..
+            } => {
+                if let Some((_, (offset, _))) = removed {
+                    Self::remove(key_file, offset)?;
+                }
+                let key_path = contracts_dir
+                    .join(contract_hash.encode())
+                    .with_extension("wasm");
+                std::fs::remove_file(key_path)?;
+            }
+            CodeStorage::Memory(contracts) => {
+                // the code may be shared by other contracts
+                if !self
+                    .key_to_code_part
+                    .iter()
+                    .any(|entry| entry.value().1 == contract_hash)
+                {
+                    contracts.remove(&contract_hash);
+                    self.contract_cache.remove(&contract_hash);
+                }
+            }
         }
-        let key_path = self
-            .contracts_dir
-            .join(contract_hash.encode())
-            .with_extension("wasm");
-        std::fs::remove_file(key_path)?;
         Ok(())
     }
 
+    /// Bytes of memory taken by the code of the contract, if kept in memory.
+    pub fn memory_size(&self, id: &ContractInstanceId) -> usize {
+        let CodeStorage::Memory(contracts) = &self.storage else {
+            return 0;
+        };
+        self.key_to_code_part
+            .get(id)
+            .and_then(|entry| contracts.get(&entry.value().1))
+            .map_or(0, |code| code.data().len())
+    }
+
     pub fn code_hash_from_key(&self, key: &ContractKey) -> Option<CodeHash> {
         self.key_to_code_part.get(key.id()).map(|r| r.value().1)
     }
@@ -223,4 +299,27 @@ mod test {
         assert!(f.is_some());
         Ok(())
     }
+
+    #[test]
+    fn store_and_load_in_memory() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = ContractStore::in_memory(10_000);
+        let contract = WrappedContract::new(
+            Arc::new(ContractCode::from(vec![0, 1, 2])),
+            [0, 1].as_ref().into(),
+        );
+        let container = ContractContainer::Wasm(ContractWasmAPIVersion::V1(contract.clone()));
+        store.store_contract(container)?;
+        // loaded from the store and not from the cache
+        store.contract_cache.wait()?;
+        store.contract_cache.clear()?;
+        let f = store.fetch_contract(contract.key(), &[0, 1].as_ref().into());
+        assert!(f.is_some());
+        assert_eq!(store.memory_size(contract.key().id()), 3);
+
+        store.remove_contract(contract.key())?;
+        assert!(store
+            .fetch_contract(contract.key(), &[0, 1].as_ref().into())
+            .is_none());
+        Ok(())
+    }
 }
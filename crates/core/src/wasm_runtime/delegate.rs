@@ -2,9 +2,9 @@ use std::collections::{HashMap, HashSet, VecDeque};
 
 use chacha20poly1305::{XChaCha20Poly1305, XNonce};
 use freenet_stdlib::prelude::{
-    ApplicationMessage, ClientResponse, DelegateContainer, DelegateContext, DelegateError,
-    DelegateInterfaceResult, DelegateKey, GetSecretRequest, GetSecretResponse, InboundDelegateMsg,
-    OutboundDelegateMsg, Parameters, SecretsId, SetSecretRequest,
+    ApplicationMessage, ClientResponse, CodeHash, DelegateContainer, DelegateContext,
+    DelegateError, DelegateInterfaceResult, DelegateKey, GetSecretRequest, GetSecretResponse,
+    InboundDelegateMsg, OutboundDelegateMsg, Parameters, SecretsId, SetSecretRequest,
 };
 use serde::{Deserialize, Serialize};
 use wasmer::{Instance, TypedFunction};
@@ -57,6 +57,9 @@ pub(crate) trait DelegateRuntimeInterface {
     ) -> RuntimeResult<()>;
 
     fn unregister_delegate(&mut self, key: &DelegateKey) -> RuntimeResult<()>;
+
+    /// Hash of the code of the delegate, if registered.
+    fn delegate_code_hash(&self, key: &DelegateKey) -> Option<CodeHash>;
 }
 
 impl Runtime {
@@ -495,6 +498,11 @@ impl DelegateRuntimeInterface for Runtime {
     fn unregister_delegate(&mut self, key: &DelegateKey) -> RuntimeResult<()> {
         self.delegate_store.remove_delegate(key)
     }
+
+    #[inline]
+    fn delegate_code_hash(&self, key: &DelegateKey) -> Option<CodeHash> {
+        self.delegate_store.code_hash_from_key(key)
+    }
 }
 
 #[cfg(test)]
@@ -3,7 +3,7 @@ use freenet_stdlib::prelude::{
     APIVersion, CodeHash, Delegate, DelegateCode, DelegateContainer, DelegateKey,
     DelegateWasmAPIVersion, Parameters,
 };
-use std::{fs::File, io::Write, path::PathBuf, sync::Arc};
+use std::{collections::HashMap, fs::File, io::Write, path::PathBuf, sync::Arc};
 use stretto::Cache;
 
 use crate::wasm_runtime::store::SafeWriter;
@@ -12,11 +12,20 @@ use super::store::StoreFsManagement;
 use super::RuntimeResult;
 
 pub struct DelegateStore {
-    delegates_dir: PathBuf,
     delegate_cache: Cache<CodeHash, DelegateCode<'static>>,
     key_to_code_part: Arc<DashMap<DelegateKey, (u64, CodeHash)>>,
-    index_file: SafeWriter<Self>,
-    key_file: PathBuf,
+    storage: CodeStorage,
+}
+
+/// Where the code of the delegates is kept.
+enum CodeStorage {
+    Disk {
+        delegates_dir: PathBuf,
+        index_file: SafeWriter<DelegateStore>,
+        key_file: PathBuf,
+    },
+    /// Never written to disk, for nodes running without storage.
+    Memory(HashMap<CodeHash, DelegateCode<'static>>),
 }
 
 impl StoreFsManagement for DelegateStore {
@@ -54,13 +63,24 @@ impl DelegateStore {
         let index_file = SafeWriter::new(&key_file, false)?;
         Ok(Self {
             delegate_cache: Cache::new(100, max_size).expect(ERR),
-            delegates_dir,
             key_to_code_part,
-            index_file,
-            key_file,
+            storage: CodeStorage::Disk {
+                delegates_dir,
+                index_file,
+                key_file,
+            },
         })
     }
 
+    /// A store keeping the delegates in memory, never written to disk.
+    pub fn in_memory(max_size: i64) -> Self {
+        Self {
+            delegate_cache: Cache::new(100, max_size).expect("failed to build mem cache"),
+            key_to_code_part: Arc::new(DashMap::new()),
+            storage: CodeStorage::Memory(HashMap::new()),
+        }
+    }
+
     // Returns a copy of the delegate bytes if available, none otherwise.
     pub fn fetch_delegate(
         &self,
@@ -71,8 +91,14 @@ impl DelegateStore {
             return Some(Delegate::from((delegate_code.value(), params)).into_owned());
         }
         self.key_to_code_part.get(key).and_then(|code_part| {
-            let delegate_code_path = self
-                .delegates_dir
+            let delegates_dir = match &self.storage {
+                CodeStorage::Disk { delegates_dir, .. } => delegates_dir,
+                CodeStorage::Memory(delegates) => {
+                    let delegate_code = delegates.get(&code_part.value().1)?;
+                    return Some(Delegate::from((delegate_code, params)).into_owned());
+                }
+            };
+            let delegate_code_path = delegates_dir
                 .join(code_part.value().1.encode())
                 .with_extension("wasm");
             tracing::debug!("loading delegate `{key}` from {delegate_code_path:?}");
@@ -104,8 +130,23 @@ impl DelegateStore {
 
         let key = delegate.key();
 
+        let (delegates_dir, index_file, key_file) = match &mut self.storage {
+            CodeStorage::Disk {
+                delegates_dir,
+                index_file,
+                key_file,
+            } => (delegates_dir, index_file, key_file),
+            CodeStorage::Memory(delegates) => {
+                let code = delegate.code().clone().into_owned();
+                let size = code.as_ref().len() as i64;
+                self.delegate_cache.insert(*code_hash, code.clone(), size);
+                delegates.insert(*code_hash, code);
+                self.key_to_code_part.insert(key.clone(), (0, *code_hash));
+                return Ok(());
+            }
+        };
         let key_path = code_hash.encode();
-        let delegate_path = self.delegates_dir.join(key_path).with_extension("wasm");
+        let delegate_path = delegates_dir.join(key_path).with_extension("wasm");
         if let Ok((code, _ver)) = DelegateCode::load_versioned_from_path(delegate_path.as_path()) {
             let size = delegate.code().size() as i64;
             self.delegate_cache.insert(*code_hash, code, size);
@@ -134,13 +175,13 @@ impl DelegateStore {
                 let current_version_offset = v.get().0;
                 let prev_val = &mut v.get_mut().1;
                 // first mark the old entry (if it exists) as removed
-                Self::remove(&self.key_file, current_version_offset)?;
-                let new_offset = Self::insert(&mut self.index_file, key.clone(), code_hash)?;
+                Self::remove(key_file, current_version_offset)?;
+                let new_offset = Self::insert(index_file, key.clone(), code_hash)?;
                 *prev_val = *code_hash;
                 v.get_mut().0 = new_offset;
             }
             dashmap::mapref::entry::Entry::Vacant(v) => {
-                let offset = Self::insert(&mut self.index_file, key.clone(), code_hash)?;
+                let offset = Self::insert(index_file, key.clone(), code_hash)?;
                 v.insert((offset, *code_hash));
             }
         }
@@ -150,9 +191,21 @@ impl DelegateStore {
 
     pub fn remove_delegate(&mut self, key: &DelegateKey) -> RuntimeResult<()> {
         self.delegate_cache.remove(key.code_hash());
-        let cmp_path: PathBuf = self.delegates_dir.join(key.encode()).with_extension("wasm");
-        if let Some((_, (offset, _))) = self.key_to_code_part.remove(key) {
-            Self::remove(&self.key_file, offset)?;
+        let removed = self.key_to_code_part.remove(key);
+        let (delegates_dir, key_file) = match &mut self.storage {
+            CodeStorage::Disk {
+                delegates_dir,
+                key_file,
+                ..
+            } => (delegates_dir, key_file),
+            CodeStorage::Memory(delegates) => {
+                delegates.remove(key.code_hash());
+                return Ok(());
+            }
+        };
+        let cmp_path: PathBuf = delegates_dir.join(key.encode()).with_extension("wasm");
+        if let Some((_, (offset, _))) = removed {
+            Self::remove(key_file, offset)?;
         }
         match std::fs::remove_file(cmp_path) {
             Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
@@ -162,8 +215,11 @@ impl DelegateStore {
     }
 
     pub fn get_delegate_path(&mut self, key: &DelegateKey) -> RuntimeResult<PathBuf> {
+        let CodeStorage::Disk { delegates_dir, .. } = &self.storage else {
+            return Err(anyhow::anyhow!("delegate `{key}` is kept in memory").into());
+        };
         let key_path = key.encode().to_lowercase();
-        Ok(self.delegates_dir.join(key_path).with_extension("wasm"))
+        Ok(delegates_dir.join(key_path).with_extension("wasm"))
     }
 
     pub fn code_hash_from_key(&self, key: &DelegateKey) -> Option<CodeHash> {
@@ -191,4 +247,25 @@ mod test {
         assert!(f.is_some());
         Ok(())
     }
+
+    #[test]
+    fn store_and_load_in_memory() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = DelegateStore::in_memory(10_000);
+        let delegate = {
+            let delegate = Delegate::from((&vec![0, 1, 2].into(), &vec![].into()));
+            DelegateContainer::Wasm(DelegateWasmAPIVersion::V1(delegate))
+        };
+        store.store_delegate(delegate.clone())?;
+        // loaded from the store and not from the cache
+        store.delegate_cache.wait()?;
+        store.delegate_cache.clear()?;
+        let f = store.fetch_delegate(delegate.key(), &vec![].into());
+        assert!(f.is_some());
+
+        store.remove_delegate(delegate.key())?;
+        assert!(store
+            .fetch_delegate(delegate.key(), &vec![].into())
+            .is_none());
+        Ok(())
+    }
 }
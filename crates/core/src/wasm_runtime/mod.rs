@@ -20,4 +20,16 @@ pub use runtime::{ContractExecError, Runtime};
 pub(crate) use secrets_store::SecretStoreError;
 pub use secrets_store::SecretsStore;
 pub use state_store::StateStore;
-pub(crate) use state_store::{StateStorage, StateStoreError};
+pub(crate) use state_store::{StateStorage, StateStoreError, StoredState};
+
+/// Compacts the index files of the stores under the given directories, dropping the
+/// records of removed entries.
+pub(crate) fn compact_stores(
+    contracts_dir: &std::path::Path,
+    delegates_dir: &std::path::Path,
+    secrets_dir: &std::path::Path,
+) -> std::io::Result<()> {
+    store::compact_index_file::<ContractStore>(&contracts_dir.join("KEY_DATA"))?;
+    store::compact_index_file::<DelegateStore>(&delegates_dir.join("KEY_DATA"))?;
+    store::compact_index_file::<SecretsStore>(&secrets_dir.join("KEY_DATA"))
+}
@@ -91,6 +91,9 @@ pub enum ContractExecError {
     #[error("unexpected result from contract interface")]
     UnexpectedResult,
 
+    #[error("the contract doesn't export the `{0}` entry point")]
+    MissingEntryPoint(&'static str),
+
     #[error("The operation ran out of gas. This might be caused by an infinite loop or an inefficient computation.")]
     OutOfGas,
 
@@ -38,16 +38,25 @@ struct Encryption {
 }
 
 pub struct SecretsStore {
-    base_path: PathBuf,
     #[allow(unused)]
     secrets: Secrets,
     ciphers: HashMap<DelegateKey, Encryption>,
     key_to_secret_part: Arc<DashMap<DelegateKey, (u64, HashSet<SecretKey>)>>,
-    index_file: SafeWriter<Self>,
-    key_file: PathBuf,
+    storage: SecretsStorage,
     default_encryption: Encryption,
 }
 
+/// Where the encrypted secrets are kept.
+enum SecretsStorage {
+    Disk {
+        base_path: PathBuf,
+        index_file: SafeWriter<SecretsStore>,
+        key_file: PathBuf,
+    },
+    /// Never written to disk, for nodes running without storage.
+    Memory(HashMap<(DelegateKey, SecretKey), Vec<u8>>),
+}
+
 pub(super) struct ConcatenatedSecretKeys(Vec<u8>);
 
 impl AsRef<[u8]> for ConcatenatedSecretKeys {
@@ -114,11 +123,13 @@ impl SecretsStore {
 
         let index_file = SafeWriter::new(&key_file, false)?;
         Ok(Self {
-            base_path: secrets_dir,
             ciphers: HashMap::new(),
             key_to_secret_part,
-            index_file,
-            key_file,
+            storage: SecretsStorage::Disk {
+                base_path: secrets_dir,
+                index_file,
+                key_file,
+            },
             default_encryption: Encryption {
                 cipher: secrets.cipher(),
                 nonce: secrets.nonce(),
@@ -127,6 +138,20 @@ impl SecretsStore {
         })
     }
 
+    /// A store keeping the secrets in memory, never written to disk.
+    pub fn in_memory(secrets: Secrets) -> Self {
+        Self {
+            ciphers: HashMap::new(),
+            key_to_secret_part: Arc::new(DashMap::new()),
+            storage: SecretsStorage::Memory(HashMap::new()),
+            default_encryption: Encryption {
+                cipher: secrets.cipher(),
+                nonce: secrets.nonce(),
+            },
+            secrets,
+        }
+    }
+
     pub fn register_delegate(
         &mut self,
         delegate: DelegateKey,
@@ -146,8 +171,6 @@ impl SecretsStore {
         key: &SecretsId,
         plaintext: Vec<u8>,
     ) -> RuntimeResult<()> {
-        let delegate_path = self.base_path.join(delegate.encode());
-        let secret_file_path = delegate_path.join(key.encode());
         let secret_key = *key.hash();
         let encryption = self
             .ciphers
@@ -165,6 +188,25 @@ impl SecretsStore {
                 }
             })?;
 
+        let (base_path, index_file, key_file) = match &mut self.storage {
+            SecretsStorage::Disk {
+                base_path,
+                index_file,
+                key_file,
+            } => (base_path, index_file, key_file),
+            SecretsStorage::Memory(secrets) => {
+                self.key_to_secret_part
+                    .entry(delegate.clone())
+                    .or_default()
+                    .1
+                    .insert(secret_key);
+                secrets.insert((delegate.clone(), secret_key), ciphertext);
+                return Ok(());
+            }
+        };
+        let delegate_path = base_path.join(delegate.encode());
+        let secret_file_path = delegate_path.join(key.encode());
+
         // Update index
         let hashes = self.key_to_secret_part.entry(delegate.clone());
         match hashes {
@@ -176,18 +218,15 @@ impl SecretsStore {
                     value.extend_from_slice(hash);
                 }
                 // first mark the old entry (if it exists) as removed
-                Self::remove(&self.key_file, current_version_offset)?;
-                let new_offset = Self::insert(
-                    &mut self.index_file,
-                    delegate.clone(),
-                    &ConcatenatedSecretKeys(value),
-                )?;
+                Self::remove(key_file, current_version_offset)?;
+                let new_offset =
+                    Self::insert(index_file, delegate.clone(), &ConcatenatedSecretKeys(value))?;
                 secret_hashes.insert(secret_key);
                 v.get_mut().0 = new_offset;
             }
             dashmap::mapref::entry::Entry::Vacant(v) => {
                 let offset = Self::insert(
-                    &mut self.index_file,
+                    index_file,
                     delegate.clone(),
                     &ConcatenatedSecretKeys(secret_key.to_vec()),
                 )?;
@@ -207,7 +246,14 @@ impl SecretsStore {
         delegate: &DelegateKey,
         key: &SecretsId,
     ) -> Result<(), SecretStoreError> {
-        let secret_path = self.base_path.join(delegate.encode()).join(key.encode());
+        let base_path = match &mut self.storage {
+            SecretsStorage::Disk { base_path, .. } => base_path,
+            SecretsStorage::Memory(secrets) => {
+                secrets.remove(&(delegate.clone(), *key.hash()));
+                return Ok(());
+            }
+        };
+        let secret_path = base_path.join(delegate.encode()).join(key.encode());
         match fs::remove_file(secret_path) {
             Ok(_) => Ok(()),
             Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
@@ -220,14 +266,21 @@ impl SecretsStore {
         delegate: &DelegateKey,
         key: &SecretsId,
     ) -> Result<Vec<u8>, SecretStoreError> {
-        let secret_path = self.base_path.join(delegate.encode()).join(key.encode());
         let encryption = self
             .ciphers
             .get(delegate)
             .unwrap_or(&self.default_encryption);
 
-        let ciphertext =
-            fs::read(secret_path).map_err(|_| SecretStoreError::MissingSecret(key.clone()))?;
+        let ciphertext = match &self.storage {
+            SecretsStorage::Disk { base_path, .. } => {
+                let secret_path = base_path.join(delegate.encode()).join(key.encode());
+                fs::read(secret_path).ok()
+            }
+            SecretsStorage::Memory(secrets) => {
+                secrets.get(&(delegate.clone(), *key.hash())).cloned()
+            }
+        }
+        .ok_or_else(|| SecretStoreError::MissingSecret(key.clone()))?;
         let plaintext = encryption
             .cipher
             .decrypt(&encryption.nonce, ciphertext.as_ref())
@@ -271,4 +324,27 @@ mod test {
         assert!(f.is_ok());
         Ok(())
     }
+
+    #[test]
+    fn store_and_load_in_memory() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = SecretsStore::in_memory(Default::default());
+
+        let delegate = Delegate::from((&vec![0, 1, 2].into(), &vec![].into()));
+
+        let cipher = XChaCha20Poly1305::new(&XChaCha20Poly1305::generate_key(&mut OsRng));
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let secret_id = SecretsId::new(vec![0, 1, 2]);
+        let text = vec![0, 1, 2];
+
+        store.register_delegate(delegate.key().clone(), cipher, nonce)?;
+        store.store_secret(delegate.key(), &secret_id, text.clone())?;
+        assert_eq!(store.get_secret(delegate.key(), &secret_id)?, text);
+
+        store.remove_secret(delegate.key(), &secret_id)?;
+        assert!(matches!(
+            store.get_secret(delegate.key(), &secret_id),
+            Err(SecretStoreError::MissingSecret(_))
+        ));
+        Ok(())
+    }
 }
@@ -1,4 +1,6 @@
 use core::future::Future;
+use std::{collections::HashMap, time::SystemTime};
+
 use freenet_stdlib::prelude::*;
 use stretto::AsyncCache;
 
@@ -39,16 +41,33 @@ pub trait StateStorage {
         &self,
         key: &ContractKey,
     ) -> impl Future<Output = Result<Option<WrappedState>, Self::Error>> + Send;
+    /// The contracts with a stored state, and the size of it.
+    fn list(
+        &self,
+    ) -> impl Future<Output = Result<Vec<(ContractInstanceId, usize)>, Self::Error>> + Send;
+    /// Removes the state and parameters of the contract.
+    fn remove(&mut self, key: &ContractKey)
+        -> impl Future<Output = Result<(), Self::Error>> + Send;
     fn get_params<'a>(
         &'a self,
         key: &'a ContractKey,
     ) -> impl Future<Output = Result<Option<Parameters<'static>>, Self::Error>> + Send + 'a;
 }
 
+/// A contract state kept in the store.
+#[derive(Debug)]
+pub struct StoredState {
+    pub id: ContractInstanceId,
+    pub size: usize,
+    /// Last time the state was stored, `None` if it wasn't since the store was opened.
+    pub updated_at: Option<SystemTime>,
+}
+
 pub struct StateStore<S: StateStorage> {
     state_mem_cache: AsyncCache<ContractKey, WrappedState>,
     // params_mem_cache: AsyncCache<ContractKey, Parameters<'static>>,
     store: S,
+    updated_at: HashMap<ContractInstanceId, SystemTime>,
 }
 
 impl<S> StateStore<S>
@@ -68,6 +87,7 @@ where
             // params_mem_cache: AsyncCache::new(counters, max_size as i64)
             //     .map_err(|err| StateStoreError::Any(Box::new(err)))?,
             store,
+            updated_at: HashMap::new(),
         })
     }
 
@@ -88,6 +108,7 @@ where
             .store(*key, state.clone())
             .await
             .map_err(Into::into)?;
+        self.updated_at.insert(*key.id(), SystemTime::now());
         let cost = state.size() as i64;
         self.state_mem_cache.insert(*key, state, cost).await;
         Ok(())
@@ -103,6 +124,7 @@ where
             .store(key, state.clone())
             .await
             .map_err(Into::into)?;
+        self.updated_at.insert(*key.id(), SystemTime::now());
         let cost = state.size() as i64;
         self.state_mem_cache.insert(key, state, cost).await;
         self.store
@@ -122,6 +144,31 @@ where
         r.ok_or_else(|| StateStoreError::MissingContract(*key))
     }
 
+    /// Last time the state of the contract was updated, `None` if it wasn't since the node
+    /// started.
+    pub fn updated_at(&self, key: &ContractKey) -> Option<SystemTime> {
+        self.updated_at.get(key.id()).copied()
+    }
+
+    pub async fn remove(&mut self, key: &ContractKey) -> Result<(), StateStoreError> {
+        self.store.remove(key).await.map_err(Into::into)?;
+        self.state_mem_cache.remove(key).await;
+        self.updated_at.remove(key.id());
+        Ok(())
+    }
+
+    pub async fn stored(&self) -> Result<Vec<StoredState>, StateStoreError> {
+        let states = self.store.list().await.map_err(Into::into)?;
+        Ok(states
+            .into_iter()
+            .map(|(id, size)| StoredState {
+                id,
+                size,
+                updated_at: self.updated_at.get(&id).copied(),
+            })
+            .collect())
+    }
+
     pub async fn get_params<'a>(
         &'a self,
         key: &'a ContractKey,
@@ -292,7 +292,9 @@ where
     }
 }
 
-fn compact_index_file<S: StoreFsManagement>(key_file_path: &Path) -> std::io::Result<()> {
+pub(super) fn compact_index_file<S: StoreFsManagement>(
+    key_file_path: &Path,
+) -> std::io::Result<()> {
     // Define the path to the lock file
     let lock_file_path = key_file_path.with_extension("lock");
 
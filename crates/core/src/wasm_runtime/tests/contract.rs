@@ -1,6 +1,7 @@
 use freenet_stdlib::prelude::*;
 
 use crate::wasm_runtime::tests::TestSetup;
+use crate::wasm_runtime::{ContractExecError, RuntimeInnerError};
 
 use super::super::contract::*;
 use super::super::Runtime;
@@ -105,3 +106,38 @@ fn get_state_delta() -> Result<(), Box<dyn std::error::Error>> {
     std::mem::drop(temp_dir);
     Ok(())
 }
+
+#[test]
+fn query_state_requires_entry_point() -> Result<(), Box<dyn std::error::Error>> {
+    let TestSetup {
+        contract_store,
+        delegate_store,
+        secrets_store,
+        contract_key,
+        temp_dir,
+    } = super::setup_test_contract(TEST_CONTRACT_1)?;
+    let mut runtime = Runtime::build(contract_store, delegate_store, secrets_store, false).unwrap();
+
+    // the test contract only computes deltas, the query isn't taken for a summary
+    let result = runtime.query_state(
+        &contract_key,
+        &Parameters::from([].as_ref()),
+        &WrappedState::new(vec![5, 2, 3, 4]),
+        &StateSummary::from([2, 3].as_ref()),
+    );
+    assert!(matches!(
+        result.as_ref().err().map(|e| e.deref()),
+        Some(RuntimeInnerError::ContractExecError(
+            ContractExecError::MissingEntryPoint("query_state")
+        ))
+    ));
+    // the runtime is still usable
+    runtime.get_state_delta(
+        &contract_key,
+        &Parameters::from([].as_ref()),
+        &WrappedState::new(vec![5, 2, 3, 4]),
+        &StateSummary::from([2, 3].as_ref()),
+    )?;
+    std::mem::drop(temp_dir);
+    Ok(())
+}
@@ -59,6 +59,7 @@ async fn base_node_test_config(
         ws_api: WebsocketApiArgs {
             address: Some(Ipv4Addr::LOCALHOST.into()),
             ws_api_port: Some(ws_api_port),
+            ..Default::default()
         },
         network_api: NetworkArgs {
             public_address: Some(Ipv4Addr::LOCALHOST.into()),
@@ -71,7 +72,36 @@ async fn base_node_test_config(
             address: Some(Ipv4Addr::LOCALHOST.into()),
             network_port: public_port,
             bandwidth_limit: None,
+            download_limit: None,
+            peer_upload_limit: None,
+            peer_download_limit: None,
+            min_connections: None,
+            max_connections: None,
+            rekey_interval: None,
+            rekey_bytes: None,
+            replay_window: None,
+            relay: false,
+            relay_rate_limit: None,
+            location_strategy: None,
+            peer_message_rate_limit: None,
+            peer_op_rate_limit: None,
+            max_concurrent_gets: None,
+            max_concurrent_puts: None,
+            max_concurrent_subscribes: None,
+            max_queued_ops: None,
+            opportunistic_caching: false,
+            cache_budget: None,
+            storage_less: false,
+            bootstrap_budget: None,
             blocked_addresses: None,
+            quic_port: None,
+            port_mapping: false,
+            dns_seed: None,
+            seed_list: None,
+            seed_list_keys: None,
+            mdns: false,
+            allowed_peers: None,
+            denied_peers: None,
         },
         config_paths: {
             freenet::config::ConfigPathsArgs {